@@ -1,30 +1,491 @@
 use crate::commands;
-use anyhow::{Context, Result};
+use crate::commands::lib::lock::DataDirLock;
+use crate::commands::lib::{
+    cluster_data_dir, cluster_names, confirm, get_cluster_dir, OutputFormat, CLUSTERS_DIR,
+};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
 use log::{info, warn};
-use std::{fs, path::Path};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn cmd(data_dir: &Path, plugin_path: &Path) -> Result<()> {
-    info!("Clearing cluster data directory:");
-    let params = commands::stop::ParamsBuilder::default()
-        .data_dir(data_dir.into())
-        .plugin_path(plugin_path.into())
-        .build()
-        .unwrap();
-    let _ = commands::stop::cmd(&params).context("failed stop cluster before clean");
-
-    let plugin_data_dir = plugin_path.join(data_dir);
-    if plugin_data_dir.exists() {
-        fs::remove_dir_all(&plugin_data_dir).context(format!(
-            "failed to remove directory {}",
-            plugin_data_dir.display()
-        ))?;
+#[derive(Debug, Builder)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    /// Clean data of this instance only, e.g. `default_1_1` or `i1`.
+    #[builder(default)]
+    instance_name: Option<String>,
+    /// Keep `*.log` files of instances.
+    #[builder(default = "false")]
+    keep_logs: bool,
+    /// Also stop and remove named clusters kept in `clusters/` of the data directory.
+    #[builder(default = "false")]
+    all: bool,
+    /// Print paths which would be removed without stopping instances and removing anything.
+    #[builder(default = "false")]
+    dry_run: bool,
+    /// Ask the user to confirm removal when running in a terminal.
+    #[builder(default = "true")]
+    confirm: bool,
+    /// Remove only snapshots and WAL files older than kept checkpoints, instances keep running.
+    #[builder(default = "false")]
+    snapshots: bool,
+    /// Number of the newest checkpoints of each instance kept by `snapshots` cleanup.
+    #[builder(default = "1")]
+    keep_checkpoints: usize,
+    /// Take over the lock of the data directory if its owner is not running anymore.
+    #[builder(default = "false")]
+    force_unlock: bool,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+/// Result of the clean printed with `--output json`.
+#[derive(Debug, Default, Serialize)]
+struct Cleaned {
+    /// Paths were only listed, nothing was removed.
+    dry_run: bool,
+    removed: Vec<PathBuf>,
+}
+
+const SNAP_EXT: &str = "snap";
+const XLOG_EXT: &str = "xlog";
+const MIB: f64 = 1024.0 * 1024.0;
+
+fn is_log(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "log")
+}
+
+/// Entries of `dir` to remove, directories with kept files are not removed
+/// themselves but their other content is. Symlinks are removed and not followed.
+fn collect_removable(dir: &Path, keep_logs: bool, removable: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let file_type = fs::symlink_metadata(&path)
+            .context(format!("failed to read metadata of {}", path.display()))?
+            .file_type();
+        if keep_logs && file_type.is_dir() {
+            let mut nested = vec![];
+            collect_removable(&path, keep_logs, &mut nested)?;
+            let keeps_files = fs::read_dir(&path)?.count() != nested.len();
+            if keeps_files {
+                removable.extend(nested);
+            } else {
+                removable.push(path);
+            }
+        } else if !(keep_logs && is_log(&path)) {
+            removable.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `default_1_1` to the data directory `i1` the symlink points to.
+fn instance_dir(cluster_dir: &Path, instance_name: &str) -> Result<PathBuf> {
+    let path = cluster_dir.join(instance_name);
+    if fs::symlink_metadata(&path).is_err() {
+        bail!("failed to locate directory of the instance '{instance_name}'");
+    }
+    if path.is_symlink() {
+        let target = fs::read_link(&path).context(format!("failed to read {}", path.display()))?;
+        return Ok(cluster_dir.join(target));
+    }
+    Ok(path)
+}
+
+/// Paths which are removed by clean with `params`, named clusters are kept unless `all` is set.
+fn removable_paths(params: &Params) -> Result<Vec<PathBuf>> {
+    let plugin_data_dir = params.plugin_path.join(&params.data_dir);
+    if !plugin_data_dir.exists() {
+        return Ok(vec![]);
+    }
+    let named_clusters_dir = plugin_data_dir.join(CLUSTERS_DIR);
+    let keeps_named_clusters = !params.all && named_clusters_dir.exists();
+
+    let mut removable = vec![];
+    match &params.instance_name {
+        Some(instance_name) => {
+            let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+            let dir = instance_dir(&cluster_dir, instance_name)?;
+            collect_removable(&dir, params.keep_logs, &mut removable)?;
+        }
+        None if params.keep_logs || keeps_named_clusters => {
+            collect_removable(&plugin_data_dir, params.keep_logs, &mut removable)?;
+            if keeps_named_clusters {
+                removable.retain(|path| !path.starts_with(&named_clusters_dir));
+            }
+        }
+        None => removable.push(plugin_data_dir),
+    }
+    Ok(removable)
+}
+
+/// LSN in the name of a snapshot or WAL file, e.g. `00000000000000000042.xlog`.
+fn checkpoint_lsn(path: &Path, ext: &str) -> Option<u64> {
+    if path.extension()? != ext {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Snapshots and WAL files of the instance which are not needed to recover
+/// from its `keep` newest snapshots.
+///
+/// WAL file contains records from the LSN in its name up to the next file,
+/// so the file the oldest kept snapshot falls into is kept too.
+fn stale_checkpoint_files(instance_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut snaps = vec![];
+    let mut xlogs = vec![];
+    for entry in
+        fs::read_dir(instance_dir).context(format!("failed to read {}", instance_dir.display()))?
+    {
+        let path = entry?.path();
+        if let Some(lsn) = checkpoint_lsn(&path, SNAP_EXT) {
+            snaps.push((lsn, path));
+        } else if let Some(lsn) = checkpoint_lsn(&path, XLOG_EXT) {
+            xlogs.push((lsn, path));
+        }
+    }
+    snaps.sort();
+    xlogs.sort();
+
+    if snaps.len() <= keep {
+        return Ok(vec![]);
+    }
+    let stale_snaps = snaps.len() - keep.max(1);
+    let oldest_kept_lsn = snaps[stale_snaps].0;
+    let stale_xlogs = xlogs
+        .iter()
+        .rposition(|(lsn, _)| *lsn <= oldest_kept_lsn)
+        .unwrap_or(0);
+
+    Ok(snaps
+        .into_iter()
+        .take(stale_snaps)
+        .chain(xlogs.into_iter().take(stale_xlogs))
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Data directories of instances cleaned by `params`, symlinks to them are skipped.
+fn instance_dirs(params: &Params) -> Result<Vec<PathBuf>> {
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    if let Some(instance_name) = &params.instance_name {
+        return Ok(vec![instance_dir(&cluster_dir, instance_name)?]);
+    }
+
+    let mut dirs = vec![];
+    for entry in
+        fs::read_dir(&cluster_dir).context(format!("failed to read {}", cluster_dir.display()))?
+    {
+        let path = entry?.path();
+        if fs::symlink_metadata(&path)?.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Removes old snapshots and WAL files of running or stopped instances.
+fn clean_snapshots(params: &Params) -> Result<Cleaned> {
+    let mut stale = vec![];
+    for dir in instance_dirs(params)? {
+        stale.extend(stale_checkpoint_files(&dir, params.keep_checkpoints)?);
+    }
+    if stale.is_empty() {
         info!(
-            "Successfully cleaned: {}",
-            plugin_data_dir.to_string_lossy()
+            "Nothing to clean, instances have no snapshots older than {} newest checkpoint(s)",
+            params.keep_checkpoints.max(1)
         );
-    } else {
-        warn!("Data directory does not exist");
+        return Ok(Cleaned::default());
+    }
+
+    let mut size = 0;
+    for path in &stale {
+        size += fs::metadata(path)
+            .context(format!("failed to read metadata of {}", path.display()))?
+            .len();
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let size_mib = size as f64 / MIB;
+
+    if params.dry_run {
+        info!("The following files would be removed ({size_mib:.1} MiB):");
+        if params.output == OutputFormat::Text {
+            for path in &stale {
+                println!("{}", path.display());
+            }
+        }
+        return Ok(Cleaned {
+            dry_run: true,
+            removed: stale,
+        });
+    }
+
+    let paths: Vec<String> = stale.iter().map(|p| p.display().to_string()).collect();
+    if params.confirm
+        && !confirm(
+            "The following snapshots and WAL files will be removed",
+            &paths,
+        )?
+    {
+        info!("Clean is cancelled");
+        return Ok(Cleaned::default());
+    }
+
+    let _lock = DataDirLock::acquire(
+        &params.plugin_path,
+        &params.data_dir,
+        "clean",
+        params.force_unlock,
+    )?;
+    for path in &stale {
+        fs::remove_file(path).context(format!("failed to remove {}", path.display()))?;
     }
+    info!(
+        "Removed {} snapshot and WAL file(s), reclaimed {size_mib:.1} MiB",
+        stale.len()
+    );
+    Ok(Cleaned {
+        dry_run: false,
+        removed: stale,
+    })
+}
 
+/// Stops the cluster and removes its data directory, named clusters
+/// in the data directory are stopped and removed only with `all`.
+///
+/// Only data of `instance_name` is removed if it is set, and only this instance is stopped.
+/// With `snapshots` only old snapshots and WAL files are removed and instances keep running.
+/// With `--output json` the removed paths are printed.
+pub fn cmd(params: &Params) -> Result<()> {
+    let cleaned = if params.snapshots {
+        clean_snapshots(params)?
+    } else {
+        clean(params)?
+    };
+    if params.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&cleaned)?);
+    }
     Ok(())
 }
+
+fn clean(params: &Params) -> Result<Cleaned> {
+    let removable = removable_paths(params)?;
+    if removable.is_empty() {
+        warn!("Nothing to clean, data directory does not exist or is empty");
+        return Ok(Cleaned::default());
+    }
+    let paths: Vec<String> = removable.iter().map(|p| p.display().to_string()).collect();
+
+    if params.dry_run {
+        info!("The following paths would be removed:");
+        if params.output == OutputFormat::Text {
+            for path in &paths {
+                println!("{path}");
+            }
+        }
+        return Ok(Cleaned {
+            dry_run: true,
+            removed: removable,
+        });
+    }
+
+    if params.confirm
+        && !confirm(
+            "Running instances will be stopped and the following paths will be removed",
+            &paths,
+        )?
+    {
+        info!("Clean is cancelled");
+        return Ok(Cleaned::default());
+    }
+
+    let mut data_dirs = vec![params.data_dir.clone()];
+    // Named clusters are removed with the data directory, so they are stopped too.
+    if params.all && params.instance_name.is_none() {
+        for name in cluster_names(&params.plugin_path, &params.data_dir)? {
+            data_dirs.push(cluster_data_dir(params.data_dir.clone(), Some(&name))?);
+        }
+    }
+    let _locks = data_dirs
+        .iter()
+        .map(|data_dir| {
+            DataDirLock::acquire(&params.plugin_path, data_dir, "clean", params.force_unlock)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    info!("Clearing cluster data directory:");
+    for data_dir in data_dirs {
+        let stop_params = commands::stop::ParamsBuilder::default()
+            .data_dir(data_dir)
+            .plugin_path(params.plugin_path.clone())
+            .instance_name(params.instance_name.clone())
+            .build()
+            .unwrap();
+        let _ = commands::stop::cmd(&stop_params).context("failed stop cluster before clean");
+    }
+
+    for path in &removable {
+        let result = if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        result.context(format!("failed to remove {}", path.display()))?;
+        info!("Successfully cleaned: {}", path.display());
+    }
+
+    Ok(Cleaned {
+        dry_run: false,
+        removed: removable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::os::unix::fs::symlink;
+
+    fn relative(paths: &[PathBuf], root: &Path) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.strip_prefix(root).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn logs_and_other_instances_are_kept() {
+        let workspace = TempWorkspace::new("clean-selective").unwrap();
+        let cluster_dir = workspace.join("tmp/cluster");
+        for instance in ["i1", "i2"] {
+            fs::create_dir_all(cluster_dir.join(instance).join("snap")).unwrap();
+            fs::write(cluster_dir.join(instance).join("picodata.log"), "").unwrap();
+            fs::write(cluster_dir.join(instance).join("00.xlog"), "").unwrap();
+        }
+        symlink("i2", cluster_dir.join("default_2_1")).unwrap();
+        fs::write(workspace.join("tmp/cluster.json"), "{}").unwrap();
+
+        let params = |instance_name: Option<&str>, keep_logs| {
+            ParamsBuilder::default()
+                .plugin_path(workspace.path().to_path_buf())
+                .instance_name(instance_name.map(String::from))
+                .keep_logs(keep_logs)
+                .build()
+                .unwrap()
+        };
+        let removable = |params| relative(&removable_paths(&params).unwrap(), workspace.path());
+
+        assert_eq!(removable(params(None, false)), ["tmp"]);
+        assert_eq!(
+            removable(params(None, true)),
+            [
+                "tmp/cluster/default_2_1",
+                "tmp/cluster/i1/00.xlog",
+                "tmp/cluster/i1/snap",
+                "tmp/cluster/i2/00.xlog",
+                "tmp/cluster/i2/snap",
+                "tmp/cluster.json",
+            ]
+        );
+        assert_eq!(
+            removable(params(Some("default_2_1"), false)),
+            [
+                "tmp/cluster/i2/00.xlog",
+                "tmp/cluster/i2/picodata.log",
+                "tmp/cluster/i2/snap",
+            ]
+        );
+        assert_eq!(
+            removable(params(Some("i1"), true)),
+            ["tmp/cluster/i1/00.xlog", "tmp/cluster/i1/snap"]
+        );
+        assert!(removable_paths(&params(Some("i3"), false)).is_err());
+    }
+
+    #[test]
+    fn named_clusters_are_kept_unless_all() {
+        let workspace = TempWorkspace::new("clean-named").unwrap();
+        fs::create_dir_all(workspace.join("tmp/cluster/i1")).unwrap();
+        fs::create_dir_all(workspace.join("tmp/clusters/staging/cluster/i1")).unwrap();
+        fs::write(workspace.join("tmp/cluster/i1/picodata.log"), "").unwrap();
+        fs::write(workspace.join("tmp/cluster.json"), "{}").unwrap();
+
+        let params = |keep_logs, all| {
+            ParamsBuilder::default()
+                .plugin_path(workspace.path().to_path_buf())
+                .keep_logs(keep_logs)
+                .all(all)
+                .build()
+                .unwrap()
+        };
+        let removable = |params| relative(&removable_paths(&params).unwrap(), workspace.path());
+
+        assert_eq!(
+            removable(params(false, false)),
+            ["tmp/cluster", "tmp/cluster.json"]
+        );
+        assert_eq!(removable(params(true, false)), ["tmp/cluster.json"]);
+        assert_eq!(removable(params(false, true)), ["tmp"]);
+        assert_eq!(
+            removable(params(true, true)),
+            ["tmp/cluster.json", "tmp/clusters"]
+        );
+    }
+
+    #[test]
+    fn checkpoints_older_than_kept_ones_are_stale() {
+        let workspace = TempWorkspace::new("clean-snapshots").unwrap();
+        for file in [
+            "00000000000000000000.snap",
+            "00000000000000000000.xlog",
+            "00000000000000000010.xlog",
+            "00000000000000000015.snap",
+            "00000000000000000020.xlog",
+            "00000000000000000030.snap",
+            "00000000000000000030.xlog",
+            "00000000000000000042.xlog",
+            "00000000000000000050.snap.inprogress",
+            "picodata.log",
+        ] {
+            fs::write(workspace.join(file), "").unwrap();
+        }
+        let stale = |keep| {
+            let mut files = relative(
+                &stale_checkpoint_files(workspace.path(), keep).unwrap(),
+                workspace.path(),
+            );
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            stale(1),
+            [
+                "00000000000000000000.snap",
+                "00000000000000000000.xlog",
+                "00000000000000000010.xlog",
+                "00000000000000000015.snap",
+                "00000000000000000020.xlog",
+            ]
+        );
+        // The snapshot at LSN 15 needs records of the WAL file started at LSN 10.
+        assert_eq!(
+            stale(2),
+            ["00000000000000000000.snap", "00000000000000000000.xlog"]
+        );
+        assert!(stale(3).is_empty());
+    }
+}
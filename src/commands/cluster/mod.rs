@@ -0,0 +1 @@
+pub(crate) mod rolling_restart;
@@ -0,0 +1,159 @@
+//! Restart of all instances of the running cluster one at a time,
+//! the way plugins are redeployed in production.
+
+use crate::commands::instance::restart::{self, DEFAULT_RESTART_SIGNAL};
+use crate::commands::lib::cluster_state::{wait_ready, ClusterState};
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::run::Topology;
+use crate::commands::stop::DEFAULT_STOP_TIMEOUT;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use nix::sys::signal::Signal;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// Default time for the cluster to see the restarted instance Online.
+pub const DEFAULT_ONLINE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    topology: Topology,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = "false")]
+    use_release: bool,
+    #[builder(default = "PathBuf::from(\"target\")")]
+    target_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./picodata.yaml\")")]
+    config_path: PathBuf,
+    #[builder(default = "false")]
+    with_audit: bool,
+    #[builder(default = DEFAULT_RESTART_SIGNAL)]
+    signal: Signal,
+    #[builder(default = DEFAULT_STOP_TIMEOUT)]
+    timeout: Duration,
+    /// Time to wait for the restarted instance to be Online in the cluster.
+    #[builder(default = DEFAULT_ONLINE_TIMEOUT)]
+    online_timeout: Duration,
+}
+
+impl Params {
+    fn restart_params(&self, instance_name: &str) -> restart::Params {
+        restart::ParamsBuilder::default()
+            .instance_name(instance_name.to_string())
+            .topology(self.topology.clone())
+            .data_dir(self.data_dir.clone())
+            .plugin_path(self.plugin_path.clone())
+            .picodata_path(self.picodata_path.clone())
+            .use_release(self.use_release)
+            .target_dir(self.target_dir.clone())
+            .config_path(self.config_path.clone())
+            .with_audit(self.with_audit)
+            .signal(self.signal)
+            .timeout(self.timeout)
+            .build()
+            .unwrap()
+    }
+}
+
+/// Names of instances which are not expelled grouped by replicasets.
+fn instances_by_replicaset(state: &ClusterState) -> BTreeMap<&str, Vec<&str>> {
+    let mut replicasets: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for instance in state.instances.iter().filter(|i| !i.is_expelled()) {
+        replicasets
+            .entry(&instance.replicaset_name)
+            .or_default()
+            .push(&instance.name);
+    }
+    for instances in replicasets.values_mut() {
+        instances.sort_unstable();
+    }
+    replicasets
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let state = ClusterState::fetch(&cluster_dir, &params.picodata_path)
+        .context("failed to read state of the cluster")?;
+    if !state.has_quorum() {
+        bail!("cluster has no quorum, rolling restart is not started: {state}");
+    }
+
+    let start = Instant::now();
+    let replicasets = instances_by_replicaset(&state);
+    for (replicaset_name, instances) in &replicasets {
+        info!("restarting replicaset '{replicaset_name}'");
+
+        for &instance_name in instances {
+            restart::cmd(&params.restart_params(instance_name))?;
+
+            // Next instance is stopped only when the cluster sees this one Online again.
+            wait_ready(
+                &cluster_dir,
+                &params.picodata_path,
+                params.online_timeout,
+                |state| {
+                    state.has_quorum()
+                        && state
+                            .instances
+                            .iter()
+                            .any(|i| i.name == instance_name && i.is_online())
+                },
+            )
+            .context(format!(
+                "cluster lost quorum or instance '{instance_name}' is not Online \
+                after restart, rolling restart is aborted"
+            ))?;
+        }
+    }
+
+    info!(
+        "rolling restart of {} instance(s) completed in {:?}",
+        replicasets.values().map(Vec::len).sum::<usize>(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_are_restarted_by_replicasets() {
+        let instance = |name: &str, replicaset_name: &str, state: &str| {
+            serde_json::json!({
+                "name": name,
+                "replicaset_name": replicaset_name,
+                "tier": "default",
+                "current_state": [state, 1],
+                "target_state": [state, 1],
+            })
+        };
+        let state = ClusterState {
+            instances: serde_json::from_value(serde_json::json!([
+                instance("default_2_1", "default_2", "Online"),
+                instance("default_1_2", "default_1", "Online"),
+                instance("default_1_1", "default_1", "Online"),
+                instance("default_2_2", "default_2", "Expelled"),
+            ]))
+            .unwrap(),
+            plugins: vec![],
+        };
+
+        let replicasets = instances_by_replicaset(&state);
+        assert_eq!(
+            replicasets.into_iter().collect::<Vec<_>>(),
+            [
+                ("default_1", vec!["default_1_1", "default_1_2"]),
+                ("default_2", vec!["default_2_1"]),
+            ]
+        );
+    }
+}
@@ -1,15 +1,26 @@
-use anyhow::{bail, Context, Result};
+use super::{diff, get, schema};
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::poll::RetryPolicy;
+use crate::commands::lib::sql::{self, quote_ident, quote_literal};
+use crate::commands::lib::{find_active_socket_path, get_cluster_dir, OutputFormat};
+use crate::error::Error;
+use anyhow::{anyhow, bail, Context, Result};
 use derive_builder::Builder;
-use log::info;
-use serde::Deserialize;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env, fs,
     io::{BufRead, BufReader, Read, Write},
     path::{Path, PathBuf},
-    process::{self, Command, Stdio},
+    process::{Command, Stdio},
 };
-use toml_edit::DocumentMut;
+use toml_edit::{DocumentMut, Item};
+
+/// Single lookup of a running instance, `config apply` fails at once if the cluster is down.
+pub const DEFAULT_RETRY_POLICY: RetryPolicy =
+    RetryPolicy::new(Duration::ZERO, Duration::from_millis(500));
 
 /// Mapping of plugin service names to their properties specified in
 /// [plugin configuration](https://github.com/picodata/pike?tab=readme-ov-file#config-apply).
@@ -48,24 +59,38 @@ use toml_edit::DocumentMut;
 pub type ConfigMap = HashMap<String, HashMap<String, serde_norway::Value>>;
 
 const DEFAULT_PLUGIN_CONFIG_PATH: &str = "plugin_config.yaml";
-const WISE_PIKE: &str = r"
-  ________________________________________
-/ You are trying to apply config from     \
-| custom directory, however to use this   |
-| flag, you must specify the plugin with  |
-\           --plugin-name                 /
- ----------------------------------------
- o
-o      ______/~/~/~/__           /((
-  o  // __            ====__    /_((
- o  //  @))       ))))      ===/__((
-    ))           )))))))        __((
-    \\     \)     ))))    __===\ _((
-     \\_______________====      \_((
-                                 \((
- ";
-
-fn read_config_from_path(path: &PathBuf) -> Result<ConfigMap> {
+
+/// Key of the service section with its config on particular tiers, e.g.
+///
+/// ```yaml
+/// main:
+///   value: common
+///   tiers:
+///     storage:
+///       value: storage only
+/// ```
+const TIERS_KEY: &str = "tiers";
+
+/// Keys of the service config overridden on tiers, keyed by tier names.
+type TierConfigs = BTreeMap<String, HashMap<String, serde_norway::Value>>;
+
+/// Splits `tiers` sections out of services config, the rest of it is applied to all tiers.
+fn split_tier_configs(config: &ConfigMap) -> Result<(ConfigMap, BTreeMap<String, TierConfigs>)> {
+    let mut common = config.clone();
+    let mut tier_configs = BTreeMap::new();
+    for (service, service_config) in &mut common {
+        let Some(tiers) = service_config.remove(TIERS_KEY) else {
+            continue;
+        };
+        let tiers: TierConfigs = serde_norway::from_value(tiers).context(format!(
+            "'{TIERS_KEY}' section of service {service} must map tier names to service keys"
+        ))?;
+        tier_configs.insert(service.clone(), tiers);
+    }
+    Ok((common, tier_configs))
+}
+
+pub(crate) fn read_config_from_path(path: &Path) -> Result<ConfigMap> {
     serde_norway::from_str(
         &fs::read_to_string(path)
             .context(format!("failed to read config file at {}", path.display()))?,
@@ -76,25 +101,48 @@ fn read_config_from_path(path: &PathBuf) -> Result<ConfigMap> {
     ))
 }
 
-fn apply_service_config(
+/// Statements which set keys of the service config, on the `tier` only if it is passed.
+fn service_config_queries(
     plugin_name: &str,
     plugin_version: &str,
     service_name: &str,
     config: &HashMap<String, serde_norway::Value>,
-    admin_socket: &Path,
-    picodata_path: &Path,
-) -> Result<()> {
+    tier: Option<&str>,
+) -> Result<Vec<String>> {
+    let tier_clause = tier
+        .map(|tier| format!(" ON TIER {}", quote_ident(tier)))
+        .unwrap_or_default();
     let mut queries: Vec<String> = Vec::new();
 
     for (key, value) in config {
         let value = serde_json::to_string(&value)
             .context(format!("failed to serialize the string with key {key}"))?;
         queries.push(format!(
-            r#"ALTER PLUGIN "{plugin_name}" {plugin_version} SET "{service_name}"."{key}"='{value}';"#
+            "ALTER PLUGIN {} {} SET {}.{}={}{tier_clause};",
+            quote_ident(plugin_name),
+            sql::plugin_version(plugin_version)?,
+            quote_ident(service_name),
+            quote_ident(key),
+            quote_literal(&value)
         ));
     }
 
-    for query in queries {
+    Ok(queries)
+}
+
+/// Executes statements setting the service config and returns them.
+fn apply_service_config(
+    plugin_name: &str,
+    plugin_version: &str,
+    service_name: &str,
+    config: &HashMap<String, serde_norway::Value>,
+    tier: Option<&str>,
+    admin_socket: &Path,
+    picodata_path: &Path,
+) -> Result<Vec<String>> {
+    let queries = service_config_queries(plugin_name, plugin_version, service_name, config, tier)?;
+
+    for query in &queries {
         log::info!("picodata admin: {query}");
 
         let mut picodata_admin = Command::new(picodata_path)
@@ -143,60 +191,431 @@ fn apply_service_config(
         }
     }
 
-    Ok(())
+    Ok(queries)
 }
 
-fn apply_plugin_config(params: &Params, current_plugin_path: &str) -> Result<()> {
-    let cur_plugin_dir = env::current_dir()?
-        .join(&params.plugin_path)
-        .join(current_plugin_path);
-
-    let admin_socket = params
-        .plugin_path
-        .join(&params.data_dir)
-        .join("cluster")
-        .join("i1")
-        .join("admin.sock");
-
-    let cargo_manifest: &CargoManifest = &toml::from_str(
-        &fs::read_to_string(cur_plugin_dir.join("Cargo.toml"))
-            .context("failed to read Cargo.toml")?,
-    )
-    .context("failed to parse Cargo.toml")?;
+/// Config of a plugin handled by `config apply`, printed with `--output json`.
+#[derive(Debug, Serialize)]
+struct AppliedConfig {
+    plugin: String,
+    version: String,
+    /// Statements executed on the cluster, or which would be executed by a preview.
+    queries: Vec<String>,
+}
+
+/// Statements setting `config` and `tier_configs` of the plugin.
+fn plugin_config_queries(
+    package: &Package,
+    config: &ConfigMap,
+    tier_configs: &BTreeMap<String, TierConfigs>,
+) -> Result<Vec<String>> {
+    let mut queries = vec![];
+    for (service_name, service_config) in config {
+        queries.extend(service_config_queries(
+            &package.name,
+            &package.version,
+            service_name,
+            service_config,
+            None,
+        )?);
+    }
+    for (service_name, tiers) in tier_configs {
+        for (tier, tier_config) in tiers {
+            queries.extend(service_config_queries(
+                &package.name,
+                &package.version,
+                service_name,
+                tier_config,
+                Some(tier),
+            )?);
+        }
+    }
+    Ok(queries)
+}
+
+fn apply_plugin_config(
+    params: &Params,
+    plugin: &TargetPlugin,
+    config: &ConfigMap,
+    admin_socket: &Path,
+) -> Result<AppliedConfig> {
+    let (config, tier_configs) = split_tier_configs(config)?;
+    let schema_path = params
+        .schema_path
+        .clone()
+        .unwrap_or_else(|| plugin.dir.join(schema::SCHEMA_FILE_NAME));
+    if schema_path.exists() {
+        info!("Validating plugin config against {}", schema_path.display());
+        let schema = schema::load(&schema_path)?;
+        schema::validate_config(&schema, &config)?;
+        // Config on a tier is the common one with overridden keys.
+        for (service_name, tiers) in &tier_configs {
+            for (tier, tier_config) in tiers {
+                let mut merged = config.get(service_name).cloned().unwrap_or_default();
+                merged.extend(tier_config.clone());
+                schema::validate_config(&schema, &HashMap::from([(service_name.clone(), merged)]))
+                    .context(format!(
+                        "config of service {service_name} on tier {tier} is invalid"
+                    ))?;
+            }
+        }
+    }
 
-    let config: ConfigMap = match &params.config_source {
-        ConfigSource::Map(map) => map.clone(),
-        ConfigSource::Path(path) => read_config_from_path(&cur_plugin_dir.join(path))?,
+    let mut applied = AppliedConfig {
+        plugin: plugin.package.name.clone(),
+        version: plugin.package.version.clone(),
+        queries: vec![],
     };
+    if params.dry_run || params.diff {
+        if params.output == OutputFormat::Text {
+            preview_plugin_config(
+                params,
+                &plugin.package,
+                admin_socket,
+                &config,
+                &tier_configs,
+            )?;
+        }
+        applied.queries = plugin_config_queries(&plugin.package, &config, &tier_configs)?;
+        return Ok(applied);
+    }
 
-    for (service_name, service_config) in config {
-        apply_service_config(
-            &cargo_manifest.package.name,
-            &cargo_manifest.package.version,
-            &service_name,
-            &service_config,
-            &admin_socket,
+    for (service_name, service_config) in &config {
+        let queries = apply_service_config(
+            &plugin.package.name,
+            &plugin.package.version,
+            service_name,
+            service_config,
+            None,
+            admin_socket,
             &params.picodata_path,
         )
         .context(format!(
             "failed to apply service config for service {service_name}"
         ))?;
+        applied.queries.extend(queries);
+    }
+
+    for (service_name, tiers) in &tier_configs {
+        for (tier, tier_config) in tiers {
+            let queries = apply_service_config(
+                &plugin.package.name,
+                &plugin.package.version,
+                service_name,
+                tier_config,
+                Some(tier),
+                admin_socket,
+                &params.picodata_path,
+            )
+            .context(format!(
+                "failed to apply service config for service {service_name} on tier {tier}"
+            ))?;
+            applied.queries.extend(queries);
+        }
+    }
+
+    Ok(applied)
+}
+
+/// Prints changes which would be made by applying `config`
+/// without executing any queries on the cluster.
+fn preview_plugin_config(
+    params: &Params,
+    package: &Package,
+    admin_socket: &Path,
+    config: &ConfigMap,
+    tier_configs: &BTreeMap<String, TierConfigs>,
+) -> Result<()> {
+    let (plugin_name, plugin_version) = (&package.name, &package.version);
+    let instance_dir = admin_socket
+        .parent()
+        .context("admin socket path has no parent directory")?;
+    let current = get::fetch_from_instance(
+        instance_dir,
+        &params.picodata_path,
+        plugin_name,
+        plugin_version,
+    )?;
+
+    if params.diff {
+        let (before, after) = diff::merge(&current, config);
+        print!(
+            "{}",
+            diff::unified_diff(
+                &get::render_config(&before)?,
+                &get::render_config(&after)?,
+                &format!("{plugin_name}:{plugin_version} (applied)"),
+                &format!("{plugin_name}:{plugin_version} (local)"),
+            )
+        );
+        if !tier_configs.is_empty() {
+            info!("Config of services on tiers is not compared, use --dry-run to list its keys");
+        }
+        return Ok(());
+    }
+
+    let changes = diff::changed_keys(&current, config);
+    if changes.is_empty() && tier_configs.is_empty() {
+        println!("{plugin_name}:{plugin_version}: no changes");
+        return Ok(());
+    }
+
+    println!("{plugin_name}:{plugin_version}:");
+    print!("{}", diff::format_changes(&changes)?);
+    // Values applied on tiers are not known, so all keys of tier sections are listed.
+    for (service_name, tiers) in tier_configs {
+        for (tier, tier_config) in tiers {
+            let mut keys: Vec<_> = tier_config.iter().collect();
+            keys.sort_by_key(|(key, _)| *key);
+            for (key, value) in keys {
+                println!(
+                    "  = {service_name}.{key} on tier {tier}: {}",
+                    serde_json::to_string(value).context("failed to serialize config value")?
+                );
+            }
+        }
     }
 
     Ok(())
 }
 
 #[derive(Debug, Deserialize)]
-struct Package {
-    name: String,
-    version: String,
+pub(super) struct Package {
+    pub(super) name: String,
+    pub(super) version: String,
 }
 
 #[derive(Debug, Deserialize)]
-struct CargoManifest {
+pub(super) struct CargoManifest {
+    pub(super) package: Package,
+}
+
+/// Plugin crate which config is applied.
+#[derive(Debug)]
+struct TargetPlugin {
+    dir: PathBuf,
     package: Package,
 }
 
+#[derive(Deserialize)]
+struct ManifestService {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PluginManifest {
+    #[serde(default)]
+    services: Vec<ManifestService>,
+}
+
+fn parse_cargo_toml(dir: &Path) -> Result<DocumentMut> {
+    let cargo_toml_path = dir.join("Cargo.toml");
+    fs::read_to_string(&cargo_toml_path)
+        .context(format!(
+            "failed to read Cargo.toml in {}",
+            cargo_toml_path.display()
+        ))?
+        .parse()
+        .context(format!(
+            "failed to parse Cargo.toml in {}",
+            cargo_toml_path.display()
+        ))
+}
+
+/// Reads name and version of the plugin crate, version may be
+/// inherited from the workspace with `version.workspace = true`.
+fn read_package(plugin_dir: &Path, workspace_manifest: Option<&DocumentMut>) -> Result<Package> {
+    let manifest = parse_cargo_toml(plugin_dir)?;
+    let package = manifest.get("package").context(format!(
+        "Cargo.toml in {} has no [package] section",
+        plugin_dir.display()
+    ))?;
+
+    let name = package.get("name").and_then(Item::as_str).context(format!(
+        "package name is missing in Cargo.toml in {}",
+        plugin_dir.display()
+    ))?;
+
+    let version = match package.get("version") {
+        Some(version) if version.get("workspace").and_then(Item::as_bool) == Some(true) => {
+            workspace_manifest
+                .and_then(|m| m.get("workspace")?.get("package")?.get("version")?.as_str())
+                .context(format!(
+                    "package {name} inherits version from workspace, but workspace version is missing"
+                ))?
+        }
+        Some(version) => version.as_str().context(format!(
+            "failed to parse version of package {name} in Cargo.toml"
+        ))?,
+        None => bail!("version of package {name} is missing in Cargo.toml"),
+    };
+
+    Ok(Package {
+        name: name.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// Returns plugins of the workspace, i.e. members with `manifest.yaml.template`.
+fn workspace_plugins(
+    root_dir: &Path,
+    workspace_manifest: &DocumentMut,
+) -> Result<Vec<TargetPlugin>> {
+    let members = workspace_manifest
+        .get("workspace")
+        .and_then(|w| w.get("members"))
+        .and_then(Item::as_array);
+
+    let mut plugins = vec![];
+    for member in members.into_iter().flatten() {
+        let Some(member) = member.as_str() else {
+            continue;
+        };
+        let dir = root_dir.join(member);
+        if !dir.join("manifest.yaml.template").exists() {
+            continue;
+        }
+        let package = read_package(&dir, Some(workspace_manifest))?;
+        plugins.push(TargetPlugin { dir, package });
+    }
+
+    Ok(plugins)
+}
+
+/// Returns names of services declared in `manifest.yaml.template` of the plugin.
+fn declared_services(plugin: &TargetPlugin) -> Result<Vec<String>> {
+    let template_path = plugin.dir.join("manifest.yaml.template");
+    let template = fs::read_to_string(&template_path)
+        .context(format!("failed to read {}", template_path.display()))?;
+
+    let manifest = minijinja::Environment::new()
+        .render_str(
+            &template,
+            minijinja::context! {
+                version => &plugin.package.version,
+                migrations => Vec::<String>::new(),
+            },
+        )
+        .context(format!("failed to render {}", template_path.display()))?;
+    let manifest: PluginManifest = serde_norway::from_str(&manifest)
+        .context(format!("failed to parse {}", template_path.display()))?;
+
+    Ok(manifest.services.into_iter().map(|s| s.name).collect())
+}
+
+/// Resolves the plugin chosen with `--plugin-name`, or the plugin at the plugin path.
+///
+/// Plugin is looked up by directory name first and by package name among
+/// workspace members then. If both `--plugin-name` and `--plugin-version` are passed,
+/// the plugin does not have to be a part of the project.
+fn resolve_plugin(
+    params: &Params,
+    root_dir: &Path,
+    workspace_manifest: Option<&DocumentMut>,
+) -> Result<TargetPlugin> {
+    let Some(plugin_name) = &params.plugin_name else {
+        let mut package = read_package(root_dir, None)?;
+        if let Some(plugin_version) = &params.plugin_version {
+            package.version.clone_from(plugin_version);
+        }
+        return Ok(TargetPlugin {
+            dir: root_dir.to_path_buf(),
+            package,
+        });
+    };
+
+    let member_dir = root_dir.join(plugin_name);
+    let mut plugin = if member_dir.join("Cargo.toml").exists() {
+        Some(TargetPlugin {
+            package: read_package(&member_dir, workspace_manifest)?,
+            dir: member_dir,
+        })
+    } else if let Some(workspace_manifest) = workspace_manifest {
+        workspace_plugins(root_dir, workspace_manifest)?
+            .into_iter()
+            .find(|plugin| &plugin.package.name == plugin_name)
+    } else {
+        Some(TargetPlugin {
+            package: read_package(root_dir, None)?,
+            dir: root_dir.to_path_buf(),
+        })
+        .filter(|plugin| &plugin.package.name == plugin_name)
+    };
+
+    if let Some(plugin_version) = &params.plugin_version {
+        let plugin = plugin.get_or_insert_with(|| TargetPlugin {
+            dir: root_dir.to_path_buf(),
+            package: Package {
+                name: plugin_name.clone(),
+                version: plugin_version.clone(),
+            },
+        });
+        plugin.package.name.clone_from(plugin_name);
+        plugin.package.version.clone_from(plugin_version);
+    }
+
+    plugin.context(format!(
+        "plugin {plugin_name} is not found in {}, pass --plugin-version to apply config \
+        of the plugin outside of the project",
+        root_dir.display()
+    ))
+}
+
+/// Applies single config to the workspace plugins, each plugin receives
+/// sections of the services declared in its manifest.
+fn apply_shared_config(
+    params: &Params,
+    plugins: &[TargetPlugin],
+    mut config: ConfigMap,
+) -> Result<Vec<AppliedConfig>> {
+    let mut plugin_configs = Vec::with_capacity(plugins.len());
+    for plugin in plugins {
+        let plugin_config: ConfigMap = declared_services(plugin)?
+            .iter()
+            .filter_map(|service| config.remove_entry(service))
+            .collect();
+        if plugin_config.is_empty() {
+            info!(
+                "Config has no sections for services of plugin {} - skipping",
+                plugin.package.name
+            );
+            continue;
+        }
+        plugin_configs.push((plugin, plugin_config));
+    }
+
+    if !config.is_empty() {
+        let mut unknown_services: Vec<&String> = config.keys().collect();
+        unknown_services.sort();
+        warn!(
+            "Services {} are not declared by any plugin of the workspace - skipping",
+            unknown_services
+                .iter()
+                .map(|s| format!("'{s}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if plugin_configs.is_empty() {
+        bail!("config has no sections for services of the workspace plugins");
+    }
+
+    let admin_socket = find_admin_socket(params)?;
+    let mut applied = vec![];
+    for (plugin, plugin_config) in plugin_configs {
+        info!("Applying plugin config for plugin {}", plugin.package.name);
+        applied.push(apply_plugin_config(
+            params,
+            plugin,
+            &plugin_config,
+            &admin_socket,
+        )?);
+    }
+
+    Ok(applied)
+}
+
 #[derive(Debug, Clone)]
 pub enum ConfigSource {
     Map(ConfigMap),
@@ -213,14 +632,33 @@ impl Default for ConfigSource {
 pub struct Params {
     #[builder(default, setter(custom))]
     config_source: ConfigSource,
-    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
     data_dir: PathBuf,
-    #[builder(default = "PathBuf::from(\"./\")")]
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
     plugin_path: PathBuf,
     #[builder(default)]
     plugin_name: Option<String>,
-    #[builder(default = "PathBuf::from(\"picodata\")")]
+    /// Version of the plugin, by default it is read from `Cargo.toml` of the plugin.
+    #[builder(default)]
+    plugin_version: Option<String>,
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
     picodata_path: PathBuf,
+    /// Path to schema of the config, by default `config.schema.json`
+    /// in the plugin directory is used if exists.
+    #[builder(default)]
+    schema_path: Option<PathBuf>,
+    /// Print keys which would change instead of applying the config.
+    #[builder(default)]
+    dry_run: bool,
+    /// Print changes as unified diff instead of applying the config.
+    #[builder(default)]
+    diff: bool,
+    /// Waits for a running instance of the cluster, e.g. started right before in background.
+    #[builder(default = "DEFAULT_RETRY_POLICY")]
+    retry: RetryPolicy,
+    /// Print applied statements of each plugin as JSON instead of previews.
+    #[builder(default)]
+    output: OutputFormat,
 }
 
 impl ParamsBuilder {
@@ -236,79 +674,104 @@ impl ParamsBuilder {
     }
 }
 
+fn find_admin_socket(params: &Params) -> Result<PathBuf> {
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let socket_path = params
+        .retry
+        .poll(|| find_active_socket_path(&cluster_dir))?;
+    socket_path.ok_or_else(|| {
+        Error::AdminSocketUnavailable(anyhow!(
+            "failed to find running instance in cluster data dir {}",
+            cluster_dir.display()
+        ))
+        .into()
+    })
+}
+
+fn read_config(params: &Params, plugin_dir: &Path) -> Result<ConfigMap> {
+    match &params.config_source {
+        ConfigSource::Map(map) => Ok(map.clone()),
+        ConfigSource::Path(path) => read_config_from_path(&plugin_dir.join(path)),
+    }
+}
+
 pub fn cmd(params: &Params) -> Result<()> {
-    // If plugin name flag was specified, apply config only for
-    // this exact plugin
-    if let Some(plugin_name) = &params.plugin_name {
-        info!("Applying plugin config for plugin {plugin_name}");
-        apply_plugin_config(params, plugin_name)?;
-        return Ok(());
+    let applied = apply(params)?;
+    if params.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "dry_run": params.dry_run || params.diff,
+                "plugins": applied,
+            }))?
+        );
     }
+    Ok(())
+}
 
+fn apply(params: &Params) -> Result<Vec<AppliedConfig>> {
     let root_dir = env::current_dir()?.join(&params.plugin_path);
+    let root_manifest = parse_cargo_toml(&root_dir)?;
+    let workspace_manifest = root_manifest
+        .get("workspace")
+        .is_some()
+        .then_some(&root_manifest);
 
-    let cargo_toml_path = root_dir.join("Cargo.toml");
-    let cargo_toml_content = fs::read_to_string(&cargo_toml_path).context(format!(
-        "Failed to read Cargo.toml in {}",
-        &cargo_toml_path.display()
-    ))?;
+    // If plugin name flag was specified, apply config only for
+    // this exact plugin
+    let Some(workspace_manifest) = workspace_manifest.filter(|_| params.plugin_name.is_none())
+    else {
+        let plugin = resolve_plugin(params, &root_dir, workspace_manifest)?;
+        info!(
+            "Applying plugin config for plugin {}:{}",
+            plugin.package.name, plugin.package.version
+        );
+        let config = read_config(params, &plugin.dir)?;
+        let applied = apply_plugin_config(params, &plugin, &config, &find_admin_socket(params)?)?;
+        return Ok(vec![applied]);
+    };
 
-    let parsed_toml: DocumentMut = cargo_toml_content
-        .parse()
-        .context("Failed to parse Cargo.toml")?;
+    if params.plugin_version.is_some() {
+        bail!("--plugin-version requires --plugin-name when plugin path points to a workspace");
+    }
 
-    if let Some(workspace) = parsed_toml.get("workspace") {
-        if let ConfigSource::Path(config_path) = &params.config_source {
-            if config_path.to_str().unwrap() != DEFAULT_PLUGIN_CONFIG_PATH {
-                println!("{WISE_PIKE}");
-                process::exit(1);
-            }
-        }
-        info!("Applying plugin config for each plugin");
-
-        if let Some(members) = workspace.get("members") {
-            if let Some(members_array) = members.as_array() {
-                for member in members_array {
-                    let member_str = member.as_str();
-                    if member_str.is_none() {
-                        continue;
-                    }
-
-                    if !root_dir
-                        .join(member_str.unwrap())
-                        .join("manifest.yaml.template")
-                        .exists()
-                    {
-                        continue;
-                    }
-                    apply_plugin_config(params, member_str.unwrap())?;
+    info!("Applying plugin config for each plugin");
+    let plugins = workspace_plugins(&root_dir, workspace_manifest)?;
+
+    match &params.config_source {
+        // Each plugin of the workspace has its own config.
+        ConfigSource::Path(path) if path.as_os_str() == DEFAULT_PLUGIN_CONFIG_PATH => {
+            let admin_socket = find_admin_socket(params)?;
+            let mut applied = vec![];
+            for plugin in &plugins {
+                let config_path = plugin.dir.join(path);
+                if !config_path.exists() {
+                    info!(
+                        "Plugin {} has no {} - skipping",
+                        plugin.package.name, DEFAULT_PLUGIN_CONFIG_PATH
+                    );
+                    continue;
                 }
+                info!("Applying plugin config for plugin {}", plugin.package.name);
+                let config = read_config_from_path(&config_path)?;
+                applied.push(apply_plugin_config(params, plugin, &config, &admin_socket)?);
             }
+            Ok(applied)
+        }
+        _ => {
+            let config = read_config(params, &root_dir)?;
+            apply_shared_config(params, &plugins, config)
         }
-
-        return Ok(());
     }
-
-    info!("Applying plugin config");
-
-    apply_plugin_config(params, "./")?;
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use crate::commands::lib::temp_workspace::TempWorkspace;
 
-    fn tmp_dir(prefix: &str) -> PathBuf {
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let mut dir = env::temp_dir();
-        dir.push(format!("pike-config-apply-ut-{prefix}-{ts}"));
-        dir
+    fn tmp_dir(name: &str) -> TempWorkspace {
+        TempWorkspace::new(&format!("config-apply-{name}")).unwrap()
     }
 
     #[test]
@@ -327,6 +790,7 @@ mod tests {
             "0.1.0",
             "svc",
             &service_cfg,
+            None,
             bogus_socket,
             &bogus_picodata,
         )
@@ -339,12 +803,134 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tier_sections_are_applied_on_tiers() {
+        let config: ConfigMap = serde_norway::from_str(
+            "main:\n  value: common\n  tiers:\n    storage:\n      value: storage\naux:\n  port: 80\n",
+        )
+        .unwrap();
+        let (common, tier_configs) = split_tier_configs(&config).unwrap();
+        assert_eq!(
+            common["main"],
+            HashMap::from([("value".to_string(), "common".into())])
+        );
+        assert_eq!(common["aux"].len(), 1);
+        assert_eq!(
+            service_config_queries(
+                "p",
+                "0.1.0",
+                "main",
+                &tier_configs["main"]["storage"],
+                Some("storage")
+            )
+            .unwrap(),
+            [r#"ALTER PLUGIN "p" 0.1.0 SET "main"."value"='"storage"' ON TIER "storage";"#]
+        );
+        assert!(!tier_configs.contains_key("aux"));
+
+        let package = Package {
+            name: "p".to_string(),
+            version: "0.1.0".to_string(),
+        };
+        let common = HashMap::from([("main".to_string(), common["main"].clone())]);
+        assert_eq!(
+            plugin_config_queries(&package, &common, &tier_configs).unwrap(),
+            [
+                r#"ALTER PLUGIN "p" 0.1.0 SET "main"."value"='"common"';"#,
+                r#"ALTER PLUGIN "p" 0.1.0 SET "main"."value"='"storage"' ON TIER "storage";"#,
+            ]
+        );
+
+        let invalid: ConfigMap = serde_norway::from_str("main:\n  tiers: [storage]\n").unwrap();
+        let err = split_tier_configs(&invalid).unwrap_err();
+        assert!(format!("{err}").contains("must map tier names"), "{err}");
+    }
+
     #[test]
     fn params_builder_has_default_picodata_path() {
         let params = ParamsBuilder::default().build().unwrap();
         assert_eq!(params.picodata_path, PathBuf::from("picodata"));
     }
 
+    fn write_workspace(name: &str) -> TempWorkspace {
+        let workspace = tmp_dir(name);
+        let root = workspace.path();
+        let member = root.join("sub-dir");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"sub-dir\"]\n\n[workspace.package]\nversion = \"1.2.3\"\n",
+        )
+        .unwrap();
+        fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"sub_plugin\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+        fs::write(
+            member.join("manifest.yaml.template"),
+            "name: sub_plugin\nversion: {{ version }}\nservices:\n  - name: main\n  - name: aux\n\
+             migration:\n{% for migration in migrations -%}\n- {{ migration }}\n{% endfor -%}\n",
+        )
+        .unwrap();
+        workspace
+    }
+
+    #[test]
+    fn workspace_plugins_inherit_workspace_version() {
+        let workspace = write_workspace("members");
+        let root = workspace.path();
+        let plugins = workspace_plugins(root, &parse_cargo_toml(root).unwrap()).unwrap();
+
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].dir, root.join("sub-dir"));
+        assert_eq!(plugins[0].package.name, "sub_plugin");
+        assert_eq!(plugins[0].package.version, "1.2.3");
+        assert_eq!(declared_services(&plugins[0]).unwrap(), ["main", "aux"]);
+    }
+
+    #[test]
+    fn resolve_plugin_finds_workspace_member_by_package_name() {
+        let workspace = write_workspace("resolve");
+        let root = workspace.path();
+        let workspace_manifest = parse_cargo_toml(root).unwrap();
+
+        let params = ParamsBuilder::default()
+            .plugin_name(Some("sub_plugin".into()))
+            .build()
+            .unwrap();
+        let plugin = resolve_plugin(&params, root, Some(&workspace_manifest)).unwrap();
+        assert_eq!(plugin.dir, root.join("sub-dir"));
+        assert_eq!(plugin.package.version, "1.2.3");
+
+        let params = ParamsBuilder::default()
+            .plugin_name(Some("unknown".into()))
+            .build()
+            .unwrap();
+        let err = resolve_plugin(&params, root, Some(&workspace_manifest)).unwrap_err();
+        assert!(
+            format!("{err}").contains("plugin unknown is not found"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn resolve_plugin_with_explicit_version_does_not_require_project() {
+        let workspace = write_workspace("explicit");
+        let root = workspace.path();
+        let workspace_manifest = parse_cargo_toml(root).unwrap();
+
+        let params = ParamsBuilder::default()
+            .plugin_name(Some("external".into()))
+            .plugin_version(Some("0.4.0".into()))
+            .build()
+            .unwrap();
+        let plugin = resolve_plugin(&params, root, Some(&workspace_manifest)).unwrap();
+        assert_eq!(plugin.dir, root);
+        assert_eq!(plugin.package.name, "external");
+        assert_eq!(plugin.package.version, "0.4.0");
+    }
+
     #[test]
     fn read_config_from_path_reports_read_error() {
         let dir = tmp_dir("cfg");
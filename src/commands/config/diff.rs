@@ -0,0 +1,277 @@
+//! Comparison of services config applied on the cluster with the local one.
+
+use super::apply::ConfigMap;
+use anyhow::{Context, Result};
+use std::fmt::Write;
+
+/// Number of unchanged lines printed around each change in unified diff.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Change of a single service key which `config apply` would make.
+#[derive(Debug, PartialEq)]
+pub(super) struct KeyChange<'a> {
+    pub(super) service: &'a str,
+    pub(super) key: &'a str,
+    /// Value applied on the cluster, `None` if the key is not set.
+    pub(super) old: Option<&'a serde_norway::Value>,
+    pub(super) new: &'a serde_norway::Value,
+}
+
+/// Returns keys of `desired` config whose values differ from the `current` one,
+/// sorted by service name and key.
+///
+/// Keys which are present only in `current` config are not reported
+/// as `config apply` never removes them.
+pub(super) fn changed_keys<'a>(
+    current: &'a ConfigMap,
+    desired: &'a ConfigMap,
+) -> Vec<KeyChange<'a>> {
+    let mut changes: Vec<KeyChange> = desired
+        .iter()
+        .flat_map(|(service, service_config)| {
+            service_config.iter().map(move |(key, new)| KeyChange {
+                service,
+                key,
+                old: current.get(service).and_then(|c| c.get(key)),
+                new,
+            })
+        })
+        .filter(|change| change.old != Some(change.new))
+        .collect();
+    changes.sort_by(|a, b| (a.service, a.key).cmp(&(b.service, b.key)));
+    changes
+}
+
+fn display_value(value: &serde_norway::Value) -> Result<String> {
+    serde_json::to_string(value).context("failed to serialize config value")
+}
+
+/// Formats changes one per line as `service.key: old -> new`.
+pub(super) fn format_changes(changes: &[KeyChange]) -> Result<String> {
+    let mut out = String::new();
+    for change in changes {
+        let new = display_value(change.new)?;
+        match change.old {
+            Some(old) => writeln!(
+                out,
+                "  ~ {}.{}: {} -> {new}",
+                change.service,
+                change.key,
+                display_value(old)?
+            ),
+            None => writeln!(out, "  + {}.{}: {new}", change.service, change.key),
+        }
+        .expect("writing to string never fails");
+    }
+    Ok(out)
+}
+
+/// Returns `current` and resulting config limited to services of `desired` config.
+pub(super) fn merge(current: &ConfigMap, desired: &ConfigMap) -> (ConfigMap, ConfigMap) {
+    let mut before = ConfigMap::with_capacity(desired.len());
+    let mut after = ConfigMap::with_capacity(desired.len());
+    for (service, service_config) in desired {
+        let current_service_config = current.get(service).cloned().unwrap_or_default();
+        let mut merged = current_service_config.clone();
+        merged.extend(
+            service_config
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        before.insert(service.clone(), current_service_config);
+        after.insert(service.clone(), merged);
+    }
+    (before, after)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes line edit script based on the longest common subsequence.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // lcs[i][j] is the length of LCS of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            ops.push((Op::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((Op::Delete, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| (Op::Delete, *line)));
+    ops.extend(new[j..].iter().map(|line| (Op::Insert, *line)));
+    ops
+}
+
+/// Renders difference between two texts in unified diff format.
+///
+/// Returns empty string if texts are equal.
+pub(super) fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    // Line numbers in old and new text preceding each operation.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for (op, _) in &ops {
+        positions.push((old_pos, new_pos));
+        match op {
+            Op::Equal => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Op::Delete => old_pos += 1,
+            Op::Insert => new_pos += 1,
+        }
+    }
+
+    // Ranges of operations forming hunks, overlapping ones are merged.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (idx, _) in ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+    {
+        let start = idx.saturating_sub(DIFF_CONTEXT_LINES);
+        let end = (idx + 1 + DIFF_CONTEXT_LINES).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Delete).count();
+        // Empty range starts at the line preceding it.
+        let (old_start, new_start) = positions[start];
+        let old_start = if old_len > 0 {
+            old_start + 1
+        } else {
+            old_start
+        };
+        let new_start = if new_len > 0 {
+            new_start + 1
+        } else {
+            new_start
+        };
+
+        writeln!(out, "@@ -{old_start},{old_len} +{new_start},{new_len} @@")
+            .expect("writing to string never fails");
+        for (op, line) in hunk {
+            let prefix = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            writeln!(out, "{prefix}{line}").expect("writing to string never fails");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str, &str)]) -> ConfigMap {
+        let mut config = ConfigMap::new();
+        for (service, key, value) in entries {
+            config
+                .entry((*service).to_string())
+                .or_default()
+                .insert((*key).to_string(), serde_norway::from_str(value).unwrap());
+        }
+        config
+    }
+
+    #[test]
+    fn changed_keys_skips_unchanged_values() {
+        let current = config(&[("main", "value", "a"), ("main", "port", "80")]);
+        let desired = config(&[
+            ("main", "value", "b"),
+            ("main", "port", "80"),
+            ("main", "new", "true"),
+        ]);
+
+        let changes = changed_keys(&current, &desired);
+        assert_eq!(
+            format_changes(&changes).unwrap(),
+            "  + main.new: true\n  ~ main.value: \"a\" -> \"b\"\n"
+        );
+    }
+
+    #[test]
+    fn merge_keeps_keys_absent_in_desired_config() {
+        let current = config(&[
+            ("main", "value", "a"),
+            ("main", "port", "80"),
+            ("aux", "x", "1"),
+        ]);
+        let desired = config(&[("main", "value", "b")]);
+
+        let (before, after) = merge(&current, &desired);
+        assert_eq!(
+            before,
+            config(&[("main", "value", "a"), ("main", "port", "80")])
+        );
+        assert_eq!(
+            after,
+            config(&[("main", "value", "b"), ("main", "port", "80")])
+        );
+    }
+
+    #[test]
+    fn unified_diff_of_equal_texts_is_empty() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+    }
+
+    #[test]
+    fn unified_diff_groups_changes_into_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_of_added_text() {
+        assert_eq!(
+            unified_diff("", "a\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n"
+        );
+    }
+}
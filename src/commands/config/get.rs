@@ -0,0 +1,191 @@
+use super::apply::{CargoManifest, ConfigMap};
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::instance_info::InstanceSocketClient;
+use crate::commands::lib::{find_active_socket_path, get_cluster_dir};
+use crate::error::Error;
+use anyhow::{anyhow, bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::DocumentMut;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
+    data_dir: PathBuf,
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
+    plugin_path: PathBuf,
+    #[builder(default)]
+    plugin_name: Option<String>,
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
+    picodata_path: PathBuf,
+    /// Names of services to export, all services are exported if empty.
+    #[builder(default)]
+    services: Vec<String>,
+}
+
+fn read_cargo_manifest(params: &Params) -> Result<CargoManifest> {
+    let plugin_dir = params
+        .plugin_path
+        .join(params.plugin_name.as_deref().unwrap_or("./"));
+    let cargo_toml_path = plugin_dir.join("Cargo.toml");
+    let cargo_toml_content = fs::read_to_string(&cargo_toml_path).context(format!(
+        "failed to read Cargo.toml in {}",
+        cargo_toml_path.display()
+    ))?;
+
+    let parsed_toml: DocumentMut = cargo_toml_content
+        .parse()
+        .context("failed to parse Cargo.toml")?;
+    if parsed_toml.get("workspace").is_some() && params.plugin_name.is_none() {
+        bail!("plugin path points to a workspace, choose the plugin with --plugin-name");
+    }
+
+    toml::from_str(&cargo_toml_content).context("failed to parse Cargo.toml")
+}
+
+fn filter_services(mut config: ConfigMap, services: &[String]) -> ConfigMap {
+    if services.is_empty() {
+        return config;
+    }
+
+    for service in services {
+        if !config.contains_key(service) {
+            warn!("Service '{service}' has no config in the cluster - skipping");
+        }
+    }
+    config.retain(|service_name, _| services.contains(service_name));
+    config
+}
+
+/// Renders config as YAML in the format of `plugin_config.yaml`.
+///
+/// Services and their keys are sorted to keep the output stable between runs.
+pub(super) fn render_config(config: &ConfigMap) -> Result<String> {
+    let sorted: BTreeMap<&String, BTreeMap<&String, &serde_norway::Value>> = config
+        .iter()
+        .map(|(service_name, service_config)| (service_name, service_config.iter().collect()))
+        .collect();
+
+    serde_norway::to_string(&sorted).context("failed to serialize plugin config to YAML")
+}
+
+/// Reads services config of the plugin through admin socket of the instance.
+pub(super) fn fetch_from_instance(
+    instance_dir: &Path,
+    picodata_path: &PathBuf,
+    plugin_name: &str,
+    plugin_version: &str,
+) -> Result<ConfigMap> {
+    let raw_config = InstanceSocketClient::new(instance_dir, picodata_path)
+        .plugin_config(plugin_name, plugin_version)
+        .context(format!(
+            "failed to fetch config of plugin {plugin_name}:{plugin_version}"
+        ))?;
+
+    let mut config = ConfigMap::with_capacity(raw_config.len());
+    for (service_name, service_config) in raw_config {
+        let mut properties = HashMap::with_capacity(service_config.len());
+        for (key, value) in service_config {
+            let value = serde_norway::to_value(value)
+                .context(format!("failed to convert value with key {key}"))?;
+            properties.insert(key, value);
+        }
+        config.insert(service_name, properties);
+    }
+
+    Ok(config)
+}
+
+/// Fetches services config of the plugin from the running cluster.
+pub fn fetch(params: &Params) -> Result<ConfigMap> {
+    let cargo_manifest = read_cargo_manifest(params)?;
+    let (plugin_name, plugin_version) = (
+        &cargo_manifest.package.name,
+        &cargo_manifest.package.version,
+    );
+
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let Some(socket_path) = find_active_socket_path(&cluster_dir)? else {
+        return Err(Error::AdminSocketUnavailable(anyhow!(
+            "failed to find running instance in cluster data dir {}",
+            cluster_dir.display()
+        ))
+        .into());
+    };
+    let instance_dir = socket_path
+        .parent()
+        .context("admin socket path has no parent directory")?;
+
+    info!("Fetching plugin config for plugin {plugin_name}:{plugin_version}");
+
+    let config = fetch_from_instance(
+        instance_dir,
+        &params.picodata_path,
+        plugin_name,
+        plugin_version,
+    )?;
+
+    Ok(filter_services(config, &params.services))
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let config = fetch(params)?;
+    print!("{}", render_config(&config)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_fixture() -> ConfigMap {
+        HashMap::from([
+            (
+                "main".to_string(),
+                HashMap::from([
+                    (
+                        "value".to_string(),
+                        serde_norway::to_value("changed").unwrap(),
+                    ),
+                    ("answer".to_string(), serde_norway::to_value(42).unwrap()),
+                ]),
+            ),
+            (
+                "aux".to_string(),
+                HashMap::from([("enabled".to_string(), serde_norway::to_value(true).unwrap())]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn render_config_is_sorted_and_round_trips() {
+        let config = config_fixture();
+        let yaml = render_config(&config).unwrap();
+
+        assert_eq!(
+            yaml,
+            "aux:\n  enabled: true\nmain:\n  answer: 42\n  value: changed\n"
+        );
+
+        let parsed: ConfigMap = serde_norway::from_str(&yaml).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn filter_services_keeps_only_requested() {
+        let config = filter_services(config_fixture(), &["main".to_string(), "nope".to_string()]);
+        assert_eq!(config.len(), 1);
+        assert!(config.contains_key("main"));
+    }
+
+    #[test]
+    fn filter_services_keeps_everything_when_empty() {
+        let config = filter_services(config_fixture(), &[]);
+        assert_eq!(config.len(), 2);
+    }
+}
@@ -1 +1,4 @@
 pub(crate) mod apply;
+mod diff;
+pub(crate) mod get;
+pub(crate) mod schema;
@@ -0,0 +1,267 @@
+//! Schema of the plugin services config.
+//!
+//! Schema is a subset of [JSON Schema](https://json-schema.org/): only `type`,
+//! `properties` and `additionalProperties` keywords are taken into account.
+//! It is either provided by the plugin author in [`SCHEMA_FILE_NAME`] file
+//! or derived from `default_configuration` of services in `manifest.yaml`.
+//! Derived schema only guesses the types from default values, so it leaves
+//! objects open and treats every number as `number`.
+
+use super::apply::ConfigMap;
+use crate::error::Error;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{fs, path::Path};
+
+/// Name of the schema file in plugin project and in plugin archive.
+pub const SCHEMA_FILE_NAME: &str = "config.schema.json";
+
+#[derive(Deserialize)]
+struct ManifestService {
+    name: String,
+    #[serde(default)]
+    default_configuration: Value,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    services: Vec<ManifestService>,
+}
+
+fn schema_for_value(value: &Value) -> Value {
+    match value {
+        // Nothing is known about the type of the key.
+        Value::Null => json!({}),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        // Integer default may be overridden with a float.
+        Value::Number(_) => json!({ "type": "number" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(_) => json!({ "type": "array" }),
+        Value::Object(map) => {
+            let properties: Map<String, Value> = map
+                .iter()
+                .map(|(key, value)| (key.clone(), schema_for_value(value)))
+                .collect();
+            // Keys missing from the default, e.g. entries of a map, are allowed.
+            json!({
+                "type": "object",
+                "properties": properties,
+            })
+        }
+    }
+}
+
+/// Derives schema from `default_configuration` of services declared in `manifest.yaml`.
+pub fn derive_from_manifest(manifest_path: &Path) -> Result<Value> {
+    let manifest: Manifest = serde_norway::from_str(
+        &fs::read_to_string(manifest_path)
+            .context(format!("failed to read {}", manifest_path.display()))?,
+    )
+    .context(format!("failed to parse {}", manifest_path.display()))?;
+
+    let services: Map<String, Value> = manifest
+        .services
+        .iter()
+        .map(|service| {
+            let schema = match &service.default_configuration {
+                // Service without default configuration has no keys to configure.
+                Value::Null => schema_for_value(&Value::Object(Map::new())),
+                default_configuration => schema_for_value(default_configuration),
+            };
+            (service.name.clone(), schema)
+        })
+        .collect();
+
+    Ok(json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "properties": services,
+    }))
+}
+
+/// Reads schema from JSON file.
+pub fn load(schema_path: &Path) -> Result<Value> {
+    serde_json::from_str(
+        &fs::read_to_string(schema_path)
+            .context(format!("failed to read {}", schema_path.display()))?,
+    )
+    .context(format!(
+        "failed to parse config schema at {}",
+        schema_path.display()
+    ))
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        // Unknown types are not validated.
+        _ => true,
+    }
+}
+
+fn collect_violations(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    let expected_types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    if !expected_types.is_empty() && !expected_types.iter().any(|t| type_matches(t, value)) {
+        violations.push(format!(
+            "{path}: expected {}, got {value}",
+            expected_types.join(" or ")
+        ));
+        return;
+    }
+
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let additional_allowed = schema
+        .get("additionalProperties")
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+
+    for (key, nested_value) in object {
+        let nested_path = format!("{path}.{key}");
+        match properties.and_then(|p| p.get(key)) {
+            Some(nested_schema) => {
+                collect_violations(nested_schema, nested_value, &nested_path, violations);
+            }
+            None if !additional_allowed => {
+                violations.push(format!("{nested_path}: unknown key"));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Validates services config against the schema.
+///
+/// All violations are reported in a single error.
+pub fn validate_config(schema: &Value, config: &ConfigMap) -> Result<()> {
+    let config = serde_json::to_value(config).context("failed to convert config to JSON")?;
+
+    let mut violations = vec![];
+    collect_violations(schema, &config, "$", &mut violations);
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    violations.sort();
+    Err(Error::InvalidConfig(anyhow!(
+        "plugin config does not match the schema:\n  {}",
+        violations.join("\n  ")
+    ))
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::collections::HashMap;
+
+    fn manifest_schema(name: &str, manifest: &str) -> Value {
+        let workspace = TempWorkspace::new(&format!("config-schema-{name}")).unwrap();
+        let path = workspace.join("manifest.yaml");
+        fs::write(&path, manifest).unwrap();
+        derive_from_manifest(&path).unwrap()
+    }
+
+    fn config(service: &str, key: &str, value: &str) -> ConfigMap {
+        HashMap::from([(
+            service.to_string(),
+            HashMap::from([(key.to_string(), serde_norway::from_str(value).unwrap())]),
+        )])
+    }
+
+    const MANIFEST: &str = r"
+name: test
+version: 0.1.0
+services:
+  - name: main
+    default_configuration:
+      value: example
+      port: 8080
+      ratio: 0.5
+      nested:
+        enabled: true
+  - name: empty
+";
+
+    #[test]
+    fn derived_schema_accepts_matching_config() {
+        let schema = manifest_schema("accepts", MANIFEST);
+        validate_config(&schema, &config("main", "value", "changed")).unwrap();
+        validate_config(&schema, &config("main", "ratio", "1")).unwrap();
+        validate_config(&schema, &config("main", "nested", "{enabled: false}")).unwrap();
+    }
+
+    #[test]
+    fn derived_schema_allows_keys_missing_from_defaults() {
+        let schema = manifest_schema("allows-unknown", MANIFEST);
+
+        validate_config(&schema, &config("other", "value", "x")).unwrap();
+        validate_config(&schema, &config("empty", "value", "x")).unwrap();
+        validate_config(&schema, &config("main", "nested", "{extra: 1}")).unwrap();
+        // Integer default overridden with a float.
+        validate_config(&schema, &config("main", "port", "8080.5")).unwrap();
+    }
+
+    #[test]
+    fn declared_schema_rejects_unknown_key() {
+        let schema = json!({
+            "properties": {
+                "main": { "properties": {}, "additionalProperties": false }
+            },
+            "additionalProperties": false
+        });
+
+        let err = validate_config(&schema, &config("other", "value", "x")).unwrap_err();
+        assert!(format!("{err}").contains("$.other: unknown key"), "{err}");
+
+        let err = validate_config(&schema, &config("main", "value", "x")).unwrap_err();
+        assert!(
+            format!("{err}").contains("$.main.value: unknown key"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn derived_schema_rejects_wrong_types() {
+        let schema = manifest_schema("rejects-types", MANIFEST);
+
+        let err = validate_config(&schema, &config("main", "port", "'80'")).unwrap_err();
+        assert!(
+            format!("{err}").contains("$.main.port: expected number"),
+            "{err}"
+        );
+
+        let err = validate_config(&schema, &config("main", "nested", "{enabled: 1}")).unwrap_err();
+        assert!(
+            format!("{err}").contains("$.main.nested.enabled: expected boolean"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn schema_allows_additional_properties_by_default() {
+        let schema = json!({
+            "properties": {
+                "main": { "properties": { "value": { "type": ["string", "null"] } } }
+            }
+        });
+        validate_config(&schema, &config("main", "value", "~")).unwrap();
+        validate_config(&schema, &config("main", "other", "1")).unwrap();
+        validate_config(&schema, &config("other", "other", "1")).unwrap();
+    }
+}
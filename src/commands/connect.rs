@@ -0,0 +1,260 @@
+//! Interactive `psql` session to an instance of the cluster started by pike.
+//!
+//! Pg port of the instance is read from `cluster.json`. Unless `--user` is set,
+//! the session is opened by the `pike` user created on the first connect with
+//! a random password kept in the cluster data dir, so `psql` doesn't ask for it.
+
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::lib::instance_info::{InstanceSocketClient, Row};
+use crate::commands::lib::sql::{quote_ident, quote_literal};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use rand::RngExt;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// User created by pike for `connect` sessions.
+pub const DEFAULT_USER: &str = "pike";
+
+const PASSWORD_FILE_NAME: &str = "pike_password";
+const PASSWORD_LEN: usize = 24;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"psql\")")]
+    psql_path: PathBuf,
+    /// Instance to connect to, the first running one of `tier` if not set.
+    #[builder(default)]
+    instance_name: Option<String>,
+    #[builder(default)]
+    tier: Option<String>,
+    /// Existing user to connect as, `psql` asks for its password.
+    #[builder(default)]
+    user: Option<String>,
+    /// Arguments passed to `psql` as is, e.g. `-c 'SELECT 1'`.
+    #[builder(default)]
+    psql_args: Vec<String>,
+}
+
+/// Chooses the instance by name or the first running instance of the tier.
+fn select_instance<'a>(
+    instances: &'a [InstanceMetadata],
+    instance_name: Option<&str>,
+    tier: Option<&str>,
+    is_running: impl Fn(&InstanceMetadata) -> bool,
+) -> Result<&'a InstanceMetadata> {
+    if let Some(name) = instance_name {
+        let Some(instance) = instances.iter().find(|instance| instance.name == name) else {
+            let known: Vec<&str> = instances.iter().map(|i| i.name.as_str()).collect();
+            bail!(
+                "instance '{name}' is not found in the cluster, known instances: {}",
+                known.join(", ")
+            );
+        };
+        if tier.is_some_and(|tier| tier != instance.tier) {
+            bail!(
+                "instance '{name}' belongs to tier '{}', not '{}'",
+                instance.tier,
+                tier.unwrap_or_default()
+            );
+        }
+        if !is_running(instance) {
+            bail!("instance '{name}' is not running");
+        }
+        return Ok(instance);
+    }
+
+    let mut candidates = instances
+        .iter()
+        .filter(|instance| tier.is_none_or(|tier| tier == instance.tier))
+        .peekable();
+    if candidates.peek().is_none() {
+        bail!("tier '{}' has no instances", tier.unwrap_or_default());
+    }
+    candidates
+        .find(|instance| is_running(instance))
+        .context("cluster has no running instances, start it with `cargo pike run`")
+}
+
+/// Password which satisfies the default password policy of picodata:
+/// upper and lower case letters and digits.
+fn generate_password() -> String {
+    const CLASSES: [&[u8]; 3] = [
+        b"ABCDEFGHJKLMNPQRSTUVWXYZ",
+        b"abcdefghijkmnopqrstuvwxyz",
+        b"23456789",
+    ];
+    let mut rng = rand::rng();
+    (0..PASSWORD_LEN)
+        .map(|i| {
+            let class = CLASSES[i % CLASSES.len()];
+            char::from(class[rng.random_range(0..class.len())])
+        })
+        .collect()
+}
+
+/// Password of [`DEFAULT_USER`] saved in `cluster_dir`, generated if missing.
+fn default_user_password(cluster_dir: &Path) -> Result<String> {
+    let path = cluster_dir.join(PASSWORD_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(password) => return Ok(password.trim().to_string()),
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err).context(format!("failed to read {}", path.display())),
+    }
+    let password = generate_password();
+    fs::write(&path, &password).context(format!("failed to write {}", path.display()))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+        .context(format!("failed to set permissions of {}", path.display()))?;
+    Ok(password)
+}
+
+/// Statements creating [`DEFAULT_USER`] allowed to manage and query tables.
+fn create_user_queries(user: &str, password: &str) -> Vec<String> {
+    let user = quote_ident(user);
+    let mut queries = vec![format!(
+        "CREATE USER IF NOT EXISTS {user} WITH PASSWORD {} USING md5",
+        quote_literal(password)
+    )];
+    queries.extend(
+        ["CREATE", "ALTER", "DROP", "READ", "WRITE"]
+            .map(|privilege| format!("GRANT {privilege} TABLE TO {user}")),
+    );
+    queries
+}
+
+/// Creates [`DEFAULT_USER`] on the instance unless it exists and returns its password.
+pub(crate) fn ensure_default_user(
+    cluster_dir: &Path,
+    instance: &InstanceMetadata,
+    picodata_path: &PathBuf,
+) -> Result<String> {
+    let password = default_user_password(cluster_dir)?;
+    let client = InstanceSocketClient::new(&instance.data_dir, picodata_path);
+    for query in create_user_queries(DEFAULT_USER, &password) {
+        client
+            .query::<Row>(&query)
+            .context(format!("failed to create user '{DEFAULT_USER}'"))?;
+    }
+    Ok(password)
+}
+
+/// Opens `psql` session to the chosen instance, the process of pike is
+/// replaced with `psql`, so the function returns only on errors.
+pub fn cmd(params: &Params) -> Result<()> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(metadata) = ClusterMetadata::load(&path)? else {
+        bail!(
+            "cluster in {} has never been started",
+            params.plugin_path.join(&params.data_dir).display()
+        );
+    };
+    let instance = select_instance(
+        &metadata.instances,
+        params.instance_name.as_deref(),
+        params.tier.as_deref(),
+        |instance| UnixStream::connect(&instance.admin_socket).is_ok(),
+    )?;
+
+    let mut psql = Command::new(&params.psql_path);
+    let user = if let Some(user) = &params.user {
+        user.clone()
+    } else {
+        let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+        let password = ensure_default_user(&cluster_dir, instance, &params.picodata_path)?;
+        psql.env("PGPASSWORD", password);
+        DEFAULT_USER.to_string()
+    };
+
+    info!(
+        "Connecting to instance '{}' at 127.0.0.1:{} as '{user}'",
+        instance.name, instance.pg_port
+    );
+    let err = psql
+        .arg(format!(
+            "host=127.0.0.1 port={} user={user} dbname=postgres sslmode=prefer",
+            instance.pg_port
+        ))
+        .args(&params.psql_args)
+        .exec();
+    if err.kind() == ErrorKind::NotFound {
+        bail!(
+            "{} is not found, install PostgreSQL client or pass its path with --psql-path",
+            params.psql_path.display()
+        );
+    }
+    Err(err).context(format!("failed to execute {}", params.psql_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(name: &str, tier: &str) -> InstanceMetadata {
+        InstanceMetadata {
+            name: name.to_string(),
+            instance_id: 1,
+            tier: tier.to_string(),
+            pid: 1,
+            data_dir: PathBuf::from("tmp/cluster/i1"),
+            admin_socket: PathBuf::from("tmp/cluster/i1/admin.sock"),
+            bin_port: 3001,
+            http_port: 8001,
+            pg_port: 5433,
+            restarts: 0,
+        }
+    }
+
+    #[test]
+    fn instance_is_selected_by_name_or_tier() {
+        let instances = [
+            instance("default_1_1", "default"),
+            instance("default_2_1", "default"),
+            instance("storage_1_1", "storage"),
+        ];
+        let running = |i: &InstanceMetadata| i.name != "default_1_1";
+        let name = |name, tier| {
+            select_instance(&instances, name, tier, running)
+                .map(|instance| instance.name.as_str())
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(name(None, None), Ok("default_2_1"));
+        assert_eq!(name(None, Some("storage")), Ok("storage_1_1"));
+        assert_eq!(name(Some("storage_1_1"), None), Ok("storage_1_1"));
+        assert!(name(Some("default_1_1"), None)
+            .unwrap_err()
+            .contains("is not running"));
+        assert!(name(Some("storage_1_1"), Some("default"))
+            .unwrap_err()
+            .contains("belongs to tier 'storage'"));
+        assert!(name(None, Some("router"))
+            .unwrap_err()
+            .contains("has no instances"));
+        assert!(name(Some("router_1_1"), None)
+            .unwrap_err()
+            .contains("known instances: default_1_1, default_2_1, storage_1_1"));
+    }
+
+    #[test]
+    fn password_satisfies_picodata_policy() {
+        let password = generate_password();
+        assert_eq!(password.len(), PASSWORD_LEN);
+        assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+        assert!(password.chars().any(|c| c.is_ascii_lowercase()));
+        assert!(password.chars().any(|c| c.is_ascii_digit()));
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+}
@@ -0,0 +1,309 @@
+//! Opening core dump of a crashed instance in gdb or lldb together with
+//! the picodata binary and shared libraries of the plugins.
+
+use crate::commands::lib::{
+    core_pattern, find_executable, get_cluster_dir, shell_command, BuildType, LIB_EXT,
+};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Debugger {
+    Gdb,
+    Lldb,
+}
+
+impl FromStr for Debugger {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gdb" => Ok(Self::Gdb),
+            "lldb" => Ok(Self::Lldb),
+            _ => bail!("unknown debugger '{s}', expected gdb or lldb"),
+        }
+    }
+}
+
+impl Debugger {
+    fn binary(self) -> &'static str {
+        match self {
+            Self::Gdb => "gdb",
+            Self::Lldb => "lldb",
+        }
+    }
+
+    /// Arguments of the debugger which opens `core` of `executable`
+    /// and looks for shared libraries in `lib_dirs`.
+    ///
+    /// Search paths are set before the core is loaded, so libraries
+    /// of the plugins are resolved right away.
+    fn args(self, executable: &Path, core: &Path, lib_dirs: &[PathBuf]) -> Vec<String> {
+        let executable = executable.display().to_string();
+        let core = core.display().to_string();
+        let mut args = vec![];
+
+        match self {
+            Self::Gdb => {
+                if !lib_dirs.is_empty() {
+                    let dirs: Vec<String> =
+                        lib_dirs.iter().map(|d| d.display().to_string()).collect();
+                    args.push("-iex".to_string());
+                    args.push(format!("set solib-search-path {}", dirs.join(":")));
+                }
+                args.extend([executable, core]);
+            }
+            Self::Lldb => {
+                for dir in lib_dirs {
+                    args.push("-O".to_string());
+                    args.push(format!(
+                        "settings append target.exec-search-paths {}",
+                        dir.display()
+                    ));
+                }
+                args.extend([executable, "--core".to_string(), core]);
+            }
+        }
+
+        args
+    }
+}
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    instance_name: String,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"target\")")]
+    target_dir: PathBuf,
+    /// Core dump to open, the newest one from the instance directory by default.
+    #[builder(default)]
+    core_path: Option<PathBuf>,
+    /// Debugger to use, gdb or lldb, whichever is installed by default.
+    #[builder(default)]
+    debugger: Option<Debugger>,
+    /// Print the debugger command without running it.
+    #[builder(default)]
+    print_only: bool,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let instance_dir = cluster_dir.join(&params.instance_name);
+    if !instance_dir.is_dir() {
+        bail!(
+            "failed to find instance data directory with path {}",
+            instance_dir.display()
+        );
+    }
+
+    let core = match &params.core_path {
+        Some(path) => path.clone(),
+        None => find_core(&instance_dir)?.with_context(|| no_core_hint(&instance_dir))?,
+    };
+    let core = std::path::absolute(&core)
+        .context(format!("failed to get absolute path of {}", core.display()))?;
+    info!("Using core dump {}", core.display());
+
+    let picodata_path = find_executable(&params.picodata_path).context(format!(
+        "failed to find picodata binary {}",
+        params.picodata_path.display()
+    ))?;
+
+    let mut share_dirs: Vec<PathBuf> = [BuildType::Debug, BuildType::Release]
+        .iter()
+        .map(|profile| {
+            params
+                .plugin_path
+                .join(&params.target_dir)
+                .join(profile.to_string())
+        })
+        .collect();
+    share_dirs.push(cluster_dir.join("plugins"));
+    let lib_dirs = plugin_lib_dirs(&share_dirs)?;
+    if lib_dirs.is_empty() {
+        warn!("no built plugins found, symbols of plugins won't be resolved");
+    }
+
+    let (debugger, installed) = match params.debugger {
+        Some(debugger) => (
+            debugger,
+            find_executable(Path::new(debugger.binary())).is_some(),
+        ),
+        None => [Debugger::Gdb, Debugger::Lldb]
+            .into_iter()
+            .find(|d| find_executable(Path::new(d.binary())).is_some())
+            .map_or((Debugger::Gdb, false), |d| (d, true)),
+    };
+    let args = debugger.args(&picodata_path, &core, &lib_dirs);
+
+    println!("{}", shell_command(debugger.binary(), &args));
+    if params.print_only {
+        return Ok(());
+    }
+    if !installed {
+        warn!(
+            "{} is not installed, run the command above when it is available",
+            debugger.binary()
+        );
+        return Ok(());
+    }
+
+    let status = Command::new(debugger.binary())
+        .args(&args)
+        .status()
+        .context(format!("failed to execute {}", debugger.binary()))?;
+    if !status.success() {
+        bail!("{} exited with {status}", debugger.binary());
+    }
+
+    Ok(())
+}
+
+/// Returns the newest `core` or `core.<pid>` file in the instance directory.
+fn find_core(instance_dir: &Path) -> Result<Option<PathBuf>> {
+    let mut newest = None;
+    for entry in
+        fs::read_dir(instance_dir).context(format!("failed to read {}", instance_dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name != "core" && !name.starts_with("core.") {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified()?;
+        if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+fn no_core_hint(instance_dir: &Path) -> String {
+    let hint = match core_pattern() {
+        Some(pattern) if pattern.starts_with('|') => {
+            let pid = fs::read_to_string(instance_dir.join("pid"))
+                .map_or_else(|_| "<pid>".to_string(), |pid| pid.trim().to_string());
+            format!("core dumps are piped to '{pattern}', try `coredumpctl debug {pid}`")
+        }
+        _ => "set `core_dumps = true` in [process] section of the topology \
+            to keep core dumps in the instance directory"
+            .to_string(),
+    };
+    format!(
+        "no core dump found in {}: {hint}, or pass the core with --core-path",
+        instance_dir.display()
+    )
+}
+
+/// Returns directories `<share_dir>/<plugin>/<version>` which contain shared libraries.
+fn plugin_lib_dirs(share_dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let lib_suffix = format!(".{LIB_EXT}");
+    let mut lib_dirs = vec![];
+
+    for share_dir in share_dirs.iter().filter(|dir| dir.is_dir()) {
+        for plugin_dir in subdirs(share_dir)? {
+            for version_dir in subdirs(&plugin_dir)? {
+                let has_lib = fs::read_dir(&version_dir)?
+                    .filter_map(Result::ok)
+                    .any(|e| e.file_name().to_string_lossy().ends_with(&lib_suffix));
+                if has_lib {
+                    lib_dirs.push(std::path::absolute(&version_dir)?);
+                }
+            }
+        }
+    }
+
+    Ok(lib_dirs)
+}
+
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("failed to read {}", dir.display()))?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn debugger_args_set_library_paths_before_core() {
+        let lib_dirs = [PathBuf::from("/p/a/0.1.0"), PathBuf::from("/p/b/0.2.0")];
+        let picodata = Path::new("/usr/bin/picodata");
+        let core = Path::new("/data/i2/core.42");
+
+        let gdb = Debugger::Gdb.args(picodata, core, &lib_dirs);
+        assert_eq!(
+            shell_command("gdb", &gdb),
+            "gdb -iex 'set solib-search-path /p/a/0.1.0:/p/b/0.2.0' \
+            /usr/bin/picodata /data/i2/core.42"
+        );
+
+        let lldb = Debugger::Lldb.args(picodata, core, &lib_dirs[..1]);
+        assert_eq!(
+            shell_command("lldb", &lldb),
+            "lldb -O 'settings append target.exec-search-paths /p/a/0.1.0' \
+            /usr/bin/picodata --core /data/i2/core.42"
+        );
+
+        assert_eq!(
+            Debugger::Gdb.args(picodata, core, &[]),
+            ["/usr/bin/picodata", "/data/i2/core.42"]
+        );
+    }
+
+    #[test]
+    fn newest_core_is_found() {
+        let workspace = TempWorkspace::new("debug-core-find").unwrap();
+        assert_eq!(find_core(workspace.path()).unwrap(), None);
+
+        fs::write(workspace.join("picodata.log"), "").unwrap();
+        let old = fs::File::create(workspace.join("core.1")).unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        let new = fs::File::create(workspace.join("core.2")).unwrap();
+        new.set_modified(SystemTime::now()).unwrap();
+
+        assert_eq!(
+            find_core(workspace.path()).unwrap(),
+            Some(workspace.join("core.2"))
+        );
+    }
+
+    #[test]
+    fn only_dirs_with_libraries_are_searched() {
+        let workspace = TempWorkspace::new("debug-core-libs").unwrap();
+        let with_lib = workspace.join("debug/plugin_a/0.1.0");
+        let without_lib = workspace.join("debug/plugin_b/0.1.0");
+        fs::create_dir_all(&with_lib).unwrap();
+        fs::create_dir_all(&without_lib).unwrap();
+        fs::write(with_lib.join(format!("libplugin_a.{LIB_EXT}")), "").unwrap();
+        fs::write(without_lib.join("manifest.yaml"), "").unwrap();
+
+        let lib_dirs =
+            plugin_lib_dirs(&[workspace.join("debug"), workspace.join("missing")]).unwrap();
+        assert_eq!(lib_dirs, [with_lib]);
+    }
+}
@@ -0,0 +1,154 @@
+//! SQL or Lua run on a chosen instance of the cluster through its admin socket,
+//! e.g. to look at the state local to a replicaset.
+
+use crate::commands::lib::cluster_metadata::ClusterMetadata;
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::sql::quote_lua;
+use crate::commands::lib::{get_cluster_dir, run_query_in_picodata_admin};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    /// Name of the instance, e.g. `default_1_2`, or its directory, e.g. `i2`.
+    instance_name: String,
+    /// SQL statements or Lua code, read from `file` if not set.
+    #[builder(default)]
+    query: Option<String>,
+    #[builder(default)]
+    file: Option<PathBuf>,
+    /// Run Lua code instead of SQL.
+    #[builder(default = "false")]
+    lua: bool,
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
+    data_dir: PathBuf,
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
+    plugin_path: PathBuf,
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
+    picodata_path: PathBuf,
+}
+
+/// Runs the query on the instance and prints output of `picodata admin`.
+pub fn cmd(params: &Params) -> Result<()> {
+    let query = match (&params.query, &params.file) {
+        (Some(query), None) => query.clone(),
+        (None, Some(file)) => fs::read_to_string(file)
+            .context(format!("failed to read query from {}", file.display()))?,
+        (Some(_), Some(_)) => bail!("query and --file can't be used together"),
+        (None, None) => bail!("query or --file is required"),
+    };
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let socket_path = admin_socket(
+        &params.plugin_path,
+        &params.data_dir,
+        &cluster_dir,
+        &params.instance_name,
+    )?;
+
+    let script = if params.lua {
+        lua_script(&query)
+    } else {
+        sql_script(&query)
+    };
+    let output =
+        run_query_in_picodata_admin(&params.picodata_path, &socket_path, &script).context(
+            format!("failed to run query on instance '{}'", params.instance_name),
+        )?;
+    print!("{output}");
+    Ok(())
+}
+
+/// Admin socket of the running instance, instances are found by their names
+/// as symlinks and by their directories in the cluster directory.
+fn admin_socket(
+    plugin_path: &Path,
+    data_dir: &Path,
+    cluster_dir: &Path,
+    instance_name: &str,
+) -> Result<PathBuf> {
+    let instance_dir = cluster_dir.join(instance_name);
+    if instance_name.contains('/') || !instance_dir.is_dir() {
+        let path = ClusterMetadata::path(plugin_path, data_dir);
+        let known = ClusterMetadata::load(&path)?
+            .map(|metadata| {
+                metadata
+                    .instances
+                    .into_iter()
+                    .map(|instance| instance.name)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if known.is_empty() {
+            bail!(
+                "instance '{instance_name}' is not found in {}",
+                cluster_dir.display()
+            );
+        }
+        bail!(
+            "instance '{instance_name}' is not found in the cluster, known instances: {}",
+            known.join(", ")
+        );
+    }
+    let socket_path = instance_dir.join("admin.sock");
+    if UnixStream::connect(&socket_path).is_err() {
+        bail!("instance '{instance_name}' is not running");
+    }
+    Ok(socket_path)
+}
+
+/// SQL for the admin console, which runs statements terminated with `;`.
+fn sql_script(query: &str) -> String {
+    let query = query.trim();
+    if query.ends_with(';') {
+        format!("{query}\n")
+    } else {
+        format!("{query};\n")
+    }
+}
+
+/// Lua code for the admin console, which runs it line by line, so it is
+/// passed as a single string. Expressions are evaluated like in the console.
+fn lua_script(code: &str) -> String {
+    format!(
+        "\\lua\nlocal code = {}; \
+        local chunk = loadstring('return ' .. code) or assert(loadstring(code)); \
+        return chunk()\n",
+        quote_lua(code.trim())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn queries_are_prepared_for_admin_console() {
+        assert_eq!(sql_script(" SELECT 1 \n"), "SELECT 1;\n");
+        assert_eq!(sql_script("SELECT 1;\nSELECT 2;"), "SELECT 1;\nSELECT 2;\n");
+
+        let script = lua_script("local x = 1\nreturn x\n");
+        assert_eq!(script.lines().count(), 2, "{script}");
+        assert!(script.starts_with("\\lua\nlocal code = "));
+        assert!(script.contains(&quote_lua("local x = 1\nreturn x")));
+    }
+
+    #[test]
+    fn instance_is_found_in_cluster_dir() {
+        let workspace = TempWorkspace::new("exec").unwrap();
+        let data_dir = Path::new("tmp");
+        let cluster_dir = workspace.join("tmp/cluster");
+        fs::create_dir_all(cluster_dir.join("i1")).unwrap();
+        let socket = |name: &str| admin_socket(workspace.path(), data_dir, &cluster_dir, name);
+
+        let err = socket("i2").unwrap_err();
+        assert!(err.to_string().contains("'i2' is not found"), "{err}");
+        let err = socket("../cluster/i1").unwrap_err();
+        assert!(err.to_string().contains("is not found"), "{err}");
+        let err = socket("i1").unwrap_err();
+        assert_eq!(err.to_string(), "instance 'i1' is not running");
+    }
+}
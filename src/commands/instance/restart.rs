@@ -0,0 +1,86 @@
+//! Restart of a single instance of the running cluster.
+
+use crate::commands::{run, stop};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use nix::sys::signal::Signal;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Instance is stopped gracefully to let the cluster notice its leave.
+pub const DEFAULT_RESTART_SIGNAL: Signal = Signal::SIGTERM;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    instance_name: String,
+    topology: run::Topology,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = "false")]
+    use_release: bool,
+    #[builder(default = "PathBuf::from(\"target\")")]
+    target_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./picodata.yaml\")")]
+    config_path: PathBuf,
+    #[builder(default = "false")]
+    with_audit: bool,
+    #[builder(default = DEFAULT_RESTART_SIGNAL)]
+    signal: Signal,
+    #[builder(default = stop::DEFAULT_STOP_TIMEOUT)]
+    timeout: Duration,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let Params {
+        instance_name,
+        topology,
+        data_dir,
+        plugin_path,
+        picodata_path,
+        use_release,
+        target_dir,
+        config_path,
+        with_audit,
+        signal,
+        timeout,
+    } = params;
+
+    let stop_params = stop::ParamsBuilder::default()
+        .data_dir(data_dir.clone())
+        .plugin_path(plugin_path.clone())
+        .instance_name(Some(instance_name.clone()))
+        .signal(*signal)
+        .timeout(*timeout)
+        .build()
+        .unwrap();
+    stop::cmd(&stop_params).context(format!("failed to stop instance '{instance_name}'"))?;
+
+    // Plugins are not rebuilt, so the instance runs the same code as the rest of the cluster.
+    let run_params = run::ParamsBuilder::default()
+        .topology(topology.clone())
+        .data_dir(data_dir.clone())
+        .plugin_path(plugin_path.clone())
+        .picodata_path(picodata_path.clone())
+        .use_release(*use_release)
+        .target_dir(target_dir.clone())
+        .config_path(config_path.clone())
+        .with_audit(*with_audit)
+        .instance_name(Some(instance_name.clone()))
+        .no_build(true)
+        .daemon(true)
+        .build()
+        .unwrap();
+    let instances =
+        run::cluster(run_params).context(format!("failed to start instance '{instance_name}'"))?;
+    if instances.is_empty() {
+        bail!("instance '{instance_name}' is still running after stop");
+    }
+
+    info!("instance '{instance_name}' is restarted and Online");
+    Ok(())
+}
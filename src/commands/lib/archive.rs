@@ -0,0 +1,492 @@
+//! Archives of packed plugins: tarballs compressed with gzip or zstd and zip.
+//!
+//! Format of an existing archive is detected by its first bytes, so archives
+//! are recognized whatever their file names are.
+//!
+//! Reproducible archives don't depend on the time of packing and on owners,
+//! modification times and permissions of packed files except the executable
+//! bit, so packing the same sources twice gives byte-identical archives.
+
+use anyhow::{bail, Context, Result};
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::GzBuilder;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Modification time of entries of reproducible tarballs, the one
+/// `tar::HeaderMode::Deterministic` sets.
+const REPRODUCIBLE_MTIME: u64 = 1_153_704_088;
+
+/// Format of the plugin archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ArchiveFormat {
+    #[default]
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    const ALL: [Self; 3] = [Self::TarGz, Self::TarZst, Self::Zip];
+
+    /// Extension of archives of the format without the leading dot.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+            Self::Zip => "zip",
+        }
+    }
+
+    /// File name without the extension of any archive format,
+    /// e.g. `plugin_0.1.0` for `plugin_0.1.0.zip`.
+    #[must_use]
+    pub fn strip_extension(file_name: &str) -> &str {
+        Self::ALL
+            .iter()
+            .find_map(|format| file_name.strip_suffix(&format!(".{}", format.extension())))
+            .unwrap_or(file_name)
+    }
+
+    /// Detects format of the archive by its first bytes.
+    pub fn detect(path: &Path) -> Result<Self> {
+        let mut magic = [0; 4];
+        let mut file = File::open(path).context(format!("failed to open {}", path.display()))?;
+        let len = file
+            .read(&mut magic)
+            .context(format!("failed to read {}", path.display()))?;
+        let magic = &magic[..len];
+        if magic.starts_with(GZIP_MAGIC) {
+            Ok(Self::TarGz)
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Ok(Self::TarZst)
+        } else if magic.starts_with(ZIP_MAGIC) {
+            Ok(Self::Zip)
+        } else {
+            bail!("{} is not a tar.gz, tar.zst or zip archive", path.display())
+        }
+    }
+}
+
+impl Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tar.gz" | "tgz" => Ok(Self::TarGz),
+            "tar.zst" | "zstd" => Ok(Self::TarZst),
+            "zip" => Ok(Self::Zip),
+            _ => bail!("unknown archive format '{s}', expected tar.gz, tar.zst or zip"),
+        }
+    }
+}
+
+/// How hard the content of the archive is compressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CompressionLevel {
+    /// Content is stored as is, zstd uses its fastest level.
+    None,
+    Fast,
+    #[default]
+    Best,
+}
+
+impl FromStr for CompressionLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "fast" => Ok(Self::Fast),
+            "best" => Ok(Self::Best),
+            _ => bail!("unknown compression '{s}', expected none, fast or best"),
+        }
+    }
+}
+
+/// Compressed stream of a tarball.
+enum TarEncoder {
+    Gz(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Write for TarEncoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Gz(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Gz(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl TarEncoder {
+    fn finish(self) -> io::Result<File> {
+        match self {
+            Self::Gz(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+enum Writer {
+    Tar(tar::Builder<TarEncoder>),
+    Zip(Box<ZipWriter<File>>, SimpleFileOptions),
+}
+
+/// Archive being written, entries are appended with paths inside the archive.
+pub struct ArchiveWriter {
+    writer: Writer,
+    reproducible: bool,
+}
+
+impl ArchiveWriter {
+    pub fn create(
+        path: &Path,
+        format: ArchiveFormat,
+        compression: CompressionLevel,
+    ) -> Result<Self> {
+        let file = File::create(path).context("failed to create archive file")?;
+        let writer = match format {
+            ArchiveFormat::TarGz => {
+                let level = match compression {
+                    CompressionLevel::None => flate2::Compression::none(),
+                    CompressionLevel::Fast => flate2::Compression::fast(),
+                    CompressionLevel::Best => flate2::Compression::best(),
+                };
+                // Header without file name and modification time, it is the same for all archives.
+                let encoder = GzBuilder::new().mtime(0).write(file, level);
+                Writer::Tar(tar::Builder::new(TarEncoder::Gz(encoder)))
+            }
+            ArchiveFormat::TarZst => {
+                let level = match compression {
+                    CompressionLevel::None => *zstd::compression_level_range().start(),
+                    CompressionLevel::Fast => 1,
+                    CompressionLevel::Best => 19,
+                };
+                let encoder =
+                    zstd::Encoder::new(file, level).context("failed to start zstd compression")?;
+                Writer::Tar(tar::Builder::new(TarEncoder::Zstd(encoder)))
+            }
+            ArchiveFormat::Zip => {
+                let options = match compression {
+                    CompressionLevel::None => {
+                        SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+                    }
+                    CompressionLevel::Fast => SimpleFileOptions::default()
+                        .compression_method(CompressionMethod::Deflated)
+                        .compression_level(Some(1)),
+                    CompressionLevel::Best => SimpleFileOptions::default()
+                        .compression_method(CompressionMethod::Deflated)
+                        .compression_level(Some(9)),
+                };
+                Writer::Zip(Box::new(ZipWriter::new(file)), options)
+            }
+        };
+        Ok(Self {
+            writer,
+            reproducible: false,
+        })
+    }
+
+    /// Makes the archive reproducible, see the module documentation.
+    #[must_use]
+    pub fn reproducible(mut self) -> Self {
+        if let Writer::Tar(builder) = &mut self.writer {
+            builder.mode(tar::HeaderMode::Deterministic);
+        }
+        self.reproducible = true;
+        self
+    }
+
+    /// Appends file or directory with all its content.
+    pub fn append_path(&mut self, path_in_archive: &Path, path: &Path) -> Result<()> {
+        if !path.is_dir() {
+            return self.append_file(path_in_archive, path);
+        }
+
+        self.append_dir(path_in_archive, path)?;
+        let mut entries = fs::read_dir(path)
+            .context(format!("failed to read {}", path.display()))?
+            .collect::<io::Result<Vec<_>>>()
+            .context(format!("failed to read {}", path.display()))?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+        for entry in entries {
+            self.append_path(&path_in_archive.join(entry.file_name()), &entry.path())?;
+        }
+        Ok(())
+    }
+
+    /// Appends directory entry without its content.
+    pub fn append_dir(&mut self, path_in_archive: &Path, dir: &Path) -> Result<()> {
+        match &mut self.writer {
+            Writer::Tar(builder) => builder.append_dir(path_in_archive, dir)?,
+            Writer::Zip(zip, options) => {
+                let mode = fs::metadata(dir)?.permissions().mode();
+                let options = zip_options(self.reproducible, *options, mode);
+                zip.add_directory(zip_name(path_in_archive), options)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append_file(&mut self, path_in_archive: &Path, path: &Path) -> Result<()> {
+        let mut file =
+            File::open(path).context(format!("failed to open file {}", path.display()))?;
+        match &mut self.writer {
+            Writer::Tar(builder) => builder.append_file(path_in_archive, &mut file)?,
+            Writer::Zip(zip, options) => {
+                let mode = file.metadata()?.permissions().mode();
+                let options = zip_options(self.reproducible, *options, mode);
+                zip.start_file(zip_name(path_in_archive), options)?;
+                io::copy(&mut file, zip.as_mut())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends generated file with `content`.
+    pub fn append_data(&mut self, path_in_archive: &Path, content: &[u8]) -> Result<()> {
+        match &mut self.writer {
+            Writer::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(if self.reproducible {
+                    REPRODUCIBLE_MTIME
+                } else {
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs())
+                });
+                header.set_cksum();
+                builder.append_data(&mut header, path_in_archive, content)?;
+            }
+            Writer::Zip(zip, options) => {
+                let options = zip_options(self.reproducible, *options, 0o644);
+                zip.start_file(zip_name(path_in_archive), options)?;
+                zip.write_all(content)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.writer {
+            Writer::Tar(builder) => {
+                builder
+                    .into_inner()
+                    .context("failed to finish building tar archive")?
+                    .finish()
+                    .context("failed to finish compression")?;
+            }
+            Writer::Zip(zip, _) => {
+                zip.finish()
+                    .context("failed to finish building zip archive")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options of the zip entry for the file with `mode`.
+fn zip_options(reproducible: bool, options: SimpleFileOptions, mode: u32) -> SimpleFileOptions {
+    if !reproducible {
+        return options.unix_permissions(mode);
+    }
+    // Only the executable bit is kept, like tar does in the deterministic mode.
+    let mode = if mode & 0o100 == 0 { 0o644 } else { 0o755 };
+    options
+        .unix_permissions(mode)
+        .last_modified_time(DateTime::default())
+}
+
+/// Name of the zip entry, zip always separates components with `/`.
+fn zip_name(path_in_archive: &Path) -> String {
+    path_in_archive.to_string_lossy().into_owned()
+}
+
+/// Reader of the tarball decompressing it according to its format.
+fn open_tar(path: &Path, format: ArchiveFormat) -> Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(
+        File::open(path).context(format!("failed to open archive {}", path.display()))?,
+    );
+    let decoder: Box<dyn Read> = match format {
+        ArchiveFormat::TarGz => Box::new(GzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(
+            zstd::Decoder::with_buffer(file).context("failed to start zstd decompression")?,
+        ),
+        ArchiveFormat::Zip => unreachable!("zip is not a tarball"),
+    };
+    Ok(tar::Archive::new(decoder))
+}
+
+fn open_zip(path: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(path).context(format!("failed to open archive {}", path.display()))?;
+    ZipArchive::new(file).context(format!("failed to read zip archive {}", path.display()))
+}
+
+/// Paths of all entries of the archive.
+pub fn entry_paths(path: &Path) -> Result<Vec<PathBuf>> {
+    match ArchiveFormat::detect(path)? {
+        ArchiveFormat::Zip => Ok(open_zip(path)?.file_names().map(PathBuf::from).collect()),
+        format => {
+            let mut archive = open_tar(path, format)?;
+            let mut paths = vec![];
+            for entry in archive
+                .entries()
+                .context("failed to read archive entries")?
+            {
+                let entry = entry.context("failed to read archive entry")?;
+                paths.push(entry.path()?.into_owned());
+            }
+            Ok(paths)
+        }
+    }
+}
+
+/// Unpacks the archive into `dst`, existing files are overwritten.
+pub fn unpack(path: &Path, dst: &Path) -> Result<()> {
+    match ArchiveFormat::detect(path)? {
+        ArchiveFormat::Zip => open_zip(path)?.extract(dst)?,
+        format => open_tar(path, format)?.unpack(dst)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn archives_of_all_formats_are_read_back() {
+        let workspace = TempWorkspace::new("archive-formats").unwrap();
+        let src = workspace.join("src");
+        fs::create_dir_all(src.join("migrations")).unwrap();
+        fs::write(src.join("migrations/0001_init.sql"), "CREATE TABLE t;").unwrap();
+        fs::write(src.join("libplugin.so"), "lib").unwrap();
+
+        for format in ArchiveFormat::ALL {
+            for compression in [
+                CompressionLevel::None,
+                CompressionLevel::Fast,
+                CompressionLevel::Best,
+            ] {
+                let path = workspace.join(format!("plugin.{format}"));
+                let mut writer = ArchiveWriter::create(&path, format, compression).unwrap();
+                let root = Path::new("plugin/0.1.0");
+                writer
+                    .append_file(&root.join("libplugin.so"), &src.join("libplugin.so"))
+                    .unwrap();
+                writer
+                    .append_path(&root.join("migrations"), &src.join("migrations"))
+                    .unwrap();
+                writer
+                    .append_data(&root.join("manifest.yaml"), b"name: plugin")
+                    .unwrap();
+                writer.finish().unwrap();
+
+                assert_eq!(ArchiveFormat::detect(&path).unwrap(), format);
+                let paths: Vec<String> = entry_paths(&path)
+                    .unwrap()
+                    .iter()
+                    .map(|path| path.display().to_string().trim_end_matches('/').to_string())
+                    .collect();
+                assert_eq!(
+                    paths,
+                    [
+                        "plugin/0.1.0/libplugin.so",
+                        "plugin/0.1.0/migrations",
+                        "plugin/0.1.0/migrations/0001_init.sql",
+                        "plugin/0.1.0/manifest.yaml",
+                    ],
+                    "{format} {compression:?}"
+                );
+
+                let dst = workspace.join(format!("unpacked-{format}"));
+                unpack(&path, &dst).unwrap();
+                assert_eq!(
+                    fs::read_to_string(dst.join("plugin/0.1.0/migrations/0001_init.sql")).unwrap(),
+                    "CREATE TABLE t;"
+                );
+                assert_eq!(
+                    fs::read_to_string(dst.join("plugin/0.1.0/manifest.yaml")).unwrap(),
+                    "name: plugin"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reproducible_archives_are_identical() {
+        let workspace = TempWorkspace::new("archive-reproducible").unwrap();
+        let src = workspace.join("src");
+        fs::create_dir_all(src.join("migrations")).unwrap();
+        let migration = src.join("migrations/0001_init.sql");
+        fs::write(&migration, "CREATE TABLE t;").unwrap();
+
+        let pack = |path: &Path, format| {
+            let mut writer = ArchiveWriter::create(path, format, CompressionLevel::Best)
+                .unwrap()
+                .reproducible();
+            writer
+                .append_path(Path::new("plugin/migrations"), &src.join("migrations"))
+                .unwrap();
+            writer
+                .append_data(Path::new("plugin/manifest.yaml"), b"name: plugin")
+                .unwrap();
+            writer.finish().unwrap();
+            fs::read(path).unwrap()
+        };
+
+        for format in ArchiveFormat::ALL {
+            let first = pack(&workspace.join(format!("first.{format}")), format);
+            File::options()
+                .write(true)
+                .open(&migration)
+                .unwrap()
+                .set_modified(UNIX_EPOCH)
+                .unwrap();
+            fs::set_permissions(&migration, fs::Permissions::from_mode(0o600)).unwrap();
+            let second = pack(&workspace.join(format!("second.{format}")), format);
+            assert!(first == second, "{format} archives differ");
+            fs::set_permissions(&migration, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+    }
+
+    #[test]
+    fn extension_of_any_format_is_stripped() {
+        assert_eq!(ArchiveFormat::strip_extension("p_0.1.0.tar.gz"), "p_0.1.0");
+        assert_eq!(ArchiveFormat::strip_extension("p_0.1.0.tar.zst"), "p_0.1.0");
+        assert_eq!(ArchiveFormat::strip_extension("p_0.1.0.zip"), "p_0.1.0");
+        assert_eq!(ArchiveFormat::strip_extension("p_0.1.0"), "p_0.1.0");
+        assert_eq!("zip".parse::<ArchiveFormat>().unwrap(), ArchiveFormat::Zip);
+        assert!("rar".parse::<ArchiveFormat>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Metadata of the cluster started by pike, stored in `cluster.json`
+//! in the data directory next to directories of instances.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const CLUSTER_METADATA_FILE: &str = "cluster.json";
+
+/// Ports of the instance `i<N>` are `base + N`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BasePorts {
+    pub bin: u16,
+    pub http: u16,
+    pub pg: u16,
+}
+
+/// Instance started by pike, the data external tools need to connect to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceMetadata {
+    /// Name of the instance in the cluster, e.g. `default_1_1`.
+    pub name: String,
+    /// Number of the instance in pike, its data dir is `i<instance_id>`.
+    pub instance_id: u16,
+    pub tier: String,
+    /// Pid of the picodata process, may be stale after the instance is stopped.
+    pub pid: u32,
+    pub data_dir: PathBuf,
+    pub admin_socket: PathBuf,
+    pub bin_port: u16,
+    pub http_port: u16,
+    pub pg_port: u16,
+    /// Number of restarts of the crashed instance by `run --supervise`.
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterMetadata {
+    pub base_ports: BasePorts,
+    /// Instances started by pike ordered by `instance_id`.
+    #[serde(default)]
+    pub instances: Vec<InstanceMetadata>,
+    /// Whether the cluster was started with `--with-web-auth`, so `WebUI` asks for login.
+    #[serde(default)]
+    pub with_web_auth: bool,
+}
+
+#[allow(dead_code)]
+impl ClusterMetadata {
+    /// Path of `cluster.json` of the cluster in `data_dir`.
+    #[must_use]
+    pub fn path(plugin_path: &Path, data_dir: &Path) -> PathBuf {
+        plugin_path.join(data_dir).join(CLUSTER_METADATA_FILE)
+    }
+
+    /// Reads metadata of the cluster, returns `None` if the cluster has never been started.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).context(format!("failed to read {}", path.display()));
+            }
+        };
+
+        serde_json::from_str(&content)
+            .map(Some)
+            .context(format!("failed to parse {}", path.display()))
+    }
+
+    /// Replaces descriptions of the restarted instances and adds the new ones.
+    pub fn update_instances(&mut self, instances: impl IntoIterator<Item = InstanceMetadata>) {
+        for instance in instances {
+            self.instances
+                .retain(|known| known.instance_id != instance.instance_id);
+            self.instances.push(instance);
+        }
+        self.instances.sort_by_key(|instance| instance.instance_id);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("failed to create directory {}", parent.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n").context(format!("failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn metadata_is_saved_and_loaded() {
+        let workspace = TempWorkspace::new("cluster-metadata").unwrap();
+        let path = ClusterMetadata::path(workspace.path(), Path::new("tmp"));
+        assert_eq!(ClusterMetadata::load(&path).unwrap(), None);
+
+        let metadata = ClusterMetadata {
+            base_ports: BasePorts {
+                bin: 40000,
+                http: 40004,
+                pg: 40008,
+            },
+            instances: vec![],
+            with_web_auth: false,
+        };
+        metadata.save(&path).unwrap();
+
+        assert_eq!(ClusterMetadata::load(&path).unwrap(), Some(metadata));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path).unwrap()).unwrap(),
+            serde_json::json!({
+                "base_ports": { "bin": 40000, "http": 40004, "pg": 40008 },
+                "instances": [],
+                "with_web_auth": false,
+            })
+        );
+
+        // Metadata written before instances were recorded is still readable.
+        fs::write(
+            &path,
+            r#"{ "base_ports": { "bin": 3000, "http": 8000, "pg": 5432 } }"#,
+        )
+        .unwrap();
+        assert!(ClusterMetadata::load(&path)
+            .unwrap()
+            .unwrap()
+            .instances
+            .is_empty());
+    }
+
+    fn instance(instance_id: u16, pid: u32) -> InstanceMetadata {
+        let data_dir = PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}"));
+        InstanceMetadata {
+            name: format!("default_1_{instance_id}"),
+            instance_id,
+            tier: "default".to_string(),
+            pid,
+            admin_socket: data_dir.join("admin.sock"),
+            data_dir,
+            bin_port: 3000 + instance_id,
+            http_port: 8000 + instance_id,
+            pg_port: 5432 + instance_id,
+            restarts: 0,
+        }
+    }
+
+    #[test]
+    fn restarted_instances_are_replaced() {
+        let mut metadata = ClusterMetadata {
+            base_ports: BasePorts {
+                bin: 3000,
+                http: 8000,
+                pg: 5432,
+            },
+            instances: vec![],
+            with_web_auth: false,
+        };
+        metadata.update_instances([instance(2, 20), instance(1, 10)]);
+        metadata.update_instances([instance(2, 21), instance(3, 30)]);
+
+        let pids: Vec<(u16, u32)> = metadata
+            .instances
+            .iter()
+            .map(|i| (i.instance_id, i.pid))
+            .collect();
+        assert_eq!(pids, [(1, 10), (2, 21), (3, 30)]);
+    }
+}
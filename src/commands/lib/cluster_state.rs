@@ -0,0 +1,315 @@
+//! State of the cluster read from `_pico_instance` and `_pico_plugin`,
+//! used to decide whether the cluster is ready.
+
+use crate::commands::lib::find_active_socket_path;
+use crate::commands::lib::instance_info::{InstanceSocketClient, InstanceState};
+use crate::commands::lib::poll::{poll_until, Backoff};
+use crate::error::Error;
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use serde::{Deserialize, Deserializer};
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[allow(dead_code)]
+const READY_BACKOFF: Backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+
+#[allow(dead_code)]
+/// State of the instance with its incarnation, e.g. `["Online", 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateWithIncarnation {
+    pub variant: InstanceState,
+    pub incarnation: u64,
+}
+
+impl<'de> Deserialize<'de> for StateWithIncarnation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Depending on the version, picodata encodes the state as an array or as a map.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Array(String, u64),
+            Map { variant: String, incarnation: u64 },
+        }
+
+        let (variant, incarnation) = match Raw::deserialize(deserializer)? {
+            Raw::Array(variant, incarnation)
+            | Raw::Map {
+                variant,
+                incarnation,
+            } => (variant, incarnation),
+        };
+        Ok(Self {
+            variant: variant.parse().map_err(serde::de::Error::custom)?,
+            incarnation,
+        })
+    }
+}
+
+#[allow(dead_code)]
+/// Row of `_pico_instance`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstanceStatus {
+    pub name: String,
+    pub replicaset_name: String,
+    pub tier: String,
+    pub current_state: StateWithIncarnation,
+    pub target_state: StateWithIncarnation,
+}
+
+#[allow(dead_code)]
+impl InstanceStatus {
+    /// Instance is online and is not going to change its state.
+    #[must_use]
+    pub fn is_online(&self) -> bool {
+        self.current_state.variant.is_online() && self.target_state.variant.is_online()
+    }
+
+    #[must_use]
+    pub fn is_expelled(&self) -> bool {
+        matches!(self.target_state.variant, InstanceState::Expelled)
+    }
+}
+
+#[allow(dead_code)]
+/// Row of `_pico_plugin`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PluginStatus {
+    pub name: String,
+    pub version: String,
+    pub enabled: bool,
+}
+
+#[allow(dead_code)]
+/// Instances and plugins of the cluster.
+#[derive(Clone, Debug, Default)]
+pub struct ClusterState {
+    pub instances: Vec<InstanceStatus>,
+    pub plugins: Vec<PluginStatus>,
+}
+
+#[allow(dead_code)]
+impl ClusterState {
+    /// Reads the state through admin socket of any running instance of the cluster.
+    pub fn fetch(cluster_dir: &Path, picodata_path: &Path) -> Result<Self> {
+        let Some(socket_path) = find_active_socket_path(cluster_dir)? else {
+            return Err(Error::AdminSocketUnavailable(anyhow!(
+                "no running instances found in {}",
+                cluster_dir.display()
+            ))
+            .into());
+        };
+        let instance_dir = socket_path
+            .parent()
+            .context("admin socket path has no parent directory")?;
+        let picodata_path = picodata_path.to_path_buf();
+        let client = InstanceSocketClient::new(instance_dir, &picodata_path);
+
+        Ok(Self {
+            instances: client.query(
+                "SELECT name, replicaset_name, tier, current_state, target_state \
+                FROM _pico_instance",
+            )?,
+            plugins: client.query("SELECT name, version, enabled FROM _pico_plugin")?,
+        })
+    }
+
+    /// Number of instances which are online and are not going to change their state.
+    #[must_use]
+    pub fn online_instances(&self) -> usize {
+        self.instances.iter().filter(|i| i.is_online()).count()
+    }
+
+    /// Number of online instances of the tier.
+    #[must_use]
+    pub fn online_instances_in_tier(&self, tier: &str) -> usize {
+        self.instances
+            .iter()
+            .filter(|i| i.tier == tier && i.is_online())
+            .count()
+    }
+
+    /// Majority of instances which are not expelled are online.
+    #[must_use]
+    pub fn has_quorum(&self) -> bool {
+        let members = self.instances.iter().filter(|i| !i.is_expelled()).count();
+        self.online_instances() * 2 > members
+    }
+
+    /// All instances except expelled ones are online.
+    ///
+    /// False if the cluster has no instances yet.
+    #[must_use]
+    pub fn all_instances_online(&self) -> bool {
+        let mut instances = self
+            .instances
+            .iter()
+            .filter(|i| !i.is_expelled())
+            .peekable();
+        instances.peek().is_some() && instances.all(InstanceStatus::is_online)
+    }
+
+    #[must_use]
+    pub fn enabled_plugins(&self) -> usize {
+        self.plugins.iter().filter(|p| p.enabled).count()
+    }
+
+    /// All installed plugins are enabled.
+    ///
+    /// False if no plugins are installed yet.
+    #[must_use]
+    pub fn all_plugins_enabled(&self) -> bool {
+        !self.plugins.is_empty() && self.plugins.iter().all(|p| p.enabled)
+    }
+}
+
+impl Display for ClusterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} instance(s) online, {}/{} plugin(s) enabled",
+            self.online_instances(),
+            self.instances.len(),
+            self.enabled_plugins(),
+            self.plugins.len()
+        )
+    }
+}
+
+#[allow(dead_code)]
+/// Polls state of the cluster until `is_ready` returns true for it.
+///
+/// Errors of reading the state, e.g. when instances are not started yet,
+/// are retried until `timeout` is exceeded.
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// let state = pike::cluster::wait_ready(
+///     Path::new("./tmp/cluster"),
+///     Path::new("picodata"),
+///     Duration::from_secs(60),
+///     |state| state.online_instances() == 4 && state.all_plugins_enabled(),
+/// )
+/// .unwrap();
+/// ```
+pub fn wait_ready<F>(
+    cluster_dir: &Path,
+    picodata_path: &Path,
+    timeout: Duration,
+    mut is_ready: F,
+) -> Result<ClusterState>
+where
+    F: FnMut(&ClusterState) -> bool,
+{
+    let mut last_state = None;
+    let ready = poll_until(Instant::now() + timeout, &READY_BACKOFF, || {
+        match ClusterState::fetch(cluster_dir, picodata_path) {
+            Ok(state) if is_ready(&state) => return Ok(Some(state)),
+            Ok(state) => {
+                debug!("Cluster is not ready yet: {state}");
+                last_state = Some(state);
+            }
+            Err(err) => debug!("Failed to read cluster state: {err:#}"),
+        }
+        Ok(None)
+    })?;
+
+    match (ready, last_state) {
+        (Some(state), _) => Ok(state),
+        (None, Some(state)) => bail!(
+            "cluster is not ready within {}s: {state}",
+            timeout.as_secs()
+        ),
+        (None, None) => bail!("failed to read cluster state within {}s", timeout.as_secs()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(instances: serde_json::Value, plugins: serde_json::Value) -> ClusterState {
+        ClusterState {
+            instances: serde_json::from_value(instances).unwrap(),
+            plugins: serde_json::from_value(plugins).unwrap(),
+        }
+    }
+
+    #[test]
+    fn instance_names_do_not_affect_readiness() {
+        let state = state(
+            serde_json::json!([
+                {
+                    "name": "Online_true",
+                    "replicaset_name": "default_1",
+                    "tier": "default",
+                    "current_state": ["Offline", 1],
+                    "target_state": ["Online", 2],
+                },
+                {
+                    "name": "i2",
+                    "replicaset_name": "default_1",
+                    "tier": "default",
+                    "current_state": {"variant": "Online", "incarnation": 1},
+                    "target_state": {"variant": "Online", "incarnation": 1},
+                },
+            ]),
+            serde_json::json!([{"name": "true", "version": "0.1.0", "enabled": false}]),
+        );
+
+        assert_eq!(state.online_instances(), 1);
+        assert_eq!(state.online_instances_in_tier("default"), 1);
+        assert!(!state.all_instances_online());
+        assert!(!state.has_quorum());
+        assert_eq!(state.enabled_plugins(), 0);
+        assert!(!state.all_plugins_enabled());
+        assert_eq!(
+            state.to_string(),
+            "1/2 instance(s) online, 0/1 plugin(s) enabled"
+        );
+    }
+
+    #[test]
+    fn expelled_instances_are_ignored() {
+        let state = state(
+            serde_json::json!([
+                {
+                    "name": "i1",
+                    "replicaset_name": "default_1",
+                    "tier": "default",
+                    "current_state": ["Online", 1],
+                    "target_state": ["Online", 1],
+                },
+                {
+                    "name": "i2",
+                    "replicaset_name": "default_1",
+                    "tier": "default",
+                    "current_state": ["Expelled", 1],
+                    "target_state": ["Expelled", 1],
+                },
+            ]),
+            serde_json::json!([{"name": "p", "version": "0.1.0", "enabled": true}]),
+        );
+
+        assert!(state.all_instances_online());
+        assert!(state.has_quorum());
+        assert!(state.all_plugins_enabled());
+    }
+
+    #[test]
+    fn empty_cluster_is_not_ready() {
+        let state = ClusterState::default();
+        assert!(!state.all_instances_online());
+        assert!(!state.has_quorum());
+        assert!(!state.all_plugins_enabled());
+    }
+
+    #[test]
+    fn unknown_state_is_rejected() {
+        let err = serde_json::from_value::<StateWithIncarnation>(serde_json::json!(["Lost", 1]))
+            .unwrap_err();
+        assert!(err.to_string().contains("'lost'"), "{err}");
+    }
+}
@@ -0,0 +1,303 @@
+//! Compatibility check of picodata, pike and `picodata-plugin` SDK the plugin depends on.
+//!
+//! Plugins are loaded by picodata as shared libraries, so a plugin built with SDK
+//! of another picodata release fails at runtime with an obscure ABI error.
+//! The check runs before `run` and `plugin pack` and reports such combinations
+//! up front, `--skip-compat-check` disables it.
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use regex::Regex;
+use semver::{Op, Version, VersionReq};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use toml_edit::{DocumentMut, Item};
+
+/// Picodata releases supported by this version of pike, see "Поддерживаемые версии" of README.
+const SUPPORTED_PICODATA: &str = ">=26";
+
+const SDK_CRATE: &str = "picodata-plugin";
+
+/// Verdict on a combination of versions.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Compat {
+    Compatible,
+    /// Combination works in general, but not tested.
+    Warning(String),
+    /// Combination is known to fail at runtime.
+    Incompatible(String),
+}
+
+/// Release of picodata from the first line of `picodata --version`,
+/// e.g. `picodata 26.1.3-0-g1f2e3d4`.
+pub fn parse_picodata_version(output: &str) -> Result<Version> {
+    let pattern = Regex::new(r"(\d+)\.(\d+)\.(\d+)").expect("valid regex");
+    let first_line = output.lines().next().unwrap_or_default();
+    let captures = pattern
+        .captures(first_line)
+        .with_context(|| format!("failed to find version in picodata output: {first_line}"))?;
+    let number = |i: usize| captures[i].parse::<u64>().expect("matched digits");
+    Ok(Version::new(number(1), number(2), number(3)))
+}
+
+/// Requirements on `picodata-plugin` of the plugin crates by their directory,
+/// crates without the dependency or with a path/git one are skipped.
+pub fn sdk_requirements(plugin_path: &Path) -> Result<BTreeMap<String, VersionReq>> {
+    let root = read_cargo_toml(plugin_path)?;
+    let workspace_deps = root
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"));
+
+    let mut requirements = BTreeMap::new();
+    let mut add_crate = |dir: &Path, name: String, cargo_toml: &DocumentMut| -> Result<()> {
+        let Some(dependency) = cargo_toml
+            .get("dependencies")
+            .and_then(|deps| deps.get(SDK_CRATE))
+        else {
+            return Ok(());
+        };
+        let dependency = if dependency
+            .get("workspace")
+            .and_then(Item::as_bool)
+            .unwrap_or_default()
+        {
+            match workspace_deps.and_then(|deps| deps.get(SDK_CRATE)) {
+                Some(dependency) => dependency,
+                None => bail!(
+                    "{SDK_CRATE} of {} is inherited from workspace, which doesn't declare it",
+                    dir.display()
+                ),
+            }
+        } else {
+            dependency
+        };
+        let version = dependency
+            .as_str()
+            .or_else(|| dependency.get("version").and_then(Item::as_str));
+        let Some(version) = version else {
+            debug!(
+                "{SDK_CRATE} of {} has no version, skipping compatibility check",
+                dir.display()
+            );
+            return Ok(());
+        };
+        let requirement = VersionReq::parse(version).context(format!(
+            "invalid version '{version}' of {SDK_CRATE} in {}",
+            dir.join("Cargo.toml").display()
+        ))?;
+        requirements.insert(name, requirement);
+        Ok(())
+    };
+
+    let members = root
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(Item::as_array);
+    if let Some(members) = members {
+        for member in members.iter().filter_map(|member| member.as_str()) {
+            let dir = plugin_path.join(member);
+            add_crate(&dir, member.to_string(), &read_cargo_toml(&dir)?)?;
+        }
+    }
+    if root.get("package").is_some() {
+        add_crate(plugin_path, ".".to_string(), &root)?;
+    }
+    Ok(requirements)
+}
+
+fn read_cargo_toml(dir: &Path) -> Result<DocumentMut> {
+    let path = dir.join("Cargo.toml");
+    fs::read_to_string(&path)
+        .context(format!("failed to read {}", path.display()))?
+        .parse()
+        .context(format!("failed to parse {}", path.display()))
+}
+
+/// Checks that this version of pike supports the picodata release.
+pub fn check_pike(picodata: &Version) -> Compat {
+    let supported = VersionReq::parse(SUPPORTED_PICODATA).expect("valid requirement");
+    if supported.matches(picodata) {
+        return Compat::Compatible;
+    }
+    Compat::Incompatible(format!(
+        "pike {} supports picodata {SUPPORTED_PICODATA}, but picodata is {picodata}",
+        env!("CARGO_PKG_VERSION")
+    ))
+}
+
+/// Checks the SDK requirement against the picodata release.
+///
+/// SDK is released together with picodata under the same version: SDK of
+/// another major release or of a later minor one is incompatible, while SDK
+/// of an earlier minor release of the same major one usually works.
+pub fn check_sdk(picodata: &Version, sdk: &VersionReq) -> Compat {
+    let Some(comparator) = sdk
+        .comparators
+        .iter()
+        .find(|comparator| !matches!(comparator.op, Op::Less | Op::LessEq))
+    else {
+        return Compat::Compatible;
+    };
+    let sdk_minor = comparator.minor.unwrap_or_default();
+    if comparator.major != picodata.major || sdk_minor > picodata.minor {
+        return Compat::Incompatible(format!(
+            "{SDK_CRATE} {sdk} is built for picodata {}.{sdk_minor}, \
+            plugins with it can't be loaded by picodata {picodata}",
+            comparator.major
+        ));
+    }
+    if sdk_minor < picodata.minor {
+        return Compat::Warning(format!(
+            "{SDK_CRATE} {sdk} is older than picodata {picodata}, \
+            consider updating it to {}.{}",
+            picodata.major, picodata.minor
+        ));
+    }
+    Compat::Compatible
+}
+
+/// Checks picodata reported by `version_output` of `picodata --version`
+/// against pike and SDK of the plugin, fails on incompatible versions.
+pub fn check(plugin_path: &Path, version_output: &str) -> Result<()> {
+    let picodata = parse_picodata_version(version_output)?;
+    let mut verdicts = vec![check_pike(&picodata)];
+    if plugin_path.join("Cargo.toml").exists() {
+        for (plugin, sdk) in sdk_requirements(plugin_path)? {
+            verdicts.push(match check_sdk(&picodata, &sdk) {
+                Compat::Compatible => Compat::Compatible,
+                Compat::Warning(msg) => Compat::Warning(format!("{plugin}: {msg}")),
+                Compat::Incompatible(msg) => Compat::Incompatible(format!("{plugin}: {msg}")),
+            });
+        }
+    }
+
+    let mut incompatible = vec![];
+    for verdict in verdicts {
+        match verdict {
+            Compat::Compatible => {}
+            Compat::Warning(msg) => warn!("{msg}"),
+            Compat::Incompatible(msg) => incompatible.push(msg),
+        }
+    }
+    if !incompatible.is_empty() {
+        bail!(
+            "incompatible versions:\n  {}\nuse another picodata or pass --skip-compat-check to run anyway",
+            incompatible.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Same as [`check`] with the output of `picodata_path`, skipped with a warning
+/// if picodata isn't installed, e.g. on machines which only pack plugins.
+pub fn check_with_binary(plugin_path: &Path, picodata_path: &Path) -> Result<()> {
+    match Command::new(picodata_path).arg("--version").output() {
+        Ok(output) => check(plugin_path, &String::from_utf8_lossy(&output.stdout)),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            warn!(
+                "{} is not found, skipping compatibility check of picodata-plugin",
+                picodata_path.display()
+            );
+            Ok(())
+        }
+        Err(err) => Err(err).context(format!(
+            "failed to get version of {}",
+            picodata_path.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    fn write(workspace: &TempWorkspace, path: &str, content: &str) {
+        let path = workspace.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn req(requirement: &str) -> VersionReq {
+        VersionReq::parse(requirement).unwrap()
+    }
+
+    #[test]
+    fn picodata_version_is_parsed() {
+        let output = "picodata 26.1.3-0-g1f2e3d4\ntarantool (fork) version: 2.11.5-232\n";
+        assert_eq!(
+            parse_picodata_version(output).unwrap(),
+            Version::new(26, 1, 3)
+        );
+        assert!(parse_picodata_version("picodata\n").is_err());
+    }
+
+    #[test]
+    fn sdk_is_checked_against_picodata() {
+        let picodata = Version::new(26, 2, 1);
+        assert_eq!(check_sdk(&picodata, &req("=26.2.0")), Compat::Compatible);
+        assert_eq!(check_sdk(&picodata, &req("26.2")), Compat::Compatible);
+        assert!(matches!(
+            check_sdk(&picodata, &req("=26.1.3")),
+            Compat::Warning(_)
+        ));
+        assert!(matches!(
+            check_sdk(&picodata, &req("26.3")),
+            Compat::Incompatible(_)
+        ));
+        assert!(matches!(
+            check_sdk(&picodata, &req("=25.4.1")),
+            Compat::Incompatible(_)
+        ));
+        assert!(matches!(
+            check_pike(&Version::new(25, 4, 1)),
+            Compat::Incompatible(_)
+        ));
+    }
+
+    #[test]
+    fn sdk_requirements_are_read_from_workspace() {
+        let workspace = TempWorkspace::new("compat-workspace").unwrap();
+        write(
+            &workspace,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\n\n\
+                [workspace.dependencies]\npicodata-plugin = \"=26.1.3\"\n",
+        );
+        write(
+            &workspace,
+            "a/Cargo.toml",
+            "[package]\nname = \"a\"\n\n[dependencies]\npicodata-plugin.workspace = true\n",
+        );
+        write(
+            &workspace,
+            "b/Cargo.toml",
+            "[package]\nname = \"b\"\n\n[dependencies]\n\
+                picodata-plugin = { version = \"25.4\", features = [] }\n",
+        );
+        write(
+            &workspace,
+            "c/Cargo.toml",
+            "[package]\nname = \"c\"\n\n[dependencies]\nserde = \"1\"\n",
+        );
+
+        let requirements = sdk_requirements(workspace.path()).unwrap();
+        assert_eq!(
+            requirements,
+            BTreeMap::from([
+                ("a".to_string(), req("=26.1.3")),
+                ("b".to_string(), req("25.4")),
+            ])
+        );
+
+        let err = check(workspace.path(), "picodata 26.1.3\n").unwrap_err();
+        assert!(
+            err.to_string().contains("b: picodata-plugin ^25.4"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("a: "), "{err}");
+    }
+}
@@ -0,0 +1,118 @@
+//! Lifecycle events of the cluster printed to stdout as NDJSON with `--events-json`,
+//! so external tools can follow the cluster without parsing logs.
+//!
+//! Logs of pike and instances go to stderr in this mode, stdout has events only.
+//! Programs embedding pike receive the same events through [`Reporter`].
+
+use crate::commands::lib::cluster_metadata::InstanceMetadata;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// `cargo build` of the plugin project at `path` is started.
+    PluginBuilding {
+        path: &'a Path,
+    },
+    /// Process of the instance is spawned, it is not online yet.
+    InstanceStarting {
+        name: &'a str,
+        tier: &'a str,
+    },
+    /// Instance is started and online, its ports and paths are the ones of `cluster.json`.
+    InstanceStarted {
+        #[serde(flatten)]
+        instance: &'a InstanceMetadata,
+    },
+    PluginEnabled {
+        plugin: &'a str,
+        version: Option<&'a str>,
+    },
+    /// All instances started by the command are online and plugins are enabled.
+    ClusterReady {
+        instances: Vec<&'a str>,
+    },
+    /// Cluster waits for the raft leader election.
+    LeaderWaiting,
+    InstanceCrashed {
+        name: &'a str,
+        exit_status: String,
+    },
+    /// Instance exited by Ctrl+C or it is stopped by `stop`.
+    InstanceStopped {
+        name: &'a str,
+    },
+    ClusterStopped,
+}
+
+/// Receiver of progress events of commands run by programs embedding pike,
+/// e.g. to render the progress in GUI or CI.
+///
+/// Events are reported from the thread running the command and from threads
+/// watching instances, so the reporter has to be thread safe.
+pub trait Reporter: fmt::Debug + Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+/// Prints the event as a single line of JSON.
+pub fn emit(event: &Event) {
+    let line = serde_json::to_string(event).expect("event is serializable");
+    let mut stdout = io::stdout().lock();
+    // Closed stdout must not break the cluster, e.g. when the reader has exited.
+    let _ = writeln!(stdout, "{line}");
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn events_are_tagged_with_snake_case_names() {
+        let instance = InstanceMetadata {
+            name: "default_1_1".to_string(),
+            instance_id: 1,
+            tier: "default".to_string(),
+            pid: 42,
+            data_dir: PathBuf::from("/plugin/tmp/cluster/i1"),
+            admin_socket: PathBuf::from("/plugin/tmp/cluster/i1/admin.sock"),
+            bin_port: 3001,
+            http_port: 8001,
+            pg_port: 5433,
+            restarts: 0,
+        };
+        let started = serde_json::to_value(Event::InstanceStarted {
+            instance: &instance,
+        })
+        .unwrap();
+        assert_eq!(started["event"], "instance_started");
+        assert_eq!(started["name"], "default_1_1");
+        assert_eq!(started["pg_port"], 5433);
+
+        assert_eq!(
+            serde_json::to_value(Event::PluginEnabled {
+                plugin: "weather",
+                version: Some("0.1.0"),
+            })
+            .unwrap(),
+            json!({"event": "plugin_enabled", "plugin": "weather", "version": "0.1.0"})
+        );
+        assert_eq!(
+            serde_json::to_value(Event::ClusterStopped).unwrap(),
+            json!({"event": "cluster_stopped"})
+        );
+        assert_eq!(
+            serde_json::to_value(Event::InstanceStarting {
+                name: "i1",
+                tier: "default",
+            })
+            .unwrap(),
+            json!({"event": "instance_starting", "name": "i1", "tier": "default"})
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Environment variables overriding defaults of command line flags.
+//!
+//! Every flag `--some-flag` can be set with `PIKE_SOME_FLAG`, so CI jobs and
+//! devcontainers configure pike without editing commands. Builders of the
+//! library read the same variables for defaults of their common parameters.
+//! Values are taken in the order: command line, environment, `[defaults]` of
+//! `pike.toml`, built-in defaults.
+
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, Command};
+use log::warn;
+use std::env;
+use std::str::FromStr;
+
+const PREFIX: &str = "PIKE_";
+
+/// Variable of the flag with the long name `flag`, e.g. `PIKE_DATA_DIR` for `data-dir`.
+pub fn var_name(flag: &str) -> String {
+    format!("{PREFIX}{}", flag.to_uppercase().replace('-', "_"))
+}
+
+/// Makes flags of the command and all its subcommands read their variables,
+/// flags with a variable of their own are kept as is. Boolean flags take
+/// `true`/`false`, `1`/`0`, `yes`/`no` or `on`/`off`.
+pub fn with_env_vars(mut command: Command) -> Command {
+    let args: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| {
+            arg.get_env().is_none()
+                && !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version)
+        })
+        .filter_map(|arg| Some((arg.get_id().clone(), var_name(arg.get_long()?))))
+        .collect();
+    for (id, var) in args {
+        command = command.mut_arg(id, |arg| {
+            if matches!(arg.get_action(), ArgAction::SetTrue) {
+                arg.env(var).value_parser(BoolishValueParser::new())
+            } else {
+                arg.env(var)
+            }
+        });
+    }
+
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, with_env_vars);
+    }
+    command
+}
+
+/// Default of a builder parameter: value of the variable of the flag `flag`
+/// or `default` if it is not set or invalid.
+pub fn env_or<T: FromStr>(flag: &str, default: T) -> T {
+    let var = var_name(flag);
+    let Ok(value) = env::var(&var) else {
+        return default;
+    };
+    value.parse().unwrap_or_else(|_| {
+        warn!("ignoring {var}: invalid value '{value}'");
+        default
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    #[test]
+    fn flags_read_their_variables() {
+        assert_eq!(var_name("base-pg-port"), "PIKE_BASE_PG_PORT");
+
+        let command = with_env_vars(
+            Command::new("pike").subcommand(
+                Command::new("run")
+                    .arg(Arg::new("data_dir").long("data-dir"))
+                    .arg(
+                        Arg::new("mirror")
+                            .long("mirror")
+                            .env("PIKE_PICODATA_MIRROR"),
+                    )
+                    .arg(Arg::new("daemon").long("daemon").action(ArgAction::SetTrue))
+                    .arg(Arg::new("topology").short('t')),
+            ),
+        );
+        let run = command.find_subcommand("run").unwrap();
+        let env = |id: &str| {
+            run.get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .unwrap()
+                .get_env()
+                .map(|env| env.to_string_lossy().into_owned())
+        };
+        assert_eq!(env("data_dir").as_deref(), Some("PIKE_DATA_DIR"));
+        assert_eq!(env("mirror").as_deref(), Some("PIKE_PICODATA_MIRROR"));
+        assert_eq!(env("daemon").as_deref(), Some("PIKE_DAEMON"));
+        assert_eq!(env("topology"), None);
+    }
+}
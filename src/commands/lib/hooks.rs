@@ -0,0 +1,216 @@
+//! Hooks of `pike.toml`, shell commands and webhooks which are called
+//! on lifecycle events of the cluster.
+//!
+//! Commands are run with `sh -c` in the plugin directory and get the event in
+//! `PIKE_EVENT` and its JSON in `PIKE_EVENT_JSON`, webhooks get the JSON as body.
+//! Failed hooks are reported as warnings and never fail the command itself.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// All instances of the cluster are started and plugins are enabled.
+    PostStart,
+    /// Instances are about to be stopped by `pike stop`.
+    PreStop,
+    /// Instance run in foreground exited while pike didn't stop it.
+    InstanceCrashed,
+    /// Plugin is installed and enabled by `pike run`.
+    PluginEnabled,
+}
+
+impl Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::PostStart => "post-start",
+            Self::PreStop => "pre-stop",
+            Self::InstanceCrashed => "instance-crashed",
+            Self::PluginEnabled => "plugin-enabled",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    /// Shell command, e.g. `"./scripts/notify.sh"`.
+    Command(String),
+    /// URL the event JSON is posted to, e.g. `{ url = "https://example.com/hook" }`.
+    Webhook { url: String },
+}
+
+/// `[hooks]` section of `pike.toml`, every event takes a hook or a list of them.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub post_start: Vec<Hook>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub pre_stop: Vec<Hook>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub instance_crashed: Vec<Hook>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub plugin_enabled: Vec<Hook>,
+    /// Directory commands are run in, the plugin directory `pike.toml` is read from.
+    #[serde(skip)]
+    pub work_dir: PathBuf,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Hook>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Hook),
+        Many(Vec<Hook>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(hook) => vec![hook],
+        OneOrMany::Many(hooks) => hooks,
+    })
+}
+
+impl Hooks {
+    fn hooks(&self, event: HookEvent) -> &[Hook] {
+        match event {
+            HookEvent::PostStart => &self.post_start,
+            HookEvent::PreStop => &self.pre_stop,
+            HookEvent::InstanceCrashed => &self.instance_crashed,
+            HookEvent::PluginEnabled => &self.plugin_enabled,
+        }
+    }
+
+    /// Calls hooks of the `event` one by one, `details` are added to the event JSON.
+    pub fn fire(&self, event: HookEvent, cluster_dir: &Path, details: Value) {
+        let hooks = self.hooks(event);
+        if hooks.is_empty() {
+            return;
+        }
+
+        let payload = event_payload(event, cluster_dir, details);
+        info!("Running {} hook(s) of {event}", hooks.len());
+        for hook in hooks {
+            if let Err(err) = self.run(hook, event, &payload) {
+                warn!("hook of {event} failed: {err:#}");
+            }
+        }
+    }
+
+    fn run(&self, hook: &Hook, event: HookEvent, payload: &Value) -> Result<()> {
+        match hook {
+            Hook::Command(command) => {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .current_dir(&self.work_dir)
+                    .env("PIKE_EVENT", event.to_string())
+                    .env("PIKE_EVENT_JSON", payload.to_string())
+                    .status()
+                    .context(format!("failed to run '{command}'"))?;
+                if !status.success() {
+                    bail!("'{command}' exited with {status}");
+                }
+            }
+            Hook::Webhook { url } => {
+                let agent: ureq::Agent = ureq::Agent::config_builder()
+                    .timeout_global(Some(WEBHOOK_TIMEOUT))
+                    .build()
+                    .into();
+                agent
+                    .post(url)
+                    .send_json(payload)
+                    .context(format!("failed to post event to {url}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn event_payload(event: HookEvent, cluster_dir: &Path, details: Value) -> Value {
+    let mut payload = Map::new();
+    payload.insert("event".into(), Value::String(event.to_string()));
+    payload.insert(
+        "cluster_dir".into(),
+        Value::String(
+            std::path::absolute(cluster_dir)
+                .unwrap_or_else(|_| cluster_dir.to_path_buf())
+                .display()
+                .to_string(),
+        ),
+    );
+    if let Value::Object(details) = details {
+        payload.extend(details);
+    }
+    Value::Object(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn hooks_are_parsed_from_one_or_many_values() {
+        let hooks: Hooks = toml::from_str(
+            r#"
+            post-start = "echo started"
+            pre-stop = ["echo stopping", { url = "http://127.0.0.1:9/hook" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(hooks.post_start, [Hook::Command("echo started".into())]);
+        assert_eq!(
+            hooks.pre_stop,
+            [
+                Hook::Command("echo stopping".into()),
+                Hook::Webhook {
+                    url: "http://127.0.0.1:9/hook".into()
+                }
+            ]
+        );
+        assert!(hooks.plugin_enabled.is_empty());
+        assert!(toml::from_str::<Hooks>("post-stop = \"echo\"").is_err());
+    }
+
+    #[test]
+    fn commands_get_event_in_environment() {
+        let workspace = TempWorkspace::new("hooks-command").unwrap();
+        let hooks = Hooks {
+            plugin_enabled: vec![
+                Hook::Command("exit 1".into()),
+                Hook::Command("echo \"$PIKE_EVENT $PIKE_EVENT_JSON\" > event.txt".into()),
+            ],
+            work_dir: workspace.path().to_path_buf(),
+            ..Hooks::default()
+        };
+        hooks.fire(
+            HookEvent::PluginEnabled,
+            &workspace.join("tmp/cluster"),
+            json!({"plugin": "p", "version": "0.1.0"}),
+        );
+
+        let output = fs::read_to_string(workspace.join("event.txt")).unwrap();
+        let (event, payload) = output.trim().split_once(' ').unwrap();
+        assert_eq!(event, "plugin-enabled");
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["event"], "plugin-enabled");
+        assert_eq!(payload["plugin"], "p");
+        assert!(payload["cluster_dir"]
+            .as_str()
+            .unwrap()
+            .ends_with("tmp/cluster"));
+    }
+}
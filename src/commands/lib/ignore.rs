@@ -0,0 +1,272 @@
+//! `.gitignore`-style exclude patterns for assets of the plugin.
+//!
+//! Paths are matched relative to the `assets` folder of the plugin, so the same
+//! patterns work for copying custom assets in `build.rs` and for `plugin pack`.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// File in the root of the plugin with exclude patterns for assets.
+pub const IGNORE_FILE_NAME: &str = ".pikeignore";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    /// Parses a line of the ignore file, `None` for empty lines and comments.
+    fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        // Pattern with a slash is anchored to the root, otherwise it matches at any depth.
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        let regex = Regex::new(&format!("{prefix}{}$", glob_to_regex(pattern)))
+            .context(format!("invalid ignore pattern '{line}'"))?;
+        Ok(Some(Self {
+            regex,
+            negated,
+            dir_only,
+        }))
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+/// List of exclude patterns, the last matching pattern wins as in `.gitignore`.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreList {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreList {
+    pub fn new<I, S>(patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut list = Self::default();
+        list.extend(patterns)?;
+        Ok(list)
+    }
+
+    /// Reads patterns from the ignore file, the list is empty if there is no file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::new(content.lines()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).context(format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn extend<I, S>(&mut self, patterns: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for pattern in patterns {
+            if let Some(rule) = Rule::parse(pattern.as_ref())? {
+                self.rules.push(rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the path relative to the `assets` folder, files of ignored directories are ignored too.
+    #[must_use]
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut parents: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
+        parents.reverse();
+        parents.iter().any(|parent| self.matches(parent, true)) || self.matches(path, is_dir)
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.to_string_lossy();
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            if rule.regex.is_match(&path) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Calls `f` with the path relative to `prefix` and the full path for every entry
+/// of `dir` which is not ignored, directories are visited before their content.
+pub fn walk<F>(dir: &Path, prefix: &Path, ignore: &IgnoreList, f: &mut F) -> Result<()>
+where
+    F: FnMut(&Path, &Path, bool) -> Result<()>,
+{
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let relative = prefix.join(path.file_name().expect("entry of directory has a name"));
+        let is_dir = path.is_dir();
+        if ignore.is_ignored(&relative, is_dir) {
+            continue;
+        }
+        f(&relative, &path, is_dir)?;
+        if is_dir {
+            walk(&path, &relative, ignore, f)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies content of `from` into `to` skipping ignored entries, existing files are overwritten.
+///
+/// `prefix` is the path of `to` relative to the `assets` folder.
+#[allow(dead_code)]
+pub fn copy_dir(from: &Path, to: &Path, prefix: &Path, ignore: &IgnoreList) -> Result<()> {
+    fs::create_dir_all(to).context(format!("failed to create {}", to.display()))?;
+    walk(from, prefix, ignore, &mut |relative, path, is_dir| {
+        let destination = to.join(relative.strip_prefix(prefix)?);
+        if is_dir {
+            fs::create_dir_all(&destination)
+                .context(format!("failed to create {}", destination.display()))?;
+        } else {
+            fs::copy(path, &destination).context(format!(
+                "failed to copy {} to {}",
+                path.display(),
+                destination.display()
+            ))?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn patterns_follow_gitignore_rules() {
+        let ignore = IgnoreList::new([
+            "# editor files",
+            "*.swp",
+            ".#*",
+            "",
+            "tmp/",
+            "/dashboards/draft.json",
+            "**/cache/**",
+            "*.log",
+            "!keep.log",
+            "file[0-9].txt",
+        ])
+        .unwrap();
+        let ignored = |path: &str, is_dir: bool| ignore.is_ignored(Path::new(path), is_dir);
+
+        assert!(ignored("main.rs.swp", false));
+        assert!(ignored("src/.#main.rs", false));
+        assert!(ignored("src/tmp", true));
+        assert!(!ignored("src/tmp", false));
+        assert!(ignored("src/tmp/file.rs", false));
+        assert!(ignored("dashboards/draft.json", false));
+        assert!(!ignored("other/dashboards/draft.json", false));
+        assert!(ignored("a/cache/b/c", false));
+        assert!(ignored("logs/debug.log", false));
+        assert!(!ignored("logs/keep.log", false));
+        assert!(ignored("file1.txt", false));
+        assert!(!ignored("file10.txt", false));
+        assert!(!ignored("src/main.rs", false));
+
+        assert!(!IgnoreList::default().is_ignored(Path::new("main.rs.swp"), false));
+    }
+
+    #[test]
+    fn ignored_entries_are_not_copied() {
+        let workspace = TempWorkspace::new("ignore-copy-dir").unwrap();
+        let from = workspace.join("src");
+        fs::create_dir_all(from.join("nested/tmp")).unwrap();
+        fs::write(from.join("main.rs"), "").unwrap();
+        fs::write(from.join("main.rs.swp"), "").unwrap();
+        fs::write(from.join("nested/lib.rs"), "").unwrap();
+        fs::write(from.join("nested/tmp/scratch.rs"), "").unwrap();
+
+        let ignore = IgnoreList::new(["*.swp", "tmp/"]).unwrap();
+        let to = workspace.join("assets/src");
+        copy_dir(&from, &to, Path::new("src"), &ignore).unwrap();
+
+        let mut copied = vec![];
+        walk(
+            &to,
+            Path::new(""),
+            &IgnoreList::default(),
+            &mut |relative, _, _| {
+                copied.push(relative.display().to_string());
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(copied, ["main.rs", "nested", "nested/lib.rs"]);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::commands::lib::run_query_in_picodata_admin;
+use crate::commands::lib::sql::quote_lua;
 use anyhow::{bail, Context, Result};
 use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::{
@@ -11,7 +14,11 @@ use std::{
 const ADMIN_SOCKET_FILENAME: &str = "admin.sock";
 
 const GET_INSTANCE_NAME: &str = "\\lua\npico.instance_info().name";
+const GET_INSTANCE_PID: &str = "\\lua\nbox.info.pid";
 const GET_INSTANCE_CURRENT_STATE: &str = "\\lua\npico.instance_info().current_state.variant";
+// Raft id of the leader known to the instance, 0 if there is no leader.
+#[allow(dead_code)]
+const GET_RAFT_LEADER_ID: &str = "\\lua\npico.raft_status().leader_id";
 
 // Get configured number of buckets in the instance tier.
 const GET_TIER_BUCKET_COUNT: &str =
@@ -39,20 +46,101 @@ for _, router in pairs(vshard.router.internal.routers) do \
 end; \
 return require('json').encode(out)";
 
-fn parse_lua_json(lua_output: &str) -> Result<HashMap<String, u32>> {
+// Get statuses of replication upstreams of the instance, i.e. other instances
+// of its replicaset it replicates from, "follow" if replication is healthy.
+const GET_REPLICATION_UPSTREAM_STATUSES: &str = "\\lua\n\
+local out = setmetatable({}, { __serialize = 'seq' }); \
+for _, peer in pairs(box.info.replication) do \
+    if peer.upstream ~= nil then \
+        table.insert(out, peer.upstream.status); \
+    end; \
+end; \
+return require('json').encode(out)";
+
+// Get map of [service_name, [key, value]] stored in `_pico_plugin_config`
+// for the given plugin version. Tables are forced to be encoded as maps,
+// so an empty config produces `{}` rather than `[]`.
+fn get_plugin_config_query(plugin_name: &str, plugin_version: &str) -> String {
+    let plugin_name = quote_lua(plugin_name);
+    let plugin_version = quote_lua(plugin_version);
+    format!(
+        "\\lua\n\
+local out = setmetatable({{}}, {{ __serialize = 'map' }}); \
+for _, t in box.space._pico_plugin_config:pairs({{ {plugin_name}, {plugin_version} }}) do \
+    if out[t.entity] == nil then \
+        out[t.entity] = setmetatable({{}}, {{ __serialize = 'map' }}); \
+    end; \
+    out[t.entity][t.key] = t.value; \
+end; \
+return require('json').encode(out)"
+    )
+}
+
+// Run SQL query and return its result with column metadata as JSON.
+fn get_sql_query(sql: &str) -> String {
+    format!(
+        "\\lua\nreturn require('json').encode(pico.sql({}))",
+        quote_lua(sql)
+    )
+}
+
+/// Row of SQL query result, mapping of column names to their values.
+///
+/// Can be used as a row type of [`InstanceSocketClient::query`]
+/// when the shape of the result is not known in advance.
+pub type Row = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Deserialize)]
+struct SqlColumn {
+    name: String,
+}
+
+/// Result of `pico.sql`, DML queries return neither metadata nor rows.
+#[derive(Deserialize)]
+struct SqlResult {
+    #[serde(default)]
+    metadata: Vec<SqlColumn>,
+    #[serde(default)]
+    rows: Vec<Vec<serde_json::Value>>,
+}
+
+impl SqlResult {
+    fn into_rows<T: DeserializeOwned>(self) -> Result<Vec<T>> {
+        let columns: Vec<String> = self.metadata.into_iter().map(|c| c.name).collect();
+        self.rows
+            .into_iter()
+            .map(|values| {
+                let row: Row = columns.iter().cloned().zip(values).collect();
+                serde_json::from_value(serde_json::Value::Object(row))
+                    .context("failed to convert SQL row")
+            })
+            .collect()
+    }
+}
+
+/// Extracts the value line from YAML output of picodata admin.
+pub(crate) fn single_line_output(stdout: &str) -> Result<String> {
+    let Some(output) = stdout.lines().find_map(|line| line.strip_prefix("- ")) else {
+        bail!("unable to extract single line from Lua query output '{stdout}'");
+    };
+    Ok(output.to_string())
+}
+
+pub(crate) fn parse_lua_json<T: DeserializeOwned>(lua_output: &str) -> Result<T> {
     let trimmed = lua_output.trim();
 
-    // remove wrapping single quotes if present
-    let json = if trimmed.starts_with('\'') && trimmed.ends_with('\'') {
-        &trimmed[1..trimmed.len() - 1]
+    // remove wrapping single quotes if present, quotes inside
+    // single-quoted YAML scalar are escaped by doubling
+    let json = if trimmed.len() >= 2 && trimmed.starts_with('\'') && trimmed.ends_with('\'') {
+        trimmed[1..trimmed.len() - 1].replace("''", "'")
     } else {
-        trimmed
+        trimmed.to_string()
     };
 
-    Ok(serde_json::from_str(json)?)
+    Ok(serde_json::from_str(&json)?)
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InstanceState {
     Online,
     Offline,
@@ -60,6 +148,7 @@ pub enum InstanceState {
 }
 
 impl InstanceState {
+    #[must_use]
     pub fn is_online(self) -> bool {
         matches!(self, InstanceState::Online)
     }
@@ -100,12 +189,7 @@ impl<'a> InstanceSocketClient<'a> {
     /// Only single line is extracted from returned STDOUT.
     fn get_lua_single_line_output(&self, lua_query: &str) -> Result<String> {
         let stdout = run_query_in_picodata_admin(self.picodata_path, &self.socket_path, lua_query)?;
-
-        let Some(output) = stdout.lines().find_map(|line| line.strip_prefix("- ")) else {
-            bail!("unable to extract single line from Lua query output '{stdout}'");
-        };
-
-        Ok(output.to_string())
+        single_line_output(&stdout)
     }
 
     fn get_parsed_lua_output<T>(&self, lua_query: &str) -> anyhow::Result<T>
@@ -126,12 +210,48 @@ impl<'a> InstanceSocketClient<'a> {
         self.get_parsed_lua_output(GET_INSTANCE_NAME)
     }
 
+    /// Fetches pid of the instance process from admin socket.
+    pub fn pid(&self) -> Result<u32> {
+        self.get_parsed_lua_output(GET_INSTANCE_PID)
+    }
+
     /// Fetches state of the instance from admin socket.
     pub fn current_state(&self) -> Result<InstanceState> {
         self.get_lua_single_line_output(GET_INSTANCE_CURRENT_STATE)
             .and_then(|state| state.parse())
     }
 
+    /// Fetches raft id of the leader known to the instance.
+    #[allow(dead_code)]
+    pub fn leader_id(&self) -> Result<u64> {
+        let leader_id = self.get_parsed_lua_output(GET_RAFT_LEADER_ID)?;
+        if leader_id == 0 {
+            bail!("raft leader is not elected");
+        }
+        Ok(leader_id)
+    }
+
+    /// Fetches name of the raft leader from `_pico_instance`.
+    #[allow(dead_code)]
+    pub fn leader_name(&self) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Instance {
+            name: String,
+        }
+
+        let leader_id = self.leader_id()?;
+        let instances: Vec<Instance> = self.query(&format!(
+            "SELECT name FROM _pico_instance WHERE raft_id = {leader_id}"
+        ))?;
+        instances
+            .into_iter()
+            .next()
+            .map(|instance| instance.name)
+            .context(format!(
+                "raft leader {leader_id} is not found in _pico_instance"
+            ))
+    }
+
     // Fetches map of [replicaset_uuid, bucket_count] obtained from
     // vshard router for the instance tier.
     pub fn vshard_replicaset_map(&self) -> Result<HashMap<String, u32>> {
@@ -139,6 +259,35 @@ impl<'a> InstanceSocketClient<'a> {
             .and_then(|o| parse_lua_json(&o))
     }
 
+    /// Fetches statuses of replication upstreams of the instance, e.g. `follow` or `disconnected`.
+    pub fn replication_upstream_statuses(&self) -> Result<Vec<String>> {
+        self.get_lua_single_line_output(GET_REPLICATION_UPSTREAM_STATUSES)
+            .and_then(|o| parse_lua_json(&o))
+    }
+
+    /// Fetches services config of the plugin from `_pico_plugin_config`.
+    ///
+    /// Returns mapping of service names to their configuration keys.
+    pub fn plugin_config(
+        &self,
+        plugin_name: &str,
+        plugin_version: &str,
+    ) -> Result<HashMap<String, HashMap<String, serde_json::Value>>> {
+        self.get_lua_single_line_output(&get_plugin_config_query(plugin_name, plugin_version))
+            .and_then(|o| parse_lua_json(&o))
+    }
+
+    /// Runs SQL query and converts each row of the result into `T`.
+    ///
+    /// Rows are deserialized from mappings of column names to their values,
+    /// so `T` is usually a struct with fields named after the selected columns.
+    pub fn query<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        self.get_lua_single_line_output(&get_sql_query(sql))
+            .and_then(|o| parse_lua_json::<SqlResult>(&o))
+            .and_then(SqlResult::into_rows)
+            .with_context(|| format!("failed to execute SQL query: {sql}"))
+    }
+
     /// Fetches configured number of buckets in the instance tier.
     pub fn tier_bucket_count(&self) -> Result<u32> {
         self.get_parsed_lua_output(GET_TIER_BUCKET_COUNT)
@@ -165,10 +314,12 @@ impl<'a> InstanceSocketClient<'a> {
         let instance_name = self.instance_name()?;
 
         let current_master_name: String = self.get_parsed_lua_output(&format!(
-            "\\lua\npico.sql(\"select current_master_name from _pico_replicaset where uuid = ?\", {{ \"{replicaset_uuid}\" }}).rows[1][1]",
+            "\\lua\npico.sql(\"select current_master_name from _pico_replicaset where uuid = ?\", {{ {} }}).rows[1][1]",
+            quote_lua(replicaset_uuid)
         ))?;
         let target_master_name: String = self.get_parsed_lua_output(&format!(
-            "\\lua\npico.sql(\"select target_master_name from _pico_replicaset where uuid = ?\", {{ \"{replicaset_uuid}\" }}).rows[1][1]",
+            "\\lua\npico.sql(\"select target_master_name from _pico_replicaset where uuid = ?\", {{ {} }}).rows[1][1]",
+            quote_lua(replicaset_uuid)
         ))?;
 
         if current_master_name != target_master_name {
@@ -178,3 +329,73 @@ impl<'a> InstanceSocketClient<'a> {
         Ok(instance_name == target_master_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Instance {
+        name: String,
+        raft_id: u64,
+        replicaset_name: Option<String>,
+    }
+
+    #[test]
+    fn sql_rows_are_deserialized_by_column_names() {
+        let output = r#"'{"metadata":[{"name":"name","type":"string"},{"name":"raft_id","type":"unsigned"},{"name":"replicaset_name","type":"string"}],"rows":[["i1",1,"r1"],["i''2",2,null]]}'"#;
+
+        let rows: Vec<Instance> = parse_lua_json::<SqlResult>(output)
+            .and_then(SqlResult::into_rows)
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                Instance {
+                    name: "i1".into(),
+                    raft_id: 1,
+                    replicaset_name: Some("r1".into()),
+                },
+                Instance {
+                    name: "i'2".into(),
+                    raft_id: 2,
+                    replicaset_name: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sql_rows_fall_back_to_generic_row() {
+        let output = r#"{"metadata":[{"name":"a"},{"name":"b"}],"rows":[[1,[true]]]}"#;
+
+        let rows: Vec<Row> = parse_lua_json::<SqlResult>(output)
+            .and_then(SqlResult::into_rows)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["a"], 1);
+        assert_eq!(rows[0]["b"], serde_json::json!([true]));
+    }
+
+    #[test]
+    fn dml_result_has_no_rows() {
+        let rows: Vec<Row> = parse_lua_json::<SqlResult>(r#"{"row_count":3}"#)
+            .and_then(SqlResult::into_rows)
+            .unwrap();
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn plugin_config_query_quotes_identifiers_as_lua_strings() {
+        let query = get_plugin_config_query("weather\u{1}", "0.1.0\"");
+        assert!(query.contains(r#"pairs({ "weather\001", "0.1.0\"" })"#));
+    }
+
+    #[test]
+    fn sql_query_is_quoted_as_lua_string() {
+        assert_eq!(
+            get_sql_query(r#"select "name" from t where a = 'x'"#),
+            "\\lua\nreturn require('json').encode(pico.sql(\"select \\\"name\\\" from t where a = 'x'\"))"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Checksums and signatures of plugin archives.
+//!
+//! `plugin pack` writes `<archive>.sha256` in the format of `sha256sum` next to
+//! the archive and, with a minisign secret key, a detached signature
+//! `<archive>.minisig`. Archives are signed by the `minisign` tool, so the
+//! password of the key is asked by it, signatures are verified by pike itself.
+
+use anyhow::{anyhow, bail, Context, Result};
+use minisign_verify::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `<archive>.<extension>` next to the archive.
+fn sibling_path(archive_path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(archive_path.file_name().unwrap_or_default());
+    name.push(format!(".{extension}"));
+    archive_path.with_file_name(name)
+}
+
+#[must_use]
+pub fn checksum_path(archive_path: &Path) -> PathBuf {
+    sibling_path(archive_path, "sha256")
+}
+
+#[must_use]
+pub fn signature_path(archive_path: &Path) -> PathBuf {
+    sibling_path(archive_path, "minisig")
+}
+
+/// Hex encoded SHA-256 of the file.
+pub fn sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context(format!("failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Writes `<archive>.sha256` with `sha256` of the archive, it can be checked by `sha256sum -c`.
+pub fn write_checksum(archive_path: &Path, sha256: &str) -> Result<PathBuf> {
+    let path = checksum_path(archive_path);
+    let file_name = archive_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    fs::write(&path, format!("{sha256}  {file_name}\n"))
+        .context(format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Checks the archive against `<archive>.sha256`, returns `false` if there is no checksum file.
+pub fn verify_checksum(archive_path: &Path) -> Result<bool> {
+    let path = checksum_path(archive_path);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).context(format!("failed to read {}", path.display())),
+    };
+    let Some(expected) = content.split_whitespace().next() else {
+        bail!("checksum file {} is empty", path.display());
+    };
+    let actual = sha256(archive_path)?;
+    if !expected.eq_ignore_ascii_case(&actual) {
+        bail!(
+            "checksum of {} is {actual}, but {} expects {expected}",
+            archive_path.display(),
+            path.display()
+        );
+    }
+    Ok(true)
+}
+
+/// Signs the archive with minisign secret key, returns path of the signature.
+pub fn sign(archive_path: &Path, secret_key_path: &Path) -> Result<PathBuf> {
+    let path = signature_path(archive_path);
+    let status = Command::new("minisign")
+        .arg("-S")
+        .arg("-s")
+        .arg(secret_key_path)
+        .arg("-m")
+        .arg(archive_path)
+        .arg("-x")
+        .arg(&path)
+        .status()
+        .context("failed to run minisign, make sure it is installed")?;
+    if !status.success() {
+        bail!(
+            "minisign failed to sign {} with {}: {status}",
+            archive_path.display(),
+            secret_key_path.display()
+        );
+    }
+    Ok(path)
+}
+
+/// Public key of minisign read from the `.pub` file or from the file with the bare key.
+fn read_public_key(path: &Path) -> Result<PublicKey> {
+    let content = fs::read_to_string(path)
+        .context(format!("failed to read public key {}", path.display()))?;
+    PublicKey::decode(&content)
+        .or_else(|_| PublicKey::from_base64(content.trim()))
+        .map_err(|err| anyhow!("invalid public key {}: {err}", path.display()))
+}
+
+/// Checks `<archive>.minisig` of the archive with minisign public key.
+pub fn verify_signature(archive_path: &Path, public_key_path: &Path) -> Result<()> {
+    let public_key = read_public_key(public_key_path)?;
+    let path = signature_path(archive_path);
+    let content = fs::read_to_string(&path).context(format!(
+        "failed to read signature {}, archive must be signed to be verified with a key",
+        path.display()
+    ))?;
+    let signature = Signature::decode(&content)
+        .map_err(|err| anyhow!("invalid signature {}: {err}", path.display()))?;
+
+    let mut verifier = public_key.verify_stream(&signature).map_err(|err| {
+        anyhow!(
+            "signature {} can't be verified with {}: {err}",
+            path.display(),
+            public_key_path.display()
+        )
+    })?;
+    let mut file =
+        File::open(archive_path).context(format!("failed to open {}", archive_path.display()))?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = file
+            .read(&mut buf)
+            .context(format!("failed to read {}", archive_path.display()))?;
+        if len == 0 {
+            break;
+        }
+        verifier.update(&buf[..len]);
+    }
+    verifier.finalize().map_err(|err| {
+        anyhow!(
+            "signature {} of {} is not valid: {err}",
+            path.display(),
+            archive_path.display()
+        )
+    })
+}
+
+/// Checks checksum of the archive if it has one and its signature if `public_key_path` is set.
+pub fn verify_archive(archive_path: &Path, public_key_path: Option<&Path>) -> Result<()> {
+    verify_checksum(archive_path)?;
+    if let Some(public_key_path) = public_key_path {
+        verify_signature(archive_path, public_key_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    // Key and signature of `test` from the test suite of minisign-verify.
+    const PUBLIC_KEY: &str = "untrusted comment: minisign public key E7620F1842B4E81F
+RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3
+";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+
+    #[test]
+    fn checksum_is_verified() {
+        let dir = TempWorkspace::new("integrity-checksum").unwrap();
+        let archive = dir.join("plugin_0.1.0.tar.gz");
+        fs::write(&archive, "test").unwrap();
+        assert!(!verify_checksum(&archive).unwrap());
+
+        write_checksum(&archive, &sha256(&archive).unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("plugin_0.1.0.tar.gz.sha256")).unwrap(),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  plugin_0.1.0.tar.gz\n"
+        );
+        assert!(verify_checksum(&archive).unwrap());
+
+        fs::write(&archive, "Test").unwrap();
+        assert!(verify_checksum(&archive).is_err());
+    }
+
+    #[test]
+    fn signature_is_verified() {
+        let dir = TempWorkspace::new("integrity-signature").unwrap();
+        let archive = dir.join("plugin_0.1.0.tar.gz");
+        let public_key = dir.join("minisign.pub");
+        fs::write(&archive, "test").unwrap();
+        fs::write(&public_key, PUBLIC_KEY).unwrap();
+
+        assert!(verify_signature(&archive, &public_key).is_err());
+        assert!(verify_archive(&archive, None).is_ok());
+
+        fs::write(signature_path(&archive), SIGNATURE).unwrap();
+        verify_signature(&archive, &public_key).unwrap();
+        verify_archive(&archive, Some(&public_key)).unwrap();
+
+        fs::write(&archive, "Test").unwrap();
+        assert!(verify_signature(&archive, &public_key).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Lock of the data directory of the cluster, so that `run`, `stop` and `clean`
+//! invoked at the same time, e.g. by an IDE task and in a terminal, don't corrupt it.
+//!
+//! The lock is `flock` of `pike.lock` in the data directory, pid and operation of
+//! the owner are written into the file. The kernel releases the lock when the owner
+//! exits, but its child processes which inherited the descriptor keep it, so the
+//! lock of a dead owner can be taken over with `--force`.
+
+use crate::error::Error;
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use nix::errno::Errno;
+use nix::sys::signal;
+use nix::unistd::Pid;
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, PoisonError};
+
+pub const LOCK_FILE_NAME: &str = "pike.lock";
+
+/// Lock files held by this process, e.g. `clean` holds the lock while it stops the cluster.
+static HELD: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Exclusive lock of the data directory, released on drop.
+#[derive(Debug)]
+pub struct DataDirLock {
+    /// `None` if the lock has already been held by this process.
+    file: Option<File>,
+    path: PathBuf,
+}
+
+/// Owner of the lock as written into the lock file.
+#[derive(Debug, PartialEq)]
+struct Owner {
+    pid: i32,
+    operation: String,
+}
+
+impl Owner {
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let (pid, operation) = content.trim().split_once(' ')?;
+        Some(Self {
+            pid: pid.parse().ok()?,
+            operation: operation.to_string(),
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        // EPERM means the process exists but belongs to another user.
+        !matches!(
+            signal::kill(Pid::from_raw(self.pid), None),
+            Err(Errno::ESRCH)
+        )
+    }
+}
+
+impl DataDirLock {
+    /// Locks the data directory for `operation`, fails if another pike process holds the lock.
+    /// With `force` the lock is taken over if its owner is not running anymore.
+    pub fn acquire(
+        plugin_path: &Path,
+        data_dir: &Path,
+        operation: &str,
+        force: bool,
+    ) -> Result<Self> {
+        let dir = plugin_path.join(data_dir);
+        fs::create_dir_all(&dir).context(format!("failed to create {}", dir.display()))?;
+        let path = std::path::absolute(dir.join(LOCK_FILE_NAME))?;
+
+        let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+        if held.contains(&path) {
+            return Ok(Self { file: None, path });
+        }
+        let file = match try_lock(&path, operation)? {
+            Some(file) => file,
+            None => take_over(&path, operation, force)?,
+        };
+        held.insert(path.clone());
+        Ok(Self {
+            file: Some(file),
+            path,
+        })
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            HELD.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&self.path);
+        }
+    }
+}
+
+/// Locks the file and writes the owner into it, `None` if it is locked by another process.
+fn try_lock(path: &Path, operation: &str) -> Result<Option<File>> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .context(format!("failed to open lock file {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(None),
+        Err(TryLockError::Error(err)) => {
+            return Err(err).context(format!("failed to lock {}", path.display()))
+        }
+    }
+    file.set_len(0)?;
+    writeln!(file, "{} {operation}", process::id())
+        .context(format!("failed to write lock file {}", path.display()))?;
+    Ok(Some(file))
+}
+
+/// Takes over the lock held by a dead owner, the locked file is replaced by a new one.
+fn take_over(path: &Path, operation: &str, force: bool) -> Result<File> {
+    let locked =
+        |message: String| -> anyhow::Error { Error::DataDirLocked(anyhow!(message)).into() };
+    let Some(owner) = Owner::read(path) else {
+        return Err(locked(format!(
+            "another pike operation is in progress, {} is locked",
+            path.display()
+        )));
+    };
+    if owner.is_alive() {
+        return Err(locked(format!(
+            "another pike operation is in progress (pid {}, {}), wait for it to finish",
+            owner.pid, owner.operation
+        )));
+    }
+    if !force {
+        return Err(locked(format!(
+            "{} is locked by {} (pid {}) which is not running, its child processes keep the lock, \
+            run with --force to take it over",
+            path.display(),
+            owner.operation,
+            owner.pid
+        )));
+    }
+
+    warn!(
+        "Taking over the lock of {} from {} (pid {}) which is not running",
+        path.display(),
+        owner.operation,
+        owner.pid
+    );
+    fs::remove_file(path).context(format!("failed to remove {}", path.display()))?;
+    try_lock(path, operation)?.ok_or_else(|| {
+        locked(format!(
+            "{} has been locked by another pike operation meanwhile",
+            path.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn data_dir_is_locked_by_one_operation() {
+        let workspace = TempWorkspace::new("lock").unwrap();
+        let data_dir = Path::new("tmp");
+        let path = workspace.join("tmp").join(LOCK_FILE_NAME);
+
+        let lock = DataDirLock::acquire(workspace.path(), data_dir, "run", false).unwrap();
+        assert_eq!(
+            Owner::read(&path),
+            Some(Owner {
+                pid: i32::try_from(process::id()).unwrap(),
+                operation: "run".to_string(),
+            })
+        );
+        // The process holding the lock can acquire it again, e.g. `clean` calling `stop`.
+        drop(DataDirLock::acquire(workspace.path(), data_dir, "stop", false).unwrap());
+
+        // Another open file description conflicts like another process.
+        let other = OpenOptions::new().write(true).open(&path).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(lock);
+        other.try_lock().unwrap();
+
+        // The owner is alive, so the lock is not taken over even with force.
+        let err = take_over(&path, "clean", true).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("another pike operation is in progress"));
+        assert!(matches!(Error::from(err), Error::DataDirLocked(_)));
+
+        // Pid of an exited child is not reused right away.
+        let mut child = process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        fs::write(&path, format!("{dead_pid} run\n")).unwrap();
+        let err = take_over(&path, "clean", false).unwrap_err();
+        assert!(err.to_string().contains("run with --force"));
+
+        let file = take_over(&path, "clean", true).unwrap();
+        assert_eq!(Owner::read(&path).unwrap().operation, "clean");
+        // The file of the dead owner is replaced, so `other` doesn't hold the new lock.
+        drop(other);
+        drop(file);
+    }
+}
@@ -1,17 +1,40 @@
-use anyhow::{bail, Context, Result};
+use crate::error::Error;
+use anyhow::{anyhow, bail, Context, Result};
 use colored::Colorize;
-use flate2::bufread::GzDecoder;
 use fs_extra::dir;
 use log::info;
-use std::fmt::Display;
-use std::fs::{self, File, FileType};
-use std::io::{BufRead, BufReader, Read, Write};
+use process::{LogCapture, ManagedProcess};
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::{Display, Write as _};
+use std::fs::{self, FileType};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command, Stdio};
-use tar::Archive;
-
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+
+pub mod archive;
+pub mod cluster_metadata;
+pub mod cluster_state;
+pub mod compat;
+pub mod events;
+pub mod flag_env;
+pub mod hooks;
+pub mod ignore;
 pub mod instance_info;
+pub mod integrity;
+pub mod lock;
+pub mod pid_file;
+pub mod pike_config;
+pub mod platform;
+pub mod poll;
+pub mod process;
+pub mod repository;
+pub mod s3;
+pub mod sql;
+pub mod temp_workspace;
+pub mod timings;
 
 #[cfg(any(target_os = "linux", target_os = "freebsd"))]
 pub const LIB_EXT: &str = "so";
@@ -42,6 +65,28 @@ impl Display for BuildType {
     }
 }
 
+/// Format of the result which a command prints to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable output, logs only.
+    #[default]
+    Text,
+    /// Single JSON document for scripts, logs still go to stderr.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown output format '{s}', expected text or json"),
+        }
+    }
+}
+
 pub fn is_plugin_dir(path: &Path) -> bool {
     if !path.is_dir() {
         return false;
@@ -88,33 +133,22 @@ pub fn is_plugin_archive(test_path: &Path) -> Result<()> {
     if !test_path.is_file() {
         bail!("plugin archive path must be a file");
     }
-    let file = File::options()
-        .read(true)
-        .write(false)
-        .create(false)
-        .open(test_path)
-        .context("unable to open plugin archive candidate")?;
-    let buf_reader = BufReader::new(file);
-    let file_untar = GzDecoder::new(buf_reader);
-    let mut archive = Archive::new(file_untar);
-    let Ok(archive_entries) = archive.entries() else {
+    let Ok(entry_paths) = archive::entry_paths(test_path) else {
         bail!("unable to read plugin archive candidate");
     };
     let mut has_manifest = false;
     let mut has_lib = false;
     let lib_suffix = format!(".{LIB_EXT}");
-    for entry in archive_entries.filter_map(Result::ok) {
-        if let Ok(entry_path) = entry.path() {
-            // plugin_name / plugin_version / root_file_name
-            if entry_path.components().count() == 3 {
-                if let Some(last_part) = entry_path.components().next_back() {
-                    has_manifest = has_manifest || last_part.as_os_str() == "manifest.yaml";
-                    has_lib = has_lib
-                        || last_part
-                            .as_os_str()
-                            .to_string_lossy()
-                            .ends_with(&lib_suffix);
-                }
+    for entry_path in entry_paths {
+        // plugin_name / plugin_version / root_file_name
+        if entry_path.components().count() == 3 {
+            if let Some(last_part) = entry_path.components().next_back() {
+                has_manifest = has_manifest || last_part.as_os_str() == "manifest.yaml";
+                has_lib = has_lib
+                    || last_part
+                        .as_os_str()
+                        .to_string_lossy()
+                        .ends_with(&lib_suffix);
             }
         }
         if has_manifest && has_lib {
@@ -130,42 +164,288 @@ pub fn is_plugin_archive(test_path: &Path) -> Result<()> {
     bail!("plugin archive candidate has invalid structure");
 }
 
+/// Directory with built plugins which pike passes to picodata as `--share-dir`.
+pub const PIKE_PLUGINS_DIR_ENV: &str = "PIKE_PLUGINS_DIR";
+/// Build profile of plugins: `debug` or `release`.
+pub const PIKE_PROFILE_ENV: &str = "PIKE_PROFILE";
+/// Data directory of the cluster, set only when the plugin is built by `run`.
+pub const PIKE_DATA_DIR_ENV: &str = "PIKE_DATA_DIR";
+
+/// Flags passed through to `cargo build` of plugins.
+#[derive(Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct CargoArgs {
+    /// Features of the plugin to activate, `--features`.
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    pub locked: bool,
+    pub offline: bool,
+    /// Number of parallel jobs of cargo, `--jobs`.
+    pub jobs: Option<u32>,
+    /// Print output of cargo only if the build fails, it is not passed to cargo.
+    pub quiet: bool,
+}
+
+impl CargoArgs {
+    fn apply_to(&self, command: &mut Command) {
+        if !self.features.is_empty() {
+            command.arg("--features").arg(self.features.join(","));
+        }
+        if self.no_default_features {
+            command.arg("--no-default-features");
+        }
+        if self.locked {
+            command.arg("--locked");
+        }
+        if self.offline {
+            command.arg("--offline");
+        }
+        if let Some(jobs) = self.jobs {
+            command.arg("--jobs").arg(jobs.to_string());
+        }
+    }
+}
+
 #[allow(clippy::needless_pass_by_value)]
-pub fn cargo_build(build_type: BuildType, target_dir: &PathBuf, build_dir: &PathBuf) -> Result<()> {
-    let mut args = vec!["build"];
-    if let BuildType::Release = build_type {
-        args.push("--release");
+pub fn cargo_build(
+    build_type: BuildType,
+    target_dir: &Path,
+    build_dir: &Path,
+    cargo_args: &CargoArgs,
+) -> Result<()> {
+    let plugins_dir = build_dir.join(target_dir).join(build_type.to_string());
+    cargo_build_with_env(
+        build_type,
+        target_dir,
+        build_dir,
+        &plugins_dir,
+        None,
+        cargo_args,
+        "",
+    )
+}
+
+/// `cargo build` exited with an error, its output is kept for callers of the library,
+/// see [`crate::Error::build_output`].
+#[derive(Debug)]
+pub struct BuildFailure {
+    pub status: ExitStatus,
+    /// Interleaved stdout and stderr of cargo.
+    #[allow(dead_code)]
+    pub output: String,
+}
+
+impl Display for BuildFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "build error: cargo build exited with {}", self.status)
+    }
+}
+
+impl std::error::Error for BuildFailure {}
+
+/// Runs `cargo build` telling build scripts of plugins through `PIKE_*` variables
+/// where pike takes built plugins from and where the cluster keeps its data.
+///
+/// Lines of cargo output are echoed with `log_prefix`, so output of concurrent builds
+/// can be told apart.
+pub fn cargo_build_with_env(
+    build_type: BuildType,
+    target_dir: &Path,
+    build_dir: &Path,
+    plugins_dir: &Path,
+    data_dir: Option<&Path>,
+    cargo_args: &CargoArgs,
+    log_prefix: &str,
+) -> Result<()> {
+    let mut command = cargo_build_command(
+        build_type,
+        target_dir,
+        build_dir,
+        plugins_dir,
+        data_dir,
+        cargo_args,
+    )?;
+
+    // Cargo reports progress and errors to stderr, both streams are echoed there live,
+    // so stdout of pike is kept for results of commands, e.g. `--output json`.
+    let mut cargo = ManagedProcess::spawn(
+        "cargo",
+        command.stdout(Stdio::piped()).stderr(Stdio::piped()),
+    )
+    .map_err(|err| Error::BuildError(err.context("running cargo build")))?;
+    cargo.capture_logs(
+        &LogCapture::default()
+            .prefix(log_prefix)
+            .echo_to_stderr(true)
+            .quiet(cargo_args.quiet)
+            .keep_output(),
+    )?;
+
+    let status = cargo.wait()?;
+    if !status.success() {
+        let output = cargo.output();
+        if cargo_args.quiet {
+            for line in output.lines() {
+                eprintln!("{log_prefix}{line}");
+            }
+        }
+        return Err(Error::BuildError(BuildFailure { status, output }.into()).into());
     }
 
-    let mut child = Command::new("cargo")
-        .args(args)
+    Ok(())
+}
+
+fn cargo_build_command(
+    build_type: BuildType,
+    target_dir: &Path,
+    build_dir: &Path,
+    plugins_dir: &Path,
+    data_dir: Option<&Path>,
+    cargo_args: &CargoArgs,
+) -> Result<Command> {
+    let mut command = Command::new("cargo");
+    command.arg("build");
+    if let BuildType::Release = build_type {
+        command.arg("--release");
+    }
+    cargo_args.apply_to(&mut command);
+    command
         .arg("--target-dir")
         .arg(target_dir)
-        .stdout(Stdio::piped())
-        .current_dir(build_dir)
-        .spawn()
-        .context("running cargo build")?;
+        .current_dir(build_dir);
+
+    // Build scripts run in the crate directory, so paths relative to pike won't work there.
+    command
+        .env(PIKE_PROFILE_ENV, build_type.to_string())
+        .env(PIKE_PLUGINS_DIR_ENV, std::path::absolute(plugins_dir)?);
+    match data_dir {
+        Some(data_dir) => command.env(PIKE_DATA_DIR_ENV, std::path::absolute(data_dir)?),
+        None => command.env_remove(PIKE_DATA_DIR_ENV),
+    };
+
+    Ok(command)
+}
 
-    let stdout = child.stdout.take().expect("Failed to capture stdout");
-    let reader = BufReader::new(stdout);
-    for line in reader.lines() {
-        let line = line.unwrap_or_else(|e| format!("{e}"));
-        print!("{line}");
+/// Asks the user to confirm destructive operation, `items` it affects are listed under `action`.
+///
+/// Confirmation is not asked if stdin is not a terminal, so scripts are not blocked.
+pub fn confirm(action: &str, items: &[String]) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(true);
     }
 
-    if !child.wait().unwrap().success() {
-        let mut stderr = String::new();
-        child.stderr.unwrap().read_to_string(&mut stderr).unwrap();
-        bail!("build error: {stderr}");
+    println!("{action}:");
+    for item in items {
+        println!("  {item}");
     }
+    print!("Continue? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read confirmation")?;
+    Ok(is_confirmed(&answer))
+}
 
-    Ok(())
+fn is_confirmed(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Returns `kernel.core_pattern`, which defines where core dumps are written.
+pub fn core_pattern() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/core_pattern")
+        .ok()
+        .map(|pattern| pattern.trim().to_string())
+}
+
+/// Resolves the binary name through `PATH`, paths with separators are checked as is.
+pub fn find_executable(binary: &Path) -> Option<PathBuf> {
+    if binary.components().count() > 1 {
+        return binary.is_file().then(|| std::path::absolute(binary).ok())?;
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(binary))
+        .find(|path| path.is_file())
+}
+
+/// Renders the command, so it can be copied into the shell.
+#[must_use]
+pub fn shell_command(program: &str, args: &[String]) -> String {
+    let mut command = program.to_string();
+    for arg in args {
+        command.push(' ');
+        if arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-:+=,".contains(c))
+        {
+            command.push_str(arg);
+        } else {
+            write!(command, "'{}'", arg.replace('\'', r"'\''"))
+                .expect("writing to string never fails");
+        }
+    }
+    command
+}
+
+/// Finds the plugin project or workspace containing `dir`, i.e. the nearest directory
+/// with both `Cargo.toml` and `topology.toml`. Returns its path relative to `dir`.
+pub fn find_plugin_root(dir: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::from("./");
+    for ancestor in dir.ancestors() {
+        if ancestor.join("Cargo.toml").is_file() && ancestor.join("topology.toml").is_file() {
+            return Some(relative);
+        }
+        relative.push("..");
+    }
+    None
 }
 
 pub fn get_cluster_dir(plugin_path: &Path, data_dir: &Path) -> PathBuf {
     plugin_path.join(data_dir).join("cluster")
 }
 
+/// Directory of named clusters inside the data directory.
+pub const CLUSTERS_DIR: &str = "clusters";
+
+/// Data directory of the cluster started with `--name`, `<data_dir>/clusters/<name>`,
+/// or `data_dir` itself for the default cluster.
+pub fn cluster_data_dir(data_dir: PathBuf, name: Option<&str>) -> Result<PathBuf> {
+    let Some(name) = name else {
+        return Ok(data_dir);
+    };
+    // Names are used as directory names, so they can't contain separators.
+    let is_valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !is_valid {
+        bail!("invalid cluster name '{name}', use letters, digits, '.', '-' and '_'");
+    }
+    Ok(data_dir.join(CLUSTERS_DIR).join(name))
+}
+
+/// Names of clusters started with `--name` in the data directory.
+pub fn cluster_names(plugin_path: &Path, data_dir: &Path) -> Result<Vec<String>> {
+    let clusters_dir = plugin_path.join(data_dir).join(CLUSTERS_DIR);
+    let entries = match fs::read_dir(&clusters_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err).context(format!("failed to read {}", clusters_dir.display())),
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
 // Return socket path to the active instance
 pub fn get_active_socket_path(cluster_dir: &Path, instance_name: &str) -> Option<PathBuf> {
     let socket_path = cluster_dir.join(instance_name).join("admin.sock");
@@ -201,30 +481,106 @@ pub fn find_active_socket_path(cluster_dir: &Path) -> Result<Option<PathBuf>> {
     Ok(None)
 }
 
+/// Directory of any running instance of the cluster.
+pub fn find_active_instance_dir(cluster_dir: &Path) -> Result<PathBuf> {
+    let Some(socket_path) = find_active_socket_path(cluster_dir)? else {
+        return Err(Error::AdminSocketUnavailable(anyhow!(
+            "failed to find running instance in cluster data dir {}",
+            cluster_dir.display()
+        ))
+        .into());
+    };
+    socket_path
+        .parent()
+        .map(Path::to_path_buf)
+        .context("admin socket path has no parent directory")
+}
+
 /// Validates and unpacks plugin(s) from shipping archive into destination path,
 /// preserving archive structure. Does not create destination path itself.
-pub fn unpack_shipping_archive(src_path: &Path, dst_path: &Path) -> Result<()> {
+/// Unpacks the plugin archive into `dst_path` unless it is already unpacked there,
+/// returns `false` if unpacking is skipped.
+///
+/// Every unpacked version dir gets a `<version>.sha256` marker with the hash of the archive
+/// next to it. Versions of the plugin unpacked from other archives are removed, versions
+/// built by cargo have no marker and are kept.
+pub fn unpack_shipping_archive(src_path: &Path, dst_path: &Path) -> Result<bool> {
     is_plugin_archive(src_path).with_context(|| {
         let (from, to) = (src_path.to_string_lossy(), dst_path.to_string_lossy());
         format!("can not unpack shipping archive at {from} to {to}")
     })?;
 
-    let file = File::options()
-        .read(true)
-        .write(false)
-        .create(false)
-        .open(src_path)
-        .context("unable to open plugin archive")?;
-    let buf_reader = BufReader::new(file);
-    let decompressor = GzDecoder::new(buf_reader);
-
-    // by default - override existing, preserve mtime
-    let mut archive = Archive::new(decompressor);
-    archive.unpack(dst_path).with_context(|| {
+    let hash = integrity::sha256(src_path)?;
+    // plugin_name / plugin_version of every entry
+    let versions: BTreeSet<PathBuf> = archive::entry_paths(src_path)?
+        .iter()
+        .filter_map(|path| {
+            let mut components = path.components();
+            let plugin = components.next()?;
+            let version = components.next()?;
+            Some(Path::new(&plugin).join(version))
+        })
+        .collect();
+    let marker_path = |version: &Path| {
+        let mut name = version.as_os_str().to_os_string();
+        name.push(".sha256");
+        dst_path.join(name)
+    };
+
+    let is_unpacked = versions.iter().all(|version| {
+        dst_path.join(version).is_dir()
+            && fs::read_to_string(marker_path(version)).is_ok_and(|marker| marker.trim() == hash)
+    });
+    if is_unpacked {
+        return Ok(false);
+    }
+
+    let plugins: BTreeSet<&Path> = versions.iter().filter_map(|v| v.parent()).collect();
+    for plugin in plugins {
+        let Ok(entries) = fs::read_dir(dst_path.join(plugin)) else {
+            continue;
+        };
+        for entry in entries {
+            let marker = entry?.path();
+            let Some(version) = marker
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".sha256"))
+            else {
+                continue;
+            };
+            let version = plugin.join(version);
+            if versions.contains(&version) {
+                continue;
+            }
+            info!("Removing stale version {}", version.display());
+            remove_dir_if_exists(&dst_path.join(&version))?;
+            fs::remove_file(&marker).context(format!("failed to remove {}", marker.display()))?;
+        }
+    }
+    // Files removed from the archive must not stay in its unpacked versions.
+    for version in &versions {
+        remove_dir_if_exists(&dst_path.join(version))?;
+    }
+
+    archive::unpack(src_path, dst_path).with_context(|| {
         let (from, to) = (src_path.to_string_lossy(), dst_path.to_string_lossy());
         format!("failed to unpack shipping archive at {from} to {to}")
     })?;
-    Ok(())
+    for version in &versions {
+        let marker = marker_path(version);
+        fs::write(&marker, &hash).context(format!("failed to write {}", marker.display()))?;
+    }
+    Ok(true)
+}
+
+fn remove_dir_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(err).context(format!("failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Copies directory at `src_path` into `dst_dir`
@@ -292,3 +648,180 @@ pub fn run_query_in_picodata_admin(
 
     Ok(stdout)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_root_is_found_in_parent_directories() {
+        let workspace = temp_workspace::TempWorkspace::new("plugin-root").unwrap();
+        let root = workspace.path();
+        let member_src = root.join("member/src");
+        fs::create_dir_all(&member_src).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\n").unwrap();
+        fs::write(root.join("topology.toml"), "").unwrap();
+        // Members of the workspace have no topology of their own.
+        fs::write(root.join("member/Cargo.toml"), "[package]\n").unwrap();
+
+        assert_eq!(find_plugin_root(root).unwrap(), Path::new("./"));
+        assert_eq!(find_plugin_root(&member_src).unwrap(), Path::new("./../.."));
+        assert_eq!(
+            fs::canonicalize(member_src.join(find_plugin_root(&member_src).unwrap())).unwrap(),
+            fs::canonicalize(root).unwrap()
+        );
+    }
+
+    #[test]
+    fn named_clusters_are_kept_in_data_dir() {
+        assert_eq!(
+            cluster_data_dir(PathBuf::from("./tmp"), None).unwrap(),
+            Path::new("./tmp")
+        );
+        assert_eq!(
+            cluster_data_dir(PathBuf::from("./tmp"), Some("staging")).unwrap(),
+            Path::new("./tmp/clusters/staging")
+        );
+        for name in ["", "..", "a/b", ".hidden"] {
+            assert!(cluster_data_dir(PathBuf::from("./tmp"), Some(name)).is_err());
+        }
+
+        let workspace = temp_workspace::TempWorkspace::new("cluster-names").unwrap();
+        assert!(cluster_names(workspace.path(), Path::new("tmp"))
+            .unwrap()
+            .is_empty());
+        for name in ["staging", "dev"] {
+            fs::create_dir_all(workspace.join("tmp/clusters").join(name)).unwrap();
+        }
+        assert_eq!(
+            cluster_names(workspace.path(), Path::new("tmp")).unwrap(),
+            ["dev", "staging"]
+        );
+    }
+
+    #[test]
+    fn build_scripts_get_pike_layout() {
+        let command = cargo_build_command(
+            BuildType::Release,
+            Path::new("target"),
+            Path::new("/plugin"),
+            Path::new("/plugin/target/release"),
+            Some(Path::new("/plugin/tmp")),
+            &CargoArgs::default(),
+        )
+        .unwrap();
+        let envs: Vec<(String, Option<String>)> = command
+            .get_envs()
+            .map(|(k, v)| {
+                (
+                    k.to_string_lossy().into_owned(),
+                    v.map(|v| v.to_string_lossy().into_owned()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            envs,
+            [
+                ("PIKE_DATA_DIR".to_string(), Some("/plugin/tmp".to_string())),
+                (
+                    "PIKE_PLUGINS_DIR".to_string(),
+                    Some("/plugin/target/release".to_string())
+                ),
+                ("PIKE_PROFILE".to_string(), Some("release".to_string())),
+            ]
+        );
+
+        let command = cargo_build_command(
+            BuildType::Debug,
+            Path::new("target"),
+            Path::new("/plugin"),
+            Path::new("/plugin/target/debug"),
+            None,
+            &CargoArgs::default(),
+        )
+        .unwrap();
+        assert!(command
+            .get_envs()
+            .any(|(k, v)| k == PIKE_DATA_DIR_ENV && v.is_none()));
+    }
+
+    #[test]
+    fn cargo_args_are_passed_to_build() {
+        let args = |cargo_args: &CargoArgs| {
+            cargo_build_command(
+                BuildType::Release,
+                Path::new("target"),
+                Path::new("/plugin"),
+                Path::new("/plugin/target/release"),
+                None,
+                cargo_args,
+            )
+            .unwrap()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            args(&CargoArgs::default()),
+            ["build", "--release", "--target-dir", "target"]
+        );
+        assert_eq!(
+            args(&CargoArgs {
+                features: vec!["metrics".to_string(), "tls".to_string()],
+                no_default_features: true,
+                locked: true,
+                offline: true,
+                jobs: Some(4),
+                quiet: true,
+            }),
+            [
+                "build",
+                "--release",
+                "--features",
+                "metrics,tls",
+                "--no-default-features",
+                "--locked",
+                "--offline",
+                "--jobs",
+                "4",
+                "--target-dir",
+                "target"
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_build_keeps_cargo_output() {
+        let workspace = temp_workspace::TempWorkspace::new("cargo-build-output").unwrap();
+        fs::write(workspace.join("Cargo.toml"), "[package]\n").unwrap();
+        let cargo_args = CargoArgs {
+            quiet: true,
+            offline: true,
+            ..CargoArgs::default()
+        };
+        let err = cargo_build(
+            BuildType::Debug,
+            Path::new("target"),
+            workspace.path(),
+            &cargo_args,
+        )
+        .unwrap_err();
+
+        let err = Error::from(err);
+        assert!(matches!(err, Error::BuildError(_)));
+        assert!(err
+            .build_output()
+            .unwrap()
+            .contains("failed to parse manifest"));
+    }
+
+    #[test]
+    fn only_explicit_yes_confirms() {
+        assert!(is_confirmed("y\n"));
+        assert!(is_confirmed(" YES \n"));
+        assert!(!is_confirmed("\n"));
+        assert!(!is_confirmed("n\n"));
+        assert!(!is_confirmed("yep\n"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Pid files of instances, written by `run` and read by `stop`.
+//!
+//! Pid files and symlinks of instances outlive picodata when it crashes or the
+//! machine reboots, and the pid may be reused by another process meanwhile, so
+//! the pid is trusted only while it belongs to a running picodata process.
+
+use crate::commands::lib::get_active_socket_path;
+use anyhow::{Context, Result};
+use log::info;
+use nix::unistd::Pid;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+pub const PID_FILE_NAME: &str = "pid";
+
+/// Reads the pid from the first line of the pid file.
+pub fn read(path: &Path) -> Result<Pid> {
+    let file = fs::File::open(path)?;
+
+    let mut lines = io::BufReader::new(file).lines();
+    let pid_line = lines.next().context("PID file is empty")??;
+
+    let pid = pid_line
+        .trim()
+        .parse::<i32>()
+        .ok()
+        .filter(|pid| *pid > 0)
+        .context(format!("failed to parse PID from file {}", path.display()))?;
+
+    Ok(Pid::from_raw(pid))
+}
+
+/// Process is running and it is picodata, possibly under a debugger or a profiler.
+#[cfg(target_os = "linux")]
+pub fn is_picodata(pid: Pid) -> bool {
+    use procfs::process::{ProcState, Process};
+
+    let Ok(process) = Process::new(pid.as_raw()) else {
+        return false;
+    };
+    if process
+        .stat()
+        .and_then(|stat| stat.state())
+        .map_or(true, |state| state == ProcState::Zombie)
+    {
+        return false;
+    }
+    // Command line of a process of another user may be unreadable, it is trusted then.
+    process
+        .cmdline()
+        .map_or(true, |args| args.iter().any(|arg| mentions_picodata(arg)))
+}
+
+/// Process is running and it is picodata, possibly under a debugger or a profiler.
+#[cfg(not(target_os = "linux"))]
+pub fn is_picodata(pid: Pid) -> bool {
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-o", "command=", "-p", &pid.to_string()])
+        .output()
+    else {
+        return false;
+    };
+    output.status.success()
+        && String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .any(mentions_picodata)
+}
+
+fn mentions_picodata(arg: &str) -> bool {
+    Path::new(arg)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("picodata"))
+}
+
+/// Removes the pid file and the admin socket of the instance which is not running,
+/// returns `false` if the instance is running.
+pub fn remove_if_stale(cluster_dir: &Path, instance_dir: &Path) -> Result<bool> {
+    let Some(name) = instance_dir.file_name().and_then(|name| name.to_str()) else {
+        return Ok(false);
+    };
+    if get_active_socket_path(cluster_dir, name).is_some() {
+        return Ok(false);
+    }
+    let pid_file_path = instance_dir.join(PID_FILE_NAME);
+    if pid_file_path.exists() {
+        match read(&pid_file_path) {
+            // The instance is starting or hangs, it is not ours to clean.
+            Ok(pid) if is_picodata(pid) => return Ok(false),
+            Ok(pid) => {
+                info!("removing stale pid file of instance '{name}', pid {pid} is not a running picodata");
+            }
+            Err(err) => info!("removing stale pid file of instance '{name}': {err:#}"),
+        }
+        remove_file(&pid_file_path)?;
+    }
+    remove_file(&instance_dir.join("admin.sock"))?;
+    Ok(true)
+}
+
+/// Cleans up after instances which have crashed or have been killed with the machine:
+/// pid files and admin sockets of instances which are not running and symlinks to
+/// missing instance directories. Returns names of the cleaned up instances.
+pub fn remove_stale(cluster_dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(cluster_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err).context(format!("failed to read {}", cluster_dir.display())),
+    };
+    let mut dangling_links = vec![];
+    let mut cleaned = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_symlink() {
+            if !path.exists() {
+                dangling_links.push(path);
+            }
+            continue;
+        }
+        let had_pid_file = path.join(PID_FILE_NAME).exists();
+        if path.is_dir() && remove_if_stale(cluster_dir, &path)? && had_pid_file {
+            cleaned.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    for link in dangling_links {
+        info!("removing dangling symlink {}", link.display());
+        remove_file(&link)?;
+    }
+    cleaned.sort();
+    Ok(cleaned)
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(err).context(format!("failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::os::unix::fs::symlink;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    fn stale_pid_files_are_removed() {
+        let workspace = TempWorkspace::new("pid-file").unwrap();
+        let cluster_dir = workspace.join("cluster");
+
+        // Pid of an exited child is not reused right away.
+        let mut exited = Command::new("true").spawn().unwrap();
+        let dead_pid = exited.id();
+        exited.wait().unwrap();
+        let mut picodata = Command::new("sleep")
+            .arg0("picodata")
+            .arg("60")
+            .spawn()
+            .unwrap();
+        let mut other = Command::new("sleep").arg("60").spawn().unwrap();
+        let pid = |child: &std::process::Child| Pid::from_raw(child.id().try_into().unwrap());
+        assert!(is_picodata(pid(&picodata)));
+        assert!(!is_picodata(pid(&other)));
+        assert!(!is_picodata(Pid::from_raw(dead_pid.try_into().unwrap())));
+
+        let pids = [("i1", dead_pid), ("i2", picodata.id()), ("i3", other.id())];
+        for (name, pid) in pids {
+            fs::create_dir_all(cluster_dir.join(name)).unwrap();
+            fs::write(
+                cluster_dir.join(name).join(PID_FILE_NAME),
+                format!("{pid}\n"),
+            )
+            .unwrap();
+        }
+        fs::write(cluster_dir.join("i1/admin.sock"), "").unwrap();
+        // Instance which has been created but not started yet.
+        fs::create_dir_all(cluster_dir.join("i4")).unwrap();
+        symlink("i2", cluster_dir.join("default_1_1")).unwrap();
+        symlink("i5", cluster_dir.join("default_2_1")).unwrap();
+        assert_eq!(read(&cluster_dir.join("i2/pid")).unwrap(), pid(&picodata));
+
+        assert_eq!(remove_stale(&cluster_dir).unwrap(), ["i1", "i3"]);
+        assert!(!cluster_dir.join("i1/pid").exists());
+        assert!(!cluster_dir.join("i1/admin.sock").exists());
+        assert!(cluster_dir.join("i2/pid").exists());
+        assert!(!cluster_dir.join("i3/pid").exists());
+        assert!(cluster_dir.join("i4").is_dir());
+        assert!(cluster_dir.join("default_1_1").exists());
+        assert!(fs::symlink_metadata(cluster_dir.join("default_2_1")).is_err());
+
+        picodata.kill().unwrap();
+        other.kill().unwrap();
+        picodata.wait().unwrap();
+        other.wait().unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Settings of pike for the project in `pike.toml` next to `topology.toml`.
+
+use crate::commands::lib::hooks::Hooks;
+use crate::commands::lib::repository::{RegistryConfig, RepositoryConfig};
+use crate::commands::logs::LogsConfig;
+use crate::commands::picodata::PicodataConfig;
+use crate::commands::plugin::audit::AuditConfig;
+use anyhow::{bail, Context, Result};
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, Command};
+use log::warn;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+pub const PIKE_CONFIG_FILE_NAME: &str = "pike.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PikeConfig {
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    #[serde(default)]
+    pub publish: RepositoryConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub picodata: PicodataConfig,
+    #[serde(default)]
+    pub defaults: Defaults,
+}
+
+/// `[defaults]` section of `pike.toml`, values of command line flags by their
+/// long names, e.g. `data-dir = "./data"`. Flags passed explicitly take precedence.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Defaults(BTreeMap<String, toml::Value>);
+
+impl Defaults {
+    /// Sets defaults of flags of the command and all its subcommands. Boolean
+    /// flags enabled here can be disabled with `--flag=false`.
+    pub fn apply(&self, command: Command) -> Result<Command> {
+        let mut values = BTreeMap::new();
+        for (flag, value) in &self.0 {
+            if flag == "plugin-path" {
+                bail!("plugin-path can't be set in [defaults] of {PIKE_CONFIG_FILE_NAME}, the file is read from the plugin path");
+            }
+            values.insert(flag.as_str(), flag_values(flag, value)?);
+        }
+
+        let mut used = BTreeSet::new();
+        let command = apply_defaults(command, &values, &mut used);
+        for flag in values.keys().filter(|flag| !used.contains(*flag)) {
+            warn!("Unknown field in {PIKE_CONFIG_FILE_NAME}: defaults.{flag}");
+        }
+        Ok(command)
+    }
+}
+
+/// Value of the flag as it would be passed on the command line, arrays give
+/// several values.
+fn flag_values(flag: &str, value: &toml::Value) -> Result<Vec<String>> {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    };
+    let values = match value {
+        toml::Value::Array(values) => values.iter().map(scalar).collect(),
+        value => scalar(value).map(|value| vec![value]),
+    };
+    values.with_context(|| {
+        format!("invalid value of {flag} in [defaults] of {PIKE_CONFIG_FILE_NAME}, expected a string, number, boolean or array of them")
+    })
+}
+
+fn apply_defaults<'a>(
+    mut command: Command,
+    values: &BTreeMap<&'a str, Vec<String>>,
+    used: &mut BTreeSet<&'a str>,
+) -> Command {
+    let args: Vec<_> = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let (flag, values) = values.get_key_value(arg.get_long()?)?;
+            Some((arg.get_id().clone(), *flag, values))
+        })
+        .collect();
+    for (id, flag, values) in args {
+        used.insert(flag);
+        command = command.mut_arg(id, |arg| {
+            let arg = if matches!(arg.get_action(), ArgAction::SetTrue) {
+                arg.action(ArgAction::Set)
+                    .value_parser(BoolishValueParser::new())
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("true")
+            } else {
+                arg
+            };
+            arg.default_values(values.iter().cloned())
+        });
+    }
+
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command =
+            command.mut_subcommand(name, |subcommand| apply_defaults(subcommand, values, used));
+    }
+    command
+}
+
+impl PikeConfig {
+    /// Reads `pike.toml` of the plugin, default settings are used if there is no file.
+    pub fn load(plugin_path: &Path) -> Result<Self> {
+        let path = plugin_path.join(PIKE_CONFIG_FILE_NAME);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).context(format!("failed to read {}", path.display())),
+        };
+
+        let deserializer = toml::de::Deserializer::parse(&content)
+            .context(format!("failed to parse {}", path.display()))?;
+        let mut config: Self = serde_ignored::deserialize(deserializer, |field| {
+            warn!("Unknown field in {PIKE_CONFIG_FILE_NAME}: {field}");
+        })
+        .context(format!("failed to parse {}", path.display()))?;
+        config.hooks.work_dir = plugin_path.to_path_buf();
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgMatches};
+
+    fn command() -> Command {
+        Command::new("pike").subcommand(
+            Command::new("run")
+                .arg(Arg::new("data_dir").long("data-dir").default_value("./tmp"))
+                .arg(
+                    Arg::new("base_http_port")
+                        .long("base-http-port")
+                        .value_parser(clap::value_parser!(u16))
+                        .default_value("8000"),
+                )
+                .arg(Arg::new("daemon").long("daemon").action(ArgAction::SetTrue)),
+        )
+    }
+
+    fn run_matches(defaults: &str, args: &[&str]) -> ArgMatches {
+        let defaults: Defaults = toml::from_str(defaults).unwrap();
+        let command = defaults.apply(command()).unwrap();
+        let matches = command
+            .try_get_matches_from(["pike", "run"].iter().chain(args))
+            .unwrap();
+        matches.subcommand_matches("run").unwrap().clone()
+    }
+
+    #[test]
+    fn defaults_are_overridden_by_flags() {
+        let defaults = "data-dir = \"./data\"\nbase-http-port = 9000\ndaemon = true";
+
+        let matches = run_matches(defaults, &[]);
+        assert_eq!(matches.get_one::<String>("data_dir").unwrap(), "./data");
+        assert_eq!(matches.get_one::<u16>("base_http_port"), Some(&9000));
+        assert_eq!(matches.get_one::<bool>("daemon"), Some(&true));
+
+        let matches = run_matches(
+            defaults,
+            &[
+                "--data-dir",
+                "./other",
+                "--base-http-port=9100",
+                "--daemon=false",
+            ],
+        );
+        assert_eq!(matches.get_one::<String>("data_dir").unwrap(), "./other");
+        assert_eq!(matches.get_one::<u16>("base_http_port"), Some(&9100));
+        assert_eq!(matches.get_one::<bool>("daemon"), Some(&false));
+
+        let matches = run_matches("", &["--daemon"]);
+        assert_eq!(matches.get_one::<String>("data_dir").unwrap(), "./tmp");
+        assert_eq!(matches.get_one::<bool>("daemon"), Some(&true));
+
+        let defaults: Defaults = toml::from_str("plugin-path = \"../plugin\"").unwrap();
+        assert!(defaults.apply(command()).is_err());
+    }
+}
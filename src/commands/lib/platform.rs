@@ -0,0 +1,251 @@
+//! Detection of the OS suffix in default names of plugin archives, e.g. `ubuntu_24.04`.
+//!
+//! The suffix is taken from the first source which knows it: explicit value,
+//! `PIKE_OS_SUFFIX` variable, then detectors of the current OS, `unknown` otherwise.
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::env;
+use std::process::Command;
+
+/// Variable which overrides detected OS suffix.
+pub const OS_SUFFIX_ENV: &str = "PIKE_OS_SUFFIX";
+
+/// Suffix used when no detector knows the OS.
+const UNKNOWN: &str = "unknown";
+
+/// Distributions without versions which get `rolling` suffix instead of `unknown`.
+const ROLLING_DISTROS: &[&str] = &[
+    "arch",
+    "cachyos",
+    "gentoo",
+    "void",
+    "opensuse-tumbleweed",
+    "artix",
+    "manjaro",
+    "endeavouros",
+    "garuda",
+    "kaos",
+];
+
+/// Source of OS information, returns `None` if it doesn't know the OS.
+pub type Detector = fn() -> Result<Option<String>>;
+
+/// Detectors of the current OS in the order they are tried.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+const DETECTORS: &[(&str, Detector)] = &[
+    ("/etc/os-release", detect_from_os_release),
+    ("lsb_release", detect_from_lsb_release),
+];
+
+#[cfg(target_os = "macos")]
+const DETECTORS: &[(&str, Detector)] = &[("sw_vers", detect_from_sw_vers)];
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+const DETECTORS: &[(&str, Detector)] = &[];
+
+/// OS suffix for archive names, `explicit` value wins over `PIKE_OS_SUFFIX` and detection.
+pub fn os_suffix(explicit: Option<&str>) -> Result<String> {
+    if let Some(suffix) = explicit {
+        return validate_os_suffix(suffix).context("invalid --os-suffix");
+    }
+    if let Some(suffix) = env::var_os(OS_SUFFIX_ENV) {
+        return validate_os_suffix(&suffix.to_string_lossy())
+            .context(format!("invalid {OS_SUFFIX_ENV}"));
+    }
+    Ok(detect_os_suffix(DETECTORS))
+}
+
+/// Returns suffix of the first detector which knows the OS.
+pub fn detect_os_suffix(detectors: &[(&str, Detector)]) -> String {
+    for (name, detector) in detectors {
+        match detector() {
+            Ok(Some(suffix)) => return suffix,
+            Ok(None) => debug!("{name} doesn't know the OS"),
+            Err(err) => debug!("failed to detect OS with {name}: {err:#}"),
+        }
+    }
+    warn!(
+        "failed to detect OS, archive name gets '{UNKNOWN}' suffix, \
+        set it with --os-suffix or {OS_SUFFIX_ENV}"
+    );
+    UNKNOWN.to_string()
+}
+
+fn validate_os_suffix(suffix: &str) -> Result<String> {
+    if suffix.is_empty() {
+        bail!("OS suffix is empty");
+    }
+    if let Some(c) = suffix
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || ".-_".contains(*c)))
+    {
+        bail!(
+            "OS suffix '{suffix}' contains '{c}', \
+            allowed characters are a-z, 0-9, '.', '-' and '_'"
+        );
+    }
+    Ok(suffix.to_string())
+}
+
+fn rolling_or_unknown(id: &str) -> &'static str {
+    if ROLLING_DISTROS.contains(&id) {
+        "rolling"
+    } else {
+        UNKNOWN
+    }
+}
+
+fn detect_from_os_release() -> Result<Option<String>> {
+    const OS_RELEASE: &str = "/etc/os-release";
+    let content = std::fs::read_to_string(OS_RELEASE)
+        .with_context(|| format!("failed to read {OS_RELEASE}"))?;
+    Ok(parse_os_release(&content))
+}
+
+/// Parses `ID` and `VERSION_ID` or `VERSION_CODENAME` of os-release file.
+pub fn parse_os_release(content: &str) -> Option<String> {
+    let mut id: Option<String> = None;
+    let mut version_id: Option<String> = None;
+    let mut version_codename: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((k, v)) = line.split_once('=') {
+            let key = k.trim();
+            let val = v.trim().trim_matches('"').trim_matches('\'');
+            if key.eq_ignore_ascii_case("ID") {
+                id = Some(val.to_ascii_lowercase());
+            } else if key.eq_ignore_ascii_case("VERSION_ID") {
+                version_id = Some(val.replace(' ', "_").to_ascii_lowercase());
+            } else if key.eq_ignore_ascii_case("VERSION_CODENAME") {
+                version_codename = Some(val.to_ascii_lowercase());
+            }
+        }
+    }
+
+    let id = id.filter(|id| !id.is_empty())?;
+    let variant = version_id
+        .filter(|v| !v.is_empty())
+        .or(version_codename.filter(|c| !c.is_empty()))
+        .unwrap_or_else(|| rolling_or_unknown(&id).to_string());
+    Some(format!("{id}_{variant}"))
+}
+
+fn detect_from_lsb_release() -> Result<Option<String>> {
+    let output = Command::new("lsb_release")
+        .args(["--short", "--id", "--release"])
+        .output()
+        .context("failed to run lsb_release")?;
+    if !output.status.success() {
+        bail!("lsb_release exited with {}", output.status);
+    }
+    Ok(parse_lsb_release(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses output of `lsb_release --short --id --release`, id and release on separate lines.
+pub fn parse_lsb_release(output: &str) -> Option<String> {
+    let mut lines = output.lines().map(str::trim);
+    let id = lines
+        .next()
+        .filter(|id| !id.is_empty())?
+        .to_ascii_lowercase();
+    let release = lines
+        .next()
+        .map(|r| r.replace(' ', "_").to_ascii_lowercase())
+        .filter(|r| !r.is_empty() && r != "n/a")
+        .unwrap_or_else(|| rolling_or_unknown(&id).to_string());
+    Some(format!("{id}_{release}"))
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn detect_from_sw_vers() -> Result<Option<String>> {
+    let output = Command::new("sw_vers")
+        .output()
+        .context("failed to run sw_vers")?;
+    if !output.status.success() {
+        bail!("sw_vers exited with {}", output.status);
+    }
+    Ok(parse_sw_vers(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses `ProductName` and `ProductVersion` of `sw_vers` output.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn parse_sw_vers(output: &str) -> Option<String> {
+    let mut product_name: Option<String> = None;
+    let mut product_version: Option<String> = None;
+
+    for line in output.lines() {
+        if let Some((k, v)) = line.split_once(':') {
+            let key = k.trim();
+            let val = v.trim();
+            if key.eq_ignore_ascii_case("ProductName") {
+                product_name = Some(val.to_ascii_lowercase().replace(' ', ""));
+            } else if key.eq_ignore_ascii_case("ProductVersion") {
+                product_version = Some(val.to_ascii_lowercase());
+            }
+        }
+    }
+
+    let id = product_name.unwrap_or_else(|| "macos".into());
+    Some(format!("{id}_{}", product_version?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_release_is_parsed() {
+        let ubuntu = "NAME=\"Ubuntu\"\nID=ubuntu\nVERSION_ID=\"24.04\"\nVERSION_CODENAME=noble\n";
+        assert_eq!(parse_os_release(ubuntu).unwrap(), "ubuntu_24.04");
+        let debian_testing = "ID=debian\nVERSION_CODENAME=trixie\n";
+        assert_eq!(parse_os_release(debian_testing).unwrap(), "debian_trixie");
+        assert_eq!(parse_os_release("ID=arch\n").unwrap(), "arch_rolling");
+        assert_eq!(
+            parse_os_release("ID='nixos'\nVERSION_ID=\"\"\n").unwrap(),
+            "nixos_unknown"
+        );
+        assert_eq!(parse_os_release("NAME=Linux\n"), None);
+    }
+
+    #[test]
+    fn lsb_release_and_sw_vers_are_parsed() {
+        assert_eq!(
+            parse_lsb_release("Ubuntu\n22.04\n").unwrap(),
+            "ubuntu_22.04"
+        );
+        assert_eq!(
+            parse_lsb_release("Gentoo\nn/a\n").unwrap(),
+            "gentoo_rolling"
+        );
+        assert_eq!(parse_lsb_release(""), None);
+
+        let sw_vers = "ProductName:\t\tmacOS\nProductVersion:\t\t14.5\nBuildVersion:\t\t23F79\n";
+        assert_eq!(parse_sw_vers(sw_vers).unwrap(), "macos_14.5");
+        assert_eq!(parse_sw_vers("ProductName: macOS\n"), None);
+    }
+
+    #[test]
+    fn detectors_are_tried_in_order() {
+        let detectors: &[(&str, Detector)] = &[
+            ("failing", || bail!("no such file")),
+            ("empty", || Ok(None)),
+            ("fedora", || Ok(Some("fedora_40".to_string()))),
+            ("ubuntu", || Ok(Some("ubuntu_24.04".to_string()))),
+        ];
+        assert_eq!(detect_os_suffix(detectors), "fedora_40");
+        assert_eq!(detect_os_suffix(&detectors[..2]), "unknown");
+    }
+
+    #[test]
+    fn explicit_suffix_is_validated() {
+        assert_eq!(os_suffix(Some("alt_p10")).unwrap(), "alt_p10");
+        assert!(os_suffix(Some("")).is_err());
+        let err = os_suffix(Some("../x")).unwrap_err();
+        assert!(format!("{err:#}").contains("contains '/'"), "{err:#}");
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use rand::RngExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exponential backoff with jitter used between polling attempts.
+///
+/// Delay starts from `initial`, is multiplied by `multiplier` on each attempt
+/// and never exceeds `max`. Each delay is randomly shifted by up to `jitter`
+/// fraction of itself, so concurrent pollers do not hit the same resource in lockstep.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: u32,
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(50), Duration::from_secs(1))
+    }
+}
+
+impl Backoff {
+    /// Creates backoff doubling the delay on each attempt with 20% jitter.
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            multiplier: 2,
+            jitter: 0.2,
+        }
+    }
+
+    /// Returns delay before the next attempt without jitter applied.
+    fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(attempt);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+
+    /// Returns delay before the next attempt, `attempt` starts from zero.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt);
+        if self.jitter <= 0.0 {
+            return base;
+        }
+
+        let jitter = self.jitter.min(1.0);
+        base.mul_f64(rand::rng().random_range((1.0 - jitter)..=(1.0 + jitter)))
+    }
+}
+
+/// How long and how often an operation is retried, e.g. readiness checks of instances.
+///
+/// Attempts are made until `timeout` is reached or `max_attempts` are made,
+/// delays between them grow from 50ms up to `interval`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total time of waiting, zero makes a single attempt.
+    pub timeout: Duration,
+    /// Longest delay between attempts.
+    pub interval: Duration,
+    /// Number of attempts after which waiting stops before `timeout`, unlimited if `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl RetryPolicy {
+    /// Creates policy with unlimited number of attempts.
+    #[must_use]
+    pub const fn new(timeout: Duration, interval: Duration) -> Self {
+        Self {
+            timeout,
+            interval,
+            max_attempts: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    fn backoff(&self) -> Backoff {
+        Backoff::new(self.interval.min(Duration::from_millis(50)), self.interval)
+    }
+
+    /// Calls `check` until it returns `Some` value or the policy is exhausted,
+    /// see [`poll_until`].
+    pub fn poll<T, F>(&self, check: F) -> Result<Option<T>>
+    where
+        F: FnMut() -> Result<Option<T>>,
+    {
+        poll(
+            Instant::now() + self.timeout,
+            &self.backoff(),
+            self.max_attempts,
+            check,
+        )
+    }
+}
+
+/// Calls `check` until it returns `Some` value or `deadline` is reached,
+/// sleeping between attempts according to `backoff`.
+///
+/// Errors returned by `check` are propagated immediately.
+/// Returns `None` if the deadline was reached before the condition has been met.
+pub fn poll_until<T, F>(deadline: Instant, backoff: &Backoff, check: F) -> Result<Option<T>>
+where
+    F: FnMut() -> Result<Option<T>>,
+{
+    poll(deadline, backoff, None, check)
+}
+
+fn poll<T, F>(
+    deadline: Instant,
+    backoff: &Backoff,
+    max_attempts: Option<u32>,
+    mut check: F,
+) -> Result<Option<T>>
+where
+    F: FnMut() -> Result<Option<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        if let Some(value) = check()? {
+            return Ok(Some(value));
+        }
+
+        let now = Instant::now();
+        if now >= deadline || max_attempts.is_some_and(|max| attempt + 1 >= max) {
+            return Ok(None);
+        }
+
+        thread::sleep(backoff.delay(attempt).min(deadline - now));
+        attempt = attempt.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+
+    #[test]
+    fn backoff_grows_exponentially_and_is_capped() {
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..Backoff::new(Duration::from_millis(10), Duration::from_millis(100))
+        };
+
+        assert_eq!(backoff.delay(0), Duration::from_millis(10));
+        assert_eq!(backoff.delay(1), Duration::from_millis(20));
+        assert_eq!(backoff.delay(3), Duration::from_millis(80));
+        assert_eq!(backoff.delay(4), Duration::from_millis(100));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_within_bounds() {
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(100));
+        for attempt in 0..100 {
+            let delay = backoff.delay(attempt);
+            assert!(delay >= Duration::from_millis(80), "{delay:?}");
+            assert!(delay <= Duration::from_millis(120), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn poll_until_returns_value_when_ready() {
+        let mut attempts = 0;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(1));
+
+        let value = poll_until(deadline, &backoff, || {
+            attempts += 1;
+            Ok((attempts == 3).then_some(attempts))
+        })
+        .unwrap();
+
+        assert_eq!(value, Some(3));
+    }
+
+    #[test]
+    fn poll_until_returns_none_on_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let value: Option<()> = poll_until(deadline, &Backoff::default(), || Ok(None)).unwrap();
+        assert!(value.is_none());
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn poll_until_propagates_errors() {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let res: Result<Option<()>> = poll_until(deadline, &Backoff::default(), || bail!("boom"));
+        assert_eq!(format!("{}", res.unwrap_err()), "boom");
+    }
+
+    #[test]
+    fn retry_policy_stops_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            ..RetryPolicy::new(Duration::from_secs(5), Duration::from_millis(1))
+        };
+        let mut attempts = 0;
+        let value: Option<()> = policy
+            .poll(|| {
+                attempts += 1;
+                Ok(None)
+            })
+            .unwrap();
+        assert!(value.is_none());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let policy = RetryPolicy::new(Duration::ZERO, Duration::from_secs(1));
+        let value: Option<()> = policy
+            .poll(|| {
+                attempts += 1;
+                Ok(None)
+            })
+            .unwrap();
+        assert!(value.is_none());
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,312 @@
+//! Child processes managed by pike: picodata instances, cargo builds, etc.
+//!
+//! Output of the child is read line by line in background threads,
+//! echoed to the terminal with an optional prefix and teed into a log file.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Settings of capturing stdout and stderr of the child.
+#[derive(Debug, Clone)]
+pub struct LogCapture {
+    prefix: String,
+    echo: bool,
+    echo_to_stderr: bool,
+    log_file: Option<PathBuf>,
+    keep_output: bool,
+}
+
+impl Default for LogCapture {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            echo: true,
+            echo_to_stderr: false,
+            log_file: None,
+            keep_output: false,
+        }
+    }
+}
+
+impl LogCapture {
+    /// Prefix printed before each echoed line, e.g. name of the instance.
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// File to write captured lines into, it is truncated on start.
+    #[must_use]
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Echo captured lines to stderr instead of stdout, e.g. when stdout is kept for JSON.
+    #[must_use]
+    pub fn echo_to_stderr(mut self, echo_to_stderr: bool) -> Self {
+        self.echo_to_stderr = echo_to_stderr;
+        self
+    }
+
+    /// Do not echo captured lines to the terminal.
+    #[must_use]
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.echo = !quiet;
+        self
+    }
+
+    /// Keep captured lines in memory, they are returned by [`ManagedProcess::output`].
+    #[must_use]
+    pub fn keep_output(mut self) -> Self {
+        self.keep_output = true;
+        self
+    }
+}
+
+/// Spawned child process which is killed on drop unless detached.
+#[derive(Debug)]
+pub struct ManagedProcess {
+    name: String,
+    child: Child,
+    log_threads: Vec<JoinHandle<()>>,
+    output: Arc<Mutex<Vec<String>>>,
+    kill_on_drop: bool,
+}
+
+impl ManagedProcess {
+    /// Spawns `command`, stdio of which is configured by the caller.
+    ///
+    /// Only piped stdout and stderr are captured by [`Self::capture_logs`].
+    pub fn spawn(name: impl Into<String>, command: &mut Command) -> Result<Self> {
+        let name = name.into();
+        let child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn process {name}"))?;
+
+        Ok(Self {
+            name,
+            child,
+            log_threads: vec![],
+            output: Arc::default(),
+            kill_on_drop: true,
+        })
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Keeps the process running after the handle is dropped.
+    pub fn detach(&mut self) {
+        self.kill_on_drop = false;
+    }
+
+    /// Starts reading piped stdout and stderr of the child in background threads.
+    pub fn capture_logs(&mut self, capture: &LogCapture) -> Result<()> {
+        let file = match &capture.log_file {
+            Some(path) => Some(Arc::new(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open log file {}", path.display()))?,
+            ))),
+            None => None,
+        };
+
+        let mut outputs: Vec<Box<dyn Read + Send>> = vec![];
+        if let Some(stdout) = self.child.stdout.take() {
+            outputs.push(Box::new(stdout));
+        }
+        if let Some(stderr) = self.child.stderr.take() {
+            outputs.push(Box::new(stderr));
+        }
+        if outputs.is_empty() {
+            bail!(
+                "neither stdout nor stderr of process {} is piped",
+                self.name
+            );
+        }
+
+        for output in outputs {
+            let prefix = capture.prefix.clone();
+            let echo = capture.echo;
+            let echo_to_stderr = capture.echo_to_stderr;
+            let file = file.clone();
+            let output_lines = capture.keep_output.then(|| self.output.clone());
+
+            let thread = thread::Builder::new()
+                .name(format!("log_catcher::{}", self.name))
+                .spawn(move || {
+                    for line in BufReader::new(output).lines() {
+                        let Ok(line) = line else {
+                            break;
+                        };
+                        if echo && echo_to_stderr {
+                            eprintln!("{prefix}{line}");
+                        } else if echo {
+                            println!("{prefix}{line}");
+                        }
+                        if let Some(file) = &file {
+                            writeln!(file.lock().unwrap(), "{line}")
+                                .expect("Failed to write line to log file");
+                        }
+                        if let Some(output_lines) = &output_lines {
+                            output_lines.lock().unwrap().push(line);
+                        }
+                    }
+                })?;
+            self.log_threads.push(thread);
+        }
+
+        Ok(())
+    }
+
+    /// Lines of stdout and stderr captured so far with [`LogCapture::keep_output`],
+    /// lines of the two streams are interleaved in the order they are read.
+    #[must_use]
+    pub fn output(&self) -> String {
+        self.output.lock().unwrap().join("\n")
+    }
+
+    /// Writes pid of the child into `path`, so it can be stopped by another pike process.
+    pub fn write_pid_file(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path)
+            .with_context(|| format!("failed to create pid file {}", path.display()))?;
+        writeln!(file, "{}", self.id())?;
+        Ok(())
+    }
+
+    pub fn kill(&mut self) -> Result<()> {
+        self.child
+            .kill()
+            .with_context(|| format!("failed to kill process {}", self.name))
+    }
+
+    /// Waits until captured output is fully read, i.e. the child has closed it.
+    pub fn join(&mut self) {
+        for thread in self.log_threads.drain(..) {
+            thread
+                .join()
+                .expect("Failed to join log thread of child process");
+        }
+    }
+
+    /// Returns exit status of the child if it has exited, doesn't block.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.child
+            .try_wait()
+            .with_context(|| format!("failed to check status of process {}", self.name))
+    }
+
+    /// Waits for the child to exit and its output to be fully read.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        let status = self
+            .child
+            .wait()
+            .with_context(|| format!("failed to wait for process {}", self.name))?;
+        self.join();
+        Ok(status)
+    }
+}
+
+impl Drop for ManagedProcess {
+    fn drop(&mut self) {
+        if !self.kill_on_drop {
+            return;
+        }
+
+        // Send kill to avoid infinite
+        // waiting of blocking processes.
+        let _ = self.child.kill();
+
+        self.child.wait().expect("Failed to wait for child process");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::fs;
+    use std::process::Stdio;
+
+    #[test]
+    fn captured_output_is_teed_into_log_file() {
+        let workspace = TempWorkspace::new("process-log").unwrap();
+        let log_file = workspace.join("log");
+        let mut process = ManagedProcess::spawn(
+            "sh",
+            Command::new("sh")
+                .args(["-c", "echo out; echo err >&2"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .unwrap();
+        process
+            .capture_logs(&LogCapture::default().quiet(true).log_file(&log_file))
+            .unwrap();
+
+        assert!(process.wait().unwrap().success());
+        let log = fs::read_to_string(&log_file).unwrap();
+        let mut lines: Vec<&str> = log.lines().collect();
+        lines.sort_unstable();
+        assert_eq!(lines, ["err", "out"]);
+    }
+
+    #[test]
+    fn kept_output_is_returned() {
+        let mut process = ManagedProcess::spawn(
+            "sh",
+            Command::new("sh")
+                .args(["-c", "echo out; sleep 0.1; echo err >&2"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .unwrap();
+        process
+            .capture_logs(&LogCapture::default().quiet(true).keep_output())
+            .unwrap();
+
+        assert!(process.wait().unwrap().success());
+        assert_eq!(process.output(), "out\nerr");
+    }
+
+    #[test]
+    fn capture_requires_piped_output() {
+        let mut process = ManagedProcess::spawn("true", &mut Command::new("true")).unwrap();
+        let err = process.capture_logs(&LogCapture::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "neither stdout nor stderr of process true is piped"
+        );
+    }
+
+    #[test]
+    fn killed_process_writes_pid_and_fails() {
+        let workspace = TempWorkspace::new("process-pid").unwrap();
+        let pid_file = workspace.join("pid");
+        let mut process = ManagedProcess::spawn("sleep", Command::new("sleep").arg("10")).unwrap();
+        process.write_pid_file(&pid_file).unwrap();
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        assert_eq!(pid.trim(), process.id().to_string());
+
+        process.kill().unwrap();
+        assert!(!process.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,301 @@
+//! Artifact repository of plugin archives: S3-compatible bucket, HTTP server
+//! accepting `PUT` or local directory.
+//!
+//! Archives are stored as `<name>/<version>/<archive>` next to their checksums
+//! and signatures, [`Index`] of all of them is kept in `index.json` in the root
+//! of the repository.
+
+use crate::commands::lib::s3::Bucket;
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `[publish]` section of `pike.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// Repository archives are published to, see [`Repository::parse`].
+    pub destination: Option<String>,
+    /// Endpoint of S3-compatible storage, e.g. `http://localhost:9000` of `MinIO`.
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+/// `[registry]` section of `pike.toml`, repository of plugins which are
+/// referenced as `registry = "<name>@<version>"` in the topology.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryConfig {
+    /// Root of the repository, the same as `destination` of `[publish]`.
+    pub url: Option<String>,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+impl RegistryConfig {
+    /// Registry configured in `[registry]` or, if its URL is not set,
+    /// the repository plugins are published to.
+    pub fn open(&self, publish: &RepositoryConfig) -> Result<Repository> {
+        if let Some(url) = &self.url {
+            return Repository::parse(url, self.endpoint.as_deref(), self.region.as_deref());
+        }
+        let Some(destination) = &publish.destination else {
+            bail!("registry is not configured, set url in [registry] of pike.toml");
+        };
+        Repository::parse(
+            destination,
+            publish.endpoint.as_deref(),
+            publish.region.as_deref(),
+        )
+    }
+}
+
+/// Location of the repository.
+#[derive(Debug)]
+pub enum Repository {
+    /// `s3://<bucket>/<prefix>`, credentials are read from `AWS_*` variables.
+    S3 { bucket: Bucket, prefix: String },
+    /// `http://` or `https://` URL of the root, files are uploaded by `PUT`.
+    /// `PIKE_PUBLISH_TOKEN` is sent as a bearer token, `PIKE_PUBLISH_USER`
+    /// and `PIKE_PUBLISH_PASSWORD` as basic credentials.
+    Http { url: String, agent: ureq::Agent },
+    /// Directory on the local file system.
+    Local(PathBuf),
+}
+
+impl Repository {
+    /// Repository at `location`, `endpoint` and `region` are used by S3 buckets only.
+    pub fn parse(location: &str, endpoint: Option<&str>, region: Option<&str>) -> Result<Self> {
+        if let Some(path) = location.strip_prefix("s3://") {
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+            if bucket.is_empty() {
+                bail!("bucket is not set in {location}");
+            }
+            return Ok(Self::S3 {
+                bucket: Bucket::new(bucket, endpoint, region)?,
+                prefix: prefix.trim_matches('/').to_string(),
+            });
+        }
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Ok(Self::Http {
+                url: location.trim_end_matches('/').to_string(),
+                agent: ureq::Agent::config_builder()
+                    .tls_config(
+                        ureq::tls::TlsConfig::builder()
+                            .provider(ureq::tls::TlsProvider::NativeTls)
+                            .build(),
+                    )
+                    .timeout_global(Some(HTTP_TIMEOUT))
+                    .http_status_as_error(false)
+                    .build()
+                    .into(),
+            });
+        }
+        Ok(Self::Local(PathBuf::from(
+            location.strip_prefix("file://").unwrap_or(location),
+        )))
+    }
+
+    /// Stores `body` under `key`, a `/` separated path relative to the root.
+    pub fn put(&self, key: &str, body: &[u8]) -> Result<()> {
+        match self {
+            Self::S3 { bucket, prefix } => bucket.put_object(&join_key(prefix, key), body),
+            Self::Http { url, agent } => {
+                let url = format!("{url}/{key}");
+                let mut request = agent.put(&url);
+                if let Some(authorization) = http_authorization() {
+                    request = request.header("Authorization", authorization);
+                }
+                let response = request
+                    .send(body)
+                    .context(format!("failed to upload {url}"))?;
+                if !response.status().is_success() {
+                    bail!("failed to upload {url}: {}", response.status());
+                }
+                Ok(())
+            }
+            Self::Local(root) => {
+                let path = root.join(key);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .context(format!("failed to create {}", parent.display()))?;
+                }
+                fs::write(&path, body).context(format!("failed to write {}", path.display()))
+            }
+        }
+    }
+
+    /// Content stored under `key`, `None` if there is no such file.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::S3 { bucket, prefix } => bucket.get_object(&join_key(prefix, key)),
+            Self::Http { url, agent } => {
+                let url = format!("{url}/{key}");
+                let mut request = agent.get(&url);
+                if let Some(authorization) = http_authorization() {
+                    request = request.header("Authorization", authorization);
+                }
+                let mut response = request
+                    .call()
+                    .context(format!("failed to download {url}"))?;
+                match response.status().as_u16() {
+                    404 => Ok(None),
+                    status if (200..300).contains(&status) => Ok(Some(
+                        response
+                            .body_mut()
+                            .with_config()
+                            .limit(u64::MAX)
+                            .read_to_vec()
+                            .context(format!("failed to download {url}"))?,
+                    )),
+                    status => bail!("failed to download {url}: {status}"),
+                }
+            }
+            Self::Local(root) => {
+                let path = root.join(key);
+                match fs::read(&path) {
+                    Ok(content) => Ok(Some(content)),
+                    Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                    Err(err) => Err(err).context(format!("failed to read {}", path.display())),
+                }
+            }
+        }
+    }
+
+    /// Index of the repository, empty if it is not created yet.
+    pub fn index(&self) -> Result<Index> {
+        let Some(content) = self.get(INDEX_FILE_NAME)? else {
+            return Ok(Index::default());
+        };
+        serde_json::from_slice(&content)
+            .context(format!("failed to parse {INDEX_FILE_NAME} of {self}"))
+    }
+
+    pub fn put_index(&self, index: &Index) -> Result<()> {
+        let content = serde_json::to_vec_pretty(index).context("failed to serialize index")?;
+        self.put(INDEX_FILE_NAME, &content)
+    }
+}
+
+impl Display for Repository {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::S3 { bucket, prefix } => write!(f, "s3://{}/{prefix}", bucket.name()),
+            Self::Http { url, .. } => f.write_str(url),
+            Self::Local(root) => write!(f, "{}", root.display()),
+        }
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}/{key}")
+    }
+}
+
+fn http_authorization() -> Option<String> {
+    if let Ok(token) = env::var("PIKE_PUBLISH_TOKEN") {
+        return Some(format!("Bearer {token}"));
+    }
+    let user = env::var("PIKE_PUBLISH_USER").ok()?;
+    let password = env::var("PIKE_PUBLISH_PASSWORD").unwrap_or_default();
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+    Some(format!("Basic {credentials}"))
+}
+
+/// Archive of a plugin version in the repository.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    /// OS suffix of the archive name, `None` for archives with custom names.
+    pub os_suffix: Option<String>,
+    /// Path of the archive relative to the root of the repository.
+    pub path: String,
+    pub sha256: String,
+}
+
+/// `index.json` of the repository.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    #[serde(default)]
+    pub plugins: Vec<IndexEntry>,
+}
+
+impl Index {
+    /// Adds the entry replacing the archive of the same plugin version and OS suffix.
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.plugins.retain(|existing| {
+            (&existing.name, &existing.version, &existing.os_suffix)
+                != (&entry.name, &entry.version, &entry.os_suffix)
+        });
+        self.plugins.push(entry);
+        self.plugins.sort_by(|a, b| {
+            (&a.name, &a.version, &a.os_suffix).cmp(&(&b.name, &b.version, &b.os_suffix))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn entry(name: &str, version: &str, os_suffix: &str, sha256: &str) -> IndexEntry {
+        IndexEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            os_suffix: Some(os_suffix.to_string()),
+            path: format!("{name}/{version}/{name}_{version}-{os_suffix}.tar.gz"),
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn destinations_are_parsed() {
+        assert!(matches!(
+            Repository::parse("https://artifacts.local/plugins/", None, None).unwrap(),
+            Repository::Http { url, .. } if url == "https://artifacts.local/plugins"
+        ));
+        assert!(matches!(
+            Repository::parse("file:///srv/plugins", None, None).unwrap(),
+            Repository::Local(root) if root == Path::new("/srv/plugins")
+        ));
+        assert!(matches!(
+            Repository::parse("releases", None, None).unwrap(),
+            Repository::Local(root) if root == Path::new("releases")
+        ));
+        assert!(Repository::parse("s3:///plugins", None, None).is_err());
+        assert_eq!(join_key("", INDEX_FILE_NAME), "index.json");
+        assert_eq!(join_key("plugins", INDEX_FILE_NAME), "plugins/index.json");
+    }
+
+    #[test]
+    fn index_entries_are_replaced() {
+        let mut index = Index::default();
+        index.insert(entry("weather", "0.2.0", "ubuntu_24.04", "a"));
+        index.insert(entry("weather", "0.1.0", "ubuntu_24.04", "b"));
+        index.insert(entry("weather", "0.1.0", "alt_10", "c"));
+        index.insert(entry("weather", "0.1.0", "ubuntu_24.04", "d"));
+
+        let published: Vec<_> = index
+            .plugins
+            .iter()
+            .map(|entry| (entry.version.as_str(), entry.sha256.as_str()))
+            .collect();
+        assert_eq!(published, [("0.1.0", "c"), ("0.1.0", "d"), ("0.2.0", "a")]);
+    }
+}
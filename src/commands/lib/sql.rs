@@ -0,0 +1,128 @@
+//! Quoting of values interpolated into SQL and Lua sent to picodata admin console.
+
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::fmt::Write;
+
+/// Quotes SQL identifier, e.g. name of plugin, service or tier.
+///
+/// Quoted identifiers keep their case, double quotes inside are doubled.
+#[must_use]
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Quotes SQL string literal, single quotes inside are doubled.
+#[must_use]
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Checks that plugin version can be put into SQL as is.
+///
+/// Version is not a literal in picodata SQL grammar, so it can't be quoted.
+pub fn plugin_version(version: &str) -> Result<&str> {
+    let is_valid = !version.is_empty()
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'));
+    if !is_valid {
+        bail!("invalid plugin version '{version}': only letters, digits, '.', '-' and '+' are allowed");
+    }
+    Ok(version)
+}
+
+/// Quotes Lua string literal, so it can be passed to `\lua` mode of admin console
+/// as a single line.
+#[must_use]
+pub fn quote_lua(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            // Lua has no \u escapes, control characters are written as decimal byte codes.
+            c if c.is_ascii_control() => {
+                write!(quoted, "\\{:03}", u32::from(c)).expect("writing to string never fails");
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Replaces password literals of `CREATE USER`/`ALTER USER` statements with `'***'`,
+/// so the statements can be logged.
+#[must_use]
+pub fn redact_passwords(query: &str) -> String {
+    let password = Regex::new(r"(?i)(\bPASSWORD\s+)'(?:[^']|'')*'").expect("valid regex");
+    password.replace_all(query, "${1}'***'").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_quoted_with_doubled_quotes() {
+        assert_eq!(quote_ident("main"), r#""main""#);
+        assert_eq!(quote_ident("Main"), r#""Main""#);
+        assert_eq!(quote_ident(r#"my "svc""#), r#""my ""svc""""#);
+        assert_eq!(quote_ident(""), r#""""#);
+    }
+
+    #[test]
+    fn literals_are_quoted_with_doubled_quotes() {
+        assert_eq!(quote_literal("value"), "'value'");
+        assert_eq!(quote_literal("it's"), "'it''s'");
+        assert_eq!(
+            quote_literal("'; DROP TABLE t; --"),
+            "'''; DROP TABLE t; --'"
+        );
+        assert_eq!(quote_literal(r#"{"key":"it's"}"#), r#"'{"key":"it''s"}'"#);
+    }
+
+    #[test]
+    fn plugin_version_rejects_unsafe_characters() {
+        assert_eq!(plugin_version("0.1.0").unwrap(), "0.1.0");
+        assert_eq!(
+            plugin_version("1.0.0-rc.1+build5").unwrap(),
+            "1.0.0-rc.1+build5"
+        );
+        assert!(plugin_version("").is_err());
+        assert!(plugin_version("0.1.0; DROP").is_err());
+        assert!(plugin_version("0.1.0'").is_err());
+    }
+
+    #[test]
+    fn lua_strings_are_escaped_on_single_line() {
+        assert_eq!(quote_lua("select 1"), r#""select 1""#);
+        assert_eq!(
+            quote_lua("select \"a\\b\" from t\nwhere x = 'y'"),
+            r#""select \"a\\b\" from t\nwhere x = 'y'""#
+        );
+        assert_eq!(quote_lua("\u{1b}[0m\0"), r#""\027[0m\000""#);
+        assert_eq!(quote_lua("значение"), "\"значение\"");
+    }
+
+    #[test]
+    fn passwords_are_redacted() {
+        assert_eq!(
+            redact_passwords(r#"CREATE USER "u" WITH PASSWORD 'it''s secret' USING md5"#),
+            r#"CREATE USER "u" WITH PASSWORD '***' USING md5"#
+        );
+        assert_eq!(
+            redact_passwords("alter user u password 'x'"),
+            "alter user u password '***'"
+        );
+        assert_eq!(
+            redact_passwords("SELECT 'password' FROM t"),
+            "SELECT 'password' FROM t"
+        );
+    }
+}
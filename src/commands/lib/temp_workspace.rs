@@ -0,0 +1,125 @@
+//! Temporary directories for tests of pike and plugins.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Directory with files of a single test.
+///
+/// By default workspaces are placed under `target/tmp/<test binary name>/<name>`,
+/// so the layout is the same on every run. Directory is removed when the workspace
+/// is dropped, unless the test is panicking: then the directory is kept
+/// and its path is printed for investigation.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct TempWorkspace {
+    path: PathBuf,
+}
+
+#[allow(dead_code)]
+impl TempWorkspace {
+    /// Creates empty workspace `name` in the default root of the current test binary.
+    pub fn new(name: &str) -> Result<Self> {
+        Self::in_dir(&default_root()?, name)
+    }
+
+    /// Creates empty workspace `name` in `root`.
+    ///
+    /// Useful when the default root is too deep, e.g. for unix sockets
+    /// which paths are limited to about a hundred bytes.
+    pub fn in_dir(root: &Path, name: &str) -> Result<Self> {
+        let path = root.join(name);
+
+        // Leftovers of the previous failed run.
+        match fs::remove_dir_all(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err)
+                    .context(format!("failed to clean test workspace {}", path.display()));
+            }
+            _ => {}
+        }
+        fs::create_dir_all(&path).context(format!(
+            "failed to create test workspace {}",
+            path.display()
+        ))?;
+
+        Ok(Self { path })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[must_use]
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempWorkspace {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            eprintln!("test workspace is kept at {}", self.path.display());
+            return;
+        }
+
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Returns `target/tmp/<binary name>` for binaries built by cargo,
+/// i.e. placed in `target/<profile>/deps`, and system temp directory otherwise.
+#[allow(dead_code)]
+fn default_root() -> Result<PathBuf> {
+    let exe = env::current_exe().context("failed to get path of the test binary")?;
+    let binary_name = exe
+        .file_stem()
+        .context("test binary path has no file name")?;
+
+    let target_dir = exe
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == "deps"))
+        .and_then(|deps| deps.parent()?.parent())
+        .map_or_else(|| env::temp_dir().join("pike-tests"), Path::to_path_buf);
+
+    Ok(target_dir.join("tmp").join(binary_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_is_recreated_and_removed_on_drop() {
+        let workspace = TempWorkspace::new("temp-workspace-drop").unwrap();
+        let path = workspace.path().to_path_buf();
+        assert!(path.starts_with(default_root().unwrap()));
+        fs::write(workspace.join("file"), "").unwrap();
+
+        // Files of the previous run are removed.
+        let workspace = TempWorkspace::new("temp-workspace-drop").unwrap();
+        assert_eq!(fs::read_dir(workspace.path()).unwrap().count(), 0);
+
+        drop(workspace);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn workspace_is_kept_on_panic() {
+        let root = TempWorkspace::new("temp-workspace-panic").unwrap();
+        let root_path = root.path().to_path_buf();
+
+        let result = thread::spawn(move || {
+            let _workspace = TempWorkspace::in_dir(&root_path, "failed").unwrap();
+            panic!("test failure");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(root.join("failed").is_dir());
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn serialize_secs<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Duration of a single step of the command.
+#[derive(Debug, Clone, Serialize)]
+pub struct Timing {
+    pub name: String,
+    #[serde(rename = "seconds", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    #[serde(skip)]
+    pub started: Instant,
+}
+
+/// Breakdown of the time spent by the command, printed with `--timings`.
+///
+/// Besides the steps it keeps spans of finer phases, e.g. single queries,
+/// which are only exported to the trace of `--record-startup-profile`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Timings {
+    steps: Vec<Timing>,
+    #[serde(skip)]
+    spans: Vec<Timing>,
+    #[serde(skip)]
+    started_at: Instant,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            spans: Vec::new(),
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl Timings {
+    /// Records the step which has just finished.
+    pub fn record(&mut self, name: impl Into<String>, duration: Duration) {
+        let started = Instant::now()
+            .checked_sub(duration)
+            .unwrap_or(self.started_at);
+        self.record_step(name, started, duration);
+    }
+
+    pub fn record_step(&mut self, name: impl Into<String>, started: Instant, duration: Duration) {
+        self.steps.push(Timing {
+            name: name.into(),
+            duration,
+            started,
+        });
+    }
+
+    /// Records the phase which is only shown in the trace.
+    pub fn record_span(&mut self, name: impl Into<String>, started: Instant, duration: Duration) {
+        self.spans.push(Timing {
+            name: name.into(),
+            duration,
+            started,
+        });
+    }
+
+    /// Runs `f` and records its duration under `name`.
+    pub fn measure<T>(&mut self, name: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        self.measure_with(name, |_| f())
+    }
+
+    /// Same as [`measure`](Self::measure), `f` can record nested spans.
+    pub fn measure_with<T>(
+        &mut self,
+        name: impl Into<String>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let start = Instant::now();
+        let result = f(self);
+        self.record_step(name, start, start.elapsed());
+        result
+    }
+
+    /// Steps and spans in the [trace event format] of Chrome, which is opened by
+    /// `chrome://tracing`, Perfetto UI or speedscope.
+    ///
+    /// [trace event format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+    pub fn chrome_trace(&self) -> Value {
+        let micros = |duration: Duration| u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let mut timings: Vec<(&Timing, &str)> = self
+            .steps
+            .iter()
+            .map(|t| (t, "step"))
+            .chain(self.spans.iter().map(|t| (t, "span")))
+            .collect();
+        // Enclosing phases go first, so viewers nest the phases started at the same time.
+        timings.sort_by_key(|(t, _)| (t.started, std::cmp::Reverse(t.duration)));
+
+        let events: Vec<Value> = timings
+            .into_iter()
+            .map(|(timing, category)| {
+                json!({
+                    "name": timing.name,
+                    "cat": category,
+                    "ph": "X",
+                    "ts": micros(timing.started.saturating_duration_since(self.started_at)),
+                    "dur": micros(timing.duration),
+                    "pid": 1,
+                    "tid": 1,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+    }
+
+    pub fn save_chrome_trace(&self, path: &Path) -> Result<()> {
+        let trace = serde_json::to_string_pretty(&self.chrome_trace())?;
+        fs::write(path, trace).context(format!(
+            "failed to write startup profile to {}",
+            path.display()
+        ))
+    }
+
+    #[allow(dead_code)]
+    pub fn steps(&self) -> &[Timing] {
+        &self.steps
+    }
+
+    /// Time passed since the command has started.
+    pub fn total(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const TOTAL: &str = "total";
+
+        let width = self
+            .steps
+            .iter()
+            .map(|step| step.name.len())
+            .chain([TOTAL.len()])
+            .max()
+            .unwrap_or_default();
+
+        writeln!(f, "Timings:")?;
+        for step in &self.steps {
+            writeln!(
+                f,
+                "  {:<width$}  {:>8.2}s",
+                step.name,
+                step.duration.as_secs_f64()
+            )?;
+        }
+        writeln!(f, "  {TOTAL:<width$}  {:>8.2}s", self.total().as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timings_are_rendered_as_aligned_table() {
+        let mut timings = Timings::default();
+        timings.record("cargo build", Duration::from_millis(12_340));
+        timings.record("i1 startup", Duration::from_millis(1_200));
+
+        let rendered = timings.to_string();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "Timings:");
+        assert_eq!(lines[1], "  cargo build     12.34s");
+        assert_eq!(lines[2], "  i1 startup       1.20s");
+        assert!(lines[3].starts_with("  total      "), "{rendered}");
+    }
+
+    #[test]
+    fn measure_records_step_and_returns_result() {
+        let mut timings = Timings::default();
+        let value = timings.measure("step", || 42);
+
+        assert_eq!(value, 42);
+        assert_eq!(timings.steps().len(), 1);
+        assert_eq!(timings.steps()[0].name, "step");
+    }
+
+    #[test]
+    fn chrome_trace_has_steps_and_spans_ordered_by_start() {
+        let mut timings = Timings::default();
+        let at = |millis| timings.started_at + Duration::from_millis(millis);
+        let (install, create, startup) = (at(10), at(10), at(1));
+        timings.record_span("CREATE PLUGIN", create, Duration::from_millis(2));
+        timings.record_step("plugin install", install, Duration::from_millis(5));
+        timings.record_step("i1 startup", startup, Duration::from_millis(3));
+
+        let trace = timings.chrome_trace();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let names: Vec<&str> = events.iter().map(|e| e["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["i1 startup", "plugin install", "CREATE PLUGIN"]);
+        assert_eq!(events[0]["ts"], 1000);
+        assert_eq!(events[0]["dur"], 3000);
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[1]["cat"], "step");
+        assert_eq!(events[2]["cat"], "span");
+
+        // Spans are not printed with --timings.
+        assert_eq!(timings.to_string().lines().count(), 4);
+    }
+
+    #[test]
+    fn timings_serialize_durations_in_seconds() {
+        let mut timings = Timings::default();
+        timings.record("step", Duration::from_millis(1_500));
+
+        assert_eq!(
+            serde_json::to_value(&timings).unwrap(),
+            serde_json::json!({ "steps": [{ "name": "step", "seconds": 1.5 }] })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Logs of instances of the cluster captured into `picodata.log`, optionally
+//! only records written by a plugin service.
+//!
+//! Picodata writes records of the server and of plugin services into the same
+//! log, records of a service are told apart by its name, matched with the
+//! `service-pattern` regex of the `[logs]` section of `pike.toml`.
+
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::lib::pike_config::PikeConfig;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const LOG_FILE_NAME: &str = "picodata.log";
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Placeholder of the service name in `service-pattern`.
+const SERVICE_PLACEHOLDER: &str = "{service}";
+/// Name of the service as a separate word, e.g. `[weather_service]` or `weather_service:`.
+pub const DEFAULT_SERVICE_PATTERN: &str = r"\b{service}\b";
+
+/// `[logs]` section of `pike.toml`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LogsConfig {
+    /// Regex matching records of the service, `{service}` is replaced with its escaped name.
+    #[serde(default = "default_service_pattern")]
+    pub service_pattern: String,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            service_pattern: default_service_pattern(),
+        }
+    }
+}
+
+fn default_service_pattern() -> String {
+    DEFAULT_SERVICE_PATTERN.to_string()
+}
+
+impl LogsConfig {
+    /// Regex matching records of `service`.
+    fn service_regex(&self, service: &str) -> Result<Regex> {
+        if !self.service_pattern.contains(SERVICE_PLACEHOLDER) {
+            bail!(
+                "service-pattern '{}' of [logs] in pike.toml has no {SERVICE_PLACEHOLDER} placeholder",
+                self.service_pattern
+            );
+        }
+        let pattern = self
+            .service_pattern
+            .replace(SERVICE_PLACEHOLDER, &regex::escape(service));
+        Regex::new(&pattern).context(format!(
+            "invalid service-pattern '{}' of [logs] in pike.toml",
+            self.service_pattern
+        ))
+    }
+}
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    /// Print only records of the plugin service or plugin.
+    #[builder(default)]
+    service: Option<String>,
+    /// Print logs of a single instance instead of all of them.
+    #[builder(default)]
+    instance_name: Option<String>,
+    /// Keep printing records as they are written until interrupted.
+    #[builder(default = "false")]
+    follow: bool,
+}
+
+/// Log of an instance read by parts as it grows.
+struct LogTail {
+    instance_name: String,
+    path: PathBuf,
+    offset: u64,
+    /// Last line which is not terminated yet.
+    partial: String,
+}
+
+impl LogTail {
+    fn new(instance_name: String, path: PathBuf) -> Self {
+        Self {
+            instance_name,
+            path,
+            offset: 0,
+            partial: String::new(),
+        }
+    }
+
+    /// Complete lines written since the previous read.
+    fn read_lines(&mut self) -> Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err).context(format!("failed to open {}", self.path.display())),
+        };
+        let len = file
+            .metadata()
+            .context(format!(
+                "failed to read metadata of {}",
+                self.path.display()
+            ))?
+            .len();
+        if len < self.offset {
+            // The log was truncated or rotated.
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut content = vec![];
+        file.read_to_end(&mut content)
+            .context(format!("failed to read {}", self.path.display()))?;
+        self.offset += content.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&content));
+
+        let Some(end) = self.partial.rfind('\n') else {
+            return Ok(vec![]);
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.partial, rest)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        Ok(lines)
+    }
+
+    /// Prints new lines matching `filter` prefixed with the name of the instance.
+    fn print(&mut self, filter: Option<&Regex>, out: &mut impl Write) -> Result<()> {
+        for line in self.read_lines()? {
+            if filter.is_none_or(|filter| filter.is_match(&line)) {
+                writeln!(out, "{}| {line}", self.instance_name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Logs of instances in the cluster directory sorted by instance name.
+fn find_logs(cluster_dir: &Path, instance_name: Option<&str>) -> Result<Vec<LogTail>> {
+    let entries = match fs::read_dir(cluster_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            bail!(
+                "cluster in {} has never been started",
+                cluster_dir.display()
+            )
+        }
+        Err(err) => return Err(err).context(format!("failed to read {}", cluster_dir.display())),
+    };
+
+    let mut logs = vec![];
+    for entry in entries {
+        let entry = entry.context(format!("failed to read {}", cluster_dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path().join(LOG_FILE_NAME);
+        if instance_name.is_none_or(|instance_name| instance_name == name) && path.is_file() {
+            logs.push(LogTail::new(name, path));
+        }
+    }
+    if logs.is_empty() {
+        match instance_name {
+            Some(name) => bail!("failed to find log of the instance '{name}'"),
+            None => bail!(
+                "failed to find logs of instances in {}",
+                cluster_dir.display()
+            ),
+        }
+    }
+    logs.sort_by(|a, b| a.instance_name.cmp(&b.instance_name));
+    Ok(logs)
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let filter = match &params.service {
+        Some(service) => Some(
+            PikeConfig::load(&params.plugin_path)?
+                .logs
+                .service_regex(service)?,
+        ),
+        None => None,
+    };
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let mut logs = find_logs(&cluster_dir, params.instance_name.as_deref())?;
+
+    let mut out = io::stdout().lock();
+    loop {
+        for log in &mut logs {
+            log.print(filter.as_ref(), &mut out)?;
+        }
+        out.flush()?;
+        if !params.follow {
+            return Ok(());
+        }
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn service_pattern_matches_records_of_the_service_only() {
+        let filter = LogsConfig::default()
+            .service_regex("weather_service")
+            .unwrap();
+        assert!(filter.is_match("2025-01-01 12:00:00.000 [1] main I> [weather_service] cached"));
+        assert!(!filter.is_match("2025-01-01 12:00:00.000 [1] main I> [weather_service_v2] x"));
+        assert!(!filter.is_match("2025-01-01 12:00:00.000 [1] main I> raft: became leader"));
+
+        let config = LogsConfig {
+            service_pattern: r"plugin=\w+ service={service}$".to_string(),
+        };
+        let filter = config.service_regex("a.b").unwrap();
+        assert!(filter.is_match("msg plugin=p service=a.b"));
+        assert!(!filter.is_match("msg plugin=p service=axb"));
+
+        let config = LogsConfig {
+            service_pattern: "no placeholder".to_string(),
+        };
+        assert!(config.service_regex("a").is_err());
+    }
+
+    #[test]
+    fn only_complete_lines_are_printed() {
+        let dir = TempWorkspace::new("logs-tail").unwrap();
+        let path = dir.join(LOG_FILE_NAME);
+        fs::write(&path, "I> [svc] first\nI> other\nI> [svc] sec").unwrap();
+
+        let filter = LogsConfig::default().service_regex("svc").unwrap();
+        let mut tail = LogTail::new("i1".to_string(), path.clone());
+        let mut out = vec![];
+        tail.print(Some(&filter), &mut out).unwrap();
+        assert_eq!(String::from_utf8_lossy(&out), "i1| I> [svc] first\n");
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ond\n").unwrap();
+        out.clear();
+        tail.print(Some(&filter), &mut out).unwrap();
+        assert_eq!(String::from_utf8_lossy(&out), "i1| I> [svc] second\n");
+
+        // Rotated log is read from the start.
+        fs::write(&path, "I> [svc] new\n").unwrap();
+        out.clear();
+        tail.print(None, &mut out).unwrap();
+        assert_eq!(String::from_utf8_lossy(&out), "i1| I> [svc] new\n");
+    }
+}
@@ -0,0 +1,283 @@
+//! Prometheus metrics of all instances of the cluster started by pike.
+//!
+//! Samples scraped from `/metrics` of every instance are merged into one
+//! exposition with `instance` and `tier` labels, so a local Prometheus or
+//! Grafana needs a single target instead of one per instance.
+
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    /// Address to serve merged metrics on, printed to stdout once if not set.
+    #[builder(default)]
+    listen: Option<SocketAddr>,
+}
+
+/// Samples of a metric with its `# HELP` and `# TYPE` lines.
+#[derive(Debug, Default)]
+struct Family {
+    name: String,
+    help: Option<String>,
+    kind: Option<String>,
+    samples: Vec<String>,
+}
+
+/// Metric families of all instances in order of their first appearance.
+#[derive(Debug, Default)]
+struct Exposition {
+    families: Vec<Family>,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str) -> &mut Family {
+        let index = if let Some(index) = self.families.iter().position(|f| f.name == name) {
+            index
+        } else {
+            self.families.push(Family {
+                name: name.to_string(),
+                ..Default::default()
+            });
+            self.families.len() - 1
+        };
+        &mut self.families[index]
+    }
+
+    /// Adds metrics of the instance in the text format, its samples get
+    /// `instance` and `tier` labels.
+    fn add(&mut self, instance: &InstanceMetadata, text: &str) {
+        let labels = format!(
+            "instance=\"{}\",tier=\"{}\"",
+            escape_label_value(&instance.name),
+            escape_label_value(&instance.tier)
+        );
+        // Samples of histograms and summaries have suffixes of their family name.
+        let mut current = String::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut parts = comment.trim_start().splitn(3, ' ');
+                let (Some(keyword @ ("HELP" | "TYPE")), Some(name)) = (parts.next(), parts.next())
+                else {
+                    continue;
+                };
+                let value = parts.next().unwrap_or_default().to_string();
+                let family = self.family(name);
+                match keyword {
+                    "HELP" => family.help.get_or_insert(value),
+                    _ => family.kind.get_or_insert(value),
+                };
+                current = name.to_string();
+                continue;
+            }
+            let name_end = line
+                .find(|c: char| c == '{' || c.is_whitespace())
+                .unwrap_or(line.len());
+            let (name, rest) = line.split_at(name_end);
+            let sample = match rest.strip_prefix('{') {
+                Some(rest) if rest.trim_start().starts_with('}') => {
+                    format!("{name}{{{labels}{rest}")
+                }
+                Some(rest) => format!("{name}{{{labels},{rest}"),
+                None => format!("{name}{{{labels}}}{rest}"),
+            };
+            let family = if !current.is_empty() && name.starts_with(&current) {
+                current.clone()
+            } else {
+                name.to_string()
+            };
+            self.family(&family).samples.push(sample);
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for family in &self.families {
+            if let Some(help) = &family.help {
+                writeln!(text, "# HELP {} {help}", family.name)
+                    .expect("writing to string never fails");
+            }
+            if let Some(kind) = &family.kind {
+                writeln!(text, "# TYPE {} {kind}", family.name)
+                    .expect("writing to string never fails");
+            }
+            for sample in &family.samples {
+                text.push_str(sample);
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn scrape(agent: &ureq::Agent, instance: &InstanceMetadata) -> Result<String> {
+    let url = format!("http://127.0.0.1:{}/metrics", instance.http_port);
+    agent
+        .get(&url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .context(format!("failed to scrape {url}"))
+}
+
+/// Metrics of all instances of the cluster, unavailable instances are skipped.
+fn collect(params: &Params) -> Result<String> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(metadata) = ClusterMetadata::load(&path)? else {
+        bail!(
+            "cluster in {} has never been started",
+            params.plugin_path.join(&params.data_dir).display()
+        );
+    };
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(SCRAPE_TIMEOUT))
+        .build()
+        .into();
+
+    let mut exposition = Exposition::default();
+    for instance in &metadata.instances {
+        match scrape(&agent, instance) {
+            Ok(text) => exposition.add(instance, &text),
+            Err(err) => warn!("skipping instance '{}': {err:#}", instance.name),
+        }
+    }
+    Ok(exposition.render())
+}
+
+/// Answers a request to `/metrics` with metrics of the cluster, other paths are not found.
+fn handle(params: &Params, mut stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Headers are not needed, but unread ones make the client see a reset connection.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics" | "/")) => match collect(params) {
+            Ok(body) => ("200 OK", CONTENT_TYPE, body),
+            Err(err) => (
+                "500 Internal Server Error",
+                "text/plain",
+                format!("{err:#}\n"),
+            ),
+        },
+        _ => ("404 Not Found", "text/plain", "use /metrics\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// Prints merged metrics of the cluster or serves them on `listen` until interrupted,
+/// instances are rescraped on every request.
+pub fn cmd(params: &Params) -> Result<()> {
+    let Some(listen) = params.listen else {
+        print!("{}", collect(params)?);
+        return Ok(());
+    };
+
+    let listener = TcpListener::bind(listen).context(format!("failed to listen on {listen}"))?;
+    info!(
+        "Serving metrics of the cluster on http://{}/metrics",
+        listener.local_addr()?
+    );
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = handle(params, stream) {
+                    warn!("failed to serve metrics: {err:#}");
+                }
+            }
+            Err(err) => warn!("failed to accept connection: {err}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(name: &str, tier: &str) -> InstanceMetadata {
+        InstanceMetadata {
+            name: name.to_string(),
+            instance_id: 1,
+            tier: tier.to_string(),
+            pid: 1,
+            data_dir: PathBuf::from("tmp/cluster/i1"),
+            admin_socket: PathBuf::from("tmp/cluster/i1/admin.sock"),
+            bin_port: 3001,
+            http_port: 8001,
+            pg_port: 5433,
+            restarts: 0,
+        }
+    }
+
+    #[test]
+    fn samples_of_instances_are_merged_by_family() {
+        let metrics = |requests: u32| {
+            format!(
+                "\
+# HELP pico_requests Number of requests
+# TYPE pico_requests counter
+pico_requests{{kind=\"dml\"}} {requests}
+pico_requests{{}} 1
+
+# HELP pico_latency Latency of requests
+# TYPE pico_latency histogram
+pico_latency_bucket{{le=\"+Inf\"}} {requests}
+pico_latency_count {requests} 1700000000000
+"
+            )
+        };
+
+        let mut exposition = Exposition::default();
+        exposition.add(&instance("default_1_1", "default"), &metrics(3));
+        exposition.add(&instance("storage_1_1", "storage"), &metrics(5));
+        exposition.add(&instance("storage_1_2", "storage"), "plugin_up 1\n");
+
+        assert_eq!(
+            exposition.render(),
+            "\
+# HELP pico_requests Number of requests
+# TYPE pico_requests counter
+pico_requests{instance=\"default_1_1\",tier=\"default\",kind=\"dml\"} 3
+pico_requests{instance=\"default_1_1\",tier=\"default\"} 1
+pico_requests{instance=\"storage_1_1\",tier=\"storage\",kind=\"dml\"} 5
+pico_requests{instance=\"storage_1_1\",tier=\"storage\"} 1
+# HELP pico_latency Latency of requests
+# TYPE pico_latency histogram
+pico_latency_bucket{instance=\"default_1_1\",tier=\"default\",le=\"+Inf\"} 3
+pico_latency_count{instance=\"default_1_1\",tier=\"default\"} 3 1700000000000
+pico_latency_bucket{instance=\"storage_1_1\",tier=\"storage\",le=\"+Inf\"} 5
+pico_latency_count{instance=\"storage_1_1\",tier=\"storage\"} 5 1700000000000
+plugin_up{instance=\"storage_1_2\",tier=\"storage\"} 1
+"
+        );
+    }
+}
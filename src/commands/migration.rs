@@ -0,0 +1,133 @@
+//! Migrations of the plugin applied on the running cluster, read from
+//! `_pico_plugin_migration`.
+
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::instance_info::InstanceSocketClient;
+use crate::commands::lib::sql::quote_literal;
+use crate::commands::lib::{find_active_instance_dir, get_cluster_dir, OutputFormat};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
+    data_dir: PathBuf,
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
+    plugin_path: PathBuf,
+    /// Name of the plugin, by default it is read from Cargo.toml of the plugin.
+    #[builder(default)]
+    plugin_name: Option<String>,
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
+    picodata_path: PathBuf,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+/// Row of `_pico_plugin_migration`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AppliedMigration {
+    pub plugin_name: String,
+    /// Path of the migration relative to the plugin, e.g. `migrations/0001_init.sql`.
+    pub migration_file: String,
+    /// Hash of the migration content.
+    pub hash: String,
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<Package>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+}
+
+fn plugin_name(params: &Params) -> Result<String> {
+    if let Some(name) = &params.plugin_name {
+        return Ok(name.clone());
+    }
+
+    let cargo_toml_path = params.plugin_path.join("Cargo.toml");
+    let content = fs::read_to_string(&cargo_toml_path).context(format!(
+        "failed to read Cargo.toml in {}",
+        cargo_toml_path.display()
+    ))?;
+    let manifest: CargoManifest = toml::from_str(&content).context("failed to parse Cargo.toml")?;
+    match manifest.package {
+        Some(package) => Ok(package.name),
+        None => bail!("plugin path points to a workspace, choose the plugin with --plugin-name"),
+    }
+}
+
+/// Migrations applied on the running cluster for the plugin, in the order they are applied.
+pub fn applied(params: &Params) -> Result<Vec<AppliedMigration>> {
+    let plugin_name = plugin_name(params)?;
+
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let instance_dir = find_active_instance_dir(&cluster_dir)?;
+
+    // Build script of the plugin lists migrations in the manifest sorted by file name.
+    InstanceSocketClient::new(&instance_dir, &params.picodata_path)
+        .query(&format!(
+            "SELECT plugin_name, migration_file, hash FROM _pico_plugin_migration \
+            WHERE plugin_name = {} ORDER BY migration_file",
+            quote_literal(&plugin_name)
+        ))
+        .context(format!(
+            "failed to fetch applied migrations of plugin {plugin_name}"
+        ))
+}
+
+pub fn cmd_applied(params: &Params) -> Result<()> {
+    let migrations = applied(params)?;
+    match params.output {
+        OutputFormat::Text => {
+            for migration in &migrations {
+                println!("{}", migration.migration_file);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&migrations)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn plugin_name_is_read_from_cargo_toml() {
+        let dir = TempWorkspace::new("migration-plugin-name").unwrap();
+        let params = |plugin_name: Option<&str>| {
+            ParamsBuilder::default()
+                .plugin_path(dir.path().to_path_buf())
+                .plugin_name(plugin_name.map(String::from))
+                .build()
+                .unwrap()
+        };
+
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"weather_cache\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        assert_eq!(plugin_name(&params(None)).unwrap(), "weather_cache");
+        assert_eq!(plugin_name(&params(Some("other"))).unwrap(), "other");
+
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"weather_cache\"]\n",
+        )
+        .unwrap();
+        assert!(plugin_name(&params(None)).is_err());
+        assert_eq!(
+            plugin_name(&params(Some("weather_cache"))).unwrap(),
+            "weather_cache"
+        );
+    }
+}
@@ -1,8 +1,24 @@
 pub(crate) mod clean;
+pub(crate) mod cluster;
 pub(crate) mod config;
+pub(crate) mod connect;
+pub(crate) mod debug;
 pub(crate) mod enter;
+pub(crate) mod exec;
+pub(crate) mod instance;
 pub(crate) mod lib;
+pub(crate) mod logs;
+pub(crate) mod metrics;
+pub(crate) mod migration;
+pub(crate) mod monitor;
+pub(crate) mod picodata;
 pub(crate) mod plugin;
+pub(crate) mod profile;
 pub(crate) mod ride;
 pub(crate) mod run;
+pub(crate) mod status;
 pub(crate) mod stop;
+pub(crate) mod time;
+pub(crate) mod top;
+pub(crate) mod wait;
+pub(crate) mod webui;
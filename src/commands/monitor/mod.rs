@@ -0,0 +1,358 @@
+//! Monitor of a daemonized cluster, which checks it periodically and reports
+//! problems when they appear and when they are gone.
+
+mod notifier;
+
+pub use notifier::{Event, Notifier, Status};
+
+use crate::commands::lib::cluster_metadata::ClusterMetadata;
+use crate::commands::lib::cluster_state::ClusterState;
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::lib::instance_info::InstanceState;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+pub const DEFAULT_LOG_ERRORS_THRESHOLD: usize = 10;
+
+const LOG_FILE_NAME: &str = "picodata.log";
+const MIB: u64 = 1024 * 1024;
+
+/// Markers of error, critical and fatal records in plain and JSON logs of picodata.
+const LOG_ERROR_MARKERS: &[&str] = &[
+    " E> ",
+    " C> ",
+    " F> ",
+    " !> ",
+    "\"level\":\"error\"",
+    "\"level\":\"crit\"",
+    "\"level\":\"fatal\"",
+];
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = DEFAULT_MONITOR_INTERVAL)]
+    interval: Duration,
+    /// Size of the data directory of an instance in bytes to report, not checked by default.
+    #[builder(default)]
+    disk_limit: Option<u64>,
+    /// Number of error records in the log of an instance between checks to report.
+    #[builder(default = DEFAULT_LOG_ERRORS_THRESHOLD)]
+    log_errors_threshold: usize,
+    #[builder(default)]
+    notifiers: Vec<Notifier>,
+    /// Check the cluster once and fail if it is degraded.
+    #[builder(default = "false")]
+    once: bool,
+}
+
+/// Messages of problems found by a check keyed by [`Event::key`].
+type Problems = BTreeMap<String, String>;
+
+struct Monitor<'a> {
+    params: &'a Params,
+    cluster_dir: PathBuf,
+    /// Read positions in logs of instances, records written before the start are skipped.
+    log_offsets: HashMap<PathBuf, u64>,
+    states: HashMap<String, InstanceState>,
+}
+
+impl<'a> Monitor<'a> {
+    fn new(params: &'a Params) -> Self {
+        Self {
+            params,
+            cluster_dir: get_cluster_dir(&params.plugin_path, &params.data_dir),
+            log_offsets: HashMap::new(),
+            states: HashMap::new(),
+        }
+    }
+
+    fn check(&mut self) -> Result<Problems> {
+        let metadata_path = ClusterMetadata::path(&self.params.plugin_path, &self.params.data_dir);
+        let Some(metadata) = ClusterMetadata::load(&metadata_path)? else {
+            bail!(
+                "cluster in {} has never been started",
+                self.cluster_dir.display()
+            );
+        };
+
+        let mut problems = Problems::new();
+        for instance in &metadata.instances {
+            let name = &instance.name;
+            if UnixStream::connect(&instance.admin_socket).is_err() {
+                problems.insert(
+                    format!("down:{name}"),
+                    format!("instance {name} is not running"),
+                );
+            }
+
+            if let Some(limit) = self.params.disk_limit {
+                let size = dir_size(&instance.data_dir)?;
+                if size > limit {
+                    problems.insert(
+                        format!("disk:{name}"),
+                        format!(
+                            "data of instance {name} takes {} MiB, the limit is {} MiB",
+                            size / MIB,
+                            limit / MIB
+                        ),
+                    );
+                }
+            }
+
+            let log_path = instance.data_dir.join(LOG_FILE_NAME);
+            let (errors, offset) =
+                read_log_errors(&log_path, self.log_offsets.get(&log_path).copied())?;
+            self.log_offsets.insert(log_path, offset);
+            if errors > 0 && errors >= self.params.log_errors_threshold {
+                problems.insert(
+                    format!("log-errors:{name}"),
+                    format!(
+                        "{errors} errors in the log of instance {name} since the previous check"
+                    ),
+                );
+            }
+        }
+
+        match ClusterState::fetch(&self.cluster_dir, &self.params.picodata_path) {
+            Ok(state) => self.check_state(&state, &mut problems),
+            Err(err) => {
+                problems.insert(
+                    "unreachable".to_string(),
+                    format!("cluster is unreachable: {err:#}"),
+                );
+            }
+        }
+        Ok(problems)
+    }
+
+    fn check_state(&mut self, state: &ClusterState, problems: &mut Problems) {
+        for instance in state.instances.iter().filter(|i| !i.is_expelled()) {
+            let name = &instance.name;
+            let current = instance.current_state.variant;
+            if let Some(previous) = self.states.insert(name.clone(), current) {
+                if previous != current {
+                    info!("instance {name}: {previous:?} -> {current:?}");
+                }
+            }
+            if !instance.is_online() {
+                problems.insert(
+                    format!("state:{name}"),
+                    format!(
+                        "instance {name} is {current:?}, its target state is {:?}",
+                        instance.target_state.variant
+                    ),
+                );
+            }
+        }
+        if !state.has_quorum() {
+            problems.insert(
+                "quorum".to_string(),
+                format!("cluster has no quorum: {state}"),
+            );
+        }
+    }
+}
+
+/// Events of problems which appeared or disappeared between checks.
+fn diff(previous: &Problems, current: &Problems) -> Vec<Event> {
+    let degraded = current
+        .iter()
+        .filter(|(key, _)| !previous.contains_key(*key))
+        .map(|(key, message)| (Status::Degraded, key, message));
+    let recovered = previous
+        .iter()
+        .filter(|(key, _)| !current.contains_key(*key))
+        .map(|(key, message)| (Status::Recovered, key, message));
+    degraded
+        .chain(recovered)
+        .map(|(status, key, message)| Event {
+            status,
+            key: key.clone(),
+            message: message.clone(),
+        })
+        .collect()
+}
+
+/// Counts error records written to the log after `offset` and returns them
+/// with the new offset. Without `offset` the log is skipped to its end.
+fn read_log_errors(log_path: &Path, offset: Option<u64>) -> Result<(usize, u64)> {
+    let mut file = match File::open(log_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((0, 0)),
+        Err(err) => return Err(err).context(format!("failed to open {}", log_path.display())),
+    };
+    let len = file
+        .metadata()
+        .context(format!("failed to read metadata of {}", log_path.display()))?
+        .len();
+    let offset = match offset {
+        Some(offset) if offset <= len => offset,
+        // The log was truncated or rotated.
+        Some(_) => 0,
+        None => return Ok((0, len)),
+    };
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut content = vec![];
+    file.read_to_end(&mut content)
+        .context(format!("failed to read {}", log_path.display()))?;
+    let errors = String::from_utf8_lossy(&content)
+        .lines()
+        .filter(|line| LOG_ERROR_MARKERS.iter().any(|marker| line.contains(marker)))
+        .count();
+    Ok((errors, offset + content.len() as u64))
+}
+
+/// Size of files in `dir` and its subdirectories, symlinks are not followed.
+fn dir_size(dir: &Path) -> Result<u64> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err).context(format!("failed to read {}", dir.display())),
+    };
+    let mut size = 0;
+    // Files may be removed during the walk, e.g. old snapshots.
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let mut monitor = Monitor::new(params);
+    let mut problems = Problems::new();
+    info!(
+        "Monitoring the cluster in {} every {} sec",
+        monitor.cluster_dir.display(),
+        params.interval.as_secs()
+    );
+
+    loop {
+        let current = monitor.check()?;
+        for event in diff(&problems, &current) {
+            match event.status {
+                Status::Degraded => warn!("{}", event.message),
+                Status::Recovered => info!("recovered: {}", event.message),
+            }
+            for notifier in &params.notifiers {
+                if let Err(err) = notifier.send(&event) {
+                    warn!("failed to send notification: {err:#}");
+                }
+            }
+        }
+        problems = current;
+
+        if params.once {
+            if !problems.is_empty() {
+                bail!("cluster is degraded, {} problem(s) found", problems.len());
+            }
+            info!("Cluster is healthy");
+            return Ok(());
+        }
+        thread::sleep(params.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::io::Write;
+
+    #[test]
+    fn events_are_sent_when_problems_appear_and_disappear() {
+        let problems = |keys: &[&str]| -> Problems {
+            keys.iter()
+                .map(|key| (key.to_string(), format!("{key} message")))
+                .collect()
+        };
+
+        let events = diff(
+            &problems(&["down:i1", "quorum"]),
+            &problems(&["quorum", "disk:i2"]),
+        );
+        assert_eq!(
+            events,
+            [
+                Event {
+                    status: Status::Degraded,
+                    key: "disk:i2".into(),
+                    message: "disk:i2 message".into(),
+                },
+                Event {
+                    status: Status::Recovered,
+                    key: "down:i1".into(),
+                    message: "down:i1 message".into(),
+                },
+            ]
+        );
+        assert!(diff(&problems(&["quorum"]), &problems(&["quorum"])).is_empty());
+    }
+
+    #[test]
+    fn only_new_log_errors_are_counted() {
+        let workspace = TempWorkspace::new("monitor-log-errors").unwrap();
+        let log_path = workspace.join(LOG_FILE_NAME);
+        assert_eq!(read_log_errors(&log_path, None).unwrap(), (0, 0));
+
+        let mut log = File::create(&log_path).unwrap();
+        writeln!(
+            log,
+            "2025-01-01 10:00:00.000 [1] main/103/main E> old error"
+        )
+        .unwrap();
+        let (errors, offset) = read_log_errors(&log_path, None).unwrap();
+        assert_eq!(errors, 0);
+
+        writeln!(log, "2025-01-01 10:00:01.000 [1] main/103/main I> info").unwrap();
+        writeln!(
+            log,
+            "2025-01-01 10:00:02.000 [1] main/103/main E> new error"
+        )
+        .unwrap();
+        writeln!(log, r#"{{"level":"error","message":"json error"}}"#).unwrap();
+        let (errors, offset) = read_log_errors(&log_path, Some(offset)).unwrap();
+        assert_eq!(errors, 2);
+        assert_eq!(read_log_errors(&log_path, Some(offset)).unwrap().0, 0);
+
+        // Rotated log is read from the start.
+        fs::write(
+            &log_path,
+            "2025-01-01 10:00:03.000 [1] main/103/main F> fatal\n",
+        )
+        .unwrap();
+        assert_eq!(read_log_errors(&log_path, Some(offset)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn size_of_nested_files_is_summed() {
+        let workspace = TempWorkspace::new("monitor-dir-size").unwrap();
+        fs::create_dir_all(workspace.join("nested")).unwrap();
+        fs::write(workspace.join("00000000000000000000.snap"), [0; 100]).unwrap();
+        fs::write(workspace.join("nested/00000000000000000000.xlog"), [0; 20]).unwrap();
+        assert_eq!(dir_size(workspace.path()).unwrap(), 120);
+        assert_eq!(dir_size(&workspace.join("missing")).unwrap(), 0);
+    }
+}
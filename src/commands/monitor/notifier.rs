@@ -0,0 +1,83 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::process::Command;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const NOTIFICATION_TITLE: &str = "pike monitor";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Degraded,
+    Recovered,
+}
+
+/// Problem of the cluster which appeared or disappeared since the previous check.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Event {
+    pub status: Status,
+    /// Identifies the problem between checks, e.g. `down:default_1_1`.
+    pub key: String,
+    pub message: String,
+}
+
+impl Event {
+    fn text(&self) -> String {
+        match self.status {
+            Status::Degraded => self.message.clone(),
+            Status::Recovered => format!("recovered: {}", self.message),
+        }
+    }
+}
+
+/// Destination of events besides the log of pike.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Notifier {
+    /// `notify-send` on Linux, Notification Center on macOS.
+    Desktop,
+    /// JSON of [`Event`] is posted to the URL.
+    Webhook(String),
+}
+
+impl Notifier {
+    pub fn send(&self, event: &Event) -> Result<()> {
+        match self {
+            Self::Desktop => send_desktop_notification(&event.text()),
+            Self::Webhook(url) => {
+                let agent: ureq::Agent = ureq::Agent::config_builder()
+                    .timeout_global(Some(WEBHOOK_TIMEOUT))
+                    .build()
+                    .into();
+                agent
+                    .post(url)
+                    .send_json(event)
+                    .context(format!("failed to post event to {url}"))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn send_desktop_notification(text: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{NOTIFICATION_TITLE}\"",
+            escape(text)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg(NOTIFICATION_TITLE).arg(text);
+        command
+    };
+    let status = command
+        .status()
+        .context("failed to run desktop notification command")?;
+    if !status.success() {
+        bail!("desktop notification command exited with {status}");
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Download of a picodata release for the current OS, see [`super`].
+
+use super::{binary_in, releases_dir, MIRROR_ENV};
+use crate::commands::lib::archive;
+use crate::commands::lib::integrity;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::platform;
+use crate::commands::lib::repository::Repository;
+use anyhow::{anyhow, bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use std::env;
+use std::env::consts::ARCH;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    /// Release to install, e.g. `25.1.2`.
+    version: String,
+    /// Mirror with releases, `PIKE_PICODATA_MIRROR` or `mirror` of the `[picodata]`
+    /// section of `pike.toml` if not set.
+    #[builder(default)]
+    mirror: Option<String>,
+    /// OS suffix of the release archive, detected as for plugin archives if not set.
+    #[builder(default)]
+    os_suffix: Option<String>,
+    /// Download the release even if it is already installed.
+    #[builder(default = "false")]
+    force: bool,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let binary = install_into(&releases_dir()?, params)?;
+    println!("{}", binary.display());
+    Ok(())
+}
+
+/// Installs the release into `releases_dir`, returns the path of its binary.
+fn install_into(releases_dir: &Path, params: &Params) -> Result<PathBuf> {
+    let version = &params.version;
+    let binary = binary_in(releases_dir, version)?;
+    if binary.is_file() && !params.force {
+        info!(
+            "picodata {version} is already installed at {}",
+            binary.display()
+        );
+        return Ok(binary);
+    }
+
+    let config = PikeConfig::load(&params.plugin_path)?.picodata;
+    let mirror = params
+        .mirror
+        .clone()
+        .or_else(|| env::var(MIRROR_ENV).ok())
+        .or(config.mirror)
+        .ok_or_else(|| {
+            anyhow!("mirror of picodata releases is not set, pass --mirror, set {MIRROR_ENV} or mirror in [picodata] of pike.toml")
+        })?;
+    let repository = Repository::parse(
+        &mirror,
+        config.endpoint.as_deref(),
+        config.region.as_deref(),
+    )?;
+    let os_suffix = platform::os_suffix(params.os_suffix.as_deref())?;
+    let file_name = archive_name(version, &os_suffix);
+    let key = format!("{version}/{file_name}");
+
+    info!("Downloading picodata {version} from {repository}/{key}");
+    let content = repository.get(&key)?.with_context(|| {
+        format!("picodata {version} for {os_suffix} {ARCH} is not found at {repository}/{key}")
+    })?;
+    let checksum = repository
+        .get(&format!("{key}.sha256"))?
+        .with_context(|| format!("checksum {repository}/{key}.sha256 does not exist"))?;
+    let expected = String::from_utf8_lossy(&checksum)
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .with_context(|| format!("checksum {repository}/{key}.sha256 is empty"))?;
+
+    let staging_dir = releases_dir.join(format!(".{version}.download"));
+    remove_dir(&staging_dir)?;
+    fs::create_dir_all(&staging_dir)
+        .context(format!("failed to create {}", staging_dir.display()))?;
+    let archive_path = staging_dir.join(&file_name);
+    fs::write(&archive_path, &content)
+        .context(format!("failed to write {}", archive_path.display()))?;
+    let actual = integrity::sha256(&archive_path)?;
+    if !expected.eq_ignore_ascii_case(&actual) {
+        bail!("checksum of {repository}/{key} is {actual}, but {expected} is expected");
+    }
+
+    let unpacked_dir = staging_dir.join("unpacked");
+    archive::unpack(&archive_path, &unpacked_dir)
+        .context(format!("failed to unpack {}", archive_path.display()))?;
+    let unpacked_binary = find_binary(&unpacked_dir)?
+        .with_context(|| format!("{repository}/{key} has no picodata executable"))?;
+    fs::set_permissions(&unpacked_binary, fs::Permissions::from_mode(0o755)).context(format!(
+        "failed to make {} executable",
+        unpacked_binary.display()
+    ))?;
+
+    let version_dir = binary
+        .parent()
+        .expect("binary is in the directory of its version");
+    remove_dir(version_dir)?;
+    fs::create_dir_all(version_dir)
+        .context(format!("failed to create {}", version_dir.display()))?;
+    fs::rename(&unpacked_binary, &binary)
+        .context(format!("failed to move picodata to {}", binary.display()))?;
+    let checksum_path = version_dir.join(format!("{file_name}.sha256"));
+    fs::write(&checksum_path, format!("{actual}  {file_name}\n"))
+        .context(format!("failed to write {}", checksum_path.display()))?;
+    remove_dir(&staging_dir)?;
+
+    info!("picodata {version} is installed to {}", binary.display());
+    Ok(binary)
+}
+
+/// `picodata-<version>-<os_suffix>-<arch>.tar.gz`.
+fn archive_name(version: &str, os_suffix: &str) -> String {
+    format!("picodata-{version}-{os_suffix}-{ARCH}.tar.gz")
+}
+
+/// Finds `picodata` file in the unpacked archive, e.g. in `bin/`.
+fn find_binary(dir: &Path) -> Result<Option<PathBuf>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .context(format!("failed to read {}", dir.display()))?
+        .collect::<Result<_, _>>()
+        .context(format!("failed to read {}", dir.display()))?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in &entries {
+        let path = entry.path();
+        if path.is_file() && entry.file_name() == super::BINARY_NAME {
+            return Ok(Some(path));
+        }
+    }
+    for entry in &entries {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(binary) = find_binary(&path)? {
+                return Ok(Some(binary));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn remove_dir(dir: &Path) -> Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).context(format!("failed to remove {}", dir.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::archive::{ArchiveFormat, ArchiveWriter, CompressionLevel};
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    fn publish_release(mirror: &Path, version: &str, checksum: Option<&str>) {
+        let file_name = archive_name(version, "alt_10");
+        let dir = mirror.join(version);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(&file_name);
+        let mut archive =
+            ArchiveWriter::create(&path, ArchiveFormat::TarGz, CompressionLevel::Fast).unwrap();
+        archive
+            .append_data(Path::new("picodata-25.1/README"), b"readme")
+            .unwrap();
+        archive
+            .append_data(Path::new("picodata-25.1/bin/picodata"), b"#!/bin/sh\n")
+            .unwrap();
+        archive.finish().unwrap();
+        let sha256 = checksum.map_or_else(|| integrity::sha256(&path).unwrap(), str::to_string);
+        integrity::write_checksum(&path, &sha256).unwrap();
+    }
+
+    fn params(mirror: &Path, version: &str, workspace: &TempWorkspace) -> Params {
+        ParamsBuilder::default()
+            .version(version.to_string())
+            .mirror(Some(mirror.to_string_lossy().to_string()))
+            .os_suffix(Some("alt_10".to_string()))
+            .plugin_path(workspace.path().to_path_buf())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn release_is_installed_from_mirror() {
+        let workspace = TempWorkspace::new("picodata-install").unwrap();
+        let mirror = workspace.join("mirror");
+        let releases = workspace.join("releases");
+        publish_release(&mirror, "25.1.2", None);
+
+        let binary = install_into(&releases, &params(&mirror, "25.1.2", &workspace)).unwrap();
+        assert_eq!(binary, releases.join("25.1.2").join("picodata"));
+        assert_eq!(fs::read(&binary).unwrap(), b"#!/bin/sh\n");
+        let mode = fs::metadata(&binary).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(!releases.join(".25.1.2.download").exists());
+
+        // Installed release is not downloaded again.
+        fs::remove_dir_all(&mirror).unwrap();
+        install_into(&releases, &params(&mirror, "25.1.2", &workspace)).unwrap();
+    }
+
+    #[test]
+    fn release_with_wrong_checksum_is_rejected() {
+        let workspace = TempWorkspace::new("picodata-install-checksum").unwrap();
+        let mirror = workspace.join("mirror");
+        let releases = workspace.join("releases");
+        publish_release(&mirror, "25.1.2", Some(&"0".repeat(64)));
+
+        let err = install_into(&releases, &params(&mirror, "25.1.2", &workspace)).unwrap_err();
+        assert!(err.to_string().contains("checksum of"), "{err}");
+        assert!(!releases.join("25.1.2").exists());
+
+        let err = install_into(&releases, &params(&mirror, "25.2.0", &workspace)).unwrap_err();
+        assert!(err.to_string().contains("is not found"), "{err}");
+    }
+}
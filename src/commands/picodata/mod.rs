@@ -0,0 +1,89 @@
+//! Picodata releases installed by `picodata install` into `~/.pike/picodata/<version>/`.
+//!
+//! Releases are downloaded from a mirror, a repository laid out as
+//! `<version>/picodata-<version>-<os_suffix>-<arch>.tar.gz` with `.sha256` next to
+//! every archive, see [`crate::commands::lib::repository::Repository`] for the
+//! supported locations.
+
+pub(crate) mod install;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Variable overriding `~/.pike`, the directory with installed releases.
+pub const PIKE_HOME_ENV: &str = "PIKE_HOME";
+/// Variable overriding `mirror` of the `[picodata]` section of `pike.toml`.
+pub const MIRROR_ENV: &str = "PIKE_PICODATA_MIRROR";
+
+const BINARY_NAME: &str = "picodata";
+
+/// `[picodata]` section of `pike.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PicodataConfig {
+    /// Repository with picodata releases.
+    pub mirror: Option<String>,
+    /// Endpoint of S3-compatible storage of the mirror.
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+/// Directory with installed picodata releases.
+pub fn releases_dir() -> Result<PathBuf> {
+    let home = match env::var_os(PIKE_HOME_ENV) {
+        Some(home) => PathBuf::from(home),
+        None => PathBuf::from(env::var_os("HOME").context("HOME is not set")?).join(".pike"),
+    };
+    Ok(home.join("picodata"))
+}
+
+/// Path of the binary of the installed release, the release may be missing.
+pub fn binary_path(version: &str) -> Result<PathBuf> {
+    binary_in(&releases_dir()?, version)
+}
+
+fn binary_in(releases_dir: &Path, version: &str) -> Result<PathBuf> {
+    validate_version(version)?;
+    Ok(releases_dir.join(version).join(BINARY_NAME))
+}
+
+/// Binary of the installed release, fails with a hint to install it if it is missing.
+pub fn resolve(version: &str) -> Result<PathBuf> {
+    let path = binary_path(version)?;
+    if !path.is_file() {
+        bail!(
+            "picodata {version} is not installed, install it with `cargo pike picodata install {version}`"
+        );
+    }
+    Ok(path)
+}
+
+/// Versions are used as directory names, so they can't contain separators.
+fn validate_version(version: &str) -> Result<()> {
+    let is_valid = !version.is_empty()
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'));
+    if !is_valid {
+        bail!("invalid picodata version '{version}'");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_validated() {
+        for version in ["25.1", "25.1.2", "25.2.0-rc1", "24.6.1+build.3"] {
+            validate_version(version).unwrap();
+        }
+        for version in ["", "..", "../25.1", "25.1/bin", ".hidden"] {
+            assert!(validate_version(version).is_err(), "{version}");
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Supply-chain check of crates shipped with the plugin: `RustSec` advisories
+//! and licences, configured in the `[audit]` section of `pike.toml`.
+//!
+//! Advisories are matched against `Cargo.lock` by the `rustsec` crate, the advisory
+//! database is fetched into `~/.cargo/advisory-db` the same way `cargo audit` does.
+
+use crate::commands::plugin::sbom::{self, DependencyGraph};
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use rustsec::advisory::{Id, Informational, Severity};
+use rustsec::report::{Report, Settings};
+use rustsec::{Database, Lockfile};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// `[audit]` section of `pike.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Local checkout of the advisory database used instead of fetching it.
+    pub advisory_db: Option<PathBuf>,
+    /// Vulnerabilities of this severity and above fail the audit, all of them by default.
+    pub severity: Option<Severity>,
+    /// Kinds of informational advisories which fail the audit, e.g. `unmaintained`.
+    #[serde(default)]
+    pub informational: Vec<Informational>,
+    /// SPDX identifiers of licences crates must not be shipped under.
+    #[serde(default)]
+    pub deny_licenses: Vec<String>,
+    /// Advisories which are accepted, e.g. `RUSTSEC-2020-0071`.
+    #[serde(default)]
+    pub ignore: Vec<Id>,
+}
+
+impl AuditConfig {
+    fn settings(&self) -> Settings {
+        Settings {
+            severity: self.severity,
+            ignore: self.ignore.clone(),
+            informational_warnings: self.informational.clone(),
+            ..Settings::default()
+        }
+    }
+
+    fn database(&self) -> Result<Database> {
+        if let Some(path) = &self.advisory_db {
+            return Database::open(path).context(format!(
+                "failed to open advisory database in {}",
+                path.display()
+            ));
+        }
+        info!("Fetching RustSec advisory database...");
+        Database::fetch().context("failed to fetch advisory database")
+    }
+}
+
+/// Whether the licence expression allows no licence except the denied ones,
+/// e.g. `MIT OR GPL-3.0` is allowed when only `GPL-3.0` is denied.
+fn is_denied_license(expression: &str, denied: &[String]) -> bool {
+    let normalized = expression.replace('/', " OR ");
+    normalized.split(" OR ").all(|alternative| {
+        alternative
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .any(|id| denied.iter().any(|d| d == id))
+    })
+}
+
+/// Vulnerabilities and informational advisories of the report
+/// as `<crate> <version>: <advisory>` lines.
+fn advisory_problems(report: &Report) -> Vec<String> {
+    let vulnerabilities = report.vulnerabilities.list.iter().map(|vulnerability| {
+        let severity = vulnerability
+            .advisory
+            .cvss
+            .as_ref()
+            .map_or_else(|| "no CVSS".to_string(), |cvss| cvss.severity().to_string());
+        (
+            &vulnerability.package,
+            format!("{} ({severity})", vulnerability.advisory.id),
+            &vulnerability.advisory.title,
+        )
+    });
+    let warnings = report.warnings.values().flatten().filter_map(|warning| {
+        let advisory = warning.advisory.as_ref()?;
+        Some((
+            &warning.package,
+            format!("{} ({})", advisory.id, warning.kind),
+            &advisory.title,
+        ))
+    });
+    vulnerabilities
+        .chain(warnings)
+        .map(|(package, advisory, title)| {
+            format!("{} {}: {advisory}: {title}", package.name, package.version)
+        })
+        .collect()
+}
+
+/// Checks crates shipped with the plugin package in `plugin_dir`, fails
+/// if any of them has an advisory or a licence the config doesn't allow.
+pub fn check(plugin_dir: &Path, package_name: &str, config: &AuditConfig) -> Result<()> {
+    let metadata = sbom::cargo_metadata(plugin_dir)?;
+    let graph = DependencyGraph::new(&metadata, package_name)?;
+    let lockfile_path = metadata.workspace_root.join("Cargo.lock");
+    let mut lockfile = Lockfile::load(&lockfile_path)
+        .context(format!("failed to read {}", lockfile_path.display()))?;
+
+    // Build and dev dependencies and other members of the workspace are not shipped.
+    let shipped: HashSet<(&str, &str)> = graph
+        .dependencies()
+        .map(|package| (package.name.as_str(), package.version.as_str()))
+        .collect();
+    lockfile.packages.retain(|package| {
+        let version = package.version.to_string();
+        shipped.contains(&(package.name.as_str(), version.as_str()))
+    });
+
+    let report = Report::generate(&config.database()?, &lockfile, &config.settings());
+    let mut problems = advisory_problems(&report);
+    for package in graph.dependencies() {
+        if let Some(license) = &package.license {
+            if is_denied_license(license, &config.deny_licenses) {
+                problems.push(format!(
+                    "{} {}: licence {license} is denied",
+                    package.name, package.version
+                ));
+            }
+        }
+    }
+
+    for problem in &problems {
+        warn!("{problem}");
+    }
+    if !problems.is_empty() {
+        bail!(
+            "audit of plugin '{package_name}' failed, {} problem(s) found",
+            problems.len()
+        );
+    }
+    info!(
+        "Audit of plugin '{package_name}' passed: {} crate(s)",
+        lockfile.packages.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::fs;
+
+    const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "smallvec"
+version = "1.6.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "ansi_term"
+version = "0.12.1"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    fn advisory(id: &str, package: &str, extra: &str) -> String {
+        format!(
+            r#"```toml
+[advisory]
+id = "{id}"
+package = "{package}"
+date = "2021-01-08"
+{extra}
+
+[versions]
+patched = [">= 1.6.1"]
+```
+
+# Advisory of {package}
+"#
+        )
+    }
+
+    #[test]
+    fn report_problems_follow_config() {
+        let workspace = TempWorkspace::new("plugin-audit-db").unwrap();
+        let db_dir = workspace.path();
+        for (package, id, extra) in [
+            (
+                "smallvec",
+                "RUSTSEC-2021-0003",
+                r#"cvss = "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:L""#,
+            ),
+            (
+                "ansi_term",
+                "RUSTSEC-2021-0139",
+                r#"informational = "unmaintained""#,
+            ),
+        ] {
+            let dir = db_dir.join("crates").join(package);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("{id}.md")), advisory(id, package, extra)).unwrap();
+        }
+        let database = Database::open(db_dir).unwrap();
+        let lockfile: Lockfile = LOCKFILE.parse().unwrap();
+        let problems = |config: AuditConfig| {
+            advisory_problems(&Report::generate(&database, &lockfile, &config.settings()))
+        };
+
+        assert_eq!(
+            problems(AuditConfig::default()),
+            ["smallvec 1.6.0: RUSTSEC-2021-0003 (medium): Advisory of smallvec"]
+        );
+        let config: AuditConfig = toml::from_str(
+            r#"
+severity = "high"
+informational = ["unmaintained"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            problems(config),
+            ["ansi_term 0.12.1: RUSTSEC-2021-0139 (unmaintained): Advisory of ansi_term"]
+        );
+        let config: AuditConfig = toml::from_str(r#"ignore = ["RUSTSEC-2021-0003"]"#).unwrap();
+        assert!(problems(config).is_empty());
+    }
+
+    #[test]
+    fn license_is_denied_without_allowed_alternative() {
+        let denied = vec!["GPL-3.0".to_string(), "AGPL-3.0".to_string()];
+        assert!(is_denied_license("GPL-3.0", &denied));
+        assert!(is_denied_license("MIT AND GPL-3.0", &denied));
+        assert!(is_denied_license("(GPL-3.0 OR AGPL-3.0)", &denied));
+        assert!(!is_denied_license("MIT OR GPL-3.0", &denied));
+        assert!(!is_denied_license("MIT/Apache-2.0", &denied));
+        assert!(!is_denied_license("GPL-3.0-only", &denied));
+    }
+}
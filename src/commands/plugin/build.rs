@@ -1,13 +1,18 @@
-use std::path::PathBuf;
+use std::path::Path;
 
-use crate::commands::lib::{cargo_build, BuildType};
+use crate::commands::lib::{cargo_build, BuildType, CargoArgs};
 use anyhow::{Context, Result};
 
-pub fn cmd(release: bool, target_dir: &PathBuf, plugin_path: &PathBuf) -> Result<()> {
+pub fn cmd(
+    release: bool,
+    target_dir: &Path,
+    plugin_path: &Path,
+    cargo_args: &CargoArgs,
+) -> Result<()> {
     let build_type = if release {
         BuildType::Release
     } else {
         BuildType::Debug
     };
-    cargo_build(build_type, target_dir, plugin_path).context("building of plugin")
+    cargo_build(build_type, target_dir, plugin_path, cargo_args).context("building of plugin")
 }
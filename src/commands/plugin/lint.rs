@@ -0,0 +1,438 @@
+//! Checks of `manifest.yaml.template` of the plugin before it is built.
+//!
+//! The template is rendered the same way the build script of the plugin does
+//! and the result is checked against the plugin manifest schema of picodata
+//! and against the crate: name and version must match `Cargo.toml`, listed
+//! migrations must exist and declared services must be registered in the code.
+
+use super::verify::{Report, Status};
+use crate::commands::lib::OutputFormat;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+
+const MANIFEST_TEMPLATE_NAME: &str = "manifest.yaml.template";
+
+/// Registration of the service with a literal name, e.g. `reg.add("weather_service", ...)`.
+const SERVICE_REGISTRATION: &str = r#"\.add\s*\(\s*"([^"]+)""#;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+/// Plugin manifest schema of picodata.
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Manifest {
+    name: String,
+    version: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    services: Vec<ManifestService>,
+    #[serde(default)]
+    migration: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct ManifestService {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    default_configuration: serde_norway::Value,
+}
+
+/// Plugin crates of the project: the crate itself or members of the workspace
+/// which have a manifest template.
+fn plugin_dirs(plugin_path: &Path) -> Result<Vec<PathBuf>> {
+    let cargo_toml = read_cargo_toml(plugin_path)?;
+    let Some(workspace) = cargo_toml.get("workspace") else {
+        return Ok(vec![plugin_path.to_path_buf()]);
+    };
+    Ok(workspace
+        .get("members")
+        .and_then(|members| members.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.as_str())
+        .map(|member| plugin_path.join(member))
+        .filter(|member| member.join(MANIFEST_TEMPLATE_NAME).exists())
+        .collect())
+}
+
+fn read_cargo_toml(dir: &Path) -> Result<DocumentMut> {
+    let path = dir.join("Cargo.toml");
+    fs::read_to_string(&path)
+        .context(format!("failed to read {}", path.display()))?
+        .parse()
+        .context(format!("failed to parse {}", path.display()))
+}
+
+/// Migrations passed to the template by the build script of the plugin.
+fn migrations(plugin_dir: &Path) -> Result<Vec<String>> {
+    let migrations_dir = plugin_dir.join("migrations");
+    if !migrations_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut migrations = vec![];
+    for entry in fs::read_dir(&migrations_dir)
+        .context(format!("failed to read {}", migrations_dir.display()))?
+    {
+        let entry = entry.context(format!("failed to read {}", migrations_dir.display()))?;
+        migrations.push(format!(
+            "migrations/{}",
+            entry.file_name().to_string_lossy()
+        ));
+    }
+    migrations.sort();
+    Ok(migrations)
+}
+
+/// Names of services registered in the sources of the plugin.
+fn registered_services(
+    dir: &Path,
+    registration: &Regex,
+    services: &mut BTreeSet<String>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir).context(format!("failed to read {}", dir.display()))? {
+        let path = entry
+            .context(format!("failed to read {}", dir.display()))?
+            .path();
+        if path.is_dir() {
+            registered_services(&path, registration, services)?;
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            let source =
+                fs::read_to_string(&path).context(format!("failed to read {}", path.display()))?;
+            services.extend(
+                registration
+                    .captures_iter(&source)
+                    .map(|captures| captures[1].to_string()),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines)]
+fn lint_plugin(plugin_dir: &Path, report: &mut Report) -> Result<()> {
+    let cargo_toml = read_cargo_toml(plugin_dir)?;
+    let package = |key: &str| {
+        cargo_toml
+            .get("package")
+            .and_then(|package| package.get(key))
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    };
+    let (Some(package_name), Some(package_version)) = (package("name"), package("version")) else {
+        bail!(
+            "{} has no package name or version",
+            plugin_dir.join("Cargo.toml").display()
+        );
+    };
+    let plugin = Some(package_name.as_str());
+
+    let template_path = plugin_dir.join(MANIFEST_TEMPLATE_NAME);
+    let template = match fs::read_to_string(&template_path) {
+        Ok(template) => template,
+        Err(err) => {
+            report.error(
+                "template",
+                plugin,
+                format!("failed to read {MANIFEST_TEMPLATE_NAME}: {err}"),
+            );
+            return Ok(());
+        }
+    };
+    let migrations = migrations(plugin_dir)?;
+    let mut env = minijinja::Environment::new();
+    let rendered = env
+        .add_template("manifest", &template)
+        .and_then(|()| env.get_template("manifest"))
+        .and_then(|template| {
+            template.render(minijinja::context! {
+                version => package_version,
+                migrations => migrations,
+            })
+        });
+    let rendered = match rendered {
+        Ok(rendered) => {
+            report.ok("template", plugin, "");
+            rendered
+        }
+        Err(err) => {
+            report.error("template", plugin, format!("failed to render: {err}"));
+            return Ok(());
+        }
+    };
+
+    let mut unknown_fields = vec![];
+    let manifest: Result<Manifest, _> =
+        serde_ignored::deserialize(serde_norway::Deserializer::from_str(&rendered), |field| {
+            unknown_fields.push(field.to_string());
+        });
+    let manifest = match manifest {
+        Ok(manifest) => {
+            report.ok("manifest", plugin, "");
+            manifest
+        }
+        Err(err) => {
+            report.error(
+                "manifest",
+                plugin,
+                format!("rendered manifest doesn't match the schema: {err}"),
+            );
+            return Ok(());
+        }
+    };
+    for field in unknown_fields {
+        report.warning("manifest", plugin, format!("unknown field {field}"));
+    }
+
+    if manifest.name == package_name {
+        report.ok("name", plugin, "");
+    } else {
+        report.error(
+            "name",
+            plugin,
+            format!(
+                "manifest declares plugin {}, but the package is {package_name}",
+                manifest.name
+            ),
+        );
+    }
+    if manifest.version == package_version {
+        report.ok("version", plugin, "");
+    } else {
+        report.error(
+            "version",
+            plugin,
+            format!(
+                "manifest declares version {}, but Cargo.toml has {package_version}",
+                manifest.version
+            ),
+        );
+    }
+
+    let missing: Vec<&str> = manifest
+        .migration
+        .iter()
+        .filter(|migration| !plugin_dir.join(migration).is_file())
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        report.ok("migrations", plugin, "");
+    } else {
+        report.error(
+            "migrations",
+            plugin,
+            format!("listed in manifest, but missing: {}", missing.join(", ")),
+        );
+    }
+
+    let mut registered = BTreeSet::new();
+    let src_dir = plugin_dir.join("src");
+    if src_dir.exists() {
+        let registration = Regex::new(SERVICE_REGISTRATION).expect("valid regex");
+        registered_services(&src_dir, &registration, &mut registered)?;
+    }
+    let declared: BTreeSet<String> = manifest
+        .services
+        .iter()
+        .map(|service| service.name.clone())
+        .collect();
+    if registered.is_empty() {
+        report.skipped(
+            "services",
+            plugin,
+            "no services registered with literal names are found in src",
+        );
+    } else if declared == registered {
+        report.ok("services", plugin, "");
+    } else {
+        let list = |names: Vec<&String>| {
+            names
+                .into_iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut problems = vec![];
+        let undeclared = registered.difference(&declared).collect::<Vec<_>>();
+        if !undeclared.is_empty() {
+            problems.push(format!(
+                "registered, but not declared: {}",
+                list(undeclared)
+            ));
+        }
+        let unregistered = declared.difference(&registered).collect::<Vec<_>>();
+        if !unregistered.is_empty() {
+            problems.push(format!(
+                "declared, but not registered: {}",
+                list(unregistered)
+            ));
+        }
+        report.error("services", plugin, problems.join("; "));
+    }
+    Ok(())
+}
+
+/// Lints manifest templates of the plugin or of all plugins of the workspace.
+pub fn lint(plugin_path: &Path) -> Result<Report> {
+    let mut report = Report::default();
+    for plugin_dir in plugin_dirs(plugin_path)? {
+        lint_plugin(&plugin_dir, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Lints the plugin before it is packed, only problems are printed.
+pub fn check(plugin_path: &Path) -> Result<()> {
+    let report = lint(plugin_path)?;
+    for diagnostic in &report.diagnostics {
+        if diagnostic.status == Status::Warning {
+            warn!("{diagnostic}");
+        }
+    }
+    if !report.is_ok() {
+        report.log();
+        bail!(
+            "manifest template has {} error(s), see `cargo pike plugin lint`",
+            report.errors()
+        );
+    }
+    Ok(())
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let report = lint(&params.plugin_path)?;
+    match params.output {
+        OutputFormat::Text => report.log(),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    if !report.is_ok() {
+        bail!("lint failed with {} error(s)", report.errors());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    const TEMPLATE: &str = "name: weather_cache
+description: A plugin for picodata
+version: {{ version }}
+services:
+  - name: weather_service
+    description: weather service
+    default_configuration:
+      ttl: 60
+migration:
+{% for migration in migrations -%}
+- {{ migration }}
+{% endfor -%}
+";
+
+    fn write_plugin(dir: &Path, template: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("migrations")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"weather_cache\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join(MANIFEST_TEMPLATE_NAME), template).unwrap();
+        fs::write(dir.join("migrations/0001_init.sql"), "").unwrap();
+        fs::write(
+            dir.join("src/lib.rs"),
+            "fn registrar(reg: &mut ServiceRegistry) {\n    reg.add(\"weather_service\", VERSION, Service::default);\n}\n",
+        )
+        .unwrap();
+    }
+
+    fn errors(report: &Report) -> Vec<(&str, &str)> {
+        report
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.status == Status::Error)
+            .map(|diagnostic| (diagnostic.check.as_str(), diagnostic.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_template_passes() {
+        let dir = TempWorkspace::new("plugin-lint-valid").unwrap();
+        write_plugin(dir.path(), TEMPLATE);
+        let report = lint(dir.path()).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.check.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "template",
+                "manifest",
+                "name",
+                "version",
+                "migrations",
+                "services"
+            ]
+        );
+    }
+
+    #[test]
+    fn template_problems_are_reported() {
+        let dir = TempWorkspace::new("plugin-lint-problems").unwrap();
+        let template = TEMPLATE
+            .replace("{{ version }}", "0.2.0")
+            .replace("weather_service", "forecast_service")
+            .replace("{% for", "- migrations/0002_gone.sql\n{% for")
+            + "extra: 1\n";
+        write_plugin(dir.path(), &template);
+        let report = lint(dir.path()).unwrap();
+        assert_eq!(
+            errors(&report),
+            [
+                (
+                    "version",
+                    "manifest declares version 0.2.0, but Cargo.toml has 0.1.0"
+                ),
+                (
+                    "migrations",
+                    "listed in manifest, but missing: migrations/0002_gone.sql"
+                ),
+                (
+                    "services",
+                    "registered, but not declared: weather_service; \
+                    declared, but not registered: forecast_service"
+                ),
+            ]
+        );
+        assert!(report.diagnostics.iter().any(|diagnostic| {
+            diagnostic.status == Status::Warning && diagnostic.message == "unknown field extra"
+        }));
+
+        write_plugin(dir.path(), "name: [weather_cache");
+        let report = lint(dir.path()).unwrap();
+        assert_eq!(errors(&report)[0].0, "manifest");
+
+        write_plugin(dir.path(), "{% for %}");
+        let report = lint(dir.path()).unwrap();
+        assert_eq!(errors(&report)[0].0, "template");
+    }
+}
@@ -1,3 +1,8 @@
+pub(crate) mod audit;
 pub(crate) mod build;
+pub(crate) mod lint;
 pub(crate) mod new;
 pub(crate) mod pack;
+pub(crate) mod publish;
+pub(crate) mod sbom;
+pub(crate) mod verify;
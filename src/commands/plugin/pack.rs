@@ -1,13 +1,25 @@
-use crate::commands::lib::{cargo_build, BuildType, LIB_EXT};
+use crate::commands::config::{apply::read_config_from_path, schema};
+use crate::commands::lib::archive::{ArchiveFormat, ArchiveWriter, CompressionLevel};
+use crate::commands::lib::compat;
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::ignore::{walk, IgnoreList, IGNORE_FILE_NAME};
+use crate::commands::lib::integrity;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::platform;
+use crate::commands::lib::timings::Timings;
+use crate::commands::lib::{cargo_build, BuildType, CargoArgs, OutputFormat, LIB_EXT};
+use crate::commands::plugin::audit;
+use crate::commands::plugin::lint;
+use crate::commands::plugin::sbom;
 use anyhow::{anyhow, bail, Context, Result};
-use flate2::write::GzEncoder;
-use flate2::Compression;
+use derive_builder::Builder;
 use log::{debug, info, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs::File;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::{env, fs};
-use tar::Builder;
 use toml_edit::DocumentMut;
 
 #[derive(Deserialize)]
@@ -21,6 +33,78 @@ struct CargoManifest {
     package: PackageInfo,
 }
 
+/// Archive created by `plugin pack`, printed with `--output json`.
+#[derive(Debug, Serialize)]
+pub struct PackedArchive {
+    pub name: String,
+    pub version: String,
+    /// Absolute path of the archive.
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    /// Suffix of the default archive name, `None` if `--archive-name` is set.
+    pub os_suffix: Option<String>,
+}
+
+impl PackedArchive {
+    fn new(name: String, version: String, path: &Path, os_suffix: Option<String>) -> Result<Self> {
+        let mut file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(Self {
+            name,
+            version,
+            path: std::path::absolute(path)?,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            os_suffix,
+        })
+    }
+}
+
+/// Result of `plugin pack`, workspaces produce an archive for every member plugin.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Packed {
+    Plugin(PackedArchive),
+    Workspace(Vec<PackedArchive>),
+}
+
+#[allow(dead_code)]
+impl Packed {
+    /// All created archives.
+    #[must_use]
+    pub fn archives(&self) -> &[PackedArchive] {
+        match self {
+            Self::Plugin(archive) => std::slice::from_ref(archive),
+            Self::Workspace(archives) => archives,
+        }
+    }
+}
+
+/// Patterns of `--exclude` and `--include` flags matched against the content of the archive
+/// relative to the plugin root in it, e.g. `migrations/0002_fixtures.sql` or `fixtures/`.
+///
+/// Library, manifest and config schema of the plugin are always packed.
+#[derive(Clone, Debug, Default)]
+pub struct ContentFilter {
+    /// Entries which are not packed, in addition to `.pikeignore` for assets.
+    pub exclude: Vec<String>,
+    /// Entries which are packed even if excluded by `exclude` or `.pikeignore`.
+    pub include: Vec<String>,
+}
+
+impl ContentFilter {
+    /// Appends patterns of the filter to `ignore`, `include` patterns win as they go last.
+    fn apply_to(&self, mut ignore: IgnoreList) -> Result<IgnoreList> {
+        ignore.extend(&self.exclude)?;
+        ignore.extend(self.include.iter().map(|pattern| format!("!{pattern}")))?;
+        Ok(ignore)
+    }
+}
+
 /// Validate that pre-built plugin shipping directory contains required files
 /// Required: manifest.yaml and `lib{normalized_package_name}.{LIB_EXT}`
 fn validate_plugin_build_tree(
@@ -55,13 +139,237 @@ fn validate_plugin_build_tree(
     Ok(())
 }
 
-pub fn cmd(
-    pack_debug: bool,
-    target_dir: &PathBuf,
-    plugin_path: &PathBuf,
-    no_build: bool,
-    archive_name: Option<&PathBuf>,
+/// Returns the reason why staged build output of the package version is stale,
+/// or `None` if it matches the version.
+///
+/// Library of the last built version is a symlink into the cargo output directory,
+/// libraries of the previously built versions are copied, see `helpers::build`.
+fn stale_build_reason(build_root: &Path, package_name: &str, version: &str) -> Option<String> {
+    let package_build_dir = build_root.join(package_name);
+    let lib_name = format!("lib{}.{LIB_EXT}", package_name.replace('-', "_"));
+
+    let mut staged_versions: Vec<String> = fs::read_dir(&package_build_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .collect();
+    staged_versions.sort();
+
+    if !staged_versions.iter().any(|staged| staged == version) {
+        if staged_versions.is_empty() {
+            return Some("not built".to_string());
+        }
+        return Some(format!(
+            "not built, staged versions: {}",
+            staged_versions.join(", ")
+        ));
+    }
+
+    let version_dir = package_build_dir.join(version);
+    let manifest_version = fs::read_to_string(version_dir.join("manifest.yaml"))
+        .ok()
+        .and_then(|manifest| serde_norway::from_str::<serde_norway::Value>(&manifest).ok())
+        .and_then(|manifest| {
+            manifest
+                .get("version")
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        });
+    if let Some(manifest_version) = manifest_version.filter(|v| v != version) {
+        return Some(format!(
+            "staged manifest.yaml has version {manifest_version}"
+        ));
+    }
+
+    let is_copied_lib = fs::symlink_metadata(version_dir.join(&lib_name))
+        .is_ok_and(|metadata| !metadata.file_type().is_symlink());
+    if is_copied_lib {
+        let last_built = staged_versions
+            .iter()
+            .find(|staged| package_build_dir.join(staged).join(&lib_name).is_symlink());
+        return Some(match last_built {
+            Some(last_built) => format!("last built version is {last_built}"),
+            None => "staged library is not from the last build".to_string(),
+        });
+    }
+
+    None
+}
+
+/// Checks that staged `<member>/<version>` build output of every workspace plugin
+/// matches the version in its `Cargo.toml`.
+fn validate_workspace_build_tree(
+    build_root: &Path,
+    member_paths: &[(&str, PathBuf)],
+    build_type: BuildType,
 ) -> Result<()> {
+    let mut stale_members = vec![];
+    let mut stale_packages = vec![];
+    for (_, member_path) in member_paths {
+        let version = get_latest_plugin_version(member_path)?;
+        let cargo_manifest: CargoManifest = toml::from_str(
+            &fs::read_to_string(member_path.join("Cargo.toml"))
+                .context("failed to read Cargo.toml for packaging")?,
+        )
+        .context("failed to parse Cargo.toml for packaging")?;
+        let package_name = cargo_manifest.package.name;
+
+        if let Some(reason) = stale_build_reason(build_root, &package_name, &version) {
+            stale_members.push(format!("  - {package_name} {version}: {reason}"));
+            stale_packages.push(format!("-p {package_name}"));
+        }
+    }
+
+    if stale_members.is_empty() {
+        return Ok(());
+    }
+
+    let release_flag = match build_type {
+        BuildType::Release => " --release",
+        BuildType::Debug => "",
+    };
+    bail!(
+        "build output of workspace plugins in {} does not match their versions:\n{}\n\
+        Rebuild them with `cargo build{release_flag} {}`",
+        build_root.display(),
+        stale_members.join("\n"),
+        stale_packages.join(" ")
+    )
+}
+
+/// Resolves schema of the plugin services config.
+///
+/// Schema declared by plugin author in [`schema::SCHEMA_FILE_NAME`] takes precedence
+/// over the one derived from default configuration in built `manifest.yaml`.
+/// If `plugin_config.yaml` exists in the plugin directory, it is validated against the schema.
+/// Mismatch with the declared schema fails the pack, mismatch with the derived one is only
+/// reported as a warning, since the derived schema is a guess.
+fn resolve_config_schema(plugin_dir: &Path, plugin_build_dir: &Path) -> Result<serde_json::Value> {
+    let declared_schema_path = plugin_dir.join(schema::SCHEMA_FILE_NAME);
+    let is_declared = declared_schema_path.exists();
+    let config_schema = if is_declared {
+        info!(
+            "Using plugin config schema from {}",
+            declared_schema_path.display()
+        );
+        schema::load(&declared_schema_path)?
+    } else {
+        schema::derive_from_manifest(&plugin_build_dir.join("manifest.yaml"))
+            .context("failed to derive plugin config schema from manifest")?
+    };
+
+    let plugin_config_path = plugin_dir.join("plugin_config.yaml");
+    if plugin_config_path.exists() {
+        let plugin_config = read_config_from_path(&plugin_config_path)?;
+        let result = schema::validate_config(&config_schema, &plugin_config).with_context(|| {
+            format!(
+                "failed to validate plugin config at {}",
+                plugin_config_path.display()
+            )
+        });
+        match result {
+            Err(err) if !is_declared => warn!("{err:#}"),
+            result => result?,
+        }
+    }
+
+    Ok(config_schema)
+}
+
+#[derive(Debug, Builder)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Params {
+    /// Pack debug build of the plugin instead of the release one.
+    #[builder(default)]
+    debug: bool,
+    #[builder(default = "env_or(\"target-dir\", PathBuf::from(\"target\"))")]
+    target_dir: PathBuf,
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
+    plugin_path: PathBuf,
+    #[builder(default)]
+    no_build: bool,
+    /// Don't lint manifest template before packing.
+    #[builder(default)]
+    no_lint: bool,
+    /// Flags passed through to `cargo build` of the plugin.
+    #[builder(default)]
+    cargo_args: CargoArgs,
+    #[builder(default)]
+    archive_name: Option<PathBuf>,
+    #[builder(default)]
+    format: ArchiveFormat,
+    #[builder(default)]
+    compression: CompressionLevel,
+    /// Make archive byte-identical for the same sources, see [`ArchiveWriter::reproducible`].
+    #[builder(default)]
+    reproducible: bool,
+    /// OS suffix of the default archive name, detected if not set.
+    #[builder(default)]
+    os_suffix: Option<String>,
+    #[builder(default)]
+    filter: ContentFilter,
+    #[builder(default)]
+    timings: bool,
+    #[builder(default)]
+    output: OutputFormat,
+    /// Put `CycloneDX` SBOM of the plugin into the archive and next to it.
+    #[builder(default)]
+    sbom: bool,
+    /// Check dependencies of the plugin against advisories and licences
+    /// configured in the `[audit]` section of `pike.toml`.
+    #[builder(default)]
+    audit: bool,
+    /// Minisign secret key to sign the archive with, the signature is saved
+    /// next to the archive as `<archive>.minisig`.
+    #[builder(default)]
+    sign_key: Option<PathBuf>,
+    /// Picodata the plugin is checked against, the check is skipped
+    /// if it isn't installed.
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
+    picodata_path: PathBuf,
+    /// Don't check picodata against pike and `picodata-plugin` of the plugin.
+    #[builder(default)]
+    skip_compat_check: bool,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let mut timings = Timings::default();
+    let packed = pack(params, &mut timings)?;
+
+    match params.output {
+        OutputFormat::Text => {
+            if params.timings {
+                print!("{timings}");
+            }
+        }
+        OutputFormat::Json => {
+            // Stdout is kept for the JSON document only.
+            if params.timings {
+                eprint!("{timings}");
+            }
+            let json =
+                serde_json::to_string_pretty(&packed).context("failed to serialize pack result")?;
+            println!("{json}");
+        }
+    }
+    Ok(())
+}
+
+/// Builds the plugin unless `no_build` is set and packs it, see [`cmd`].
+pub(crate) fn pack(params: &Params, timings: &mut Timings) -> Result<Packed> {
+    let Params {
+        debug: pack_debug,
+        target_dir,
+        plugin_path,
+        no_build,
+        no_lint,
+        archive_name,
+        cargo_args,
+        ..
+    } = params;
+
     let current_dir = env::current_dir().context("failed to get current working directory")?;
     let root_dir = if plugin_path.is_absolute() {
         plugin_path.clone()
@@ -73,16 +381,27 @@ pub fn cmd(
         bail!("No Cargo.toml found at plugin path: {}", root_dir.display());
     }
 
-    let build_type = if pack_debug {
+    if !*no_lint {
+        lint::check(&root_dir)?;
+    }
+
+    if !params.skip_compat_check {
+        compat::check_with_binary(&root_dir, &params.picodata_path)?;
+    }
+
+    let build_type = if *pack_debug {
         BuildType::Debug
     } else {
         BuildType::Release
     };
 
-    if no_build {
+    if *no_build {
         info!("--no-build: skipping cargo build for plugin pack");
     } else {
-        cargo_build(build_type, target_dir, plugin_path)
+        timings
+            .measure("cargo build", || {
+                cargo_build(build_type, target_dir, plugin_path, cargo_args)
+            })
             .with_context(|| format!("building {build_type} version of plugin"))?;
     }
 
@@ -110,7 +429,7 @@ pub fn cmd(
             );
         }
 
-        let mut packaged_any = false;
+        let mut member_paths = vec![];
         if let Some(members) = workspace.get("members").and_then(|m| m.as_array()) {
             for member in members {
                 let Some(member_str) = member.as_str() else {
@@ -119,9 +438,7 @@ pub fn cmd(
                 };
                 let member_path = root_dir.join(member_str);
                 if member_path.join("manifest.yaml.template").exists() {
-                    info!("Packing workspace member plugin: {}", member_path.display());
-                    create_plugin_archive(&build_root, &member_path, None)?;
-                    packaged_any = true;
+                    member_paths.push((member_str, member_path));
                 } else {
                     debug!(
                         "Workspace member {} has no manifest.yaml.template — skipping",
@@ -130,22 +447,40 @@ pub fn cmd(
                 }
             }
         }
-        if !packaged_any {
+
+        // Members share the target directory, so build output of one version
+        // must not be packed under the version of another one.
+        validate_workspace_build_tree(&build_root, &member_paths, build_type)?;
+
+        let mut archives = vec![];
+        for (member_str, member_path) in member_paths {
+            info!("Packing workspace member plugin: {}", member_path.display());
+            archives.push(timings.measure(format!("{member_str} archive"), || {
+                create_plugin_archive(&build_root, &member_path, None, params)
+            })?);
+        }
+        if archives.is_empty() {
             warn!(
                 "No workspace members produced plugin archives (no manifest.yaml.template found)."
             );
         }
-        return Ok(());
+        return Ok(Packed::Workspace(archives));
     }
 
-    create_plugin_archive(&build_root, &root_dir, archive_name)
+    timings
+        .measure("archive", || {
+            create_plugin_archive(&build_root, &root_dir, archive_name.as_ref(), params)
+        })
+        .map(Packed::Plugin)
 }
 
+#[allow(clippy::too_many_lines)]
 fn create_plugin_archive(
     build_dir: &Path,
     plugin_dir: &Path,
     archive_name: Option<&PathBuf>,
-) -> Result<()> {
+    params: &Params,
+) -> Result<PackedArchive> {
     let plugin_version = get_latest_plugin_version(plugin_dir)?;
     let cargo_manifest: CargoManifest = toml::from_str(
         &fs::read_to_string(plugin_dir.join("Cargo.toml"))
@@ -159,12 +494,24 @@ fn create_plugin_archive(
     let root_in_archive = Path::new(&package_name).join(&plugin_version);
 
     validate_plugin_build_tree(&plugin_build_dir, &normalized_package_name)?;
+    if params.audit {
+        let config = PikeConfig::load(&params.plugin_path)?;
+        audit::check(plugin_dir, &package_name, &config.audit)?;
+    }
+    let config_schema = resolve_config_schema(plugin_dir, &plugin_build_dir)?;
+    let sbom = if params.sbom {
+        Some(generate_sbom(plugin_dir, &package_name)?)
+    } else {
+        None
+    };
 
-    let compressed_file_path = resolve_archive_path(
+    let (compressed_file_path, os_suffix) = resolve_archive_path(
         build_dir,
         archive_name,
         &package_name,
         &cargo_manifest.package.version,
+        params.os_suffix.as_deref(),
+        params.format,
     )?;
 
     if !plugin_build_dir.exists() {
@@ -191,55 +538,79 @@ fn create_plugin_archive(
         )
     })?;
 
-    let compressed_file =
-        File::create(&compressed_file_path).context("failed to create archive file")?;
-    let mut encoder = GzEncoder::new(compressed_file, Compression::best());
-
+    let mut archive =
+        ArchiveWriter::create(&compressed_file_path, params.format, params.compression)?;
+    if params.reproducible {
+        archive = archive.reproducible();
+    }
     {
-        let mut tarball = Builder::new(&mut encoder);
-
         let lib_name = format!("lib{normalized_package_name}.{LIB_EXT}");
         archive_if_exists(
             &root_in_archive,
             &plugin_build_dir.join(&lib_name),
-            &mut tarball,
+            &mut archive,
         )?;
         archive_if_exists(
             &root_in_archive,
             &plugin_build_dir.join("manifest.yaml"),
-            &mut tarball,
-        )?;
-        archive_if_exists(
-            &root_in_archive,
-            &plugin_build_dir.join("migrations"),
-            &mut tarball,
+            &mut archive,
         )?;
 
+        let migrations_dir = plugin_build_dir.join("migrations");
+        if migrations_dir.exists() {
+            let ignore = params.filter.apply_to(IgnoreList::default())?;
+            archive_dir(
+                &root_in_archive,
+                &migrations_dir,
+                Path::new("migrations"),
+                &ignore,
+                &mut archive,
+            )?;
+        }
+
+        archive_config_schema(&root_in_archive, &config_schema, &mut archive)?;
+        if let Some(sbom) = &sbom {
+            archive_data(
+                &root_in_archive.join(sbom::SBOM_FILE_NAME),
+                sbom,
+                &mut archive,
+            )
+            .context("failed to append SBOM")?;
+        }
+
         let assets_dir = plugin_build_dir.join("assets");
         if assets_dir.exists() {
-            for entry in fs::read_dir(&assets_dir)
-                .with_context(|| format!("reading assets dir {}", assets_dir.display()))?
-            {
-                let entry = entry?;
-                archive_if_exists(
-                    &root_in_archive,
-                    &assets_dir.join(entry.file_name()),
-                    &mut tarball,
-                )?;
-            }
+            let ignore = params
+                .filter
+                .apply_to(IgnoreList::from_file(&plugin_dir.join(IGNORE_FILE_NAME))?)?;
+            archive_dir(
+                &root_in_archive,
+                &assets_dir,
+                Path::new(""),
+                &ignore,
+                &mut archive,
+            )?;
         }
-
-        tarball
-            .finish()
-            .context("failed to finish building tar archive")?;
     }
-
-    encoder
-        .try_finish()
-        .context("failed to finish compression")?;
+    archive.finish()?;
 
     info!("Archive created: {}", compressed_file_path.display());
-    Ok(())
+    if let Some(sbom) = &sbom {
+        save_sbom(&compressed_file_path, sbom)?;
+    }
+    let packed = PackedArchive::new(
+        package_name,
+        cargo_manifest.package.version,
+        &compressed_file_path,
+        os_suffix,
+    )?;
+    let checksum_path = integrity::write_checksum(&compressed_file_path, &packed.sha256)?;
+    info!("Checksum created: {}", checksum_path.display());
+    if let Some(sign_key) = &params.sign_key {
+        let signature_path = integrity::sign(&compressed_file_path, sign_key)?;
+        info!("Signature created: {}", signature_path.display());
+    }
+    Ok(packed)
 }
 
 fn resolve_archive_path(
@@ -247,17 +618,25 @@ fn resolve_archive_path(
     archive_name: Option<&PathBuf>,
     package_name: &str,
     package_version: &str,
-) -> Result<PathBuf> {
+    os_suffix: Option<&str>,
+    format: ArchiveFormat,
+) -> Result<(PathBuf, Option<String>)> {
     if let Some(name) = archive_name {
         // Create path with user-specified archive name.
-        create_archive_path(build_dir, name)
+        Ok((create_archive_path(build_dir, name, format)?, None))
     } else {
         // Generate path with OS suffix.
-        generate_archive_path(build_dir, package_name, package_version)
+        let (path, os_suffix) =
+            generate_archive_path(build_dir, package_name, package_version, os_suffix, format)?;
+        Ok((path, Some(os_suffix)))
     }
 }
 
-fn create_archive_path(build_dir: &Path, archive_name: &Path) -> Result<PathBuf> {
+fn create_archive_path(
+    build_dir: &Path,
+    archive_name: &Path,
+    format: ArchiveFormat,
+) -> Result<PathBuf> {
     let mut dest = if archive_name.is_absolute() {
         archive_name.to_path_buf()
     } else {
@@ -274,8 +653,9 @@ fn create_archive_path(build_dir: &Path, archive_name: &Path) -> Result<PathBuf>
         })?
         .to_string_lossy()
         .to_string();
-    if !name.ends_with(".tar.gz") {
-        dest.set_file_name(format!("{name}.tar.gz"));
+    let extension = format.extension();
+    if !name.ends_with(&format!(".{extension}")) {
+        dest.set_file_name(format!("{name}.{extension}"));
     }
     Ok(dest)
 }
@@ -284,131 +664,16 @@ fn generate_archive_path(
     build_dir: &Path,
     package_name: &str,
     package_version: &str,
-) -> Result<PathBuf> {
+    os_suffix: Option<&str>,
+    format: ArchiveFormat,
+) -> Result<(PathBuf, String)> {
     // Default archive name with OS suffix.
-    let os_suffix = detect_os_suffix().context("failed to detect OS for archive naming")?;
-    let archive_filename = format!("{package_name}_{package_version}-{os_suffix}.tar.gz");
-    Ok(build_dir.join(archive_filename))
-}
-
-// ---------------- OS detection (per target) ----------------
-
-#[cfg(any(target_os = "linux", target_os = "freebsd"))]
-fn detect_os_suffix() -> Result<String> {
-    detect_linux_freebsd_os_suffix()
-}
-
-#[cfg(target_os = "macos")]
-fn detect_os_suffix() -> Result<String> {
-    detect_macos_os_suffix()
-}
-
-#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
-fn detect_os_suffix() -> Result<String> {
-    bail!("unsupported operating system for packing plugin (supported: linux, macos)");
-}
-
-#[cfg(any(target_os = "linux", target_os = "freebsd"))]
-fn detect_linux_freebsd_os_suffix() -> Result<String> {
-    const OS_RELEASE: &str = "/etc/os-release";
-    const ROLLING_DISTROS: &[&str] = &[
-        "arch",
-        "cachyos",
-        "gentoo",
-        "void",
-        "opensuse-tumbleweed",
-        "artix",
-        "manjaro",
-        "endeavouros",
-        "garuda",
-        "kaos",
-    ];
-
-    let content = fs::read_to_string(OS_RELEASE)
-        .with_context(|| format!("failed to read {OS_RELEASE} for determining OS"))?;
-
-    let mut id: Option<String> = None;
-    let mut version_id: Option<String> = None;
-    let mut version_codename: Option<String> = None;
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        if let Some((k, v)) = line.split_once('=') {
-            let key = k.trim();
-            let mut val = v.trim().trim_matches('"').trim_matches('\'').to_string();
-            if key.eq_ignore_ascii_case("ID") {
-                id = Some(val.to_ascii_lowercase());
-            } else if key.eq_ignore_ascii_case("VERSION_ID") {
-                val = val.replace(' ', "_").to_ascii_lowercase();
-                version_id = Some(val);
-            } else if key.eq_ignore_ascii_case("VERSION_CODENAME") {
-                version_codename = Some(val.to_ascii_lowercase());
-            }
-        }
-    }
-
-    let id = id.ok_or_else(|| anyhow!("ID not found in /etc/os-release"))?;
-
-    let variant = if let Some(vid) = version_id {
-        if vid.is_empty() {
-            resolve_linux_variant(&id, ROLLING_DISTROS)
-        } else {
-            vid
-        }
-    } else if let Some(code) = version_codename {
-        if code.is_empty() {
-            resolve_linux_variant(&id, ROLLING_DISTROS)
-        } else {
-            code
-        }
-    } else {
-        resolve_linux_variant(&id, ROLLING_DISTROS)
-    };
-
-    Ok(format!("{id}_{variant}"))
-}
-
-#[cfg(any(target_os = "linux", target_os = "freebsd"))]
-fn resolve_linux_variant(id: &str, rolling: &[&str]) -> String {
-    if rolling.contains(&id) {
-        "rolling".to_string()
-    } else {
-        "unknown".to_string()
-    }
-}
-
-#[cfg(target_os = "macos")]
-fn detect_macos_os_suffix() -> Result<String> {
-    use std::process::Command;
-    let output = Command::new("sw_vers")
-        .output()
-        .context("failed to run sw_vers")?;
-    if !output.status.success() {
-        bail!("sw_vers returned non-zero exit status");
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    let mut product_name: Option<String> = None;
-    let mut product_version: Option<String> = None;
-
-    for line in stdout.lines() {
-        if let Some((k, v)) = line.split_once(':') {
-            let key = k.trim();
-            let val = v.trim();
-            if key.eq_ignore_ascii_case("ProductName") {
-                product_name = Some(val.to_ascii_lowercase().replace(' ', ""));
-            } else if key.eq_ignore_ascii_case("ProductVersion") {
-                product_version = Some(val.to_ascii_lowercase());
-            }
-        }
-    }
-
-    let id = product_name.unwrap_or_else(|| "macos".into());
-    let variant = product_version.ok_or_else(|| anyhow!("ProductVersion not found"))?;
-    Ok(format!("{id}_{variant}"))
+    let os_suffix = platform::os_suffix(os_suffix)?;
+    let archive_filename = format!(
+        "{package_name}_{package_version}-{os_suffix}.{}",
+        format.extension()
+    );
+    Ok((build_dir.join(archive_filename), os_suffix))
 }
 
 // --------------- Helpers ---------------
@@ -416,7 +681,7 @@ fn detect_macos_os_suffix() -> Result<String> {
 fn archive_if_exists(
     root_in_archive: &Path,
     file_path: &Path,
-    tarball: &mut Builder<&mut GzEncoder<File>>,
+    archive: &mut ArchiveWriter,
 ) -> Result<()> {
     if !file_path.exists() {
         debug!(
@@ -432,21 +697,91 @@ fn archive_if_exists(
             .ok_or_else(|| anyhow!("Path without file name: {}", file_path.display()))?,
     );
 
-    if file_path.is_dir() {
-        tarball
-            .append_dir_all(&archived_name, file_path)
-            .with_context(|| format!("failed to append directory {}", file_path.display()))?;
-    } else {
-        let mut opened_file = File::open(file_path)
-            .with_context(|| format!("failed to open file {}", file_path.display()))?;
-        tarball
-            .append_file(&archived_name, &mut opened_file)
-            .with_context(|| format!("failed to append file {}", file_path.display()))?;
+    archive
+        .append_path(&archived_name, file_path)
+        .with_context(|| format!("failed to append {}", file_path.display()))
+}
+
+/// Appends content of `dir` to `prefix` in the root of the plugin in the archive
+/// skipping ignored entries, e.g. content of the assets folder goes to the root itself.
+fn archive_dir(
+    root_in_archive: &Path,
+    dir: &Path,
+    prefix: &Path,
+    ignore: &IgnoreList,
+    archive: &mut ArchiveWriter,
+) -> Result<()> {
+    if !prefix.as_os_str().is_empty() {
+        if ignore.is_ignored(prefix, true) {
+            debug!("Skipping {} excluded from the archive", dir.display());
+            return Ok(());
+        }
+        archive
+            .append_dir(&root_in_archive.join(prefix), dir)
+            .with_context(|| format!("failed to append directory {}", dir.display()))?;
     }
 
+    walk(dir, prefix, ignore, &mut |relative, path, is_dir| {
+        let archived_name = root_in_archive.join(relative);
+        if is_dir {
+            archive
+                .append_dir(&archived_name, path)
+                .with_context(|| format!("failed to append directory {}", path.display()))
+        } else {
+            archive
+                .append_file(&archived_name, path)
+                .with_context(|| format!("failed to append file {}", path.display()))
+        }
+    })
+    .with_context(|| format!("reading dir {}", dir.display()))
+}
+
+fn generate_sbom(plugin_dir: &Path, package_name: &str) -> Result<Vec<u8>> {
+    let sbom = sbom::generate(plugin_dir, package_name).context(format!(
+        "failed to generate SBOM of plugin '{package_name}'"
+    ))?;
+    serde_json::to_vec_pretty(&sbom).context("failed to serialize SBOM")
+}
+
+/// SBOM next to the archive, e.g. `plugin_0.1.0.cdx.json` for `plugin_0.1.0.tar.gz`.
+fn sbom_path(archive_path: &Path) -> PathBuf {
+    let name = archive_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let stem = ArchiveFormat::strip_extension(&name);
+    archive_path.with_file_name(format!("{stem}.cdx.json"))
+}
+
+fn save_sbom(archive_path: &Path, sbom: &[u8]) -> Result<()> {
+    let path = sbom_path(archive_path);
+    fs::write(&path, sbom).context(format!("failed to write {}", path.display()))?;
+    info!("SBOM created: {}", path.display());
     Ok(())
 }
 
+fn archive_config_schema(
+    root_in_archive: &Path,
+    config_schema: &serde_json::Value,
+    archive: &mut ArchiveWriter,
+) -> Result<()> {
+    let content = serde_json::to_vec_pretty(config_schema)
+        .context("failed to serialize plugin config schema")?;
+    archive_data(
+        &root_in_archive.join(schema::SCHEMA_FILE_NAME),
+        &content,
+        archive,
+    )
+    .context("failed to append plugin config schema")
+}
+
+/// Appends generated file with `content` to the archive.
+fn archive_data(path_in_archive: &Path, content: &[u8], archive: &mut ArchiveWriter) -> Result<()> {
+    archive
+        .append_data(path_in_archive, content)
+        .with_context(|| format!("failed to append {}", path_in_archive.display()))
+}
+
 fn get_latest_plugin_version(plugin_dir: &Path) -> Result<String> {
     let cargo_toml_path = plugin_dir.join("Cargo.toml");
     let cargo_toml = fs::read_to_string(&cargo_toml_path)
@@ -471,22 +806,18 @@ fn get_latest_plugin_version(plugin_dir: &Path) -> Result<String> {
 #[cfg(test)]
 mod tests {
     use super::{
-        create_archive_path, generate_archive_path, resolve_archive_path,
-        validate_plugin_build_tree, LIB_EXT,
+        archive_dir, create_archive_path, generate_archive_path, resolve_archive_path, sbom_path,
+        stale_build_reason, validate_plugin_build_tree, ContentFilter, IgnoreList, Packed,
+        PackedArchive, LIB_EXT,
     };
+    use crate::commands::lib::archive::{self, ArchiveFormat, ArchiveWriter, CompressionLevel};
+    use crate::commands::lib::temp_workspace::TempWorkspace;
     use std::fs;
     use std::io::Write;
     use std::path::{Path, PathBuf};
-    use std::time::{SystemTime, UNIX_EPOCH};
 
-    fn tmp_dir(prefix: &str) -> PathBuf {
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let mut dir = std::env::temp_dir();
-        dir.push(format!("pike-pack-ut-{prefix}-{ts}"));
-        dir
+    fn tmp_dir(name: &str) -> TempWorkspace {
+        TempWorkspace::new(&format!("plugin-pack-{name}")).unwrap()
     }
 
     fn touch(path: &Path) {
@@ -521,20 +852,20 @@ mod tests {
 
     #[test]
     fn validate_ok_when_all_required_files_exist() {
-        let base = tmp_dir("ok");
+        let workspace = tmp_dir("ok");
+        let base = workspace.path();
         let pkg = "some-plugin";
         let ver = "0.1.0";
-        let plugin_build_dir = make_build_tree(&base, pkg, ver, true, true);
+        let plugin_build_dir = make_build_tree(base, pkg, ver, true, true);
 
         let res = validate_plugin_build_tree(&plugin_build_dir, &pkg.replace('-', "_"));
         assert!(res.is_ok(), "Expected OK, got error: {res:?}");
-
-        let _ = fs::remove_dir_all(&base);
     }
 
     #[test]
     fn validate_fails_if_dir_missing() {
-        let base = tmp_dir("missing-dir");
+        let workspace = tmp_dir("missing-dir");
+        let base = workspace.path();
         let non_existing = base.join("nope/0.0.0");
         let res = validate_plugin_build_tree(&non_existing, "nope");
         assert!(res.is_err(), "Expected error for missing dir");
@@ -543,15 +874,15 @@ mod tests {
             msg.contains("Build output directory not found"),
             "Unexpected error message: {msg}"
         );
-        let _ = fs::remove_dir_all(&base);
     }
 
     #[test]
     fn validate_fails_if_manifest_missing() {
-        let base = tmp_dir("missing-manifest");
+        let workspace = tmp_dir("missing-manifest");
+        let base = workspace.path();
         let pkg = "x-plugin";
         let ver = "1.2.3";
-        let plugin_build_dir = make_build_tree(&base, pkg, ver, false, true);
+        let plugin_build_dir = make_build_tree(base, pkg, ver, false, true);
 
         let res = validate_plugin_build_tree(&plugin_build_dir, &pkg.replace('-', "_"));
         assert!(res.is_err(), "Expected error for missing manifest");
@@ -560,16 +891,15 @@ mod tests {
             msg.contains("Missing manifest.yaml"),
             "Unexpected error message: {msg}"
         );
-
-        let _ = fs::remove_dir_all(&base);
     }
 
     #[test]
     fn validate_fails_if_lib_missing() {
-        let base = tmp_dir("missing-lib");
+        let workspace = tmp_dir("missing-lib");
+        let base = workspace.path();
         let pkg = "another-plugin";
         let ver = "9.9.9";
-        let plugin_build_dir = make_build_tree(&base, pkg, ver, true, false);
+        let plugin_build_dir = make_build_tree(base, pkg, ver, true, false);
 
         let res = validate_plugin_build_tree(&plugin_build_dir, &pkg.replace('-', "_"));
         assert!(res.is_err(), "Expected error for missing lib");
@@ -579,8 +909,59 @@ mod tests {
             msg.contains("Missing plugin library") && msg.contains(&expected_lib),
             "Unexpected error message: {msg}"
         );
+    }
 
-        let _ = fs::remove_dir_all(&base);
+    #[test]
+    fn stale_build_reason_reports_missing_version() {
+        let workspace = tmp_dir("stale-missing");
+        let base = workspace.path();
+        make_build_tree(base, "plugin", "0.1.0", true, true);
+
+        assert_eq!(
+            stale_build_reason(base, "plugin", "0.2.0").as_deref(),
+            Some("not built, staged versions: 0.1.0")
+        );
+        assert_eq!(
+            stale_build_reason(base, "other", "0.2.0").as_deref(),
+            Some("not built")
+        );
+    }
+
+    #[test]
+    fn stale_build_reason_reports_version_built_later() {
+        let workspace = tmp_dir("stale-later");
+        let base = workspace.path();
+        let lib_name = format!("libplugin.{LIB_EXT}");
+        make_build_tree(base, "plugin", "0.1.0", true, true);
+        let last_build_dir = make_build_tree(base, "plugin", "0.2.0", true, false);
+        std::os::unix::fs::symlink(
+            base.join("plugin/0.1.0").join(&lib_name),
+            last_build_dir.join(&lib_name),
+        )
+        .unwrap();
+
+        assert_eq!(
+            stale_build_reason(base, "plugin", "0.1.0").as_deref(),
+            Some("last built version is 0.2.0")
+        );
+        assert_eq!(stale_build_reason(base, "plugin", "0.2.0"), None);
+    }
+
+    #[test]
+    fn stale_build_reason_reports_manifest_version_mismatch() {
+        let workspace = tmp_dir("stale-manifest");
+        let base = workspace.path();
+        let build_dir = make_build_tree(base, "plugin", "0.1.0", false, false);
+        fs::write(
+            build_dir.join("manifest.yaml"),
+            "name: plugin\nversion: 0.0.9\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            stale_build_reason(base, "plugin", "0.1.0").as_deref(),
+            Some("staged manifest.yaml has version 0.0.9")
+        );
     }
 
     #[test]
@@ -591,27 +972,38 @@ mod tests {
             Some(&PathBuf::from("custom.tar.gz")),
             "pkg",
             "0.1.0",
+            None,
+            ArchiveFormat::TarGz,
         )
         .unwrap();
-        assert_eq!(dest, build_dir.join("custom.tar.gz"));
+        assert_eq!(dest, (build_dir.join("custom.tar.gz"), None));
     }
 
     #[test]
     fn resolve_archive_relative_without_ext_appends_tar_gz() {
         let build_dir = PathBuf::from("/tmp/build/rel");
-        let dest = resolve_archive_path(&build_dir, Some(&PathBuf::from("custom")), "pkg", "0.1.0")
-            .unwrap();
+        let (dest, _) = resolve_archive_path(
+            &build_dir,
+            Some(&PathBuf::from("custom")),
+            "pkg",
+            "0.1.0",
+            None,
+            ArchiveFormat::TarGz,
+        )
+        .unwrap();
         assert_eq!(dest, build_dir.join("custom.tar.gz"));
     }
 
     #[test]
     fn resolve_archive_absolute_without_ext_appends_tar_gz() {
         let build_dir = PathBuf::from("/tmp/build/rel");
-        let dest = resolve_archive_path(
+        let (dest, _) = resolve_archive_path(
             &build_dir,
             Some(&PathBuf::from("/var/tmp/out/custom-name")),
             "pkg",
             "0.1.0",
+            None,
+            ArchiveFormat::TarGz,
         )
         .unwrap();
         assert_eq!(dest, PathBuf::from("/var/tmp/out/custom-name.tar.gz"));
@@ -619,14 +1011,143 @@ mod tests {
     #[test]
     fn create_archive_path_keeps_absolute_path_with_ext() {
         let build_dir = PathBuf::from("/tmp/build/rel");
-        let dest = create_archive_path(&build_dir, Path::new("/var/tmp/out/file.tar.gz")).unwrap();
+        let dest = create_archive_path(
+            &build_dir,
+            Path::new("/var/tmp/out/file.tar.gz"),
+            ArchiveFormat::TarGz,
+        )
+        .unwrap();
         assert_eq!(dest, PathBuf::from("/var/tmp/out/file.tar.gz"));
     }
 
+    #[test]
+    fn archive_path_has_extension_of_format() {
+        let build_dir = PathBuf::from("/tmp/build/rel");
+        let dest =
+            create_archive_path(&build_dir, Path::new("custom"), ArchiveFormat::Zip).unwrap();
+        assert_eq!(dest, build_dir.join("custom.zip"));
+        let dest =
+            create_archive_path(&build_dir, Path::new("custom.zip"), ArchiveFormat::Zip).unwrap();
+        assert_eq!(dest, build_dir.join("custom.zip"));
+
+        let (p, _) = generate_archive_path(
+            &build_dir,
+            "pkg",
+            "0.1.0",
+            Some("alt_p10"),
+            ArchiveFormat::TarZst,
+        )
+        .unwrap();
+        assert_eq!(p, build_dir.join("pkg_0.1.0-alt_p10.tar.zst"));
+        assert_eq!(sbom_path(&p), build_dir.join("pkg_0.1.0-alt_p10.cdx.json"));
+    }
+
     #[test]
     fn generate_archive_path_includes_suffix() {
-        let p = generate_archive_path(Path::new("/tmp/build/rel"), "pkg", "0.1.0").unwrap();
+        let (p, os_suffix) = generate_archive_path(
+            Path::new("/tmp/build/rel"),
+            "pkg",
+            "0.1.0",
+            None,
+            ArchiveFormat::TarGz,
+        )
+        .unwrap();
         let name = p.file_name().unwrap().to_string_lossy();
-        assert!(name.starts_with("pkg_0.1.0-") && name.ends_with(".tar.gz"));
+        assert_eq!(name, format!("pkg_0.1.0-{os_suffix}.tar.gz"));
+
+        let (p, os_suffix) = generate_archive_path(
+            Path::new("/tmp/build/rel"),
+            "pkg",
+            "0.1.0",
+            Some("alt_p10"),
+            ArchiveFormat::TarGz,
+        )
+        .unwrap();
+        assert_eq!(p, Path::new("/tmp/build/rel/pkg_0.1.0-alt_p10.tar.gz"));
+        assert_eq!(os_suffix, "alt_p10");
+    }
+
+    #[test]
+    fn content_filter_excludes_and_includes_entries() {
+        let workspace = tmp_dir("content-filter");
+        let build_dir = workspace.join("build");
+        touch(&build_dir.join("migrations/0001_init.sql"));
+        touch(&build_dir.join("migrations/0002_fixtures.sql"));
+        touch(&build_dir.join("assets/plugin_config.yaml"));
+        touch(&build_dir.join("assets/sample.yaml"));
+        touch(&build_dir.join("assets/sample.yaml~"));
+        touch(&build_dir.join("assets/fixtures/users.json"));
+
+        let filter = ContentFilter {
+            exclude: vec![
+                "fixtures/".to_string(),
+                "sample.yaml".to_string(),
+                "migrations/*_fixtures.sql".to_string(),
+            ],
+            include: vec!["*~".to_string()],
+        };
+        let archive_path = workspace.join("plugin.tar.gz");
+        let mut writer =
+            ArchiveWriter::create(&archive_path, ArchiveFormat::TarGz, CompressionLevel::Best)
+                .unwrap();
+        let root = Path::new("plugin/0.1.0");
+        let migrations_ignore = filter.apply_to(IgnoreList::default()).unwrap();
+        archive_dir(
+            root,
+            &build_dir.join("migrations"),
+            Path::new("migrations"),
+            &migrations_ignore,
+            &mut writer,
+        )
+        .unwrap();
+        // `.pikeignore` excludes backups, but `--include` wins.
+        let assets_ignore = filter.apply_to(IgnoreList::new(["*~"]).unwrap()).unwrap();
+        archive_dir(
+            root,
+            &build_dir.join("assets"),
+            Path::new(""),
+            &assets_ignore,
+            &mut writer,
+        )
+        .unwrap();
+        writer.finish().unwrap();
+
+        let entries: Vec<String> = archive::entry_paths(&archive_path)
+            .unwrap()
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                "plugin/0.1.0/migrations",
+                "plugin/0.1.0/migrations/0001_init.sql",
+                "plugin/0.1.0/plugin_config.yaml",
+                "plugin/0.1.0/sample.yaml~",
+            ]
+        );
+    }
+
+    #[test]
+    fn packed_archive_has_checksum_and_size() {
+        let workspace = tmp_dir("packed-archive");
+        let path = workspace.join("plugin.tar.gz");
+        fs::write(&path, "abc").unwrap();
+
+        let packed = Packed::Plugin(
+            PackedArchive::new("plugin".to_string(), "0.1.0".to_string(), &path, None).unwrap(),
+        );
+        let json = serde_json::to_value(&packed).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "plugin",
+                "version": "0.1.0",
+                "path": path,
+                "size": 3,
+                "sha256": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                "os_suffix": null,
+            })
+        );
     }
 }
@@ -0,0 +1,259 @@
+//! Upload of plugin archives created by `plugin pack` to an artifact repository,
+//! see [`crate::commands::lib::repository`].
+
+use crate::commands::lib::archive::{self, ArchiveFormat};
+use crate::commands::lib::integrity;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::repository::{IndexEntry, Repository};
+use crate::commands::lib::OutputFormat;
+use crate::commands::plugin::audit;
+use anyhow::{anyhow, bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    /// Archives created by `plugin pack`.
+    archives: Vec<PathBuf>,
+    /// Repository to publish to, `destination` of the `[publish]` section
+    /// of `pike.toml` if not set.
+    #[builder(default)]
+    destination: Option<String>,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    /// Check dependencies of published plugins against advisories and licences
+    /// configured in the `[audit]` section of `pike.toml` before uploading.
+    #[builder(default)]
+    audit: bool,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let published = publish(params)?;
+    if params.output == OutputFormat::Json {
+        let json = serde_json::to_string_pretty(&published)
+            .context("failed to serialize publish result")?;
+        println!("{json}");
+    }
+    Ok(())
+}
+
+/// Uploads archives with their checksums and signatures and adds them to the index
+/// of the repository, returns entries of the published archives.
+pub(crate) fn publish(params: &Params) -> Result<Vec<IndexEntry>> {
+    let pike_config = PikeConfig::load(&params.plugin_path)?;
+    if params.audit {
+        // Nothing is uploaded if any of the plugins fails the audit.
+        let plugins: BTreeSet<String> = params
+            .archives
+            .iter()
+            .map(|archive_path| plugin_of_archive(archive_path).map(|(name, _)| name))
+            .collect::<Result<_>>()?;
+        for plugin in &plugins {
+            audit::check(&params.plugin_path, plugin, &pike_config.audit)?;
+        }
+    }
+    let config = pike_config.publish;
+    let destination = params
+        .destination
+        .as_deref()
+        .or(config.destination.as_deref())
+        .ok_or_else(|| {
+            anyhow!(
+                "destination is not set, pass --destination or set it in [publish] of pike.toml"
+            )
+        })?;
+    let repository = Repository::parse(
+        destination,
+        config.endpoint.as_deref(),
+        config.region.as_deref(),
+    )?;
+
+    let mut index = repository.index()?;
+    let mut published = vec![];
+    for archive_path in &params.archives {
+        let entry = upload(&repository, archive_path)
+            .context(format!("failed to publish {}", archive_path.display()))?;
+        info!(
+            "Published {} {} → {repository}/{}",
+            entry.name, entry.version, entry.path
+        );
+        index.insert(entry.clone());
+        published.push(entry);
+    }
+    repository.put_index(&index)?;
+    info!("Index of {repository} updated");
+    Ok(published)
+}
+
+fn upload(repository: &Repository, archive_path: &Path) -> Result<IndexEntry> {
+    if !archive_path.is_file() {
+        bail!("{} does not exist", archive_path.display());
+    }
+    if integrity::verify_checksum(archive_path)? {
+        info!("Checksum of {} is correct", archive_path.display());
+    }
+    let (name, version) = plugin_of_archive(archive_path)?;
+    let file_name = archive_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let sha256 = integrity::sha256(archive_path)?;
+    let entry = IndexEntry {
+        os_suffix: os_suffix(&file_name, &name, &version),
+        path: format!("{name}/{version}/{file_name}"),
+        name,
+        version,
+        sha256,
+    };
+
+    let content =
+        fs::read(archive_path).context(format!("failed to read {}", archive_path.display()))?;
+    repository.put(&entry.path, &content)?;
+    repository.put(
+        &format!("{}.sha256", entry.path),
+        format!("{}  {file_name}\n", entry.sha256).as_bytes(),
+    )?;
+    let signature_path = integrity::signature_path(archive_path);
+    if signature_path.exists() {
+        let signature = fs::read(&signature_path)
+            .context(format!("failed to read {}", signature_path.display()))?;
+        repository.put(&format!("{}.minisig", entry.path), &signature)?;
+    }
+    Ok(entry)
+}
+
+/// Name and version of the single plugin packed into the archive.
+fn plugin_of_archive(archive_path: &Path) -> Result<(String, String)> {
+    let plugins: BTreeSet<(String, String)> = archive::entry_paths(archive_path)?
+        .iter()
+        .filter(|path| path.components().count() == 3 && path.ends_with("manifest.yaml"))
+        .filter_map(|path| {
+            let mut components = path.iter().map(|part| part.to_string_lossy().to_string());
+            Some((components.next()?, components.next()?))
+        })
+        .collect();
+    let mut plugins = plugins.into_iter();
+    match (plugins.next(), plugins.next()) {
+        (Some(plugin), None) => Ok(plugin),
+        (None, _) => bail!(
+            "{} is not a plugin archive: no <plugin>/<version>/manifest.yaml",
+            archive_path.display()
+        ),
+        (Some(_), Some(_)) => bail!(
+            "{} contains several plugins, publish archives of a single plugin version",
+            archive_path.display()
+        ),
+    }
+}
+
+/// OS suffix of the default archive name `<name>_<version>-<os_suffix>.<extension>`.
+fn os_suffix(file_name: &str, name: &str, version: &str) -> Option<String> {
+    ArchiveFormat::strip_extension(file_name)
+        .strip_prefix(&format!("{name}_{version}-"))
+        .filter(|suffix| !suffix.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::archive::{ArchiveWriter, CompressionLevel};
+    use crate::commands::lib::repository::{Index, INDEX_FILE_NAME};
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    fn pack(dir: &Path, file_name: &str, plugins: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join(file_name);
+        let mut archive =
+            ArchiveWriter::create(&path, ArchiveFormat::TarGz, CompressionLevel::Fast).unwrap();
+        for (name, version) in plugins {
+            archive
+                .append_data(
+                    &Path::new(name).join(version).join("manifest.yaml"),
+                    format!("name: {name}\nversion: {version}\n").as_bytes(),
+                )
+                .unwrap();
+        }
+        archive.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn os_suffix_is_taken_from_default_name() {
+        assert_eq!(
+            os_suffix("weather_0.1.0-ubuntu_24.04.tar.gz", "weather", "0.1.0").as_deref(),
+            Some("ubuntu_24.04")
+        );
+        assert_eq!(os_suffix("weather.zip", "weather", "0.1.0"), None);
+    }
+
+    #[test]
+    fn archives_are_published_to_directory() {
+        let workspace = TempWorkspace::new("plugin-publish-local").unwrap();
+        let archive = pack(
+            workspace.path(),
+            "weather_0.1.0-alt_10.tar.gz",
+            &[("weather", "0.1.0")],
+        );
+        let repository = workspace.join("repository");
+
+        let params = ParamsBuilder::default()
+            .archives(vec![archive.clone()])
+            .destination(Some(repository.to_string_lossy().to_string()))
+            .plugin_path(workspace.path().to_path_buf())
+            .build()
+            .unwrap();
+        let published = publish(&params).unwrap();
+
+        let path = "weather/0.1.0/weather_0.1.0-alt_10.tar.gz";
+        assert_eq!(
+            fs::read(repository.join(path)).unwrap(),
+            fs::read(&archive).unwrap()
+        );
+        assert!(repository.join(format!("{path}.sha256")).exists());
+        let index: Index =
+            serde_json::from_slice(&fs::read(repository.join(INDEX_FILE_NAME)).unwrap()).unwrap();
+        assert_eq!(index.plugins, published);
+        assert_eq!(published[0].os_suffix.as_deref(), Some("alt_10"));
+        assert_eq!(published[0].path, path);
+    }
+
+    #[test]
+    fn nothing_is_published_if_audit_fails() {
+        let workspace = TempWorkspace::new("plugin-publish-audit").unwrap();
+        let archive = pack(
+            workspace.path(),
+            "weather_0.1.0-alt_10.tar.gz",
+            &[("weather", "0.1.0")],
+        );
+        let repository = workspace.join("repository");
+
+        // The workspace has no Cargo.toml, dependencies of the plugin can't be audited.
+        let params = ParamsBuilder::default()
+            .archives(vec![archive])
+            .destination(Some(repository.to_string_lossy().to_string()))
+            .plugin_path(workspace.path().to_path_buf())
+            .audit(true)
+            .build()
+            .unwrap();
+        assert!(publish(&params).is_err());
+        assert!(!repository.exists());
+    }
+
+    #[test]
+    fn archive_of_several_plugins_is_rejected() {
+        let workspace = TempWorkspace::new("plugin-publish-several").unwrap();
+        let archive = pack(
+            workspace.path(),
+            "plugins.tar.gz",
+            &[("weather", "0.1.0"), ("weather", "0.2.0")],
+        );
+        let err = plugin_of_archive(&archive).unwrap_err();
+        assert!(err.to_string().contains("several plugins"), "{err}");
+    }
+}
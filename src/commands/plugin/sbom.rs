@@ -0,0 +1,305 @@
+//! `CycloneDX` SBOM of a plugin, an inventory of crates linked into its library.
+//!
+//! Crates and their licenses are taken from `cargo metadata`, checksums of
+//! registry crates from `Cargo.lock`. Only normal dependencies are listed,
+//! build and dev dependencies are not shipped in the archive.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the SBOM file in the plugin archive.
+pub const SBOM_FILE_NAME: &str = "sbom.cdx.json";
+
+const SPEC_VERSION: &str = "1.5";
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Metadata {
+    packages: Vec<Package>,
+    resolve: Resolve,
+    pub(super) workspace_root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Package {
+    id: String,
+    pub(super) name: String,
+    pub(super) version: String,
+    /// `None` for path dependencies and members of the workspace.
+    pub(super) source: Option<String>,
+    pub(super) license: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resolve {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    id: String,
+    deps: Vec<NodeDep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeDep {
+    pkg: String,
+    dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepKind {
+    /// `None` for normal dependencies, `build` or `dev` otherwise.
+    kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    checksum: Option<String>,
+}
+
+fn purl(package: &Package) -> String {
+    format!("pkg:cargo/{}@{}", package.name, package.version)
+}
+
+fn component(package: &Package, checksums: &HashMap<(&str, &str), &str>) -> Value {
+    let mut component = json!({
+        "type": "library",
+        "bom-ref": purl(package),
+        "name": package.name,
+        "version": package.version,
+        "purl": purl(package),
+    });
+    if let Some(description) = &package.description {
+        component["description"] = json!(description.trim());
+    }
+    if let Some(license) = &package.license {
+        component["licenses"] = json!([{ "expression": license }]);
+    }
+    let checksum = checksums.get(&(package.name.as_str(), package.version.as_str()));
+    if let (Some(checksum), Some(_)) = (checksum, &package.source) {
+        component["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+    }
+    component
+}
+
+/// Package and crates shipped with it: its normal dependencies and their
+/// dependencies, keyed by purl to keep the output sorted.
+pub(super) struct DependencyGraph<'a> {
+    pub(super) root: &'a Package,
+    pub(super) packages: BTreeMap<String, (&'a Package, BTreeSet<String>)>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    pub(super) fn new(metadata: &'a Metadata, package_name: &str) -> Result<Self> {
+        let packages: HashMap<&str, &Package> = metadata
+            .packages
+            .iter()
+            .map(|p| (p.id.as_str(), p))
+            .collect();
+        let nodes: HashMap<&str, &Node> = metadata
+            .resolve
+            .nodes
+            .iter()
+            .map(|n| (n.id.as_str(), n))
+            .collect();
+
+        let Some(root) = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == package_name && p.source.is_none())
+        else {
+            bail!("package '{package_name}' is not found in cargo metadata");
+        };
+
+        let mut graph = BTreeMap::new();
+        let mut queue = vec![root.id.as_str()];
+        while let Some(id) = queue.pop() {
+            let package = packages[id];
+            if graph.contains_key(&purl(package)) {
+                continue;
+            }
+            let mut depends_on = BTreeSet::new();
+            for dep in nodes.get(id).map(|n| n.deps.as_slice()).unwrap_or_default() {
+                if !dep.dep_kinds.iter().any(|k| k.kind.is_none()) {
+                    continue;
+                }
+                depends_on.insert(purl(packages[dep.pkg.as_str()]));
+                queue.push(&dep.pkg);
+            }
+            graph.insert(purl(package), (package, depends_on));
+        }
+        Ok(Self {
+            root,
+            packages: graph,
+        })
+    }
+
+    /// Shipped crates without the package itself.
+    pub(super) fn dependencies(&self) -> impl Iterator<Item = &'a Package> + '_ {
+        self.packages
+            .values()
+            .map(|(package, _)| *package)
+            .filter(|package| package.id != self.root.id)
+    }
+}
+
+/// Builds the SBOM of `package_name` from output of `cargo metadata` and `Cargo.lock`.
+fn build(metadata: &Metadata, lockfile: &Lockfile, package_name: &str) -> Result<Value> {
+    let checksums: HashMap<(&str, &str), &str> = lockfile
+        .package
+        .iter()
+        .filter_map(|p| {
+            Some((
+                (p.name.as_str(), p.version.as_str()),
+                p.checksum.as_deref()?,
+            ))
+        })
+        .collect();
+    let graph = DependencyGraph::new(metadata, package_name)?;
+    let root = graph.root;
+    let components: Vec<Value> = graph
+        .dependencies()
+        .map(|package| component(package, &checksums))
+        .collect();
+
+    Ok(json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": SPEC_VERSION,
+        "version": 1,
+        "metadata": {
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "cargo-pike",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": component(root, &checksums),
+        },
+        "components": components,
+        "dependencies": graph
+            .packages
+            .iter()
+            .map(|(reference, (_, depends_on))| json!({ "ref": reference, "dependsOn": depends_on }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+/// Runs `cargo metadata` for the package in `plugin_dir`.
+pub(super) fn cargo_metadata(plugin_dir: &Path) -> Result<Metadata> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--manifest-path"])
+        .arg(plugin_dir.join("Cargo.toml"))
+        .output()
+        .context("failed to run cargo metadata")?;
+    if !output.status.success() {
+        bail!(
+            "cargo metadata exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout).context("failed to parse cargo metadata")
+}
+
+/// Generates the SBOM of the plugin package in `plugin_dir`.
+pub fn generate(plugin_dir: &Path, package_name: &str) -> Result<Value> {
+    let metadata = cargo_metadata(plugin_dir)?;
+    let lockfile_path = metadata.workspace_root.join("Cargo.lock");
+    let lockfile: Lockfile = toml::from_str(
+        &fs::read_to_string(&lockfile_path)
+            .context(format!("failed to read {}", lockfile_path.display()))?,
+    )
+    .context(format!("failed to parse {}", lockfile_path.display()))?;
+
+    build(&metadata, &lockfile, package_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_normal_dependencies_are_listed() {
+        let registry = "registry+https://github.com/rust-lang/crates.io-index";
+        let metadata: Metadata = serde_json::from_value(json!({
+            "workspace_root": "/plugin",
+            "packages": [
+                {"id": "plugin", "name": "plugin", "version": "0.1.0", "source": null,
+                 "license": null, "description": "Plugin"},
+                {"id": "serde", "name": "serde", "version": "1.0.0", "source": registry,
+                 "license": "MIT OR Apache-2.0", "description": null},
+                {"id": "shared", "name": "shared", "version": "0.2.0", "source": null,
+                 "license": "BSD-2-Clause", "description": null},
+                {"id": "cc", "name": "cc", "version": "1.2.0", "source": registry,
+                 "license": "MIT", "description": null},
+                {"id": "rstest", "name": "rstest", "version": "0.26.0", "source": registry,
+                 "license": "MIT", "description": null},
+            ],
+            "resolve": {"nodes": [
+                {"id": "plugin", "deps": [
+                    {"pkg": "serde", "dep_kinds": [{"kind": null}]},
+                    {"pkg": "shared", "dep_kinds": [{"kind": null}]},
+                    {"pkg": "cc", "dep_kinds": [{"kind": "build"}]},
+                    {"pkg": "rstest", "dep_kinds": [{"kind": "dev"}]},
+                ]},
+                {"id": "shared", "deps": [{"pkg": "serde", "dep_kinds": [{"kind": null}]}]},
+                {"id": "serde", "deps": []},
+                {"id": "cc", "deps": []},
+                {"id": "rstest", "deps": []},
+            ]},
+        }))
+        .unwrap();
+        let lockfile: Lockfile = toml::from_str(
+            r#"
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            checksum = "abc"
+
+            [[package]]
+            name = "shared"
+            version = "0.2.0"
+            "#,
+        )
+        .unwrap();
+
+        let sbom = build(&metadata, &lockfile, "plugin").unwrap();
+        assert_eq!(
+            sbom["metadata"]["component"]["purl"],
+            "pkg:cargo/plugin@0.1.0"
+        );
+        assert_eq!(
+            sbom["components"],
+            json!([
+                {"type": "library", "bom-ref": "pkg:cargo/serde@1.0.0", "name": "serde",
+                 "version": "1.0.0", "purl": "pkg:cargo/serde@1.0.0",
+                 "licenses": [{"expression": "MIT OR Apache-2.0"}],
+                 "hashes": [{"alg": "SHA-256", "content": "abc"}]},
+                {"type": "library", "bom-ref": "pkg:cargo/shared@0.2.0", "name": "shared",
+                 "version": "0.2.0", "purl": "pkg:cargo/shared@0.2.0",
+                 "licenses": [{"expression": "BSD-2-Clause"}]},
+            ])
+        );
+        assert_eq!(
+            sbom["dependencies"][0],
+            json!({"ref": "pkg:cargo/plugin@0.1.0",
+                   "dependsOn": ["pkg:cargo/serde@1.0.0", "pkg:cargo/shared@0.2.0"]})
+        );
+        assert!(build(&metadata, &lockfile, "serde").is_err());
+    }
+}
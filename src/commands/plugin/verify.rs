@@ -0,0 +1,514 @@
+//! Checks of the plugin archive or shipping directory before it is run.
+//!
+//! Every check produces a [`Diagnostic`], so all problems of the plugin are
+//! reported at once: checksum and signature of archives, structure, manifest,
+//! shared library, migrations listed in the manifest and, optionally,
+//! entrypoints exported by the library.
+
+use crate::commands::lib::integrity;
+use crate::commands::lib::{archive, OutputFormat, LIB_EXT};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use derive_builder::Builder;
+use log::{error, info, warn};
+use object::{Object, ObjectSymbol};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Symbol which picodata calls to register services of the plugin,
+/// it is exported by `#[service_registrar]`.
+pub const SERVICE_REGISTRAR_SYMBOL: &str = "pico_service_registrar";
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    /// Plugin archive, shipping directory of the plugin or directory of its version.
+    path: PathBuf,
+    /// Minisign public key, the archive must have a valid signature made by its secret key.
+    #[builder(default)]
+    verify_key: Option<PathBuf>,
+    /// Check that the shared library exports entrypoints of a picodata plugin.
+    #[builder(default)]
+    check_symbols: bool,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Skipped,
+    /// Problem which doesn't prevent the plugin from being installed.
+    Warning,
+    Error,
+}
+
+/// Result of a single check.
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    /// What is checked, e.g. `manifest` or `migrations`.
+    pub check: String,
+    /// Plugin and version the check is about, `None` for the whole archive.
+    pub plugin: Option<String>,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Ok => "OK".green(),
+            Status::Skipped => "SKIPPED".yellow(),
+            Status::Warning => "WARNING".yellow(),
+            Status::Error => "ERROR".red(),
+        };
+        if let Some(plugin) = &self.plugin {
+            write!(f, "{plugin}: ")?;
+        }
+        write!(f, "{} - {status}", self.check)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnostics of all checks, see [`verify`] and [`super::lint::lint`].
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    fn push(&mut self, check: &str, plugin: Option<&str>, status: Status, message: String) {
+        self.diagnostics.push(Diagnostic {
+            check: check.to_string(),
+            plugin: plugin.map(str::to_string),
+            status,
+            message,
+        });
+    }
+
+    pub(crate) fn ok(&mut self, check: &str, plugin: Option<&str>, message: impl Into<String>) {
+        self.push(check, plugin, Status::Ok, message.into());
+    }
+
+    pub(crate) fn skipped(
+        &mut self,
+        check: &str,
+        plugin: Option<&str>,
+        message: impl Into<String>,
+    ) {
+        self.push(check, plugin, Status::Skipped, message.into());
+    }
+
+    pub(crate) fn warning(
+        &mut self,
+        check: &str,
+        plugin: Option<&str>,
+        message: impl Into<String>,
+    ) {
+        self.push(check, plugin, Status::Warning, message.into());
+    }
+
+    pub(crate) fn error(&mut self, check: &str, plugin: Option<&str>, message: impl Into<String>) {
+        self.push(check, plugin, Status::Error, message.into());
+    }
+
+    /// Prints diagnostics in the text format of `plugin verify` and `plugin lint`.
+    pub(crate) fn log(&self) {
+        for diagnostic in &self.diagnostics {
+            match diagnostic.status {
+                Status::Warning => warn!("{diagnostic}"),
+                Status::Error => error!("{diagnostic}"),
+                Status::Ok | Status::Skipped => info!("{diagnostic}"),
+            }
+        }
+    }
+
+    /// Plugin passed all checks.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.status != Status::Error)
+    }
+
+    #[must_use]
+    pub fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.status == Status::Error)
+            .count()
+    }
+}
+
+/// `manifest.yaml` of the plugin version, only fields which are checked.
+#[derive(Deserialize)]
+struct Manifest {
+    name: String,
+    version: String,
+    #[serde(default)]
+    migration: Vec<String>,
+}
+
+/// Checks the plugin at `params.path`, problems of the plugin are reported
+/// as diagnostics, errors are returned only if the checks can't be run.
+pub fn verify(params: &Params) -> Result<Report> {
+    let path = &params.path;
+    let mut report = Report::default();
+    if path.is_dir() {
+        if params.verify_key.is_some() {
+            report.skipped("signature", None, "directories are not signed");
+        }
+        verify_tree(path, params, &mut report)?;
+        return Ok(report);
+    }
+    if !path.is_file() {
+        bail!("{} does not exist", path.display());
+    }
+
+    match integrity::verify_checksum(path) {
+        Ok(true) => report.ok("checksum", None, ""),
+        Ok(false) => report.skipped(
+            "checksum",
+            None,
+            format!(
+                "{} does not exist",
+                integrity::checksum_path(path).display()
+            ),
+        ),
+        Err(err) => report.error("checksum", None, format!("{err:#}")),
+    }
+    if let Some(verify_key) = &params.verify_key {
+        match integrity::verify_signature(path, verify_key) {
+            Ok(()) => report.ok("signature", None, ""),
+            Err(err) => report.error("signature", None, format!("{err:#}")),
+        }
+    }
+
+    let unpacked = tempfile::Builder::new()
+        .prefix("pike-verify-")
+        .tempdir()
+        .context("failed to create directory for unpacked archive")?;
+    if let Err(err) = archive::unpack(path, unpacked.path()) {
+        report.error("archive", None, format!("{err:#}"));
+        return Ok(report);
+    }
+    report.ok("archive", None, "");
+    verify_tree(unpacked.path(), params, &mut report)?;
+    Ok(report)
+}
+
+/// Directories of plugin versions under `path`, which is either an unpacked
+/// archive `<plugin>/<version>/`, a shipping directory `<version>/` or a version itself.
+fn version_dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.join("manifest.yaml").exists() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let subdirs = |dir: &Path| -> Result<Vec<PathBuf>> {
+        let mut dirs = vec![];
+        for entry in fs::read_dir(dir).context(format!("failed to read {}", dir.display()))? {
+            let entry = entry.context(format!("failed to read {}", dir.display()))?;
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    };
+    let versions = subdirs(path)?;
+    if versions
+        .iter()
+        .any(|dir| dir.join("manifest.yaml").exists())
+    {
+        return Ok(versions);
+    }
+    let mut dirs = vec![];
+    for plugin in versions {
+        dirs.extend(subdirs(&plugin)?);
+    }
+    Ok(dirs)
+}
+
+fn verify_tree(path: &Path, params: &Params, report: &mut Report) -> Result<()> {
+    let dirs = version_dirs(path)?;
+    if dirs.is_empty() {
+        report.error(
+            "structure",
+            None,
+            "expected <plugin>/<version>/ directories with plugin files",
+        );
+    }
+    for dir in dirs {
+        verify_version(&dir, params, report);
+    }
+    Ok(())
+}
+
+/// Checks a single version of the plugin, e.g. `weather_cache/0.1.0/`.
+fn verify_version(dir: &Path, params: &Params, report: &mut Report) {
+    let name = |path: Option<&Path>| {
+        path.and_then(Path::file_name)
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let version = name(Some(dir));
+    let plugin = format!("{}/{version}", name(dir.parent()));
+    let plugin = Some(plugin.as_str());
+
+    let manifest_path = dir.join("manifest.yaml");
+    let manifest = fs::read_to_string(&manifest_path)
+        .context("manifest.yaml does not exist")
+        .and_then(|content| {
+            serde_norway::from_str::<Manifest>(&content).context("failed to parse manifest.yaml")
+        });
+    let manifest = match manifest {
+        Ok(manifest) => {
+            report.ok("manifest", plugin, "");
+            Some(manifest)
+        }
+        Err(err) => {
+            report.error("manifest", plugin, format!("{err:#}"));
+            None
+        }
+    };
+
+    if let Some(manifest) = &manifest {
+        if manifest.version == version {
+            report.ok("version", plugin, "");
+        } else {
+            report.error(
+                "version",
+                plugin,
+                format!(
+                    "manifest declares version {}, but the plugin is in directory {version}",
+                    manifest.version
+                ),
+            );
+        }
+    }
+
+    let library = manifest
+        .as_ref()
+        .map(|manifest| dir.join(format!("lib{}.{LIB_EXT}", manifest.name.replace('-', "_"))))
+        .filter(|path| path.is_file())
+        .or_else(|| find_library(dir));
+    match &library {
+        Some(library) => report.ok(
+            "library",
+            plugin,
+            library.file_name().unwrap_or_default().to_string_lossy(),
+        ),
+        None => report.error(
+            "library",
+            plugin,
+            format!("shared library lib<plugin>.{LIB_EXT} does not exist"),
+        ),
+    }
+
+    if let Some(manifest) = &manifest {
+        let missing: Vec<&str> = manifest
+            .migration
+            .iter()
+            .filter(|migration| !dir.join(migration).is_file())
+            .map(String::as_str)
+            .collect();
+        if missing.is_empty() {
+            report.ok(
+                "migrations",
+                plugin,
+                format!("{} listed in manifest", manifest.migration.len()),
+            );
+        } else {
+            report.error(
+                "migrations",
+                plugin,
+                format!("listed in manifest, but missing: {}", missing.join(", ")),
+            );
+        }
+    }
+
+    if params.check_symbols {
+        match library.as_deref().map(exports_service_registrar) {
+            Some(Ok(true)) => report.ok("entrypoints", plugin, SERVICE_REGISTRAR_SYMBOL),
+            Some(Ok(false)) => report.error(
+                "entrypoints",
+                plugin,
+                format!("library does not export {SERVICE_REGISTRAR_SYMBOL}, is #[service_registrar] missing?"),
+            ),
+            Some(Err(err)) => report.error("entrypoints", plugin, format!("{err:#}")),
+            None => report.skipped("entrypoints", plugin, "there is no library"),
+        }
+    }
+}
+
+/// Any shared library in the directory of the plugin version.
+fn find_library(dir: &Path) -> Option<PathBuf> {
+    let mut libraries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == LIB_EXT)
+        })
+        .collect();
+    libraries.sort();
+    libraries.into_iter().next()
+}
+
+fn exports_service_registrar(library: &Path) -> Result<bool> {
+    // Archives are checked unpacked into a temporary directory, so only the file name is reported.
+    let name = library.file_name().unwrap_or_default().to_string_lossy();
+    let data = fs::read(library).context(format!("failed to read {name}"))?;
+    let file = object::File::parse(&*data).context(format!("failed to parse {name}"))?;
+    // Mach-O prefixes C symbols with an underscore.
+    let is_registrar = |name: &[u8]| {
+        name == SERVICE_REGISTRAR_SYMBOL.as_bytes()
+            || name.strip_prefix(b"_") == Some(SERVICE_REGISTRAR_SYMBOL.as_bytes())
+    };
+    Ok(file.dynamic_symbols().chain(file.symbols()).any(|symbol| {
+        symbol.is_definition() && symbol.is_global() && symbol.name_bytes().is_ok_and(is_registrar)
+    }))
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let report = verify(params)?;
+    match params.output {
+        OutputFormat::Text => report.log(),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    if !report.is_ok() {
+        bail!(
+            "{} failed verification with {} error(s)",
+            params.path.display(),
+            report.errors()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    fn write_plugin(dir: &Path, manifest: &str) {
+        fs::create_dir_all(dir.join("migrations")).unwrap();
+        fs::write(dir.join("manifest.yaml"), manifest).unwrap();
+        fs::write(dir.join(format!("libweather_cache.{LIB_EXT}")), "").unwrap();
+        fs::write(dir.join("migrations/0001_init.sql"), "").unwrap();
+    }
+
+    fn errors(report: &Report) -> Vec<(&str, &str)> {
+        report
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.status == Status::Error)
+            .map(|diagnostic| (diagnostic.check.as_str(), diagnostic.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn problems_of_the_plugin_are_reported_together() {
+        let workspace = TempWorkspace::new("plugin-verify").unwrap();
+        let shipping_dir = workspace.join("weather_cache");
+        let params = |path: &Path| {
+            ParamsBuilder::default()
+                .path(path.to_path_buf())
+                .build()
+                .unwrap()
+        };
+
+        write_plugin(
+            &shipping_dir.join("0.1.0"),
+            "name: weather_cache\nversion: 0.1.0\nmigration:\n- migrations/0001_init.sql\n",
+        );
+        let report = verify(&params(&shipping_dir)).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.check.as_str())
+                .collect::<Vec<_>>(),
+            ["manifest", "version", "library", "migrations"]
+        );
+        assert_eq!(
+            report.diagnostics[0].plugin.as_deref(),
+            Some("weather_cache/0.1.0")
+        );
+
+        write_plugin(
+            &shipping_dir.join("0.2.0"),
+            "name: weather_cache\nversion: 0.1.0\nmigration:\n- migrations/0002_gone.sql\n",
+        );
+        fs::remove_file(shipping_dir.join(format!("0.2.0/libweather_cache.{LIB_EXT}"))).unwrap();
+        let report = verify(&params(&shipping_dir.join("0.2.0"))).unwrap();
+        assert_eq!(
+            errors(&report),
+            [
+                (
+                    "version",
+                    "manifest declares version 0.1.0, but the plugin is in directory 0.2.0"
+                ),
+                (
+                    "library",
+                    &*format!("shared library lib<plugin>.{LIB_EXT} does not exist")
+                ),
+                (
+                    "migrations",
+                    "listed in manifest, but missing: migrations/0002_gone.sql"
+                ),
+            ]
+        );
+
+        fs::write(shipping_dir.join("0.2.0/manifest.yaml"), "services: [").unwrap();
+        let report = verify(&params(&shipping_dir.join("0.2.0"))).unwrap();
+        assert_eq!(errors(&report)[0].0, "manifest");
+    }
+
+    #[test]
+    fn archive_is_unpacked_and_checked() {
+        let workspace = TempWorkspace::new("plugin-verify-archive").unwrap();
+        let version_dir = workspace.join("src/weather_cache/0.1.0");
+        write_plugin(
+            &version_dir,
+            "name: weather_cache\nversion: 0.1.0\nmigration: []\n",
+        );
+        let path = workspace.join("weather_cache_0.1.0.tar.gz");
+        let mut writer = archive::ArchiveWriter::create(
+            &path,
+            archive::ArchiveFormat::TarGz,
+            archive::CompressionLevel::Fast,
+        )
+        .unwrap();
+        writer
+            .append_path(
+                Path::new("weather_cache"),
+                &workspace.join("src/weather_cache"),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let params = ParamsBuilder::default()
+            .path(path.clone())
+            .check_symbols(true)
+            .build()
+            .unwrap();
+        let report = verify(&params).unwrap();
+        // The library is empty, so it can't be parsed.
+        assert_eq!(errors(&report).len(), 1, "{report:?}");
+        assert_eq!(errors(&report)[0].0, "entrypoints");
+        assert_eq!(report.diagnostics[0].check, "checksum");
+        assert_eq!(report.diagnostics[0].status, Status::Skipped);
+    }
+}
@@ -0,0 +1,2 @@
+pub(crate) mod profiler;
+pub(crate) mod report;
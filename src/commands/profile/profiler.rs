@@ -0,0 +1,147 @@
+//! Profilers which wrap the instance started with `run --profile-instance`.
+
+use anyhow::{bail, Result};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory in the instance data dir where profiles are stored.
+const PROFILE_DIR: &str = "profile";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Profiler {
+    /// Heap allocations profiler.
+    #[default]
+    Heaptrack,
+    /// CPU sampling profiler.
+    Perf,
+}
+
+impl FromStr for Profiler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "heaptrack" => Ok(Self::Heaptrack),
+            "perf" => Ok(Self::Perf),
+            _ => bail!("unknown profiler '{s}', expected heaptrack or perf"),
+        }
+    }
+}
+
+impl Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.binary())
+    }
+}
+
+impl Profiler {
+    pub(crate) fn binary(self) -> &'static str {
+        match self {
+            Self::Heaptrack => "heaptrack",
+            Self::Perf => "perf",
+        }
+    }
+
+    /// Command which runs `picodata` under the profiler writing the profile
+    /// into `profile_dir`, arguments of picodata are appended by the caller.
+    ///
+    /// Every run writes a new profile, previous ones are kept.
+    pub(crate) fn command(self, picodata: &Path, profile_dir: &Path) -> Command {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut command = Command::new(self.binary());
+        match self {
+            Self::Heaptrack => {
+                // heaptrack appends extension of the compression format to the file name.
+                let output = profile_dir.join(format!("heaptrack.picodata.{started_at}"));
+                command.arg("--record-only").arg("--output").arg(output);
+            }
+            Self::Perf => {
+                let output = profile_dir.join(format!("perf.{started_at}.data"));
+                command.args(["record", "-g", "-o"]).arg(output).arg("--");
+            }
+        }
+        command.arg(picodata);
+        command
+    }
+
+    /// Profiler which has written the profile, by the file name.
+    pub(crate) fn of_profile(profile: &Path) -> Option<Self> {
+        let name = profile.file_name()?.to_str()?;
+        if name.starts_with("heaptrack.") {
+            Some(Self::Heaptrack)
+        } else if name.starts_with("perf.")
+            && Path::new(name).extension().is_some_and(|ext| ext == "data")
+        {
+            Some(Self::Perf)
+        } else {
+            None
+        }
+    }
+
+    /// Program and arguments which print the report of `profile`.
+    pub(crate) fn report_command(self, profile: &Path) -> (&'static str, Vec<String>) {
+        let profile = profile.display().to_string();
+        match self {
+            Self::Heaptrack => ("heaptrack_print", vec![profile]),
+            Self::Perf => (
+                "perf",
+                vec![
+                    "report".to_string(),
+                    "--stdio".to_string(),
+                    "-i".to_string(),
+                    profile,
+                ],
+            ),
+        }
+    }
+}
+
+pub(crate) fn profile_dir(instance_dir: &Path) -> PathBuf {
+    instance_dir.join(PROFILE_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn picodata_is_started_under_profiler() {
+        let picodata = Path::new("/usr/bin/picodata");
+        let profile_dir = Path::new("/data/i1/profile");
+
+        let heaptrack = Profiler::Heaptrack.command(picodata, profile_dir);
+        assert_eq!(heaptrack.get_program(), "heaptrack");
+        let heaptrack = args(&heaptrack);
+        assert_eq!(heaptrack[..2], ["--record-only", "--output"]);
+        assert!(heaptrack[2].starts_with("/data/i1/profile/heaptrack.picodata."));
+        assert_eq!(heaptrack[3], "/usr/bin/picodata");
+        assert_eq!(
+            Profiler::of_profile(Path::new(&format!("{}.zst", heaptrack[2]))),
+            Some(Profiler::Heaptrack)
+        );
+
+        let perf = Profiler::Perf.command(picodata, profile_dir);
+        assert_eq!(perf.get_program(), "perf");
+        let perf = args(&perf);
+        assert_eq!(perf[..3], ["record", "-g", "-o"]);
+        assert_eq!(perf[4..], ["--", "/usr/bin/picodata"]);
+        assert_eq!(
+            Profiler::of_profile(Path::new(&perf[3])),
+            Some(Profiler::Perf)
+        );
+
+        assert_eq!(Profiler::of_profile(Path::new("picodata.log")), None);
+    }
+}
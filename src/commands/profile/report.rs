@@ -0,0 +1,121 @@
+//! Post-processing of profiles recorded by `run --profile-instance`.
+
+use super::profiler::{profile_dir, Profiler};
+use crate::commands::lib::{find_executable, get_cluster_dir, shell_command};
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    instance_name: String,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    /// Profile to report, the newest one from the instance directory by default.
+    #[builder(default)]
+    profile_path: Option<PathBuf>,
+    /// Print the report command without running it.
+    #[builder(default)]
+    print_only: bool,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let profile = if let Some(path) = &params.profile_path {
+        path.clone()
+    } else {
+        let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+        let profile_dir = profile_dir(&cluster_dir.join(&params.instance_name));
+        find_profile(&profile_dir)?.context(format!(
+            "no profiles found in {}, start the instance with \
+            `pike run --profile-instance {}` to record one",
+            profile_dir.display(),
+            params.instance_name
+        ))?
+    };
+    let Some(profiler) = Profiler::of_profile(&profile) else {
+        bail!(
+            "failed to detect profiler of {}, expected heaptrack.* or perf.*.data file",
+            profile.display()
+        );
+    };
+    info!("Using profile {}", profile.display());
+
+    let (program, args) = profiler.report_command(&profile);
+    println!("{}", shell_command(program, &args));
+    if params.print_only {
+        return Ok(());
+    }
+    if find_executable(Path::new(program)).is_none() {
+        warn!("{program} is not installed, run the command above when it is available");
+        return Ok(());
+    }
+
+    let status = Command::new(program)
+        .args(&args)
+        .status()
+        .context(format!("failed to execute {program}"))?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+
+    Ok(())
+}
+
+/// Returns the newest profile written by any of the profilers.
+fn find_profile(profile_dir: &Path) -> Result<Option<PathBuf>> {
+    if !profile_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut newest = None;
+    for entry in
+        fs::read_dir(profile_dir).context(format!("failed to read {}", profile_dir.display()))?
+    {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || Profiler::of_profile(&entry.path()).is_none() {
+            continue;
+        }
+        let modified = metadata.modified()?;
+        if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn newest_profile_is_found() {
+        let workspace = TempWorkspace::new("profile-report-find").unwrap();
+        assert_eq!(find_profile(&workspace.join("missing")).unwrap(), None);
+
+        let old = fs::File::create(workspace.join("heaptrack.picodata.1.zst")).unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        let new = fs::File::create(workspace.join("perf.2.data")).unwrap();
+        new.set_modified(SystemTime::now() - Duration::from_secs(30))
+            .unwrap();
+        fs::write(workspace.join("perf.data.old"), "").unwrap();
+
+        let profile = find_profile(workspace.path()).unwrap().unwrap();
+        assert_eq!(profile, workspace.join("perf.2.data"));
+
+        let (program, args) = Profiler::Perf.report_command(&profile);
+        assert_eq!(program, "perf");
+        assert_eq!(
+            args,
+            ["report", "--stdio", "-i", &profile.display().to_string()]
+        );
+    }
+}
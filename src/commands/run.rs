@@ -1,36 +1,79 @@
+mod access;
+mod bootstrap;
+mod debug_server;
+mod fingerprint;
+mod frozen;
+mod handle;
+mod plan;
+mod ports;
+mod preset;
 mod readiness;
-
+mod remote;
+mod sql_batch;
+mod supervisor;
+mod tls;
+
+pub use access::{Grant, User};
+use bootstrap::BootstrapLog;
+use debug_server::DebugServer;
+use fingerprint::Fingerprint;
+pub use handle::PicodataCluster;
+use plan::{ClusterPlugins, Step};
+pub use ports::AutoPorts;
+use ports::InstanceAddresses;
+pub use preset::ResourcePreset;
+use supervisor::Supervisor;
+pub use tls::TlsSettings;
+
+use crate::error::Error;
 use crate::healthcheck::api::get_health_status;
 use anyhow::{anyhow, bail, Context, Result};
 use colored::Colorize;
 use derive_builder::Builder;
-use log::{error, info, warn};
-use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
+use log::{debug, error, info, warn};
+use nix::sys::resource::{getrlimit, setrlimit, Resource};
 use rand::RngExt;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_norway::{Mapping, Value};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::io::ErrorKind;
 use std::net::SocketAddrV4;
 use std::os::unix::fs::symlink;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command, Stdio};
+use std::process::{Command, ExitStatus, Stdio};
 use std::str::{self};
 use std::sync::{Arc, Mutex};
-use std::thread::{self, JoinHandle};
+use std::thread;
 use std::time::{Duration, Instant};
 
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use crate::commands::lib::compat;
+use crate::commands::lib::events::{self, Event, Reporter};
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::hooks::{HookEvent, Hooks};
 use crate::commands::lib::instance_info::InstanceSocketClient;
+use crate::commands::lib::integrity;
+use crate::commands::lib::lock::DataDirLock;
+use crate::commands::lib::pid_file;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::poll::{poll_until, Backoff, RetryPolicy};
+use crate::commands::lib::process::{LogCapture, ManagedProcess};
+use crate::commands::lib::sql::redact_passwords;
+use crate::commands::lib::timings::{Timing, Timings};
 use crate::commands::lib::{
-    cargo_build, copy_directory_tree, find_active_socket_path, get_cluster_dir,
-    log_instance_skipped, log_instance_started, run_query_in_picodata_admin, spawn_picodata_admin,
-    unpack_shipping_archive,
+    cargo_build_with_env, copy_directory_tree, core_pattern, find_active_socket_path,
+    get_cluster_dir, log_instance_skipped, log_instance_started, run_query_in_picodata_admin,
+    unpack_shipping_archive, CargoArgs,
 };
-use crate::commands::lib::{get_active_socket_path, BuildType};
+use crate::commands::lib::{get_active_socket_path, BuildType, OutputFormat};
 use crate::commands::lib::{is_plugin_archive, is_plugin_dir, is_plugin_shipping_dir};
+use crate::commands::picodata;
+use crate::commands::profile::profiler::{profile_dir, Profiler};
+use crate::commands::time;
+use crate::commands::wait;
 
 const BAFFLED_WHALE: &str = r"
   __________________________________________________________
@@ -52,12 +95,118 @@ const BAFFLED_WHALE: &str = r"
                                  `-.,'
  ";
 
-const TIMEOUT_WAITING_FOR_INSTANCE_READINESS: Duration = Duration::from_secs(10);
+/// File in the cluster directory with statements executed during bootstrap.
+pub const BOOTSTRAP_FILE_NAME: &str = "bootstrap.sql";
+/// Waits of instances to become Online and pass readiness probes.
+pub const DEFAULT_RETRY_POLICY: RetryPolicy =
+    RetryPolicy::new(Duration::from_secs(60), Duration::from_millis(500));
+/// Instance started with `--debug-instance` is paused until a debugger is attached.
+const TIMEOUT_WAITING_FOR_DEBUGGER: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_DEBUG_PORT: u16 = 2345;
+/// External plugins built concurrently by default, each cargo uses all cores anyway.
+const DEFAULT_EXTERNAL_BUILD_JOBS: usize = 4;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Tier {
-    pub replicasets: u8,
-    pub replication_factor: u8,
+    pub replicasets: u16,
+    pub replication_factor: u16,
+}
+
+impl Tier {
+    /// Number of instances in the tier, `None` if it doesn't fit in `u16`.
+    #[must_use]
+    pub fn instance_count(&self) -> Option<u16> {
+        self.replicasets.checked_mul(self.replication_factor)
+    }
+}
+
+/// Settings of spawned picodata processes, `[process]` section of topology.
+#[derive(Default, Debug, Deserialize, Clone)]
+pub struct ProcessSettings {
+    /// Working directory of instances. Relative path is resolved against
+    /// the data directory of each instance, so `"."` runs every instance in its own one.
+    ///
+    /// By default instances inherit current directory of pike,
+    /// or run in their data directory if `core_dumps` is enabled.
+    pub working_dir: Option<PathBuf>,
+    /// Soft limit of open file descriptors (`RLIMIT_NOFILE`).
+    pub nofile: Option<u64>,
+    /// Lift the limit of core file size (`ulimit -c unlimited`),
+    /// so crashed instances leave core dumps in their working directory.
+    #[serde(default)]
+    pub core_dumps: bool,
+}
+
+impl ProcessSettings {
+    fn working_dir(&self, instance_data_dir: &Path) -> Option<PathBuf> {
+        match &self.working_dir {
+            Some(dir) => Some(instance_data_dir.join(dir)),
+            None if self.core_dumps => Some(instance_data_dir.to_path_buf()),
+            None => None,
+        }
+    }
+}
+
+/// Applies working directory and resource limits of `settings` to the picodata `command`.
+fn apply_process_settings(
+    settings: &ProcessSettings,
+    command: &mut Command,
+    working_dir: Option<&Path>,
+) -> Result<()> {
+    if let Some(working_dir) = working_dir {
+        fs::create_dir_all(working_dir).context(format!(
+            "failed to create working directory {}",
+            working_dir.display()
+        ))?;
+        command.current_dir(working_dir);
+    }
+
+    if let Some(nofile) = settings.nofile {
+        let (_, hard) =
+            getrlimit(Resource::RLIMIT_NOFILE).context("failed to get limit of open files")?;
+        if nofile > hard {
+            bail!("nofile limit {nofile} exceeds hard limit of open files {hard}");
+        }
+    }
+    if settings.core_dumps {
+        warn_if_cores_leave_working_dir();
+    }
+    if settings.nofile.is_none() && !settings.core_dumps {
+        return Ok(());
+    }
+
+    let nofile = settings.nofile;
+    let core_dumps = settings.core_dumps;
+    // SAFETY: only async-signal-safe setrlimit calls are made between fork and exec.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(nofile) = nofile {
+                let (_, hard) = getrlimit(Resource::RLIMIT_NOFILE)?;
+                setrlimit(Resource::RLIMIT_NOFILE, nofile, hard)?;
+            }
+            if core_dumps {
+                let (_, hard) = getrlimit(Resource::RLIMIT_CORE)?;
+                setrlimit(Resource::RLIMIT_CORE, hard, hard)?;
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// Core dumps are written relative to the working directory only if `core_pattern`
+/// is a relative path, otherwise they are handled by the system.
+fn warn_if_cores_leave_working_dir() {
+    let Some(core_pattern) = core_pattern() else {
+        return;
+    };
+    if core_pattern.starts_with('|') || core_pattern.starts_with('/') {
+        warn!(
+            "core dumps are handled by kernel.core_pattern = '{core_pattern}' \
+            and won't be written into the instance directory"
+        );
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -89,7 +238,7 @@ pub struct Plugin {
     #[serde(default)]
     #[serde(rename = "service")]
     pub services: BTreeMap<String, Service>,
-    #[serde(skip)]
+    /// Version of the plugin to install, the latest built version by default.
     pub version: Option<String>,
     /// Path to plugin, if it is located outside current directory.
     ///
@@ -99,67 +248,326 @@ pub struct Plugin {
     ///
     /// Path should conform to one of path kinds, see [`PluginPathKind`]
     pub path: Option<PathBuf>,
+    /// URL of the plugin archive downloaded instead of `path`, `http(s)://`,
+    /// `s3://` or `file://`.
+    pub url: Option<String>,
+    /// Expected SHA-256 of the archive at `url`.
+    pub sha256: Option<String>,
+    /// `<name>@<version>` of the plugin archive in the registry configured
+    /// in the `[registry]` section of `pike.toml`.
+    pub registry: Option<String>,
+    /// Plugins of the topology which are created, migrated and enabled
+    /// before this one.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Privileges on tables of the plugin granted after its migrations.
+    #[serde(default)]
+    pub grants: Vec<Grant>,
 }
 
 impl Plugin {
     fn is_external(&self) -> bool {
-        self.path.is_some()
+        self.path.is_some() || self.url.is_some() || self.registry.is_some()
+    }
+
+    fn validate(&self, name: &str) -> Result<()> {
+        let sources = [
+            self.path.is_some(),
+            self.url.is_some(),
+            self.registry.is_some(),
+        ];
+        if sources.into_iter().filter(|is_set| *is_set).count() > 1 {
+            bail!("plugin '{name}' must have only one of path, url and registry");
+        }
+        if self.sha256.is_some() && self.url.is_none() {
+            bail!("sha256 of plugin '{name}' is set without url");
+        }
+        for grant in &self.grants {
+            grant
+                .validate_grantee()
+                .context(format!("invalid grant of plugin '{name}'"))?;
+        }
+        if let Some(remote::Source::Registry {
+            name: registry_name,
+            version,
+        }) = remote::Source::of(self)?
+        {
+            if registry_name != name {
+                bail!("plugin '{name}' refers to plugin '{registry_name}' in the registry");
+            }
+            if self
+                .version
+                .as_ref()
+                .is_some_and(|pinned| *pinned != version)
+            {
+                bail!("version of plugin '{name}' differs from its registry version {version}");
+            }
+        }
+        Ok(())
     }
 }
 
+// Deserialization is wrapped by the `Deserialize` impl below to accept the deprecated section.
 #[derive(Default, Debug, Deserialize, Clone)]
+#[serde(remote = "Self")]
 pub struct Topology {
     #[serde(rename = "tier")]
     pub tiers: BTreeMap<String, Tier>,
     #[serde(rename = "plugin")]
     #[serde(default)]
     pub plugins: BTreeMap<String, Plugin>,
+    /// Environment of all instances, `[environment]` section. The misspelled
+    /// `[enviroment]` section is accepted with a warning until pike 6.0.
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+    /// Environment of instances of a tier, `[tier_environment.<tier>]` sections,
+    /// overrides `environment`.
+    #[serde(default)]
+    pub tier_environment: BTreeMap<String, BTreeMap<String, String>>,
+    /// Environment of an instance, `[instance_environment.<instance>]` sections where
+    /// instance is named by its directory, e.g. `i2`, overrides both of the above.
     #[serde(default)]
-    pub enviroment: BTreeMap<String, String>,
+    pub instance_environment: BTreeMap<String, BTreeMap<String, String>>,
+    /// Arguments appended to `picodata run` of every instance, e.g. flags unknown to pike.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /// Arguments of instances of a tier, `[tier_extra_args]` section, follow `extra_args`.
+    #[serde(default)]
+    pub tier_extra_args: BTreeMap<String, Vec<String>>,
+    /// Arguments of an instance named by its directory, `[instance_extra_args]` section,
+    /// follow both of the above.
+    #[serde(default)]
+    pub instance_extra_args: BTreeMap<String, Vec<String>>,
     #[serde(default)]
     pub pre_install_sql: Vec<String>,
     #[serde(default)]
     pub post_install_sql: Vec<String>,
+    #[serde(default)]
+    pub process: ProcessSettings,
+    /// Release of picodata installed by `picodata install` to run the cluster with,
+    /// overrides `--picodata-path`.
+    pub picodata_version: Option<String>,
+    /// Users created after the cluster is ready, before plugins are enabled.
+    #[serde(rename = "user")]
+    #[serde(default)]
+    pub users: Vec<User>,
+    /// TLS of pgproto and iproto listeners of instances.
+    #[serde(default)]
+    pub tls: TlsSettings,
+}
+
+const ENVIRONMENT_KEY: &str = "environment";
+/// Misspelled section of topology accepted until pike 6.0.
+const DEPRECATED_ENVIRONMENT_KEY: &str = "enviroment";
+
+impl<'de> Deserialize<'de> for Topology {
+    /// Renames the deprecated `enviroment` section and warns about it and unknown fields.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let mut table = toml::Table::deserialize(deserializer)?;
+        if let Some(environment) = table.remove(DEPRECATED_ENVIRONMENT_KEY) {
+            if table.contains_key(ENVIRONMENT_KEY) {
+                return Err(D::Error::custom(format!(
+                    "topology has both `{ENVIRONMENT_KEY}` and deprecated \
+                    `{DEPRECATED_ENVIRONMENT_KEY}` sections, merge them into `{ENVIRONMENT_KEY}`"
+                )));
+            }
+            warn!(
+                "`{DEPRECATED_ENVIRONMENT_KEY}` section of topology is deprecated and will be removed \
+                in pike 6.0, rename it to `{ENVIRONMENT_KEY}`"
+            );
+            table.insert(ENVIRONMENT_KEY.to_string(), environment);
+        }
+
+        let mut warn_unknown = |field: serde_ignored::Path| {
+            warn!("Unknown field in topology TOML: {field}");
+        };
+        Topology::deserialize(serde_ignored::Deserializer::new(
+            toml::Value::Table(table),
+            &mut warn_unknown,
+        ))
+        .map_err(D::Error::custom)
+    }
 }
 
 impl Topology {
+    /// Environment of all instances under the old misspelled name.
+    #[deprecated(note = "use the `environment` field, this accessor will be removed in pike 6.0")]
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn enviroment(&self) -> &BTreeMap<String, String> {
+        &self.environment
+    }
+
+    /// Number of instances in all tiers, saturates at `u16::MAX` for invalid topology.
+    #[must_use]
+    pub fn instance_count(&self) -> u16 {
+        self.tiers
+            .values()
+            .map(|tier| tier.instance_count().unwrap_or(u16::MAX))
+            .fold(0, u16::saturating_add)
+    }
+
+    /// Tier of the instance and number of the replicaset in the tier it is expected
+    /// to join: instances are started tier by tier and fill replicasets one by one.
+    #[must_use]
+    pub fn instance_placement(&self, instance_id: u16) -> Option<(&str, u16)> {
+        let mut first_id: u16 = 1;
+        for (tier_name, tier) in &self.tiers {
+            let count = tier.instance_count()?;
+            if instance_id < first_id.saturating_add(count) {
+                let replicaset_id = instance_id.checked_sub(first_id)? / tier.replication_factor;
+                return Some((tier_name, replicaset_id + 1));
+            }
+            first_id = first_id.saturating_add(count);
+        }
+        None
+    }
+
+    /// Checks that tiers are not empty, the total number of instances fits in `u16`,
+    /// every plugin has a single source, plugin dependencies have no cycles,
+    /// users are declared once with valid privileges and TLS files are complete.
+    pub fn validate(&self) -> Result<()> {
+        for (name, plugin) in &self.plugins {
+            plugin.validate(name)?;
+        }
+        self.plugins_in_install_order()?;
+        access::validate_users(&self.users)?;
+        self.tls.validate()?;
+        let tier_settings = self
+            .tier_environment
+            .keys()
+            .map(|name| ("environment", name))
+            .chain(self.tier_extra_args.keys().map(|name| ("extra_args", name)));
+        for (setting, tier_name) in tier_settings {
+            if !self.tiers.contains_key(tier_name) {
+                bail!("{setting} is set for tier '{tier_name}' missing in topology");
+            }
+        }
+        let mut total: u16 = 0;
+        for (tier_name, tier) in &self.tiers {
+            if tier.replicasets == 0 || tier.replication_factor == 0 {
+                bail!(
+                    "tier '{tier_name}' must have at least one replicaset and replication factor"
+                );
+            }
+            let Some(count) = tier.instance_count() else {
+                bail!(
+                    "tier '{tier_name}' has too many instances: {} replicasets with replication factor {}",
+                    tier.replicasets,
+                    tier.replication_factor
+                );
+            };
+            total = total.checked_add(count).with_context(|| {
+                format!(
+                    "topology has too many instances, at most {} are supported",
+                    u16::MAX
+                )
+            })?;
+        }
+        let instance_settings = self
+            .instance_environment
+            .keys()
+            .map(|name| ("environment", name))
+            .chain(
+                self.instance_extra_args
+                    .keys()
+                    .map(|name| ("extra_args", name)),
+            );
+        for (setting, instance_name) in instance_settings {
+            if !(1..=total).any(|id| PicodataInstance::make_name(id) == *instance_name) {
+                bail!(
+                    "{setting} is set for instance '{instance_name}' missing in topology, \
+                    instances are named i1..i{total}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Plugins ordered so that every plugin follows the plugins it depends on,
+    /// independent plugins keep alphabetical order.
+    pub fn plugins_in_install_order(&self) -> Result<Vec<(&str, &Plugin)>> {
+        fn visit<'a>(
+            topology: &'a Topology,
+            name: &'a str,
+            path: &mut Vec<&'a str>,
+            order: &mut Vec<(&'a str, &'a Plugin)>,
+        ) -> Result<()> {
+            if order.iter().any(|(visited, _)| *visited == name) {
+                return Ok(());
+            }
+            if let Some(start) = path.iter().position(|on_path| *on_path == name) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name);
+                bail!(
+                    "plugins depend on each other in a cycle: {}",
+                    cycle.join(" -> ")
+                );
+            }
+            let plugin = &topology.plugins[name];
+            path.push(name);
+            for dependency in &plugin.depends_on {
+                if !topology.plugins.contains_key(dependency) {
+                    bail!("plugin '{name}' depends on plugin '{dependency}' missing in topology");
+                }
+                visit(topology, dependency, path, order)?;
+            }
+            path.pop();
+            order.push((name, plugin));
+            Ok(())
+        }
+
+        let mut order = Vec::with_capacity(self.plugins.len());
+        for name in self.plugins.keys() {
+            visit(self, name, &mut vec![], &mut order)?;
+        }
+        Ok(order)
+    }
+
     /// Parse topology toml file and validate the fields.
     /// Emit warning upon meeting alien fields.
     pub fn parse_toml(path: &PathBuf) -> Result<Self> {
         let content = fs::read_to_string(path)
             .context(format!("failed to read topology from {}", path.display()))?;
 
-        let deserializer = toml::de::Deserializer::parse(&content)
+        let topology: Self = toml::from_str(&content)
             .context(format!("failed to parse topology from {}", path.display()))?;
-
-        serde_ignored::deserialize(deserializer, |f| {
-            warn!("Unknown field in topology TOML: {f}");
-        })
-        .map_err(anyhow::Error::from)
+        topology
+            .validate()
+            .context(format!("invalid topology in {}", path.display()))?;
+        Ok(topology)
     }
 
+    /// Sets the latest version found in `plugins_dir` for plugins without a pinned version,
+    /// pinned versions must be present in `plugins_dir` too.
     fn find_plugin_versions(&mut self, plugins_dir: &Path) -> Result<()> {
         for (plugin_name, plugin) in &mut self.plugins {
             let current_plugin_dir = plugins_dir.join(plugin_name);
 
             if !current_plugin_dir.exists() {
                 bail!(
-                    "plugin directory {} does not exist",
+                    "plugin directory {} does not exist, the plugin is not built",
                     current_plugin_dir.display()
                 );
             }
-            let mut versions: Vec<_> = fs::read_dir(current_plugin_dir)?
-                .map(|r| r.unwrap())
-                .collect();
-            versions.sort_by_key(fs::DirEntry::path);
-            let newest_version = versions
-                .last()
-                .unwrap()
-                .file_name()
-                .to_str()
-                .unwrap()
-                .to_string();
-            plugin.version = Some(newest_version);
+            let versions = plugin_versions(&current_plugin_dir)?;
+            match &plugin.version {
+                Some(version) if !current_plugin_dir.join(version).is_dir() => bail!(
+                    "version {version} of plugin '{plugin_name}' is not found in {}, \
+                    available versions: {}",
+                    current_plugin_dir.display(),
+                    versions.join(", ")
+                ),
+                Some(_) => {}
+                None => {
+                    plugin.version = Some(versions.last().cloned().context(format!(
+                        "no plugin versions found in {}",
+                        current_plugin_dir.display()
+                    ))?);
+                }
+            }
         }
         Ok(())
     }
@@ -169,43 +577,92 @@ impl Topology {
     }
 }
 
-fn enable_plugins(topology: &Topology, cluster_dir: &Path, picodata_path: &Path) -> Result<()> {
-    let mut queries: Vec<String> = Vec::new();
-
-    for (plugin_name, plugin) in &topology.plugins {
-        let Some(plugin_version) = plugin.version.as_ref() else {
-            bail!("plugin version is missing for '{plugin_name}'");
+/// Versions of the plugin from the oldest to the latest by semantic versioning,
+/// directories which are not versions are skipped.
+fn plugin_versions(plugin_dir: &Path) -> Result<Vec<String>> {
+    let mut versions = vec![];
+    for entry in
+        fs::read_dir(plugin_dir).context(format!("failed to read {}", plugin_dir.display()))?
+    {
+        let name = entry?.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
         };
+        let Ok(version) = semver::Version::parse(name) else {
+            debug!(
+                "skipping {name} in {}: not a plugin version",
+                plugin_dir.display()
+            );
+            continue;
+        };
+        versions.push((version, name.to_string()));
+    }
+    versions.sort();
+    Ok(versions.into_iter().map(|(_, name)| name).collect())
+}
 
-        // create plugin
-        queries.push(format!(
-            r#"CREATE PLUGIN "{plugin_name}" {plugin_version};"#
-        ));
-
-        // add services to tiers
-        for (service_name, service) in &plugin.services {
-            for tier_name in &service.tiers {
-                queries.push(format!(r#"ALTER PLUGIN "{plugin_name}" {plugin_version} ADD SERVICE "{service_name}" TO TIER "{tier_name}";"#));
-            }
-        }
+/// Reads plugins installed on the running cluster to plan the installation.
+///
+/// Empty state is returned if the cluster is not running or the state can't be read,
+/// in that case all statements are planned and "already exists" errors are ignored.
+fn fetch_cluster_plugins(cluster_dir: &Path, picodata_path: &Path) -> Result<ClusterPlugins> {
+    let Some(socket_path) = find_active_socket_path(cluster_dir)? else {
+        return Ok(ClusterPlugins::default());
+    };
+    let instance_dir = socket_path
+        .parent()
+        .context("admin socket path has no parent directory")?;
+    let picodata_path = picodata_path.to_path_buf();
+
+    Ok(
+        ClusterPlugins::fetch(&InstanceSocketClient::new(instance_dir, &picodata_path))
+            .unwrap_or_else(|err| {
+                warn!("failed to read installed plugins, planning full installation: {err:#}");
+                ClusterPlugins::default()
+            }),
+    )
+}
 
-        // add migration context
-        for migration_env in &plugin.migration_context {
-            queries.push(format!(
-                "ALTER PLUGIN \"{plugin_name}\" {plugin_version} SET migration_context.{}='{}';",
-                migration_env.name, migration_env.value
-            ));
-        }
+fn plan_plugins(
+    topology: &Topology,
+    cluster_dir: &Path,
+    picodata_path: &Path,
+    keep_stdout: bool,
+) -> Result<Vec<Step>> {
+    // Versions are checked before connecting to the cluster.
+    if let Some((plugin_name, _)) = topology.plugins.iter().find(|(_, p)| p.version.is_none()) {
+        bail!("plugin version is missing for '{plugin_name}'");
+    }
+
+    let steps = plan::plan(
+        topology,
+        &fetch_cluster_plugins(cluster_dir, picodata_path)?,
+    )?;
+    if keep_stdout {
+        eprint!("{}", plan::render(&steps));
+    } else {
+        print!("{}", plan::render(&steps));
+    }
+    Ok(steps)
+}
 
-        // run migrations
-        queries.push(format!(
-            r#"ALTER PLUGIN "{plugin_name}" MIGRATE TO {plugin_version};"#
-        ));
+fn enable_plugins(
+    topology: &Topology,
+    cluster_dir: &Path,
+    picodata_path: &Path,
+    keep_stdout: bool,
+    bootstrap: &BootstrapLog,
+    timings: &mut Timings,
+) -> Result<()> {
+    let mut queries: Vec<String> = plan_plugins(topology, cluster_dir, picodata_path, keep_stdout)?
+        .into_iter()
+        .filter_map(|step| step.query)
+        .collect();
 
-        // enable plugin
-        queries.push(format!(
-            r#"ALTER PLUGIN "{plugin_name}" {plugin_version} ENABLE;"#
-        ));
+    for (_, plugin) in topology.plugins_in_install_order()? {
+        for grant in &plugin.grants {
+            queries.extend(grant.queries_to_grantee());
+        }
     }
 
     if !topology.post_install_sql.is_empty() {
@@ -215,9 +672,9 @@ fn enable_plugins(topology: &Topology, cluster_dir: &Path, picodata_path: &Path)
         }
     }
 
-    execute_sql(cluster_dir, picodata_path, queries)?;
+    execute_sql(cluster_dir, picodata_path, &queries, bootstrap, timings)?;
 
-    for (plugin_name, plugin) in &topology.plugins {
+    for (plugin_name, plugin) in topology.plugins_in_install_order()? {
         info!(
             "Plugin {plugin_name}:{} has been enabled",
             plugin.version.as_ref().unwrap()
@@ -227,57 +684,128 @@ fn enable_plugins(topology: &Topology, cluster_dir: &Path, picodata_path: &Path)
     Ok(())
 }
 
+/// Executes queries through a single admin session of the first instance.
 fn execute_sql(
     cluster_dir: &Path,
     picodata_path: &Path,
-    queries: Vec<String>,
+    queries: &[String],
+    bootstrap: &BootstrapLog,
+    timings: &mut Timings,
 ) -> Result<(), anyhow::Error> {
     let admin_socket = cluster_dir.join("i1").join("admin.sock");
-    for query in queries {
+    let mut started = Instant::now();
+    let results = sql_batch::execute(picodata_path, &admin_socket, queries)?;
+    for (statement, result) in queries.iter().zip(&results) {
+        let query = &redact_passwords(statement);
         info!("picodata admin: {query}");
-
-        let mut picodata_admin = spawn_picodata_admin(picodata_path, &admin_socket)?;
-
-        {
-            let picodata_stdin = picodata_admin.stdin.as_mut().unwrap();
-            picodata_stdin
-                .write_all(query.as_bytes())
-                .context("failed to send plugin installation queries")?;
+        if let Some(error) = &result.error {
+            info!("picodata admin: {error}");
         }
+        if result.is_failed() {
+            let err = anyhow!(
+                "failed to execute picodata query {query}: {}",
+                result.error.as_deref().unwrap_or_default()
+            );
+            if query.contains(" MIGRATE TO ") {
+                return Err(Error::MigrationFailed(err).into());
+            }
+            return Err(err);
+        }
+        bootstrap.record(statement)?;
+        timings.record_span(query_span_name(query), started, result.elapsed);
+        started += result.elapsed;
+    }
+    if results.len() < queries.len() {
+        bail!(
+            "picodata admin returned results of {} SQL statements out of {}",
+            results.len(),
+            queries.len()
+        );
+    }
+    Ok(())
+}
 
-        let exit_code = picodata_admin
-            .wait()
-            .context("failed to wait for picodata admin")?
-            .code()
-            .unwrap();
+/// Name of the query in the startup profile, e.g. `ALTER PLUGIN "weather" 0.1.0 ENABLE`.
+fn query_span_name(query: &str) -> String {
+    const MAX_LEN: usize = 80;
 
-        let outputs: [Box<dyn Read + Send>; 2] = [
-            Box::new(picodata_admin.stdout.unwrap()),
-            Box::new(picodata_admin.stderr.unwrap()),
-        ];
+    let name = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name = name.trim_end_matches(';');
+    match name.char_indices().nth(MAX_LEN) {
+        Some((end, _)) => format!("{}...", &name[..end]),
+        None => name.to_string(),
+    }
+}
 
-        let mut ignore_errors = false;
-        for output in outputs {
-            let reader = BufReader::new(output);
-            for line in reader.lines() {
-                let line = line.expect("failed to read picodata admin output");
-                info!("picodata admin: {line}");
-
-                // Ignore some types of error messages like re-enabling the plugin
-                let err_messages_to_ignore: Vec<&str> = vec!["already enabled", "already exists"];
-                for err_message in err_messages_to_ignore {
-                    if line.contains(err_message) {
-                        ignore_errors = true;
-                    }
-                }
-            }
-        }
+/// Variables available in templates of environment and picodata config of the instance.
+fn instance_template_ctx(topology: &Topology, instance_id: u16) -> minijinja::Value {
+    let (tier, replicaset_id) = topology.instance_placement(instance_id).unzip();
+    minijinja::context! {
+        instance_id => instance_id,
+        instance_name => PicodataInstance::make_name(instance_id),
+        tier => tier,
+        replicaset_id => replicaset_id,
+    }
+}
 
-        if exit_code == 1 && !ignore_errors {
-            bail!("failed to execute picodata query {query}");
-        }
+/// Environment variables of the instance rendered from `environment`, `tier_environment`
+/// and `instance_environment` sections of topology, more specific ones take precedence.
+fn instance_env_vars(run_params: &Params, instance_id: u16) -> Result<BTreeMap<String, String>> {
+    let topology = &run_params.topology;
+    let instance_name = PicodataInstance::make_name(instance_id);
+    let tier_env = topology
+        .instance_placement(instance_id)
+        .and_then(|(tier, _)| topology.tier_environment.get(tier));
+    let instance_env = topology.instance_environment.get(&instance_name);
+
+    let templates: BTreeMap<&str, &str> = [Some(&topology.environment), tier_env, instance_env]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|(name, template)| (name.as_str(), template.as_str()))
+        .collect();
+    let mut template_env = minijinja::Environment::new();
+    for (name, template) in templates {
+        template_env.add_template(name, template)?;
     }
-    Ok(())
+
+    PicodataInstance::compute_env_vars(&template_env, &instance_template_ctx(topology, instance_id))
+}
+
+/// Arguments of `picodata run` of the instance from `extra_args`, `tier_extra_args`
+/// and `instance_extra_args` of topology in this order, rendered as templates.
+fn instance_extra_args(topology: &Topology, instance_id: u16) -> Result<Vec<String>> {
+    let tier_args = topology
+        .instance_placement(instance_id)
+        .and_then(|(tier, _)| topology.tier_extra_args.get(tier));
+    let instance_args = topology
+        .instance_extra_args
+        .get(&PicodataInstance::make_name(instance_id));
+
+    let template_env = minijinja::Environment::new();
+    let ctx = instance_template_ctx(topology, instance_id);
+    [Some(&topology.extra_args), tier_args, instance_args]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|arg| {
+            template_env
+                .render_str(arg, &ctx)
+                .context(format!("failed to render extra argument '{arg}'"))
+        })
+        .collect()
+}
+
+/// Command running picodata under the wrapper, arguments of picodata added to
+/// the command are passed to the wrapper after the picodata path.
+fn wrapped_command(wrapper: &str, picodata: &Path) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("exec {wrapper} \"$@\""))
+        .arg("sh")
+        .arg(picodata);
+    command
 }
 
 fn get_ipv4_from_template_var(
@@ -305,7 +833,7 @@ fn get_picodata_version(picodata_path: &PathBuf) -> Result<String> {
         Ok(o) => o,
         Err(err) if err.kind() == ErrorKind::NotFound => {
             println!("{BAFFLED_WHALE}");
-            bail!("Picodata not found")
+            return Err(Error::PicodataNotFound(anyhow!("Picodata not found")).into());
         }
         Err(err) => bail!("failed to get picodata version ({err})"),
     };
@@ -322,6 +850,7 @@ pub struct PicodataInstanceProperties<'a> {
     pub instance_name: &'a str,
     pub tier: &'a str,
     pub instance_id: &'a u16,
+    pub pid: &'a u32,
 }
 
 #[derive(Debug)]
@@ -329,15 +858,21 @@ pub struct PicodataInstance {
     instance_name: String,
     instance_id: u16,
     tier: String,
-    log_threads: Option<Vec<JoinHandle<()>>>,
-    child: Child,
-    daemon: bool,
+    process: ManagedProcess,
     disable_colors: bool,
     data_dir: PathBuf,
     log_file_path: PathBuf,
     pg_port: u16,
     bin_port: u16,
     http_port: u16,
+    /// Pid of picodata, differs from pid of the spawned process under a debug server or a profiler.
+    pid: u32,
+    /// Time passed from spawning the process until the instance became online.
+    startup_time: Duration,
+    /// Phases of the startup: spawn, name resolution and waiting for Online.
+    startup_phases: Vec<Timing>,
+    /// Number of restarts of the instance after crashes, see [`Supervisor`].
+    restarts: u32,
 }
 
 impl PicodataInstance {
@@ -364,42 +899,91 @@ impl PicodataInstance {
 
         fs::create_dir_all(&instance_data_dir).context("Failed to create instance data dir")?;
 
-        let mut template_env = minijinja::Environment::new();
-
-        for x in &run_params.topology.enviroment {
-            template_env.add_template(x.0.as_str(), x.1.as_str())?;
-        }
+        let env_templates_ctx = instance_template_ctx(&run_params.topology, instance_id);
+        let env_vars = instance_env_vars(run_params, instance_id)?;
+        let addresses = InstanceAddresses::new(run_params, instance_id)?;
+        let first_instance_bin_ipv4 = InstanceAddresses::new(run_params, 1)?.bin;
 
-        let env_templates_ctx = minijinja::context! {
-            instance_id => instance_id,
+        run_params.emit(&Event::InstanceStarting {
+            name: &instance_name,
+            tier,
+        });
+        let working_dir = run_params.topology.process.working_dir(&instance_data_dir);
+        // Paths passed to picodata are relative to the current directory of pike,
+        // they have to be absolute if the instance runs in another directory.
+        let arg_path = |path: &Path| -> PathBuf {
+            if working_dir.is_some() {
+                std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+            } else {
+                path.to_path_buf()
+            }
         };
-        let env_vars: BTreeMap<String, String> =
-            Self::compute_env_vars(&template_env, &env_templates_ctx)?;
 
-        let first_env_vars: BTreeMap<String, String> = Self::compute_env_vars(
-            &template_env,
-            &minijinja::context! {
-                instance_id => 1,
+        let debug_server = if run_params.debug_instance.as_ref() == Some(&instance_name) {
+            Some((
+                DebugServer::find()?,
+                debug_server::resolve_picodata(&run_params.picodata_path)?,
+            ))
+        } else {
+            None
+        };
+        let profiler = (run_params.profile_instance.as_ref() == Some(&instance_name))
+            .then_some(run_params.profiler);
+        let wrapper = run_params
+            .wrap
+            .as_ref()
+            .map(|wrap| minijinja::Environment::new().render_str(wrap, &env_templates_ctx))
+            .transpose()
+            .context("failed to render --wrap command")?;
+        if wrapper.is_some() && (debug_server.is_some() || profiler.is_some()) {
+            bail!("instance '{instance_name}' can't be wrapped and debugged or profiled at the same time")
+        }
+        let mut child = match (&debug_server, profiler) {
+            (Some(_), Some(_)) => {
+                bail!("instance '{instance_name}' can't be debugged and profiled at the same time")
+            }
+            (Some((server, picodata)), None) => server.command(picodata, run_params.debug_port),
+            (None, Some(profiler)) => {
+                let profile_dir = profile_dir(&instance_data_dir);
+                fs::create_dir_all(&profile_dir).context(format!(
+                    "failed to create profile directory {}",
+                    profile_dir.display()
+                ))?;
+                info!(
+                    "Profiling instance '{instance_name}' with {profiler}, \
+                    profiles are stored in {}",
+                    profile_dir.display()
+                );
+                let picodata = debug_server::resolve_picodata(&run_params.picodata_path)?;
+                profiler.command(&picodata, &arg_path(&profile_dir))
+            }
+            (None, None) => match &wrapper {
+                Some(wrapper) => {
+                    info!("Running instance '{instance_name}' under `{wrapper}`");
+                    let picodata = debug_server::resolve_picodata(&run_params.picodata_path)?;
+                    wrapped_command(wrapper, &picodata)
+                }
+                None => Command::new(&run_params.picodata_path),
             },
+        };
+        apply_process_settings(
+            &run_params.topology.process,
+            &mut child,
+            working_dir.as_deref(),
         )?;
-
-        let mut child = Command::new(&run_params.picodata_path);
         child.envs(&env_vars);
+        child.envs(time::instance_env(&instance_data_dir, &env_vars)?);
 
-        let first_instance_bin_ipv4 =
-            get_ipv4_from_template_var(&first_env_vars, "PICODATA_IPROTO_LISTEN")
-                .unwrap_or(format!("127.0.0.1:{}", run_params.base_bin_port + 1).parse()?);
-        let bin_ipv4 = get_ipv4_from_template_var(&env_vars, "PICODATA_IPROTO_LISTEN")
-            .unwrap_or(format!("127.0.0.1:{}", run_params.base_bin_port + instance_id).parse()?);
-        let http_ipv4 = get_ipv4_from_template_var(&env_vars, "PICODATA_HTTP_LISTEN")
-            .unwrap_or(format!("0.0.0.0:{}", run_params.base_http_port + instance_id).parse()?);
-        let pg_ipv4 = get_ipv4_from_template_var(&env_vars, "PICODATA_PG_LISTEN")
-            .unwrap_or(format!("127.0.0.1:{}", run_params.base_pg_port + instance_id).parse()?);
+        let InstanceAddresses {
+            bin: bin_ipv4,
+            http: http_ipv4,
+            pg: pg_ipv4,
+        } = addresses;
 
         child.args([
             "run",
             "--instance-dir",
-            instance_data_dir.to_str().expect("unreachable"),
+            arg_path(&instance_data_dir).to_str().expect("unreachable"),
             "--iproto-listen",
             &bin_ipv4.to_string(),
             "--peer",
@@ -413,6 +997,16 @@ impl PicodataInstance {
             "--config-parameter",
             &format!("cluster.tier={tiers_config}",),
         ]);
+        for parameter in run_params.preset.config_parameters() {
+            child.arg("--config-parameter").arg(parameter);
+        }
+        let tls = &run_params.topology.tls;
+        if tls.is_enabled() {
+            let certificates = tls.certificates(&run_params.plugin_path, &cluster_dir)?;
+            for parameter in tls.configure_instance(&certificates, &instance_data_dir)? {
+                child.arg("--config-parameter").arg(parameter);
+            }
+        }
 
         let config_path = run_params.plugin_path.join(&run_params.config_path);
         if config_path.exists() {
@@ -421,7 +1015,9 @@ impl PicodataInstance {
                 Self::template_config(&config_path, &instance_data_dir, &env_templates_ctx)?;
             child.args([
                 "--config",
-                instance_config_path.to_str().unwrap_or("./picodata.yaml"),
+                arg_path(&instance_config_path)
+                    .to_str()
+                    .unwrap_or("./picodata.yaml"),
             ]);
         } else {
             warn!(
@@ -433,43 +1029,72 @@ impl PicodataInstance {
         if let Some(plugins_dir) = plugins_dir {
             child.args([
                 "--share-dir",
-                plugins_dir.to_str().unwrap_or("target/debug"),
+                arg_path(plugins_dir).to_str().unwrap_or("target/debug"),
             ]);
         }
 
         if run_params.daemon {
             child.stdout(Stdio::null()).stderr(Stdio::null());
-            child.args(["--log", log_file_path.to_str().expect("unreachable")]);
+            child.args([
+                "--log",
+                arg_path(&log_file_path).to_str().expect("unreachable"),
+            ]);
         } else {
             child.stdout(Stdio::piped()).stderr(Stdio::piped());
         }
 
         if run_params.with_audit {
-            child.args(["--audit", audit_file_path.to_str().expect("unreachable")]);
+            child.args([
+                "--audit",
+                arg_path(&audit_file_path).to_str().expect("unreachable"),
+            ]);
         }
+        child.args(instance_extra_args(&run_params.topology, instance_id)?);
+
+        let spawned_at = Instant::now();
+        let process = ManagedProcess::spawn(&instance_name, &mut child).map_err(|err| {
+            let not_found = err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == ErrorKind::NotFound);
+            let err = err.context(format!("failed to start picodata instance: {instance_id}"));
+            if not_found {
+                Error::PicodataNotFound(err).into()
+            } else {
+                err
+            }
+        })?;
+        let spawn_time = spawned_at.elapsed();
 
-        let child = child
-            .spawn()
-            .context(format!("failed to start picodata instance: {instance_id}"))?;
-
-        let start = Instant::now();
-        while Instant::now().duration_since(start) < TIMEOUT_WAITING_FOR_INSTANCE_READINESS {
-            thread::sleep(Duration::from_millis(100));
+        let retry = if let Some((server, picodata)) = &debug_server {
+            info!(
+                "Instance '{instance_name}' is waiting for debugger, attach with: {}",
+                server.attach_command(picodata, run_params.debug_port)
+            );
+            run_params.retry.with_timeout(TIMEOUT_WAITING_FOR_DEBUGGER)
+        } else if wrapper.is_some() {
+            // The wrapper may be a debug server waiting for a debugger too.
+            run_params.retry.with_timeout(TIMEOUT_WAITING_FOR_DEBUGGER)
+        } else {
+            run_params.retry
+        };
+        let mut name_resolved_at = None;
+        let ready_instance_name = retry.poll(|| {
             let socket_client =
                 InstanceSocketClient::new(&instance_data_dir, &run_params.picodata_path);
             let Ok(new_instance_name) = socket_client
                 .instance_name()
                 .inspect_err(|err| log::debug!("failed to get name of the instance: {err}"))
             else {
-                continue;
+                return Ok(None);
             };
+            name_resolved_at.get_or_insert_with(Instant::now);
 
             // If name is already known, then socket is ready, i.e. we assume
             // call below should return without error.
             let instance_current_state = socket_client.current_state()?;
             if !instance_current_state.is_online() {
                 info!("Waiting for '{new_instance_name}' to become 'Online'");
-                continue;
+                return Ok(None);
             }
 
             // create symlink to real instance data dir
@@ -478,16 +1103,37 @@ impl PicodataInstance {
             symlink(&instance_name, symlink_name)
                 .context("failed create symlink to instance dir")?;
 
+            Ok(Some(new_instance_name))
+        })?;
+        if let Some(new_instance_name) = ready_instance_name {
             instance_name = new_instance_name;
-            break;
         }
+        let online_at = Instant::now();
+        let name_resolved_at = name_resolved_at.unwrap_or(online_at);
+        let resolving_started_at = spawned_at + spawn_time;
+        let startup_phases = vec![
+            Timing {
+                name: format!("{instance_name} spawn"),
+                duration: spawn_time,
+                started: spawned_at,
+            },
+            Timing {
+                name: format!("{instance_name} name resolution"),
+                duration: name_resolved_at.saturating_duration_since(resolving_started_at),
+                started: resolving_started_at,
+            },
+            Timing {
+                name: format!("{instance_name} online"),
+                duration: online_at.saturating_duration_since(name_resolved_at),
+                started: name_resolved_at,
+            },
+        ];
 
+        let pid = process.id();
         let mut pico_instance = PicodataInstance {
             instance_name,
             tier: tier.to_string(),
-            log_threads: None,
-            child,
-            daemon: run_params.daemon,
+            process,
             disable_colors: run_params.disable_colors,
             data_dir: instance_data_dir,
             log_file_path,
@@ -495,14 +1141,31 @@ impl PicodataInstance {
             bin_port: bin_ipv4.port(),
             http_port: http_ipv4.port(),
             instance_id,
+            pid,
+            startup_time: spawned_at.elapsed(),
+            startup_phases,
+            restarts: 0,
         };
 
         if !run_params.daemon {
-            pico_instance.capture_logs()?;
+            pico_instance.capture_logs(run_params.keeps_stdout())?;
         }
 
         // Save pid of picodata process to kill it after
         pico_instance.make_pid_file()?;
+        if debug_server.is_some() || profiler.is_some() || wrapper.is_some() {
+            // Spawned process is the debug server, the profiler or the wrapper,
+            // `stop` has to signal picodata itself.
+            if let Ok(pid) = pico_instance.socket_client(&run_params.picodata_path).pid() {
+                fs::write(pico_instance.data_dir.join("pid"), format!("{pid}\n"))
+                    .context("failed to write pid file of the debugged instance")?;
+                pico_instance.pid = pid;
+            }
+        }
+        // Instance which has failed to start is killed on drop even in daemon mode.
+        if run_params.daemon {
+            pico_instance.process.detach();
+        }
 
         Ok(pico_instance)
     }
@@ -526,9 +1189,20 @@ impl PicodataInstance {
             instance_name: &self.instance_name,
             tier: &self.tier,
             instance_id: &self.instance_id,
+            pid: &self.pid,
         }
     }
 
+    /// Runs SQL query on the instance through its admin socket
+    /// and converts each row of the result into `T`.
+    ///
+    /// Use [`Row`](crate::commands::lib::instance_info::Row) as `T`
+    /// to get rows as mappings of column names to values.
+    #[allow(dead_code)]
+    pub fn query<T: DeserializeOwned>(&self, picodata_path: &PathBuf, sql: &str) -> Result<Vec<T>> {
+        self.socket_client(picodata_path).query(sql)
+    }
+
     fn compute_env_vars(
         template_env: &minijinja::Environment,
         ctx: &minijinja::Value,
@@ -580,62 +1254,47 @@ impl PicodataInstance {
         Ok(instance_config_path)
     }
 
-    fn capture_logs(&mut self) -> Result<()> {
-        let mut rnd = rand::rng();
-        let instance_name_color = colored::CustomColor::new(
-            rnd.random_range(30..220),
-            rnd.random_range(30..220),
-            rnd.random_range(30..220),
-        );
-
-        let file = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(&self.log_file_path)
-            .expect("Failed to open log file");
-        let file = Arc::new(Mutex::new(file));
-
-        let mut log_threads = vec![];
-
-        let stdout = self.child.stdout.take().expect("Failed to capture stdout");
-        let stderr = self.child.stderr.take().expect("Failed to capture stderr");
-        let outputs: [Box<dyn Read + Send>; 2] = [Box::new(stdout), Box::new(stderr)];
-        for child_output in outputs {
-            let mut log_prefix = format!("{}: ", self.instance_name);
-            if !self.disable_colors {
-                log_prefix = log_prefix.custom_color(instance_name_color).to_string();
-            }
-            let file = file.clone();
-
-            let wrapper = move || {
-                let stdout_lines = BufReader::new(child_output).lines();
-                for line in stdout_lines {
-                    let line = line.unwrap();
-                    println!("{log_prefix}{line}");
-                    writeln!(file.lock().unwrap(), "{line}")
-                        .expect("Failed to write line to log file");
-                }
-            };
-
-            let t = thread::Builder::new()
-                .name(format!("log_catcher::{}", self.instance_name))
-                .spawn(wrapper)?;
-
-            log_threads.push(t);
+    fn capture_logs(&mut self, echo_to_stderr: bool) -> Result<()> {
+        let mut log_prefix = format!("{}: ", self.instance_name);
+        if !self.disable_colors {
+            let mut rnd = rand::rng();
+            let instance_name_color = colored::CustomColor::new(
+                rnd.random_range(30..220),
+                rnd.random_range(30..220),
+                rnd.random_range(30..220),
+            );
+            log_prefix = log_prefix.custom_color(instance_name_color).to_string();
         }
 
-        self.log_threads = Some(log_threads);
+        self.process.capture_logs(
+            &LogCapture::default()
+                .prefix(log_prefix)
+                .log_file(&self.log_file_path)
+                .echo_to_stderr(echo_to_stderr),
+        )
+    }
 
-        Ok(())
+    fn metadata(&self) -> Result<InstanceMetadata> {
+        let data_dir = std::path::absolute(&self.data_dir).context(format!(
+            "failed to get absolute path of {}",
+            self.data_dir.display()
+        ))?;
+        Ok(InstanceMetadata {
+            name: self.instance_name.clone(),
+            instance_id: self.instance_id,
+            tier: self.tier.clone(),
+            pid: self.pid,
+            admin_socket: data_dir.join("admin.sock"),
+            data_dir,
+            bin_port: self.bin_port,
+            http_port: self.http_port,
+            pg_port: self.pg_port,
+            restarts: self.restarts,
+        })
     }
 
     fn make_pid_file(&self) -> Result<()> {
-        let pid = self.child.id();
-        let pid_location = self.data_dir.join("pid");
-        let mut file = File::create(pid_location)?;
-        writeln!(file, "{pid}")?;
-        Ok(())
+        self.process.write_pid_file(&self.data_dir.join("pid"))
     }
 
     fn make_name(id: u16) -> String {
@@ -643,33 +1302,19 @@ impl PicodataInstance {
     }
 
     fn kill(&mut self) -> Result<()> {
-        Ok(self.child.kill()?)
+        self.process.kill()
     }
 
-    fn join(&mut self) {
-        let Some(threads) = self.log_threads.take() else {
-            return;
-        };
-        for h in threads {
-            h.join()
-                .expect("Failed to join thread for picodata instance");
-        }
+    fn detach(&mut self) {
+        self.process.detach();
     }
-}
-
-impl Drop for PicodataInstance {
-    fn drop(&mut self) {
-        if self.daemon {
-            return;
-        }
 
-        // Send kill to avoid infinite
-        // waiting of blocking processes.
-        let _ = self.child.kill();
+    fn wait(&mut self) -> Result<ExitStatus> {
+        self.process.wait()
+    }
 
-        self.child
-            .wait()
-            .expect("Failed to wait for picodata instance");
+    fn join(&mut self) {
+        self.process.join();
     }
 }
 
@@ -805,19 +1450,29 @@ fn get_external_plugin_path_kind(path: &Path) -> Result<PluginPathKind> {
 fn materialize_external_plugin(
     name: &str,
     kind: PluginPathKind,
-    path: &PathBuf,
+    path: &Path,
     params: &Params,
     plugin_run_dir: &Path,
 ) -> Result<()> {
     match kind {
         PluginPathKind::ShippingArchive => {
-            unpack_shipping_archive(path, plugin_run_dir).with_context(|| {
+            integrity::verify_archive(path, params.verify_key.as_deref()).with_context(|| {
+                format!(
+                    "failed to verify shipping archive for plugin '{}' from '{}'",
+                    name,
+                    path.display()
+                )
+            })?;
+            let unpacked = unpack_shipping_archive(path, plugin_run_dir).with_context(|| {
                 format!(
                     "failed to unpack shipping archive for plugin '{}' from '{}'",
                     name,
                     path.display()
                 )
             })?;
+            if !unpacked {
+                info!("Shipping archive of plugin '{name}' is already unpacked, skipping");
+            }
         }
         PluginPathKind::ShippingDirectory => {
             copy_directory_tree(path, plugin_run_dir).with_context(|| {
@@ -830,15 +1485,35 @@ fn materialize_external_plugin(
         }
         PluginPathKind::CrateOrWorkspaceDirectory => {
             let (profile, target_dir) = (params.get_build_profile(), &params.target_dir);
-            if !params.no_build {
-                cargo_build(profile, target_dir, path).with_context(|| {
+            let fingerprint = if params.skips_build() {
+                None
+            } else {
+                let fingerprint = Fingerprint::new(name, path, params, plugin_run_dir)?;
+                if !params.force_build && fingerprint.is_fresh(&plugin_run_dir.join(name))? {
+                    info!("External plugin '{name}' is up to date, skipping build");
+                    return Ok(());
+                }
+
+                params.emit(&Event::PluginBuilding { path });
+                let data_dir = params.plugin_path.join(&params.data_dir);
+                cargo_build_with_env(
+                    profile,
+                    target_dir,
+                    path,
+                    plugin_run_dir,
+                    Some(&data_dir),
+                    &params.cargo_args,
+                    &format!("[{name}] "),
+                )
+                .with_context(|| {
                     format!(
                         "failed to build external cargo plugin '{}' at '{}'",
                         name,
                         path.display()
                     )
                 })?;
-            }
+                Some(fingerprint)
+            };
             let src_shipping_dir = path.join(target_dir).join(profile.to_string()).join(name);
             copy_directory_tree(&src_shipping_dir, plugin_run_dir).with_context(|| {
                 format!(
@@ -848,15 +1523,25 @@ fn materialize_external_plugin(
                     profile
                 )
             })?;
+            if let Some(fingerprint) = fingerprint {
+                fingerprint.save()?;
+            }
         }
     }
     Ok(())
 }
 
+/// Where an external plugin from topology is taken from.
+enum ExternalSource {
+    Local(PluginPathKind, PathBuf),
+    Remote(remote::Source),
+}
+
 /// Prepares plugin directory structure for external plugins from topology
 ///
 /// Depending on whether plugin path destination is plugin project directory,
-/// built plugin directory or zip-packed plugin directory, maybe invoke cargo build
+/// built plugin directory or zip-packed plugin directory, maybe invoke cargo build.
+/// Plugins with `url` or `registry` are downloaded and unpacked as archives
 fn prepare_external_plugins(params: &Params, plugin_run_dir: &Path) -> Result<()> {
     if !params.topology.has_external_plugins() {
         return Ok(());
@@ -875,10 +1560,13 @@ fn prepare_external_plugins(params: &Params, plugin_run_dir: &Path) -> Result<()
         plugin_run_dir.display()
     );
 
-    let mut path_info: HashMap<&str, (PluginPathKind, PathBuf)> =
-        HashMap::with_capacity(external_plugins.len());
+    let mut sources: Vec<(&str, ExternalSource)> = Vec::with_capacity(external_plugins.len());
 
     for (name, plugin) in external_plugins {
+        if let Some(source) = remote::Source::of(plugin)? {
+            sources.push((name.as_str(), ExternalSource::Remote(source)));
+            continue;
+        }
         let path = plugin
             .path
             .as_ref()
@@ -890,44 +1578,115 @@ fn prepare_external_plugins(params: &Params, plugin_run_dir: &Path) -> Result<()
                 name
             )
         })?;
-        path_info.insert(name.as_str(), (kind, path.clone()));
-    }
+        sources.push((name.as_str(), ExternalSource::Local(kind, path.clone())));
+    }
+
+    // Every cargo project is built in its own target directory, builds sharing one
+    // are serialized by the lock cargo takes on it.
+    let queue = Mutex::new(sources.iter());
+    let errors = Mutex::new(vec![]);
+    let jobs = params.external_build_jobs.clamp(1, sources.len());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let Some((name, source)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = match source {
+                    ExternalSource::Local(kind, path) => {
+                        materialize_external_plugin(name, *kind, path, params, plugin_run_dir)
+                    }
+                    ExternalSource::Remote(source) => {
+                        remote::fetch(name, source, params, plugin_run_dir).and_then(|path| {
+                            materialize_external_plugin(
+                                name,
+                                PluginPathKind::ShippingArchive,
+                                &path,
+                                params,
+                                plugin_run_dir,
+                            )
+                        })
+                    }
+                };
+                if let Err(err) = result {
+                    errors.lock().unwrap().push((*name, err));
+                }
+            });
+        }
+    });
 
-    for (name, (kind, path)) in &path_info {
-        materialize_external_plugin(name, *kind, path, params, plugin_run_dir)?;
+    let mut errors = errors.into_inner().unwrap();
+    errors.sort_by_key(|(name, _)| *name);
+    if errors.len() > 1 {
+        for (_, err) in &errors {
+            error!("{err:#}");
+        }
+        let names: Vec<_> = errors.iter().map(|(name, _)| *name).collect();
+        let (_, err) = errors.swap_remove(0);
+        return Err(err.context(format!(
+            "failed to prepare external plugins {}",
+            names.join(", ")
+        )));
+    }
+    match errors.pop() {
+        Some((_, err)) => Err(err),
+        None => Ok(()),
     }
-    Ok(())
 }
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Builder, Clone)]
 pub struct Params {
     topology: Topology,
-    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
     data_dir: PathBuf,
     #[builder(default = "false")]
     disable_plugin_install: bool,
-    #[builder(default = "3000")]
+    #[builder(default = "env_or(\"base-bin-port\", 3000)")]
     base_bin_port: u16,
-    #[builder(default = "8000")]
+    #[builder(default = "env_or(\"base-http-port\", 8000)")]
     base_http_port: u16,
-    #[builder(default = "PathBuf::from(\"picodata\")")]
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
     picodata_path: PathBuf,
-    #[builder(default = "5432")]
+    #[builder(default = "env_or(\"base-pg-port\", 5432)")]
     base_pg_port: u16,
     #[builder(default = "false")]
     use_release: bool,
-    #[builder(default = "PathBuf::from(\"target\")")]
+    #[builder(default = "env_or(\"target-dir\", PathBuf::from(\"target\"))")]
     target_dir: PathBuf,
     #[builder(default = "false")]
     daemon: bool,
     #[builder(default = "false")]
     disable_colors: bool,
-    #[builder(default = "PathBuf::from(\"./\")")]
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
     plugin_path: PathBuf,
     #[builder(default = "false")]
     no_build: bool,
-    #[builder(default = "PathBuf::from(\"./picodata.yaml\")")]
+    /// Flags passed through to `cargo build` of the plugin and external cargo plugins.
+    #[builder(default)]
+    cargo_args: CargoArgs,
+    /// Build external cargo plugins even if their sources haven't changed since the last run.
+    #[builder(default = "false")]
+    force_build: bool,
+    /// Take over the lock of the data directory if its owner is not running anymore.
+    #[builder(default = "false")]
+    force_unlock: bool,
+    /// Number of external plugins prepared concurrently, e.g. built by cargo.
+    #[builder(default = "DEFAULT_EXTERNAL_BUILD_JOBS")]
+    external_build_jobs: usize,
+    /// Run only from built artifacts: skip cargo and fail up front if any of them is missing.
+    #[builder(default = "false")]
+    frozen: bool,
+    /// Minisign public key checking signatures of external plugin archives.
+    #[builder(default)]
+    verify_key: Option<PathBuf>,
+    /// Restart instances run in foreground when they crash.
+    #[builder(default = "false")]
+    supervise: bool,
+    /// Keep instances running if the cluster fails to start, they are killed by default.
+    #[builder(default = "false")]
+    keep_partial: bool,
+    #[builder(default = "env_or(\"config-path\", PathBuf::from(\"./picodata.yaml\"))")]
     config_path: PathBuf,
     #[builder(default)]
     instance_name: Option<String>,
@@ -939,6 +1698,69 @@ pub struct Params {
     wait_vshard_discovery: bool,
     #[builder(default = "300")]
     wait_vshard_discovery_timeout: u64,
+    /// Print time spent on each step of the cluster startup.
+    #[builder(default = "false")]
+    timings: bool,
+    /// Write phases of the cluster startup to the file as a trace of Chrome trace event format.
+    #[builder(default)]
+    startup_profile: Option<PathBuf>,
+    /// Print plan of plugins installation and exit without changing the cluster.
+    #[builder(default = "false")]
+    plan_only: bool,
+    /// How long to wait for the raft leader election to show Web UI of the leader,
+    /// `None` looks the leader up once without waiting.
+    #[builder(default)]
+    leader_wait: Option<Duration>,
+    /// Wait until every instance is online, replication is healthy and plugins
+    /// of the topology are enabled before `run` returns, `None` disables waiting.
+    #[builder(default)]
+    wait_ready: Option<Duration>,
+    /// Instance to start under gdbserver or lldb-server, e.g. `i1`.
+    #[builder(default)]
+    debug_instance: Option<String>,
+    /// Port of the debug server of `debug_instance`.
+    #[builder(default = "DEFAULT_DEBUG_PORT")]
+    debug_port: u16,
+    /// Instance to start under `profiler`, e.g. `i1`.
+    #[builder(default)]
+    profile_instance: Option<String>,
+    #[builder(default)]
+    profiler: Profiler,
+    /// Command every instance started by this run is launched under with picodata
+    /// and its arguments appended, e.g. `valgrind --leak-check=full`. It is split
+    /// by the shell and rendered as a template like `environment` of topology.
+    #[builder(default)]
+    wrap: Option<String>,
+    /// How base ports of a new cluster are changed if some of them are in use,
+    /// `None` fails on busy ports.
+    #[builder(default)]
+    auto_ports: Option<AutoPorts>,
+    /// Memory settings of instances on top of `config_path`.
+    #[builder(default)]
+    preset: ResourcePreset,
+    /// Print lifecycle events to stdout as NDJSON, logs go to stderr.
+    #[builder(default = "false")]
+    events_json: bool,
+    /// Receiver of progress events for programs embedding pike.
+    #[builder(default, setter(strip_option))]
+    reporter: Option<Arc<dyn Reporter>>,
+    /// Print started instances as JSON once the cluster is ready.
+    #[builder(default)]
+    output: OutputFormat,
+    /// Waits of instances to become Online and ready.
+    #[builder(default = "DEFAULT_RETRY_POLICY")]
+    retry: RetryPolicy,
+    /// Release of picodata installed by `picodata install`, wins over
+    /// `picodata_version` of the topology and `picodata_path`.
+    #[builder(default)]
+    picodata_version: Option<String>,
+    /// Don't check picodata against pike and `picodata-plugin` of the plugin,
+    /// see [`compat`].
+    #[builder(default = "false")]
+    skip_compat_check: bool,
+    /// Enable TLS of pgproto, see [`TlsSettings`].
+    #[builder(default = "false")]
+    tls: bool,
 }
 
 impl Params {
@@ -959,6 +1781,49 @@ impl Params {
     pub fn get_cluster_dir(&self) -> PathBuf {
         get_cluster_dir(&self.plugin_path, &self.data_dir)
     }
+
+    /// Parameters of the readiness check of the started cluster, see `--wait-ready`.
+    fn wait_params(&self, timeout: Duration) -> wait::Params {
+        wait::ParamsBuilder::default()
+            .data_dir(self.data_dir.clone())
+            .plugin_path(self.plugin_path.clone())
+            .picodata_path(self.picodata_path.clone())
+            .topology(Some(self.topology.clone()))
+            .check_plugins(!self.disable_plugin_install)
+            .timeout(timeout)
+            .build()
+            .unwrap()
+    }
+
+    fn skips_build(&self) -> bool {
+        self.no_build || self.frozen
+    }
+
+    /// Stdout is kept for events or the JSON result, so other output goes to stderr.
+    fn keeps_stdout(&self) -> bool {
+        self.events_json || self.output == OutputFormat::Json
+    }
+
+    fn emit(&self, event: &Event) {
+        if self.events_json {
+            events::emit(event);
+        }
+        if let Some(reporter) = &self.reporter {
+            reporter.report(event);
+        }
+    }
+
+    fn emit_instance_started(&self, instance: &PicodataInstance) {
+        if !self.events_json && self.reporter.is_none() {
+            return;
+        }
+        match instance.metadata() {
+            Ok(metadata) => self.emit(&Event::InstanceStarted {
+                instance: &metadata,
+            }),
+            Err(err) => warn!("failed to describe started instance: {err:#}"),
+        }
+    }
 }
 
 fn configure_web_auth<F>(
@@ -987,12 +1852,22 @@ where
 }
 
 // При ошибке только предупреждаем, запуск не падает
-fn apply_web_auth_setting(params: &Params, cluster_dir: &Path) -> Result<()> {
+fn apply_web_auth_setting(
+    params: &Params,
+    cluster_dir: &Path,
+    bootstrap: Option<&BootstrapLog>,
+) -> Result<()> {
     let Some(socket_path) = find_active_socket_path(cluster_dir)? else {
         bail!("не удалось найти активный admin.sock для применения настройки WebUI auth");
     };
 
-    let run_admin = |p: &Path, s: &Path, q: &str| run_query_in_picodata_admin(p, s, q);
+    let run_admin = |p: &Path, s: &Path, q: &str| {
+        let output = run_query_in_picodata_admin(p, s, q)?;
+        if let Some(bootstrap) = bootstrap {
+            bootstrap.record(q)?;
+        }
+        Ok(output)
+    };
 
     if let Err(err) = configure_web_auth(
         &params.picodata_path,
@@ -1065,7 +1940,7 @@ fn run_single_instance(
     let mut instance_id_counter = 0;
     let mut instance_tier_name = &String::new();
     for (tier_name, tier) in &params.topology.tiers {
-        instance_id_counter += u16::from(tier.replicasets * tier.replication_factor);
+        instance_id_counter += tier.instance_count().unwrap_or(u16::MAX);
         if instance_id <= instance_id_counter {
             instance_tier_name = tier_name;
             break;
@@ -1076,36 +1951,137 @@ fn run_single_instance(
         PicodataInstance::new(instance_id, plugins_dir, instance_tier_name, params)?;
 
     log_instance_started(instance_name);
+    params.emit_instance_started(&pico_instance);
 
-    apply_web_auth_setting(params, &cluster_dir)?;
+    apply_web_auth_setting(params, &cluster_dir, None)?;
 
     Ok(vec![pico_instance])
 }
 
-fn run_cluster(params: &Params, plugins_dir: Option<&PathBuf>) -> Result<Vec<PicodataInstance>> {
+/// Describes started instances in `cluster.json`, so external tools
+/// can connect to them without parsing logs of pike.
+fn save_instances_metadata(params: &Params, instances: &[PicodataInstance]) -> Result<()> {
+    if instances.is_empty() {
+        return Ok(());
+    }
+
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let mut metadata = ClusterMetadata::load(&path)?.unwrap_or_else(|| ClusterMetadata {
+        base_ports: ports::base_ports(params),
+        instances: vec![],
+        with_web_auth: params.with_web_auth,
+    });
+    metadata.with_web_auth = params.with_web_auth;
+    metadata.update_instances(
+        instances
+            .iter()
+            .map(PicodataInstance::metadata)
+            .collect::<Result<Vec<_>>>()?,
+    );
+    metadata.save(&path)
+}
+
+fn record_instances_startup(timings: &mut Timings, instances: &[PicodataInstance]) {
+    for instance in instances {
+        let name = format!("{} startup", instance.instance_name);
+        match instance.startup_phases.first() {
+            Some(spawn) => timings.record_step(name, spawn.started, instance.startup_time),
+            None => timings.record(name, instance.startup_time),
+        }
+        for phase in &instance.startup_phases {
+            timings.record_span(phase.name.clone(), phase.started, phase.duration);
+        }
+    }
+}
+
+fn run_cluster(
+    params: &Params,
+    plugins_dir: Option<&PathBuf>,
+    timings: &mut Timings,
+) -> Result<Vec<PicodataInstance>> {
     assert!(params.instance_name.is_none(), "invariant");
 
     let cluster_dir = params.get_cluster_dir();
+    let hooks = PikeConfig::load(&params.plugin_path)?.hooks;
     let picodata_version = get_picodata_version(&params.picodata_path)?;
 
     info!("Running the cluster with {picodata_version}...");
     let start_cluster_run = Instant::now();
     let mut picodata_processes = start_instances_in_tiers(params, plugins_dir)?;
+    record_instances_startup(timings, &picodata_processes);
+    if let Err(err) = bootstrap_cluster(params, plugins_dir, timings, &picodata_processes, &hooks) {
+        roll_back(params, &mut picodata_processes);
+        return Err(err);
+    }
+
+    info!(
+        "Picodata cluster has started (launch time: {} sec, total instances: {})",
+        start_cluster_run.elapsed().as_secs(),
+        picodata_processes.len()
+    );
+    let instance_names: Vec<&str> = picodata_processes
+        .iter()
+        .map(|p| p.instance_name.as_str())
+        .collect();
+    hooks.fire(
+        HookEvent::PostStart,
+        &cluster_dir,
+        serde_json::json!({"instances": instance_names}),
+    );
+
+    Ok(picodata_processes)
+}
+
+/// Waits for started instances and installs plugins of the topology into the cluster.
+fn bootstrap_cluster(
+    params: &Params,
+    plugins_dir: Option<&PathBuf>,
+    timings: &mut Timings,
+    picodata_processes: &[PicodataInstance],
+    hooks: &Hooks,
+) -> Result<()> {
+    let cluster_dir = params.get_cluster_dir();
+    let bootstrap = BootstrapLog::create(&cluster_dir)?;
 
-    readiness::wait_instances_ready(&picodata_processes)?;
-    apply_web_auth_setting(params, &cluster_dir)?;
+    timings.measure("readiness checks", || {
+        readiness::wait_instances_ready(picodata_processes, &params.retry)
+    })?;
+    apply_web_auth_setting(params, &cluster_dir, Some(&bootstrap))?;
 
     if params.wait_vshard_discovery {
-        readiness::wait_vshard_discovery(&picodata_processes, params)?;
+        timings.measure("vshard discovery", || {
+            readiness::wait_vshard_discovery(picodata_processes, params)
+        })?;
     }
 
     if !params.topology.pre_install_sql.is_empty() {
         info!("Executing pre-install SQL scripts...");
-        let mut queries: Vec<String> = Vec::new();
-        for query in &params.topology.pre_install_sql {
-            queries.push(query.clone());
+        timings.measure_with("pre-install SQL", |timings| {
+            execute_sql(
+                &cluster_dir,
+                &params.picodata_path,
+                &params.topology.pre_install_sql,
+                &bootstrap,
+                timings,
+            )
+        })?;
+    }
+
+    if !params.topology.users.is_empty() {
+        info!("Creating users...");
+        let mut queries = vec![];
+        for user in &params.topology.users {
+            queries.extend(user.queries()?);
         }
-        execute_sql(&cluster_dir, &params.picodata_path, queries)?;
+        timings.measure_with("users", |timings| {
+            execute_sql(
+                &cluster_dir,
+                &params.picodata_path,
+                &queries,
+                &bootstrap,
+                timings,
+            )
+        })?;
     }
 
     if !params.disable_plugin_install && !params.topology.plugins.is_empty() {
@@ -1113,24 +2089,65 @@ fn run_cluster(params: &Params, plugins_dir: Option<&PathBuf>) -> Result<Vec<Pic
             bail!("failed to enable plugins: directory with plugins is missing.")
         }
         info!("Enabling plugins...");
-        let result = enable_plugins(&params.topology, &cluster_dir, &params.picodata_path);
-        if let Err(e) = result {
-            for process in &mut picodata_processes {
-                process.kill().unwrap_or_else(|e| {
-                    error!("failed to kill picodata instances: {e:#}");
-                });
-            }
-            bail!("failed to enable plugins: {e}");
+        let result = timings.measure_with("plugin install", |timings| {
+            enable_plugins(
+                &params.topology,
+                &cluster_dir,
+                &params.picodata_path,
+                params.keeps_stdout(),
+                &bootstrap,
+                timings,
+            )
+        });
+        result.context("failed to enable plugins")?;
+        for (plugin_name, plugin) in params.topology.plugins_in_install_order()? {
+            params.emit(&Event::PluginEnabled {
+                plugin: plugin_name,
+                version: plugin.version.as_deref(),
+            });
+            hooks.fire(
+                HookEvent::PluginEnabled,
+                &cluster_dir,
+                serde_json::json!({"plugin": plugin_name, "version": plugin.version}),
+            );
         }
     }
 
-    info!(
-        "Picodata cluster has started (launch time: {} sec, total instances: {})",
-        start_cluster_run.elapsed().as_secs(),
-        picodata_processes.len()
-    );
+    Ok(())
+}
 
-    Ok(picodata_processes)
+/// Kills instances started by this run after it has failed and removes their pid files,
+/// so the cluster is not left half-started. With `keep_partial` they are kept running.
+fn roll_back(params: &Params, instances: &mut [PicodataInstance]) {
+    if instances.is_empty() {
+        return;
+    }
+    if params.keep_partial {
+        for instance in instances.iter_mut() {
+            instance.detach();
+        }
+        warn!(
+            "Started instances are kept running because of --keep-partial, \
+            stop them with `cargo pike stop`"
+        );
+        return;
+    }
+    info!("Stopping {} started instance(s)...", instances.len());
+    for instance in instances.iter_mut() {
+        if let Err(e) = instance.kill() {
+            error!("failed to kill picodata instance: {e:#}");
+            continue;
+        }
+        // Pid of the killed instance still exists until it is reaped,
+        // its pid file becomes stale only after the wait.
+        if let Err(e) = instance.wait() {
+            error!("{e:#}");
+            continue;
+        }
+        if let Err(e) = pid_file::remove_if_stale(&params.get_cluster_dir(), &instance.data_dir) {
+            error!("{e:#}");
+        }
+    }
 }
 
 /// Spins up Picodata instances for all tiers defined in the provided topology.
@@ -1153,7 +2170,7 @@ fn start_instances_in_tiers(
 
     for (tier_name, tier) in &params.topology.tiers {
         info!("Starting instances in tier '{tier_name}' ...");
-        for _ in 0..(tier.replicasets * tier.replication_factor) {
+        for _ in 0..tier.instance_count().unwrap_or_default() {
             instance_id += 1;
             let instance_name = PicodataInstance::make_name(instance_id);
 
@@ -1162,26 +2179,73 @@ fn start_instances_in_tiers(
                 continue;
             }
 
-            let pico_instance = PicodataInstance::new(instance_id, plugins_dir, tier_name, params)?;
+            let pico_instance =
+                match PicodataInstance::new(instance_id, plugins_dir, tier_name, params) {
+                    Ok(pico_instance) => pico_instance,
+                    Err(err) => {
+                        roll_back(params, &mut picodata_processes);
+                        return Err(err);
+                    }
+                };
+            log_instance_started(instance_name);
+            params.emit_instance_started(&pico_instance);
             picodata_processes.push(pico_instance);
+        }
+    }
 
-            log_instance_started(instance_name);
+    if let Some(debug_instance) = &params.debug_instance {
+        if !picodata_processes
+            .iter()
+            .any(|p| p.data_dir.ends_with(debug_instance))
+        {
+            warn!("instance '{debug_instance}' is not started by this run, it is not debugged");
+        }
+    }
+    if let Some(profile_instance) = &params.profile_instance {
+        if !picodata_processes
+            .iter()
+            .any(|p| p.data_dir.ends_with(profile_instance))
+        {
+            warn!("instance '{profile_instance}' is not started by this run, it is not profiled");
         }
     }
 
     Ok(picodata_processes)
 }
 
-fn prepare_directory_with_plugins(params: &mut Params) -> anyhow::Result<Option<PathBuf>> {
+fn prepare_directory_with_plugins(
+    params: &mut Params,
+    timings: &mut Timings,
+) -> anyhow::Result<Option<PathBuf>> {
+    if params.frozen {
+        frozen::check_artifacts(params)?;
+    }
+
     if is_plugin_dir(&params.plugin_path) {
         let plugins_dir = params.get_plugins_dir();
         let build_profile = params.get_build_profile();
 
-        if !params.no_build {
-            cargo_build(build_profile, &params.target_dir, &params.plugin_path)?;
+        if !params.skips_build() {
+            params.emit(&Event::PluginBuilding {
+                path: &params.plugin_path,
+            });
+            timings.measure("cargo build", || {
+                let data_dir = params.plugin_path.join(&params.data_dir);
+                cargo_build_with_env(
+                    build_profile,
+                    &params.target_dir,
+                    &params.plugin_path,
+                    &plugins_dir,
+                    Some(&data_dir),
+                    &params.cargo_args,
+                    "",
+                )
+            })?;
         }
 
-        prepare_external_plugins(params, &plugins_dir)?;
+        timings.measure("external plugins", || {
+            prepare_external_plugins(params, &plugins_dir)
+        })?;
         params.topology.find_plugin_versions(&plugins_dir)?;
         return Ok(Some(plugins_dir));
     }
@@ -1198,7 +2262,9 @@ fn prepare_directory_with_plugins(params: &mut Params) -> anyhow::Result<Option<
             )
         })?;
 
-        prepare_external_plugins(params, &plugins_dir)?;
+        timings.measure("external plugins", || {
+            prepare_external_plugins(params, &plugins_dir)
+        })?;
         params.topology.find_plugin_versions(&plugins_dir)?;
         return Ok(Some(plugins_dir));
     }
@@ -1213,94 +2279,262 @@ fn prepare_directory_with_plugins(params: &mut Params) -> anyhow::Result<Option<
     Ok(None)
 }
 
-pub fn cluster(mut params: Params) -> Result<Vec<PicodataInstance>> {
-    let plugins_dir = prepare_directory_with_plugins(&mut params)?;
+/// Starts the cluster and returns its handle.
+#[allow(dead_code)]
+pub fn cluster(mut params: Params) -> Result<PicodataCluster> {
+    let (instances, plugins_dir) = cluster_with_timings(&mut params, &mut Timings::default())?;
+    Ok(PicodataCluster::new(params, plugins_dir, instances))
+}
+
+/// Starts the cluster, `params` are completed with ports and versions of plugins
+/// actually used, so instances can be started again with them.
+///
+/// Returns started instances and the directory with plugins.
+fn cluster_with_timings(
+    params: &mut Params,
+    timings: &mut Timings,
+) -> Result<(Vec<PicodataInstance>, Option<PathBuf>)> {
+    params.topology.validate()?;
+    // Released once instances are started, so the running cluster can be stopped meanwhile.
+    let _lock = (!params.plan_only)
+        .then(|| {
+            DataDirLock::acquire(
+                &params.plugin_path,
+                &params.data_dir,
+                "run",
+                params.force_unlock,
+            )
+        })
+        .transpose()?;
+    if let Some(version) = params
+        .picodata_version
+        .clone()
+        .or_else(|| params.topology.picodata_version.clone())
+    {
+        params.picodata_path = picodata::resolve(&version)?;
+        info!(
+            "Using picodata {version} at {}",
+            params.picodata_path.display()
+        );
+    }
+    if !params.skip_compat_check {
+        let version_output = get_picodata_version(&params.picodata_path)?;
+        compat::check(&params.plugin_path, &version_output)?;
+    }
+    if params.tls {
+        params.topology.tls.pgproto = true;
+    }
+    if params.topology.tls.is_enabled() && !params.plan_only {
+        // Certificates are generated once before instances start.
+        params
+            .topology
+            .tls
+            .certificates(&params.plugin_path, &params.get_cluster_dir())?;
+    }
+    let plugins_dir = prepare_directory_with_plugins(params, timings)?;
+
+    if params.plan_only {
+        let steps = plan_plugins(
+            &params.topology,
+            &params.get_cluster_dir(),
+            &params.picodata_path,
+            params.keeps_stdout(),
+        )?;
+        if params.output == OutputFormat::Json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "plan": steps }))?
+            );
+        }
+        return Ok((vec![], plugins_dir));
+    }
+
+    // Leftovers of instances which have crashed or have been killed with the machine.
+    pid_file::remove_stale(&params.get_cluster_dir())?;
+    let has_saved_ports = ports::restore_base_ports(params)?;
 
     if params.instance_name.is_some() {
         info!("Starting single cluster instance");
-        return run_single_instance(&params, plugins_dir.as_ref());
+        let instances = run_single_instance(params, plugins_dir.as_ref())?;
+        record_instances_startup(timings, &instances);
+        save_instances_metadata(params, &instances)?;
+        return Ok((instances, plugins_dir));
     }
 
-    if let Some(sock_path) = find_active_socket_path(&params.get_cluster_dir())? {
+    let cluster_dir = params.get_cluster_dir();
+    let stopped_instances: Vec<u16> = (1..=params.topology.instance_count())
+        .filter(|&id| {
+            get_active_socket_path(&cluster_dir, &PicodataInstance::make_name(id)).is_none()
+        })
+        .collect();
+
+    if let Some(sock_path) = find_active_socket_path(&cluster_dir)? {
         info!("Cluster is running (connected via {})", sock_path.display());
+        // Ports of the running cluster can't be changed, revived instances must use the same ones.
+        if params.auto_ports.is_some() {
+            warn!("--auto-ports is ignored, because the cluster is already running");
+        }
+        ports::ensure_ports_available(params, &stopped_instances, None)?;
         // Reviving terminated instances and exit.
-        return start_instances_in_tiers(&params, plugins_dir.as_ref());
+        let instances = start_instances_in_tiers(params, plugins_dir.as_ref())?;
+        record_instances_startup(timings, &instances);
+        save_instances_metadata(params, &instances)?;
+        return Ok((instances, plugins_dir));
     }
 
-    run_cluster(&params, plugins_dir.as_ref())
+    // Instances of the stopped cluster are restarted with the ports they had before.
+    if has_saved_ports && params.auto_ports.is_some() {
+        warn!("--auto-ports is ignored, ports of the cluster are already saved in its data dir");
+    }
+    let auto_ports = params.auto_ports.filter(|_| !has_saved_ports);
+    ports::ensure_ports_available(params, &stopped_instances, auto_ports)?;
+    ports::save_base_ports(params)?;
+
+    let instances = run_cluster(params, plugins_dir.as_ref(), timings)?;
+    save_instances_metadata(params, &instances)?;
+    Ok((instances, plugins_dir))
 }
 
-fn print_webui_url(pico_instances: &[PicodataInstance]) {
-    let Some(first) = pico_instances.first() else {
-        return;
-    };
+/// Name of the raft leader from health status of the instance,
+/// `None` if no leader has been elected yet or the health query fails.
+fn leader_name(instance: &PicodataInstance) -> Option<String> {
+    get_health_status(instance)
+        .ok()
+        .map(|s| s.raft.leader_name)
+        .filter(|name| !name.is_empty())
+}
+
+/// Polls health status of the instance until the raft leader is known.
+///
+/// Returns `None` if no leader has been elected within `leader_wait`.
+fn wait_leader_name(instance: &PicodataInstance, leader_wait: Duration) -> Option<String> {
+    let deadline = Instant::now() + leader_wait;
+    poll_until(deadline, &Backoff::default(), || Ok(leader_name(instance)))
+        .ok()
+        .flatten()
+}
+
+/// Prints URL of Web UI, to stderr if stdout is kept for events or the result.
+fn print_webui_url(
+    params: &Params,
+    pico_instances: &[PicodataInstance],
+    timings: &mut Timings,
+) -> Option<String> {
+    let first = pico_instances.first()?;
 
     // Prefer the raft leader's HTTP port
     // Fall back to the first instance if the leader is unknown for any reason
     // e.g. election still in progress or the health query fails.
-    let leader_name = get_health_status(first)
-        .ok()
-        .map(|s| s.raft.leader_name)
-        .unwrap_or_default();
-    // Find the leader instance by name; an empty name means no leader elected yet.
-    let leader_instance = pico_instances
-        .iter()
-        .find(|i| !leader_name.is_empty() && i.properties().instance_name == leader_name);
+    // Single instance is the leader itself, so there is nothing to wait for.
+    let leader_name = match params.leader_wait {
+        _ if pico_instances.len() == 1 => None,
+        Some(leader_wait) => {
+            params.emit(&Event::LeaderWaiting);
+            timings.measure("leader election", || wait_leader_name(first, leader_wait))
+        }
+        None => leader_name(first),
+    };
+    let leader_instance = leader_name.and_then(|leader_name| {
+        pico_instances
+            .iter()
+            .find(|i| i.properties().instance_name == leader_name)
+    });
     let port = leader_instance.unwrap_or(first).http_port();
 
-    let url = format!("http://localhost:{port}").bold();
-    println!("\nCluster is running. To open Web UI, visit:\n  {url}\n");
+    let url = format!("http://localhost:{port}");
+    let message = format!(
+        "\nCluster is running. To open Web UI, visit:\n  {}\n",
+        url.bold()
+    );
+    if params.keeps_stdout() {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+    Some(url)
 }
 
-pub fn cmd(params: Params) -> Result<()> {
-    let is_daemon_mode = params.daemon;
-    let mut pico_instances = cluster(params)?;
-
-    print_webui_url(&pico_instances);
+/// Started cluster printed with `--output json`.
+#[derive(Debug, Serialize)]
+struct RunResult {
+    cluster_dir: PathBuf,
+    webui_url: Option<String>,
+    instances: Vec<InstanceMetadata>,
+}
 
-    if is_daemon_mode {
+pub fn cmd(mut params: Params) -> Result<()> {
+    if params.events_json && params.output == OutputFormat::Json {
+        bail!("--events-json and --output json can't be used together, both are printed to stdout");
+    }
+    let is_daemon_mode = params.daemon;
+    let hooks = PikeConfig::load(&params.plugin_path)?.hooks;
+    let print_timings = params.timings;
+    let plan_only = params.plan_only;
+    let mut timings = Timings::default();
+    let (mut pico_instances, plugins_dir) = cluster_with_timings(&mut params, &mut timings)?;
+    if plan_only {
         return Ok(());
     }
-
-    // Set Ctrl+C handler. Upon receiving Ctrl+C signal
-    // All instances would be killed, then joined and
-    // destructors will be called
-    let picodata_pids: Vec<u32> = pico_instances.iter().map(|p| p.child.id()).collect();
-    ctrlc::set_handler(move || {
-        info!("received Ctrl+C. Shutting down ...");
-
-        for &pid in &picodata_pids {
-            let _ = kill(Pid::from_raw(pid.cast_signed()), Signal::SIGKILL);
+    if let Some(timeout) = params.wait_ready {
+        let result = timings.measure("cluster readiness", || {
+            wait::cmd(&params.wait_params(timeout))
+        });
+        if let Err(err) = result {
+            roll_back(&params, &mut pico_instances);
+            return Err(err);
         }
-    })
-    .context("failed to set Ctrl+c handler")?;
+    }
 
-    // Wait for all instances to stop
-    for instance in &mut pico_instances {
-        instance.join();
+    let instance_names = pico_instances
+        .iter()
+        .map(|i| i.instance_name.as_str())
+        .collect();
+    params.emit(&Event::ClusterReady {
+        instances: instance_names,
+    });
+    let webui_url = print_webui_url(&params, &pico_instances, &mut timings);
+    if let Some(path) = &params.startup_profile {
+        timings.save_chrome_trace(path)?;
+        info!("Startup profile is written to {}", path.display());
+    }
+    if print_timings && params.keeps_stdout() {
+        eprint!("{timings}");
+    } else if print_timings {
+        print!("{timings}");
+    }
+    if params.output == OutputFormat::Json {
+        let result = RunResult {
+            cluster_dir: std::path::absolute(params.get_cluster_dir())?,
+            webui_url,
+            instances: pico_instances
+                .iter()
+                .map(PicodataInstance::metadata)
+                .collect::<Result<_>>()?,
+        };
+        println!("{}", serde_json::to_string_pretty(&result)?);
     }
 
+    if is_daemon_mode {
+        return Ok(());
+    }
+
+    Supervisor::new(&params, plugins_dir.as_ref(), hooks)?.wait(pico_instances);
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
     use crate::commands::lib::LIB_EXT;
     use flate2::write::GzEncoder;
     use flate2::Compression;
     use std::cell::RefCell;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::fs::File;
     use tar::Builder;
 
-    fn tmp_dir(prefix: &str) -> PathBuf {
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let mut dir = std::env::temp_dir();
-        dir.push(format!("pike-run-ut-{prefix}-{ts}"));
-        dir
+    fn tmp_dir(name: &str) -> TempWorkspace {
+        TempWorkspace::new(&format!("run-{name}")).unwrap()
     }
 
     fn capture_runner(
@@ -1346,19 +2580,10 @@ mod tests {
         );
     }
 
-    fn temp_dir_unique(prefix: &str) -> PathBuf {
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let dir = std::env::temp_dir().join(format!("{prefix}_{nanos}"));
-        fs::create_dir_all(&dir).unwrap();
-        dir
-    }
-
     #[test]
     fn merged_cluster_tier_config_ok_when_config_missing() {
-        let plugin_dir = temp_dir_unique("pike_test_missing");
+        let workspace = tmp_dir("missing");
+        let plugin_dir = workspace.path();
         // config_path relative to plugin_dir
         let config_path = PathBuf::from("picodata.yaml");
 
@@ -1371,7 +2596,7 @@ mod tests {
             },
         );
 
-        let json = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers).unwrap();
+        let json = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers).unwrap();
         let v: serde_json::Value = serde_json::from_str(&json).unwrap();
         assert!(v.get("t1").is_some());
         assert_eq!(
@@ -1383,7 +2608,8 @@ mod tests {
 
     #[test]
     fn merged_cluster_tier_config_ok_when_config_empty() {
-        let plugin_dir = temp_dir_unique("pike_test_empty");
+        let workspace = tmp_dir("empty");
+        let plugin_dir = workspace.path();
         let config_path = PathBuf::from("picodata.yaml");
         let config_file = plugin_dir.join(&config_path);
         fs::write(&config_file, "").unwrap();
@@ -1397,14 +2623,15 @@ mod tests {
             },
         );
 
-        let json = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers).unwrap();
+        let json = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers).unwrap();
         let v: serde_json::Value = serde_json::from_str(&json).unwrap();
         assert_eq!(v["t1"]["replication_factor"].as_i64().unwrap(), 3);
     }
 
     #[test]
     fn merged_cluster_tier_config_errors_on_invalid_yaml() {
-        let plugin_dir = temp_dir_unique("pike_test_invalid");
+        let workspace = tmp_dir("invalid");
+        let plugin_dir = workspace.path();
         let config_path = PathBuf::from("picodata.yaml");
         let config_file = plugin_dir.join(&config_path);
         // invalid YAML
@@ -1419,13 +2646,14 @@ mod tests {
             },
         );
 
-        let res = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers);
+        let res = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers);
         assert!(res.is_err(), "invalid YAML must return error");
     }
 
     #[test]
     fn merged_cluster_tier_config_preserves_valid_config_and_overrides_replication_factor() {
-        let plugin_dir = temp_dir_unique("pike_test_valid");
+        let workspace = tmp_dir("valid");
+        let plugin_dir = workspace.path();
         let config_path = PathBuf::from("picodata.yaml");
         let config_file = plugin_dir.join(&config_path);
         let yaml = r#"
@@ -1454,7 +2682,7 @@ mod tests {
             },
         );
 
-        let json = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers).unwrap();
+        let json = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers).unwrap();
         let v: serde_json::Value = serde_json::from_str(&json).unwrap();
 
         // t1 must become a map with replication_factor
@@ -1467,7 +2695,8 @@ mod tests {
 
     #[test]
     fn merged_cluster_tier_config_errors_on_non_mapping_root() {
-        let plugin_dir = temp_dir_unique("pike_test_non_mapping_root");
+        let workspace = tmp_dir("non_mapping_root");
+        let plugin_dir = workspace.path();
         let config_path = PathBuf::from("picodata.yaml");
         let config_file = plugin_dir.join(&config_path);
 
@@ -1482,7 +2711,7 @@ mod tests {
             },
         );
 
-        let err = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers).unwrap_err();
+        let err = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers).unwrap_err();
         let msg = format!("{err:#}");
         assert!(
             msg.contains("expected YAML mapping"),
@@ -1503,17 +2732,33 @@ mod tests {
                         services: BTreeMap::new(),
                         version: None,
                         path: None,
+                        url: None,
+                        sha256: None,
+                        registry: None,
+                        depends_on: vec![],
+                        grants: vec![],
                     },
                 );
                 m
             },
-            enviroment: BTreeMap::new(),
+            environment: BTreeMap::new(),
             pre_install_sql: vec![],
             post_install_sql: vec![],
+            ..Default::default()
         };
-        let cluster_dir = temp_dir_unique("pike_test_cluster");
+        let workspace = tmp_dir("cluster");
+        let cluster_dir = workspace.path();
         let picodata_path = Path::new("picodata");
-        let err = enable_plugins(&topology, &cluster_dir, picodata_path).unwrap_err();
+        let bootstrap = BootstrapLog::create(cluster_dir).unwrap();
+        let err = enable_plugins(
+            &topology,
+            cluster_dir,
+            picodata_path,
+            false,
+            &bootstrap,
+            &mut Timings::default(),
+        )
+        .unwrap_err();
         let msg = format!("{err:#}");
         assert!(
             msg.contains("plugin version is missing"),
@@ -1523,7 +2768,8 @@ mod tests {
 
     #[test]
     fn merged_cluster_tier_config_errors_on_null_root() {
-        let plugin_dir = temp_dir_unique("pike_test_null_root");
+        let workspace = tmp_dir("null_root");
+        let plugin_dir = workspace.path();
         let config_path = PathBuf::from("picodata.yaml");
         let config_file = plugin_dir.join(&config_path);
 
@@ -1538,7 +2784,7 @@ mod tests {
             },
         );
 
-        let err = get_merged_cluster_tier_config(&plugin_dir, &config_path, &tiers).unwrap_err();
+        let err = get_merged_cluster_tier_config(plugin_dir, &config_path, &tiers).unwrap_err();
         assert!(
             format!("{err:#}").contains("expected YAML mapping"),
             "expected error about null root, got: {err}"
@@ -1548,7 +2794,8 @@ mod tests {
     #[test]
     fn external_plugin_absolute_path_supported() {
         // Create a shipping directory structure: plugin_name/version/manifest.yaml
-        let base = tmp_dir("abs");
+        let workspace = tmp_dir("abs");
+        let base = workspace.path();
         let plugin_name = "abs_plugin";
         let version = "0.1.0";
         let shipping_root = base.join(plugin_name).join(version);
@@ -1561,7 +2808,8 @@ mod tests {
 
     #[test]
     fn materialize_external_plugin_shipping_directory() {
-        let base = tmp_dir("mat_dir");
+        let workspace = tmp_dir("mat_dir");
+        let base = workspace.path();
         let src = base.join("test_plugin");
         let dst = base.join("dst");
         fs::create_dir_all(&src).unwrap();
@@ -1586,8 +2834,9 @@ mod tests {
 
     #[test]
     fn materialize_external_plugin_shipping_archive() {
-        let base = tmp_dir("mat_arc");
-        fs::create_dir_all(&base).unwrap();
+        let workspace = tmp_dir("mat_arc");
+        let base = workspace.path();
+        fs::create_dir_all(base).unwrap();
         let archive_path = base.join("plugin.tar.gz");
         let dst = base.join("dst");
         fs::create_dir_all(&dst).unwrap();
@@ -1638,9 +2887,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn shipping_archive_is_unpacked_once() {
+        let workspace = tmp_dir("unpack_once");
+        let base = workspace.path();
+        let archive_path = base.join("plugin.tar.gz");
+        let dst = base.join("dst");
+        fs::create_dir_all(&dst).unwrap();
+        let pack = |version: &str, manifest: &str| {
+            let file = File::create(&archive_path).unwrap();
+            let mut tar = Builder::new(GzEncoder::new(file, Compression::default()));
+            for (name, content) in [
+                ("manifest.yaml", manifest),
+                (&format!("libtest.{LIB_EXT}"), ""),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_cksum();
+                let path = format!("test_plugin/{version}/{name}");
+                tar.append_data(&mut header, path, content.as_bytes())
+                    .unwrap();
+            }
+            tar.into_inner().unwrap().finish().unwrap();
+        };
+        // Version built by cargo is not touched.
+        fs::create_dir_all(dst.join("test_plugin/0.0.1")).unwrap();
+
+        pack("0.1.0", "name: test_plugin");
+        assert!(unpack_shipping_archive(&archive_path, &dst).unwrap());
+        assert_eq!(
+            fs::read_to_string(dst.join("test_plugin/0.1.0.sha256")).unwrap(),
+            integrity::sha256(&archive_path).unwrap()
+        );
+        assert!(!unpack_shipping_archive(&archive_path, &dst).unwrap());
+
+        // Removed version dir is unpacked again.
+        fs::remove_dir_all(dst.join("test_plugin/0.1.0")).unwrap();
+        assert!(unpack_shipping_archive(&archive_path, &dst).unwrap());
+
+        pack("0.2.0", "name: test_plugin");
+        assert!(unpack_shipping_archive(&archive_path, &dst).unwrap());
+        assert!(dst.join("test_plugin/0.2.0/manifest.yaml").exists());
+        assert!(!dst.join("test_plugin/0.1.0").exists());
+        assert!(!dst.join("test_plugin/0.1.0.sha256").exists());
+        assert!(dst.join("test_plugin/0.0.1").exists());
+
+        pack("0.2.0", "name: test_plugin\n");
+        assert!(unpack_shipping_archive(&archive_path, &dst).unwrap());
+        assert_eq!(
+            fs::read_to_string(dst.join("test_plugin/0.2.0/manifest.yaml")).unwrap(),
+            "name: test_plugin\n"
+        );
+    }
+
     #[test]
     fn materialize_external_plugin_crate_no_build() {
-        let base = tmp_dir("mat_crate");
+        let workspace = tmp_dir("mat_crate");
+        let base = workspace.path();
         let plugin_path = base.join("my_plugin");
         // emulate build artifact: target/debug/my_plugin/manifest.yaml
         let artifact_path = plugin_path.join("target/debug/my_plugin");
@@ -1669,6 +2972,53 @@ mod tests {
 
         assert!(dst.join("my_plugin/manifest.yaml").exists());
     }
+
+    #[test]
+    fn external_plugins_are_prepared_concurrently() {
+        use std::fmt::Write as _;
+
+        let workspace = tmp_dir("ext_plugins");
+        let base = workspace.path();
+        let mut toml_str =
+            String::from("[tier.default]\nreplicasets = 1\nreplication_factor = 1\n");
+        for name in ["a_plugin", "b_plugin", "c_plugin", "d_plugin"] {
+            let plugin_path = base.join(name);
+            fs::create_dir_all(&plugin_path).unwrap();
+            fs::write(plugin_path.join("Cargo.toml"), "").unwrap();
+            fs::write(plugin_path.join("manifest.yaml.template"), "").unwrap();
+            // Only the first two plugins are built.
+            if name < "c_plugin" {
+                let artifact_path = plugin_path.join("target/debug").join(name);
+                fs::create_dir_all(&artifact_path).unwrap();
+                fs::write(artifact_path.join("manifest.yaml"), "artifact").unwrap();
+            }
+            writeln!(
+                toml_str,
+                "[plugin.{name}]\npath = \"{}\"",
+                plugin_path.display()
+            )
+            .unwrap();
+        }
+        let dst = base.join("dst");
+        fs::create_dir_all(&dst).unwrap();
+
+        let params = ParamsBuilder::default()
+            .topology(toml::from_str(&toml_str).unwrap())
+            .no_build(true)
+            .external_build_jobs(2)
+            .build()
+            .unwrap();
+        let err = prepare_external_plugins(&params, &dst).unwrap_err();
+
+        assert!(dst.join("a_plugin/manifest.yaml").exists());
+        assert!(dst.join("b_plugin/manifest.yaml").exists());
+        assert_eq!(
+            err.to_string(),
+            "failed to prepare external plugins c_plugin, d_plugin"
+        );
+        assert!(format!("{err:#}").contains("failed to copy built plugin 'c_plugin'"));
+    }
+
     #[test]
     fn test_topology_deserialization_with_pre_install_sql() {
         let toml_str = r#"
@@ -1699,6 +3049,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_topology_deserialization_with_users() {
+        let toml_str = r#"
+        [tier.default]
+        replicasets = 1
+        replication_factor = 1
+
+        [[user]]
+        name = "tester"
+        password_env = "TESTER_PASSWORD"
+        roles = ["analyst"]
+        grants = [{ table = "orders", privileges = ["read"] }]
+
+        [[user]]
+        name = "tester"
+        password = "Passw0rd"
+        "#;
+        let topology: Topology = toml::from_str(toml_str).unwrap();
+        assert_eq!(topology.users.len(), 2);
+        assert_eq!(
+            topology.users[0].password_env.as_deref(),
+            Some("TESTER_PASSWORD")
+        );
+        assert_eq!(topology.users[0].grants[0].table.as_deref(), Some("orders"));
+        assert!(topology
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("user 'tester' is declared more than once"));
+    }
+
     #[test]
     fn test_topology_deserialization_with_post_install_sql() {
         let toml_str = r#"
@@ -1728,4 +3109,345 @@ mod tests {
             "INSERT INTO \"t\" VALUES (3);"
         );
     }
+
+    #[test]
+    fn test_topology_validation_of_instance_count() {
+        let toml_str = r"
+        [tier.default]
+        replicasets = 20
+        replication_factor = 3
+
+        [tier.storage]
+        replicasets = 100
+        replication_factor = 2
+        ";
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.validate().unwrap();
+        assert_eq!(topology.instance_count(), 260);
+
+        topology.tiers.get_mut("storage").unwrap().replicasets = 40000;
+        let err = topology.validate().unwrap_err();
+        assert!(err.to_string().contains("too many instances"), "{err:#}");
+        assert_eq!(topology.instance_count(), u16::MAX);
+
+        topology
+            .tiers
+            .get_mut("storage")
+            .unwrap()
+            .replication_factor = 0;
+        assert!(topology.validate().is_err());
+    }
+
+    #[test]
+    fn test_topology_validation_of_plugin_sources() {
+        let toml_str = r#"
+        [tier.default]
+        replicasets = 1
+        replication_factor = 1
+
+        [plugin.weather]
+        url = "https://artifacts.local/weather/0.2.0/weather_0.2.0-ubuntu_24.04.tar.gz"
+        sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+
+        [plugin.cache]
+        registry = "cache@0.3.1"
+        "#;
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.validate().unwrap();
+        assert!(topology.has_external_plugins());
+
+        let cache = topology.plugins.get_mut("cache").unwrap();
+        cache.version = Some("0.3.0".into());
+        let err = topology.validate().unwrap_err();
+        assert!(err.to_string().contains("registry version 0.3.1"), "{err}");
+
+        let cache = topology.plugins.get_mut("cache").unwrap();
+        cache.version = None;
+        cache.registry = Some("other@0.3.1".into());
+        let err = topology.validate().unwrap_err();
+        assert!(
+            err.to_string().contains("refers to plugin 'other'"),
+            "{err}"
+        );
+
+        let cache = topology.plugins.get_mut("cache").unwrap();
+        cache.registry = None;
+        cache.sha256 = Some("0".repeat(64));
+        let err = topology.validate().unwrap_err();
+        assert!(err.to_string().contains("set without url"), "{err}");
+
+        topology.plugins.get_mut("cache").unwrap().sha256 = None;
+        let weather = topology.plugins.get_mut("weather").unwrap();
+        weather.path = Some("../weather".into());
+        let err = topology.validate().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("only one of path, url and registry"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_plugin_install_order() {
+        let toml_str = r#"
+        [tier.default]
+        replicasets = 1
+        replication_factor = 1
+
+        [plugin.auth]
+        depends_on = ["storage"]
+
+        [plugin.billing]
+        depends_on = ["auth", "storage"]
+
+        [plugin.storage]
+
+        [plugin.metrics]
+        "#;
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.validate().unwrap();
+        let order: Vec<&str> = topology
+            .plugins_in_install_order()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(order, ["storage", "auth", "billing", "metrics"]);
+
+        topology.plugins.get_mut("storage").unwrap().depends_on = vec!["billing".into()];
+        let err = topology.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "plugins depend on each other in a cycle: auth -> storage -> billing -> auth"
+        );
+
+        topology.plugins.get_mut("storage").unwrap().depends_on = vec!["cache".into()];
+        let err = topology.validate().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("plugin 'storage' depends on plugin 'cache' missing in topology"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn plugin_versions_are_compared_as_semver() {
+        let workspace = tmp_dir("plugin_versions");
+        let plugins_dir = workspace.path();
+        for version in ["0.2.0", "0.9.0", "0.10.0", "0.10.0-rc.1", "latest"] {
+            fs::create_dir_all(plugins_dir.join("p").join(version)).unwrap();
+            fs::create_dir_all(plugins_dir.join("pinned").join(version)).unwrap();
+        }
+
+        let toml_str = r#"
+        [tier.default]
+        replicasets = 1
+        replication_factor = 1
+
+        [plugin.p]
+        [plugin.pinned]
+        version = "0.2.0"
+        "#;
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.find_plugin_versions(plugins_dir).unwrap();
+        assert_eq!(topology.plugins["p"].version.as_deref(), Some("0.10.0"));
+        assert_eq!(topology.plugins["pinned"].version.as_deref(), Some("0.2.0"));
+
+        assert_eq!(
+            plugin_versions(&plugins_dir.join("p")).unwrap(),
+            ["0.2.0", "0.9.0", "0.10.0-rc.1", "0.10.0"]
+        );
+
+        topology.plugins.get_mut("pinned").unwrap().version = Some("0.3.0".into());
+        let err = topology.find_plugin_versions(plugins_dir).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("version 0.3.0 of plugin 'pinned' is not found"),
+            "{err:#}"
+        );
+
+        fs::create_dir_all(plugins_dir.join("empty/latest")).unwrap();
+        topology.plugins.clear();
+        topology.plugins.insert("empty".into(), Plugin::default());
+        let err = topology.find_plugin_versions(plugins_dir).unwrap_err();
+        assert!(
+            err.to_string().contains("no plugin versions found"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn test_topology_deserialization_with_process_settings() {
+        let toml_str = r"
+        [tier.default]
+        replicasets = 1
+        replication_factor = 1
+
+        [process]
+        nofile = 65536
+        core_dumps = true
+        ";
+        let topology: Topology = toml::from_str(toml_str).unwrap();
+        assert_eq!(topology.process.nofile, Some(65536));
+        assert!(topology.process.core_dumps);
+
+        // Instances with core dumps run in their data directory by default.
+        let instance_dir = Path::new("tmp/cluster/i1");
+        assert_eq!(
+            topology.process.working_dir(instance_dir).as_deref(),
+            Some(instance_dir)
+        );
+        let settings = ProcessSettings {
+            working_dir: Some("work".into()),
+            ..ProcessSettings::default()
+        };
+        assert_eq!(
+            settings.working_dir(instance_dir),
+            Some(instance_dir.join("work"))
+        );
+        assert_eq!(ProcessSettings::default().working_dir(instance_dir), None);
+    }
+
+    #[test]
+    fn test_instance_environment_is_overridden_by_tier_and_instance() {
+        let toml_str = r#"
+        [tier.router]
+        replicasets = 1
+        replication_factor = 1
+
+        [tier.storage]
+        replicasets = 2
+        replication_factor = 2
+
+        [environment]
+        CACHE = "small"
+        NAME = "{{ tier }}_{{ replicaset_id }}/{{ instance_name }}"
+
+        [tier_environment.storage]
+        CACHE = "large"
+
+        [instance_environment.i5]
+        CACHE = "{{ instance_id * 10 }}"
+        "#;
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.validate().unwrap();
+        // The misspelled section is still accepted, but not together with the right one.
+        let misspelled = toml_str.replace("[environment]", "[enviroment]");
+        let misspelled: Topology = toml::from_str(&misspelled).unwrap();
+        assert_eq!(misspelled.environment, topology.environment);
+        let both = format!("{toml_str}\n[enviroment]\nOTHER = \"1\"\n");
+        let err = toml::from_str::<Topology>(&both).unwrap_err();
+        assert!(err.to_string().contains("both `environment`"), "{err}");
+        assert_eq!(topology.instance_placement(1), Some(("router", 1)));
+        assert_eq!(topology.instance_placement(3), Some(("storage", 1)));
+        assert_eq!(topology.instance_placement(4), Some(("storage", 2)));
+        assert_eq!(topology.instance_placement(6), None);
+
+        let params = ParamsBuilder::default()
+            .topology(topology.clone())
+            .build()
+            .unwrap();
+        let env = |instance_id| instance_env_vars(&params, instance_id).unwrap();
+        assert_eq!(env(1)["CACHE"], "small");
+        assert_eq!(env(1)["NAME"], "router_1/i1");
+        assert_eq!(env(3)["CACHE"], "large");
+        assert_eq!(env(4)["NAME"], "storage_2/i4");
+        assert_eq!(env(5)["CACHE"], "50");
+
+        topology
+            .instance_environment
+            .insert("i6".into(), BTreeMap::new());
+        let err = topology.validate().unwrap_err();
+        assert!(err.to_string().contains("instance 'i6'"), "{err}");
+        topology.instance_environment.clear();
+        topology
+            .tier_environment
+            .insert("arbiter".into(), BTreeMap::new());
+        let err = topology.validate().unwrap_err();
+        assert!(err.to_string().contains("tier 'arbiter'"), "{err}");
+    }
+
+    #[test]
+    fn test_extra_args_are_appended_by_tier_and_instance() {
+        let toml_str = r#"
+        extra_args = ["--log-level", "verbose"]
+
+        [tier.router]
+        replicasets = 1
+        replication_factor = 1
+
+        [tier.storage]
+        replicasets = 1
+        replication_factor = 2
+
+        [tier_extra_args]
+        storage = ["--memtx-memory", "2G"]
+
+        [instance_extra_args]
+        i3 = ["--failure-domain", "HOST={{ instance_name }}"]
+        "#;
+        let mut topology: Topology = toml::from_str(toml_str).unwrap();
+        topology.validate().unwrap();
+        assert_eq!(
+            instance_extra_args(&topology, 1).unwrap(),
+            ["--log-level", "verbose"]
+        );
+        assert_eq!(
+            instance_extra_args(&topology, 3).unwrap(),
+            [
+                "--log-level",
+                "verbose",
+                "--memtx-memory",
+                "2G",
+                "--failure-domain",
+                "HOST=i3"
+            ]
+        );
+
+        topology.tier_extra_args.insert("arbiter".into(), vec![]);
+        let err = topology.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "extra_args is set for tier 'arbiter' missing in topology"
+        );
+    }
+
+    #[test]
+    fn wrapped_command_passes_picodata_and_its_arguments_to_wrapper() {
+        let output = wrapped_command("env 'WRAPPER=a  b'", Path::new("sh"))
+            .args(["-c", "echo \"$WRAPPER|$0|$1\"", "x y"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a  b|x y|\n");
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingReporter(std::sync::Mutex<Vec<String>>);
+
+    impl Reporter for RecordingReporter {
+        fn report(&self, event: &Event) {
+            let event = serde_json::to_value(event).unwrap();
+            self.0.lock().unwrap().push(event["event"].to_string());
+        }
+    }
+
+    #[test]
+    fn events_are_reported_to_reporter() {
+        let reporter = Arc::new(RecordingReporter::default());
+        let params = ParamsBuilder::default()
+            .topology(Topology::default())
+            .reporter(reporter.clone())
+            .build()
+            .unwrap();
+
+        params.emit(&Event::PluginBuilding {
+            path: Path::new("./"),
+        });
+        params.emit(&Event::LeaderWaiting);
+        assert_eq!(
+            *reporter.0.lock().unwrap(),
+            ["\"plugin_building\"", "\"leader_waiting\""]
+        );
+    }
 }
@@ -0,0 +1,336 @@
+//! Users, roles and privileges declared in `[[user]]` sections of the topology,
+//! created after the cluster is ready and before plugins are enabled, and grants
+//! of plugins applied after their migrations.
+
+use crate::commands::lib::sql::{quote_ident, quote_literal};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::env;
+
+/// Authentication methods of picodata users.
+const AUTH_METHODS: [&str; 3] = ["md5", "chap-sha1", "ldap"];
+
+/// Privileges on tables, `create` is granted only on all tables.
+const TABLE_PRIVILEGES: [&str; 5] = ["read", "write", "create", "alter", "drop"];
+
+/// User created by pike, e.g. to connect over pgproto in tests.
+#[derive(Default, Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct User {
+    pub name: String,
+    pub password: Option<String>,
+    /// Environment variable with the password, so it isn't kept in the topology.
+    pub password_env: Option<String>,
+    /// `md5` (default, required by pgproto), `chap-sha1` or `ldap`.
+    pub auth_method: Option<String>,
+    /// Roles granted to the user, created if missing.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+}
+
+/// Privileges on a table or on all tables.
+#[derive(Default, Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Grant {
+    /// Table the privileges are granted on, all tables if not set.
+    pub table: Option<String>,
+    /// `read`, `write`, `create`, `alter` or `drop`.
+    pub privileges: Vec<String>,
+    /// User or role the privileges of the plugin are granted to,
+    /// privileges of `[[user]]` are granted to the user itself.
+    pub to: Option<String>,
+}
+
+impl Grant {
+    fn validate(&self, grantee: &str) -> Result<()> {
+        if self.privileges.is_empty() {
+            bail!("grant to '{grantee}' has no privileges");
+        }
+        for privilege in &self.privileges {
+            let privilege = privilege.to_lowercase();
+            if !TABLE_PRIVILEGES.contains(&privilege.as_str()) {
+                bail!(
+                    "unknown privilege '{privilege}' granted to '{grantee}', expected one of: {}",
+                    TABLE_PRIVILEGES.join(", ")
+                );
+            }
+            if privilege == "create" && self.table.is_some() {
+                bail!("privilege 'create' granted to '{grantee}' can't be limited to a table");
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the grant of a plugin, which must name its grantee.
+    pub(super) fn validate_grantee(&self) -> Result<()> {
+        let Some(grantee) = &self.to else {
+            bail!("grant of {} has no `to`", self.object_name());
+        };
+        self.validate(grantee)
+    }
+
+    fn object_name(&self) -> String {
+        match &self.table {
+            Some(table) => format!("table '{table}'"),
+            None => "all tables".to_string(),
+        }
+    }
+
+    /// `GRANT` statements of the plugin grant to its grantee.
+    pub(super) fn queries_to_grantee(&self) -> Vec<String> {
+        self.to
+            .as_deref()
+            .map(|grantee| self.queries(grantee))
+            .unwrap_or_default()
+    }
+
+    /// `GRANT` statements of the privileges, one per privilege.
+    pub(super) fn queries(&self, grantee: &str) -> Vec<String> {
+        let object = match &self.table {
+            Some(table) => format!("ON TABLE {}", quote_ident(table)),
+            None => "TABLE".to_string(),
+        };
+        self.privileges
+            .iter()
+            .map(|privilege| {
+                format!(
+                    "GRANT {} {object} TO {}",
+                    privilege.to_uppercase(),
+                    quote_ident(grantee)
+                )
+            })
+            .collect()
+    }
+}
+
+impl User {
+    fn auth_method(&self) -> &str {
+        self.auth_method.as_deref().unwrap_or("md5")
+    }
+
+    pub(super) fn validate(&self) -> Result<()> {
+        let name = &self.name;
+        if name.is_empty() {
+            bail!("user name must not be empty");
+        }
+        if !AUTH_METHODS.contains(&self.auth_method()) {
+            bail!(
+                "unknown auth_method '{}' of user '{name}', expected one of: {}",
+                self.auth_method(),
+                AUTH_METHODS.join(", ")
+            );
+        }
+        match (&self.password, &self.password_env, self.auth_method()) {
+            (Some(_), Some(_), _) => {
+                bail!("user '{name}' must have only one of password and password_env")
+            }
+            (None, None, method) if method != "ldap" => {
+                bail!("user '{name}' must have password or password_env")
+            }
+            (Some(_), _, "ldap") | (_, Some(_), "ldap") => {
+                bail!("user '{name}' authenticated with ldap must not have a password")
+            }
+            _ => {}
+        }
+        for grant in &self.grants {
+            if grant.to.is_some() {
+                bail!(
+                    "grant of {} to user '{name}' must not have `to`",
+                    grant.object_name()
+                );
+            }
+            grant.validate(name)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_password(&self) -> Result<Option<String>> {
+        if let Some(var) = &self.password_env {
+            return env::var(var).map(Some).with_context(|| {
+                format!(
+                    "variable {var} with password of user '{}' is not set",
+                    self.name
+                )
+            });
+        }
+        Ok(self.password.clone())
+    }
+
+    /// Statements creating the user with its roles and privileges, existing
+    /// users and roles are kept as is.
+    pub(super) fn queries(&self) -> Result<Vec<String>> {
+        let user = quote_ident(&self.name);
+        let password = match self.resolve_password()? {
+            Some(password) => format!(" WITH PASSWORD {}", quote_literal(&password)),
+            None => String::new(),
+        };
+        let mut queries = vec![format!(
+            "CREATE USER IF NOT EXISTS {user}{password} USING {}",
+            self.auth_method()
+        )];
+        for role in &self.roles {
+            let role = quote_ident(role);
+            queries.push(format!("CREATE ROLE IF NOT EXISTS {role}"));
+            queries.push(format!("GRANT {role} TO {user}"));
+        }
+        for grant in &self.grants {
+            queries.extend(grant.queries(&self.name));
+        }
+        Ok(queries)
+    }
+}
+
+/// Checks users of the topology, names must be unique.
+pub(super) fn validate_users(users: &[User]) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for user in users {
+        user.validate()?;
+        if !names.insert(&user.name) {
+            bail!("user '{}' is declared more than once", user.name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(toml: &str) -> User {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn user_queries_create_roles_and_grant_privileges() {
+        let user = user(
+            r#"
+            name = "tester"
+            password = "Passw0rd'"
+            roles = ["analyst"]
+            grants = [
+                { privileges = ["create", "read"] },
+                { table = "orders", privileges = ["WRITE"] },
+            ]
+            "#,
+        );
+        user.validate().unwrap();
+        assert_eq!(
+            user.queries().unwrap(),
+            [
+                r#"CREATE USER IF NOT EXISTS "tester" WITH PASSWORD 'Passw0rd''' USING md5"#,
+                r#"CREATE ROLE IF NOT EXISTS "analyst""#,
+                r#"GRANT "analyst" TO "tester""#,
+                r#"GRANT CREATE TABLE TO "tester""#,
+                r#"GRANT READ TABLE TO "tester""#,
+                r#"GRANT WRITE ON TABLE "orders" TO "tester""#,
+            ]
+        );
+
+        let ldap = User {
+            name: "ldap_user".to_string(),
+            auth_method: Some("ldap".to_string()),
+            ..Default::default()
+        };
+        ldap.validate().unwrap();
+        assert_eq!(
+            ldap.queries().unwrap(),
+            [r#"CREATE USER IF NOT EXISTS "ldap_user" USING ldap"#]
+        );
+    }
+
+    #[test]
+    fn invalid_users_are_rejected() {
+        let err = |toml: &str| user(toml).validate().unwrap_err().to_string();
+        assert!(err(r#"name = "u""#).contains("must have password or password_env"));
+        assert!(err(r#"name = "u"
+            password = "a"
+            password_env = "B""#)
+        .contains("only one of"));
+        assert!(err(r#"name = "u"
+            password = "a"
+            auth_method = "plain""#)
+        .contains("unknown auth_method 'plain'"));
+        assert!(err(r#"name = "u"
+            password = "a"
+            grants = [{ privileges = ["execute"] }]"#)
+        .contains("unknown privilege 'execute'"));
+        assert!(err(r#"name = "u"
+            password = "a"
+            grants = [{ table = "t", privileges = ["create"] }]"#)
+        .contains("can't be limited to a table"));
+
+        let users = [
+            user(
+                r#"name = "u"
+            password = "a""#,
+            ),
+            user(
+                r#"name = "u"
+            password = "b""#,
+            ),
+        ];
+        assert!(validate_users(&users)
+            .unwrap_err()
+            .to_string()
+            .contains("declared more than once"));
+    }
+
+    #[test]
+    fn plugin_grants_need_grantee() {
+        let grant: Grant = toml::from_str(
+            r#"
+            table = "weather"
+            privileges = ["read", "write"]
+            to = "analyst"
+            "#,
+        )
+        .unwrap();
+        grant.validate_grantee().unwrap();
+        assert_eq!(
+            grant.queries_to_grantee(),
+            [
+                r#"GRANT READ ON TABLE "weather" TO "analyst""#,
+                r#"GRANT WRITE ON TABLE "weather" TO "analyst""#,
+            ]
+        );
+
+        let grant = Grant { to: None, ..grant };
+        assert!(grant
+            .validate_grantee()
+            .unwrap_err()
+            .to_string()
+            .contains("grant of table 'weather' has no `to`"));
+
+        let user = user(
+            r#"
+            name = "u"
+            password = "a"
+            grants = [{ privileges = ["read"], to = "other" }]
+            "#,
+        );
+        assert!(user
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("must not have `to`"));
+    }
+
+    #[test]
+    fn password_is_read_from_environment() {
+        let user = user(
+            r#"
+            name = "u"
+            password_env = "PIKE_TEST_MISSING_USER_PASSWORD"
+            "#,
+        );
+        user.validate().unwrap();
+        let err = user.queries().unwrap_err().to_string();
+        assert!(
+            err.contains("PIKE_TEST_MISSING_USER_PASSWORD with password of user 'u' is not set"),
+            "{err}"
+        );
+    }
+}
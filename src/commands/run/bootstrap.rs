@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use super::BOOTSTRAP_FILE_NAME;
+
+/// Records statements pike executes on the cluster, so the bootstrap
+/// can be reviewed, compared between runs and replayed elsewhere.
+/// Statements are kept as is, including passwords of users, so the file
+/// is readable by its owner only.
+#[derive(Debug)]
+pub(super) struct BootstrapLog {
+    path: PathBuf,
+}
+
+impl BootstrapLog {
+    /// Starts a new log in `cluster_dir`, statements of the previous run are discarded.
+    pub(super) fn create(cluster_dir: &Path) -> Result<Self> {
+        let path = cluster_dir.join(BOOTSTRAP_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .context(format!("failed to create {}", path.display()))?;
+        // Mode is applied only to a new file, the log of an older pike may be readable by others.
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .context(format!("failed to restrict access to {}", path.display()))?;
+        writeln!(
+            file,
+            "-- Statements executed by pike during the cluster bootstrap, in order."
+        )
+        .context(format!("failed to write to {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// Appends the statement, `;` is added if it is missing.
+    pub(super) fn record(&self, query: &str) -> Result<()> {
+        let query = query.trim();
+        let terminator = if query.ends_with(';') { "" } else { ";" };
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .context(format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{query}{terminator}")
+            .context(format!("failed to write to {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn statements_are_recorded_in_order() {
+        let workspace = TempWorkspace::new("bootstrap-log").unwrap();
+        let log = BootstrapLog::create(workspace.path()).unwrap();
+        log.record("ALTER SYSTEM SET jwt_secret = '';").unwrap();
+        log.record("\n  CREATE TABLE t (id INT PRIMARY KEY)\n")
+            .unwrap();
+
+        let log = BootstrapLog::create(workspace.path()).unwrap();
+        log.record("ALTER PLUGIN p 0.1.0 ENABLE;").unwrap();
+
+        let content = fs::read_to_string(workspace.join(BOOTSTRAP_FILE_NAME)).unwrap();
+        let statements: Vec<_> = content.lines().skip(1).collect();
+        assert_eq!(statements, ["ALTER PLUGIN p 0.1.0 ENABLE;"]);
+
+        log.record("CREATE TABLE t (id INT PRIMARY KEY)").unwrap();
+        let content = fs::read_to_string(workspace.join(BOOTSTRAP_FILE_NAME)).unwrap();
+        assert!(content.ends_with("ENABLE;\nCREATE TABLE t (id INT PRIMARY KEY);\n"));
+
+        let mode = fs::metadata(workspace.join(BOOTSTRAP_FILE_NAME))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
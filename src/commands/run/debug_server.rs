@@ -0,0 +1,99 @@
+use crate::commands::lib::find_executable;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Remote debugging server which starts picodata paused
+/// until a debugger connects to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum DebugServer {
+    Gdbserver,
+    LldbServer,
+}
+
+impl DebugServer {
+    /// Picks gdbserver or lldb-server, whichever is installed.
+    pub(super) fn find() -> Result<Self> {
+        [Self::Gdbserver, Self::LldbServer]
+            .into_iter()
+            .find(|server| find_executable(Path::new(server.binary())).is_some())
+            .context("neither gdbserver nor lldb-server is installed")
+    }
+
+    fn binary(self) -> &'static str {
+        match self {
+            Self::Gdbserver => "gdbserver",
+            Self::LldbServer => "lldb-server",
+        }
+    }
+
+    /// Command which runs `picodata` under the server listening on `port`,
+    /// arguments of picodata are appended by the caller.
+    pub(super) fn command(self, picodata: &Path, port: u16) -> Command {
+        let mut command = Command::new(self.binary());
+        let address = format!("127.0.0.1:{port}");
+        match self {
+            Self::Gdbserver => command.args(["--no-startup-with-shell", &address]),
+            Self::LldbServer => command.args(["gdbserver", &address, "--"]),
+        };
+        command.arg(picodata);
+        command
+    }
+
+    /// Command to attach a debugger to the server listening on `port`.
+    pub(super) fn attach_command(self, picodata: &Path, port: u16) -> String {
+        let picodata = picodata.display();
+        match self {
+            Self::Gdbserver => format!("gdb -ex 'target remote 127.0.0.1:{port}' {picodata}"),
+            Self::LldbServer => format!("lldb {picodata} -o 'gdb-remote 127.0.0.1:{port}'"),
+        }
+    }
+}
+
+/// Resolves picodata binary, the debug server doesn't look for it in `PATH`.
+pub(super) fn resolve_picodata(picodata_path: &Path) -> Result<PathBuf> {
+    let Some(path) = find_executable(picodata_path) else {
+        bail!("failed to find picodata binary {}", picodata_path.display());
+    };
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn picodata_is_started_under_server() {
+        let picodata = Path::new("/usr/bin/picodata");
+
+        let gdbserver = DebugServer::Gdbserver.command(picodata, 2345);
+        assert_eq!(gdbserver.get_program(), "gdbserver");
+        assert_eq!(
+            args(&gdbserver),
+            [
+                "--no-startup-with-shell",
+                "127.0.0.1:2345",
+                "/usr/bin/picodata"
+            ]
+        );
+
+        let lldb_server = DebugServer::LldbServer.command(picodata, 2345);
+        assert_eq!(lldb_server.get_program(), "lldb-server");
+        assert_eq!(
+            args(&lldb_server),
+            ["gdbserver", "127.0.0.1:2345", "--", "/usr/bin/picodata"]
+        );
+
+        assert_eq!(
+            DebugServer::Gdbserver.attach_command(picodata, 2345),
+            "gdb -ex 'target remote 127.0.0.1:2345' /usr/bin/picodata"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Fingerprints of external cargo plugins, so `run` doesn't rebuild and copy
+//! plugins whose sources haven't changed since the last run.
+
+use super::Params;
+use crate::commands::lib::ignore::{walk, IgnoreList};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Directory in the plugins dir of the cluster where fingerprints are kept.
+const FINGERPRINTS_DIR: &str = ".pike-fingerprints";
+
+/// Fingerprint of the external plugin `name` built from the cargo project at `path`.
+#[derive(Debug)]
+pub(super) struct Fingerprint {
+    file: PathBuf,
+    hash: String,
+}
+
+impl Fingerprint {
+    /// Hashes paths, sizes and modification times of the project files together
+    /// with everything passed to `cargo build`, target and `.git` directories are skipped.
+    pub(super) fn new(
+        name: &str,
+        path: &Path,
+        params: &Params,
+        plugin_run_dir: &Path,
+    ) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(params.get_build_profile().to_string());
+        hasher.update(format!("{:?}", params.cargo_args));
+        hasher.update(
+            params
+                .plugin_path
+                .join(&params.data_dir)
+                .as_os_str()
+                .as_encoded_bytes(),
+        );
+        hasher.update(plugin_run_dir.as_os_str().as_encoded_bytes());
+
+        let mut ignore = IgnoreList::new([".git/"])?;
+        if params.target_dir.is_relative() {
+            let target_dir = params
+                .target_dir
+                .strip_prefix(".")
+                .unwrap_or(&params.target_dir);
+            ignore.extend([format!("/{}/", target_dir.display())])?;
+        }
+        walk(
+            path,
+            Path::new(""),
+            &ignore,
+            &mut |relative, full_path, is_dir| {
+                if is_dir {
+                    return Ok(());
+                }
+                let metadata = fs::metadata(full_path).context(format!(
+                    "failed to read metadata of {}",
+                    full_path.display()
+                ))?;
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                hasher.update(relative.as_os_str().as_encoded_bytes());
+                hasher.update(metadata.len().to_le_bytes());
+                hasher.update(modified.as_nanos().to_le_bytes());
+                Ok(())
+            },
+        )
+        .context(format!(
+            "failed to compute fingerprint of plugin '{name}' at {}",
+            path.display()
+        ))?;
+
+        Ok(Self {
+            file: plugin_run_dir.join(FINGERPRINTS_DIR).join(name),
+            hash: format!("{:x}", hasher.finalize()),
+        })
+    }
+
+    /// The plugin is built from the same sources and its copy in the plugins dir is kept.
+    pub(super) fn is_fresh(&self, plugin_dir: &Path) -> Result<bool> {
+        if !plugin_dir.is_dir() {
+            return Ok(false);
+        }
+        match fs::read_to_string(&self.file) {
+            Ok(stored) => Ok(stored.trim() == self.hash),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).context(format!("failed to read {}", self.file.display())),
+        }
+    }
+
+    /// Stores the fingerprint after the plugin is built and copied.
+    pub(super) fn save(&self) -> Result<()> {
+        let dir = self.file.parent().expect("fingerprint file has a parent");
+        fs::create_dir_all(dir).context(format!("failed to create {}", dir.display()))?;
+        fs::write(&self.file, &self.hash)
+            .context(format!("failed to write {}", self.file.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use crate::commands::run::{ParamsBuilder, Topology};
+
+    #[test]
+    fn fingerprint_changes_with_sources_and_build_flags() {
+        let workspace = TempWorkspace::new("run-fingerprint").unwrap();
+        let plugin_path = workspace.join("ext_plugin");
+        let run_dir = workspace.join("plugins");
+        fs::create_dir_all(plugin_path.join("src")).unwrap();
+        fs::create_dir_all(plugin_path.join("target/debug")).unwrap();
+        fs::write(plugin_path.join("Cargo.toml"), "[package]\n").unwrap();
+        fs::write(plugin_path.join("src/lib.rs"), "").unwrap();
+
+        let params = ParamsBuilder::default()
+            .topology(Topology::default())
+            .build()
+            .unwrap();
+        let fingerprint = |params: &Params| {
+            Fingerprint::new("ext_plugin", &plugin_path, params, &run_dir).unwrap()
+        };
+
+        let first = fingerprint(&params);
+        assert!(!first.is_fresh(&run_dir.join("ext_plugin")).unwrap());
+        fs::create_dir_all(run_dir.join("ext_plugin")).unwrap();
+        assert!(!first.is_fresh(&run_dir.join("ext_plugin")).unwrap());
+        first.save().unwrap();
+        assert!(first.is_fresh(&run_dir.join("ext_plugin")).unwrap());
+
+        // Build output doesn't change the fingerprint.
+        fs::write(plugin_path.join("target/debug/libext_plugin.so"), "").unwrap();
+        assert_eq!(fingerprint(&params).hash, first.hash);
+
+        fs::write(plugin_path.join("src/lib.rs"), "// changed").unwrap();
+        let changed = fingerprint(&params);
+        assert_ne!(changed.hash, first.hash);
+        assert!(!changed.is_fresh(&run_dir.join("ext_plugin")).unwrap());
+
+        let release = ParamsBuilder::default()
+            .topology(Topology::default())
+            .use_release(true)
+            .build()
+            .unwrap();
+        assert_ne!(fingerprint(&release).hash, changed.hash);
+    }
+}
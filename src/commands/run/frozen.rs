@@ -0,0 +1,114 @@
+//! Checks of `--frozen` runs, which start the cluster only from already built artifacts.
+
+use super::{get_external_plugin_path_kind, plugin_versions, Params, PluginPathKind};
+use crate::commands::lib::{find_executable, is_plugin_dir};
+use anyhow::{bail, Context, Result};
+
+/// Checks that picodata and all plugins of the topology are built,
+/// every missing artifact is reported at once.
+pub(super) fn check_artifacts(params: &Params) -> Result<()> {
+    let mut missing = vec![];
+    if find_executable(&params.picodata_path).is_none() {
+        missing.push(format!(
+            "picodata executable '{}'",
+            params.picodata_path.display()
+        ));
+    }
+
+    let profile = params.get_build_profile();
+    let in_plugin_dir = is_plugin_dir(&params.plugin_path);
+    for (name, plugin) in &params.topology.plugins {
+        // Downloaded plugins are reported by `remote::fetch`, it doesn't download in frozen runs.
+        if plugin.url.is_some() || plugin.registry.is_some() {
+            continue;
+        }
+        let build_dir = match &plugin.path {
+            None if in_plugin_dir => params.get_plugins_dir(),
+            // Plugins without a project are reported by `prepare_directory_with_plugins`.
+            None => continue,
+            Some(path) => match get_external_plugin_path_kind(path)
+                .context(format!("failed to validate path of plugin '{name}'"))?
+            {
+                PluginPathKind::CrateOrWorkspaceDirectory => {
+                    path.join(&params.target_dir).join(profile.to_string())
+                }
+                PluginPathKind::ShippingArchive | PluginPathKind::ShippingDirectory => continue,
+            },
+        };
+
+        let plugin_dir = build_dir.join(name);
+        let is_built = match &plugin.version {
+            Some(version) => plugin_dir.join(version).is_dir(),
+            None => plugin_dir.is_dir() && !plugin_versions(&plugin_dir)?.is_empty(),
+        };
+        if !is_built {
+            missing.push(format!(
+                "plugin '{name}' {} built with {profile} profile in {}",
+                plugin.version.as_deref().unwrap_or("of any version"),
+                plugin_dir.display()
+            ));
+        }
+    }
+
+    if !missing.is_empty() {
+        bail!(
+            "--frozen run requires built artifacts, missing:\n  - {}",
+            missing.join("\n  - ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use crate::commands::run::{ParamsBuilder, Plugin, Topology};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    #[test]
+    fn missing_artifacts_are_listed() {
+        let workspace = TempWorkspace::new("run-frozen").unwrap();
+        let plugin_path = workspace.path();
+        fs::write(plugin_path.join("Cargo.toml"), "").unwrap();
+        fs::write(plugin_path.join("manifest.yaml.template"), "").unwrap();
+        fs::create_dir_all(plugin_path.join("target/debug/built/0.1.0")).unwrap();
+        fs::create_dir_all(plugin_path.join("target/debug/pinned/0.1.0")).unwrap();
+
+        let plugins = BTreeMap::from([
+            ("built".to_string(), Plugin::default()),
+            (
+                "pinned".to_string(),
+                Plugin {
+                    version: Some("0.2.0".into()),
+                    ..Plugin::default()
+                },
+            ),
+            ("absent".to_string(), Plugin::default()),
+        ]);
+        let params = |plugins| {
+            ParamsBuilder::default()
+                .topology(Topology {
+                    plugins,
+                    ..Topology::default()
+                })
+                .plugin_path(plugin_path.to_path_buf())
+                .picodata_path(plugin_path.join("Cargo.toml"))
+                .build()
+                .unwrap()
+        };
+
+        let err = check_artifacts(&params(plugins.clone())).unwrap_err();
+        let msg = err.to_string();
+        assert!(
+            msg.contains("plugin 'pinned' 0.2.0 built with debug"),
+            "{msg}"
+        );
+        assert!(msg.contains("plugin 'absent' of any version"), "{msg}");
+        assert!(!msg.contains("'built'"), "{msg}");
+
+        let built = plugins.into_iter().filter(|(name, _)| name == "built");
+        check_artifacts(&params(built.collect())).unwrap();
+    }
+}
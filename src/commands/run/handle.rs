@@ -0,0 +1,180 @@
+//! Handle of the cluster started by [`cluster`](super::cluster), which lets programs
+//! embedding pike query and manage instances without calling pike commands.
+
+use super::{save_instances_metadata, wait_leader_name, Params, PicodataInstance};
+use crate::commands::instance::restart::DEFAULT_RESTART_SIGNAL;
+use crate::commands::lib::cluster_metadata::ClusterMetadata;
+use crate::commands::lib::cluster_state::{wait_ready, ClusterState};
+use crate::commands::lib::events::Event;
+use crate::commands::stop;
+use crate::error::{Error, Result};
+use anyhow::{anyhow, Context};
+use log::info;
+use nix::sys::signal::Signal;
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long [`PicodataCluster::leader`] waits for the raft leader election.
+#[allow(dead_code)]
+const LEADER_WAIT: Duration = Duration::from_secs(10);
+
+/// Started cluster, derefs to its running instances.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct PicodataCluster {
+    params: Params,
+    plugins_dir: Option<PathBuf>,
+    instances: Vec<PicodataInstance>,
+}
+
+#[allow(dead_code)]
+impl PicodataCluster {
+    pub(super) fn new(
+        params: Params,
+        plugins_dir: Option<PathBuf>,
+        instances: Vec<PicodataInstance>,
+    ) -> Self {
+        Self {
+            params,
+            plugins_dir,
+            instances,
+        }
+    }
+
+    /// Instances started by this handle which are still running.
+    #[must_use]
+    pub fn instances(&self) -> &[PicodataInstance] {
+        &self.instances
+    }
+
+    fn instance(&self, instance_name: &str) -> anyhow::Result<&PicodataInstance> {
+        self.instances
+            .iter()
+            .find(|i| i.instance_name == instance_name)
+            .context(format!("instance '{instance_name}' is not running"))
+    }
+
+    fn first(&self) -> Result<&PicodataInstance> {
+        self.instances.first().ok_or_else(|| {
+            Error::AdminSocketUnavailable(anyhow!("no instances of the cluster are running"))
+        })
+    }
+
+    /// Runs SQL query on the first running instance and converts each row of the result into `T`,
+    /// see [`PicodataInstance::query`].
+    pub fn sql<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        Ok(self.first()?.query(&self.params.picodata_path, sql)?)
+    }
+
+    /// Waits until all instances are Online and all plugins are enabled.
+    pub fn wait_ready(&self, timeout: Duration) -> Result<ClusterState> {
+        Ok(wait_ready(
+            &self.params.get_cluster_dir(),
+            &self.params.picodata_path,
+            timeout,
+            |state| state.all_instances_online() && state.plugins.iter().all(|p| p.enabled),
+        )?)
+    }
+
+    /// Instance which is the raft leader of the cluster.
+    pub fn leader(&self) -> Result<&PicodataInstance> {
+        self.params.emit(&Event::LeaderWaiting);
+        let leader_name = wait_leader_name(self.first()?, LEADER_WAIT).context(format!(
+            "raft leader is not elected within {}s",
+            LEADER_WAIT.as_secs()
+        ))?;
+        Ok(self.instance(&leader_name).context(format!(
+            "leader '{leader_name}' is not started by this handle"
+        ))?)
+    }
+
+    fn stop_params(&self, instance_name: Option<&str>, signal: Signal) -> stop::Params {
+        let mut builder = stop::ParamsBuilder::default();
+        builder
+            .data_dir(self.params.data_dir.clone())
+            .plugin_path(self.params.plugin_path.clone())
+            .instance_name(instance_name.map(str::to_string))
+            .signal(signal);
+        if let Some(reporter) = &self.params.reporter {
+            builder.reporter(reporter.clone());
+        }
+        builder.build().unwrap()
+    }
+
+    /// Stops all instances of the cluster, its data is kept.
+    pub fn stop(mut self) -> Result<()> {
+        stop::cmd(&self.stop_params(None, stop::DEFAULT_STOP_SIGNAL))?;
+        for mut instance in self.instances.drain(..) {
+            instance.join();
+        }
+        Ok(())
+    }
+
+    /// Stops the instance, it can be started again with [`restart_instance`](Self::restart_instance).
+    pub fn stop_instance(&mut self, instance_name: &str) -> Result<()> {
+        self.stop_instance_with(instance_name, stop::DEFAULT_STOP_SIGNAL)
+            .map(|_| ())
+    }
+
+    fn stop_instance_with(
+        &mut self,
+        instance_name: &str,
+        signal: Signal,
+    ) -> Result<Option<PicodataInstance>> {
+        stop::cmd(&self.stop_params(Some(instance_name), signal))
+            .context(format!("failed to stop instance '{instance_name}'"))?;
+        let Some(index) = self
+            .instances
+            .iter()
+            .position(|i| i.instance_name == instance_name)
+        else {
+            return Ok(None);
+        };
+        let mut instance = self.instances.remove(index);
+        instance.join();
+        Ok(Some(instance))
+    }
+
+    /// Stops the instance gracefully if it is running and starts it again
+    /// with the same plugins, which are not rebuilt.
+    pub fn restart_instance(&mut self, instance_name: &str) -> Result<&PicodataInstance> {
+        let stopped = self.stop_instance_with(instance_name, DEFAULT_RESTART_SIGNAL)?;
+        let (instance_id, tier, restarts) = if let Some(instance) = stopped {
+            (instance.instance_id, instance.tier, instance.restarts)
+        } else {
+            // Instance stopped before is found in the description of the cluster.
+            let path = ClusterMetadata::path(&self.params.plugin_path, &self.params.data_dir);
+            let instance = ClusterMetadata::load(&path)?
+                .and_then(|m| m.instances.into_iter().find(|i| i.name == instance_name));
+            let Some(instance) = instance else {
+                return Err(anyhow!(
+                    "instance '{instance_name}' is not found in {}",
+                    path.display()
+                )
+                .into());
+            };
+            (instance.instance_id, instance.tier, instance.restarts)
+        };
+
+        let mut instance =
+            PicodataInstance::new(instance_id, self.plugins_dir.as_ref(), &tier, &self.params)
+                .context(format!("failed to start instance '{instance_name}'"))?;
+        // Restarts after crashes are kept, this one is requested.
+        instance.restarts = restarts;
+        save_instances_metadata(&self.params, std::slice::from_ref(&instance))?;
+        info!("instance '{instance_name}' is restarted");
+
+        self.instances.push(instance);
+        Ok(self.instances.last().unwrap())
+    }
+}
+
+impl Deref for PicodataCluster {
+    type Target = [PicodataInstance];
+
+    fn deref(&self) -> &Self::Target {
+        &self.instances
+    }
+}
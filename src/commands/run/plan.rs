@@ -0,0 +1,373 @@
+use crate::commands::lib::instance_info::InstanceSocketClient;
+use crate::commands::lib::sql::{self, quote_ident, quote_literal};
+use crate::commands::run::Topology;
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Debug, Deserialize)]
+struct PluginRow {
+    name: String,
+    version: String,
+    enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceRow {
+    plugin_name: String,
+    name: String,
+    version: String,
+    #[serde(default)]
+    tiers: Vec<String>,
+}
+
+/// Plugins and services already installed on the cluster,
+/// read from `_pico_plugin` and `_pico_service`.
+#[derive(Debug, Default)]
+pub(super) struct ClusterPlugins {
+    plugins: Vec<PluginRow>,
+    services: Vec<ServiceRow>,
+}
+
+impl ClusterPlugins {
+    pub(super) fn fetch(client: &InstanceSocketClient) -> Result<Self> {
+        Ok(Self {
+            plugins: client.query("SELECT name, version, enabled FROM _pico_plugin")?,
+            services: client
+                .query("SELECT plugin_name, name, version, tiers FROM _pico_service")?,
+        })
+    }
+
+    fn plugin(&self, name: &str, version: &str) -> Option<&PluginRow> {
+        self.plugins
+            .iter()
+            .find(|p| p.name == name && p.version == version)
+    }
+
+    fn has_service_on_tier(&self, plugin: &str, version: &str, service: &str, tier: &str) -> bool {
+        self.services.iter().any(|s| {
+            s.plugin_name == plugin
+                && s.version == version
+                && s.name == service
+                && s.tiers.iter().any(|t| t == tier)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum StepKind {
+    Create,
+    Update,
+    Skip,
+}
+
+/// Single step of plugins installation.
+#[derive(Debug, Serialize)]
+pub(super) struct Step {
+    pub(super) kind: StepKind,
+    pub(super) description: String,
+    /// Statement to execute, skipped steps have none.
+    pub(super) query: Option<String>,
+}
+
+impl Step {
+    fn run(kind: StepKind, description: String, query: String) -> Self {
+        Self {
+            kind,
+            description,
+            query: Some(query),
+        }
+    }
+
+    fn skip(description: String) -> Self {
+        Self {
+            kind: StepKind::Skip,
+            description,
+            query: None,
+        }
+    }
+}
+
+/// Compares plugins of the topology with the ones installed on the cluster
+/// and returns steps to bring the cluster to the topology.
+pub(super) fn plan(topology: &Topology, cluster: &ClusterPlugins) -> Result<Vec<Step>> {
+    let mut steps = vec![];
+
+    for (plugin_name, plugin) in topology.plugins_in_install_order()? {
+        let Some(plugin_version) = plugin.version.as_ref() else {
+            bail!("plugin version is missing for '{plugin_name}'");
+        };
+        let installed = cluster.plugin(plugin_name, plugin_version);
+        let plugin_version = sql::plugin_version(plugin_version)?;
+        let plugin_ident = quote_ident(plugin_name);
+        let enabled = installed.is_some_and(|p| p.enabled);
+
+        let description = format!("plugin {plugin_name} {plugin_version}");
+        steps.push(match installed {
+            Some(_) => Step::skip(format!("{description} (already exists)")),
+            None => Step::run(
+                StepKind::Create,
+                format!("create {description}"),
+                format!("CREATE PLUGIN {plugin_ident} {plugin_version};"),
+            ),
+        });
+
+        for (service_name, service) in &plugin.services {
+            for tier_name in &service.tiers {
+                let description =
+                    format!("service {service_name} of {plugin_name} on tier {tier_name}");
+                steps.push(
+                    if cluster.has_service_on_tier(plugin_name, plugin_version, service_name, tier_name) {
+                        Step::skip(format!("{description} (already added)"))
+                    } else {
+                        Step::run(
+                            StepKind::Create,
+                            format!("add {description}"),
+                            format!(
+                                "ALTER PLUGIN {plugin_ident} {plugin_version} ADD SERVICE {} TO TIER {};",
+                                quote_ident(service_name),
+                                quote_ident(tier_name)
+                            ),
+                        )
+                    },
+                );
+            }
+        }
+
+        // Migrations of enabled plugin have been already applied.
+        for migration_env in &plugin.migration_context {
+            let description = format!("migration context {} of {plugin_name}", migration_env.name);
+            steps.push(if enabled {
+                Step::skip(format!("{description} (plugin is enabled)"))
+            } else {
+                Step::run(
+                    StepKind::Update,
+                    format!("set {description}"),
+                    format!(
+                        "ALTER PLUGIN {plugin_ident} {plugin_version} SET migration_context.{}={};",
+                        quote_ident(&migration_env.name),
+                        quote_literal(&migration_env.value)
+                    ),
+                )
+            });
+        }
+
+        steps.push(if enabled {
+            Step::skip(format!("migrations of {description} (plugin is enabled)"))
+        } else {
+            Step::run(
+                StepKind::Update,
+                format!("migrate {plugin_name} to {plugin_version}"),
+                format!("ALTER PLUGIN {plugin_ident} MIGRATE TO {plugin_version};"),
+            )
+        });
+
+        steps.push(if enabled {
+            Step::skip(format!("{description} (already enabled)"))
+        } else {
+            Step::run(
+                StepKind::Create,
+                format!("enable {description}"),
+                format!("ALTER PLUGIN {plugin_ident} {plugin_version} ENABLE;"),
+            )
+        });
+    }
+
+    Ok(steps)
+}
+
+/// Renders plan as colored list of steps.
+pub(super) fn render(steps: &[Step]) -> String {
+    let mut out = String::from("Plugins installation plan:\n");
+    for step in steps {
+        let line = match step.kind {
+            StepKind::Create => format!("  + {}", step.description).green(),
+            StepKind::Update => format!("  ~ {}", step.description).yellow(),
+            StepKind::Skip => format!("  = skip {}", step.description).dimmed(),
+        };
+        writeln!(out, "{line}").expect("writing to string never fails");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::run::{MigrationContextVar, Plugin, Service};
+    use std::collections::BTreeMap;
+
+    fn topology() -> Topology {
+        Topology {
+            plugins: BTreeMap::from([(
+                "p".to_string(),
+                Plugin {
+                    migration_context: vec![MigrationContextVar {
+                        name: "k".to_string(),
+                        value: "v".to_string(),
+                    }],
+                    services: BTreeMap::from([(
+                        "svc".to_string(),
+                        Service {
+                            tiers: vec!["a".to_string(), "b".to_string()],
+                        },
+                    )]),
+                    version: Some("0.1.0".to_string()),
+                    path: None,
+                    url: None,
+                    sha256: None,
+                    registry: None,
+                    depends_on: vec![],
+                    grants: vec![],
+                },
+            )]),
+            ..Topology::default()
+        }
+    }
+
+    fn kinds(steps: &[Step]) -> Vec<(StepKind, &str)> {
+        steps
+            .iter()
+            .map(|s| (s.kind, s.description.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn plan_for_empty_cluster_installs_everything() {
+        let steps = plan(&topology(), &ClusterPlugins::default()).unwrap();
+
+        assert_eq!(
+            kinds(&steps),
+            [
+                (StepKind::Create, "create plugin p 0.1.0"),
+                (StepKind::Create, "add service svc of p on tier a"),
+                (StepKind::Create, "add service svc of p on tier b"),
+                (StepKind::Update, "set migration context k of p"),
+                (StepKind::Update, "migrate p to 0.1.0"),
+                (StepKind::Create, "enable plugin p 0.1.0"),
+            ]
+        );
+        assert!(steps.iter().all(|s| s.query.is_some()));
+    }
+
+    #[test]
+    fn plan_installs_dependencies_first() {
+        let mut topology = topology();
+        let mut dependency = topology.plugins["p"].clone();
+        dependency.services.clear();
+        dependency.migration_context.clear();
+        topology.plugins.get_mut("p").unwrap().depends_on = vec!["z".to_string()];
+        topology.plugins.insert("z".to_string(), dependency);
+
+        let steps = plan(&topology, &ClusterPlugins::default()).unwrap();
+        let descriptions: Vec<&str> = steps.iter().map(|s| s.description.as_str()).collect();
+        assert_eq!(
+            &descriptions[..3],
+            [
+                "create plugin z 0.1.0",
+                "migrate z to 0.1.0",
+                "enable plugin z 0.1.0"
+            ]
+        );
+        assert_eq!(descriptions[3], "create plugin p 0.1.0");
+    }
+
+    #[test]
+    fn plan_skips_installed_parts() {
+        let cluster = ClusterPlugins {
+            plugins: vec![PluginRow {
+                name: "p".to_string(),
+                version: "0.1.0".to_string(),
+                enabled: false,
+            }],
+            services: vec![ServiceRow {
+                plugin_name: "p".to_string(),
+                name: "svc".to_string(),
+                version: "0.1.0".to_string(),
+                tiers: vec!["a".to_string()],
+            }],
+        };
+
+        let steps = plan(&topology(), &cluster).unwrap();
+        assert_eq!(
+            kinds(&steps),
+            [
+                (StepKind::Skip, "plugin p 0.1.0 (already exists)"),
+                (StepKind::Skip, "service svc of p on tier a (already added)"),
+                (StepKind::Create, "add service svc of p on tier b"),
+                (StepKind::Update, "set migration context k of p"),
+                (StepKind::Update, "migrate p to 0.1.0"),
+                (StepKind::Create, "enable plugin p 0.1.0"),
+            ]
+        );
+        assert!(steps[0].query.is_none());
+    }
+
+    #[test]
+    fn plan_quotes_names_and_values() {
+        let mut topology = topology();
+        let plugin = topology.plugins.remove("p").unwrap();
+        let mut plugin = Plugin {
+            migration_context: vec![MigrationContextVar {
+                name: "Key".to_string(),
+                value: "it's".to_string(),
+            }],
+            ..plugin
+        };
+        plugin.services = BTreeMap::from([(
+            "my \"svc\"".to_string(),
+            Service {
+                tiers: vec!["a".to_string()],
+            },
+        )]);
+        topology.plugins.insert("p\"".to_string(), plugin);
+
+        let queries: Vec<String> = plan(&topology, &ClusterPlugins::default())
+            .unwrap()
+            .into_iter()
+            .filter_map(|s| s.query)
+            .collect();
+        assert_eq!(
+            queries,
+            [
+                r#"CREATE PLUGIN "p""" 0.1.0;"#,
+                r#"ALTER PLUGIN "p""" 0.1.0 ADD SERVICE "my ""svc""" TO TIER "a";"#,
+                r#"ALTER PLUGIN "p""" 0.1.0 SET migration_context."Key"='it''s';"#,
+                r#"ALTER PLUGIN "p""" MIGRATE TO 0.1.0;"#,
+                r#"ALTER PLUGIN "p""" 0.1.0 ENABLE;"#,
+            ]
+        );
+
+        topology.plugins.get_mut("p\"").unwrap().version = Some("0.1.0; DROP".to_string());
+        assert!(plan(&topology, &ClusterPlugins::default()).is_err());
+    }
+
+    #[test]
+    fn plan_skips_migrations_of_enabled_plugin() {
+        let cluster = ClusterPlugins {
+            plugins: vec![PluginRow {
+                name: "p".to_string(),
+                version: "0.1.0".to_string(),
+                enabled: true,
+            }],
+            services: vec![],
+        };
+
+        let steps = plan(&topology(), &cluster).unwrap();
+        let skipped: Vec<&str> = steps
+            .iter()
+            .filter(|s| s.kind == StepKind::Skip)
+            .map(|s| s.description.as_str())
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                "plugin p 0.1.0 (already exists)",
+                "migration context k of p (plugin is enabled)",
+                "migrations of plugin p 0.1.0 (plugin is enabled)",
+                "plugin p 0.1.0 (already enabled)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use super::{get_ipv4_from_template_var, instance_env_vars, Params, PicodataInstance};
+use crate::commands::lib::cluster_metadata::{BasePorts, ClusterMetadata};
+use crate::error::Error;
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use std::str::FromStr;
+
+/// How many ranges `--auto-ports` tries before giving up.
+const AUTO_PORTS_ATTEMPTS: u16 = 100;
+
+/// How `--auto-ports` changes base ports when some ports of instances are in use.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AutoPorts {
+    /// Shift base ports to the next range of free ports.
+    #[default]
+    Shift,
+    /// Pick free ranges from the ephemeral range even if base ports are free,
+    /// so that several new clusters don't compete for the same ports.
+    Ephemeral,
+}
+
+impl FromStr for AutoPorts {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "shift" => Ok(Self::Shift),
+            "ephemeral" => Ok(Self::Ephemeral),
+            _ => bail!("unknown auto ports mode '{s}', expected shift or ephemeral"),
+        }
+    }
+}
+
+/// Addresses the instance listens on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct InstanceAddresses {
+    pub(super) bin: SocketAddrV4,
+    pub(super) http: SocketAddrV4,
+    pub(super) pg: SocketAddrV4,
+}
+
+impl InstanceAddresses {
+    /// Addresses set in `environment` section of topology,
+    /// or derived from base ports of `params` otherwise.
+    pub(super) fn new(params: &Params, instance_id: u16) -> Result<Self> {
+        let env_vars = instance_env_vars(params, instance_id)?;
+        let address = |variable: &str, ip: Ipv4Addr, base_port: u16| -> Result<SocketAddrV4> {
+            if let Some(address) = get_ipv4_from_template_var(&env_vars, variable) {
+                return Ok(address);
+            }
+            let port = base_port.checked_add(instance_id).context(format!(
+                "port of instance {instance_id} with base port {base_port} is out of range"
+            ))?;
+            Ok(SocketAddrV4::new(ip, port))
+        };
+
+        Ok(Self {
+            bin: address(
+                "PICODATA_IPROTO_LISTEN",
+                Ipv4Addr::LOCALHOST,
+                params.base_bin_port,
+            )?,
+            http: address(
+                "PICODATA_HTTP_LISTEN",
+                Ipv4Addr::UNSPECIFIED,
+                params.base_http_port,
+            )?,
+            pg: address(
+                "PICODATA_PG_LISTEN",
+                Ipv4Addr::LOCALHOST,
+                params.base_pg_port,
+            )?,
+        })
+    }
+
+    fn named(&self) -> [(&'static str, SocketAddrV4); 3] {
+        [("iproto", self.bin), ("http", self.http), ("pg", self.pg)]
+    }
+}
+
+/// Returns descriptions of addresses which instances can't listen on:
+/// occupied by other processes or shared by several instances.
+fn find_conflicts(instances: &[(u16, InstanceAddresses)]) -> Vec<String> {
+    let mut conflicts = vec![];
+    let mut used_ports: HashMap<u16, String> = HashMap::new();
+
+    for (instance_id, addresses) in instances {
+        let instance_name = PicodataInstance::make_name(*instance_id);
+        for (kind, address) in addresses.named() {
+            let owner = format!("{kind} port {} of instance {instance_name}", address.port());
+            if let Some(other) = used_ports.get(&address.port()) {
+                conflicts.push(format!("{owner} is the same as {other}"));
+                continue;
+            }
+            if TcpListener::bind(address).is_err() {
+                conflicts.push(format!("{owner} ({address}) is already in use"));
+            }
+            used_ports.insert(address.port(), owner);
+        }
+    }
+
+    conflicts
+}
+
+/// Restores base ports saved in `cluster.json` by the previous run,
+/// so restarted instances listen on the addresses known to their peers.
+///
+/// Returns `false` if the cluster has never been started.
+pub(super) fn restore_base_ports(params: &mut Params) -> Result<bool> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(metadata) = ClusterMetadata::load(&path)? else {
+        return Ok(false);
+    };
+
+    let ports = metadata.base_ports;
+    if ports != base_ports(params) {
+        info!(
+            "Using ports of the cluster from {}: base iproto port {}, base http port {}, base pg port {}",
+            path.display(),
+            ports.bin,
+            ports.http,
+            ports.pg
+        );
+    }
+    params.base_bin_port = ports.bin;
+    params.base_http_port = ports.http;
+    params.base_pg_port = ports.pg;
+    Ok(true)
+}
+
+/// Saves base ports of `params` in `cluster.json` of the cluster.
+pub(super) fn save_base_ports(params: &Params) -> Result<()> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let metadata = match ClusterMetadata::load(&path)? {
+        Some(metadata) => ClusterMetadata {
+            base_ports: base_ports(params),
+            ..metadata
+        },
+        None => ClusterMetadata {
+            base_ports: base_ports(params),
+            instances: vec![],
+            with_web_auth: params.with_web_auth,
+        },
+    };
+    metadata.save(&path)
+}
+
+pub(super) fn base_ports(params: &Params) -> BasePorts {
+    BasePorts {
+        bin: params.base_bin_port,
+        http: params.base_http_port,
+        pg: params.base_pg_port,
+    }
+}
+
+/// Picks base ports from the ephemeral range, so that several clusters started
+/// at the same time get different ports. Ranges of iproto, http and pg ports follow each other.
+///
+/// Returns `None` if the picked range doesn't fit below the last port.
+fn pick_ephemeral_base_ports(instance_count: u16) -> Result<Option<BasePorts>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .context("failed to get a free port from the system")?;
+    // Ports of the instance `i<N>` are `base + N`, so the first instance gets the picked port.
+    let bin = listener.local_addr()?.port() - 1;
+
+    let ports = bin.checked_add(instance_count).and_then(|http| {
+        let pg = http.checked_add(instance_count)?;
+        pg.checked_add(instance_count)?;
+        Some(BasePorts { bin, http, pg })
+    });
+    Ok(ports)
+}
+
+/// Checks that instances `instance_ids` can listen on their addresses before they are started.
+///
+/// With `auto_ports` base ports of `params` are changed until all addresses are free,
+/// see [`AutoPorts`] for the ways they are changed.
+pub(super) fn ensure_ports_available(
+    params: &mut Params,
+    instance_ids: &[u16],
+    auto_ports: Option<AutoPorts>,
+) -> Result<()> {
+    let instance_count = params.topology.instance_count().max(1);
+
+    for attempt in 0..=AUTO_PORTS_ATTEMPTS {
+        if auto_ports == Some(AutoPorts::Ephemeral) {
+            let Some(ports) = pick_ephemeral_base_ports(instance_count)? else {
+                continue;
+            };
+            params.base_bin_port = ports.bin;
+            params.base_http_port = ports.http;
+            params.base_pg_port = ports.pg;
+        }
+
+        let addresses = instance_ids
+            .iter()
+            .map(|&id| Ok((id, InstanceAddresses::new(params, id)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let conflicts = find_conflicts(&addresses);
+        if conflicts.is_empty() {
+            if attempt > 0 || auto_ports == Some(AutoPorts::Ephemeral) {
+                info!(
+                    "Using free ports: base iproto port {}, base http port {}, base pg port {}",
+                    params.base_bin_port, params.base_http_port, params.base_pg_port
+                );
+            }
+            return Ok(());
+        }
+
+        match auto_ports {
+            None => {
+                return Err(Error::ClusterAlreadyRunning(anyhow!(
+                    "ports of instances are not available:\n  {}\n\
+                    Free the ports, change base ports or run with --auto-ports to pick free ones",
+                    conflicts.join("\n  ")
+                ))
+                .into());
+            }
+            Some(AutoPorts::Shift) => {
+                let shift = |port: u16| {
+                    port.checked_add(instance_count)
+                        .context("failed to find free ports: port range is exhausted")
+                };
+                params.base_bin_port = shift(params.base_bin_port)?;
+                params.base_http_port = shift(params.base_http_port)?;
+                params.base_pg_port = shift(params.base_pg_port)?;
+            }
+            Some(AutoPorts::Ephemeral) => {}
+        }
+    }
+
+    bail!(
+        "failed to find free ports in {AUTO_PORTS_ATTEMPTS} attempts, \
+        ports set in `environment` section of topology are not changed"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use crate::commands::run::{ParamsBuilder, Tier, Topology};
+    use std::collections::BTreeMap;
+
+    fn params(base_port: u16) -> Params {
+        ParamsBuilder::default()
+            .topology(Topology {
+                tiers: BTreeMap::from([(
+                    "default".to_string(),
+                    Tier {
+                        replicasets: 1,
+                        replication_factor: 2,
+                    },
+                )]),
+                ..Topology::default()
+            })
+            .base_bin_port(base_port)
+            .base_http_port(base_port + 10)
+            .base_pg_port(base_port + 20)
+            .build()
+            .unwrap()
+    }
+
+    /// Returns port from the ephemeral range, ports next to it are most likely free.
+    fn free_base_port() -> u16 {
+        loop {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            if port < u16::MAX - 100 {
+                return port;
+            }
+        }
+    }
+
+    #[test]
+    fn occupied_and_shared_ports_are_reported() {
+        let base_port = free_base_port();
+        let params = params(base_port);
+        let busy = TcpListener::bind(("127.0.0.1", base_port + 2)).unwrap();
+
+        let mut addresses: Vec<(u16, InstanceAddresses)> = [1, 2]
+            .iter()
+            .map(|&id| (id, InstanceAddresses::new(&params, id).unwrap()))
+            .collect();
+        addresses[1].1.pg = addresses[0].1.http;
+
+        let conflicts = find_conflicts(&addresses);
+        drop(busy);
+        assert_eq!(
+            conflicts,
+            [
+                format!(
+                    "iproto port {} of instance i2 (127.0.0.1:{}) is already in use",
+                    base_port + 2,
+                    base_port + 2
+                ),
+                format!(
+                    "pg port {} of instance i2 is the same as http port {} of instance i1",
+                    base_port + 11,
+                    base_port + 11
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_ports_shift_base_ports() {
+        let base_port = free_base_port();
+        let mut params = params(base_port);
+        let busy = TcpListener::bind(("127.0.0.1", base_port + 1)).unwrap();
+
+        let err = ensure_ports_available(&mut params, &[1, 2], None).unwrap_err();
+        assert!(err.to_string().contains("--auto-ports"), "{err}");
+        assert_eq!(params.base_bin_port, base_port);
+
+        ensure_ports_available(&mut params, &[1, 2], Some(AutoPorts::Shift)).unwrap();
+        drop(busy);
+        assert!(params.base_bin_port > base_port);
+        assert_eq!(params.base_http_port - params.base_bin_port, 10);
+    }
+
+    #[test]
+    fn auto_ports_pick_free_ranges() {
+        let base_port = free_base_port();
+        let mut params = params(base_port);
+        let busy = TcpListener::bind(("127.0.0.1", base_port + 1)).unwrap();
+
+        ensure_ports_available(&mut params, &[1, 2], Some(AutoPorts::Ephemeral)).unwrap();
+        drop(busy);
+        assert_ne!(params.base_bin_port, base_port);
+        assert_eq!(params.base_http_port - params.base_bin_port, 2);
+        assert_eq!(params.base_pg_port - params.base_http_port, 2);
+    }
+
+    #[test]
+    fn base_ports_are_restored_from_metadata() {
+        let workspace = TempWorkspace::new("run-ports-metadata").unwrap();
+        let mut params = params(3000);
+        params.plugin_path = workspace.path().to_path_buf();
+        assert!(!restore_base_ports(&mut params).unwrap());
+
+        params.base_bin_port = 40000;
+        save_base_ports(&params).unwrap();
+
+        let mut restarted = self::params(3000);
+        restarted.plugin_path = workspace.path().to_path_buf();
+        assert!(restore_base_ports(&mut restarted).unwrap());
+        assert_eq!(
+            (
+                restarted.base_bin_port,
+                restarted.base_http_port,
+                restarted.base_pg_port
+            ),
+            (40000, 3010, 3020)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Presets of memory settings of instances, passed to picodata as `--config-parameter`
+//! on top of `picodata.yaml`.
+
+const MIB: u64 = 1024 * 1024;
+const GIB: u64 = 1024 * MIB;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ResourcePreset {
+    /// Small memory limits and rare snapshots to run a dozen instances on a laptop.
+    Tiny,
+    /// Settings of `picodata.yaml` or defaults of picodata.
+    #[default]
+    Default,
+    /// Realistic sizing for load tests.
+    Big,
+}
+
+impl ResourcePreset {
+    /// Config parameters of the preset in `key=value` form.
+    pub(crate) fn config_parameters(self) -> Vec<String> {
+        let parameters: &[(&str, u64)] = match self {
+            Self::Tiny => &[
+                ("instance.memtx.memory", 32 * MIB),
+                ("instance.memtx.checkpoint_count", 1),
+                ("instance.memtx.checkpoint_interval", 24 * 60 * 60),
+                ("instance.vinyl.memory", 16 * MIB),
+                ("instance.vinyl.cache", 16 * MIB),
+            ],
+            Self::Default => &[],
+            Self::Big => &[
+                ("instance.memtx.memory", 2 * GIB),
+                ("instance.memtx.checkpoint_count", 2),
+                ("instance.memtx.checkpoint_interval", 60 * 60),
+                ("instance.vinyl.memory", GIB),
+                ("instance.vinyl.cache", 512 * MIB),
+            ],
+        };
+        parameters
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_set_memory_of_instances() {
+        assert!(ResourcePreset::default().config_parameters().is_empty());
+
+        let tiny = ResourcePreset::Tiny.config_parameters();
+        assert!(tiny.contains(&"instance.memtx.memory=33554432".to_string()));
+        let big = ResourcePreset::Big.config_parameters();
+        assert!(big.contains(&"instance.memtx.memory=2147483648".to_string()));
+        assert_eq!(tiny.len(), big.len());
+    }
+}
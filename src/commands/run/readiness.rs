@@ -1,20 +1,22 @@
+use crate::commands::lib::poll::{poll_until, Backoff, RetryPolicy};
 use crate::commands::run::Params;
 use crate::healthcheck::api;
 use anyhow::{bail, Result};
 use log::{debug, info};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::thread;
 use std::time::{Duration, Instant};
 
 use super::PicodataInstance;
 
-const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
-const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+const CHECK_BACKOFF: Backoff = Backoff::new(Duration::from_millis(50), Duration::from_millis(500));
 
 /// Polls startup and readiness probes on each instance until all return 200,
-/// or until the timeout is exceeded.
-pub(super) fn wait_instances_ready(instances: &[PicodataInstance]) -> Result<()> {
+/// or until the `retry` policy is exhausted.
+pub(super) fn wait_instances_ready(
+    instances: &[PicodataInstance],
+    retry: &RetryPolicy,
+) -> Result<()> {
     if instances.is_empty() {
         return Ok(());
     }
@@ -22,19 +24,10 @@ pub(super) fn wait_instances_ready(instances: &[PicodataInstance]) -> Result<()>
     info!(
         "Waiting for {} instance(s) to become ready (timeout {}s)",
         instances.len(),
-        HEALTH_CHECK_TIMEOUT.as_secs()
+        retry.timeout.as_secs()
     );
 
-    let start = Instant::now();
-
-    loop {
-        if start.elapsed() >= HEALTH_CHECK_TIMEOUT {
-            bail!(
-                "cluster setup timed out: not all instances became ready within {}s",
-                HEALTH_CHECK_TIMEOUT.as_secs()
-            );
-        }
-
+    let all_ready = retry.poll(|| {
         let ready_count = instances
             .iter()
             .map(api::is_instance_ready)
@@ -44,8 +37,7 @@ pub(super) fn wait_instances_ready(instances: &[PicodataInstance]) -> Result<()>
             .count();
 
         if ready_count == instances.len() {
-            info!("All {} instance(s) are ready", instances.len());
-            return Ok(());
+            return Ok(Some(()));
         }
 
         debug!(
@@ -53,8 +45,18 @@ pub(super) fn wait_instances_ready(instances: &[PicodataInstance]) -> Result<()>
             ready_count,
             instances.len()
         );
-        thread::sleep(CHECK_INTERVAL);
+        Ok(None)
+    })?;
+
+    if all_ready.is_none() {
+        bail!(
+            "cluster setup timed out: not all instances became ready within {}s",
+            retry.timeout.as_secs()
+        );
     }
+
+    info!("All {} instance(s) are ready", instances.len());
+    Ok(())
 }
 
 /// Waits for vshard discovery to complete across all instances.
@@ -129,7 +131,7 @@ fn wait_for_initial_resharding(
             instance.instance_name
         );
 
-        let instance_bucket_count = loop {
+        let instance_bucket_count = poll_until(start + timeout, &CHECK_BACKOFF, || {
             let instance_bucket_count: u32 = instance_socket.bucket_count()?;
 
             if (tier_replicaset_count * instance_bucket_count).abs_diff(tier_bucket_count)
@@ -139,18 +141,17 @@ fn wait_for_initial_resharding(
                     "Instance '{}': resharding completed (bucket_count = {})",
                     instance.instance_name, instance_bucket_count
                 );
-                break instance_bucket_count;
+                return Ok(Some(instance_bucket_count));
             }
 
-            if start.elapsed() >= timeout {
-                bail!(
-                    "Resharding timed out on instance '{}' within {}s",
-                    instance.instance_name,
-                    timeout.as_secs()
-                );
-            }
-
-            thread::sleep(CHECK_INTERVAL);
+            Ok(None)
+        })?;
+        let Some(instance_bucket_count) = instance_bucket_count else {
+            bail!(
+                "Resharding timed out on instance '{}' within {}s",
+                instance.instance_name,
+                timeout.as_secs()
+            );
         };
 
         // If current instance is master, preserve number of buckets in the map.
@@ -193,7 +194,7 @@ fn wait_for_vshard_router_init(
             instance.instance_name
         );
 
-        loop {
+        let synced = poll_until(start + timeout, &CHECK_BACKOFF, || {
             // Fetch vshard.router map from socket.
             match instance_socket.vshard_replicaset_map() {
                 Ok(map) if map == *bucket_count_per_replicaset => {
@@ -201,7 +202,7 @@ fn wait_for_vshard_router_init(
                         "Instance '{}': has synced vshard router",
                         instance.instance_name
                     );
-                    break;
+                    return Ok(Some(()));
                 }
                 Ok(map) => {
                     debug!(
@@ -217,14 +218,14 @@ fn wait_for_vshard_router_init(
                 }
             }
 
-            if start.elapsed() >= timeout {
-                bail!(
-                    "Initialization of vshard.router timed out within {}s",
-                    timeout.as_secs()
-                );
-            }
+            Ok(None)
+        })?;
 
-            thread::sleep(CHECK_INTERVAL);
+        if synced.is_none() {
+            bail!(
+                "Initialization of vshard.router timed out within {}s",
+                timeout.as_secs()
+            );
         }
     }
 
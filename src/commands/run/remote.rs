@@ -0,0 +1,398 @@
+//! External plugins downloaded by `url` or `registry` coordinates of the topology
+//! instead of being taken from a local `path`.
+//!
+//! Archives are kept in the plugins dir of the cluster, so they are downloaded
+//! again only when the source or the checksum of the plugin changes.
+
+use super::{Params, Plugin};
+use crate::commands::lib::archive;
+use crate::commands::lib::integrity;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::platform;
+use crate::commands::lib::repository::{IndexEntry, Repository};
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Directory in the plugins dir of the cluster where downloaded archives are kept.
+const DOWNLOADS_DIR: &str = ".pike-downloads";
+/// File with the source and the name of the archive downloaded for the plugin.
+const SOURCE_FILE_NAME: &str = "source";
+
+/// Where the archive of the plugin is downloaded from.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Source {
+    /// URL of the archive with its expected SHA-256.
+    Url { url: String, sha256: Option<String> },
+    /// Plugin version in the repository configured in `[registry]` of `pike.toml`.
+    Registry { name: String, version: String },
+}
+
+impl Source {
+    /// Source of the plugin, `None` if it is not downloaded.
+    pub(super) fn of(plugin: &Plugin) -> Result<Option<Self>> {
+        if let Some(url) = &plugin.url {
+            return Ok(Some(Self::Url {
+                url: url.clone(),
+                sha256: plugin.sha256.clone(),
+            }));
+        }
+        let Some(coordinates) = &plugin.registry else {
+            return Ok(None);
+        };
+        let Some((name, version)) = coordinates.split_once('@') else {
+            bail!("registry coordinates '{coordinates}' must be <name>@<version>");
+        };
+        if name.is_empty() || version.is_empty() {
+            bail!("registry coordinates '{coordinates}' must be <name>@<version>");
+        }
+        Ok(Some(Self::Registry {
+            name: name.to_string(),
+            version: version.to_string(),
+        }))
+    }
+
+    fn key(&self) -> String {
+        match self {
+            Self::Url { url, .. } => url.clone(),
+            Self::Registry { name, version } => format!("registry:{name}@{version}"),
+        }
+    }
+}
+
+/// Archive of the plugin downloaded earlier from the same source.
+fn cached_archive(download_dir: &Path, source: &Source) -> Option<PathBuf> {
+    let marker = fs::read_to_string(download_dir.join(SOURCE_FILE_NAME)).ok()?;
+    let (key, file_name) = marker.trim_end().split_once('\n')?;
+    let path = download_dir.join(file_name);
+    (key == source.key() && path.is_file()).then_some(path)
+}
+
+/// Downloads the archive of the external plugin `name` unless it is already
+/// downloaded, returns the path of the archive with its checksum next to it.
+///
+/// `--frozen` runs use downloaded archives only.
+pub(super) fn fetch(
+    name: &str,
+    source: &Source,
+    params: &Params,
+    plugin_run_dir: &Path,
+) -> Result<PathBuf> {
+    let download_dir = plugin_run_dir.join(DOWNLOADS_DIR).join(name);
+    let cached = cached_archive(&download_dir, source);
+    if params.frozen {
+        return cached.with_context(|| {
+            format!(
+                "plugin '{name}' from {} is not downloaded, --frozen run doesn't download plugins",
+                source.key()
+            )
+        });
+    }
+
+    let (repository, entry) = match source {
+        Source::Url { url, sha256 } => {
+            let Some((root, file_name)) = url.rsplit_once('/') else {
+                bail!("invalid URL of plugin '{name}': {url}");
+            };
+            let entry = IndexEntry {
+                name: name.to_string(),
+                version: String::new(),
+                os_suffix: None,
+                path: file_name.to_string(),
+                sha256: sha256.clone().unwrap_or_default(),
+            };
+            (Repository::parse(root, None, None)?, entry)
+        }
+        Source::Registry {
+            name: registry_name,
+            version,
+        } => {
+            let config = PikeConfig::load(&params.plugin_path)?;
+            let repository = config.registry.open(&config.publish)?;
+            let os_suffix = platform::os_suffix(None)?;
+            let entry = find_in_registry(&repository, registry_name, version, &os_suffix)?;
+            (repository, entry)
+        }
+    };
+
+    if let Some(cached) = cached {
+        let is_signed = params.verify_key.is_none() || integrity::signature_path(&cached).exists();
+        let is_fresh = entry.sha256.is_empty() || integrity::sha256(&cached)? == entry.sha256;
+        if is_signed && is_fresh {
+            info!(
+                "Plugin '{name}' is already downloaded from {}",
+                source.key()
+            );
+            return Ok(cached);
+        }
+    }
+
+    info!(
+        "Downloading plugin '{name}' from {repository}/{}",
+        entry.path
+    );
+    let content = repository
+        .get(&entry.path)?
+        .with_context(|| format!("{repository}/{} does not exist", entry.path))?;
+    if let Err(err) = fs::remove_dir_all(&download_dir) {
+        if err.kind() != ErrorKind::NotFound {
+            return Err(err).context(format!("failed to remove {}", download_dir.display()));
+        }
+    }
+    fs::create_dir_all(&download_dir)
+        .context(format!("failed to create {}", download_dir.display()))?;
+
+    let file_name = Path::new(&entry.path)
+        .file_name()
+        .with_context(|| format!("invalid archive path {}", entry.path))?
+        .to_string_lossy()
+        .to_string();
+    let archive_path = download_dir.join(&file_name);
+    fs::write(&archive_path, &content)
+        .context(format!("failed to write {}", archive_path.display()))?;
+    let sha256 = integrity::sha256(&archive_path)?;
+    if !entry.sha256.is_empty() && !entry.sha256.eq_ignore_ascii_case(&sha256) {
+        bail!(
+            "checksum of plugin '{name}' downloaded from {repository}/{} is {sha256}, expected {}",
+            entry.path,
+            entry.sha256
+        );
+    }
+    integrity::write_checksum(&archive_path, &sha256)?;
+    if params.verify_key.is_some() {
+        let signature = repository
+            .get(&format!("{}.minisig", entry.path))?
+            .with_context(|| {
+                format!(
+                    "signature {repository}/{}.minisig does not exist, \
+                    plugins must be signed to be verified with a key",
+                    entry.path
+                )
+            })?;
+        let signature_path = integrity::signature_path(&archive_path);
+        fs::write(&signature_path, signature)
+            .context(format!("failed to write {}", signature_path.display()))?;
+    }
+    check_archive_plugin(&archive_path, name)?;
+
+    fs::write(
+        download_dir.join(SOURCE_FILE_NAME),
+        format!("{}\n{file_name}\n", source.key()),
+    )
+    .context(format!("failed to write source of plugin '{name}'"))?;
+    Ok(archive_path)
+}
+
+/// Archive of the plugin version in the registry built for the OS of the host,
+/// or the only archive of the version if none of them has its OS suffix.
+fn find_in_registry(
+    repository: &Repository,
+    name: &str,
+    version: &str,
+    os_suffix: &str,
+) -> Result<IndexEntry> {
+    let candidates: Vec<IndexEntry> = repository
+        .index()?
+        .plugins
+        .into_iter()
+        .filter(|entry| entry.name == name && entry.version == version)
+        .collect();
+    if candidates.is_empty() {
+        bail!("plugin {name}@{version} is not found in the registry {repository}");
+    }
+    if let Some(entry) = candidates
+        .iter()
+        .find(|entry| entry.os_suffix.as_deref() == Some(os_suffix))
+    {
+        return Ok(entry.clone());
+    }
+    if let [entry] = candidates.as_slice() {
+        return Ok(entry.clone());
+    }
+    let suffixes: Vec<_> = candidates
+        .iter()
+        .map(|entry| entry.os_suffix.as_deref().unwrap_or("none"))
+        .collect();
+    bail!(
+        "plugin {name}@{version} in the registry {repository} is not built for {os_suffix}, \
+        available OS suffixes: {}",
+        suffixes.join(", ")
+    )
+}
+
+fn check_archive_plugin(archive_path: &Path, name: &str) -> Result<()> {
+    let has_plugin = archive::entry_paths(archive_path)?
+        .iter()
+        .any(|path| path.starts_with(name));
+    if !has_plugin {
+        bail!(
+            "archive {} does not contain plugin '{name}'",
+            archive_path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::archive::{ArchiveFormat, ArchiveWriter, CompressionLevel};
+    use crate::commands::lib::repository::Index;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+    use crate::commands::run::{ParamsBuilder, Topology};
+
+    fn pack(path: &Path, name: &str, version: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut archive =
+            ArchiveWriter::create(path, ArchiveFormat::TarGz, CompressionLevel::Fast).unwrap();
+        archive
+            .append_data(
+                &Path::new(name).join(version).join("manifest.yaml"),
+                format!("name: {name}\nversion: {version}\n").as_bytes(),
+            )
+            .unwrap();
+        archive.finish().unwrap();
+    }
+
+    fn params(plugin_path: &Path, frozen: bool) -> Params {
+        ParamsBuilder::default()
+            .topology(Topology::default())
+            .plugin_path(plugin_path.to_path_buf())
+            .frozen(frozen)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn registry_coordinates_are_parsed() {
+        let plugin = |registry: &str| Plugin {
+            registry: Some(registry.to_string()),
+            ..Plugin::default()
+        };
+        assert_eq!(
+            Source::of(&plugin("weather@0.2.0")).unwrap(),
+            Some(Source::Registry {
+                name: "weather".to_string(),
+                version: "0.2.0".to_string()
+            })
+        );
+        assert!(Source::of(&plugin("weather")).is_err());
+        assert!(Source::of(&plugin("@0.2.0")).is_err());
+        assert_eq!(Source::of(&Plugin::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn archive_is_downloaded_by_url_once() {
+        let workspace = TempWorkspace::new("run-remote-url").unwrap();
+        let archive = workspace.join("repository/weather_0.1.0-alt_10.tar.gz");
+        pack(&archive, "weather", "0.1.0");
+        let run_dir = workspace.join("plugins");
+        let source = Source::Url {
+            url: format!("file://{}", archive.display()),
+            sha256: Some(integrity::sha256(&archive).unwrap()),
+        };
+
+        let err = fetch(
+            "weather",
+            &source,
+            &params(workspace.path(), true),
+            &run_dir,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not downloaded"), "{err}");
+
+        let downloaded = fetch(
+            "weather",
+            &source,
+            &params(workspace.path(), false),
+            &run_dir,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&downloaded).unwrap(), fs::read(&archive).unwrap());
+        assert!(integrity::verify_checksum(&downloaded).unwrap());
+
+        fs::remove_file(&archive).unwrap();
+        let cached = fetch(
+            "weather",
+            &source,
+            &params(workspace.path(), false),
+            &run_dir,
+        )
+        .unwrap();
+        assert_eq!(cached, downloaded);
+
+        let err = fetch("other", &source, &params(workspace.path(), false), &run_dir).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn archive_with_wrong_checksum_is_rejected() {
+        let workspace = TempWorkspace::new("run-remote-checksum").unwrap();
+        let archive = workspace.join("repository/weather.tar.gz");
+        pack(&archive, "weather", "0.1.0");
+        let source = Source::Url {
+            url: format!("file://{}", archive.display()),
+            sha256: Some("0".repeat(64)),
+        };
+        let err = fetch(
+            "weather",
+            &source,
+            &params(workspace.path(), false),
+            &workspace.join("plugins"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("expected 000"), "{err}");
+    }
+
+    #[test]
+    fn archive_is_found_in_registry() {
+        let workspace = TempWorkspace::new("run-remote-registry").unwrap();
+        let repository_dir = workspace.join("repository");
+        let mut index = Index::default();
+        for os_suffix in ["alt_10", "ubuntu_24.04"] {
+            let path = format!("weather/0.1.0/weather_0.1.0-{os_suffix}.tar.gz");
+            pack(&repository_dir.join(&path), "weather", "0.1.0");
+            index.insert(IndexEntry {
+                name: "weather".to_string(),
+                version: "0.1.0".to_string(),
+                os_suffix: Some(os_suffix.to_string()),
+                sha256: integrity::sha256(&repository_dir.join(&path)).unwrap(),
+                path,
+            });
+        }
+        let repository = Repository::parse(&repository_dir.to_string_lossy(), None, None).unwrap();
+        repository.put_index(&index).unwrap();
+        fs::write(
+            workspace.join("pike.toml"),
+            format!("[registry]\nurl = \"{}\"\n", repository_dir.display()),
+        )
+        .unwrap();
+
+        let err = find_in_registry(&repository, "weather", "0.2.0", "alt_10").unwrap_err();
+        assert!(err.to_string().contains("not found"), "{err}");
+        let entry = find_in_registry(&repository, "weather", "0.1.0", "ubuntu_24.04").unwrap();
+        assert_eq!(entry.os_suffix.as_deref(), Some("ubuntu_24.04"));
+        let err = find_in_registry(&repository, "weather", "0.1.0", "debian_12").unwrap_err();
+        assert!(err.to_string().contains("alt_10, ubuntu_24.04"), "{err}");
+
+        // The only archive of the version is taken whatever OS it is built for.
+        index
+            .plugins
+            .retain(|entry| entry.os_suffix.as_deref() == Some("alt_10"));
+        repository.put_index(&index).unwrap();
+        let source = Source::Registry {
+            name: "weather".to_string(),
+            version: "0.1.0".to_string(),
+        };
+        let downloaded = fetch(
+            "weather",
+            &source,
+            &params(workspace.path(), false),
+            &workspace.join("plugins"),
+        )
+        .unwrap();
+        assert!(downloaded.ends_with("weather_0.1.0-alt_10.tar.gz"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Execution of bootstrap statements through a single `picodata admin` session.
+//!
+//! Statements are sent as one Lua script which runs them with `pico.sql` one by one,
+//! skips tolerated errors, stops at the first other failure and returns the result
+//! of every executed statement.
+
+use crate::commands::lib::instance_info::{parse_lua_json, single_line_output};
+use crate::commands::lib::run_query_in_picodata_admin;
+use crate::commands::lib::sql::quote_lua;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Errors of statements which were applied by a previous run, e.g. re-enabling the plugin.
+const TOLERATED_ERRORS: [&str; 2] = ["already enabled", "already exists"];
+
+/// Result of a statement executed by the batch.
+#[derive(Debug, PartialEq)]
+pub(super) struct StatementResult {
+    pub(super) elapsed: Duration,
+    pub(super) error: Option<String>,
+}
+
+impl StatementResult {
+    /// Whether the statement failed with an error which can't be ignored.
+    pub(super) fn is_failed(&self) -> bool {
+        self.error
+            .as_deref()
+            .is_some_and(|error| !TOLERATED_ERRORS.iter().any(|msg| error.contains(msg)))
+    }
+}
+
+#[derive(Deserialize)]
+struct RawResult {
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+    elapsed: f64,
+}
+
+/// Executes `queries` until the first failed one, statements failed with
+/// a tolerated error don't stop the batch. Results are returned for the executed
+/// statements in order.
+pub(super) fn execute(
+    picodata_path: &Path,
+    admin_socket: &Path,
+    queries: &[String],
+) -> Result<Vec<StatementResult>> {
+    let stdout = run_query_in_picodata_admin(picodata_path, admin_socket, &batch_script(queries))
+        .context("failed to send SQL statements to picodata admin")?;
+    let results = parse_results(&stdout)?;
+    if results.is_empty() && !queries.is_empty() {
+        bail!("picodata admin returned no results of SQL statements: {stdout}");
+    }
+    Ok(results)
+}
+
+fn batch_script(queries: &[String]) -> String {
+    let queries: Vec<String> = queries
+        .iter()
+        .map(|query| quote_lua(query.trim().trim_end_matches(';').trim_end()))
+        .collect();
+    let tolerated: Vec<String> = TOLERATED_ERRORS.iter().map(|msg| quote_lua(msg)).collect();
+    format!(
+        "\\lua\n\
+local json = require('json'); local clock = require('clock'); local results = {{}}; \
+local function is_tolerated(err) \
+    for _, msg in ipairs({{ {} }}) do \
+        if err:find(msg, 1, true) then return true end; \
+    end; \
+    return false; \
+end; \
+for _, query in ipairs({{ {} }}) do \
+    local started = clock.monotonic(); \
+    local ok, res, err = pcall(pico.sql, query); \
+    if ok and res == nil and err ~= nil then ok, res = false, err end; \
+    local message = (not ok) and tostring(res) or nil; \
+    table.insert(results, {{ ok = ok, error = message, elapsed = clock.monotonic() - started }}); \
+    if not ok and not is_tolerated(message) then break end; \
+end; \
+return json.encode(results)",
+        tolerated.join(", "),
+        queries.join(", ")
+    )
+}
+
+fn parse_results(stdout: &str) -> Result<Vec<StatementResult>> {
+    let raw: Vec<RawResult> = single_line_output(stdout)
+        .and_then(|output| parse_lua_json(&output))
+        .context("failed to parse results of SQL statements")?;
+    Ok(raw
+        .into_iter()
+        .map(|result| StatementResult {
+            elapsed: Duration::from_secs_f64(result.elapsed.max(0.0)),
+            error: if result.ok {
+                None
+            } else {
+                Some(result.error.unwrap_or_else(|| "unknown error".to_string()))
+            },
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_are_sent_in_one_script() {
+        let script = batch_script(&[
+            "CREATE PLUGIN \"p\" 0.1.0;".to_string(),
+            "ALTER PLUGIN \"p\" 0.1.0\nENABLE ; ".to_string(),
+        ]);
+        let (mode, body) = script.split_once('\n').unwrap();
+        assert_eq!(mode, "\\lua");
+        assert!(!body.contains('\n'), "{body}");
+        assert!(
+            body.contains(
+                r#"ipairs({ "CREATE PLUGIN \"p\" 0.1.0", "ALTER PLUGIN \"p\" 0.1.0\nENABLE" })"#
+            ),
+            "{body}"
+        );
+        assert!(
+            body.contains(r#"ipairs({ "already enabled", "already exists" })"#),
+            "{body}"
+        );
+        assert!(
+            body.contains("if not ok and not is_tolerated(message) then break end;"),
+            "{body}"
+        );
+    }
+
+    #[test]
+    fn results_are_parsed_from_admin_output() {
+        let stdout = "---\n\
+- '[{\"ok\":true,\"elapsed\":0.25},{\"ok\":false,\"error\":\"plugin p already exists\",\"elapsed\":0.5},\
+{\"ok\":false,\"error\":\"it''s broken\",\"elapsed\":0}]'\n\
+...\n";
+        let results = parse_results(stdout).unwrap();
+        assert_eq!(
+            results,
+            [
+                StatementResult {
+                    elapsed: Duration::from_millis(250),
+                    error: None,
+                },
+                StatementResult {
+                    elapsed: Duration::from_millis(500),
+                    error: Some("plugin p already exists".to_string()),
+                },
+                StatementResult {
+                    elapsed: Duration::ZERO,
+                    error: Some("it's broken".to_string()),
+                },
+            ]
+        );
+        let failed: Vec<bool> = results.iter().map(StatementResult::is_failed).collect();
+        assert_eq!(failed, [false, false, true]);
+    }
+}
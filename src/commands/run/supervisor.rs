@@ -0,0 +1,260 @@
+//! Supervision of instances run in foreground: reports crashed instances
+//! and restarts them with backoff when `run --supervise` is set.
+
+use super::{save_instances_metadata, Params, PicodataInstance};
+use crate::commands::lib::events::Event;
+use crate::commands::lib::hooks::{HookEvent, Hooks};
+use crate::commands::lib::poll::Backoff;
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const RESTART_BACKOFF: Backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+/// Instance which has been running this long after restart is stable, its backoff is reset.
+const STABLE_RUN_TIME: Duration = Duration::from_secs(60);
+
+/// Whether the instance exited by itself and not by signal of Ctrl+C or `pike stop`.
+fn is_crash(status: ExitStatus) -> bool {
+    if status.success() {
+        return false;
+    }
+    !matches!(
+        status.signal().and_then(|s| Signal::try_from(s).ok()),
+        Some(Signal::SIGKILL | Signal::SIGTERM | Signal::SIGINT)
+    )
+}
+
+/// Crashes of the instance in a row, instances which crash right after restart
+/// are restarted with growing delays.
+struct CrashLoop {
+    crashes: u32,
+    restarted_at: Instant,
+}
+
+struct PendingRestart {
+    instance_id: u16,
+    instance_name: String,
+    tier: String,
+    restarts: u32,
+    at: Instant,
+}
+
+pub(super) struct Supervisor<'a> {
+    params: &'a Params,
+    plugins_dir: Option<&'a PathBuf>,
+    hooks: Hooks,
+    cluster_dir: PathBuf,
+    /// Pids of running instances, Ctrl+C handler kills them.
+    pids: Arc<Mutex<Vec<u32>>>,
+    shutting_down: Arc<AtomicBool>,
+    crash_loops: HashMap<u16, CrashLoop>,
+    pending: Vec<PendingRestart>,
+}
+
+impl<'a> Supervisor<'a> {
+    pub(super) fn new(
+        params: &'a Params,
+        plugins_dir: Option<&'a PathBuf>,
+        hooks: Hooks,
+    ) -> Result<Self> {
+        let pids: Arc<Mutex<Vec<u32>>> = Arc::default();
+        let shutting_down = Arc::new(AtomicBool::new(false));
+
+        // Set Ctrl+C handler. Upon receiving Ctrl+C signal
+        // All instances would be killed, then joined and
+        // destructors will be called
+        let handler_pids = Arc::clone(&pids);
+        let handler_shutting_down = Arc::clone(&shutting_down);
+        ctrlc::set_handler(move || {
+            info!("received Ctrl+C. Shutting down ...");
+            handler_shutting_down.store(true, Ordering::SeqCst);
+
+            for &pid in handler_pids.lock().unwrap().iter() {
+                let _ = kill(Pid::from_raw(pid.cast_signed()), Signal::SIGKILL);
+            }
+        })
+        .context("failed to set Ctrl+c handler")?;
+
+        Ok(Self {
+            params,
+            plugins_dir,
+            hooks,
+            cluster_dir: params.get_cluster_dir(),
+            pids,
+            shutting_down,
+            crash_loops: HashMap::new(),
+            pending: vec![],
+        })
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    fn update_pids(&self, instances: &[PicodataInstance]) {
+        *self.pids.lock().unwrap() = instances.iter().map(|i| i.process.id()).collect();
+    }
+
+    /// Waits until all instances stop, crashed instances are restarted if supervision is on.
+    pub(super) fn wait(mut self, mut instances: Vec<PicodataInstance>) {
+        self.update_pids(&instances);
+        loop {
+            let mut i = 0;
+            while i < instances.len() {
+                match instances[i].process.try_wait() {
+                    Ok(None) => i += 1,
+                    Ok(Some(status)) => {
+                        let mut instance = instances.swap_remove(i);
+                        instance.join();
+                        self.on_exit(&instance, status);
+                    }
+                    Err(err) => {
+                        warn!("{err:#}");
+                        i += 1;
+                    }
+                }
+            }
+
+            if self.is_shutting_down() {
+                self.pending.clear();
+            }
+            if let Some(instance) = self.restart_due() {
+                instances.push(instance);
+                self.update_pids(&instances);
+                if self.is_shutting_down() {
+                    // Ctrl+C came during the restart, the handler didn't know the new pid.
+                    let _ = instances.last_mut().unwrap().kill();
+                }
+            }
+
+            if instances.is_empty() && self.pending.is_empty() {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn on_exit(&mut self, instance: &PicodataInstance, status: ExitStatus) {
+        if !is_crash(status) || self.is_shutting_down() {
+            self.params.emit(&Event::InstanceStopped {
+                name: &instance.instance_name,
+            });
+            return;
+        }
+
+        error!("instance {} exited with {status}", instance.instance_name);
+        self.params.emit(&Event::InstanceCrashed {
+            name: &instance.instance_name,
+            exit_status: status.to_string(),
+        });
+        self.hooks.fire(
+            HookEvent::InstanceCrashed,
+            &self.cluster_dir,
+            serde_json::json!({
+                "instance": instance.instance_name,
+                "exit_status": status.to_string(),
+            }),
+        );
+        if !self.params.supervise {
+            return;
+        }
+
+        let crashes = match self.crash_loops.get(&instance.instance_id) {
+            Some(crash_loop) if crash_loop.restarted_at.elapsed() < STABLE_RUN_TIME => {
+                crash_loop.crashes
+            }
+            _ => 0,
+        };
+        let delay = RESTART_BACKOFF.delay(crashes);
+        info!(
+            "restarting instance {} in {:.1} sec",
+            instance.instance_name,
+            delay.as_secs_f64()
+        );
+        self.crash_loops.insert(
+            instance.instance_id,
+            CrashLoop {
+                crashes: crashes.saturating_add(1),
+                restarted_at: Instant::now() + delay,
+            },
+        );
+        self.pending.push(PendingRestart {
+            instance_id: instance.instance_id,
+            instance_name: instance.instance_name.clone(),
+            tier: instance.tier.clone(),
+            restarts: instance.restarts,
+            at: Instant::now() + delay,
+        });
+    }
+
+    /// Starts the first instance which restart is due, failed restarts are retried later.
+    fn restart_due(&mut self) -> Option<PicodataInstance> {
+        let now = Instant::now();
+        let index = self.pending.iter().position(|p| p.at <= now)?;
+        let mut pending = self.pending.swap_remove(index);
+
+        let result = PicodataInstance::new(
+            pending.instance_id,
+            self.plugins_dir,
+            &pending.tier,
+            self.params,
+        );
+        let mut instance = match result {
+            Ok(instance) => instance,
+            Err(err) => {
+                let crash_loop = self.crash_loops.get_mut(&pending.instance_id).unwrap();
+                let delay = RESTART_BACKOFF.delay(crash_loop.crashes);
+                crash_loop.crashes = crash_loop.crashes.saturating_add(1);
+                crash_loop.restarted_at = Instant::now() + delay;
+                warn!(
+                    "failed to restart instance {}, retrying in {:.1} sec: {err:#}",
+                    pending.instance_name,
+                    delay.as_secs_f64()
+                );
+                pending.at = Instant::now() + delay;
+                self.pending.push(pending);
+                return None;
+            }
+        };
+
+        instance.restarts = pending.restarts.saturating_add(1);
+        info!(
+            "instance {} is restarted (restarts: {})",
+            instance.instance_name, instance.restarts
+        );
+        if let Err(err) = save_instances_metadata(self.params, std::slice::from_ref(&instance)) {
+            warn!(
+                "failed to save restarts of {}: {err:#}",
+                instance.instance_name
+            );
+        }
+        self.params.emit_instance_started(&instance);
+        Some(instance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_unexpected_exits_are_crashes() {
+        // Raw wait statuses: exit code in the second byte, terminating signal in the first.
+        assert!(!is_crash(ExitStatus::from_raw(0)));
+        assert!(is_crash(ExitStatus::from_raw(1 << 8)));
+        assert!(is_crash(ExitStatus::from_raw(Signal::SIGSEGV as i32)));
+        assert!(is_crash(ExitStatus::from_raw(Signal::SIGABRT as i32)));
+        assert!(!is_crash(ExitStatus::from_raw(Signal::SIGKILL as i32)));
+        assert!(!is_crash(ExitStatus::from_raw(Signal::SIGTERM as i32)));
+    }
+}
@@ -0,0 +1,298 @@
+//! TLS of pgproto and iproto listeners of instances, `[tls]` section of topology.
+//!
+//! Picodata reads the pgproto certificate from `server.crt`, `server.key` and,
+//! to verify client certificates, `ca.crt` in the instance directory, so they are
+//! copied there. Iproto TLS takes paths of the files in config parameters.
+//! Without `cert` and `key` a self-signed certificate for `localhost` and
+//! `127.0.0.1` is generated by `openssl` into `<data-dir>/cluster/tls`.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const GENERATED_DIR: &str = "tls";
+const CERT_FILE_NAME: &str = "server.crt";
+const KEY_FILE_NAME: &str = "server.key";
+const CA_FILE_NAME: &str = "ca.crt";
+const GENERATED_DAYS: &str = "3650";
+
+#[derive(Default, Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// Accept only TLS connections of pgproto clients.
+    #[serde(default)]
+    pub pgproto: bool,
+    /// Connect instances to each other over TLS.
+    #[serde(default)]
+    pub iproto: bool,
+    /// Certificate of instances, relative paths are resolved against the plugin
+    /// directory. Generated if neither `cert` nor `key` is set.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// CA certificate clients and peers are verified with, not verified if not set.
+    pub ca: Option<PathBuf>,
+}
+
+/// Files of the certificate shared by all instances.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Certificates {
+    cert: PathBuf,
+    key: PathBuf,
+    ca: Option<PathBuf>,
+}
+
+impl TlsSettings {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.pgproto || self.iproto
+    }
+
+    pub(super) fn validate(&self) -> Result<()> {
+        if self.cert.is_some() != self.key.is_some() {
+            bail!("tls must have both cert and key or none of them");
+        }
+        if self.ca.is_some() && self.cert.is_none() {
+            bail!("tls ca requires cert and key, generated certificates are self-signed");
+        }
+        Ok(())
+    }
+
+    /// Certificates of the cluster, generated in `cluster_dir` unless configured.
+    pub(super) fn certificates(
+        &self,
+        plugin_path: &Path,
+        cluster_dir: &Path,
+    ) -> Result<Certificates> {
+        let absolute = |path: &Path| std::path::absolute(plugin_path.join(path));
+        let certificates = if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            Certificates {
+                cert: absolute(cert)?,
+                key: absolute(key)?,
+                ca: self.ca.as_deref().map(absolute).transpose()?,
+            }
+        } else {
+            let dir = std::path::absolute(cluster_dir.join(GENERATED_DIR))?;
+            let certificates = Certificates {
+                cert: dir.join(CERT_FILE_NAME),
+                key: dir.join(KEY_FILE_NAME),
+                ca: None,
+            };
+            if !certificates.cert.exists() || !certificates.key.exists() {
+                generate_self_signed(&certificates)?;
+            }
+            certificates
+        };
+        for path in [&certificates.cert, &certificates.key]
+            .into_iter()
+            .chain(&certificates.ca)
+        {
+            if !path.is_file() {
+                bail!("TLS file {} does not exist", path.display());
+            }
+        }
+        Ok(certificates)
+    }
+
+    /// Copies pgproto certificates into the instance directory and returns
+    /// config parameters of the instance in `key=value` form.
+    pub(super) fn configure_instance(
+        &self,
+        certificates: &Certificates,
+        instance_data_dir: &Path,
+    ) -> Result<Vec<String>> {
+        let mut parameters = vec![];
+        if self.pgproto {
+            copy_file(&certificates.cert, &instance_data_dir.join(CERT_FILE_NAME))?;
+            copy_file(&certificates.key, &instance_data_dir.join(KEY_FILE_NAME))?;
+            if let Some(ca) = &certificates.ca {
+                copy_file(ca, &instance_data_dir.join(CA_FILE_NAME))?;
+            }
+            parameters.push("instance.pg.ssl=true".to_string());
+        }
+        if self.iproto {
+            parameters.extend(iproto_parameters(certificates));
+        }
+        Ok(parameters)
+    }
+}
+
+fn iproto_parameters(certificates: &Certificates) -> Vec<String> {
+    let mut parameters = vec![
+        "instance.iproto_tls.enabled=true".to_string(),
+        format!(
+            "instance.iproto_tls.cert_file={}",
+            certificates.cert.display()
+        ),
+        format!(
+            "instance.iproto_tls.key_file={}",
+            certificates.key.display()
+        ),
+    ];
+    if let Some(ca) = &certificates.ca {
+        parameters.push(format!("instance.iproto_tls.ca_file={}", ca.display()));
+    }
+    parameters
+}
+
+/// Copies the file, the copy of a private key is readable by the owner only.
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    match fs::remove_file(to) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(err).context(format!("failed to remove {}", to.display()));
+        }
+        _ => {}
+    }
+    fs::copy(from, to).context(format!(
+        "failed to copy {} to {}",
+        from.display(),
+        to.display()
+    ))?;
+    fs::set_permissions(to, fs::Permissions::from_mode(0o600))
+        .context(format!("failed to set permissions of {}", to.display()))
+}
+
+fn generate_self_signed(certificates: &Certificates) -> Result<()> {
+    let dir = certificates
+        .cert
+        .parent()
+        .context("certificate path has no parent directory")?;
+    fs::create_dir_all(dir).context(format!("failed to create {}", dir.display()))?;
+    info!(
+        "Generating self-signed TLS certificate {}",
+        certificates.cert.display()
+    );
+    let output = Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "rsa:2048", "-nodes"])
+        .args(["-days", GENERATED_DAYS, "-subj", "/CN=localhost"])
+        .args(["-addext", "subjectAltName=DNS:localhost,IP:127.0.0.1"])
+        .arg("-keyout")
+        .arg(&certificates.key)
+        .arg("-out")
+        .arg(&certificates.cert)
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            bail!("openssl is not found, install it or set cert and key of [tls] in topology")
+        }
+        Err(err) => return Err(err).context("failed to execute openssl"),
+    };
+    if !output.status.success() {
+        bail!(
+            "failed to generate TLS certificate: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn configured_certificates_are_copied_into_instance_dir() {
+        let workspace = TempWorkspace::new("tls").unwrap();
+        for file in ["certs/dev.crt", "certs/dev.key", "certs/ca.crt"] {
+            let path = workspace.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        let instance_dir = workspace.join("cluster/i1");
+        fs::create_dir_all(&instance_dir).unwrap();
+
+        let settings: TlsSettings = toml::from_str(
+            r#"
+            pgproto = true
+            iproto = true
+            cert = "certs/dev.crt"
+            key = "certs/dev.key"
+            ca = "certs/ca.crt"
+            "#,
+        )
+        .unwrap();
+        settings.validate().unwrap();
+        let certificates = settings
+            .certificates(workspace.path(), &workspace.join("cluster"))
+            .unwrap();
+        let parameters = settings
+            .configure_instance(&certificates, &instance_dir)
+            .unwrap();
+
+        let certs_dir = std::path::absolute(workspace.join("certs")).unwrap();
+        assert_eq!(
+            parameters,
+            [
+                "instance.pg.ssl=true".to_string(),
+                "instance.iproto_tls.enabled=true".to_string(),
+                format!(
+                    "instance.iproto_tls.cert_file={}",
+                    certs_dir.join("dev.crt").display()
+                ),
+                format!(
+                    "instance.iproto_tls.key_file={}",
+                    certs_dir.join("dev.key").display()
+                ),
+                format!(
+                    "instance.iproto_tls.ca_file={}",
+                    certs_dir.join("ca.crt").display()
+                ),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(instance_dir.join(KEY_FILE_NAME)).unwrap(),
+            "certs/dev.key"
+        );
+        assert_eq!(
+            fs::read_to_string(instance_dir.join(CA_FILE_NAME)).unwrap(),
+            "certs/ca.crt"
+        );
+        let mode = fs::metadata(instance_dir.join(KEY_FILE_NAME))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn incomplete_settings_are_rejected() {
+        let settings = TlsSettings {
+            pgproto: true,
+            cert: Some(PathBuf::from("dev.crt")),
+            ..Default::default()
+        };
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("both cert and key"));
+
+        let settings = TlsSettings {
+            pgproto: true,
+            ca: Some(PathBuf::from("ca.crt")),
+            ..Default::default()
+        };
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("ca requires cert and key"));
+
+        let workspace = TempWorkspace::new("tls-missing").unwrap();
+        let settings = TlsSettings {
+            pgproto: true,
+            cert: Some(PathBuf::from("dev.crt")),
+            key: Some(PathBuf::from("dev.key")),
+            ..Default::default()
+        };
+        let err = settings
+            .certificates(workspace.path(), &workspace.join("cluster"))
+            .unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+}
@@ -0,0 +1,137 @@
+//! Status of instances of the cluster started by pike, read from `cluster.json`.
+
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use crate::commands::lib::OutputFormat;
+use anyhow::{bail, Result};
+use derive_builder::Builder;
+use serde::Serialize;
+use std::fmt::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+/// Instance printed with `--output json`.
+#[derive(Debug, Serialize)]
+struct InstanceStatus<'a> {
+    #[serde(flatten)]
+    instance: &'a InstanceMetadata,
+    running: bool,
+}
+
+const HEADER: [&str; 6] = ["NAME", "TIER", "STATE", "PID", "RESTARTS", "DATA DIR"];
+
+/// Table of instances, `is_running` tells whether the instance is running.
+fn render(
+    instances: &[InstanceMetadata],
+    is_running: impl Fn(&InstanceMetadata) -> bool,
+) -> String {
+    let rows: Vec<[String; 6]> = instances
+        .iter()
+        .map(|instance| {
+            let running = is_running(instance);
+            [
+                instance.name.clone(),
+                instance.tier.clone(),
+                if running { "running" } else { "stopped" }.to_string(),
+                if running {
+                    instance.pid.to_string()
+                } else {
+                    "-".to_string()
+                },
+                instance.restarts.to_string(),
+                instance.data_dir.display().to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(HEADER.map(String::from)).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(widths) {
+            let _ = write!(line, "{cell:<width$}  ");
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Prints instances of the cluster, whether they are running and how many
+/// times `run --supervise` restarted them.
+pub fn cmd(params: &Params) -> Result<()> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(metadata) = ClusterMetadata::load(&path)? else {
+        bail!(
+            "cluster in {} has never been started",
+            params.plugin_path.join(&params.data_dir).display()
+        );
+    };
+
+    let is_running =
+        |instance: &InstanceMetadata| UnixStream::connect(&instance.admin_socket).is_ok();
+    match params.output {
+        OutputFormat::Text => print!("{}", render(&metadata.instances, is_running)),
+        OutputFormat::Json => {
+            let instances: Vec<InstanceStatus> = metadata
+                .instances
+                .iter()
+                .map(|instance| InstanceStatus {
+                    instance,
+                    running: is_running(instance),
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "instances": instances }))?
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_are_rendered_as_table() {
+        let instance = |instance_id: u16, restarts| InstanceMetadata {
+            name: format!("default_1_{instance_id}"),
+            instance_id,
+            tier: "default".to_string(),
+            pid: 100 + u32::from(instance_id),
+            data_dir: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}")),
+            admin_socket: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}/admin.sock")),
+            bin_port: 3000 + instance_id,
+            http_port: 8000 + instance_id,
+            pg_port: 5432 + instance_id,
+            restarts,
+        };
+
+        let table = render(&[instance(1, 0), instance(2, 3)], |i| i.instance_id == 1);
+        assert_eq!(
+            table,
+            "\
+NAME         TIER     STATE    PID  RESTARTS  DATA DIR
+default_1_1  default  running  101  0         /plugin/tmp/cluster/i1
+default_1_2  default  stopped  -    3         /plugin/tmp/cluster/i2
+"
+        );
+    }
+}
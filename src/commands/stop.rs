@@ -1,4 +1,12 @@
-use crate::commands::lib::{get_active_socket_path, get_cluster_dir};
+use crate::commands::lib::cluster_metadata::ClusterMetadata;
+use crate::commands::lib::events::{self, Event, Reporter};
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::hooks::HookEvent;
+use crate::commands::lib::lock::DataDirLock;
+use crate::commands::lib::pid_file;
+use crate::commands::lib::pike_config::PikeConfig;
+use crate::commands::lib::poll::RetryPolicy;
+use crate::commands::lib::{confirm, get_active_socket_path, get_cluster_dir, OutputFormat};
 use anyhow::{bail, Context, Result};
 use colored::Colorize;
 use derive_builder::Builder;
@@ -6,47 +14,175 @@ use log::{info, warn};
 use nix::errno::Errno;
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fs::{self};
-use std::io::{self, BufRead};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::thread::sleep;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 // Default signal sent by unix kill command during `pike stop` command.
 pub const DEFAULT_STOP_SIGNAL: Signal = Signal::SIGKILL;
 // Default timeout for graceful process shutdown.
 pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Waits of instances to terminate after the signal, SIGKILL is sent when it is exhausted.
+pub const DEFAULT_RETRY_POLICY: RetryPolicy =
+    RetryPolicy::new(DEFAULT_STOP_TIMEOUT, Duration::from_millis(200));
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Builder)]
 pub struct Params {
-    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
     data_dir: PathBuf,
-    #[builder(default = "PathBuf::from(\"./\")")]
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
     plugin_path: PathBuf,
     #[builder(default)]
     instance_name: Option<String>,
     #[builder(default = DEFAULT_STOP_SIGNAL)]
     signal: Signal,
-    #[builder(default = DEFAULT_STOP_TIMEOUT)]
-    timeout: Duration,
+    /// Waits of the whole cluster or the instance to terminate.
+    #[builder(default = DEFAULT_RETRY_POLICY)]
+    retry: RetryPolicy,
+    /// Ask the user to confirm stopping of instances when running in a terminal.
+    #[builder(default = "false")]
+    confirm: bool,
+    /// Print stopped instances to stdout as NDJSON events.
+    #[builder(default = "false")]
+    events_json: bool,
+    /// Fail if the cluster or the instance is not running instead of doing nothing.
+    #[builder(default = "false")]
+    fail_if_not_running: bool,
+    /// Take over the lock of the data directory if its owner is not running anymore.
+    #[builder(default = "false")]
+    force_unlock: bool,
+    /// Receiver of stop events for programs embedding pike.
+    #[builder(default, setter(strip_option))]
+    reporter: Option<Arc<dyn Reporter>>,
+    #[builder(default)]
+    output: OutputFormat,
+}
+
+/// Instance handled by `stop`, printed with `--output json`.
+#[derive(Debug, Serialize)]
+struct StopOutcome {
+    name: String,
+    /// Whether the instance was running and has been stopped.
+    stopped: bool,
+}
+
+impl ParamsBuilder {
+    /// Sets timeout of graceful shutdown keeping other settings of the retry policy.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        let retry = self.retry.unwrap_or(DEFAULT_RETRY_POLICY);
+        self.retry = Some(retry.with_timeout(timeout));
+        self
+    }
+}
+
+impl Params {
+    fn emit(&self, event: &Event) {
+        if self.events_json {
+            events::emit(event);
+        }
+        if let Some(reporter) = &self.reporter {
+            reporter.report(event);
+        }
+    }
+}
+
+/// Names of instances in the cluster keyed by their data directories, e.g. `i1` -> `default_1_1`.
+fn instance_names(plugin_path: &Path, data_dir: &Path) -> HashMap<OsString, String> {
+    let path = ClusterMetadata::path(plugin_path, data_dir);
+    let Ok(Some(metadata)) = ClusterMetadata::load(&path) else {
+        return HashMap::new();
+    };
+    metadata
+        .instances
+        .into_iter()
+        .filter_map(|i| Some((i.data_dir.file_name()?.to_os_string(), i.name)))
+        .collect()
+}
+
+impl Params {
+    /// Fails with `message` if strict behavior is requested, otherwise only reports it.
+    fn not_running(&self, message: &str) -> Result<Vec<StopOutcome>> {
+        if self.fail_if_not_running {
+            bail!("{message}");
+        }
+        info!("{message}, nothing to stop");
+        Ok(vec![])
+    }
 }
 
+/// Stops the cluster or the instance, with `--output json` prints
+/// whether each instance has been stopped.
 pub fn cmd(params: &Params) -> Result<()> {
+    if params.events_json && params.output == OutputFormat::Json {
+        bail!("--events-json and --output json can't be used together, both are printed to stdout");
+    }
+    let outcomes = stop(params)?;
+    if params.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "instances": outcomes }))?
+        );
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines)]
+fn stop(params: &Params) -> Result<Vec<StopOutcome>> {
     let instances_path = params.plugin_path.join(params.data_dir.join("cluster"));
-    let dirs = fs::read_dir(&instances_path).context(format!(
-        "cluster data dir with path {} does not exist",
-        instances_path.to_string_lossy()
-    ))?;
+    let dirs = match fs::read_dir(&instances_path) {
+        Ok(dirs) => dirs,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return params.not_running(&format!(
+                "cluster data dir with path {} does not exist",
+                instances_path.to_string_lossy()
+            ));
+        }
+        Err(err) => {
+            return Err(err).context(format!(
+                "failed to read cluster data dir {}",
+                instances_path.to_string_lossy()
+            ))
+        }
+    };
 
     let Params {
         data_dir,
         plugin_path,
         instance_name,
         signal,
-        timeout,
+        retry,
+        confirm: ask_confirmation,
+        events_json,
+        reporter,
+        fail_if_not_running: _,
+        output: _,
+        force_unlock,
     } = params;
+    let _lock = DataDirLock::acquire(plugin_path, data_dir, "stop", *force_unlock)?;
 
     let cluster_dir = get_cluster_dir(plugin_path, data_dir);
+    let names = instance_names(plugin_path, data_dir);
+    let name_of = |instance_dir: &Path| {
+        let dir_name = instance_dir.file_name().unwrap_or_default();
+        names
+            .get(dir_name)
+            .cloned()
+            .unwrap_or_else(|| dir_name.to_string_lossy().into_owned())
+    };
+    let emit_stopped = |instance_dir: &Path| {
+        if !events_json && reporter.is_none() {
+            return;
+        }
+        params.emit(&Event::InstanceStopped {
+            name: &name_of(instance_dir),
+        });
+    };
 
     if let Some(instance_name) = instance_name {
         info!(
@@ -68,10 +204,28 @@ pub fn cmd(params: &Params) -> Result<()> {
         });
 
         let Some(instance_dir) = instance_dir else {
-            bail!("failed to locate directory of the instance '{instance_name}'");
+            return params.not_running(&format!(
+                "failed to locate directory of the instance '{instance_name}'"
+            ));
         };
+        if !is_running(&cluster_dir, &instance_dir) {
+            return params.not_running(&format!("instance '{instance_name}' is not running"));
+        }
 
-        stop_instance(&cluster_dir, &instance_dir, *signal, *timeout)
+        if *ask_confirmation && !confirm_stop(&[&instance_dir])? {
+            info!("stop is cancelled");
+            return Ok(vec![]);
+        }
+
+        fire_pre_stop(plugin_path, &cluster_dir, &[instance_name.as_str()]);
+        let stopped = stop_instance(&cluster_dir, &instance_dir, *signal, retry)?;
+        if stopped {
+            emit_stopped(&instance_dir);
+        }
+        Ok(vec![StopOutcome {
+            name: name_of(&instance_dir),
+            stopped,
+        }])
     } else {
         info!(
             "stopping picodata cluster, data folder: {}",
@@ -93,37 +247,98 @@ pub fn cmd(params: &Params) -> Result<()> {
             instance_dirs.push(instance_dir);
         }
 
-        if instance_dirs.is_empty() {
-            info!("cluster is empty");
-            return Ok(());
+        if !instance_dirs
+            .iter()
+            .any(|instance_dir| is_running(&cluster_dir, instance_dir))
+        {
+            return params.not_running("cluster is not running");
         }
 
+        let instance_dir_refs: Vec<&Path> = instance_dirs.iter().map(PathBuf::as_path).collect();
+        if *ask_confirmation && !confirm_stop(&instance_dir_refs)? {
+            info!("stop is cancelled");
+            return Ok(vec![]);
+        }
+
+        let instance_names: Vec<String> = instance_dirs
+            .iter()
+            .map(|dir| dir.file_name().unwrap_or_default().to_string_lossy().into())
+            .collect();
+        let instance_names: Vec<&str> = instance_names.iter().map(String::as_str).collect();
+        fire_pre_stop(plugin_path, &cluster_dir, &instance_names);
+
         info!(
-            "stopping {} instance(s) (timeout = {timeout:?})",
-            instance_dirs.len()
+            "stopping {} instance(s) (timeout = {:?})",
+            instance_dirs.len(),
+            retry.timeout
         );
 
         // Calculate timeout per picodata process.
         let start = Instant::now();
-        let timeout_per_instance = *timeout / u32::try_from(instance_dirs.len())?;
+        let retry_per_instance =
+            retry.with_timeout(retry.timeout / u32::try_from(instance_dirs.len())?);
 
         // Iterate over instances and stop them one-by-one.
+        let mut outcomes = vec![];
         for instance_dir in instance_dirs {
-            stop_instance(&cluster_dir, &instance_dir, *signal, timeout_per_instance)?;
+            let stopped = stop_instance(&cluster_dir, &instance_dir, *signal, &retry_per_instance)?;
+            if stopped {
+                emit_stopped(&instance_dir);
+            }
+            outcomes.push(StopOutcome {
+                name: name_of(&instance_dir),
+                stopped,
+            });
         }
 
         info!("cluster stopped in {:?}", start.elapsed());
+        params.emit(&Event::ClusterStopped);
 
-        Ok(())
+        Ok(outcomes)
     }
 }
 
+/// Calls `pre-stop` hooks, broken `pike.toml` doesn't prevent stopping of the cluster.
+fn fire_pre_stop(plugin_path: &Path, cluster_dir: &Path, instance_names: &[&str]) {
+    match PikeConfig::load(plugin_path) {
+        Ok(config) => config.hooks.fire(
+            HookEvent::PreStop,
+            cluster_dir,
+            serde_json::json!({"instances": instance_names}),
+        ),
+        Err(err) => warn!("pre-stop hooks are skipped: {err:#}"),
+    }
+}
+
+fn confirm_stop(instance_dirs: &[&Path]) -> Result<bool> {
+    let items: Vec<String> = instance_dirs
+        .iter()
+        .map(|dir| {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            match pid_file::read(&dir.join(pid_file::PID_FILE_NAME)) {
+                Ok(pid) => format!("{name} (pid {pid})"),
+                Err(_) => name.into_owned(),
+            }
+        })
+        .collect();
+    confirm("The following instances will be stopped", &items)
+}
+
+/// Instance accepts connections on its admin socket.
+fn is_running(cluster_dir: &Path, instance_dir: &Path) -> bool {
+    instance_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| get_active_socket_path(cluster_dir, name).is_some())
+}
+
+/// Stops the instance, returns `false` if it is not running.
 fn stop_instance(
     cluster_dir: &Path,
     instance_dir: &Path,
     signal: Signal,
-    timeout: Duration,
-) -> Result<()> {
+    retry: &RetryPolicy,
+) -> Result<bool> {
     if !instance_dir.is_dir() {
         bail!("{} is not a directory", instance_dir.to_string_lossy());
     }
@@ -134,7 +349,16 @@ fn stop_instance(
         );
     };
 
-    let pid_file_path = instance_dir.join("pid");
+    if pid_file::remove_if_stale(cluster_dir, instance_dir)? {
+        info!(
+            "stopping picodata instance: {} - {}",
+            link_name.to_string_lossy(),
+            "SKIPPED".yellow()
+        );
+        return Ok(false);
+    }
+
+    let pid_file_path = instance_dir.join(pid_file::PID_FILE_NAME);
     if !pid_file_path.exists() {
         bail!(
             "PID file does not exist in folder: {}",
@@ -142,18 +366,19 @@ fn stop_instance(
         );
     }
 
-    let pid = read_pid_from_file(&pid_file_path).context("failed to read the PID file")?;
-
-    if get_active_socket_path(cluster_dir, link_name.to_str().unwrap()).is_none() {
-        info!(
-            "stopping picodata instance: {} - {}",
-            link_name.to_string_lossy(),
-            "SKIPPED".yellow()
+    let pid = pid_file::read(&pid_file_path).context("failed to read the PID file")?;
+    if !pid_file::is_picodata(pid) {
+        // The instance accepts connections, but the pid has been reused by another process.
+        warn!(
+            "PID {pid} of instance '{}' is not a running picodata process, it is not signalled",
+            link_name.to_string_lossy()
         );
-        return Ok(());
+        fs::remove_file(&pid_file_path)
+            .context(format!("failed to remove {}", pid_file_path.display()))?;
+        return Ok(false);
     }
 
-    if let Err(e) = send_signal_and_wait(pid, signal, timeout) {
+    if let Err(e) = send_signal_and_wait(pid, signal, retry) {
         bail!("failed to stop picodata instance with PID {pid}. Error: {e}");
     }
 
@@ -163,42 +388,25 @@ fn stop_instance(
         "OK".green()
     );
 
-    Ok(())
-}
-
-fn read_pid_from_file(pid_file_path: &Path) -> Result<Pid> {
-    let file = fs::File::open(pid_file_path)?;
-
-    let mut lines = io::BufReader::new(file).lines();
-    let pid_line = lines.next().context("PID file is empty")??;
-
-    let pid = pid_line.trim().parse::<i32>().context(format!(
-        "failed to parse PID from file {}",
-        pid_file_path.display()
-    ))?;
-
-    assert!(pid > 0);
-
-    Ok(Pid::from_raw(pid))
+    Ok(true)
 }
 
 /// Send signal to process and wait until it exits.
 ///
-/// If the process does not terminate within the specified timeout,
+/// If the process does not terminate until the retry policy is exhausted,
 /// SIGKILL is sent as a fallback.
-fn send_signal_and_wait(pid: Pid, signal: Signal, timeout: Duration) -> anyhow::Result<()> {
-    kill(pid, signal)?;
-
-    let start = Instant::now();
-    let delay = Duration::from_millis(100);
-
-    while start.elapsed() < timeout {
-        sleep(delay);
+fn send_signal_and_wait(pid: Pid, signal: Signal, retry: &RetryPolicy) -> anyhow::Result<()> {
+    match kill(pid, signal) {
+        // The process has exited meanwhile.
+        Err(Errno::ESRCH) => return Ok(()),
+        result => result?,
+    }
 
+    let terminated = retry.poll(|| {
         match kill(pid, None) {
             Err(Errno::ESRCH) => {
                 // Process no longer exists.
-                return Ok(());
+                return Ok(Some(()));
             }
             Err(err) => bail!(err),
             Ok(()) => {}
@@ -206,11 +414,20 @@ fn send_signal_and_wait(pid: Pid, signal: Signal, timeout: Duration) -> anyhow::
 
         #[cfg(target_os = "linux")]
         if is_zombie(pid) {
-            return Ok(());
+            return Ok(Some(()));
         }
+
+        Ok(None)
+    })?;
+
+    if terminated.is_some() {
+        return Ok(());
     }
 
-    warn!("Process {pid} did not terminate within {timeout:?}. Sending SIGKILL...");
+    warn!(
+        "Process {pid} did not terminate within {:?}. Sending SIGKILL...",
+        retry.timeout
+    );
     kill(pid, Signal::SIGKILL)?;
     Ok(())
 }
@@ -226,3 +443,51 @@ fn is_zombie(pid: Pid) -> bool {
         // Or we can't check process state or there is not such process in /proc.
         .map_or(true, |state| state == ProcState::Zombie)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn stop_of_not_running_cluster_fails_only_if_requested() {
+        let dir = TempWorkspace::new("stop-not-running").unwrap();
+        let params = |instance_name: Option<&str>, fail_if_not_running: bool| {
+            ParamsBuilder::default()
+                .plugin_path(dir.path().to_path_buf())
+                .instance_name(instance_name.map(String::from))
+                .confirm(false)
+                .fail_if_not_running(fail_if_not_running)
+                .build()
+                .unwrap()
+        };
+
+        // Cluster has never been started.
+        assert!(cmd(&params(None, false)).is_ok());
+        assert!(cmd(&params(None, true)).is_err());
+        assert!(cmd(&params(Some("i1"), false)).is_ok());
+        assert!(cmd(&params(Some("i1"), true)).is_err());
+
+        // Instance is already stopped, it has no pid file and no admin socket.
+        fs::create_dir_all(dir.join("tmp/cluster/i1")).unwrap();
+        assert!(cmd(&params(None, false)).is_ok());
+        assert!(cmd(&params(None, true)).is_err());
+        assert!(cmd(&params(Some("i1"), false)).is_ok());
+        assert!(cmd(&params(Some("i1"), true)).is_err());
+
+        // Instance has crashed, its pid file is left.
+        let mut exited = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = exited.id();
+        exited.wait().unwrap();
+        fs::write(dir.join("tmp/cluster/i1/pid"), format!("{dead_pid}\n")).unwrap();
+        let instance_dir = dir.join("tmp/cluster/i1");
+        assert!(!stop_instance(
+            &dir.join("tmp/cluster"),
+            &instance_dir,
+            DEFAULT_STOP_SIGNAL,
+            &DEFAULT_RETRY_POLICY
+        )
+        .unwrap());
+        assert!(!instance_dir.join("pid").exists());
+    }
+}
@@ -0,0 +1,272 @@
+//! Shift of the clock of single instances with libfaketime, so plugins with
+//! TTL or scheduling logic can be tested against clock skew and jumps.
+//!
+//! Offset of the instance is kept in the `faketime` file of its directory,
+//! libfaketime rereads it on each call, so the clock of the running instance
+//! jumps as soon as the offset is changed. The instance has to be started with
+//! the library preloaded, so it is restarted when its clock is shifted the first time.
+
+use crate::commands::instance::restart;
+use crate::commands::lib::{get_active_socket_path, get_cluster_dir};
+use crate::commands::run;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::info;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File with the offset of the instance clock, read by libfaketime.
+const OFFSET_FILE: &str = "faketime";
+/// File with the path to libfaketime preloaded into the instance.
+const LIB_FILE: &str = "faketime.lib";
+
+/// Environment variable to override the path to libfaketime.
+pub const FAKETIME_LIB_ENV: &str = "PIKE_FAKETIME_LIB";
+
+/// Places where packages of the distributions install libfaketime.
+const FAKETIME_LIB_PATHS: &[&str] = &[
+    "/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1",
+    "/usr/lib/aarch64-linux-gnu/faketime/libfaketime.so.1",
+    "/usr/lib/faketime/libfaketime.so.1",
+    "/usr/lib64/faketime/libfaketime.so.1",
+    "/usr/local/lib/faketime/libfaketime.so.1",
+];
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    instance_name: String,
+    /// Offset of the clock, e.g. `2h` or `-30m`, zero resets the clock.
+    offset: String,
+    /// Path to libfaketime, found in the known places by default.
+    #[builder(default)]
+    faketime_lib: Option<PathBuf>,
+    topology: run::Topology,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = "false")]
+    use_release: bool,
+    #[builder(default = "PathBuf::from(\"target\")")]
+    target_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./picodata.yaml\")")]
+    config_path: PathBuf,
+    #[builder(default = "false")]
+    with_audit: bool,
+}
+
+pub fn cmd(params: &Params) -> Result<()> {
+    let offset = parse_offset(&params.offset)?;
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let instance_dir = cluster_dir.join(&params.instance_name);
+    if !instance_dir.is_dir() {
+        bail!(
+            "failed to locate directory of the instance '{}'",
+            params.instance_name
+        );
+    }
+
+    let lib_path = instance_dir.join(LIB_FILE);
+    let preloaded = lib_path.exists();
+    if !preloaded {
+        let lib = find_faketime_lib(params.faketime_lib.as_deref())?;
+        fs::write(&lib_path, lib.to_string_lossy().as_bytes())
+            .context(format!("failed to write {}", lib_path.display()))?;
+    }
+    let offset_path = instance_dir.join(OFFSET_FILE);
+    fs::write(&offset_path, format!("{offset}\n"))
+        .context(format!("failed to write {}", offset_path.display()))?;
+    info!(
+        "clock of instance '{}' is shifted by {offset}",
+        params.instance_name
+    );
+
+    if get_active_socket_path(&cluster_dir, &params.instance_name).is_none() {
+        info!(
+            "instance '{}' is not running, the offset applies when it is started",
+            params.instance_name
+        );
+        return Ok(());
+    }
+    if preloaded {
+        return Ok(());
+    }
+
+    info!(
+        "restarting instance '{}' to preload libfaketime",
+        params.instance_name
+    );
+    let restart_params = restart::ParamsBuilder::default()
+        .instance_name(params.instance_name.clone())
+        .topology(params.topology.clone())
+        .data_dir(params.data_dir.clone())
+        .plugin_path(params.plugin_path.clone())
+        .picodata_path(params.picodata_path.clone())
+        .use_release(params.use_release)
+        .target_dir(params.target_dir.clone())
+        .config_path(params.config_path.clone())
+        .with_audit(params.with_audit)
+        .build()
+        .unwrap();
+    restart::cmd(&restart_params)
+}
+
+#[derive(Debug, Builder)]
+pub struct ResetParams {
+    instance_name: String,
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+}
+
+/// Returns the clock of the instance to the real time, libfaketime stays preloaded
+/// until the instance is restarted.
+pub fn reset(params: &ResetParams) -> Result<()> {
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let instance_dir = cluster_dir.join(&params.instance_name);
+    if !instance_dir.join(LIB_FILE).exists() {
+        info!(
+            "clock of instance '{}' is not shifted",
+            params.instance_name
+        );
+        return Ok(());
+    }
+
+    let offset_path = instance_dir.join(OFFSET_FILE);
+    fs::write(&offset_path, "+0\n")
+        .context(format!("failed to write {}", offset_path.display()))?;
+    info!("clock of instance '{}' is reset", params.instance_name);
+    Ok(())
+}
+
+/// Environment which preloads libfaketime into the instance, if its clock is shifted.
+pub(crate) fn instance_env(
+    instance_dir: &Path,
+    env_vars: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>> {
+    let lib_path = instance_dir.join(LIB_FILE);
+    if !lib_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let lib =
+        fs::read_to_string(&lib_path).context(format!("failed to read {}", lib_path.display()))?;
+    let lib = lib.trim();
+    if !Path::new(lib).is_file() {
+        bail!(
+            "libfaketime {lib} used to shift the clock of the instance is not found, \
+            run `pike time shift` again or remove {}",
+            lib_path.display()
+        );
+    }
+
+    let preload = match env_vars.get("LD_PRELOAD") {
+        Some(preload) if !preload.is_empty() => format!("{lib}:{preload}"),
+        _ => lib.to_string(),
+    };
+    let offset_path = std::path::absolute(instance_dir.join(OFFSET_FILE))
+        .context("failed to get absolute path of the faketime file")?;
+    Ok(BTreeMap::from([
+        ("LD_PRELOAD".to_string(), preload),
+        (
+            "FAKETIME_TIMESTAMP_FILE".to_string(),
+            offset_path.to_string_lossy().into_owned(),
+        ),
+        // Offset is reread on each call, otherwise changes are noticed in 10 seconds.
+        ("FAKETIME_NO_CACHE".to_string(), "1".to_string()),
+    ]))
+}
+
+fn find_faketime_lib(lib: Option<&Path>) -> Result<PathBuf> {
+    let lib = lib
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os(FAKETIME_LIB_ENV).map(PathBuf::from));
+    if let Some(lib) = lib {
+        if !lib.is_file() {
+            bail!("libfaketime is not found at {}", lib.display());
+        }
+        return std::path::absolute(&lib)
+            .context(format!("failed to get absolute path of {}", lib.display()));
+    }
+
+    FAKETIME_LIB_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+        .context(format!(
+            "libfaketime is not found, install it (e.g. `apt install faketime`) \
+            or pass its path with --faketime-lib or {FAKETIME_LIB_ENV}"
+        ))
+}
+
+/// Converts offset like `2h`, `-30m` or `+90s` to the relative offset of libfaketime.
+fn parse_offset(offset: &str) -> Result<String> {
+    let invalid = || {
+        format!(
+            "invalid offset '{offset}', expected a number of seconds \
+            with optional suffix s, m, h, d or y, e.g. 2h or -30m"
+        )
+    };
+
+    let (sign, rest) = match offset.strip_prefix('-') {
+        Some(rest) => ('-', rest),
+        None => ('+', offset.strip_prefix('+').unwrap_or(offset)),
+    };
+    let (number, suffix) = match rest.char_indices().last() {
+        Some((i, c)) if "smhdy".contains(c) => (&rest[..i], &rest[i..]),
+        _ => (rest, ""),
+    };
+    let value: f64 = number.parse().ok().context(invalid())?;
+    if !number.chars().all(|c| c.is_ascii_digit() || c == '.') || !value.is_finite() {
+        bail!(invalid());
+    }
+
+    // libfaketime takes seconds without a suffix.
+    let suffix = if suffix == "s" { "" } else { suffix };
+    Ok(format!("{sign}{number}{suffix}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::temp_workspace::TempWorkspace;
+
+    #[test]
+    fn offset_is_converted_to_faketime_format() {
+        assert_eq!(parse_offset("2h").unwrap(), "+2h");
+        assert_eq!(parse_offset("+2h").unwrap(), "+2h");
+        assert_eq!(parse_offset("-30m").unwrap(), "-30m");
+        assert_eq!(parse_offset("90s").unwrap(), "+90");
+        assert_eq!(parse_offset("1.5d").unwrap(), "+1.5d");
+        assert_eq!(parse_offset("0").unwrap(), "+0");
+
+        for offset in ["", "h", "2w", "--2h", "+-1", "2 h", "inf"] {
+            assert!(parse_offset(offset).is_err(), "{offset}");
+        }
+    }
+
+    #[test]
+    fn environment_preloads_faketime_of_shifted_instance() {
+        let dir = TempWorkspace::new("time-instance-env").unwrap();
+        assert!(instance_env(dir.path(), &BTreeMap::new())
+            .unwrap()
+            .is_empty());
+
+        let lib = dir.path().join("libfaketime.so.1");
+        fs::write(&lib, "").unwrap();
+        fs::write(dir.path().join(LIB_FILE), lib.to_str().unwrap()).unwrap();
+        let env_vars = BTreeMap::from([("LD_PRELOAD".to_string(), "libother.so".to_string())]);
+        let env = instance_env(dir.path(), &env_vars).unwrap();
+        assert_eq!(
+            env["LD_PRELOAD"],
+            format!("{}:libother.so", lib.to_str().unwrap())
+        );
+        assert_eq!(
+            env["FAKETIME_TIMESTAMP_FILE"],
+            dir.path().join(OFFSET_FILE).to_str().unwrap()
+        );
+    }
+}
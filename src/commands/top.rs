@@ -0,0 +1,350 @@
+//! Live view of resources used by picodata processes of the cluster started by pike.
+//!
+//! Instances are taken from `cluster.json`, their processes from pid files in
+//! the instance directories. CPU is measured between two refreshes, so the
+//! first table is printed after one interval.
+
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use crate::commands::lib::instance_info::InstanceSocketClient;
+use anyhow::{bail, Result};
+use derive_builder::Builder;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TOP_INTERVAL: Duration = Duration::from_secs(2);
+
+const XLOG_EXT: &str = "xlog";
+const MIB: f64 = 1024.0 * 1024.0;
+const HEADER: [&str; 8] = ["NAME", "TIER", "PID", "CPU%", "RSS", "FDS", "WAL", "LEADER"];
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    #[builder(default = DEFAULT_TOP_INTERVAL)]
+    interval: Duration,
+    /// Print a single table and exit.
+    #[builder(default = "false")]
+    once: bool,
+}
+
+/// Resources of a process at some moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ProcessSample {
+    /// User and system CPU time consumed since the process start.
+    cpu_time: Duration,
+    rss: u64,
+    /// Open file descriptors, not known on systems without procfs.
+    fds: Option<usize>,
+}
+
+#[cfg(target_os = "linux")]
+fn sample_process(pid: u32) -> Option<ProcessSample> {
+    use procfs::process::{ProcState, Process};
+    use procfs::WithCurrentSystemInfo;
+
+    let process = Process::new(i32::try_from(pid).ok()?).ok()?;
+    let stat = process.stat().ok()?;
+    if stat.state().ok()? == ProcState::Zombie {
+        return None;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let cpu_time = Duration::from_secs_f64(
+        (stat.utime + stat.stime) as f64 / procfs::ticks_per_second() as f64,
+    );
+    Some(ProcessSample {
+        cpu_time,
+        rss: stat.rss_bytes().get(),
+        fds: process.fd_count().ok(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sample_process(pid: u32) -> Option<ProcessSample> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "time=,rss=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut fields = output.split_whitespace();
+    let cpu_time = parse_ps_time(fields.next()?)?;
+    let rss_kib: u64 = fields.next()?.parse().ok()?;
+    Some(ProcessSample {
+        cpu_time,
+        rss: rss_kib * 1024,
+        fds: None,
+    })
+}
+
+/// CPU time printed by `ps -o time`, `[[dd-]hh:]mm:ss[.ss]`.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_ps_time(time: &str) -> Option<Duration> {
+    let (days, time) = match time.split_once('-') {
+        Some((days, time)) => (days.parse::<f64>().ok()?, time),
+        None => (0.0, time),
+    };
+    let mut seconds = days * 86400.0;
+    let mut multiplier = 1.0;
+    for part in time.rsplit(':') {
+        seconds += part.parse::<f64>().ok()? * multiplier;
+        multiplier *= 60.0;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// Pid from the pid file of the instance, `None` if it has never been started.
+fn read_pid(instance_dir: &Path) -> Option<u32> {
+    fs::read_to_string(instance_dir.join("pid"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Size of WAL files of the instance.
+fn wal_size(instance_dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(instance_dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == XLOG_EXT))
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// State of an instance at one refresh.
+#[derive(Debug, Clone, PartialEq)]
+struct InstanceSample {
+    pid: u32,
+    process: ProcessSample,
+    wal: u64,
+}
+
+fn sample_instances(instances: &[InstanceMetadata]) -> HashMap<String, InstanceSample> {
+    instances
+        .iter()
+        .filter_map(|instance| {
+            let pid = read_pid(&instance.data_dir)?;
+            let process = sample_process(pid)?;
+            let sample = InstanceSample {
+                pid,
+                process,
+                wal: wal_size(&instance.data_dir),
+            };
+            Some((instance.name.clone(), sample))
+        })
+        .collect()
+}
+
+fn mib(bytes: u64) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let mib = bytes as f64 / MIB;
+    format!("{mib:.1}M")
+}
+
+/// Table of instances, CPU usage is computed from samples `elapsed` apart.
+fn render(
+    instances: &[InstanceMetadata],
+    previous: &HashMap<String, InstanceSample>,
+    current: &HashMap<String, InstanceSample>,
+    elapsed: Duration,
+    leader: Option<&str>,
+) -> String {
+    let rows: Vec<[String; 8]> = instances
+        .iter()
+        .map(|instance| {
+            let mut row = [
+                instance.name.clone(),
+                instance.tier.clone(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                if leader == Some(instance.name.as_str()) {
+                    "*"
+                } else {
+                    ""
+                }
+                .to_string(),
+            ];
+            let Some(sample) = current.get(&instance.name) else {
+                return row;
+            };
+            row[2] = sample.pid.to_string();
+            // The instance might be restarted between samples.
+            if let Some(previous) = previous
+                .get(&instance.name)
+                .filter(|previous| previous.pid == sample.pid && !elapsed.is_zero())
+            {
+                let cpu = sample
+                    .process
+                    .cpu_time
+                    .saturating_sub(previous.process.cpu_time);
+                row[3] = format!("{:.1}", cpu.as_secs_f64() / elapsed.as_secs_f64() * 100.0);
+            }
+            row[4] = mib(sample.process.rss);
+            if let Some(fds) = sample.process.fds {
+                row[5] = fds.to_string();
+            }
+            row[6] = mib(sample.wal);
+            row
+        })
+        .collect();
+
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(HEADER.map(String::from)).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(widths) {
+            let _ = write!(line, "{cell:<width$}  ");
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Raft leader known to the first running instance.
+fn leader_name(
+    picodata_path: &PathBuf,
+    instances: &[InstanceMetadata],
+    samples: &HashMap<String, InstanceSample>,
+) -> Option<String> {
+    let instance = instances
+        .iter()
+        .find(|instance| samples.contains_key(&instance.name))?;
+    InstanceSocketClient::new(&instance.data_dir, picodata_path)
+        .leader_name()
+        .ok()
+}
+
+/// Refreshes the table of instances every interval until interrupted.
+pub fn cmd(params: &Params) -> Result<()> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(mut metadata) = ClusterMetadata::load(&path)? else {
+        bail!(
+            "cluster in {} has never been started",
+            params.plugin_path.join(&params.data_dir).display()
+        );
+    };
+    let interactive = !params.once && io::stdout().is_terminal();
+
+    let mut previous = sample_instances(&metadata.instances);
+    let mut sampled_at = Instant::now();
+    loop {
+        thread::sleep(params.interval);
+        // Instances might be added by `run --instance-name` meanwhile.
+        if let Some(updated) = ClusterMetadata::load(&path)? {
+            metadata = updated;
+        }
+        let current = sample_instances(&metadata.instances);
+        let elapsed = sampled_at.elapsed();
+        sampled_at = Instant::now();
+        let leader = leader_name(&params.picodata_path, &metadata.instances, &current);
+
+        let table = render(
+            &metadata.instances,
+            &previous,
+            &current,
+            elapsed,
+            leader.as_deref(),
+        );
+        if interactive {
+            // Clear the screen and move the cursor home.
+            print!("\x1b[2J\x1b[H");
+        }
+        print!("{table}");
+        if params.once {
+            return Ok(());
+        }
+        if !interactive {
+            println!();
+        }
+        previous = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_time_of_ps_is_parsed() {
+        assert_eq!(parse_ps_time("0:01.50"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_ps_time("01:02:03"), Some(Duration::from_secs(3723)));
+        assert_eq!(
+            parse_ps_time("2-00:00:01"),
+            Some(Duration::from_secs(2 * 86400 + 1))
+        );
+        assert_eq!(parse_ps_time("-"), None);
+    }
+
+    #[test]
+    fn instances_are_rendered_with_resources() {
+        let instance = |instance_id: u16| InstanceMetadata {
+            name: format!("default_1_{instance_id}"),
+            instance_id,
+            tier: "default".to_string(),
+            pid: 100,
+            data_dir: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}")),
+            admin_socket: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}/admin.sock")),
+            bin_port: 3000 + instance_id,
+            http_port: 8000 + instance_id,
+            pg_port: 5432 + instance_id,
+            restarts: 0,
+        };
+        let sample = |pid, cpu_ms, fds| InstanceSample {
+            pid,
+            process: ProcessSample {
+                cpu_time: Duration::from_millis(cpu_ms),
+                rss: 64 * 1024 * 1024,
+                fds,
+            },
+            wal: 1024 * 1024,
+        };
+        let previous = HashMap::from([
+            ("default_1_1".to_string(), sample(101, 1000, Some(40))),
+            ("default_1_2".to_string(), sample(102, 1000, None)),
+        ]);
+        let current = HashMap::from([
+            ("default_1_1".to_string(), sample(101, 2500, Some(42))),
+            // Restarted instance has no CPU usage until the next refresh.
+            ("default_1_2".to_string(), sample(202, 100, None)),
+        ]);
+
+        let table = render(
+            &[instance(1), instance(2), instance(3)],
+            &previous,
+            &current,
+            Duration::from_secs(2),
+            Some("default_1_1"),
+        );
+        assert_eq!(
+            table,
+            "\
+NAME         TIER     PID  CPU%  RSS    FDS  WAL   LEADER
+default_1_1  default  101  75.0  64.0M  42   1.0M  *
+default_1_2  default  202  -     64.0M  -    1.0M
+default_1_3  default  -    -     -      -    -
+"
+        );
+    }
+}
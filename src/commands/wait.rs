@@ -0,0 +1,321 @@
+//! Waiting for the running cluster to become ready, so scripts can run
+//! `pike run -d && pike wait && cargo test` without polling loops of their own.
+
+use crate::commands::lib::cluster_state::ClusterState;
+use crate::commands::lib::find_active_instance_dir;
+use crate::commands::lib::flag_env::env_or;
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::lib::instance_info::{InstanceSocketClient, Row};
+use crate::commands::lib::poll::{poll_until, Backoff};
+use crate::commands::run::Topology;
+use anyhow::Result;
+use derive_builder::Builder;
+use log::{debug, info};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Exit code of `pike wait` if the cluster is not ready within the timeout.
+pub const NOT_READY_EXIT_CODE: i32 = 2;
+/// Every check runs `picodata admin` for each instance of the cluster.
+const CHECK_BACKOFF: Backoff = Backoff::new(Duration::from_millis(200), Duration::from_secs(2));
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "env_or(\"data-dir\", PathBuf::from(\"./tmp\"))")]
+    data_dir: PathBuf,
+    #[builder(default = "env_or(\"plugin-path\", PathBuf::from(\"./\"))")]
+    plugin_path: PathBuf,
+    #[builder(default = "env_or(\"picodata-path\", PathBuf::from(\"picodata\"))")]
+    picodata_path: PathBuf,
+    /// Topology the cluster is started with: every its instance has to be online
+    /// and its plugins enabled. Without it all instances and plugins found in
+    /// the cluster are checked.
+    #[builder(default)]
+    topology: Option<Topology>,
+    /// Check that plugins are enabled.
+    #[builder(default = "true")]
+    check_plugins: bool,
+    /// SQL query which has to return a row without `false`, `null` or `0` values
+    /// once the other checks have passed.
+    #[builder(default)]
+    until_sql: Option<String>,
+    #[builder(default = "DEFAULT_WAIT_TIMEOUT")]
+    timeout: Duration,
+}
+
+/// The cluster is not ready within the timeout.
+#[derive(Debug)]
+pub struct NotReady {
+    timeout: Duration,
+    /// Checks which haven't passed last time.
+    problems: Vec<String>,
+}
+
+impl Display for NotReady {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cluster is not ready within {}s:",
+            self.timeout.as_secs()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NotReady {}
+
+/// Waits until every instance is online and is not going to change its state,
+/// replication in replicasets is healthy, plugins are enabled and `until_sql`
+/// returns a row, fails with [`NotReady`] listing the problems left on timeout.
+pub fn cmd(params: &Params) -> Result<()> {
+    info!(
+        "Waiting for the cluster to become ready (timeout {}s)",
+        params.timeout.as_secs()
+    );
+    let start = Instant::now();
+    let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+    let mut problems = vec![];
+    let ready = poll_until(start + params.timeout, &CHECK_BACKOFF, || {
+        problems = check(params, &cluster_dir);
+        if problems.is_empty() {
+            return Ok(Some(()));
+        }
+        debug!("Cluster is not ready yet: {}", problems.join(", "));
+        Ok(None)
+    })?;
+
+    if ready.is_none() {
+        return Err(NotReady {
+            timeout: params.timeout,
+            problems,
+        }
+        .into());
+    }
+    info!("Cluster is ready in {:.2?}", start.elapsed());
+    Ok(())
+}
+
+/// Problems preventing the cluster from being ready, empty if it is ready.
+fn check(params: &Params, cluster_dir: &Path) -> Vec<String> {
+    let state = match ClusterState::fetch(cluster_dir, &params.picodata_path) {
+        Ok(state) => state,
+        Err(err) => return vec![format!("failed to read state of the cluster: {err:#}")],
+    };
+    let mut problems = readiness_problems(&state, params.topology.as_ref(), params.check_plugins);
+    if problems.is_empty() {
+        problems = replication_problems(&state, cluster_dir, &params.picodata_path);
+    }
+    if problems.is_empty() {
+        if let Some(sql) = &params.until_sql {
+            problems.extend(sql_problem(sql, cluster_dir, &params.picodata_path));
+        }
+    }
+    problems
+}
+
+/// Instances and plugins which are not ready yet.
+fn readiness_problems(
+    state: &ClusterState,
+    topology: Option<&Topology>,
+    check_plugins: bool,
+) -> Vec<String> {
+    let mut problems = vec![];
+    if state.instances.is_empty() {
+        problems.push("cluster has no instances yet".to_string());
+    }
+    for instance in &state.instances {
+        if !instance.is_expelled() && !instance.is_online() {
+            problems.push(format!(
+                "instance '{}' is {:?}, its target state is {:?}",
+                instance.name, instance.current_state.variant, instance.target_state.variant
+            ));
+        }
+    }
+    if let Some(topology) = topology {
+        for (tier_name, tier) in &topology.tiers {
+            let expected = usize::from(tier.instance_count().unwrap_or_default());
+            let online = state.online_instances_in_tier(tier_name);
+            if online < expected {
+                problems.push(format!(
+                    "tier '{tier_name}' has {online}/{expected} instance(s) online"
+                ));
+            }
+        }
+    }
+    if !check_plugins {
+        return problems;
+    }
+
+    let Some(topology) = topology else {
+        problems.extend(
+            state
+                .plugins
+                .iter()
+                .filter(|plugin| !plugin.enabled)
+                .map(|plugin| {
+                    format!("plugin '{}' {} is not enabled", plugin.name, plugin.version)
+                }),
+        );
+        return problems;
+    };
+    for (plugin_name, plugin) in &topology.plugins {
+        let installed = state.plugins.iter().find(|installed| {
+            installed.name == *plugin_name
+                && plugin
+                    .version
+                    .as_ref()
+                    .is_none_or(|version| installed.version == *version)
+        });
+        match installed {
+            Some(installed) if installed.enabled => {}
+            Some(installed) => problems.push(format!(
+                "plugin '{plugin_name}' {} is not enabled",
+                installed.version
+            )),
+            None => problems.push(format!("plugin '{plugin_name}' is not installed")),
+        }
+    }
+    problems
+}
+
+/// Instances which don't follow other instances of their replicasets.
+fn replication_problems(
+    state: &ClusterState,
+    cluster_dir: &Path,
+    picodata_path: &Path,
+) -> Vec<String> {
+    let picodata_path = picodata_path.to_path_buf();
+    let mut problems = vec![];
+    for instance in state.instances.iter().filter(|i| !i.is_expelled()) {
+        // Names of instances are symlinks to their directories.
+        let client = InstanceSocketClient::new(&cluster_dir.join(&instance.name), &picodata_path);
+        match client.replication_upstream_statuses() {
+            Ok(statuses) => {
+                problems.extend(statuses.iter().filter(|status| *status != "follow").map(
+                    |status| format!("replication of instance '{}' is {status}", instance.name),
+                ));
+            }
+            Err(err) => problems.push(format!(
+                "failed to read replication status of instance '{}': {err:#}",
+                instance.name
+            )),
+        }
+    }
+    problems
+}
+
+/// Problem with the result of `--until-sql`, `None` if it is satisfied.
+fn sql_problem(sql: &str, cluster_dir: &Path, picodata_path: &Path) -> Option<String> {
+    let rows = find_active_instance_dir(cluster_dir).and_then(|instance_dir| {
+        InstanceSocketClient::new(&instance_dir, &picodata_path.to_path_buf()).query::<Row>(sql)
+    });
+    match rows {
+        Ok(rows) if is_satisfied(&rows) => None,
+        Ok(_) => Some(format!("query '{sql}' is not satisfied yet")),
+        Err(err) => Some(format!("{err:#}")),
+    }
+}
+
+/// The first row exists and has no `false`, `null` or `0` values.
+fn is_satisfied(rows: &[Row]) -> bool {
+    rows.first().is_some_and(|row| {
+        row.values().all(|value| {
+            !matches!(
+                value,
+                serde_json::Value::Null | serde_json::Value::Bool(false)
+            ) && value.as_f64() != Some(0.0)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::lib::instance_info::InstanceState;
+
+    #[test]
+    fn cluster_is_ready_when_topology_is_online_and_enabled() {
+        let topology: Topology = toml::from_str(
+            r#"
+            [tier.default]
+            replicasets = 1
+            replication_factor = 2
+
+            [plugin.p]
+            version = "0.2.0"
+            "#,
+        )
+        .unwrap();
+        let instance = |name: &str, current: &str, target: &str| {
+            serde_json::json!({
+                "name": name,
+                "replicaset_name": "default_1",
+                "tier": "default",
+                "current_state": [current, 1],
+                "target_state": [target, 1],
+            })
+        };
+        let mut state = ClusterState {
+            instances: serde_json::from_value(serde_json::json!([
+                instance("default_1_1", "Online", "Online"),
+                instance("default_1_2", "Offline", "Online"),
+            ]))
+            .unwrap(),
+            plugins: serde_json::from_value(serde_json::json!([
+                {"name": "p", "version": "0.1.0", "enabled": true},
+                {"name": "p", "version": "0.2.0", "enabled": false},
+            ]))
+            .unwrap(),
+        };
+
+        assert_eq!(
+            readiness_problems(&state, Some(&topology), true),
+            [
+                "instance 'default_1_2' is Offline, its target state is Online",
+                "tier 'default' has 1/2 instance(s) online",
+                "plugin 'p' 0.2.0 is not enabled",
+            ]
+        );
+        assert_eq!(readiness_problems(&state, Some(&topology), false).len(), 2);
+        assert_eq!(
+            readiness_problems(&state, None, true),
+            [
+                "instance 'default_1_2' is Offline, its target state is Online",
+                "plugin 'p' 0.2.0 is not enabled",
+            ]
+        );
+
+        state.instances[1].current_state.variant = InstanceState::Online;
+        state.plugins[1].enabled = true;
+        assert!(readiness_problems(&state, Some(&topology), true).is_empty());
+        assert!(readiness_problems(&state, None, true).is_empty());
+
+        state.plugins.clear();
+        assert_eq!(
+            readiness_problems(&state, Some(&topology), true),
+            ["plugin 'p' is not installed"]
+        );
+        assert_eq!(
+            readiness_problems(&ClusterState::default(), None, true),
+            ["cluster has no instances yet"]
+        );
+    }
+
+    #[test]
+    fn sql_predicate_needs_a_row_without_false_values() {
+        let rows = |rows: serde_json::Value| -> Vec<Row> { serde_json::from_value(rows).unwrap() };
+        assert!(!is_satisfied(&rows(serde_json::json!([]))));
+        assert!(is_satisfied(&rows(
+            serde_json::json!([{"id": 1, "name": "a"}])
+        )));
+        assert!(is_satisfied(&rows(serde_json::json!([{"COL_1": true}]))));
+        assert!(!is_satisfied(&rows(serde_json::json!([{"COL_1": false}]))));
+        assert!(!is_satisfied(&rows(serde_json::json!([{"COL_1": 0}]))));
+        assert!(!is_satisfied(&rows(serde_json::json!([{"id": null}]))));
+    }
+}
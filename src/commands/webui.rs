@@ -0,0 +1,184 @@
+//! Web UI addresses of instances of the cluster started by pike, read from `cluster.json`.
+//!
+//! If the cluster runs with `--with-web-auth`, Web UI asks for login, so the
+//! `pike` user of `connect` is created and its credentials are printed.
+
+use crate::commands::connect::{ensure_default_user, DEFAULT_USER};
+use crate::commands::lib::cluster_metadata::{ClusterMetadata, InstanceMetadata};
+use crate::commands::lib::get_cluster_dir;
+use crate::commands::lib::instance_info::InstanceSocketClient;
+use anyhow::{bail, Context, Result};
+use derive_builder::Builder;
+use log::{info, warn};
+use std::fmt::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Debug, Builder)]
+pub struct Params {
+    #[builder(default = "PathBuf::from(\"./tmp\")")]
+    data_dir: PathBuf,
+    #[builder(default = "PathBuf::from(\"./\")")]
+    plugin_path: PathBuf,
+    #[builder(default = "PathBuf::from(\"picodata\")")]
+    picodata_path: PathBuf,
+    /// Open Web UI of the leader in the default browser.
+    #[builder(default = "false")]
+    open: bool,
+}
+
+const HEADER: [&str; 5] = ["NAME", "TIER", "STATE", "LEADER", "URL"];
+
+/// Address of Web UI of the instance.
+fn url(instance: &InstanceMetadata) -> String {
+    format!("http://localhost:{}", instance.http_port)
+}
+
+/// Table of instances with addresses of their Web UI.
+fn render(
+    instances: &[InstanceMetadata],
+    leader: Option<&str>,
+    is_running: impl Fn(&InstanceMetadata) -> bool,
+) -> String {
+    let rows: Vec<[String; 5]> = instances
+        .iter()
+        .map(|instance| {
+            [
+                instance.name.clone(),
+                instance.tier.clone(),
+                if is_running(instance) {
+                    "running"
+                } else {
+                    "stopped"
+                }
+                .to_string(),
+                if leader == Some(instance.name.as_str()) {
+                    "*"
+                } else {
+                    ""
+                }
+                .to_string(),
+                url(instance),
+            ]
+        })
+        .collect();
+
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(HEADER.map(String::from)).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(widths) {
+            let _ = write!(line, "{cell:<width$}  ");
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Opens the URL in the default browser.
+fn open_in_browser(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let status = Command::new(opener)
+        .arg(url)
+        .status()
+        .context(format!("failed to execute {opener}, open {url} manually"))?;
+    if !status.success() {
+        bail!("{opener} failed with {status}, open {url} manually");
+    }
+    Ok(())
+}
+
+/// Prints Web UI addresses of instances and credentials to log in, opens
+/// Web UI of the raft leader with `--open`.
+pub fn cmd(params: &Params) -> Result<()> {
+    let path = ClusterMetadata::path(&params.plugin_path, &params.data_dir);
+    let Some(metadata) = ClusterMetadata::load(&path)? else {
+        bail!(
+            "cluster in {} has never been started",
+            params.plugin_path.join(&params.data_dir).display()
+        );
+    };
+
+    let is_running =
+        |instance: &InstanceMetadata| UnixStream::connect(&instance.admin_socket).is_ok();
+    let running = metadata.instances.iter().find(|i| is_running(i));
+    let leader = running.and_then(|instance| {
+        InstanceSocketClient::new(&instance.data_dir, &params.picodata_path)
+            .leader_name()
+            .inspect_err(|err| warn!("failed to get raft leader: {err:#}"))
+            .ok()
+    });
+    print!(
+        "{}",
+        render(&metadata.instances, leader.as_deref(), is_running)
+    );
+
+    let Some(running) = running else {
+        bail!("cluster has no running instances, start it with `cargo pike run`");
+    };
+    let target = leader
+        .as_deref()
+        .and_then(|leader| metadata.instances.iter().find(|i| i.name == leader))
+        .unwrap_or(running);
+
+    if metadata.with_web_auth {
+        let cluster_dir = get_cluster_dir(&params.plugin_path, &params.data_dir);
+        let password = ensure_default_user(&cluster_dir, target, &params.picodata_path)?;
+        println!("\nWeb UI login: {DEFAULT_USER}\nWeb UI password: {password}");
+    }
+
+    if params.open {
+        let url = url(target);
+        info!("Opening Web UI of instance '{}' at {url}", target.name);
+        open_in_browser(&url)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_are_rendered_with_urls() {
+        let instance = |instance_id: u16| InstanceMetadata {
+            name: format!("default_1_{instance_id}"),
+            instance_id,
+            tier: "default".to_string(),
+            pid: 100,
+            data_dir: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}")),
+            admin_socket: PathBuf::from(format!("/plugin/tmp/cluster/i{instance_id}/admin.sock")),
+            bin_port: 3000 + instance_id,
+            http_port: 8000 + instance_id,
+            pg_port: 5432 + instance_id,
+            restarts: 0,
+        };
+
+        let table = render(
+            &[instance(1), instance(2), instance(3)],
+            Some("default_1_2"),
+            |i| i.instance_id != 3,
+        );
+        assert_eq!(
+            table,
+            "\
+NAME         TIER     STATE    LEADER  URL
+default_1_1  default  running          http://localhost:8001
+default_1_2  default  running  *       http://localhost:8002
+default_1_3  default  stopped          http://localhost:8003
+"
+        );
+    }
+}
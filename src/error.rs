@@ -0,0 +1,130 @@
+//! Errors returned by the public API of pike.
+//!
+//! Commands use [`anyhow`] internally, an error is marked with the variant of [`Error`]
+//! where the failure is detected and context is added on top of it as usual. Public
+//! functions convert the resulting [`anyhow::Error`] into [`Error`] of the marked
+//! variant, so callers can match on what went wrong and still get the whole context chain.
+
+use crate::commands::lib::BuildFailure;
+use std::fmt;
+
+/// Error of the public API, each variant keeps the whole chain of the failure.
+#[derive(Debug)]
+#[non_exhaustive]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    /// `cargo build` of the plugin failed.
+    BuildError(anyhow::Error),
+    /// Picodata executable is not found.
+    PicodataNotFound(anyhow::Error),
+    /// Ports of the cluster are taken, usually by another cluster which is already running.
+    ClusterAlreadyRunning(anyhow::Error),
+    /// Cluster has no instance accepting connections on its admin socket.
+    AdminSocketUnavailable(anyhow::Error),
+    /// Migration of the plugin failed while it was installed.
+    MigrationFailed(anyhow::Error),
+    /// Config of plugin services doesn't match the schema.
+    InvalidConfig(anyhow::Error),
+    /// Data directory of the cluster is locked by another pike process.
+    DataDirLocked(anyhow::Error),
+    /// Any other failure.
+    Other(anyhow::Error),
+}
+
+/// Result of the public API of pike.
+#[allow(dead_code)]
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Self::BuildError(err)
+            | Self::PicodataNotFound(err)
+            | Self::ClusterAlreadyRunning(err)
+            | Self::AdminSocketUnavailable(err)
+            | Self::MigrationFailed(err)
+            | Self::InvalidConfig(err)
+            | Self::DataDirLocked(err)
+            | Self::Other(err) => err,
+        }
+    }
+
+    /// Output of `cargo build` if the build of the plugin failed.
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn build_output(&self) -> Option<&str> {
+        match self {
+            Self::BuildError(err) => err
+                .chain()
+                .find_map(|err| err.downcast_ref::<BuildFailure>())
+                .map(|failure| failure.output.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Constructor of the same variant.
+    fn variant(&self) -> fn(anyhow::Error) -> Self {
+        match self {
+            Self::BuildError(_) => Self::BuildError,
+            Self::PicodataNotFound(_) => Self::PicodataNotFound,
+            Self::ClusterAlreadyRunning(_) => Self::ClusterAlreadyRunning,
+            Self::AdminSocketUnavailable(_) => Self::AdminSocketUnavailable,
+            Self::MigrationFailed(_) => Self::MigrationFailed,
+            Self::InvalidConfig(_) => Self::InvalidConfig,
+            Self::DataDirLocked(_) => Self::DataDirLocked,
+            Self::Other(_) => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.inner(), f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().source()
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    /// Takes the variant of the innermost marked error of the chain.
+    fn from(err: anyhow::Error) -> Self {
+        // Downcast succeeds under context too, so it's done only if nothing is added on top.
+        if matches!(err.chain().next(), Some(outer) if outer.is::<Error>()) {
+            return err.downcast().expect("unreachable");
+        }
+        let variant = err
+            .chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .last()
+            .map_or(Self::Other as fn(_) -> _, Error::variant);
+        variant(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn variant_of_marked_error_is_kept_under_context() {
+        let err = Err::<(), _>(Error::MigrationFailed(anyhow!("MIGRATE TO 0.2.0 failed")))
+            .context("failed to install plugins")
+            .context("failed to run the cluster")
+            .unwrap_err();
+        let err = Error::from(err);
+        assert!(matches!(err, Error::MigrationFailed(_)));
+        assert_eq!(err.to_string(), "failed to run the cluster");
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(err)),
+            "failed to run the cluster: failed to install plugins: MIGRATE TO 0.2.0 failed"
+        );
+
+        let err = Error::from(anyhow!("something went wrong"));
+        assert!(matches!(err, Error::Other(_)));
+    }
+}
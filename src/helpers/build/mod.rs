@@ -1,7 +1,11 @@
+use crate::commands::lib::ignore::{copy_dir, IgnoreList, IGNORE_FILE_NAME};
+use crate::commands::lib::{PIKE_DATA_DIR_ENV, PIKE_PLUGINS_DIR_ENV, PIKE_PROFILE_ENV};
 use derive_builder::Builder;
 use fs_extra::dir;
 use fs_extra::dir::CopyOptions;
+use std::collections::HashMap;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
@@ -21,7 +25,33 @@ fn get_output_path() -> PathBuf {
         .collect()
 }
 
+fn pike_env(name: &str) -> Option<OsString> {
+    println!("cargo::rerun-if-env-changed={name}");
+    env::var_os(name)
+}
+
+/// Directory with built plugins which pike passes to picodata as `--share-dir`.
+///
+/// Returns `None` if the plugin is built by plain `cargo build` instead of pike.
+#[must_use]
+pub fn pike_plugins_dir() -> Option<PathBuf> {
+    pike_env(PIKE_PLUGINS_DIR_ENV).map(PathBuf::from)
+}
+
+/// Build profile of the plugin, `debug` or `release`, set by pike.
+#[must_use]
+pub fn pike_profile() -> Option<String> {
+    pike_env(PIKE_PROFILE_ENV).map(|profile| profile.to_string_lossy().into_owned())
+}
+
+/// Data directory of the cluster, set only when the plugin is built by `cargo pike run`.
+#[must_use]
+pub fn pike_data_dir() -> Option<PathBuf> {
+    pike_env(PIKE_DATA_DIR_ENV).map(PathBuf::from)
+}
+
 #[derive(Debug, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Params {
     #[builder(default = "PathBuf::from(MANIFEST_TEMPLATE_NAME)")]
     #[builder(setter(custom))]
@@ -29,6 +59,9 @@ pub struct Params {
     #[builder(default)]
     #[builder(setter(custom))]
     custom_assets: Vec<(PathBuf, PathBuf)>,
+    #[builder(default)]
+    #[builder(setter(custom))]
+    assets_ignore: Vec<String>,
 }
 
 impl ParamsBuilder {
@@ -73,9 +106,51 @@ impl ParamsBuilder {
 
         self
     }
+
+    /// Sets `.gitignore`-style patterns of files which are not copied
+    /// from custom asset directories, in addition to patterns from `.pikeignore`
+    pub fn assets_ignore<I, S>(&mut self, patterns: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut t = self.assets_ignore.take().unwrap_or_default();
+        t.extend(patterns.into_iter().map(|p| p.as_ref().to_string()));
+        self.assets_ignore = Some(t);
+
+        self
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_asset_collisions(self.custom_assets.as_deref().unwrap_or_default())
+    }
 }
 
-fn add_custom_assets(custom_assets: &Vec<(PathBuf, PathBuf)>, plugin_path: &Path) {
+/// Fails if different custom assets are copied to the same destination,
+/// otherwise the result depends on the order of copying.
+fn check_asset_collisions(custom_assets: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+    let mut sources: HashMap<PathBuf, &Path> = HashMap::new();
+    for (from_asset_path, to_asset_path) in custom_assets {
+        let source = sources
+            .entry(normalize_path(to_asset_path))
+            .or_insert(from_asset_path);
+        if *source != from_asset_path {
+            return Err(format!(
+                "custom assets {} and {} have the same destination {} in the assets folder",
+                source.display(),
+                from_asset_path.display(),
+                to_asset_path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn add_custom_assets(
+    custom_assets: &Vec<(PathBuf, PathBuf)>,
+    plugin_path: &Path,
+    ignore: &IgnoreList,
+) {
     for (from_asset_path, to_asset_path) in custom_assets {
         if !from_asset_path.exists() {
             println!(
@@ -104,15 +179,7 @@ fn add_custom_assets(custom_assets: &Vec<(PathBuf, PathBuf)>, plugin_path: &Path
         println!("cargo::rerun-if-changed={}", from_asset_path.display());
 
         if from_asset_path.is_dir() {
-            if !destination.exists() {
-                fs::create_dir_all(&destination).unwrap();
-            }
-
-            let mut options = fs_extra::dir::CopyOptions::new();
-            options.overwrite = true;
-            options.copy_inside = true;
-            options.content_only = true;
-            fs_extra::dir::copy(from_asset_path, &destination, &options).unwrap();
+            copy_dir(from_asset_path, &destination, to_asset_path, ignore).unwrap();
         } else {
             // Create a directory into which a file should be copied
             let parent_destination_directory = destination.parent().unwrap();
@@ -253,10 +320,40 @@ pub fn main(params: &Params) {
     // Create symlinks for newest plugin version, which would be created after build.rs script
     std::os::unix::fs::symlink(out_dir.join(&lib_name), plugin_path.join(lib_name)).unwrap();
 
-    add_custom_assets(&params.custom_assets, &plugin_path);
+    let ignore_file = crate_dir.join(IGNORE_FILE_NAME);
+    println!("cargo::rerun-if-changed={}", ignore_file.display());
+    let mut ignore = IgnoreList::from_file(&ignore_file).unwrap();
+    ignore.extend(&params.assets_ignore).unwrap();
+    add_custom_assets(&params.custom_assets, &plugin_path, &ignore);
 
     // Trigger on Cargo.toml change in order not to run cargo update each time
     // version is changed
     println!("cargo::rerun-if-changed=Cargo.toml");
     println!("cargo::rerun-if-changed={MANIFEST_TEMPLATE_NAME}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_assets_with_same_destination_are_rejected() {
+        let err = ParamsBuilder::default()
+            .custom_assets(["config/plugin_config.yaml"])
+            .custom_assets_with_targets([("plugin_config.yaml", "./plugin_config.yaml")])
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "custom assets config/plugin_config.yaml and plugin_config.yaml \
+            have the same destination ./plugin_config.yaml in the assets folder"
+        );
+
+        // The same asset listed twice is copied once.
+        ParamsBuilder::default()
+            .custom_assets(["plugin_config.yaml", "plugin_config.yaml"])
+            .custom_assets_with_targets([("src", "sources"), ("src", "sources/src")])
+            .build()
+            .unwrap();
+    }
+}
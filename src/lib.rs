@@ -1,27 +1,205 @@
 #![allow(dead_code, clippy::missing_errors_doc, clippy::missing_panics_doc)]
 mod commands;
+mod error;
 pub mod healthcheck;
 
+pub use error::{Error, Result};
+
 pub mod cluster {
-    pub use crate::commands::run::cluster as run;
+    use crate::commands::{run, stop};
+    use crate::Result;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    pub use crate::commands::lib::CargoArgs;
     pub use crate::commands::run::ParamsBuilder as RunParamsBuilder;
 
+    pub use crate::commands::lib::cluster_metadata::{
+        BasePorts, ClusterMetadata, InstanceMetadata,
+    };
+    pub use crate::commands::lib::cluster_state::{
+        ClusterState, InstanceStatus, PluginStatus, StateWithIncarnation,
+    };
+    pub use crate::commands::lib::events::{Event, Reporter};
+    pub use crate::commands::lib::instance_info::{InstanceState, Row};
+    pub use crate::commands::lib::poll::RetryPolicy;
+    pub use crate::commands::profile::profiler::Profiler;
+    pub use crate::commands::run::AutoPorts;
+    pub use crate::commands::run::Grant;
     pub use crate::commands::run::MigrationContextVar;
+    pub use crate::commands::run::PicodataCluster;
     pub use crate::commands::run::PicodataInstance;
     pub use crate::commands::run::PicodataInstanceProperties;
     pub use crate::commands::run::Plugin;
+    pub use crate::commands::run::ProcessSettings;
+    pub use crate::commands::run::ResourcePreset;
     pub use crate::commands::run::Service;
     pub use crate::commands::run::Tier;
+    pub use crate::commands::run::TlsSettings;
     pub use crate::commands::run::Topology;
+    pub use crate::commands::run::User;
+    pub use crate::commands::run::BOOTSTRAP_FILE_NAME;
 
-    pub use crate::commands::stop::cmd as stop;
     pub use crate::commands::stop::ParamsBuilder as StopParamsBuilder;
+
+    /// State of instances and raft leadership read through admin sockets.
+    pub mod info {
+        use crate::commands::lib::find_active_instance_dir;
+        use crate::commands::lib::instance_info::InstanceSocketClient;
+        use crate::Result;
+        use std::path::Path;
+
+        pub use crate::commands::lib::instance_info::InstanceState;
+
+        /// Name of the instance with data in `instance_dir`.
+        pub fn instance_name(instance_dir: &Path, picodata_path: &Path) -> Result<String> {
+            let picodata_path = picodata_path.to_path_buf();
+            Ok(InstanceSocketClient::new(instance_dir, &picodata_path).instance_name()?)
+        }
+
+        /// Current state of the instance with data in `instance_dir`.
+        pub fn current_state(instance_dir: &Path, picodata_path: &Path) -> Result<InstanceState> {
+            let picodata_path = picodata_path.to_path_buf();
+            Ok(InstanceSocketClient::new(instance_dir, &picodata_path).current_state()?)
+        }
+
+        /// Raft id of the leader of the cluster, fails if the leader is not elected.
+        pub fn leader_id(cluster_dir: &Path, picodata_path: &Path) -> Result<u64> {
+            let picodata_path = picodata_path.to_path_buf();
+            let instance_dir = find_active_instance_dir(cluster_dir)?;
+            Ok(InstanceSocketClient::new(&instance_dir, &picodata_path).leader_id()?)
+        }
+
+        /// Name of the instance which is the raft leader of the cluster.
+        pub fn leader_name(cluster_dir: &Path, picodata_path: &Path) -> Result<String> {
+            let picodata_path = picodata_path.to_path_buf();
+            let instance_dir = find_active_instance_dir(cluster_dir)?;
+            Ok(InstanceSocketClient::new(&instance_dir, &picodata_path).leader_name()?)
+        }
+    }
+
+    /// Data directory of the cluster named `name` inside `data_dir`, same as `--name`
+    /// of `cargo pike run`, so several clusters of the plugin can run at the same time.
+    pub fn named_data_dir(data_dir: &Path, name: &str) -> Result<PathBuf> {
+        Ok(crate::commands::lib::cluster_data_dir(
+            data_dir.to_path_buf(),
+            Some(name),
+        )?)
+    }
+
+    /// Builds plugins and starts the cluster, instances which are already running are kept.
+    pub fn run(params: run::Params) -> Result<PicodataCluster> {
+        Ok(run::cluster(params)?)
+    }
+
+    /// Stops instances of the cluster.
+    pub fn stop(params: &stop::Params) -> Result<()> {
+        Ok(stop::cmd(params)?)
+    }
+
+    /// Polls the cluster until `is_ready` returns `true` for its state,
+    /// see [`ClusterState`].
+    pub fn wait_ready<F>(
+        cluster_dir: &Path,
+        picodata_path: &Path,
+        timeout: Duration,
+        is_ready: F,
+    ) -> Result<ClusterState>
+    where
+        F: FnMut(&ClusterState) -> bool,
+    {
+        Ok(crate::commands::lib::cluster_state::wait_ready(
+            cluster_dir,
+            picodata_path,
+            timeout,
+            is_ready,
+        )?)
+    }
 }
 
 pub mod config {
-    pub use crate::commands::config::apply::cmd as apply;
+    use crate::commands::config::{apply, get};
+    use crate::Result;
+
     pub use crate::commands::config::apply::ConfigMap as PluginConfigMap;
     pub use crate::commands::config::apply::ParamsBuilder as ApplyParamsBuilder;
+    pub use crate::commands::config::get::ParamsBuilder as GetParamsBuilder;
+    pub use crate::commands::lib::poll::RetryPolicy;
+
+    /// Applies config of plugin services to the running cluster.
+    pub fn apply(params: &apply::Params) -> Result<()> {
+        Ok(apply::cmd(params)?)
+    }
+
+    /// Prints config of plugin services of the running cluster.
+    pub fn get(params: &get::Params) -> Result<()> {
+        Ok(get::cmd(params)?)
+    }
+
+    /// Reads config of plugin services of the running cluster.
+    pub fn fetch(params: &get::Params) -> Result<PluginConfigMap> {
+        Ok(get::fetch(params)?)
+    }
 }
 
 pub mod helpers;
+
+pub mod plugin {
+    use crate::commands::lib::timings::Timings;
+    use crate::commands::plugin::{lint, pack, publish, verify};
+    use crate::Result;
+    use std::path::Path;
+
+    pub use crate::commands::lib::archive::{ArchiveFormat, CompressionLevel};
+    pub use crate::commands::lib::repository::{Index, IndexEntry};
+    pub use crate::commands::lib::CargoArgs;
+    pub use crate::commands::plugin::pack::ParamsBuilder as PackParamsBuilder;
+    pub use crate::commands::plugin::pack::{ContentFilter, Packed, PackedArchive};
+    pub use crate::commands::plugin::publish::ParamsBuilder as PublishParamsBuilder;
+    pub use crate::commands::plugin::verify::ParamsBuilder as VerifyParamsBuilder;
+    pub use crate::commands::plugin::verify::{Diagnostic, Report, Status};
+
+    /// Builds the plugin and packs it into archives for shipping,
+    /// same as `cargo pike plugin pack`.
+    pub fn pack(params: &pack::Params) -> Result<Packed> {
+        Ok(pack::pack(params, &mut Timings::default())?)
+    }
+
+    /// Uploads plugin archives to the artifact repository and updates its index,
+    /// same as `cargo pike plugin publish`.
+    pub fn publish(params: &publish::Params) -> Result<Vec<IndexEntry>> {
+        Ok(publish::publish(params)?)
+    }
+
+    /// Checks the plugin archive or shipping directory, same as `cargo pike plugin verify`.
+    /// Problems of the plugin are returned as diagnostics of the report.
+    pub fn verify(params: &verify::Params) -> Result<Report> {
+        Ok(verify::verify(params)?)
+    }
+
+    /// Checks `manifest.yaml.template` of the plugin project, same as `cargo pike plugin lint`.
+    pub fn lint(plugin_path: &Path) -> Result<Report> {
+        Ok(lint::lint(plugin_path)?)
+    }
+}
+
+pub mod migration {
+    use crate::commands::migration;
+    use crate::Result;
+
+    pub use crate::commands::migration::AppliedMigration;
+    pub use crate::commands::migration::ParamsBuilder as AppliedParamsBuilder;
+
+    /// Migrations applied on the running cluster for the plugin, in the order they are applied.
+    pub fn applied(params: &migration::Params) -> Result<Vec<AppliedMigration>> {
+        Ok(migration::applied(params)?)
+    }
+}
+
+pub mod process {
+    pub use crate::commands::lib::process::{LogCapture, ManagedProcess};
+}
+
+pub mod testing {
+    pub use crate::commands::lib::temp_workspace::TempWorkspace;
+}
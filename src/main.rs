@@ -1,15 +1,25 @@
 use crate::commands::{
+    cluster::rolling_restart::DEFAULT_ONLINE_TIMEOUT,
+    instance::restart::DEFAULT_RESTART_SIGNAL,
+    lib::{find_plugin_root, flag_env, pike_config::PikeConfig, poll::RetryPolicy},
+    monitor::{Notifier, DEFAULT_LOG_ERRORS_THRESHOLD, DEFAULT_MONITOR_INTERVAL},
+    picodata::MIRROR_ENV,
     ride,
+    run::{AutoPorts, ResourcePreset},
     stop::{DEFAULT_STOP_SIGNAL, DEFAULT_STOP_TIMEOUT},
+    top::DEFAULT_TOP_INTERVAL,
 };
 use anyhow::{bail, Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use nix::{
     sys::signal::Signal,
     unistd::{fork, ForkResult},
 };
 use std::{
-    env, fs,
+    env,
+    ffi::OsString,
+    fs,
+    net::SocketAddr,
     path::{Path, PathBuf},
     process, thread,
     time::Duration,
@@ -17,6 +27,7 @@ use std::{
 use toml_edit::{DocumentMut, Item, Value};
 
 mod commands;
+mod error;
 mod healthcheck;
 
 const CK_CHECK_PARRENT_INTERVAL_SEC: u64 = 3;
@@ -72,10 +83,20 @@ const HUNGRY_SHARK: &str = r"
     disable_help_subcommand = true
 )]
 struct Cli {
+    /// Maximum time in seconds to wait for instances: readiness after `run`,
+    /// termination on `stop` and restarts, running instance on `config apply`
+    #[arg(long, global = true, value_name = "SECONDS")]
+    timeout: Option<u64>,
+    /// Format of the result printed to stdout: text or json. JSON is a single
+    /// document describing what was done, logs still go to stderr
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    output: commands::lib::OutputFormat,
     #[command(subcommand)]
     command: Command,
 }
 
+// Parsed once, size of the flags of `run` doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Command {
     /// Run Picodata cluster
@@ -86,21 +107,38 @@ enum Command {
         /// Path to data directory of the cluster
         #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
         data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`.
+        /// Several named clusters can run at the same time, a new named cluster
+        /// gets free ports like with --auto-ports=ephemeral unless base ports are set
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
         /// Disable the automatic installation of plugins
         #[arg(long)]
         disable_install_plugins: bool,
-        /// Base iproto port for picodata instances
-        #[arg(long, default_value = "3000")]
-        base_bin_port: u16,
-        /// Base http port for picodata instances
-        #[arg(long, default_value = "8000")]
-        base_http_port: u16,
-        /// Port for Pgproto server
-        #[arg(long, default_value = "5432")]
-        base_pg_port: u16,
+        /// Base iproto port for picodata instances, 3000 by default
+        #[arg(long)]
+        base_bin_port: Option<u16>,
+        /// Base http port for picodata instances, 8000 by default
+        #[arg(long)]
+        base_http_port: Option<u16>,
+        /// Port for Pgproto server, 5432 by default
+        #[arg(long)]
+        base_pg_port: Option<u16>,
         /// Specify path to picodata binary
         #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
         picodata_path: PathBuf,
+        /// Run the release installed by `picodata install` instead of `--picodata-path`,
+        /// overrides `picodata_version` of the topology. Example value: `25.1.2`
+        #[arg(long, value_name = "VERSION")]
+        picodata_version: Option<String>,
+        /// Do not check picodata version against pike and `picodata-plugin`
+        /// dependency of the plugin before the start
+        #[arg(long)]
+        skip_compat_check: bool,
+        /// Accept only TLS connections of pgproto clients, the certificate is taken
+        /// from `[tls]` of the topology or generated with openssl
+        #[arg(long)]
+        tls: bool,
         /// Run release version of plugin
         #[arg(long)]
         release: bool,
@@ -114,11 +152,39 @@ enum Command {
         #[arg(long)]
         disable_colors: bool,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
         /// Disable plugin build before cluster start
         #[arg(long)]
         no_build: bool,
+        #[command(flatten)]
+        cargo: CargoFlags,
+        /// Build external plugins from topology even if their sources haven't changed
+        #[arg(long)]
+        force_build: bool,
+        /// Take over the lock of the data directory held by a pike process
+        /// which is not running anymore
+        #[arg(long)]
+        force: bool,
+        /// Number of external plugins from topology built concurrently
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        external_build_jobs: usize,
+        /// Run only from built artifacts, fail before start if any of them is missing
+        #[arg(long)]
+        frozen: bool,
+        /// Minisign public key, external plugin archives must be signed by its secret key
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        verify_key: Option<PathBuf>,
+        /// Restart crashed instances with backoff, ignored with --daemon
+        #[arg(long)]
+        supervise: bool,
+        /// Keep started instances running if another instance or plugin
+        /// installation fails, by default they are killed
+        #[arg(long)]
+        keep_partial: bool,
+        /// Print lifecycle events of the cluster to stdout as NDJSON, logs go to stderr
+        #[arg(long)]
+        events_json: bool,
         /// Path to picodata config file
         #[arg(long, value_name = "CONFIG_PATH", default_value = "./picodata.yaml")]
         config_path: PathBuf,
@@ -145,14 +211,90 @@ enum Command {
         /// Timeout in seconds for waiting vshard discovery to complete.
         #[arg(long, value_name = "SECONDS", default_value_t = 300)]
         wait_vshard_discovery_timeout: u64,
+        /// Print time spent on cargo build, startup of each instance and plugin installation
+        #[arg(long)]
+        timings: bool,
+        /// Write phases of the cluster startup to the file in Chrome trace event format,
+        /// it is opened by Perfetto UI or `chrome://tracing`
+        #[arg(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            default_missing_value = "startup_profile.json"
+        )]
+        record_startup_profile: Option<PathBuf>,
+        /// Timeout in seconds for waiting raft leader election to print Web UI URL of the leader.
+        /// By default the leader is looked up once without waiting
+        #[arg(long, value_name = "SECONDS")]
+        leader_wait: Option<u64>,
+        /// Wait until every instance is Online with Online target state, replication
+        /// in replicasets is healthy and plugins of the topology are enabled,
+        /// fail if the cluster is not ready within the timeout in seconds
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "60"
+        )]
+        wait_ready: Option<u64>,
+        /// Print plan of plugins installation against the running cluster and exit
+        /// without starting instances or installing plugins
+        #[arg(long)]
+        plan_only: bool,
+        /// Start the instance under gdbserver or lldb-server paused until a debugger
+        /// is attached, other instances are started as usual. Example value: `i1`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        debug_instance: Option<String>,
+        /// Port of the debug server started for --debug-instance
+        #[arg(long, value_name = "PORT", default_value_t = 2345)]
+        debug_port: u16,
+        /// Change base ports of a new cluster if some ports of instances are in use,
+        /// instead of failing: `shift` moves them to the next free range, `ephemeral`
+        /// picks free ranges from the ephemeral range, so several clusters can run
+        /// at the same time. Ports are saved in `cluster.json` of the data dir
+        /// and reused on restarts
+        #[arg(
+            long,
+            value_name = "MODE",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "shift"
+        )]
+        auto_ports: Option<AutoPorts>,
+        /// Start the instance under the profiler, profiles are stored
+        /// in the `profile` directory of the instance. Example value: `i1`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        profile_instance: Option<String>,
+        /// Profiler for --profile-instance: heaptrack or perf
+        #[arg(long, value_name = "PROFILER", default_value = "heaptrack")]
+        profiler: commands::profile::profiler::Profiler,
+        /// Launch instances started by this run under the command, e.g.
+        /// `"valgrind --leak-check=full"`, use with --instance-name to wrap one instance.
+        /// The command is a template like `environment` of topology:
+        /// `"gdbserver :{{ instance_id + 1233 }}"`
+        #[arg(long, value_name = "COMMAND")]
+        wrap: Option<String>,
+        /// Small memory limits of instances, e.g. to run many instances on a laptop
+        #[arg(long, group = "preset")]
+        tiny: bool,
+        /// Memory settings from picodata config or picodata defaults
+        #[arg(long, group = "preset")]
+        default: bool,
+        /// Realistic memory settings of instances for load tests
+        #[arg(long, group = "preset")]
+        big: bool,
     },
     /// Stop Picodata cluster or a specific instance
     Stop {
         /// Path to data directory of the cluster
         #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
         data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
         /// Name of the instance to stop. If not specified, this command
         /// will stop all instances in the cluster.
@@ -166,25 +308,117 @@ enum Command {
             help = "Unix signal (e.g. SIGTERM, SIGKILL, SIGINT)"
         )]
         signal: Signal,
-        /// Maximum time to wait for graceful cluster termination.
-        /// If exceeded, instances will be killed with SIGKILL.
-        #[arg(
-            long,
-            short,
-            value_name = "TIMEOUT_SECS",
-            default_value_t = DEFAULT_STOP_TIMEOUT.as_secs(),
-            help = "Graceful shutdown timeout in seconds"
-        )]
-        timeout: u64,
+        /// Do not ask for confirmation before stopping instances
+        #[arg(short, long)]
+        yes: bool,
+        /// Print stopped instances to stdout as NDJSON events
+        #[arg(long)]
+        events_json: bool,
+        /// Exit with an error if the cluster or the instance is not running
+        #[arg(long)]
+        fail_if_not_running: bool,
+        /// Take over the lock of the data directory held by a pike process
+        /// which is not running anymore
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show instances of the cluster, their state and restarts
+    Status {
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+    },
+    /// Wait until the running cluster is ready: instances are Online, replication
+    /// is healthy and plugins are enabled. Exits with code 2 if the cluster is not
+    /// ready within --timeout (60 seconds by default)
+    Wait {
+        /// Topology the cluster is started with, if the file exists every its instance
+        /// has to be Online and its plugins enabled
+        #[arg(short, long, value_name = "TOPOLOGY", default_value = "topology.toml")]
+        topology: PathBuf,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// SQL query which also has to return a row without false, null or 0 values,
+        /// e.g. "SELECT count(*) > 0 FROM warehouse"
+        #[arg(long, value_name = "QUERY")]
+        until_sql: Option<String>,
+    },
+    /// Print logs of instances of the cluster
+    Logs {
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Print only records of the plugin service, matched by `service-pattern` of pike.toml
+        #[arg(long, value_name = "SERVICE_NAME")]
+        service: Option<String>,
+        /// Print logs of a single instance
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        instance_name: Option<String>,
+        /// Keep printing new records until interrupted
+        #[arg(short, long)]
+        follow: bool,
     },
     /// Remove all data files of previous cluster run
     Clean {
         /// Path to data directory of the cluster
         #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
         data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
+        /// Do not ask for confirmation before removing the data directory
+        #[arg(short, long)]
+        yes: bool,
+        /// Print paths which would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Name of the instance to clean. If not specified, data of
+        /// all instances is removed.
+        #[arg(short = 'i', long = "instance", value_name = "INSTANCE_NAME")]
+        instance_name: Option<String>,
+        /// Keep logs of instances
+        #[arg(long)]
+        keep_logs: bool,
+        /// Also stop and remove named clusters kept in `<DATA_DIR>/clusters`
+        #[arg(long, conflicts_with_all = ["name", "instance_name", "snapshots"])]
+        all: bool,
+        /// Remove only snapshots and WAL files older than the latest checkpoint
+        /// of each instance without stopping the cluster
+        #[arg(long, conflicts_with = "keep_logs")]
+        snapshots: bool,
+        /// Number of the newest checkpoints of each instance to keep with --snapshots
+        #[arg(long, value_name = "N", default_value = "1", requires = "snapshots",
+            value_parser = clap::value_parser!(u16).range(1..))]
+        keep: u16,
+        /// Take over the lock of the data directory held by a pike process
+        /// which is not running anymore
+        #[arg(long)]
+        force: bool,
     },
     /// Enter specific instance by name
     Enter {
@@ -193,13 +427,165 @@ enum Command {
         /// Path to data directory of the cluster
         #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
         data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+    },
+    /// Run SQL or Lua on an instance of the running cluster through its admin socket
+    Exec {
+        /// Name of the instance, e.g. `default_1_2`, or its directory, e.g. `i2`
+        instance_name: String,
+        /// SQL statements or Lua code with --lua
+        #[arg(required_unless_present = "file", conflicts_with = "file")]
+        query: Option<String>,
+        /// Read the query from the file
+        #[arg(short, long, value_name = "PATH")]
+        file: Option<PathBuf>,
+        /// Run Lua code instead of SQL
+        #[arg(long)]
+        lua: bool,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
         /// Specify path to picodata binary
         #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
         picodata_path: PathBuf,
     },
+    /// Open psql session to an instance of the running cluster
+    Connect {
+        /// Name of the instance to connect to, the first running instance if not set.
+        /// Example value: `default_1_1`
+        #[arg(long = "instance", value_name = "INSTANCE_NAME")]
+        instance_name: Option<String>,
+        /// Connect to the first running instance of the tier
+        #[arg(long, value_name = "TIER")]
+        tier: Option<String>,
+        /// Existing user to connect as, psql asks for its password. By default
+        /// pike creates user `pike` with a password saved in the data directory
+        #[arg(long, value_name = "USER")]
+        user: Option<String>,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Specify path to psql binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "psql")]
+        psql_path: PathBuf,
+        /// Arguments passed to psql after `--`. Example: `-- -c 'SELECT 1'`
+        #[arg(last = true, value_name = "PSQL_ARGS")]
+        psql_args: Vec<String>,
+    },
+    /// Print Web UI addresses of instances of the running cluster
+    Webui {
+        /// Open Web UI of the raft leader in the default browser
+        #[arg(long)]
+        open: bool,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+    },
+    /// Print Prometheus metrics of all instances of the running cluster with instance
+    /// and tier labels or serve them on a single port
+    Metrics {
+        /// Serve merged metrics on the address instead of printing them once.
+        /// Example value: `127.0.0.1:9100`
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<SocketAddr>,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+    },
+    /// Show CPU, memory, open files and WAL size of instances of the running cluster,
+    /// refreshed until interrupted
+    Top {
+        /// Interval between refreshes in seconds
+        #[arg(long, value_name = "SECS", default_value_t = DEFAULT_TOP_INTERVAL.as_secs())]
+        interval: u64,
+        /// Print the table once and exit
+        #[arg(long)]
+        once: bool,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+    },
+    /// Watch the running cluster and report instances which are down or not Online,
+    /// loss of quorum, large data directories and spikes of errors in logs
+    Monitor {
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Interval between checks of the cluster in seconds
+        #[arg(long, value_name = "SECS", default_value_t = DEFAULT_MONITOR_INTERVAL.as_secs())]
+        interval: u64,
+        /// Report instances which data directory is larger than the limit in MiB
+        #[arg(long, value_name = "MIB")]
+        disk_limit: Option<u64>,
+        /// Report instances which wrote at least this many errors to the log between checks
+        #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_LOG_ERRORS_THRESHOLD)]
+        log_errors: usize,
+        /// Show desktop notifications about problems
+        #[arg(long)]
+        notify: bool,
+        /// Post JSON of problems to the URL
+        #[arg(long, value_name = "URL")]
+        webhook: Vec<String>,
+        /// Check the cluster once and exit with an error if it is degraded
+        #[arg(long)]
+        once: bool,
+    },
     /// Helpers for work with plugins
     Plugin {
         #[command(subcommand)]
@@ -210,6 +596,41 @@ enum Command {
         #[command(subcommand)]
         command: Config,
     },
+    /// Helpers for debugging of crashed instances
+    Debug {
+        #[command(subcommand)]
+        command: Debug,
+    },
+    /// Helpers for profiling of instances
+    Profile {
+        #[command(subcommand)]
+        command: Profile,
+    },
+    /// Manage a single instance of the running cluster
+    Instance {
+        #[command(subcommand)]
+        command: Instance,
+    },
+    /// Manage all instances of the running cluster
+    Cluster {
+        #[command(subcommand)]
+        command: Cluster,
+    },
+    /// Manage picodata releases used by `run --picodata-version`
+    Picodata {
+        #[command(subcommand)]
+        command: Picodata,
+    },
+    /// Shift the clock of instances of the running cluster
+    Time {
+        #[command(subcommand)]
+        command: Time,
+    },
+    /// Helpers for work with migrations of plugins
+    Migration {
+        #[command(subcommand)]
+        command: Migration,
+    },
     #[command(hide = true)]
     /// Make life a ride
     Ride {},
@@ -226,14 +647,84 @@ enum Plugin {
         #[arg(long, value_name = "TARGET_DIR", default_value = "target")]
         target_dir: PathBuf,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
         /// Disable plugin build before packing the archive
         #[arg(long)]
         no_build: bool,
+        /// Do not lint manifest.yaml.template before packing the archive
+        #[arg(long)]
+        no_lint: bool,
+        #[command(flatten)]
+        cargo: CargoFlags,
         /// Override resulting archive file name or path (if relative, placed under <target>/<profile>)
         #[arg(long, value_name = "ARCHIVE_NAME")]
         archive_name: Option<PathBuf>,
+        /// Format of the archive: tar.gz, tar.zst or zip
+        #[arg(long, value_name = "FORMAT", default_value = "tar.gz")]
+        format: commands::lib::archive::ArchiveFormat,
+        /// Compression of the archive: none, fast or best
+        #[arg(long, value_name = "COMPRESSION", default_value = "best")]
+        compression: commands::lib::archive::CompressionLevel,
+        /// Normalize modification times, owners and permissions of archived files,
+        /// so packing the same sources gives byte-identical archives
+        #[arg(long)]
+        reproducible: bool,
+        /// Print time spent on cargo build and archive creation
+        #[arg(long)]
+        timings: bool,
+        /// `.gitignore`-style pattern of files not packed into the archive, matched
+        /// relative to the plugin root in the archive. Example value: `fixtures/`
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Pattern of files packed even if excluded by --exclude or `.pikeignore`
+        #[arg(long, value_name = "PATTERN")]
+        include: Vec<String>,
+        /// Do not pack migrations of the plugin, same as `--exclude migrations/`
+        #[arg(long)]
+        exclude_migrations: bool,
+        /// OS suffix of the default archive name instead of the detected one,
+        /// also can be set with `PIKE_OS_SUFFIX`. Example value: `ubuntu_24.04`
+        #[arg(long, value_name = "OS_SUFFIX")]
+        os_suffix: Option<String>,
+        /// Generate `CycloneDX` SBOM of the plugin dependencies, it is packed
+        /// into the archive and saved next to it as `<archive>.cdx.json`
+        #[arg(long)]
+        sbom: bool,
+        /// Check dependencies of the plugin against `RustSec` advisories and
+        /// licences, configured in the `[audit]` section of `pike.toml`
+        #[arg(long)]
+        audit: bool,
+        /// Sign the archive with the minisign secret key, the signature is saved
+        /// next to the archive as `<archive>.minisig`. Requires `minisign` installed
+        #[arg(long, value_name = "SECRET_KEY")]
+        sign_key: Option<PathBuf>,
+        /// Specify path to picodata binary the plugin is checked against,
+        /// the check is skipped if it is not installed
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Do not check picodata version against pike and `picodata-plugin`
+        /// dependency of the plugin before packing
+        #[arg(long)]
+        skip_compat_check: bool,
+    },
+    /// Upload plugin archives with their checksums and signatures to an artifact
+    /// repository and add them to its `index.json`
+    Publish {
+        /// Archives created by `plugin pack`
+        #[arg(value_name = "ARCHIVE", required = true)]
+        archives: Vec<PathBuf>,
+        /// Repository to upload to: `s3://<bucket>/<prefix>`, `http(s)://` URL or local
+        /// directory. By default `destination` of the `[publish]` section of pike.toml
+        #[arg(long, value_name = "DESTINATION")]
+        destination: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Check dependencies of the plugins against `RustSec` advisories and
+        /// licences before uploading, configured in the `[audit]` section of `pike.toml`
+        #[arg(long)]
+        audit: bool,
     },
     /// Alias for cargo build command
     Build {
@@ -244,8 +735,10 @@ enum Plugin {
         #[arg(long, short)]
         release: bool,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
+        #[command(flatten)]
+        cargo: CargoFlags,
     },
     /// Create a new Picodata plugin
     New {
@@ -263,7 +756,7 @@ enum Plugin {
         #[arg(value_name = "path")]
         path: PathBuf,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
     },
     /// Create a new Picodata plugin in an existing directory
@@ -275,6 +768,24 @@ enum Plugin {
         #[arg(long)]
         workspace: bool,
     },
+    /// Render manifest.yaml.template and check it against the plugin crate
+    Lint {
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+    },
+    /// Check structure, checksum and signature of the plugin archive
+    Verify {
+        /// Path to the plugin archive or shipping directory
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        /// Minisign public key to check the `<archive>.minisig` signature with
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        verify_key: Option<PathBuf>,
+        /// Check that the shared library exports entrypoints of a picodata plugin
+        #[arg(long)]
+        check_symbols: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -292,17 +803,350 @@ enum Config {
         /// Path to data directory of the cluster
         #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
         data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
         /// Path to the plugin's project directory
-        #[arg(long, value_name = "PLUGIN_PATH", default_value = "./")]
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
         plugin_path: PathBuf,
         /// Choose plugin which config should be applied
         #[arg(long, value_name = "PLUGIN_NAME")]
         plugin_name: Option<String>,
+        /// Version of the plugin, by default it is read from Cargo.toml of the plugin
+        #[arg(long, value_name = "PLUGIN_VERSION")]
+        plugin_version: Option<String>,
         #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
         picodata_path: PathBuf,
+        /// Path to schema of the config to validate against before applying.
+        /// By default `config.schema.json` from the plugin directory is used if exists
+        #[arg(long, value_name = "SCHEMA_PATH")]
+        schema_path: Option<PathBuf>,
+        /// Print keys which would be changed on the cluster with their old
+        /// and new values without applying the config
+        #[arg(long)]
+        dry_run: bool,
+        /// Print changes of the config on the cluster in unified diff format
+        /// without applying the config
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Print services config applied on Picodata cluster as YAML
+    Get {
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Choose plugin which config should be printed
+        #[arg(long, value_name = "PLUGIN_NAME")]
+        plugin_name: Option<String>,
+        /// Print config only for the given service, can be repeated
+        #[arg(long = "service", value_name = "SERVICE_NAME")]
+        services: Vec<String>,
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum Debug {
+    /// Open core dump of the crashed instance in gdb or lldb
+    Core {
+        /// Name of the crashed Picodata instance. Example value: `default_1_1`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        instance_name: String,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Name of the target directory with built plugins
+        #[arg(long, value_name = "TARGET_DIR", default_value = "target")]
+        target_dir: PathBuf,
+        /// Path to core dump, by default the newest one from the instance directory is used
+        #[arg(long, value_name = "CORE_PATH")]
+        core_path: Option<PathBuf>,
+        /// Debugger to use: gdb or lldb. By default the installed one is chosen
+        #[arg(long, value_name = "DEBUGGER")]
+        debugger: Option<commands::debug::core_dump::Debugger>,
+        /// Only print the debugger command without running it
+        #[arg(long)]
+        print_only: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum Profile {
+    /// Print report of the profile recorded by `run --profile-instance`
+    Report {
+        /// Name of the profiled Picodata instance. Example value: `i1`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        instance_name: String,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Path to the profile, by default the newest one of the instance is used
+        #[arg(long, value_name = "PROFILE_PATH")]
+        profile_path: Option<PathBuf>,
+        /// Only print the report command without running it
+        #[arg(long)]
+        print_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum Instance {
+    /// Stop the instance and start it again with the same parameters,
+    /// waiting for it to become Online
+    Restart {
+        /// Name of the instance to restart. Example value: `i1`
+        #[arg(value_name = "INSTANCE_NAME")]
+        instance_name: String,
+        #[arg(short, long, value_name = "TOPOLOGY", default_value = "topology.toml")]
+        topology: PathBuf,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Run release version of plugin
+        #[arg(long)]
+        release: bool,
+        /// Change target folder
+        #[arg(long, value_name = "TARGET_DIR", default_value = "target")]
+        target_dir: PathBuf,
+        /// Path to picodata config file
+        #[arg(long, value_name = "CONFIG_PATH", default_value = "./picodata.yaml")]
+        config_path: PathBuf,
+        /// Enables Picodata audit logging of the restarted instance
+        #[arg(long, value_name = "WITH_AUDIT", default_value_t = false)]
+        with_audit: bool,
+        /// Signal used to stop the instance.
+        #[arg(
+            long,
+            value_name = "SIGNAL",
+            default_value_t = DEFAULT_RESTART_SIGNAL,
+            help = "Unix signal (e.g. SIGTERM, SIGKILL, SIGINT)"
+        )]
+        signal: Signal,
+    },
+}
+
+#[derive(Subcommand)]
+enum Migration {
+    /// Print migrations of the plugin applied on the running cluster in the order they are applied
+    Applied {
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Name of the plugin, by default it is read from Cargo.toml of the plugin
+        #[arg(long, value_name = "PLUGIN_NAME")]
+        plugin_name: Option<String>,
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum Time {
+    /// Shift the clock of the instance with libfaketime, the instance is restarted
+    /// when its clock is shifted the first time
+    Shift {
+        /// Name of the instance. Example value: `i2`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        instance_name: String,
+        /// Offset of the clock in seconds or with suffix m, h, d, y. Example value: `2h`, `-30m`
+        #[arg(long, value_name = "OFFSET", allow_hyphen_values = true)]
+        offset: String,
+        /// Path to libfaketime, by default `PIKE_FAKETIME_LIB` or the known places are used
+        #[arg(long, value_name = "LIB_PATH")]
+        faketime_lib: Option<PathBuf>,
+        #[arg(short, long, value_name = "TOPOLOGY", default_value = "topology.toml")]
+        topology: PathBuf,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Run release version of plugin
+        #[arg(long)]
+        release: bool,
+        /// Change target folder
+        #[arg(long, value_name = "TARGET_DIR", default_value = "target")]
+        target_dir: PathBuf,
+        /// Path to picodata config file
+        #[arg(long, value_name = "CONFIG_PATH", default_value = "./picodata.yaml")]
+        config_path: PathBuf,
+        /// Enables Picodata audit logging of the restarted instance
+        #[arg(long, value_name = "WITH_AUDIT", default_value_t = false)]
+        with_audit: bool,
+    },
+    /// Return the clock of the instance to the real time
+    Reset {
+        /// Name of the instance. Example value: `i2`
+        #[arg(long, value_name = "INSTANCE_NAME")]
+        instance_name: String,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum Picodata {
+    /// Download picodata release for the current OS into `~/.pike/picodata/<version>/`
+    /// and verify its checksum
+    Install {
+        /// Release to install. Example value: `25.1.2`
+        #[arg(value_name = "VERSION")]
+        version: String,
+        /// Repository with releases, also can be set with `PIKE_PICODATA_MIRROR`
+        /// or `mirror` of the `[picodata]` section of `pike.toml`
+        #[arg(long, value_name = "MIRROR", env = MIRROR_ENV)]
+        mirror: Option<String>,
+        /// OS suffix of the release archive instead of the detected one,
+        /// also can be set with `PIKE_OS_SUFFIX`. Example value: `ubuntu_24.04`
+        #[arg(long, value_name = "OS_SUFFIX")]
+        os_suffix: Option<String>,
+        /// Download the release even if it is already installed
+        #[arg(long)]
+        force: bool,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum Cluster {
+    /// Restart instances one at a time replicaset by replicaset, waiting for each
+    /// of them to become Online. Aborts if the cluster loses quorum
+    RollingRestart {
+        #[arg(short, long, value_name = "TOPOLOGY", default_value = "topology.toml")]
+        topology: PathBuf,
+        /// Path to data directory of the cluster
+        #[arg(long, value_name = "DATA_DIR", default_value = "./tmp")]
+        data_dir: PathBuf,
+        /// Name of the cluster, its data is kept in `<DATA_DIR>/clusters/<NAME>`
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Path to the plugin's project directory
+        #[arg(long, value_name = "PLUGIN_PATH", default_value_os_t = default_plugin_path())]
+        plugin_path: PathBuf,
+        /// Specify path to picodata binary
+        #[arg(long, value_name = "BINARY_PATH", default_value = "picodata")]
+        picodata_path: PathBuf,
+        /// Run release version of plugin
+        #[arg(long)]
+        release: bool,
+        /// Change target folder
+        #[arg(long, value_name = "TARGET_DIR", default_value = "target")]
+        target_dir: PathBuf,
+        /// Path to picodata config file
+        #[arg(long, value_name = "CONFIG_PATH", default_value = "./picodata.yaml")]
+        config_path: PathBuf,
+        /// Enables Picodata audit logging of restarted instances
+        #[arg(long, value_name = "WITH_AUDIT", default_value_t = false)]
+        with_audit: bool,
+        /// Signal used to stop instances.
+        #[arg(
+            long,
+            value_name = "SIGNAL",
+            default_value_t = DEFAULT_RESTART_SIGNAL,
+            help = "Unix signal (e.g. SIGTERM, SIGKILL, SIGINT)"
+        )]
+        signal: Signal,
+        /// Maximum time to wait for the cluster to see the restarted instance Online
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_ONLINE_TIMEOUT.as_secs())]
+        online_timeout: u64,
+    },
+}
+
+/// Flags of `cargo build` passed through when pike builds plugins.
+#[derive(Args)]
+#[allow(clippy::struct_excessive_bools)]
+struct CargoFlags {
+    /// Space or comma separated list of plugin features to activate
+    #[arg(long, short = 'F', value_name = "FEATURES", value_delimiter = ',')]
+    features: Vec<String>,
+    /// Do not activate the `default` feature of the plugin
+    #[arg(long)]
+    no_default_features: bool,
+    /// Require Cargo.lock to be up to date
+    #[arg(long)]
+    locked: bool,
+    /// Build without accessing the network
+    #[arg(long)]
+    offline: bool,
+    /// Number of parallel jobs of cargo
+    #[arg(long, short = 'j', value_name = "N")]
+    jobs: Option<u32>,
+    /// Print output of cargo build only if it fails
+    #[arg(long)]
+    quiet: bool,
+}
+
+impl From<CargoFlags> for commands::lib::CargoArgs {
+    fn from(flags: CargoFlags) -> Self {
+        Self {
+            features: flags
+                .features
+                .iter()
+                .flat_map(|features| features.split_whitespace())
+                .map(String::from)
+                .collect(),
+            no_default_features: flags.no_default_features,
+            locked: flags.locked,
+            offline: flags.offline,
+            jobs: flags.jobs,
+            quiet: flags.quiet,
+        }
+    }
+}
+
 /// Separated supervisor process to kill child processes if the parent is dead.
 ///
 /// # Safety
@@ -319,6 +1163,16 @@ fn run_child_killer() {
         }
 
         libc::setsid();
+
+        // Supervisor outlives pike until the next check, it must not hold
+        // stdout of pike open for callers reading it until EOF.
+        let dev_null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if dev_null >= 0 {
+            for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                libc::dup2(dev_null, fd);
+            }
+            libc::close(dev_null);
+        }
     }
 
     let master_pid = i32::try_from(master_pid).expect("Master PID to big");
@@ -336,6 +1190,44 @@ fn run_child_killer() {
     process::exit(0)
 }
 
+/// Default of `--plugin-path`: the project containing the current directory,
+/// so pike can be run from its subdirectories and workspace members like cargo.
+fn default_plugin_path() -> PathBuf {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| find_plugin_root(&dir))
+        .unwrap_or_else(|| PathBuf::from("./"))
+}
+
+/// `--plugin-path` of the subcommand, `None` if the command has no such flag.
+fn plugin_path_of(matches: &ArgMatches) -> Option<PathBuf> {
+    match matches.subcommand() {
+        Some((_, matches)) => plugin_path_of(matches),
+        None => matches
+            .try_get_one::<PathBuf>("plugin_path")
+            .ok()
+            .flatten()
+            .cloned(),
+    }
+}
+
+/// Parses the command line with flags overridden by `PIKE_*` variables and defaults
+/// of flags from `[defaults]` of `pike.toml`, which is read from `--plugin-path`
+/// of the command.
+fn parse_cli() -> Result<Cli> {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    let command = flag_env::with_env_vars(Cli::command());
+    let plugin_path = command
+        .clone()
+        .try_get_matches_from(&args)
+        .ok()
+        .and_then(|matches| plugin_path_of(&matches))
+        .unwrap_or_else(default_plugin_path);
+    let command = PikeConfig::load(&plugin_path)?.defaults.apply(command)?;
+    let matches = command.get_matches_from(&args);
+    Ok(Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()))
+}
+
 fn is_required_path_exists(
     plugin_dir: &Path,
     required_path: &Path,
@@ -391,19 +1283,30 @@ fn modify_workspace(plugin_name: &str, plugin_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Retry policy of the command with timeout set by the global `--timeout` flag.
+fn retry_policy(default: RetryPolicy, timeout: Option<Duration>) -> RetryPolicy {
+    timeout.map_or(default, |timeout| default.with_timeout(timeout))
+}
+
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<()> {
     colog::init();
-    let cli = Cli::parse_from(env::args().skip(1));
+    let cli = parse_cli()?;
+    let timeout = cli.timeout.map(Duration::from_secs);
+    let output = cli.output;
 
     match cli.command {
         Command::Run {
             topology,
             data_dir,
+            name,
             disable_install_plugins: disable_plugin_install,
             base_bin_port,
             base_http_port,
             picodata_path,
+            picodata_version,
+            skip_compat_check,
+            tls,
             base_pg_port,
             release,
             target_dir,
@@ -411,13 +1314,37 @@ fn main() -> Result<()> {
             disable_colors,
             plugin_path,
             no_build,
+            cargo,
+            force_build,
+            force,
+            external_build_jobs,
+            frozen,
+            verify_key,
+            supervise,
+            keep_partial,
+            events_json,
             config_path,
             instance_name,
             with_web_auth,
             with_audit,
             wait_vshard_discovery,
             wait_vshard_discovery_timeout,
+            timings,
+            record_startup_profile,
+            leader_wait,
+            wait_ready,
+            plan_only,
+            debug_instance,
+            debug_port,
+            auto_ports,
+            profile_instance,
+            profiler,
+            wrap,
+            tiny,
+            default: _,
+            big,
         } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
             is_required_path_exists(&plugin_path, &topology, CARING_PIKE, 1);
 
             if !daemon {
@@ -426,60 +1353,204 @@ fn main() -> Result<()> {
 
             let topology = commands::run::Topology::parse_toml(&plugin_path.join(topology))?;
 
-            let params = commands::run::ParamsBuilder::default()
+            let mut builder = commands::run::ParamsBuilder::default();
+            let ports_are_set = [base_bin_port, base_http_port, base_pg_port]
+                .iter()
+                .any(Option::is_some);
+            if let Some(port) = base_bin_port {
+                builder.base_bin_port(port);
+            }
+            if let Some(port) = base_http_port {
+                builder.base_http_port(port);
+            }
+            if let Some(port) = base_pg_port {
+                builder.base_pg_port(port);
+            }
+            // A new named cluster must not take ports of the default one running nearby.
+            let is_new_named = name.is_some()
+                && !commands::lib::cluster_metadata::ClusterMetadata::path(&plugin_path, &data_dir)
+                    .exists();
+
+            let params = builder
+                .output(output)
                 .topology(topology)
                 .data_dir(data_dir)
                 .disable_plugin_install(disable_plugin_install)
-                .base_bin_port(base_bin_port)
-                .base_http_port(base_http_port)
                 .picodata_path(picodata_path)
-                .base_pg_port(base_pg_port)
+                .picodata_version(picodata_version)
+                .skip_compat_check(skip_compat_check)
+                .tls(tls)
                 .use_release(release)
                 .target_dir(target_dir)
                 .daemon(daemon)
                 .disable_colors(disable_colors)
                 .plugin_path(plugin_path)
                 .no_build(no_build)
+                .cargo_args(cargo.into())
+                .force_build(force_build)
+                .force_unlock(force)
+                .external_build_jobs(external_build_jobs)
+                .frozen(frozen)
+                .verify_key(verify_key)
+                .supervise(supervise)
+                .keep_partial(keep_partial)
+                .events_json(events_json)
                 .config_path(config_path)
                 .instance_name(instance_name)
                 .with_web_auth(with_web_auth)
                 .with_audit(with_audit)
                 .wait_vshard_discovery(wait_vshard_discovery)
                 .wait_vshard_discovery_timeout(wait_vshard_discovery_timeout)
+                .timings(timings)
+                .startup_profile(record_startup_profile)
+                .leader_wait(leader_wait.map(Duration::from_secs))
+                .wait_ready(wait_ready.map(Duration::from_secs))
+                .plan_only(plan_only)
+                .debug_instance(debug_instance)
+                .debug_port(debug_port)
+                .auto_ports(
+                    auto_ports.or((is_new_named && !ports_are_set).then_some(AutoPorts::Ephemeral)),
+                )
+                .profile_instance(profile_instance)
+                .profiler(profiler)
+                .wrap(wrap)
+                .retry(retry_policy(commands::run::DEFAULT_RETRY_POLICY, timeout))
+                .preset(if tiny {
+                    ResourcePreset::Tiny
+                } else if big {
+                    ResourcePreset::Big
+                } else {
+                    ResourcePreset::Default
+                })
                 .build()
                 .unwrap();
             commands::run::cmd(params).context("failed to execute Run command")?;
         }
         Command::Stop {
             data_dir,
+            name,
             plugin_path,
             instance_name,
             signal,
-            timeout,
+            yes,
+            events_json,
+            fail_if_not_running,
+            force,
         } => {
-            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
 
             run_child_killer();
-            let timeout = Duration::from_secs(timeout);
             let params = commands::stop::ParamsBuilder::default()
+                .output(output)
                 .data_dir(data_dir)
                 .plugin_path(plugin_path)
                 .instance_name(instance_name)
                 .signal(signal)
-                .timeout(timeout)
+                .retry(retry_policy(commands::stop::DEFAULT_RETRY_POLICY, timeout))
+                .confirm(!yes)
+                .events_json(events_json)
+                .fail_if_not_running(fail_if_not_running)
+                .force_unlock(force)
                 .build()
                 .unwrap();
             commands::stop::cmd(&params).context("failed to execute \"stop\" command")?;
         }
+        Command::Status {
+            data_dir,
+            name,
+            plugin_path,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            let params = commands::status::ParamsBuilder::default()
+                .output(output)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .build()
+                .unwrap();
+            commands::status::cmd(&params).context("failed to execute \"status\" command")?;
+        }
+        Command::Wait {
+            topology,
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+            until_sql,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            let topology_path = plugin_path.join(topology);
+            let topology = topology_path
+                .exists()
+                .then(|| commands::run::Topology::parse_toml(&topology_path))
+                .transpose()?;
+            let params = commands::wait::ParamsBuilder::default()
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .topology(topology)
+                .until_sql(until_sql)
+                .timeout(timeout.unwrap_or(commands::wait::DEFAULT_WAIT_TIMEOUT))
+                .build()
+                .unwrap();
+            if let Err(err) = commands::wait::cmd(&params) {
+                if let Some(not_ready) = err.downcast_ref::<commands::wait::NotReady>() {
+                    log::error!("{not_ready}");
+                    process::exit(commands::wait::NOT_READY_EXIT_CODE);
+                }
+                return Err(err.context("failed to execute \"wait\" command"));
+            }
+        }
+        Command::Logs {
+            data_dir,
+            name,
+            plugin_path,
+            service,
+            instance_name,
+            follow,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            let params = commands::logs::ParamsBuilder::default()
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .service(service)
+                .instance_name(instance_name)
+                .follow(follow)
+                .build()
+                .unwrap();
+            commands::logs::cmd(&params).context("failed to execute \"logs\" command")?;
+        }
         Command::Clean {
             data_dir,
+            name,
             plugin_path,
+            yes,
+            dry_run,
+            instance_name,
+            keep_logs,
+            all,
+            snapshots,
+            keep,
+            force,
         } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
             is_required_path_exists(&plugin_path, &data_dir, HUNGRY_SHARK, 0);
 
             run_child_killer();
-            commands::clean::cmd(&data_dir, &plugin_path)
-                .context("failed to execute \"clean\" command")?;
+            let params = commands::clean::ParamsBuilder::default()
+                .output(output)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .instance_name(instance_name)
+                .keep_logs(keep_logs)
+                .all(all)
+                .dry_run(dry_run)
+                .confirm(!yes)
+                .snapshots(snapshots)
+                .keep_checkpoints(keep.into())
+                .force_unlock(force)
+                .build()
+                .unwrap();
+            commands::clean::cmd(&params).context("failed to execute \"clean\" command")?;
         }
         Command::Ride {} => {
             ride::cmd()?;
@@ -487,15 +1558,156 @@ fn main() -> Result<()> {
         Command::Enter {
             instance_name,
             data_dir,
+            name,
             plugin_path,
             picodata_path,
         } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
             is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
 
             run_child_killer();
             commands::enter::cmd(&instance_name, &data_dir, &plugin_path, &picodata_path)
                 .context("failed to execute \"enter\" command")?;
         }
+        Command::Exec {
+            instance_name,
+            query,
+            file,
+            lua,
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            let params = commands::exec::ParamsBuilder::default()
+                .instance_name(instance_name)
+                .query(query)
+                .file(file)
+                .lua(lua)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .build()
+                .unwrap();
+            commands::exec::cmd(&params).context("failed to execute \"exec\" command")?;
+        }
+        Command::Connect {
+            instance_name,
+            tier,
+            user,
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+            psql_path,
+            psql_args,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+            let params = commands::connect::ParamsBuilder::default()
+                .instance_name(instance_name)
+                .tier(tier)
+                .user(user)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .psql_path(psql_path)
+                .psql_args(psql_args)
+                .build()
+                .unwrap();
+            commands::connect::cmd(&params).context("failed to execute \"connect\" command")?;
+        }
+        Command::Webui {
+            open,
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+            let params = commands::webui::ParamsBuilder::default()
+                .open(open)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .build()
+                .unwrap();
+            commands::webui::cmd(&params).context("failed to execute \"webui\" command")?;
+        }
+        Command::Metrics {
+            listen,
+            data_dir,
+            name,
+            plugin_path,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+            let params = commands::metrics::ParamsBuilder::default()
+                .listen(listen)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .build()
+                .unwrap();
+            commands::metrics::cmd(&params).context("failed to execute \"metrics\" command")?;
+        }
+        Command::Top {
+            interval,
+            once,
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+            let params = commands::top::ParamsBuilder::default()
+                .interval(Duration::from_secs(interval))
+                .once(once)
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .build()
+                .unwrap();
+            commands::top::cmd(&params).context("failed to execute \"top\" command")?;
+        }
+        Command::Monitor {
+            data_dir,
+            name,
+            plugin_path,
+            picodata_path,
+            interval,
+            disk_limit,
+            log_errors,
+            notify,
+            webhook,
+            once,
+        } => {
+            let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+            is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+            let mut notifiers: Vec<Notifier> = webhook.into_iter().map(Notifier::Webhook).collect();
+            if notify {
+                notifiers.push(Notifier::Desktop);
+            }
+            let params = commands::monitor::ParamsBuilder::default()
+                .data_dir(data_dir)
+                .plugin_path(plugin_path)
+                .picodata_path(picodata_path)
+                .interval(Duration::from_secs(interval))
+                .disk_limit(disk_limit.map(|mib| mib * 1024 * 1024))
+                .log_errors_threshold(log_errors)
+                .notifiers(notifiers)
+                .once(once)
+                .build()
+                .unwrap();
+            commands::monitor::cmd(&params).context("failed to execute \"monitor\" command")?;
+        }
         Command::Plugin { command } => {
             run_child_killer();
             match command {
@@ -504,27 +1716,79 @@ fn main() -> Result<()> {
                     target_dir,
                     plugin_path,
                     no_build,
+                    no_lint,
+                    cargo,
                     archive_name,
+                    format,
+                    compression,
+                    reproducible,
+                    timings,
+                    mut exclude,
+                    include,
+                    exclude_migrations,
+                    os_suffix,
+                    sbom,
+                    audit,
+                    sign_key,
+                    picodata_path,
+                    skip_compat_check,
                 } => {
                     is_required_path_exists(&plugin_path, Path::new("Cargo.toml"), CARING_PIKE, 1);
 
-                    commands::plugin::pack::cmd(
-                        debug,
-                        &target_dir,
-                        &plugin_path,
-                        no_build,
-                        archive_name.as_ref(),
-                    )
-                    .context("failed to execute \"pack\" command")?;
+                    if exclude_migrations {
+                        exclude.push("migrations/".to_string());
+                    }
+                    let params = commands::plugin::pack::ParamsBuilder::default()
+                        .debug(debug)
+                        .target_dir(target_dir)
+                        .plugin_path(plugin_path)
+                        .no_build(no_build)
+                        .no_lint(no_lint)
+                        .cargo_args(cargo.into())
+                        .archive_name(archive_name)
+                        .format(format)
+                        .compression(compression)
+                        .reproducible(reproducible)
+                        .os_suffix(os_suffix)
+                        .filter(commands::plugin::pack::ContentFilter { exclude, include })
+                        .timings(timings)
+                        .output(output)
+                        .sbom(sbom)
+                        .audit(audit)
+                        .sign_key(sign_key)
+                        .picodata_path(picodata_path)
+                        .skip_compat_check(skip_compat_check)
+                        .build()
+                        .unwrap();
+                    commands::plugin::pack::cmd(&params)
+                        .context("failed to execute \"pack\" command")?;
+                }
+                Plugin::Publish {
+                    archives,
+                    destination,
+                    plugin_path,
+                    audit,
+                } => {
+                    let params = commands::plugin::publish::ParamsBuilder::default()
+                        .archives(archives)
+                        .destination(destination)
+                        .plugin_path(plugin_path)
+                        .audit(audit)
+                        .output(output)
+                        .build()
+                        .unwrap();
+                    commands::plugin::publish::cmd(&params)
+                        .context("failed to execute \"plugin publish\" command")?;
                 }
                 Plugin::Build {
                     release,
                     target_dir,
                     plugin_path,
+                    cargo,
                 } => {
                     is_required_path_exists(&plugin_path, Path::new("Cargo.toml"), CARING_PIKE, 1);
 
-                    commands::plugin::build::cmd(release, &target_dir, &plugin_path)
+                    commands::plugin::build::cmd(release, &target_dir, &plugin_path, &cargo.into())
                         .context("failed to execute \"build\" command")?;
                 }
                 Plugin::New {
@@ -550,29 +1814,308 @@ fn main() -> Result<()> {
                     fs::remove_file(plugin_path.join(&path).join("picodata.yaml"))?;
                     fs::remove_file(plugin_path.join(&path).join("topology.toml"))?;
                 }
+                Plugin::Lint { plugin_path } => {
+                    is_required_path_exists(&plugin_path, Path::new("Cargo.toml"), CARING_PIKE, 1);
+
+                    let params = commands::plugin::lint::ParamsBuilder::default()
+                        .plugin_path(plugin_path)
+                        .output(output)
+                        .build()
+                        .unwrap();
+                    commands::plugin::lint::cmd(&params)
+                        .context("failed to execute \"plugin lint\" command")?;
+                }
+                Plugin::Verify {
+                    path,
+                    verify_key,
+                    check_symbols,
+                } => {
+                    let params = commands::plugin::verify::ParamsBuilder::default()
+                        .path(path)
+                        .verify_key(verify_key)
+                        .check_symbols(check_symbols)
+                        .output(output)
+                        .build()
+                        .unwrap();
+                    commands::plugin::verify::cmd(&params)
+                        .context("failed to execute \"plugin verify\" command")?;
+                }
             }
         }
+        Command::Debug { command } => match command {
+            Debug::Core {
+                instance_name,
+                data_dir,
+                name,
+                plugin_path,
+                picodata_path,
+                target_dir,
+                core_path,
+                debugger,
+                print_only,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                let params = commands::debug::core_dump::ParamsBuilder::default()
+                    .instance_name(instance_name)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .picodata_path(picodata_path)
+                    .target_dir(target_dir)
+                    .core_path(core_path)
+                    .debugger(debugger)
+                    .print_only(print_only)
+                    .build()
+                    .unwrap();
+                commands::debug::core_dump::cmd(&params)
+                    .context("failed to execute \"debug core\" command")?;
+            }
+        },
+        Command::Profile { command } => match command {
+            Profile::Report {
+                instance_name,
+                data_dir,
+                name,
+                plugin_path,
+                profile_path,
+                print_only,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                let params = commands::profile::report::ParamsBuilder::default()
+                    .instance_name(instance_name)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .profile_path(profile_path)
+                    .print_only(print_only)
+                    .build()
+                    .unwrap();
+                commands::profile::report::cmd(&params)
+                    .context("failed to execute \"profile report\" command")?;
+            }
+        },
+        Command::Instance { command } => match command {
+            Instance::Restart {
+                instance_name,
+                topology,
+                data_dir,
+                name,
+                plugin_path,
+                picodata_path,
+                release,
+                target_dir,
+                config_path,
+                with_audit,
+                signal,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+                let topology = commands::run::Topology::parse_toml(&plugin_path.join(topology))?;
+                let params = commands::instance::restart::ParamsBuilder::default()
+                    .instance_name(instance_name)
+                    .topology(topology)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .picodata_path(picodata_path)
+                    .use_release(release)
+                    .target_dir(target_dir)
+                    .config_path(config_path)
+                    .with_audit(with_audit)
+                    .signal(signal)
+                    .timeout(timeout.unwrap_or(DEFAULT_STOP_TIMEOUT))
+                    .build()
+                    .unwrap();
+                commands::instance::restart::cmd(&params)
+                    .context("failed to execute \"instance restart\" command")?;
+            }
+        },
+        Command::Migration { command } => match command {
+            Migration::Applied {
+                data_dir,
+                name,
+                plugin_path,
+                plugin_name,
+                picodata_path,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                run_child_killer();
+                let params = commands::migration::ParamsBuilder::default()
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .plugin_name(plugin_name)
+                    .picodata_path(picodata_path)
+                    .output(output)
+                    .build()
+                    .unwrap();
+                commands::migration::cmd_applied(&params)
+                    .context("failed to execute \"migration applied\" command")?;
+            }
+        },
+        Command::Time { command } => match command {
+            Time::Shift {
+                instance_name,
+                offset,
+                faketime_lib,
+                topology,
+                data_dir,
+                name,
+                plugin_path,
+                picodata_path,
+                release,
+                target_dir,
+                config_path,
+                with_audit,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+                let topology = commands::run::Topology::parse_toml(&plugin_path.join(topology))?;
+                let params = commands::time::ParamsBuilder::default()
+                    .instance_name(instance_name)
+                    .offset(offset)
+                    .faketime_lib(faketime_lib)
+                    .topology(topology)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .picodata_path(picodata_path)
+                    .use_release(release)
+                    .target_dir(target_dir)
+                    .config_path(config_path)
+                    .with_audit(with_audit)
+                    .build()
+                    .unwrap();
+                commands::time::cmd(&params).context("failed to execute \"time shift\" command")?;
+            }
+            Time::Reset {
+                instance_name,
+                data_dir,
+                name,
+                plugin_path,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+                let params = commands::time::ResetParamsBuilder::default()
+                    .instance_name(instance_name)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .build()
+                    .unwrap();
+                commands::time::reset(&params)
+                    .context("failed to execute \"time reset\" command")?;
+            }
+        },
+        Command::Picodata { command } => match command {
+            Picodata::Install {
+                version,
+                mirror,
+                os_suffix,
+                force,
+                plugin_path,
+            } => {
+                let params = commands::picodata::install::ParamsBuilder::default()
+                    .version(version)
+                    .mirror(mirror)
+                    .os_suffix(os_suffix)
+                    .force(force)
+                    .plugin_path(plugin_path)
+                    .build()
+                    .unwrap();
+                commands::picodata::install::cmd(&params)
+                    .context("failed to execute \"picodata install\" command")?;
+            }
+        },
+        Command::Cluster { command } => match command {
+            Cluster::RollingRestart {
+                topology,
+                data_dir,
+                name,
+                plugin_path,
+                picodata_path,
+                release,
+                target_dir,
+                config_path,
+                with_audit,
+                signal,
+                online_timeout,
+            } => {
+                let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                is_required_path_exists(&plugin_path, &data_dir, CARING_PIKE, 1);
+
+                let topology = commands::run::Topology::parse_toml(&plugin_path.join(topology))?;
+                let params = commands::cluster::rolling_restart::ParamsBuilder::default()
+                    .topology(topology)
+                    .data_dir(data_dir)
+                    .plugin_path(plugin_path)
+                    .picodata_path(picodata_path)
+                    .use_release(release)
+                    .target_dir(target_dir)
+                    .config_path(config_path)
+                    .with_audit(with_audit)
+                    .signal(signal)
+                    .timeout(timeout.unwrap_or(DEFAULT_STOP_TIMEOUT))
+                    .online_timeout(Duration::from_secs(online_timeout))
+                    .build()
+                    .unwrap();
+                commands::cluster::rolling_restart::cmd(&params)
+                    .context("failed to execute \"cluster rolling-restart\" command")?;
+            }
+        },
         Command::Config { command } => {
             run_child_killer();
             match command {
                 Config::Apply {
                     config_path,
                     data_dir,
+                    name,
                     plugin_path,
                     plugin_name,
+                    plugin_version,
                     picodata_path,
+                    schema_path,
+                    dry_run,
+                    diff,
                 } => {
+                    let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
                     let params = commands::config::apply::ParamsBuilder::default()
+                        .output(output)
                         .config_path(config_path)
                         .data_dir(data_dir)
                         .plugin_path(plugin_path)
                         .plugin_name(plugin_name)
+                        .plugin_version(plugin_version)
                         .picodata_path(picodata_path)
+                        .schema_path(schema_path)
+                        .dry_run(dry_run)
+                        .diff(diff)
+                        .retry(retry_policy(
+                            commands::config::apply::DEFAULT_RETRY_POLICY,
+                            timeout,
+                        ))
                         .build()
                         .unwrap();
                     commands::config::apply::cmd(&params)
                         .context("failed to execute \"config apply\" command")?;
                 }
+                Config::Get {
+                    data_dir,
+                    name,
+                    plugin_path,
+                    plugin_name,
+                    services,
+                    picodata_path,
+                } => {
+                    let data_dir = commands::lib::cluster_data_dir(data_dir, name.as_deref())?;
+                    let params = commands::config::get::ParamsBuilder::default()
+                        .data_dir(data_dir)
+                        .plugin_path(plugin_path)
+                        .plugin_name(plugin_name)
+                        .services(services)
+                        .picodata_path(picodata_path)
+                        .build()
+                        .unwrap();
+                    commands::config::get::cmd(&params)
+                        .context("failed to execute \"config get\" command")?;
+                }
             }
         }
     }
@@ -0,0 +1,10 @@
+use pike::helpers::build;
+
+fn main() {
+    let params = build::ParamsBuilder::default()
+        .custom_assets_with_targets([("src", "sources")])
+        .assets_ignore(["*.swp"])
+        .build()
+        .unwrap();
+    build::main(&params);
+}
@@ -5,9 +5,8 @@ use std::path::Path;
 
 #[test]
 fn test_cargo_build() {
-    let plugin_path = Path::new("./tests/tmp/test-plugin-build");
-
-    init_plugin("test-plugin-build");
+    let plugin = init_plugin("test-plugin-build");
+    let plugin_path = plugin.path();
 
     build_plugin(&helpers::BuildType::Debug, "0.1.0", plugin_path);
     build_plugin(&helpers::BuildType::Debug, "0.1.1", plugin_path);
@@ -1,8 +1,8 @@
 mod helpers;
 
 use helpers::{
-    exec_pike, get_picodata_table, init_plugin_workspace, run_cluster, CmdArguments, PLUGIN_DIR,
-    TESTS_DIR,
+    exec_pike, get_picodata_table, init_plugin_workspace, run_cluster,
+    wait_cluster_start_completed, wait_until, CmdArguments, ASSETS_DIR, PLUGIN_DIR, TESTS_DIR,
 };
 use rstest::rstest;
 use std::{
@@ -11,7 +11,7 @@ use std::{
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    time::{Duration, Instant},
+    time::Duration,
     vec,
 };
 
@@ -66,19 +66,19 @@ fn test_config_apply(#[case] params_builder: ApplyParamsBuilder) {
 
     pike::config::apply(&params).expect("Failed to apply plugin configuration");
 
-    let start = Instant::now();
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
+    let applied = wait_until(Duration::from_secs(60), || {
         let pico_plugin_config = get_picodata_table(
             Path::new(PLUGIN_DIR),
             Path::new("tmp"),
             "_pico_plugin_config",
         );
-        if pico_plugin_config.contains("value") && pico_plugin_config.contains("changed") {
-            return;
-        }
-    }
+        pico_plugin_config.contains("value") && pico_plugin_config.contains("changed")
+    });
 
-    panic!("Timeouted while trying to apply cluster config, value hasn't changed");
+    assert!(
+        applied,
+        "Timeouted while trying to apply cluster config, value hasn't changed"
+    );
 }
 
 #[test]
@@ -92,7 +92,7 @@ fn test_corrupted_config() {
 
     let _ = fs::remove_file(Path::new(PLUGIN_DIR).join("plugin_config.yaml"));
     fs::copy(
-        Path::new(TESTS_DIR).join("../assets/corrupted_config.yaml"),
+        Path::new(ASSETS_DIR).join("corrupted_config.yaml"),
         Path::new(PLUGIN_DIR).join("plugin_config.yaml"),
     )
     .unwrap();
@@ -137,7 +137,7 @@ fn test_workspace_config_apply() {
     let tests_dir = Path::new(TESTS_DIR);
     let workspace_path = tests_dir.join("workspace_plugin");
 
-    init_plugin_workspace("workspace_plugin");
+    let _plugin = init_plugin_workspace("workspace_plugin");
 
     exec_pike([
         "plugin",
@@ -176,21 +176,9 @@ fn test_workspace_config_apply() {
     // Run cluster and check successful plugin installation
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut is_cluster_valid = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_instance");
-        let pico_plugin = get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_plugin");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        // Also check that both of the plugins were enabled
-        if pico_instance.matches("Online").count() == 8 && pico_plugin.matches("true").count() == 2
-        {
-            is_cluster_valid = true;
-            break;
-        }
-    }
+    let mut is_cluster_valid = wait_cluster_start_completed(&workspace_path, |state| {
+        state.online_instances() == 4 && state.enabled_plugins() == 2
+    });
     assert!(is_cluster_valid, "Cluster didn't start successfully");
 
     // Test all possibilities with applying cofnig to workspace
@@ -199,26 +187,20 @@ fn test_workspace_config_apply() {
     // Change config for one plugin
     let _ = fs::remove_file(workspace_path.join("sub_plugin/plugin_config.yaml"));
     fs::copy(
-        tests_dir.join("../assets/plugin_config_1.yaml"),
+        Path::new(ASSETS_DIR).join("plugin_config_1.yaml"),
         workspace_path.join("sub_plugin/plugin_config.yaml"),
     )
     .unwrap();
 
     exec_pike(["config", "apply", "--plugin-path", "workspace_plugin"]);
-    is_cluster_valid = false;
-    let start = Instant::now();
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
+    is_cluster_valid = wait_until(Duration::from_secs(60), || {
         let pico_plugin_config =
             get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_plugin_config");
 
-        if pico_plugin_config.contains("value")
+        pico_plugin_config.contains("value")
             && pico_plugin_config.contains("changed")
             && pico_plugin_config.contains("config1")
-        {
-            is_cluster_valid = true;
-            break;
-        }
-    }
+    });
 
     assert!(
         is_cluster_valid,
@@ -229,7 +211,7 @@ fn test_workspace_config_apply() {
 
     let _ = fs::remove_file(workspace_path.join("sub_plugin/plugin_config.yaml"));
     fs::copy(
-        tests_dir.join("../assets/plugin_config_2.yaml"),
+        Path::new(ASSETS_DIR).join("plugin_config_2.yaml"),
         workspace_path.join("sub_plugin/modified_config.yaml"),
     )
     .unwrap();
@@ -245,20 +227,14 @@ fn test_workspace_config_apply() {
         "sub_plugin",
     ]);
 
-    is_cluster_valid = false;
-    let start = Instant::now();
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
+    is_cluster_valid = wait_until(Duration::from_secs(60), || {
         let pico_plugin_config =
             get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_plugin_config");
 
-        if pico_plugin_config.contains("value")
+        pico_plugin_config.contains("value")
             && pico_plugin_config.contains("changed")
             && pico_plugin_config.contains("config2")
-        {
-            is_cluster_valid = true;
-            break;
-        }
-    }
+    });
 
     assert!(is_cluster_valid, "Failed to apply config for one plugin");
 
@@ -267,7 +243,7 @@ fn test_workspace_config_apply() {
 
 #[test]
 fn test_plugin_apply_wrong_cmd_combination() {
-    init_plugin_workspace("workspace_plugin");
+    let _plugin = init_plugin_workspace("workspace_plugin");
 
     exec_pike([
         "plugin",
@@ -277,7 +253,7 @@ fn test_plugin_apply_wrong_cmd_combination() {
         "workspace_plugin",
     ]);
 
-    // Test uncle Pike wise advice's
+    // Plugin version makes sense only for the exact plugin of the workspace.
     // Forced to call Command manually instead of exec_pike to read output
     let root_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
     let mut wrong_plugin_path_cmd = Command::new(format!("{root_dir}/target/debug/cargo-pike"))
@@ -285,8 +261,8 @@ fn test_plugin_apply_wrong_cmd_combination() {
             "pike",
             "config",
             "apply",
-            "--config-path",
-            "gangam_style",
+            "--plugin-version",
+            "0.1.0",
             "--plugin-path",
             "workspace_plugin",
         ])
@@ -298,12 +274,12 @@ fn test_plugin_apply_wrong_cmd_combination() {
     wrong_plugin_path_cmd.wait().unwrap();
 
     let mut good_output = false;
-    if let Some(stdout) = wrong_plugin_path_cmd.stdout.take() {
-        let reader = BufReader::new(stdout);
+    if let Some(stderr) = wrong_plugin_path_cmd.stderr.take() {
+        let reader = BufReader::new(stderr);
         for line in reader.lines() {
             if line
                 .unwrap()
-                .contains("You are trying to apply config from")
+                .contains("--plugin-version requires --plugin-name")
             {
                 good_output = true;
             }
@@ -3,7 +3,9 @@
 use constcat::concat;
 use flate2::bufread::GzDecoder;
 use log::info;
-use regex::Regex;
+use pike::cluster::{wait_ready, ClusterState, RetryPolicy};
+use pike::testing::TempWorkspace;
+use regex::{NoExpand, Regex};
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Write};
@@ -16,16 +18,23 @@ use std::{
     io::ErrorKind,
     path::Path,
     process::{Child, Command, Stdio},
-    time::{Duration, Instant},
+    time::Duration,
 };
 use tar::Archive;
 use toml_edit::{DocumentMut, Item};
 
-pub const TESTS_DIR: &str = "./tests/tmp/";
+/// Root of plugin projects of the test binary, `target/tmp/<test binary>/`.
+/// Path is relative to keep admin socket paths of clusters short.
+pub const TESTS_DIR: &str = concat!("./target/tmp/", env!("CARGO_CRATE_NAME"), "/");
+/// Directory of external plugins used by plugins in [`TESTS_DIR`].
+pub const EXT_TESTS_DIR: &str = concat!(TESTS_DIR, "ext/");
+pub const ASSETS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/assets/");
 pub const PLUGIN_NAME: &str = "test-plugin";
 pub const PLUGIN_DIR: &str = concat!(TESTS_DIR, PLUGIN_NAME);
-pub const SHARED_TARGET_NAME: &str = "shared_target";
-pub const SHARED_TARGET_PATH: &str = concat!(TESTS_DIR, SHARED_TARGET_NAME);
+/// Build cache of test plugins shared by all test binaries.
+pub const SHARED_TARGET_PATH: &str = "./target/tmp/shared_target";
+/// Build cache of external test plugins.
+pub const EXT_SHARED_TARGET_PATH: &str = "./target/tmp/ext_shared_target";
 
 #[cfg(any(target_os = "linux", target_os = "freebsd"))]
 pub const LIB_EXT: &str = "so";
@@ -50,6 +59,8 @@ pub struct CmdArguments {
 pub struct Cluster {
     run_handler: Option<Child>,
     pub cmd_args: CmdArguments,
+    /// Project of the test plugin, removed after the cluster is stopped.
+    _plugin: TempWorkspace,
 }
 
 impl Drop for Cluster {
@@ -65,7 +76,7 @@ impl Drop for Cluster {
 }
 
 impl Cluster {
-    fn new(run_params: CmdArguments) -> Cluster {
+    fn new(run_params: CmdArguments, plugin: TempWorkspace) -> Cluster {
         info!("cleaning artefacts from previous run");
 
         match fs::remove_file(Path::new(TESTS_DIR).join("instance.log")) {
@@ -79,6 +90,7 @@ impl Cluster {
         Cluster {
             run_handler: None,
             cmd_args: run_params,
+            _plugin: plugin,
         }
     }
 
@@ -93,13 +105,11 @@ where
 {
     /// Plugin name for new plugin
     pub name: String,
-    /// Additional args for pike new command
+    /// Additional args for pike init command
     pub init_args: Vec<A>,
-    /// Plugin project path
-    pub plugin_path: PathBuf,
     /// Shared target directory to use as a cache
     pub shared_target_path: PathBuf,
-    /// Current directory to run pike in
+    /// Directory where the plugin project is created
     pub working_dir: PathBuf,
 }
 
@@ -107,7 +117,6 @@ impl TestPluginInitParams {
     pub fn new(plugin_name: &str) -> Self {
         Self {
             name: plugin_name.to_string(),
-            plugin_path: Path::new(TESTS_DIR).join(plugin_name),
             ..Default::default()
         }
     }
@@ -115,7 +124,6 @@ impl TestPluginInitParams {
     pub fn new_workspace(plugin_name: &str) -> Self {
         Self {
             name: plugin_name.to_string(),
-            plugin_path: Path::new(TESTS_DIR).join(plugin_name),
             init_args: vec!["--workspace".to_string()],
             ..Default::default()
         }
@@ -130,43 +138,48 @@ where
         Self {
             name: String::from(PLUGIN_NAME),
             init_args: vec![],
-            plugin_path: PathBuf::from(PLUGIN_DIR),
             shared_target_path: PathBuf::from(SHARED_TARGET_PATH),
             working_dir: PathBuf::from(TESTS_DIR),
         }
     }
 }
 
-pub fn init_plugin(plugin_name: &str) {
-    init_plugin_with_args(TestPluginInitParams::new(plugin_name));
+#[must_use = "plugin project is removed when the workspace is dropped"]
+pub fn init_plugin(plugin_name: &str) -> TempWorkspace {
+    init_plugin_with_args(TestPluginInitParams::new(plugin_name))
 }
 
-pub fn init_plugin_workspace(plugin_name: &str) {
-    init_plugin_with_args(TestPluginInitParams::new_workspace(plugin_name));
+#[must_use = "plugin project is removed when the workspace is dropped"]
+pub fn init_plugin_workspace(plugin_name: &str) -> TempWorkspace {
+    init_plugin_with_args(TestPluginInitParams::new_workspace(plugin_name))
 }
 
-pub fn init_plugin_with_args<A>(init_params: TestPluginInitParams<A>)
+/// Creates plugin project `working_dir/name` from the template.
+///
+/// Project is removed when the returned workspace is dropped,
+/// unless the test fails.
+#[must_use = "plugin project is removed when the workspace is dropped"]
+pub fn init_plugin_with_args<A>(init_params: TestPluginInitParams<A>) -> TempWorkspace
 where
     A: AsRef<OsStr> + std::fmt::Debug,
 {
-    // Delete plugin project directory, if it exists from previous runs
-    cleanup_dir(&init_params.plugin_path);
+    // Workspace removes plugin project left from previous runs
+    let workspace = TempWorkspace::in_dir(&init_params.working_dir, &init_params.name).unwrap();
+    let plugin_path = workspace.path();
 
     // Create new plugin and link target folder to shared target folder
-    let default_args = vec!["plugin", "new", &init_params.name];
+    let default_args = ["plugin", "init"];
     let plugin_args = init_params.init_args.iter().map(A::as_ref);
     let args = default_args
         .into_iter()
         .map(str::as_ref)
         .chain(plugin_args)
         .collect::<Vec<_>>();
-    exec_pike_in(args, init_params.working_dir);
+    exec_pike_in(args, plugin_path);
 
     // Ensure that directory for plugin build artifacts does exist
     let shared_target_path = init_params.shared_target_path;
-    if !shared_target_path.exists() {
-        fs::create_dir(&shared_target_path).unwrap();
-    }
+    fs::create_dir_all(&shared_target_path).unwrap();
     let shared_target_path = shared_target_path.canonicalize().unwrap();
 
     let normalized_package_name = init_params.name.replace('-', "_");
@@ -188,17 +201,15 @@ where
     clean_dir_with_exceptions(&shared_target_path.join("release"), &profile_dir_whitelist);
 
     // Link target dir to shared target dir
-    let plugin_target_dir = init_params
-        .plugin_path
-        .canonicalize()
-        .unwrap()
-        .join("target");
+    let plugin_target_dir = plugin_path.canonicalize().unwrap().join("target");
 
     // Compute relative path for shared target directory
     let target_rel_symlink = compute_relative_symlink(&shared_target_path, &plugin_target_dir);
     dbg!(target_rel_symlink);
 
-    symlink(shared_target_path, init_params.plugin_path.join("target")).unwrap();
+    symlink(shared_target_path, plugin_path.join("target")).unwrap();
+
+    workspace
 }
 
 pub fn clean_dir_with_exceptions<I, S>(path: &PathBuf, exceptions: I)
@@ -326,22 +337,16 @@ pub fn run_cluster(
     total_instances: i32,
     cmd_args: CmdArguments,
 ) -> Result<Cluster, std::io::Error> {
-    // Set up cleanup function
-    let mut cluster_handle = Cluster::new(cmd_args);
-
     // Create plugin from template
-    let mut args = cluster_handle
-        .cmd_args
-        .plugin_args
-        .iter()
-        .map(String::as_str);
-
-    init_plugin_with_args(TestPluginInitParams {
+    let plugin = init_plugin_with_args(TestPluginInitParams {
         name: "test-plugin".to_string(),
-        init_args: args.collect(),
+        init_args: cmd_args.plugin_args.iter().map(String::as_str).collect(),
         ..Default::default()
     });
 
+    // Set up cleanup function
+    let mut cluster_handle = Cluster::new(cmd_args, plugin);
+
     // Build the plugin
     Command::new("cargo")
         .arg("build")
@@ -360,103 +365,51 @@ pub fn run_cluster(
         .unwrap();
     cluster_handle.set_run_handler(run_handler);
 
-    let start_time = Instant::now();
-
-    // Run in the loop until we get info about successful plugin installation
-    loop {
-        // Get path to data dir from cmd_args
-        let cur_run_args = &cluster_handle.cmd_args.run_args;
-        let mut data_dir_path = Path::new("tmp");
-        if let Some(index) = cur_run_args.iter().position(|x| x == "--data-dir") {
-            if index + 1 < cur_run_args.len() {
-                data_dir_path = Path::new(&cur_run_args[index + 1]);
-            }
-        }
-        // Check if cluster set up correctly
-        let mut picodata_admin = await_picodata_admin(
-            Duration::from_secs(60),
-            Path::new(PLUGIN_DIR),
-            data_dir_path,
-        )?;
-        let stdout = picodata_admin
-            .stdout
-            .take()
-            .expect("Failed to capture stdout");
-
-        assert!(start_time.elapsed() < timeout, "cluster setup timeouted");
-
-        let queries = vec![
-            r"SELECT enabled FROM _pico_plugin;",
-            r"SELECT current_state FROM _pico_instance;",
-        ];
-
-        // New scope to avoid infinite cycle while reading picodata stdout
-        {
-            let picodata_stdin = picodata_admin.stdin.as_mut().unwrap();
-            for query in queries {
-                picodata_stdin.write_all(query.as_bytes()).unwrap();
-            }
-            picodata_admin.wait().unwrap();
-        }
-
-        let mut plugin_ready = false;
-        let mut online_instances_counter = 0;
-
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            let line = line.expect("failed to read picodata stdout");
-            if line.contains("true") {
-                plugin_ready = true;
-            }
-            if line.contains("Online") {
-                online_instances_counter += 1;
-            }
-        }
-
-        picodata_admin.kill().unwrap();
-
-        if plugin_ready && online_instances_counter == total_instances {
-            return Ok(cluster_handle);
+    // Get path to data dir from cmd_args
+    let cur_run_args = &cluster_handle.cmd_args.run_args;
+    let mut data_dir_path = Path::new("tmp");
+    if let Some(index) = cur_run_args.iter().position(|x| x == "--data-dir") {
+        if index + 1 < cur_run_args.len() {
+            data_dir_path = Path::new(&cur_run_args[index + 1]);
         }
+    }
 
-        thread::sleep(Duration::from_secs(5));
+    // Wait until all instances are online and the plugin is installed
+    let total_instances = usize::try_from(total_instances).unwrap();
+    let cluster_dir = Path::new(PLUGIN_DIR).join(data_dir_path).join("cluster");
+    if let Err(err) = wait_ready(&cluster_dir, Path::new("picodata"), timeout, |state| {
+        state.online_instances() == total_instances && state.all_plugins_enabled()
+    }) {
+        panic!("cluster setup timeouted: {err:#}");
     }
-}
 
-pub struct ClusterStateToCheck<'a> {
-    pub pico_instance: &'a str,
-    pub pico_plugin: &'a str,
+    Ok(cluster_handle)
 }
 
+/// Waits until `state_check_fn` returns true for the cluster started in `plugin_path`.
+///
+/// Check function may panic, e.g. on failed assertion, that is treated as not ready state.
 pub fn wait_cluster_start_completed<P, CheckFn>(plugin_path: P, state_check_fn: CheckFn) -> bool
 where
     P: AsRef<Path>,
-    for<'a> CheckFn: Fn(ClusterStateToCheck<'a>) -> bool,
+    CheckFn: Fn(&ClusterState) -> bool,
 {
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance =
-            get_picodata_table(plugin_path.as_ref(), Path::new("tmp"), "_pico_instance");
-        let pico_plugin =
-            get_picodata_table(plugin_path.as_ref(), Path::new("tmp"), "_pico_plugin");
-        let current_state = ClusterStateToCheck {
-            pico_instance: &pico_instance,
-            pico_plugin: &pico_plugin,
-        };
-        let check_fn = std::panic::AssertUnwindSafe(|| state_check_fn(current_state));
-        let check_result = std::panic::catch_unwind(check_fn);
-        if let Ok(value) = check_result {
-            cluster_started = value;
-            break;
-        }
-    }
-    cluster_started
+    let cluster_dir = plugin_path.as_ref().join("tmp").join("cluster");
+    wait_ready(
+        &cluster_dir,
+        Path::new("picodata"),
+        Duration::from_secs(60),
+        |state| {
+            let check_fn = std::panic::AssertUnwindSafe(|| state_check_fn(state));
+            std::panic::catch_unwind(check_fn).unwrap_or(false)
+        },
+    )
+    .is_ok()
 }
 
 pub fn get_picodata_table(plugin_path: &Path, data_dir_path: &Path, table_name: &str) -> String {
     let mut picodata_admin =
-        await_picodata_admin(Duration::from_secs(60), plugin_path, data_dir_path).unwrap();
+        await_picodata_admin(Duration::from_secs(60), plugin_path, data_dir_path);
 
     // New scope to avoid infinite cycle while reading picodata stdout
     {
@@ -497,8 +450,11 @@ fn set_current_version_of_pike(plugin_path: &OsStr) {
         return;
     };
     let re = Regex::new(r"picodata-pike =.*").unwrap();
-    let cargo_with_fixed_pike =
-        re.replace_all(&cargo_content, r#"picodata-pike = { path = "../../.." }"#);
+    let fixed_pike = format!(
+        r#"picodata-pike = {{ path = "{}" }}"#,
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let cargo_with_fixed_pike = re.replace_all(&cargo_content, NoExpand(&fixed_pike));
     let file = fs::OpenOptions::new()
         .write(true)
         .truncate(true)
@@ -512,6 +468,8 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr> + std::fmt::Debug,
 {
+    // Root of the test binary is created by the first test using it
+    fs::create_dir_all(TESTS_DIR).unwrap();
     exec_pike_in(args, TESTS_DIR);
 }
 
@@ -536,6 +494,7 @@ where
         .arg("pike")
         .args(args)
         .current_dir(work_dir)
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
@@ -558,60 +517,41 @@ where
     assert!(status.success(), "pike run failed");
 }
 
-pub fn wait_for_proc(proc: &mut Child, timeout: Duration) {
-    let start_time = Instant::now();
-
-    loop {
-        assert!(
-            start_time.elapsed() < timeout,
-            "Process hanging for too long"
-        );
+/// Calls `check` with growing delays up to a second until it returns `true`,
+/// returns `false` if `timeout` has passed before.
+pub fn wait_until(timeout: Duration, mut check: impl FnMut() -> bool) -> bool {
+    RetryPolicy::new(timeout, Duration::from_secs(1))
+        .poll(|| Ok(check().then_some(())))
+        .unwrap()
+        .is_some()
+}
 
-        match proc.try_wait().unwrap() {
-            Some(_) => {
-                break;
-            }
-            None => {
-                std::thread::sleep(std::time::Duration::from_millis(100));
-            }
-        }
-    }
+pub fn wait_for_proc(proc: &mut Child, timeout: Duration) {
+    let exited = wait_until(timeout, || proc.try_wait().unwrap().is_some());
+    assert!(exited, "Process hanging for too long");
 }
 
-pub fn await_picodata_admin(
-    timeout: Duration,
-    plugin_path: &Path,
-    data_dir_path: &Path,
-) -> Result<Child, std::io::Error> {
-    let start_time = Instant::now();
-    loop {
-        assert!(
-            start_time.elapsed() < timeout,
-            "process hanging for too long"
-        );
-
-        let picodata_admin = Command::new("picodata")
-            .arg("admin")
-            .arg(
-                plugin_path
-                    .join(data_dir_path)
-                    .join("cluster/i1/admin.sock"),
-            )
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn();
-
-        match picodata_admin {
-            Ok(process) => {
-                info!("successfully connected to picodata cluster.");
-                return Ok(process);
-            }
-            Err(_) => {
-                std::thread::sleep(Duration::from_secs(1));
-            }
-        }
-    }
+pub fn await_picodata_admin(timeout: Duration, plugin_path: &Path, data_dir_path: &Path) -> Child {
+    let picodata_admin = RetryPolicy::new(timeout, Duration::from_secs(1))
+        .poll(|| {
+            Ok(Command::new("picodata")
+                .arg("admin")
+                .arg(
+                    plugin_path
+                        .join(data_dir_path)
+                        .join("cluster/i1/admin.sock"),
+                )
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .ok())
+        })
+        .unwrap();
+
+    let process = picodata_admin.expect("process hanging for too long");
+    info!("successfully connected to picodata cluster.");
+    process
 }
 
 /// Recursively deletes directory, if exists.
@@ -1,16 +1,17 @@
 mod helpers;
 
-use helpers::{cleanup_dir, exec_pike, PLUGIN_DIR};
+use helpers::{cleanup_dir, exec_pike_in, PLUGIN_NAME, TESTS_DIR};
+use pike::testing::TempWorkspace;
 use std::fs;
 use std::path::Path;
 
 #[test]
 fn test_cargo_plugin_new() {
-    let root_dir = Path::new(PLUGIN_DIR);
-    cleanup_dir(root_dir);
+    let workspace = TempWorkspace::in_dir(Path::new(TESTS_DIR), "plugin-new").unwrap();
+    let root_dir = &workspace.join(PLUGIN_NAME);
 
     // Test creating simple plugin
-    exec_pike(["plugin", "new", "test-plugin"]);
+    exec_pike_in(["plugin", "new", PLUGIN_NAME], workspace.path());
 
     assert!(root_dir.join("picodata.yaml").exists());
     assert!(root_dir.join(".git").exists());
@@ -21,16 +22,22 @@ fn test_cargo_plugin_new() {
     cleanup_dir(root_dir);
 
     // Test creating plugin without git
-    exec_pike(["plugin", "new", "test-plugin", "--without-git"]);
+    exec_pike_in(
+        ["plugin", "new", PLUGIN_NAME, "--without-git"],
+        workspace.path(),
+    );
 
     assert!(!root_dir.join(".git").exists());
 
     cleanup_dir(root_dir);
 
     // Test creating plugin as workspace
-    exec_pike(["plugin", "new", "test-plugin", "--workspace"]);
+    exec_pike_in(
+        ["plugin", "new", PLUGIN_NAME, "--workspace"],
+        workspace.path(),
+    );
 
-    let subcrate_path = Path::new(PLUGIN_DIR).join("test-plugin");
+    let subcrate_path = root_dir.join(PLUGIN_NAME);
     assert!(subcrate_path.exists());
 
     assert!(root_dir.join(".cargo").join("config.toml").exists());
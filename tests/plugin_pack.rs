@@ -1,10 +1,11 @@
 mod helpers;
 
-use helpers::{exec_pike, init_plugin, init_plugin_workspace, LIB_EXT, TESTS_DIR};
+use helpers::{exec_pike, init_plugin, init_plugin_workspace, ASSETS_DIR, LIB_EXT, TESTS_DIR};
 use std::{
     fs::{self, OpenOptions},
     io::Write,
     path::{Path, PathBuf},
+    process::Command,
 };
 
 pub const PACK_PLUGIN_NAME: &str = "test-pack-plugin";
@@ -96,7 +97,7 @@ fn assert_os_suffix(file_name: &str, name: &str, version: &str) -> (String, Stri
 
 #[test]
 fn test_cargo_pack() {
-    init_plugin(PACK_PLUGIN_NAME);
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
 
     exec_pike([
         "plugin",
@@ -130,21 +131,18 @@ fn test_cargo_pack() {
         .exists());
     assert!(base_file_path.join("manifest.yaml").exists());
     assert!(base_file_path.join("migrations").is_dir());
+    assert!(base_file_path.join("config.schema.json").exists());
 }
 
 #[test]
 fn test_cargo_pack_assets() {
     let pack_plugin_path = Path::new(TESTS_DIR).join(PACK_PLUGIN_NAME);
 
-    init_plugin(PACK_PLUGIN_NAME);
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
 
     // Change build script for sub plugin to test custom assets
     fs::copy(
-        Path::new(TESTS_DIR)
-            .parent()
-            .unwrap()
-            .join("assets")
-            .join("custom_assets_build.rs"),
+        Path::new(ASSETS_DIR).join("custom_assets_build.rs"),
         pack_plugin_path.join("build.rs"),
     )
     .unwrap();
@@ -251,11 +249,11 @@ fn test_custom_assets_with_targets() {
     let tests_dir = Path::new(TESTS_DIR);
     let plugin_path = tests_dir.join(PACK_PLUGIN_NAME);
 
-    init_plugin(PACK_PLUGIN_NAME);
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
 
     // Change build script for plugin to test custom assets
     fs::copy(
-        tests_dir.join("../assets/custom_assets_with_targets_build.rs"),
+        Path::new(ASSETS_DIR).join("custom_assets_with_targets_build.rs"),
         plugin_path.join("build.rs"),
     )
     .unwrap();
@@ -330,9 +328,249 @@ fn test_custom_assets_with_targets() {
         .exists());
 }
 
+#[test]
+fn test_assets_ignore() {
+    let tests_dir = Path::new(TESTS_DIR);
+    let plugin_path = tests_dir.join(PACK_PLUGIN_NAME);
+
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    // Patterns come from both the build script and `.pikeignore`
+    fs::copy(
+        Path::new(ASSETS_DIR).join("assets_ignore_build.rs"),
+        plugin_path.join("build.rs"),
+    )
+    .unwrap();
+    fs::write(plugin_path.join(".pikeignore"), "# editor backups\n*~\n").unwrap();
+    fs::write(plugin_path.join("src").join(".lib.rs.swp"), "").unwrap();
+    fs::write(plugin_path.join("src").join("lib.rs~"), "").unwrap();
+
+    exec_pike(["plugin", "pack", "--plugin-path", PACK_PLUGIN_NAME]);
+
+    let release_dir = plugin_path.join("target").join("release");
+    let release_archive = find_archive(&release_dir, PACK_PLUGIN_NAME, VERSION);
+    let unzipped_release = plugin_path.join("unzipped_release");
+    helpers::unpack_archive(&release_archive, &unzipped_release);
+
+    let sources = unzipped_release
+        .join(PACK_PLUGIN_NAME)
+        .join(VERSION)
+        .join("sources");
+    assert!(sources.join("lib.rs").exists());
+    assert!(!sources.join(".lib.rs.swp").exists());
+    assert!(!sources.join("lib.rs~").exists());
+}
+
+#[test]
+fn test_pack_content_filter() {
+    let tests_dir = Path::new(TESTS_DIR);
+    let plugin_path = tests_dir.join(PACK_PLUGIN_NAME);
+
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    fs::copy(
+        Path::new(ASSETS_DIR).join("custom_assets_with_targets_build.rs"),
+        plugin_path.join("build.rs"),
+    )
+    .unwrap();
+
+    exec_pike([
+        "plugin",
+        "pack",
+        "--plugin-path",
+        PACK_PLUGIN_NAME,
+        "--exclude",
+        "plugin_config.yaml",
+        "--exclude",
+        "other/name/*.rs",
+        "--include",
+        "other/name/lib.rs",
+        "--exclude-migrations",
+    ]);
+
+    let release_dir = plugin_path.join("target").join("release");
+    let release_archive = find_archive(&release_dir, PACK_PLUGIN_NAME, VERSION);
+    let unzipped_release = plugin_path.join("unzipped_release");
+    helpers::unpack_archive(&release_archive, &unzipped_release);
+
+    let plugin_root = unzipped_release.join(PACK_PLUGIN_NAME).join(VERSION);
+    assert!(plugin_root.join("manifest.yaml").exists());
+    assert!(plugin_root
+        .join(format!(
+            "lib{}.{LIB_EXT}",
+            PACK_PLUGIN_NAME.replace('-', "_")
+        ))
+        .exists());
+    assert!(!plugin_root.join("plugin_config.yaml").exists());
+    assert!(!plugin_root.join("migrations").exists());
+    assert!(plugin_root.join("not.cargo").exists());
+    let sources = plugin_root.join("other").join("name");
+    assert!(sources.join("lib.rs").exists());
+    assert!(!sources.join("service.rs").exists());
+    assert!(sources.join("Cargo.unlock").exists());
+}
+
+#[test]
+fn test_pack_output_json() {
+    let tests_dir = Path::new(TESTS_DIR);
+    let plugin_path = tests_dir.join(PACK_PLUGIN_NAME);
+
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+    exec_pike(["plugin", "pack", "--plugin-path", PACK_PLUGIN_NAME]);
+
+    let root_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let output = Command::new(format!("{root_dir}/target/debug/cargo-pike"))
+        .args(["pike", "plugin", "pack", "--no-build", "--output", "json"])
+        .args(["--plugin-path", PACK_PLUGIN_NAME])
+        .current_dir(tests_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Stdout holds only the result, logs go to stderr.
+    let packed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let archive = PathBuf::from(packed["path"].as_str().unwrap());
+    assert!(archive.is_absolute());
+    assert_eq!(
+        archive.canonicalize().unwrap(),
+        find_archive(
+            &plugin_path.join("target").join("release"),
+            PACK_PLUGIN_NAME,
+            VERSION
+        )
+        .canonicalize()
+        .unwrap()
+    );
+    assert_eq!(packed["name"], PACK_PLUGIN_NAME);
+    assert_eq!(packed["version"], VERSION);
+    assert_eq!(packed["size"], fs::metadata(&archive).unwrap().len());
+    assert_eq!(packed["sha256"].as_str().unwrap().len(), 64);
+    let os_suffix = packed["os_suffix"].as_str().unwrap();
+    assert!(archive
+        .to_string_lossy()
+        .ends_with(&format!("-{os_suffix}.tar.gz")));
+}
+
+#[test]
+fn test_pack_os_suffix_override() {
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    exec_pike([
+        "plugin",
+        "pack",
+        "--plugin-path",
+        PACK_PLUGIN_NAME,
+        "--os-suffix",
+        "custom_1.0",
+    ]);
+
+    let release_dir = Path::new(TESTS_DIR)
+        .join(PACK_PLUGIN_NAME)
+        .join("target")
+        .join("release");
+    assert!(release_dir
+        .join(format!("{PACK_PLUGIN_NAME}_{VERSION}-custom_1.0.tar.gz"))
+        .exists());
+}
+
+#[test]
+fn test_pack_zip_and_zstd_formats() {
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    let release_dir = Path::new(TESTS_DIR)
+        .join(PACK_PLUGIN_NAME)
+        .join("target")
+        .join("release");
+    for (format, compression, magic) in [
+        ("zip", "fast", b"PK\x03\x04".as_slice()),
+        ("tar.zst", "best", [0x28, 0xb5, 0x2f, 0xfd].as_slice()),
+    ] {
+        exec_pike([
+            "plugin",
+            "pack",
+            "--plugin-path",
+            PACK_PLUGIN_NAME,
+            "--archive-name",
+            "formatted",
+            "--format",
+            format,
+            "--compression",
+            compression,
+        ]);
+
+        let archive = release_dir.join(format!("formatted.{format}"));
+        let content = fs::read(&archive).unwrap();
+        assert!(content.starts_with(magic), "{}", archive.display());
+    }
+}
+
+#[test]
+fn test_pack_library_api() {
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    let params = pike::plugin::PackParamsBuilder::default()
+        .plugin_path(Path::new(TESTS_DIR).join(PACK_PLUGIN_NAME))
+        .os_suffix(Some("custom_1.0".to_string()))
+        .build()
+        .unwrap();
+    let packed = pike::plugin::pack(&params).unwrap();
+
+    let [archive] = packed.archives() else {
+        panic!("Expected a single archive, got {packed:?}");
+    };
+    assert_eq!(archive.name, PACK_PLUGIN_NAME);
+    assert_eq!(archive.version, VERSION);
+    assert_eq!(archive.os_suffix.as_deref(), Some("custom_1.0"));
+    assert!(archive.path.ends_with(format!(
+        "target/release/{PACK_PLUGIN_NAME}_{VERSION}-custom_1.0.tar.gz"
+    )));
+    assert_eq!(archive.size, fs::metadata(&archive.path).unwrap().len());
+
+    let checksum = fs::read_to_string(format!("{}.sha256", archive.path.display())).unwrap();
+    assert_eq!(
+        checksum,
+        format!(
+            "{}  {PACK_PLUGIN_NAME}_{VERSION}-custom_1.0.tar.gz\n",
+            archive.sha256
+        )
+    );
+}
+
+#[test]
+fn test_plugin_verify() {
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
+
+    exec_pike(["plugin", "pack", "--plugin-path", PACK_PLUGIN_NAME]);
+    let release_dir = Path::new(TESTS_DIR)
+        .join(PACK_PLUGIN_NAME)
+        .join("target")
+        .join("release");
+    let archive = find_archive(&release_dir, PACK_PLUGIN_NAME, VERSION);
+
+    let root_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let verify = |archive: &Path| {
+        Command::new(format!("{root_dir}/target/debug/cargo-pike"))
+            .args(["pike", "plugin", "verify"])
+            .arg(archive)
+            .status()
+            .unwrap()
+    };
+    assert!(verify(&archive).success());
+
+    // Archive not matching its checksum is rejected.
+    let tampered = release_dir.join("tampered.tar.gz");
+    fs::copy(&archive, &tampered).unwrap();
+    fs::write(
+        release_dir.join("tampered.tar.gz.sha256"),
+        format!("{}  tampered.tar.gz\n", "0".repeat(64)),
+    )
+    .unwrap();
+    assert!(!verify(&tampered).success());
+}
+
 #[test]
 fn test_no_legacy_archive_name() {
-    init_plugin(PACK_PLUGIN_NAME);
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
 
     exec_pike(["plugin", "pack", "--plugin-path", PACK_PLUGIN_NAME]);
 
@@ -363,7 +601,7 @@ fn test_workspace_pack_multiple_archives() {
     const WS_NAME: &str = "ws-pack";
     const SUB: &str = "sub_plugin_ws";
 
-    init_plugin_workspace(WS_NAME);
+    let _plugin = init_plugin_workspace(WS_NAME);
 
     exec_pike(["plugin", "add", SUB, "--plugin-path", WS_NAME]);
 
@@ -427,7 +665,7 @@ fn test_workspace_pack_multiple_archives() {
 
 #[test]
 fn test_os_suffix_semantics_rolling_or_variant() {
-    init_plugin(PACK_PLUGIN_NAME);
+    let _plugin = init_plugin(PACK_PLUGIN_NAME);
 
     exec_pike(["plugin", "pack", "--plugin-path", PACK_PLUGIN_NAME]);
 
@@ -1,12 +1,12 @@
 mod helpers;
 
-use helpers::{exec_pike, init_plugin};
-use std::{fs, path::Path, process::Command};
+use helpers::init_plugin;
+use std::{fs, process::Command};
 
 #[test]
 fn test_plugin_run_clippy() {
-    let plugin_path = Path::new("./tests/tmp/plugin-template-tests");
-    init_plugin("plugin-template-tests");
+    let plugin = init_plugin("plugin-template-tests");
+    let plugin_path = plugin.path();
 
     let output = Command::new("cargo")
         .args([
@@ -37,18 +37,11 @@ fn test_plugin_run_clippy() {
 
 #[test]
 fn test_plugin_run_tests() {
-    // We make this kludge for fix "No buffer space available" error
-    // that occurs due to the socket path being too long.
-    let tmp_test_dir = Path::new("/tmp/pike-tests");
-    let _ = fs::remove_dir_all(tmp_test_dir);
-    fs::create_dir(tmp_test_dir).unwrap();
-    let plugin_path = tmp_test_dir.join("plugin-template-tests");
-
-    exec_pike(["plugin", "new", plugin_path.to_str().unwrap()]);
+    let plugin = init_plugin("plugin-template-tests");
 
     let output = Command::new("cargo")
         .arg("test")
-        .current_dir(plugin_path)
+        .current_dir(plugin.path())
         .output()
         .expect("Cargo run error");
 
@@ -62,8 +55,8 @@ fn test_plugin_run_tests() {
 
 #[test]
 fn test_picodata_config_template_rendering() {
-    let plugin_path = Path::new("./tests/tmp/plugin-template-config-test");
-    init_plugin("plugin-template-config-test");
+    let plugin = init_plugin("plugin-template-config-test");
+    let plugin_path = plugin.path();
 
     // Create a picodata.yaml config template with jinja variables
     let config_path = plugin_path.join("picodata.yaml");
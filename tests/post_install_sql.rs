@@ -1,11 +1,7 @@
 mod helpers;
 
-use helpers::{get_picodata_table, init_plugin, PLUGIN_DIR, PLUGIN_NAME};
-use std::{
-    collections::BTreeMap,
-    path::Path,
-    time::{Duration, Instant},
-};
+use helpers::{get_picodata_table, init_plugin, wait_until, PLUGIN_DIR, PLUGIN_NAME};
+use std::{collections::BTreeMap, path::Path, time::Duration};
 
 use pike::cluster::run;
 use pike::cluster::Plugin;
@@ -15,7 +11,7 @@ use pike::cluster::Topology;
 
 #[test]
 fn test_post_install_sql_execution() {
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugin_path = Path::new(PLUGIN_DIR);
 
@@ -35,9 +31,10 @@ fn test_post_install_sql_execution() {
     let topology = Topology {
         tiers,
         plugins,
-        enviroment: BTreeMap::new(),
+        environment: BTreeMap::new(),
         pre_install_sql: vec![],
         post_install_sql: vec![r#"CREATE USER "post_install_user" USING ldap;"#.to_string()],
+        ..Default::default()
     };
 
     let params = RunParamsBuilder::default()
@@ -49,23 +46,10 @@ fn test_post_install_sql_execution() {
 
     let _instances = run(params).expect("Cluster run failed");
 
-    let start = Instant::now();
-    let mut check_passed = false;
-
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let result = std::panic::catch_unwind(|| {
-            get_picodata_table(plugin_path, Path::new("tmp"), "_pico_user")
-        });
-
-        if let Ok(output) = result {
-            if output.contains("post_install_user") {
-                check_passed = true;
-                break;
-            }
-        }
-
-        std::thread::sleep(Duration::from_secs(1));
-    }
+    let check_passed = wait_until(Duration::from_secs(60), || {
+        std::panic::catch_unwind(|| get_picodata_table(plugin_path, Path::new("tmp"), "_pico_user"))
+            .is_ok_and(|output| output.contains("post_install_user"))
+    });
 
     pike::cluster::stop(
         &pike::cluster::StopParamsBuilder::default()
@@ -1,11 +1,7 @@
 mod helpers;
 
-use helpers::{get_picodata_table, init_plugin, PLUGIN_DIR, PLUGIN_NAME};
-use std::{
-    collections::BTreeMap,
-    path::Path,
-    time::{Duration, Instant},
-};
+use helpers::{get_picodata_table, init_plugin, wait_until, PLUGIN_DIR, PLUGIN_NAME};
+use std::{collections::BTreeMap, path::Path, time::Duration};
 
 use pike::cluster::run;
 use pike::cluster::Plugin;
@@ -15,7 +11,7 @@ use pike::cluster::Topology;
 
 #[test]
 fn test_pre_install_sql_execution() {
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugin_path = Path::new(PLUGIN_DIR);
 
@@ -33,13 +29,14 @@ fn test_pre_install_sql_execution() {
     let topology = Topology {
         tiers,
         plugins,
-        enviroment: BTreeMap::new(),
+        environment: BTreeMap::new(),
         pre_install_sql: vec![
             r#"CREATE TABLE "pre_install_check" ("id" INTEGER PRIMARY KEY, "val" TEXT);"#
                 .to_string(),
             r#"INSERT INTO "pre_install_check" VALUES (1, 'success');"#.to_string(),
         ],
         post_install_sql: vec![],
+        ..Default::default()
     };
 
     let params = RunParamsBuilder::default()
@@ -51,23 +48,27 @@ fn test_pre_install_sql_execution() {
 
     let _instances = run(params).expect("Cluster run failed");
 
-    let start = Instant::now();
-    let mut check_passed = false;
-
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let result = std::panic::catch_unwind(|| {
+    let check_passed = wait_until(Duration::from_secs(60), || {
+        std::panic::catch_unwind(|| {
             get_picodata_table(plugin_path, Path::new("tmp"), "\"pre_install_check\"")
-        });
-
-        if let Ok(output) = result {
-            if output.contains("success") {
-                check_passed = true;
-                break;
-            }
-        }
+        })
+        .is_ok_and(|output| output.contains("success"))
+    });
 
-        std::thread::sleep(Duration::from_secs(1));
-    }
+    let bootstrap = std::fs::read_to_string(
+        plugin_path
+            .join("tmp/cluster")
+            .join(pike::cluster::BOOTSTRAP_FILE_NAME),
+    )
+    .unwrap();
+    let position = |statement: &str| {
+        bootstrap
+            .find(statement)
+            .unwrap_or_else(|| panic!("{statement} is missing in bootstrap.sql:\n{bootstrap}"))
+    };
+    assert!(position("jwt_secret") < position("CREATE TABLE \"pre_install_check\""));
+    assert!(position("CREATE TABLE \"pre_install_check\"") < position("INSERT INTO"));
+    assert!(position("INSERT INTO") < position("ENABLE;"));
 
     pike::cluster::stop(
         &pike::cluster::StopParamsBuilder::default()
@@ -1,11 +1,19 @@
 mod helpers;
 
+use constcat::concat;
 use helpers::{
-    build_plugin, cleanup_dir, exec_pike, exec_pike_in, get_picodata_table, init_plugin,
-    init_plugin_with_args, init_plugin_workspace, run_cluster, wait_cluster_start_completed,
+    build_plugin, exec_pike, exec_pike_in, get_picodata_table, init_plugin, init_plugin_with_args,
+    init_plugin_workspace, run_cluster, wait_cluster_start_completed, wait_until,
 };
-use helpers::{CmdArguments, TestPluginInitParams, LIB_EXT, PLUGIN_DIR, PLUGIN_NAME, TESTS_DIR};
-use pike::cluster::{run, MigrationContextVar, Plugin, RunParamsBuilder, Service, Tier, Topology};
+use helpers::{
+    CmdArguments, TestPluginInitParams, ASSETS_DIR, EXT_SHARED_TARGET_PATH, EXT_TESTS_DIR, LIB_EXT,
+    PLUGIN_DIR, PLUGIN_NAME, TESTS_DIR,
+};
+use pike::cluster::{
+    run, AutoPorts, ClusterMetadata, MigrationContextVar, Plugin, Row, RunParamsBuilder, Service,
+    Tier, Topology,
+};
+use pike::testing::TempWorkspace;
 use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::Write;
@@ -14,8 +22,7 @@ use std::process::Command;
 use std::{
     fs::{self},
     path::Path,
-    thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use crate::helpers::is_instance_running;
@@ -91,7 +98,7 @@ fn test_cluster_daemon_and_arguments() {
         run_args: [
             "-d",
             "--topology",
-            "../../assets/topology.toml",
+            concat!(ASSETS_DIR, "topology.toml"),
             "--base-http-port",
             "8001",
             "--base-pg-port",
@@ -132,7 +139,7 @@ fn test_cluster_daemon_and_arguments() {
 fn test_topology_struct_run() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugins = BTreeMap::from([(
         PLUGIN_NAME.to_string(),
@@ -174,30 +181,33 @@ fn test_topology_struct_run() {
 
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(plugin_path, Path::new("tmp"), "_pico_instance");
-        let pico_plugin = get_picodata_table(plugin_path, Path::new("tmp"), "_pico_plugin");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        if pico_instance.matches("Online").count() == 8 && pico_plugin.contains("true") {
-            cluster_started = true;
-            break;
-        }
-    }
+    let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
+        state.online_instances() == 4 && state.enabled_plugins() > 0
+    });
+
+    let migrations = pike::migration::applied(
+        &pike::migration::AppliedParamsBuilder::default()
+            .plugin_path(plugin_path.into())
+            .build()
+            .unwrap(),
+    );
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
 
     assert!(cluster_started);
+    let files: Vec<_> = migrations
+        .unwrap()
+        .into_iter()
+        .map(|migration| migration.migration_file)
+        .collect();
+    assert_eq!(files, ["migrations/0001_init.sql"]);
 }
 
 #[test]
 fn test_multiple_run_attempt_are_idempotent() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugins = BTreeMap::from([(
         PLUGIN_NAME.to_string(),
@@ -242,8 +252,8 @@ fn test_multiple_run_attempt_are_idempotent() {
     for _ in 0..1 {
         run(params.clone()).expect("Failed to run cluster");
         assert!(wait_cluster_start_completed(plugin_path, |state| {
-            assert_eq!(state.pico_instance.matches("Online").count(), 8);
-            assert!(state.pico_plugin.contains("true"));
+            assert_eq!(state.online_instances(), 4);
+            assert!(state.enabled_plugins() > 0);
             true
         }));
     }
@@ -255,7 +265,7 @@ fn test_multiple_run_attempt_are_idempotent() {
 fn test_cluster_failure() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     // Write trash inside migraions file
     let mut file = OpenOptions::new()
@@ -294,16 +304,17 @@ fn test_cluster_failure() {
 
     let err = cluster_status.unwrap_err();
     assert!(
-        err.to_string().contains("MIGRATE"),
+        format!("{err:#}").contains("MIGRATE"),
         "expected migration error"
     );
+    assert!(matches!(err, pike::Error::MigrationFailed(_)), "{err:?}");
 }
 
 #[test]
 fn test_topology_struct_one_tier() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let tiers = BTreeMap::from([(
         "default".to_string(),
@@ -329,19 +340,9 @@ fn test_topology_struct_one_tier() {
 
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(plugin_path, Path::new("tmp"), "_pico_instance");
-        let pico_plugin = get_picodata_table(plugin_path, Path::new("tmp"), "_pico_plugin");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        if pico_instance.matches("Online").count() == 8 && pico_plugin.contains("true") {
-            cluster_started = true;
-            break;
-        }
-    }
+    let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
+        state.online_instances() == 4 && state.enabled_plugins() > 0
+    });
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
 
@@ -352,7 +353,7 @@ fn test_topology_struct_one_tier() {
 fn test_topology_struct_run_no_plugin() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let tiers = BTreeMap::from([(
         "default".to_string(),
@@ -376,29 +377,73 @@ fn test_topology_struct_run_no_plugin() {
 
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(plugin_path, Path::new("tmp"), "_pico_instance");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        if pico_instance.matches("Online").count() == 8 {
-            cluster_started = true;
-            break;
-        }
-    }
+    let cluster_started =
+        wait_cluster_start_completed(plugin_path, |state| state.online_instances() == 4);
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
 
     assert!(cluster_started);
 }
 
+#[test]
+fn test_auto_ports_are_saved_in_cluster_metadata() {
+    let plugin_path = Path::new(PLUGIN_DIR);
+
+    let _plugin = init_plugin(PLUGIN_NAME);
+
+    let topology = Topology {
+        tiers: BTreeMap::from([(
+            "default".to_string(),
+            Tier {
+                replicasets: 1,
+                replication_factor: 2,
+            },
+        )]),
+        ..Default::default()
+    };
+    let params = |auto_ports: Option<AutoPorts>| {
+        RunParamsBuilder::default()
+            .topology(topology.clone())
+            .daemon(true)
+            .plugin_path(plugin_path.into())
+            .auto_ports(auto_ports)
+            .build()
+            .unwrap()
+    };
+
+    let pico_instances = run(params(Some(AutoPorts::Ephemeral))).unwrap();
+    let metadata_path = ClusterMetadata::path(plugin_path, Path::new("./tmp"));
+    let metadata = ClusterMetadata::load(&metadata_path).unwrap().unwrap();
+    let base_ports = metadata.base_ports;
+    let properties = pico_instances.first().unwrap().properties();
+    assert_eq!(*properties.bin_port, base_ports.bin + 1);
+    assert_eq!(*properties.http_port, base_ports.http + 1);
+    assert_eq!(*properties.pg_port, base_ports.pg + 1);
+    assert_ne!(*properties.bin_port, 3001);
+
+    // Started instances are described for external tools.
+    assert_eq!(metadata.instances.len(), 2);
+    let instance = &metadata.instances[0];
+    assert_eq!(instance.name, properties.instance_name);
+    assert_eq!(instance.tier, "default");
+    assert_eq!(instance.pid, *properties.pid);
+    assert_eq!(instance.bin_port, *properties.bin_port);
+    assert!(instance.data_dir.is_absolute());
+    assert!(instance.admin_socket.exists());
+    exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
+
+    // Restarted cluster keeps its ports instead of the default ones.
+    let pico_instances = run(params(None)).unwrap();
+    let properties = pico_instances.first().unwrap().properties();
+    assert_eq!(*properties.bin_port, base_ports.bin + 1);
+    exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
+}
+
 #[test]
 fn test_picodata_instance_interaction() {
     let plugin_path = Path::new(PLUGIN_DIR);
 
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugins = BTreeMap::from([(
         PLUGIN_NAME.to_string(),
@@ -449,15 +494,83 @@ fn test_picodata_instance_interaction() {
     assert_eq!(properties.instance_id, &1);
     assert_eq!(properties.tier, "default");
     assert_eq!(properties.instance_name, "default_1_1");
-    assert_eq!(data_dir, "./tests/tmp/test-plugin/./tmp/cluster/i1");
+    assert_eq!(data_dir, concat!(PLUGIN_DIR, "/./tmp/cluster/i1"));
     assert_eq!(
         Path::new(data_dir).join("audit.log").to_str().unwrap(),
-        "./tests/tmp/test-plugin/./tmp/cluster/i1/audit.log"
+        concat!(PLUGIN_DIR, "/./tmp/cluster/i1/audit.log")
     );
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
 }
 
+#[test]
+fn cluster_handle_manages_instances() {
+    let plugin_path = Path::new(PLUGIN_DIR);
+
+    let _plugin = init_plugin(PLUGIN_NAME);
+
+    let tiers = BTreeMap::from([(
+        "default".to_string(),
+        Tier {
+            replicasets: 2,
+            replication_factor: 1,
+        },
+    )]);
+    let topology = Topology {
+        tiers,
+        ..Default::default()
+    };
+    let params = RunParamsBuilder::default()
+        .topology(topology)
+        .daemon(true)
+        .plugin_path(plugin_path.into())
+        .build()
+        .unwrap();
+
+    let mut cluster = run(params).unwrap();
+    cluster.wait_ready(Duration::from_secs(60)).unwrap();
+    assert_eq!(cluster.instances().len(), 2);
+
+    let rows: Vec<Row> = cluster.sql("SELECT name FROM _pico_instance").unwrap();
+    assert_eq!(rows.len(), 2);
+    let leader = cluster
+        .leader()
+        .unwrap()
+        .properties()
+        .instance_name
+        .to_string();
+    assert!(cluster
+        .iter()
+        .any(|i| i.properties().instance_name == leader));
+
+    let cluster_dir = plugin_path.join("tmp").join("cluster");
+    let picodata_path = Path::new("picodata");
+    let leader_name = pike::cluster::info::leader_name(&cluster_dir, picodata_path).unwrap();
+    assert_eq!(leader_name, leader);
+    let instance_dir = cluster_dir.join(&leader);
+    assert_eq!(
+        pike::cluster::info::instance_name(&instance_dir, picodata_path).unwrap(),
+        leader
+    );
+    assert!(
+        pike::cluster::info::current_state(&instance_dir, picodata_path)
+            .unwrap()
+            .is_online()
+    );
+
+    let name = cluster.instances()[1]
+        .properties()
+        .instance_name
+        .to_string();
+    cluster.stop_instance(&name).unwrap();
+    assert_eq!(cluster.instances().len(), 1);
+    let restarted = cluster.restart_instance(&name).unwrap();
+    assert_eq!(restarted.properties().instance_name, name);
+    cluster.wait_ready(Duration::from_secs(60)).unwrap();
+
+    cluster.stop().unwrap();
+}
+
 #[test]
 fn test_quickstart_pipeline() {
     let quickstart_path = Path::new(TESTS_DIR).join("quickstart");
@@ -477,7 +590,7 @@ fn test_quickstart_pipeline() {
         "Received unexpected output, while trying to run pike in wrong directory, where is the fish? Output: {stdout}"
     );
 
-    init_plugin("quickstart");
+    let _plugin = init_plugin("quickstart");
 
     let plugins = BTreeMap::from([("quickstart".to_string(), Plugin::default())]);
     let tiers = BTreeMap::from([(
@@ -504,19 +617,8 @@ fn test_quickstart_pipeline() {
     // Run cluster and check successful plugin installation
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_plugin_config =
-            get_picodata_table(&quickstart_path, Path::new("tmp"), "_pico_instance");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        if pico_plugin_config.matches("Online").count() == 8 {
-            cluster_started = true;
-            break;
-        }
-    }
+    let cluster_started =
+        wait_cluster_start_completed(&quickstart_path, |state| state.online_instances() == 4);
 
     exec_pike(["stop", "--plugin-path", "quickstart"]);
     assert!(cluster_started);
@@ -538,7 +640,7 @@ fn test_workspace_pipeline() {
     let tests_dir = Path::new(TESTS_DIR);
     let workspace_path = tests_dir.join("workspace_plugin");
 
-    init_plugin_workspace("workspace_plugin");
+    let _plugin = init_plugin_workspace("workspace_plugin");
 
     exec_pike([
         "plugin",
@@ -555,7 +657,7 @@ fn test_workspace_pipeline() {
 
     // Change build script for sub plugin to test custom assets
     fs::copy(
-        tests_dir.join("../assets/custom_assets_build.rs"),
+        Path::new(ASSETS_DIR).join("custom_assets_build.rs"),
         workspace_path.join("sub_plugin/build.rs"),
     )
     .unwrap();
@@ -592,21 +694,9 @@ fn test_workspace_pipeline() {
     // Run cluster and check successful plugin installation
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_instance");
-        let pico_plugin = get_picodata_table(&workspace_path, Path::new("tmp"), "_pico_plugin");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        // Also check that both of the plugins were enabled
-        if pico_instance.matches("Online").count() == 8 && pico_plugin.matches("true").count() == 2
-        {
-            cluster_started = true;
-            break;
-        }
-    }
+    let cluster_started = wait_cluster_start_completed(&workspace_path, |state| {
+        state.online_instances() == 4 && state.enabled_plugins() == 2
+    });
 
     exec_pike(["stop", "--plugin-path", "workspace_plugin"]);
     assert!(cluster_started);
@@ -656,9 +746,11 @@ fn test_workspace_pipeline() {
 
 #[test]
 fn test_run_without_plugin_directory() {
-    let plugin_dir = Path::new(TESTS_DIR).join("test_run_without_plugin_directory");
-
-    cleanup_dir(&plugin_dir);
+    let workspace =
+        TempWorkspace::in_dir(Path::new(TESTS_DIR), "test_run_without_plugin_directory").unwrap();
+    // Directory of the cluster is created by run
+    fs::remove_dir(workspace.path()).unwrap();
+    let plugin_dir = workspace.path().to_path_buf();
 
     let tiers = BTreeMap::from([(
         "default".to_string(),
@@ -682,20 +774,8 @@ fn test_run_without_plugin_directory() {
 
     run(params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        let pico_instance = get_picodata_table(&plugin_dir, Path::new("tmp"), "_pico_instance");
-
-        // Compare with 8, because table gives current state and target state
-        // both of them should be online
-        if pico_instance.matches("Online").count() == 8 {
-            cluster_started = true;
-            break;
-        }
-
-        thread::sleep(Duration::from_secs(1));
-    }
+    let cluster_started =
+        wait_cluster_start_completed(&plugin_dir, |state| state.online_instances() == 4);
 
     exec_pike(["stop", "--plugin-path", "test_run_without_plugin_directory"]);
 
@@ -708,80 +788,36 @@ fn test_run_with_several_tiers() {
         run_args: vec![
             "-d".into(),
             "--topology".into(),
-            "../../assets/topology_several_tiers.toml".into(),
+            concat!(ASSETS_DIR, "topology_several_tiers.toml").into(),
         ],
         ..Default::default()
     };
 
     let _cluster_handle = run_cluster(Duration::from_secs(120), 6, run_params).unwrap();
 
-    let start = Instant::now();
-    let mut cluster_started = false;
-    while Instant::now().duration_since(start) < Duration::from_secs(60) {
-        thread::sleep(Duration::from_secs(1));
-
-        // example value:
-        // +-------------+--------------------------------------+---------+-----------------+--------------------------------------+---------------+---------------+----------------+---------+--------------------+
-        // | name        | uuid                                 | raft_id | replicaset_name | replicaset_uuid                      | current_state | target_state  | failure_domain | tier    | picodata_version   |
-        // +=======================================================================================================================================================================================================+
-        // | default_1_1 | 4d607252-4603-42bf-88fa-c4b1bb4fab23 | 1       | default_1       | 25d1dfd1-bbb4-4fd0-880f-77b7512b07b6 | ["Online", 1] | ["Online", 1] | {}             | default | 25.1.1-0-g38230552 |
-        // |-------------+--------------------------------------+---------+-----------------+--------------------------------------+---------------+---------------+----------------+---------+--------------------|
-        // | default_1_2 | ef6ccfee-c855-479b-a15a-a050a6493d08 | 2       | default_1       | 25d1dfd1-bbb4-4fd0-880f-77b7512b07b6 | ["Online", 1] | ["Online", 1] | {}             | default | 25.1.1-0-g38230552 |
-        // |-------------+--------------------------------------+---------+-----------------+--------------------------------------+---------------+---------------+----------------+---------+--------------------|
-        let pico_instance =
-            get_picodata_table(Path::new(PLUGIN_DIR), Path::new("tmp"), "_pico_instance");
-
-        // Tier default == 1 replicaset and replication_factor is 3 => "default" must be met 9 times
-        if pico_instance.matches("default").count() != 9 {
-            dbg!(pico_instance);
-            continue;
-        }
-        // Tier second == 1 replicaset and replication_factor is 1 => "second" must be met 3 times
-        if pico_instance.matches("second").count() != 3 {
-            dbg!(pico_instance);
-            continue;
-        }
-        // Tier third == 1 replicaset and replication_factor is 2 => "third" must be met 6 times
-        if pico_instance.matches("third").count() != 6 {
-            dbg!(pico_instance);
-            continue;
-        }
-        // Total instances is 6 => "Online" must be meet 12 times
-        if pico_instance.matches("Online").count() != 12 {
-            dbg!(pico_instance);
-            continue;
-        }
-
-        // example value:
-        // +-------------+---------+---------------------+---------+-----------------------+------------------------------+
-        // | name        | enabled | services            | version | description           | migration_list               |
-        // +==============================================================================================================+
-        // | test-plugin | true    | ["example_service"] | 0.1.0   | A plugin for picodata | ["migrations/0001_init.sql"] |
-        // +-------------+---------+---------------------+---------+-----------------------+------------------------------+
-        let pico_plugin =
-            get_picodata_table(Path::new(PLUGIN_DIR), Path::new("tmp"), "_pico_plugin");
-        if !pico_plugin.contains("true") {
-            dbg!(pico_plugin);
-            continue;
-        }
-
-        // example value:
-        // +-------------+-----------------+---------+---------------------+-----------------+
-        // | plugin_name | name            | version | tiers               | description     |
-        // +=================================================================================+
-        // | test-plugin | example_service | 0.1.0   | ["second", "third"] | default service |
-        // +-------------+-----------------+---------+---------------------+-----------------+
-        let pico_service =
-            get_picodata_table(Path::new(PLUGIN_DIR), Path::new("tmp"), "_pico_service");
-        if !(pico_service.contains("second") && pico_service.contains("third")) {
-            dbg!(pico_service);
-            continue;
-        }
-
-        cluster_started = true;
-    }
-
+    // Tier default has 1 replicaset with replication_factor 3,
+    // tier second - 1 replicaset with replication_factor 1,
+    // tier third - 1 replicaset with replication_factor 2
+    let cluster_started = wait_cluster_start_completed(Path::new(PLUGIN_DIR), |state| {
+        state.online_instances_in_tier("default") == 3
+            && state.online_instances_in_tier("second") == 1
+            && state.online_instances_in_tier("third") == 2
+            && state.all_instances_online()
+            && state.all_plugins_enabled()
+    });
     assert!(cluster_started);
+
+    // example value:
+    // +-------------+-----------------+---------+---------------------+-----------------+
+    // | plugin_name | name            | version | tiers               | description     |
+    // +=================================================================================+
+    // | test-plugin | example_service | 0.1.0   | ["second", "third"] | default service |
+    // +-------------+-----------------+---------+---------------------+-----------------+
+    let pico_service = get_picodata_table(Path::new(PLUGIN_DIR), Path::new("tmp"), "_pico_service");
+    assert!(
+        pico_service.contains("second") && pico_service.contains("third"),
+        "{pico_service}"
+    );
 }
 
 /// Create simple pike run parameters using provided plugins.
@@ -810,26 +846,23 @@ fn make_ext_run_params(plugin_path: &Path, plugins: BTreeMap<String, Plugin>) ->
 #[test]
 fn run_with_external_plugin_directory() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     build_plugin(&helpers::BuildType::Debug, "0.1.0", plugin_path);
 
-    let ext_plugin_path = PathBuf::from("./tests/tmp_ext/external-plugin-1");
-    init_plugin_with_args(TestPluginInitParams::<String> {
+    let ext_plugin = init_plugin_with_args(TestPluginInitParams::<String> {
         name: "external-plugin-1".to_string(),
-        plugin_path: ext_plugin_path.clone(),
-        shared_target_path: "./tests/tmp_ext/ext_shared_target".into(),
-        working_dir: "./tests/tmp_ext".into(),
+        shared_target_path: EXT_SHARED_TARGET_PATH.into(),
+        working_dir: EXT_TESTS_DIR.into(),
         ..Default::default()
     });
-    build_plugin(&helpers::BuildType::Debug, "0.1.0", &ext_plugin_path);
+    build_plugin(&helpers::BuildType::Debug, "0.1.0", ext_plugin.path());
 
-    let our_plugin_path = Path::new("./tests/tmp/test-plugin");
-    let ext_plugin_path =
-        Path::new("./tests/tmp_ext/external-plugin-1/target/debug/external-plugin-1");
+    let our_plugin_path = plugin_path;
+    let ext_plugin_path = ext_plugin.join("target/debug/external-plugin-1");
 
     let our_plugin = Plugin::default();
     let external_plugin = Plugin {
-        path: Some(ext_plugin_path.into()),
+        path: Some(ext_plugin_path),
         ..Default::default()
     };
     let plugins = BTreeMap::from([
@@ -843,8 +876,8 @@ fn run_with_external_plugin_directory() {
     run(params).unwrap();
 
     let cluster_started = wait_cluster_start_completed(our_plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
-        assert_eq!(state.pico_plugin.matches("true").count(), 2);
+        assert_eq!(state.online_instances(), 4);
+        assert_eq!(state.enabled_plugins(), 2);
         true
     });
 
@@ -856,26 +889,24 @@ fn run_with_external_plugin_directory() {
 #[test]
 fn run_with_external_plugin_archive() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     build_plugin(&helpers::BuildType::Debug, "0.1.0", plugin_path);
 
     // setup and pack external plugin
-    let ext_plugin_root = PathBuf::from("./tests/tmp_ext/external-plugin-1");
-    init_plugin_with_args(TestPluginInitParams::<String> {
+    let ext_plugin = init_plugin_with_args(TestPluginInitParams::<String> {
         name: "external-plugin-1".to_string(),
-        plugin_path: ext_plugin_root.join(""),
-        shared_target_path: "./tests/tmp_ext/ext_shared_target".into(),
-        working_dir: "./tests/tmp_ext".into(),
+        shared_target_path: EXT_SHARED_TARGET_PATH.into(),
+        working_dir: EXT_TESTS_DIR.into(),
         ..Default::default()
     });
-    build_plugin(&helpers::BuildType::Release, "0.1.0", &ext_plugin_root);
+    build_plugin(&helpers::BuildType::Release, "0.1.0", ext_plugin.path());
     let pack_args = ["plugin", "pack", "--plugin-path", "./external-plugin-1"];
-    exec_pike_in(pack_args, "./tests/tmp_ext");
+    exec_pike_in(pack_args, EXT_TESTS_DIR);
 
     // Find new-format archive
-    let ext_release_dir = Path::new("./tests/tmp_ext/external-plugin-1/target/release");
+    let ext_release_dir = ext_plugin.join("target/release");
     let ext_plugin_archive =
-        find_os_suffixed_archive(ext_release_dir, "external-plugin-1", "0.1.0");
+        find_os_suffixed_archive(&ext_release_dir, "external-plugin-1", "0.1.0");
 
     let plugins = BTreeMap::from([
         (PLUGIN_NAME.to_string(), Plugin::default()),
@@ -887,18 +918,15 @@ fn run_with_external_plugin_archive() {
             },
         ),
     ]);
-    let params = make_ext_run_params(Path::new("./tests/tmp/test-plugin"), plugins)
-        .build()
-        .unwrap();
+    let params = make_ext_run_params(plugin_path, plugins).build().unwrap();
 
     run(params).unwrap();
 
-    let cluster_started =
-        wait_cluster_start_completed(Path::new("./tests/tmp/test-plugin"), |state| {
-            assert_eq!(state.pico_instance.matches("Online").count(), 8);
-            assert_eq!(state.pico_plugin.matches("true").count(), 2);
-            true
-        });
+    let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
+        assert_eq!(state.online_instances(), 4);
+        assert_eq!(state.enabled_plugins(), 2);
+        true
+    });
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
 
@@ -908,21 +936,19 @@ fn run_with_external_plugin_archive() {
 #[test]
 fn run_with_external_plugin_project() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     build_plugin(&helpers::BuildType::Debug, "0.1.0", plugin_path);
 
     // init external plugin and do not build it - we'll check that run calls "cargo build"
-    let ext_plugin_path = PathBuf::from("./tests/tmp_ext/external-plugin-1");
-    init_plugin_with_args(TestPluginInitParams::<String> {
+    let ext_plugin = init_plugin_with_args(TestPluginInitParams::<String> {
         name: "external-plugin-1".to_string(),
-        plugin_path: ext_plugin_path,
-        shared_target_path: "./tests/tmp_ext/ext_shared_target".into(),
-        working_dir: "./tests/tmp_ext".into(),
+        shared_target_path: EXT_SHARED_TARGET_PATH.into(),
+        working_dir: EXT_TESTS_DIR.into(),
         ..Default::default()
     });
 
-    let our_plugin_path = Path::new("./tests/tmp/test-plugin");
-    let ext_plugin_path = Path::new("./tests/tmp_ext/external-plugin-1");
+    let our_plugin_path = plugin_path;
+    let ext_plugin_path = ext_plugin.path();
 
     let plugins = BTreeMap::from([
         (PLUGIN_NAME.to_string(), Plugin::default()),
@@ -941,8 +967,8 @@ fn run_with_external_plugin_project() {
     run(params).unwrap();
 
     let cluster_started = wait_cluster_start_completed(our_plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
-        assert_eq!(state.pico_plugin.matches("true").count(), 2);
+        assert_eq!(state.online_instances(), 4);
+        assert_eq!(state.enabled_plugins(), 2);
         true
     });
 
@@ -954,17 +980,16 @@ fn run_with_external_plugin_project() {
 #[test]
 fn run_with_external_plugin_workspace() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     build_plugin(&helpers::BuildType::Debug, "0.1.0", plugin_path);
 
-    let ext_workspace_path = Path::new("./tests/tmp_ext/ext-workspace-plugin");
-    init_plugin_with_args(TestPluginInitParams {
+    let ext_workspace = init_plugin_with_args(TestPluginInitParams {
         name: "ext-workspace-plugin".to_string(),
-        plugin_path: ext_workspace_path.to_owned(),
         init_args: vec!["--workspace"],
-        shared_target_path: "./tests/tmp_ext/ext_shared_target".into(),
-        working_dir: "./tests/tmp_ext".into(),
+        shared_target_path: EXT_SHARED_TARGET_PATH.into(),
+        working_dir: EXT_TESTS_DIR.into(),
     });
+    let ext_workspace_path = ext_workspace.path();
     exec_pike_in(["plugin", "add", "ext-sub-plugin"], ext_workspace_path);
 
     let plugins = BTreeMap::from([
@@ -989,8 +1014,8 @@ fn run_with_external_plugin_workspace() {
     run(params).unwrap();
 
     let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
-        assert_eq!(state.pico_plugin.matches("true").count(), 3);
+        assert_eq!(state.online_instances(), 4);
+        assert_eq!(state.enabled_plugins(), 3);
         true
     });
 
@@ -1002,7 +1027,7 @@ fn run_with_external_plugin_workspace() {
 #[test]
 fn run_specific_instance() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     let target_instance = "i2";
 
     let _cluster_handle = run_cluster(
@@ -1025,17 +1050,10 @@ fn run_specific_instance() {
     let instance_dir = data_dir.join(target_instance);
 
     // Wait while stopping instance is not killed.
-    let start = Instant::now();
-    let timeout = Duration::from_secs(60);
-
-    while is_instance_running(&instance_dir) {
-        thread::sleep(Duration::from_secs(1));
-
-        assert!(
-            Instant::now().duration_since(start) < timeout,
-            "Timeout has reached. Instance was not stopped."
-        );
-    }
+    let stopped = wait_until(Duration::from_secs(60), || {
+        !is_instance_running(&instance_dir)
+    });
+    assert!(stopped, "Timeout has reached. Instance was not stopped.");
 
     // Check that all other instances were not killed.
     for entry in fs::read_dir(&data_dir).unwrap() {
@@ -1069,7 +1087,7 @@ fn run_specific_instance() {
     ]);
 
     let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
+        assert_eq!(state.online_instances(), 4);
         true
     });
 
@@ -1086,7 +1104,7 @@ fn run_specific_instance() {
     ]);
 
     let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
+        assert_eq!(state.online_instances(), 4);
         true
     });
 
@@ -1099,7 +1117,7 @@ fn run_specific_instance() {
 fn revive_terminated_instances() {
     let plugin_path = Path::new(PLUGIN_DIR);
     let data_dir = plugin_path.join("tmp").join("cluster");
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
     let terminated_instances = ["i1", "i2"];
 
     let _cluster_handle = run_cluster(
@@ -1114,18 +1132,14 @@ fn revive_terminated_instances() {
     for i in &terminated_instances {
         exec_pike(["stop", "--plugin-path", PLUGIN_NAME, "--instance-name", i]);
 
-        let start = Instant::now();
-        let timeout = Duration::from_secs(60);
         let instance_dir = data_dir.join(i);
-
-        while is_instance_running(&instance_dir) {
-            thread::sleep(Duration::from_secs(1));
-
-            assert!(
-                Instant::now().duration_since(start) < timeout,
-                "Timeout has reached. Instance '{i}' was not stopped."
-            );
-        }
+        let stopped = wait_until(Duration::from_secs(60), || {
+            !is_instance_running(&instance_dir)
+        });
+        assert!(
+            stopped,
+            "Timeout has reached. Instance '{i}' was not stopped."
+        );
     }
 
     // Execute "pike run" once again.
@@ -1133,7 +1147,7 @@ fn revive_terminated_instances() {
     exec_pike(["run", "--plugin-path", PLUGIN_NAME, "--daemon"]);
 
     let cluster_started = wait_cluster_start_completed(plugin_path, |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
+        assert_eq!(state.online_instances(), 4);
         true
     });
 
@@ -1144,7 +1158,7 @@ fn revive_terminated_instances() {
 #[test]
 fn run_with_env_variables() {
     let plugin_path = Path::new(PLUGIN_DIR);
-    init_plugin(PLUGIN_NAME);
+    let _plugin = init_plugin(PLUGIN_NAME);
 
     let plugins = BTreeMap::from([(
         PLUGIN_NAME.to_string(),
@@ -1171,7 +1185,7 @@ fn run_with_env_variables() {
         },
     )]);
 
-    let enviroment = BTreeMap::from_iter([
+    let environment = BTreeMap::from_iter([
         (
             String::from("PICODATA_HTTP_LISTEN"),
             String::from("0.0.0.0:{{ instance_id + 18000 }}"),
@@ -1187,11 +1201,12 @@ fn run_with_env_variables() {
     ]);
 
     let topology = Topology {
-        enviroment,
+        environment,
         plugins,
         tiers,
         pre_install_sql: vec![],
         post_install_sql: vec![],
+        ..Default::default()
     };
     let params = RunParamsBuilder::default()
         .topology(topology)
@@ -1211,7 +1226,7 @@ fn run_with_env_variables() {
     assert_eq!(properties.instance_name, "default_1_1");
     assert_eq!(
         properties.data_dir.to_str().unwrap(),
-        "./tests/tmp/test-plugin/./tmp/cluster/i1"
+        concat!(PLUGIN_DIR, "/./tmp/cluster/i1")
     );
 
     exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
@@ -1235,8 +1250,8 @@ fn run_with_wait_vshard_discovery() {
     .unwrap();
 
     let cluster_started = wait_cluster_start_completed(Path::new(PLUGIN_DIR), |state| {
-        assert_eq!(state.pico_instance.matches("Online").count(), 8);
-        assert!(state.pico_plugin.contains("true"));
+        assert_eq!(state.online_instances(), 4);
+        assert!(state.enabled_plugins() > 0);
         true
     });
 
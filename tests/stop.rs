@@ -1,11 +1,10 @@
 mod helpers;
 
-use helpers::{exec_pike, run_cluster, CmdArguments, PLUGIN_DIR, PLUGIN_NAME};
+use helpers::{exec_pike, run_cluster, wait_until, CmdArguments, PLUGIN_DIR, PLUGIN_NAME};
 use std::{
     fs::{self},
     path::Path,
-    thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use crate::helpers::is_instance_running;
@@ -15,30 +14,17 @@ const CLUSTER_STOP_TIMEOUT: Duration = Duration::from_secs(60);
 const CLUSTER_START_TIMEOUT: Duration = Duration::from_secs(120);
 
 fn assert_cluster_stopped(timeout: Duration) {
-    let start = Instant::now();
-    let delay = Duration::from_millis(200);
-
-    while Instant::now().duration_since(start) < timeout {
-        // Search for PID's of picodata instances and check their liveness
-        let mut cluster_stopped = true;
-        let cluster_dir = Path::new(PLUGIN_DIR).join("tmp").join("cluster");
-
-        for instance_dir in fs::read_dir(cluster_dir).unwrap() {
-            // Check if proccess of picodata is still running
-            if is_instance_running(&instance_dir.unwrap().path()) {
-                cluster_stopped = false;
-                break;
-            }
-        }
+    let cluster_dir = Path::new(PLUGIN_DIR).join("tmp").join("cluster");
 
-        if cluster_stopped {
-            return;
-        }
+    // Search for PID's of picodata instances and check their liveness
+    let cluster_stopped = wait_until(timeout, || {
+        fs::read_dir(&cluster_dir)
+            .unwrap()
+            .all(|instance_dir| !is_instance_running(&instance_dir.unwrap().path()))
+    });
 
-        thread::sleep(delay);
-    }
-
-    panic!(
+    assert!(
+        cluster_stopped,
         "Timeouted while trying to stop cluster, processes with associated PID's are still running"
     );
 }
@@ -121,17 +107,10 @@ fn test_pike_stop_of_specific_instance() {
     let instance_dir = data_dir.join(target_instance);
 
     // Wait while stopping instance is not killed.
-    let start = Instant::now();
-    let timeout = Duration::from_secs(60);
-
-    while is_instance_running(&instance_dir) {
-        thread::sleep(Duration::from_secs(1));
-
-        assert!(
-            Instant::now().duration_since(start) < timeout,
-            "Timeout has reached. Instance was not stopped."
-        );
-    }
+    let stopped = wait_until(Duration::from_secs(60), || {
+        !is_instance_running(&instance_dir)
+    });
+    assert!(stopped, "Timeout has reached. Instance was not stopped.");
 
     // Check that all other instances were not killed.
     for entry in fs::read_dir(data_dir).unwrap() {
@@ -154,3 +133,75 @@ fn test_pike_stop_of_specific_instance() {
         );
     }
 }
+
+#[test]
+fn test_pike_instance_restart() {
+    let target_instance = "i2";
+
+    let cmd_args = CmdArguments {
+        run_args: ["--daemon"].iter().map(|&s| s.into()).collect(),
+        ..Default::default()
+    };
+    let _cluster_handle = run_cluster(CLUSTER_START_TIMEOUT, TOTAL_INSTANCES, cmd_args)
+        .expect("Failed to start cluster");
+
+    let instance_dir = Path::new(PLUGIN_DIR)
+        .join("tmp")
+        .join("cluster")
+        .join(target_instance);
+    let pid_before = fs::read_to_string(instance_dir.join("pid")).unwrap();
+
+    // Restart returns only when the instance is Online again.
+    exec_pike([
+        "instance",
+        "restart",
+        target_instance,
+        "--plugin-path",
+        PLUGIN_NAME,
+    ]);
+
+    assert!(is_instance_running(&instance_dir));
+    let pid_after = fs::read_to_string(instance_dir.join("pid")).unwrap();
+    assert_ne!(pid_before, pid_after, "instance should be started again");
+
+    exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
+    assert_cluster_stopped(CLUSTER_STOP_TIMEOUT);
+}
+
+#[test]
+fn test_pike_cluster_rolling_restart() {
+    let cmd_args = CmdArguments {
+        run_args: ["--daemon"].iter().map(|&s| s.into()).collect(),
+        ..Default::default()
+    };
+    let _cluster_handle = run_cluster(CLUSTER_START_TIMEOUT, TOTAL_INSTANCES, cmd_args)
+        .expect("Failed to start cluster");
+
+    let cluster_dir = Path::new(PLUGIN_DIR).join("tmp").join("cluster");
+    let read_pids = || {
+        let mut pids = vec![];
+        for entry in fs::read_dir(&cluster_dir).unwrap() {
+            let instance_dir = entry.unwrap().path();
+            if fs::symlink_metadata(&instance_dir).unwrap().is_symlink() {
+                continue;
+            }
+            assert!(is_instance_running(&instance_dir));
+            pids.push(fs::read_to_string(instance_dir.join("pid")).unwrap());
+        }
+        pids.sort();
+        pids
+    };
+    let pids_before = read_pids();
+
+    exec_pike(["cluster", "rolling-restart", "--plugin-path", PLUGIN_NAME]);
+
+    let pids_after = read_pids();
+    assert_eq!(pids_after.len(), pids_before.len());
+    assert!(
+        pids_after.iter().all(|pid| !pids_before.contains(pid)),
+        "all instances should be restarted"
+    );
+
+    exec_pike(["stop", "--plugin-path", PLUGIN_NAME]);
+    assert_cluster_stopped(CLUSTER_STOP_TIMEOUT);
+}